@@ -0,0 +1,621 @@
+//! Checking individual RPKI objects.
+//!
+//! This module provides a report on a single RPKI object given as its DER
+//! encoding. The object is parsed both in strict and relaxed mode and its
+//! content is made available for display. This is meant for operators of
+//! certification authorities who want to check an object before
+//! publishing it.
+//!
+//! The checks are purely syntactic and semantic. No attempt is made to
+//! validate the object all the way to a trust anchor. However, if the
+//! certificate of the issuing CA can be found in the store, the signature
+//! of the object’s certificate is verified against it.
+
+use std::io;
+use bytes::Bytes;
+use rpki::oid;
+use rpki::repository::aspa::Aspa;
+use rpki::repository::cert::Cert;
+use rpki::repository::crl::Crl;
+use rpki::repository::manifest::Manifest;
+use rpki::repository::resources::{IpBlocksForFamily, IpResources};
+use rpki::repository::roa::Roa;
+use rpki::repository::sigobj::SignedObject;
+use rpki::repository::x509::Time;
+use rpki::uri;
+use crate::error::Failed;
+use crate::store::Store;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use crate::utils::str::append_hex;
+
+
+//------------ ObjectType ----------------------------------------------------
+
+/// The types of objects that can be checked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ObjectType {
+    /// A resource certificate.
+    Cert,
+
+    /// A certificate revocation list.
+    Crl,
+
+    /// A manifest.
+    Manifest,
+
+    /// A route origin authorization.
+    Roa,
+
+    /// An autonomous system provider authorization.
+    Aspa,
+}
+
+impl ObjectType {
+    /// Determines the object type from a file name’s extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        match ext.to_ascii_lowercase().as_str() {
+            "cer" => Some(ObjectType::Cert),
+            "crl" => Some(ObjectType::Crl),
+            "mft" => Some(ObjectType::Manifest),
+            "roa" => Some(ObjectType::Roa),
+            "asa" => Some(ObjectType::Aspa),
+            _ => None
+        }
+    }
+
+    /// Determines the object type from the object’s content.
+    ///
+    /// Signed objects are recognized by their content type. Anything else
+    /// is tried as a certificate and then a CRL.
+    pub fn from_content(data: &Bytes) -> Option<Self> {
+        if let Ok(signed) = SignedObject::decode(data.clone(), false) {
+            let content_type = signed.content_type();
+            return if *content_type == oid::ROUTE_ORIGIN_AUTHZ {
+                Some(ObjectType::Roa)
+            }
+            else if *content_type == oid::CT_RPKI_MANIFEST {
+                Some(ObjectType::Manifest)
+            }
+            else if *content_type == oid::CT_ASPA {
+                Some(ObjectType::Aspa)
+            }
+            else {
+                None
+            }
+        }
+        if Cert::decode(data.clone()).is_ok() {
+            Some(ObjectType::Cert)
+        }
+        else if Crl::decode(data.clone()).is_ok() {
+            Some(ObjectType::Crl)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns the name of the object type.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectType::Cert => "certificate",
+            ObjectType::Crl => "crl",
+            ObjectType::Manifest => "manifest",
+            ObjectType::Roa => "roa",
+            ObjectType::Aspa => "aspa",
+        }
+    }
+}
+
+
+//------------ ParsedObject --------------------------------------------------
+
+/// A successfully parsed object.
+#[derive(Clone, Debug)]
+enum ParsedObject {
+    Cert(Cert),
+    Crl(Crl),
+    Manifest(Manifest),
+    Roa(Roa),
+    Aspa(Aspa),
+}
+
+impl ParsedObject {
+    /// Parses and inspects an object of the given type.
+    fn parse(
+        object_type: ObjectType, data: Bytes, strict: bool
+    ) -> Result<Self, String> {
+        let res = match object_type {
+            ObjectType::Cert => {
+                let cert = Cert::decode(data).map_err(|err| {
+                    err.to_string()
+                })?;
+                if cert.basic_ca() == Some(true) {
+                    cert.inspect_ca(strict)
+                }
+                else if cert.extended_key_usage().is_some() {
+                    cert.inspect_router(strict)
+                }
+                else {
+                    cert.inspect_ee(strict)
+                }.map_err(|err| err.to_string())?;
+                return Ok(ParsedObject::Cert(cert))
+            }
+            ObjectType::Crl => {
+                return Crl::decode(data).map(ParsedObject::Crl).map_err(
+                    |err| err.to_string()
+                )
+            }
+            ObjectType::Manifest => {
+                Manifest::decode(data, strict).map(ParsedObject::Manifest)
+            }
+            ObjectType::Roa => {
+                Roa::decode(data, strict).map(ParsedObject::Roa)
+            }
+            ObjectType::Aspa => {
+                Aspa::decode(data, strict).map(ParsedObject::Aspa)
+            }
+        };
+        let res = res.map_err(|err| err.to_string())?;
+        if let Some(cert) = res.cert() {
+            cert.inspect_ee(strict).map_err(|err| err.to_string())?;
+        }
+        Ok(res)
+    }
+
+    /// Returns the certificate of the object if it has one.
+    ///
+    /// For a certificate, this is the certificate itself. For signed
+    /// objects, it is the EE certificate.
+    fn cert(&self) -> Option<&Cert> {
+        match *self {
+            ParsedObject::Cert(ref cert) => Some(cert),
+            ParsedObject::Crl(_) => None,
+            ParsedObject::Manifest(ref mft) => Some(mft.cert()),
+            ParsedObject::Roa(ref roa) => Some(roa.cert()),
+            ParsedObject::Aspa(ref aspa) => Some(aspa.cert()),
+        }
+    }
+}
+
+
+//------------ ObjectReport --------------------------------------------------
+
+/// The report on checking a single object.
+#[derive(Clone, Debug)]
+pub struct ObjectReport {
+    /// The type of the object if it could be determined.
+    object_type: Option<ObjectType>,
+
+    /// The error when parsing in strict mode or `None` if successful.
+    strict_error: Option<String>,
+
+    /// The error when parsing in relaxed mode or `None` if successful.
+    relaxed_error: Option<String>,
+
+    /// Warnings about the object.
+    warnings: Vec<String>,
+
+    /// The object as parsed in relaxed mode.
+    parsed: Option<ParsedObject>,
+
+    /// The result of verifying the signature against the issuer.
+    ///
+    /// This is only present if the issuer certificate was found.
+    signature: Option<(uri::Rsync, Result<(), String>)>,
+}
+
+impl ObjectReport {
+    /// Checks an object.
+    ///
+    /// If a file name is given, its extension is used to determine the
+    /// type of the object if the content can’t be recognized.
+    pub fn new(data: Bytes, name: Option<&str>) -> Self {
+        let mut warnings = Vec::new();
+        let by_name = name.and_then(ObjectType::from_name);
+        let by_content = ObjectType::from_content(&data);
+        let object_type = match (by_name, by_content) {
+            (Some(by_name), Some(by_content)) => {
+                if by_name != by_content {
+                    warnings.push(format!(
+                        "file name suggests a {} but content is a {}",
+                        by_name.as_str(), by_content.as_str()
+                    ));
+                }
+                Some(by_content)
+            }
+            (by_name, by_content) => by_content.or(by_name),
+        };
+        let object_type = match object_type {
+            Some(object_type) => object_type,
+            None => {
+                return ObjectReport {
+                    object_type: None,
+                    strict_error: Some("unknown object type".into()),
+                    relaxed_error: Some("unknown object type".into()),
+                    warnings,
+                    parsed: None,
+                    signature: None,
+                }
+            }
+        };
+
+        let strict = ParsedObject::parse(object_type, data.clone(), true);
+        let relaxed = ParsedObject::parse(object_type, data, false);
+        let mut res = ObjectReport {
+            object_type: Some(object_type),
+            strict_error: strict.err(),
+            relaxed_error: relaxed.as_ref().err().cloned(),
+            warnings,
+            parsed: relaxed.ok(),
+            signature: None,
+        };
+        res.add_warnings();
+        res
+    }
+
+    /// Adds warnings about the content of a parsed object.
+    fn add_warnings(&mut self) {
+        let now = Time::now();
+        if let Some(cert) = self.parsed.as_ref().and_then(|p| p.cert()) {
+            let validity = cert.validity();
+            if validity.not_before() > now {
+                self.warnings.push("certificate is not yet valid".into());
+            }
+            if validity.not_after() < now {
+                self.warnings.push("certificate has expired".into());
+            }
+        }
+        match self.parsed {
+            Some(ParsedObject::Crl(ref crl)) if crl.next_update() < now => {
+                self.warnings.push("CRL is stale".into());
+            }
+            Some(ParsedObject::Manifest(ref mft)) if mft.is_stale() => {
+                self.warnings.push("manifest is stale".into());
+            }
+            _ => { }
+        }
+    }
+
+    /// Verifies the signature if the issuer certificate is in the store.
+    ///
+    /// The issuer certificate is looked up via the certificate’s
+    /// authority information access extension. If it can’t be found,
+    /// nothing happens.
+    pub fn check_signature(&mut self, store: &Store) -> Result<(), Failed> {
+        let cert = match self.parsed.as_ref().and_then(|p| p.cert()) {
+            Some(cert) => cert,
+            None => return Ok(())
+        };
+        let issuer_uri = match cert.ca_issuer() {
+            Some(uri) => uri.clone(),
+            None => return Ok(())
+        };
+        let issuer = match store.find_object(&issuer_uri)? {
+            Some(issuer) => issuer,
+            None => return Ok(())
+        };
+        let res = match Cert::decode(issuer) {
+            Ok(issuer) => {
+                cert.verify_signature(&issuer, false).map_err(|err| {
+                    err.to_string()
+                })
+            }
+            Err(err) => Err(format!("invalid issuer certificate: {}", err))
+        };
+        self.signature = Some((issuer_uri, res));
+        Ok(())
+    }
+
+    /// Returns whether the object is acceptable in the given mode.
+    pub fn is_acceptable(&self, strict: bool) -> bool {
+        let error = if strict {
+            &self.strict_error
+        }
+        else {
+            &self.relaxed_error
+        };
+        error.is_none()
+            && !matches!(self.signature, Some((_, Err(_))))
+    }
+
+    /// Writes the report as text.
+    pub fn write_text(&self, target: &mut impl io::Write) -> io::Result<()> {
+        writeln!(
+            target, "Type: {}",
+            self.object_type.map(ObjectType::as_str).unwrap_or("unknown")
+        )?;
+        writeln!(target, "Strict: {}", Self::status(&self.strict_error))?;
+        writeln!(target, "Relaxed: {}", Self::status(&self.relaxed_error))?;
+        if let Some((ref uri, ref res)) = self.signature {
+            writeln!(
+                target, "Signature: {} (issuer {})",
+                Self::status(&res.as_ref().err().cloned()), uri
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(target, "Warning: {}", warning)?;
+        }
+        let parsed = match self.parsed {
+            Some(ref parsed) => parsed,
+            None => return Ok(())
+        };
+        if let Some(cert) = parsed.cert() {
+            let validity = cert.validity();
+            writeln!(
+                target, "Not before: {}",
+                format_iso_date(*validity.not_before())
+            )?;
+            writeln!(
+                target, "Not after: {}",
+                format_iso_date(*validity.not_after())
+            )?;
+            writeln!(target, "AS resources: {}", cert.as_resources())?;
+            writeln!(
+                target, "IPv4 resources: {}",
+                ip_resources(cert.v4_resources(), true)
+            )?;
+            writeln!(
+                target, "IPv6 resources: {}",
+                ip_resources(cert.v6_resources(), false)
+            )?;
+            if let Some(uri) = cert.ca_issuer() {
+                writeln!(target, "Issuer: {}", uri)?;
+            }
+        }
+        match *parsed {
+            ParsedObject::Cert(_) => { }
+            ParsedObject::Crl(ref crl) => {
+                writeln!(
+                    target, "This update: {}",
+                    format_iso_date(*crl.this_update())
+                )?;
+                writeln!(
+                    target, "Next update: {}",
+                    format_iso_date(*crl.next_update())
+                )?;
+                writeln!(
+                    target, "Revoked certificates: {}",
+                    crl.revoked_certs().iter().count()
+                )?;
+            }
+            ParsedObject::Manifest(ref mft) => {
+                writeln!(
+                    target, "Manifest number: {}", mft.manifest_number()
+                )?;
+                writeln!(
+                    target, "This update: {}",
+                    format_iso_date(*mft.this_update())
+                )?;
+                writeln!(
+                    target, "Next update: {}",
+                    format_iso_date(*mft.next_update())
+                )?;
+                writeln!(target, "Files:")?;
+                for (file, hash) in manifest_files(mft) {
+                    writeln!(target, "    {} {}", hash, file)?;
+                }
+            }
+            ParsedObject::Roa(ref roa) => {
+                writeln!(target, "ASN: {}", roa.content().as_id())?;
+                writeln!(target, "Prefixes:")?;
+                for addr in roa.content().iter() {
+                    writeln!(target, "    {}", addr)?;
+                }
+            }
+            ParsedObject::Aspa(ref aspa) => {
+                writeln!(
+                    target, "Customer ASN: {}", aspa.content().customer_as()
+                )?;
+                writeln!(target, "Provider ASNs:")?;
+                for asn in aspa.content().provider_as_set().iter() {
+                    writeln!(target, "    {}", asn)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the report as JSON.
+    pub fn to_json(&self) -> String {
+        JsonBuilder::build(|json| {
+            json.member_str(
+                "type",
+                self.object_type.map(ObjectType::as_str).unwrap_or("unknown")
+            );
+            json.member_raw("valid", self.relaxed_error.is_none());
+            json.member_raw("validStrict", self.strict_error.is_none());
+            if let Some(ref err) = self.relaxed_error {
+                json.member_str("error", err);
+            }
+            if let Some(ref err) = self.strict_error {
+                json.member_str("strictError", err);
+            }
+            json.member_array("warnings", |json| {
+                for warning in &self.warnings {
+                    json.array_str(warning);
+                }
+            });
+            if let Some((ref uri, ref res)) = self.signature {
+                json.member_object("signature", |json| {
+                    json.member_str("issuer", uri);
+                    json.member_raw("verified", res.is_ok());
+                    if let Err(ref err) = res {
+                        json.member_str("error", err);
+                    }
+                });
+            }
+            if let Some(ref parsed) = self.parsed {
+                Self::parsed_json(parsed, json);
+            }
+        })
+    }
+
+    /// Adds the content of a parsed object to the JSON report.
+    fn parsed_json(parsed: &ParsedObject, json: &mut JsonBuilder) {
+        if let Some(cert) = parsed.cert() {
+            json.member_object("certificate", |json| {
+                let validity = cert.validity();
+                json.member_str(
+                    "notBefore", format_iso_date(*validity.not_before())
+                );
+                json.member_str(
+                    "notAfter", format_iso_date(*validity.not_after())
+                );
+                json.member_str("asResources", cert.as_resources());
+                json.member_str(
+                    "ipv4Resources", ip_resources(cert.v4_resources(), true)
+                );
+                json.member_str(
+                    "ipv6Resources", ip_resources(cert.v6_resources(), false)
+                );
+                if let Some(uri) = cert.ca_issuer() {
+                    json.member_str("issuer", uri);
+                }
+            });
+        }
+        match *parsed {
+            ParsedObject::Cert(_) => { }
+            ParsedObject::Crl(ref crl) => {
+                json.member_object("crl", |json| {
+                    json.member_str(
+                        "thisUpdate", format_iso_date(*crl.this_update())
+                    );
+                    json.member_str(
+                        "nextUpdate", format_iso_date(*crl.next_update())
+                    );
+                    json.member_raw(
+                        "revokedCount", crl.revoked_certs().iter().count()
+                    );
+                });
+            }
+            ParsedObject::Manifest(ref mft) => {
+                json.member_object("manifest", |json| {
+                    json.member_str(
+                        "manifestNumber", mft.manifest_number()
+                    );
+                    json.member_str(
+                        "thisUpdate", format_iso_date(*mft.this_update())
+                    );
+                    json.member_str(
+                        "nextUpdate", format_iso_date(*mft.next_update())
+                    );
+                    json.member_array("files", |json| {
+                        for (file, hash) in manifest_files(mft) {
+                            json.array_object(|json| {
+                                json.member_str("file", file);
+                                json.member_str("hash", hash);
+                            })
+                        }
+                    });
+                });
+            }
+            ParsedObject::Roa(ref roa) => {
+                json.member_object("roa", |json| {
+                    json.member_str("asn", roa.content().as_id());
+                    json.member_array("prefixes", |json| {
+                        for addr in roa.content().iter() {
+                            json.array_object(|json| {
+                                json.member_str(
+                                    "prefix",
+                                    format_args!(
+                                        "{}/{}",
+                                        addr.address(), addr.address_length()
+                                    )
+                                );
+                                json.member_raw(
+                                    "maxLength", addr.max_length()
+                                );
+                            })
+                        }
+                    });
+                });
+            }
+            ParsedObject::Aspa(ref aspa) => {
+                json.member_object("aspa", |json| {
+                    json.member_str(
+                        "customer", aspa.content().customer_as()
+                    );
+                    json.member_array("providers", |json| {
+                        for asn in aspa.content().provider_as_set().iter() {
+                            json.array_str(asn);
+                        }
+                    });
+                });
+            }
+        }
+    }
+
+    /// Returns a status string for an optional error.
+    fn status(err: &Option<String>) -> String {
+        match err {
+            Some(err) => format!("failed: {}", err),
+            None => "ok".into(),
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Formats IP resources of one address family.
+fn ip_resources(resources: &IpResources, v4: bool) -> String {
+    if resources.is_inherited() {
+        return "inherit".into()
+    }
+    match resources.to_blocks() {
+        Ok(blocks) => {
+            if v4 {
+                IpBlocksForFamily::v4(&blocks).to_string()
+            }
+            else {
+                IpBlocksForFamily::v6(&blocks).to_string()
+            }
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// Returns the file names and hex-encoded hashes of a manifest.
+fn manifest_files(mft: &Manifest) -> Vec<(String, String)> {
+    mft.content().iter().map(|item| {
+        let (file, hash) = item.into_pair();
+        let mut hex = String::new();
+        append_hex(&hash, &mut hex);
+        (String::from_utf8_lossy(&file).into_owned(), hex)
+    }).collect()
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn object_type_from_name() {
+        assert_eq!(ObjectType::from_name("foo.roa"), Some(ObjectType::Roa));
+        assert_eq!(
+            ObjectType::from_name("a.b.MFT"), Some(ObjectType::Manifest)
+        );
+        assert_eq!(ObjectType::from_name("foo.asa"), Some(ObjectType::Aspa));
+        assert_eq!(ObjectType::from_name("foo"), None);
+        assert_eq!(ObjectType::from_name("foo.gbr"), None);
+    }
+
+    #[test]
+    fn garbage_object() {
+        let report = ObjectReport::new(
+            Bytes::from_static(b"garbage"), Some("foo.roa")
+        );
+        assert_eq!(report.object_type, Some(ObjectType::Roa));
+        assert!(!report.is_acceptable(false));
+        assert!(!report.is_acceptable(true));
+
+        let report = ObjectReport::new(Bytes::from_static(b"garbage"), None);
+        assert_eq!(report.object_type, None);
+        assert!(report.to_json().contains("\"unknown object type\""));
+    }
+}
@@ -2,17 +2,20 @@
 //!
 //! This is a private module. It’s types are re-exported by the parent.
 
+use std::cmp;
 use std::collections::HashSet;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use bytes::Bytes;
-use log::info;
+use log::{error, info, warn};
 use rpki::repository::tal::TalUri;
 use rpki::uri;
-use crate::config::{Config, FallbackPolicy};
+use crate::config::{Config, FallbackPolicy, TransportPreference};
 use crate::error::{Failed, Fatal, RunFailed};
-use crate::metrics::Metrics;
+use crate::metrics::{CacheUsageMetrics, Metrics};
 use crate::engine::CaCert;
+use crate::utils::dump::DumpTarget;
 use super::{rrdp, rsync};
 
 
@@ -42,6 +45,12 @@ pub struct Collector {
 
     /// The policy for falling back from RRDP to rsync.
     rrdp_fallback: FallbackPolicy,
+
+    /// Which transport to try first.
+    transport_preference: TransportPreference,
+
+    /// The maximum size of the cache in bytes.
+    cache_max_size: Option<u64>,
 }
 
 impl Collector {
@@ -68,6 +77,8 @@ impl Collector {
             rrdp: rrdp::Collector::new(config)?,
             rsync: rsync::Collector::new(config)?,
             rrdp_fallback: config.rrdp_fallback,
+            transport_preference: config.transport_preference,
+            cache_max_size: config.cache_max_size,
         })
     }
 
@@ -93,17 +104,51 @@ impl Collector {
     }
 
     /// Starts a new validation run using this collector.
-    pub fn start(&self) -> Run {
-        Run::new(self)
+    ///
+    /// If `deadline` is given, any fetches still in progress at that time
+    /// are abandoned.
+    pub fn start(&self, deadline: Option<Instant>) -> Run<'_> {
+        Run::new(self, deadline)
+    }
+
+    /// Downloads the TAL at the given HTTPS URI.
+    ///
+    /// This uses the HTTP client of the RRDP collector and therefore fails
+    /// if RRDP has been disabled. The collector needs to have been ignited
+    /// before. If downloading fails, diagnostic information is logged and
+    /// `None` is returned.
+    pub fn load_tal(&self, uri: &uri::Https) -> Option<Bytes> {
+        match self.rrdp.as_ref() {
+            Some(rrdp) => rrdp.load_tal(uri),
+            None => {
+                warn!("Cannot fetch TAL {}: RRDP is disabled.", uri);
+                None
+            }
+        }
     }
 
-    /// Dumps the content of the collector and store owned by the engine.
-    pub fn dump(&self, dir: &Path) -> Result<(), Failed> {
+    /// Dumps the content of the collector.
+    pub fn dump(&self, target: &mut DumpTarget) -> Result<(), Failed> {
         if let Some(rrdp) = self.rrdp.as_ref() {
-            rrdp.dump(dir)?;
+            rrdp.dump(target)?;
         }
         if let Some(rsync) = self.rsync.as_ref() {
-            rsync.dump(dir)?;
+            rsync.dump(target)?;
+        }
+        Ok(())
+    }
+
+    /// Restores the content of the collector from a dump.
+    ///
+    /// The dump must have been unpacked into the directory `source`.
+    /// Transports disabled in `config` are skipped. The caller needs to
+    /// make sure that the cache is not currently in use.
+    pub fn restore(config: &Config, source: &Path) -> Result<(), Failed> {
+        if !config.disable_rrdp {
+            rrdp::Collector::restore(config, source)?;
+        }
+        if !config.disable_rsync {
+            rsync::Collector::restore(config, source)?;
         }
         Ok(())
     }
@@ -135,11 +180,13 @@ pub struct Run<'a> {
 
 impl<'a> Run<'a> {
     /// Creates a new validation run for the given collector.
-    fn new(collector: &'a Collector) -> Self {
+    fn new(collector: &'a Collector, deadline: Option<Instant>) -> Self {
         Run {
             collector,
-            rsync: collector.rsync.as_ref().map(|rsync| rsync.start()),
-            rrdp: collector.rrdp.as_ref().map(|rrdp| rrdp.start()),
+            rsync: collector.rsync.as_ref().map(|rsync| {
+                rsync.start(deadline)
+            }),
+            rrdp: collector.rrdp.as_ref().map(|rrdp| rrdp.start(deadline)),
         }
     }
 
@@ -191,6 +238,15 @@ impl<'a> Run<'a> {
     pub fn repository<'s>(
         &'s self, ca: &'s CaCert
     ) -> Result<Option<Repository<'s>>, RunFailed> {
+        if matches!(
+            self.collector.transport_preference,
+            TransportPreference::PreferRsync
+        ) {
+            if let Some(ref rsync) = self.rsync {
+                return self.rsync_repository(rsync, ca)
+            }
+        }
+
         // See if we should and can use RRDP
         if let Some(rrdp_uri) = ca.rpki_notify() {
             if let Some(ref rrdp) = self.rrdp {
@@ -237,7 +293,9 @@ impl<'a> Run<'a> {
 
         // Well, okay, then. How about rsync?
         if let Some(ref rsync) = self.rsync {
-            rsync.load_module(ca.ca_repository());
+            if !rsync.load_module(ca.ca_repository()) {
+                return Ok(None)
+            }
             return Ok(Some(Repository::rsync(rsync)))
         }
 
@@ -245,8 +303,45 @@ impl<'a> Run<'a> {
         Ok(None)
     }
 
+    /// Accesses the repository for the provided CA preferring rsync.
+    ///
+    /// If updating the rsync module fails and the CA also has an RRDP
+    /// repository, that is used instead if it can be updated.
+    fn rsync_repository<'s>(
+        &'s self, rsync: &'s rsync::Run<'a>, ca: &'s CaCert
+    ) -> Result<Option<Repository<'s>>, RunFailed> {
+        if !rsync.load_module(ca.ca_repository()) {
+            return Ok(None)
+        }
+        if rsync.has_failed(ca.ca_repository()) {
+            if let (Some(rrdp_uri), Some(rrdp)) = (
+                ca.rpki_notify(), self.rrdp.as_ref()
+            ) {
+                let (repo, _) = rrdp.load_repository(rrdp_uri)?;
+                if let rrdp::LoadResult::Updated(repo) = repo {
+                    if rsync.note_rrdp_fallback(ca.ca_repository()) {
+                        info!(
+                            "{}: Falling back to RRDP via {}.",
+                            ca.ca_repository(), rrdp_uri
+                        );
+                    }
+                    return Ok(Some(Repository::rrdp(repo)))
+                }
+            }
+        }
+        Ok(Some(Repository::rsync(rsync)))
+    }
+
     /// Returns whether the repository for the PRKI CA has been updated.
     pub fn was_updated(&self, ca: &CaCert) -> bool {
+        if matches!(
+            self.collector.transport_preference,
+            TransportPreference::PreferRsync
+        ) {
+            if let Some(ref rsync) = self.rsync {
+                return rsync.was_updated(ca.ca_repository());
+            }
+        }
         if let Some(rrdp_uri) = ca.rpki_notify() {
             if let Some(ref rrdp) = self.rrdp {
                 return rrdp.was_updated(rrdp_uri);
@@ -261,15 +356,29 @@ impl<'a> Run<'a> {
     /// Cleans the collector.
     ///
     /// Any RRDP repository or rsync module not included in `retain` will
-    /// be deleted.
-    pub fn cleanup(&self, retain: &mut Cleanup) -> Result<(), Failed> {
+    /// be deleted. Returns the disk usage of the repositories kept.
+    pub fn cleanup(
+        &self, retain: &mut Cleanup
+    ) -> Result<CacheUsageMetrics, Failed> {
+        let mut usage = CacheUsageMetrics::new(self.collector.cache_max_size);
         if let Some(rsync) = self.rsync.as_ref() {
-            rsync.cleanup(&mut retain.rsync)?;
+            rsync.cleanup(&mut retain.rsync, &mut usage)?;
         }
         if let Some(rrdp) = self.rrdp.as_ref() {
-            rrdp.cleanup(&mut retain.rrdp)?;
+            rrdp.cleanup(&mut retain.rrdp, &mut usage)?;
         }
-        Ok(())
+        let exceeded = usage.is_exceeded();
+        if exceeded {
+            error!(
+                "The cache uses {} bytes, exceeding its maximum size of {} \
+                 bytes.",
+                usage.total(), usage.max_size.unwrap_or_default()
+            );
+        }
+        if let Some(rrdp) = self.collector.rrdp.as_ref() {
+            rrdp.set_cache_exceeded(exceeded);
+        }
+        Ok(usage)
     }
 }
 
@@ -361,3 +470,51 @@ impl Cleanup {
     }
 }
 
+
+//------------ limit_timeout -------------------------------------------------
+
+/// Returns the timeout for a fetch that has to be finished by `deadline`.
+///
+/// This is the shorter of the configured `timeout` and the time left until
+/// the deadline. If the deadline has passed already, the timeout is zero.
+pub fn limit_timeout(
+    timeout: Option<Duration>, deadline: Option<Instant>
+) -> Option<Duration> {
+    let left = match deadline {
+        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        None => return timeout,
+    };
+    Some(match timeout {
+        Some(timeout) => cmp::min(timeout, left),
+        None => left,
+    })
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_timeout_by_deadline() {
+        let minute = Duration::from_secs(60);
+        let hour = Duration::from_secs(3600);
+        assert_eq!(limit_timeout(None, None), None);
+        assert_eq!(limit_timeout(Some(minute), None), Some(minute));
+
+        let deadline = Instant::now() + hour;
+        assert_eq!(limit_timeout(Some(minute), Some(deadline)), Some(minute));
+        let limited = limit_timeout(None, Some(deadline)).unwrap();
+        assert!(limited <= hour);
+        let limited = limit_timeout(Some(2 * hour), Some(deadline)).unwrap();
+        assert!(limited <= hour);
+
+        let passed = Instant::now() - minute;
+        assert_eq!(
+            limit_timeout(Some(minute), Some(passed)), Some(Duration::ZERO)
+        );
+    }
+}
+
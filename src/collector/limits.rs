@@ -0,0 +1,140 @@
+//! Limits for the content of a single repository.
+//!
+//! This is a private module. Its types are used by both the RRDP and rsync
+//! collectors.
+
+use std::{error, fmt};
+use crate::config::{Config, FilterPolicy};
+
+
+//------------ RepositoryLimits ----------------------------------------------
+
+/// The limits on the number and size of objects in a repository.
+#[derive(Clone, Copy, Debug)]
+pub struct RepositoryLimits {
+    /// The maximum number of objects in a repository.
+    max_objects: Option<u64>,
+
+    /// The maximum overall size of all objects in a repository in bytes.
+    max_size: Option<u64>,
+
+    /// What to do with a repository that exceeds the limits.
+    policy: FilterPolicy,
+}
+
+impl RepositoryLimits {
+    /// Creates the limits from the configuration.
+    pub fn from_config(config: &Config) -> Self {
+        RepositoryLimits {
+            max_objects: config.max_repository_objects,
+            max_size: config.max_repository_size,
+            policy: config.repository_limit_policy,
+        }
+    }
+
+    /// Returns whether any limits are set.
+    pub fn is_enabled(&self) -> bool {
+        self.max_objects.is_some() || self.max_size.is_some()
+    }
+
+    /// Checks a repository’s content against the limits.
+    ///
+    /// The number of objects in the repository is given via `objects` and
+    /// their overall size in bytes via `size`.
+    pub fn check(
+        &self, objects: u64, size: u64
+    ) -> Result<(), LimitExceeded> {
+        if let Some(limit) = self.max_objects {
+            if objects > limit {
+                return Err(LimitExceeded::Objects { objects, limit })
+            }
+        }
+        if let Some(limit) = self.max_size {
+            if size > limit {
+                return Err(LimitExceeded::Size { size, limit })
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the policy for repositories exceeding the limits.
+    pub fn policy(&self) -> FilterPolicy {
+        self.policy
+    }
+}
+
+
+//------------ LimitExceeded -------------------------------------------------
+
+/// A repository has exceeded one of the limits.
+#[derive(Clone, Copy, Debug)]
+pub enum LimitExceeded {
+    /// The repository contains too many objects.
+    Objects { objects: u64, limit: u64 },
+
+    /// The repository’s objects are too large.
+    Size { size: u64, limit: u64 },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitExceeded::Objects { objects, limit } => {
+                write!(
+                    f, "repository has more than {} objects ({})",
+                    limit, objects
+                )
+            }
+            LimitExceeded::Size { size, limit } => {
+                write!(
+                    f, "repository is larger than {} bytes ({})",
+                    limit, size
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for LimitExceeded { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_limits() {
+        let limits = RepositoryLimits {
+            max_objects: Some(10),
+            max_size: None,
+            policy: FilterPolicy::Reject,
+        };
+        assert!(limits.is_enabled());
+        assert!(limits.check(10, u64::MAX).is_ok());
+        assert!(matches!(
+            limits.check(11, 0),
+            Err(LimitExceeded::Objects { objects: 11, limit: 10 })
+        ));
+
+        let limits = RepositoryLimits {
+            max_objects: None,
+            max_size: Some(1000),
+            policy: FilterPolicy::Reject,
+        };
+        assert!(limits.check(u64::MAX, 1000).is_ok());
+        assert!(matches!(
+            limits.check(0, 1001),
+            Err(LimitExceeded::Size { size: 1001, limit: 1000 })
+        ));
+
+        let limits = RepositoryLimits {
+            max_objects: None,
+            max_size: None,
+            policy: FilterPolicy::Reject,
+        };
+        assert!(!limits.is_enabled());
+        assert!(limits.check(u64::MAX, u64::MAX).is_ok());
+    }
+}
@@ -25,9 +25,14 @@
 //  `Collector`, `Run`, and `Repository` types.
 //
 pub use self::base::{Collector, Cleanup, Run, Repository};
-pub use self::rrdp::{HttpStatus, RrdpArchive, SnapshotReason};
+pub use self::rrdp::{
+    Collector as RrdpCollector, HttpStatus, RrdpArchive, SnapshotReason,
+    SpkiPin, UpdateHistory, UpdateRecord,
+};
+pub use self::rsync::Collector as RsyncCollector;
 
 mod base;
+mod limits;
 mod rrdp;
 mod rsync;
 
@@ -1,5 +1,5 @@
 use std::{cmp, io, fs};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
@@ -8,14 +8,17 @@ use chrono::{DateTime, TimeZone, Utc};
 use log::{error, warn};
 use rand::Rng;
 use rpki::{rrdp, uri};
+use tempfile::NamedTempFile;
 use uuid::Uuid;
 use crate::config::Config;
 use crate::error::RunFailed;
+use crate::metrics::RrdpUpdateKind;
 use crate::utils::archive;
 use crate::utils::archive::{
     Archive, ArchiveError, ArchiveStats, FetchError, OpenError, PublishError
 };
-use crate::utils::binio::{Compose, Parse};
+use crate::utils::binio::{Compose, Parse, ParseError};
+use super::update::SnapshotReason;
 
 
 //------------ RrdpArchive ---------------------------------------------------
@@ -86,6 +89,96 @@ impl RrdpArchive {
         Ok(archive.verify()?)
     }
 
+    /// Verifies the archive and returns its rpkiNotify URI and statistics.
+    ///
+    /// Unlike the other methods, this never deletes a broken archive. The
+    /// URI is taken from the stored repository state and will be `None` if
+    /// that is missing or broken.
+    pub fn inspect(
+        path: &Path
+    ) -> Result<(Option<uri::Https>, ArchiveStats), OpenError> {
+        let archive = archive::Archive::<RrdpObjectMeta>::open(path, false)?;
+        let stats = archive.verify()?;
+        let notify = archive.fetch(b"state").ok().and_then(|data| {
+            RepositoryState::parse(&mut data.as_ref()).ok()
+        }).map(|state| state.rpki_notify);
+        Ok((notify, stats))
+    }
+
+    /// Rewrites the archive at `path` into a fresh file.
+    ///
+    /// All objects and the repository state are copied into a new archive
+    /// created as a temporary file in `tmp_dir` which then atomically
+    /// replaces the original file. Thus, `tmp_dir` needs to be on the same
+    /// file system as `path`.
+    ///
+    /// Returns the statistics of the new archive.
+    pub fn compact(
+        path: &Path, tmp_dir: &Path
+    ) -> Result<ArchiveStats, RunFailed> {
+        let source = Self::open(Arc::new(path.into()))?;
+        let state = source.load_state()?;
+
+        let tmp = NamedTempFile::new_in(tmp_dir).map_err(|err| {
+            error!(
+                "Fatal: Failed to create temporary RRDP file in {}: {}",
+                tmp_dir.display(), err
+            );
+            RunFailed::fatal()
+        })?;
+        let file = tmp.as_file().try_clone().map_err(|err| {
+            error!(
+                "Fatal: Failed to access temporary RRDP file {}: {}",
+                tmp.path().display(), err
+            );
+            RunFailed::fatal()
+        })?;
+        let mut target = Self::create_with_file(
+            file, Arc::new(tmp.path().into())
+        )?;
+        target.publish_state(&state)?;
+        for item in source.objects()? {
+            let (uri, content) = item?;
+            target.publish_object(&uri, &content).map_err(|err| {
+                match err {
+                    PublishError::Archive(ArchiveError::Io(err)) => {
+                        error!(
+                            "Fatal: Failed to write to temporary RRDP file \
+                             {}: {}",
+                            tmp.path().display(), err
+                        );
+                        RunFailed::fatal()
+                    }
+                    _ => {
+                        warn!(
+                            "RRDP repository file {} contains duplicate \
+                             object {}.",
+                            path.display(), uri
+                        );
+                        RunFailed::retry()
+                    }
+                }
+            })?;
+        }
+        drop(target);
+        drop(source);
+
+        tmp.persist(path).map_err(|err| {
+            error!(
+                "Fatal: Failed to replace RRDP repository file {}: {}",
+                path.display(), err.error
+            );
+            RunFailed::fatal()
+        })?;
+        Self::verify(path).map_err(|_| {
+            error!(
+                "Fatal: compacted RRDP repository file {} is corrupt.",
+                path.display()
+            );
+            RunFailed::fatal()
+        })
+    }
+
     /// Loads an object from the archive.
     ///
     /// The object is identified by its rsync URI. If the object doesn’t
@@ -125,6 +218,15 @@ impl RrdpArchive {
         })
     }
 
+    /// Returns statistics for the archive.
+    ///
+    /// This needs to walk over the entire archive, so it may be costly.
+    pub fn stats(&self) -> Result<ArchiveStats, RunFailed> {
+        self.archive.verify().map_err(|err| {
+            archive_err(err, self.path.as_ref())
+        })
+    }
+
     /// Iterates over all the objects in the repository.
     pub fn objects(
         &self
@@ -379,19 +481,31 @@ pub struct RepositoryState {
 
     /// Information of the deltas since in the last notificiation.
     pub delta_state: HashMap<u64, rrdp::Hash>,
+
+    /// The outcomes of the most recent update attempts.
+    pub history: UpdateHistory,
+
+    /// The time until which the server asked not to be contacted.
+    ///
+    /// Given as the Unix timestamp in seconds. This is set when the server
+    /// rate-limited the last update attempt.
+    pub retry_after_ts: Option<i64>,
 }
 
 impl RepositoryState {
     /// The current version of the data.
     ///
-    /// This is 1 since version 0 was in the main branch for quite some time.
-    const VERSION: u8 = 1;
+    /// This started at 1 since version 0 was in the main branch for quite
+    /// some time. Version 2 added the update history and version 3 the
+    /// retry time. Older data is still accepted and results in an empty
+    /// history and no retry time.
+    const VERSION: u8 = 3;
 
     /// Reads the state from an IO reader.
     fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
         // Version number.
         let version = u8::parse(reader)?;
-        if version != Self::VERSION {
+        if !(1..=Self::VERSION).contains(&version) {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
                 format!("unexpected version {}", version)
@@ -407,6 +521,18 @@ impl RepositoryState {
             last_modified_ts: Parse::parse(reader)?,
             etag: Parse::parse(reader)?,
             delta_state: Parse::parse(reader)?,
+            history: if version < 2 {
+                UpdateHistory::default()
+            }
+            else {
+                UpdateHistory::parse(reader)?
+            },
+            retry_after_ts: if version < 3 {
+                None
+            }
+            else {
+                Parse::parse(reader)?
+            },
         })
     }
 
@@ -421,9 +547,54 @@ impl RepositoryState {
         self.last_modified_ts.compose(writer)?;
         self.etag.compose(writer)?;
         self.delta_state.compose(writer)?;
+        self.history.compose(writer)?;
+        self.retry_after_ts.compose(writer)?;
         Ok(())
     }
 
+    /// Reads the state of a repository stored by an old version.
+    ///
+    /// Before repositories were kept in archives, each of them was stored
+    /// as a directory tree of individual files with the state in a file
+    /// named `state.bin` in the directory `dir`. Returns `Ok(None)` if
+    /// there is no such file.
+    pub fn read_legacy(dir: &Path) -> Result<Option<Self>, io::Error> {
+        let mut file = match fs::File::open(dir.join("state.bin")) {
+            Ok(file) => io::BufReader::new(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+        Self::parse_legacy(&mut file).map(Some)
+    }
+
+    /// Reads the state stored by an old version from an IO reader.
+    ///
+    /// This encoding only has version 0 and lacks the delta state and
+    /// everything added later.
+    fn parse_legacy(reader: &mut impl io::Read) -> Result<Self, io::Error> {
+        let version = u8::parse(reader)?;
+        if version != 0 {
+            return Err(io::Error::other(
+                format!("unexpected version {}", version)
+            ))
+        }
+
+        Ok(RepositoryState {
+            rpki_notify: Parse::parse(reader)?,
+            session: Parse::parse(reader)?,
+            serial: Parse::parse(reader)?,
+            updated_ts: Parse::parse(reader)?,
+            best_before_ts: Parse::parse(reader)?,
+            last_modified_ts: Parse::parse(reader)?,
+            etag: Parse::parse(reader)?,
+            delta_state: HashMap::new(),
+            history: UpdateHistory::default(),
+            retry_after_ts: None,
+        })
+    }
+
     /// Returns the last update time as proper timestamp.
     ///
     /// Returns `None` if the time cannot be converted into a timestamp for
@@ -446,6 +617,20 @@ impl RepositoryState {
         self.best_before_ts = fallback.best_before().timestamp();
     }
 
+    /// Returns the time until which the server asked not to be contacted.
+    ///
+    /// Returns `None` if there is no such time or if it has passed
+    /// already.
+    pub fn retry_after(&self) -> Option<DateTime<Utc>> {
+        let res = Utc.timestamp_opt(self.retry_after_ts?, 0).single()?;
+        if res > Utc::now() {
+            Some(res)
+        }
+        else {
+            None
+        }
+    }
+
     /// Returns whether this repository should be considered expired.
     ///
     /// If in doubt, this will return `true`.
@@ -466,6 +651,206 @@ impl RepositoryState {
 }
 
 
+//------------ UpdateHistory -------------------------------------------------
+
+/// The outcomes of the most recent update attempts of an RRDP repository.
+///
+/// The history keeps at most [`UpdateHistory::MAX_LEN`] records. When a
+/// new record is added to a full history, the oldest one is dropped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UpdateHistory {
+    /// The records, the most recent one first.
+    records: VecDeque<UpdateRecord>,
+}
+
+impl UpdateHistory {
+    /// The maximum number of records kept.
+    pub const MAX_LEN: usize = 32;
+
+    /// The current version of the encoded history.
+    const VERSION: u8 = 0;
+
+    /// Adds a new record as the most recent one.
+    pub fn push(&mut self, record: UpdateRecord) {
+        self.records.push_front(record);
+        self.records.truncate(Self::MAX_LEN);
+    }
+
+    /// Returns the most recent record if there is one.
+    pub fn latest(&self) -> Option<&UpdateRecord> {
+        self.records.front()
+    }
+
+    /// Returns an iterator over the records, the most recent one first.
+    pub fn iter(&self) -> impl Iterator<Item = &UpdateRecord> + '_ {
+        self.records.iter()
+    }
+
+    /// Reads the history from an IO reader.
+    ///
+    /// The history has its own version so it can change independently of
+    /// the repository state. Records beyond the maximum length are read
+    /// but dropped.
+    fn parse(reader: &mut impl io::Read) -> Result<Self, ParseError> {
+        let version = u8::parse(reader)?;
+        if version != Self::VERSION {
+            return Err(ParseError::format(
+                format!("unexpected update history version {}", version)
+            ))
+        }
+        let len = u32::parse(reader)?;
+        let mut records = VecDeque::new();
+        for _ in 0..len {
+            let record = UpdateRecord::parse(reader)?;
+            if records.len() < Self::MAX_LEN {
+                records.push_back(record);
+            }
+        }
+        Ok(UpdateHistory { records })
+    }
+
+    /// Composes the encoded history.
+    fn compose(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
+        Self::VERSION.compose(writer)?;
+        u32::try_from(self.records.len()).map_err(|_| {
+            io::Error::other("excessive update history")
+        })?.compose(writer)?;
+        for record in &self.records {
+            record.compose(writer)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ UpdateRecord --------------------------------------------------
+
+/// The outcome of a single update attempt of an RRDP repository.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UpdateRecord {
+    /// Unix timestamp in seconds of the time of the update attempt.
+    pub timestamp: i64,
+
+    /// The kind of update performed.
+    ///
+    /// This is `None` if the update failed.
+    pub kind: Option<RrdpUpdateKind>,
+
+    /// Did the update fail because the server rate-limited us?
+    ///
+    /// This is only relevant if `kind` is `None`.
+    pub rate_limited: bool,
+
+    /// The reason why a snapshot was used if one was used.
+    ///
+    /// This can be present even if the update failed, in which case it
+    /// describes why a snapshot update was attempted.
+    pub snapshot_reason: Option<SnapshotReason>,
+}
+
+impl UpdateRecord {
+    /// Creates a new record for an update attempt made now.
+    pub fn now(
+        kind: Option<RrdpUpdateKind>,
+        rate_limited: bool,
+        snapshot_reason: Option<SnapshotReason>,
+    ) -> Self {
+        UpdateRecord {
+            timestamp: Utc::now().timestamp(),
+            kind,
+            rate_limited: kind.is_none() && rate_limited,
+            snapshot_reason,
+        }
+    }
+
+    /// Returns the time of the update attempt.
+    ///
+    /// Returns `None` if the time cannot be converted into a timestamp for
+    /// some reason.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(self.timestamp, 0).single()
+    }
+
+    /// Returns a shorthand code for the outcome of the update.
+    pub fn outcome(&self) -> &'static str {
+        match self.kind {
+            Some(kind) => kind.code(),
+            None if self.rate_limited => "rate-limited",
+            None => "failed",
+        }
+    }
+
+    /// Reads a record from an IO reader.
+    ///
+    /// The kind and snapshot reason are each encoded as a single octet
+    /// with zero meaning `None`. A failed update due to rate limiting is
+    /// encoded as kind 4.
+    fn parse(reader: &mut impl io::Read) -> Result<Self, ParseError> {
+        use SnapshotReason::*;
+
+        let timestamp = i64::parse(reader)?;
+        let (kind, rate_limited) = match u8::parse(reader)? {
+            0 => (None, false),
+            1 => (Some(RrdpUpdateKind::Snapshot), false),
+            2 => (Some(RrdpUpdateKind::Delta), false),
+            3 => (Some(RrdpUpdateKind::NotModified), false),
+            4 => (None, true),
+            kind => {
+                return Err(ParseError::format(
+                    format!("unknown update kind {}", kind)
+                ))
+            }
+        };
+        let snapshot_reason = match u8::parse(reader)? {
+            0 => None,
+            1 => Some(NewRepository),
+            2 => Some(NewSession),
+            3 => Some(BadDeltaSet),
+            4 => Some(LargeDeltaSet),
+            5 => Some(DeltaMutation),
+            6 => Some(LargeSerial),
+            7 => Some(OutdatedLocal),
+            8 => Some(ConflictingDelta),
+            9 => Some(TooManyDeltas),
+            10 => Some(CorruptArchive),
+            reason => {
+                return Err(ParseError::format(
+                    format!("unknown snapshot reason {}", reason)
+                ))
+            }
+        };
+        Ok(UpdateRecord { timestamp, kind, rate_limited, snapshot_reason })
+    }
+
+    /// Composes the encoded record.
+    fn compose(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
+        use SnapshotReason::*;
+
+        self.timestamp.compose(writer)?;
+        match self.kind {
+            None if self.rate_limited => 4u8,
+            None => 0,
+            Some(RrdpUpdateKind::Snapshot) => 1,
+            Some(RrdpUpdateKind::Delta) => 2,
+            Some(RrdpUpdateKind::NotModified) => 3,
+        }.compose(writer)?;
+        match self.snapshot_reason {
+            None => 0u8,
+            Some(NewRepository) => 1,
+            Some(NewSession) => 2,
+            Some(BadDeltaSet) => 3,
+            Some(LargeDeltaSet) => 4,
+            Some(DeltaMutation) => 5,
+            Some(LargeSerial) => 6,
+            Some(OutdatedLocal) => 7,
+            Some(ConflictingDelta) => 8,
+            Some(TooManyDeltas) => 9,
+            Some(CorruptArchive) => 10,
+        }.compose(writer)
+    }
+}
+
+
 //------------ FallbackTime --------------------------------------------------
 
 /// Parameters for calculating the best-before time of repositories.
@@ -553,11 +938,143 @@ mod test {
                 (18, rrdp::Hash::from_data(b"123")),
                 (19, rrdp::Hash::from_data(b"332")),
             ].iter().cloned().collect(),
+            history: UpdateHistory::default(),
+            retry_after_ts: Some(1_700_000_300),
         };
         let mut buf = Vec::new();
         state.compose(&mut buf).unwrap();
         let parsed = RepositoryState::parse(&mut buf.as_slice()).unwrap();
         assert_eq!(state, parsed);
     }
+
+    #[test]
+    fn parse_legacy_repository_state() {
+        let state = RepositoryState {
+            rpki_notify: uri::Https::from_str(
+                "https://foo.bar/baz"
+            ).unwrap(),
+            session: Uuid::from_u128(0xa1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8u128),
+            serial: 12,
+            updated_ts: 1_700_000_000,
+            best_before_ts: 1_700_003_600,
+            last_modified_ts: Some(1_699_999_000),
+            etag: Some(Bytes::from_static(b"\"tag\"")),
+            delta_state: HashMap::new(),
+            history: UpdateHistory::default(),
+            retry_after_ts: None,
+        };
+        let mut buf = Vec::new();
+        0u8.compose(&mut buf).unwrap();
+        state.rpki_notify.compose(&mut buf).unwrap();
+        state.session.compose(&mut buf).unwrap();
+        state.serial.compose(&mut buf).unwrap();
+        state.updated_ts.compose(&mut buf).unwrap();
+        state.best_before_ts.compose(&mut buf).unwrap();
+        state.last_modified_ts.compose(&mut buf).unwrap();
+        state.etag.compose(&mut buf).unwrap();
+        let parsed = RepositoryState::parse_legacy(
+            &mut buf.as_slice()
+        ).unwrap();
+        assert_eq!(state, parsed);
+
+        // The current encoding is refused.
+        let mut buf = Vec::new();
+        state.compose(&mut buf).unwrap();
+        assert!(RepositoryState::parse_legacy(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn repository_state_retry_after() {
+        let mut state = RepositoryState {
+            rpki_notify: uri::Https::from_str(
+                "https://foo.bar/baz"
+            ).unwrap(),
+            session: Uuid::from_u128(1),
+            serial: 12,
+            updated_ts: 0,
+            best_before_ts: 0,
+            last_modified_ts: None,
+            etag: None,
+            delta_state: HashMap::new(),
+            history: UpdateHistory::default(),
+            retry_after_ts: None,
+        };
+        assert_eq!(state.retry_after(), None);
+        state.retry_after_ts = Some(Utc::now().timestamp() - 10);
+        assert_eq!(state.retry_after(), None);
+        let ts = Utc::now().timestamp() + 600;
+        state.retry_after_ts = Some(ts);
+        assert_eq!(
+            state.retry_after().map(|time| time.timestamp()), Some(ts)
+        );
+    }
+
+    #[test]
+    fn compose_parse_update_history() {
+        let mut state = RepositoryState {
+            rpki_notify: uri::Https::from_str(
+                "https://foo.bar/baz"
+            ).unwrap(),
+            session: Uuid::from_u128(0xa1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8u128),
+            serial: 12,
+            updated_ts: 1_700_000_000,
+            best_before_ts: 1_700_003_600,
+            last_modified_ts: None,
+            etag: Some(Bytes::from_static(b"\"tag\"")),
+            delta_state: HashMap::new(),
+            history: UpdateHistory::default(),
+            retry_after_ts: None,
+        };
+
+        // A version 1 state loads with an empty history.
+        let mut buf = Vec::new();
+        state.compose(&mut buf).unwrap();
+        let history_len = 1 + 4; // version and length of empty history
+        let retry_len = 1; // empty option
+        buf.truncate(buf.len() - history_len - retry_len);
+        buf[0] = 1;
+        let parsed = RepositoryState::parse(&mut buf.as_slice()).unwrap();
+        assert_eq!(state, parsed);
+
+        // The history is bounded and survives a round trip.
+        for i in 0..(UpdateHistory::MAX_LEN + 3) {
+            state.history.push(UpdateRecord {
+                timestamp: i as i64,
+                kind: match i % 4 {
+                    0 => None,
+                    1 => Some(RrdpUpdateKind::Snapshot),
+                    2 => Some(RrdpUpdateKind::Delta),
+                    _ => Some(RrdpUpdateKind::NotModified),
+                },
+                rate_limited: i % 8 == 0,
+                snapshot_reason: (i % 4 == 1).then_some(
+                    SnapshotReason::DeltaMutation
+                ),
+            });
+        }
+        assert_eq!(state.history.iter().count(), UpdateHistory::MAX_LEN);
+        assert_eq!(
+            state.history.latest().map(|record| record.timestamp),
+            Some(UpdateHistory::MAX_LEN as i64 + 2)
+        );
+        let mut buf = Vec::new();
+        state.compose(&mut buf).unwrap();
+        let parsed = RepositoryState::parse(&mut buf.as_slice()).unwrap();
+        assert_eq!(state, parsed);
+
+        // Rate-limited failures survive the round trip, too.
+        assert_eq!(
+            parsed.history.iter().filter(|record| {
+                record.outcome() == "rate-limited"
+            }).count(),
+            UpdateHistory::MAX_LEN / 8
+        );
+
+        // Unknown history versions are rejected.
+        let pos = buf.len() - history_len - retry_len
+            - 10 * UpdateHistory::MAX_LEN;
+        buf[pos] = 0xff;
+        assert!(RepositoryState::parse(&mut buf.as_slice()).is_err());
+    }
 }
 
@@ -0,0 +1,268 @@
+//! Backing off from failing RRDP repositories.
+//!
+//! If fetching the notification file of a repository fails because of a
+//! connection error or a server error, the repository is not contacted
+//! again for an exponentially growing interval, bounded by the
+//! `rrdp-max-backoff` option. A Retry-After header in the error response
+//! is honoured if it asks for a longer delay. A successful update resets
+//! the backoff.
+//!
+//! The state is kept in a file in the cache directory so that it survives
+//! restarts. It is discarded when starting with `--fresh`.
+
+use std::{fs, io};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, TimeZone, Utc};
+use log::{info, warn};
+use rpki::uri;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::metrics::RrdpBackoffMetrics;
+use crate::utils::sync::Mutex;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The name of the file holding the backoff state.
+const FILE_NAME: &str = "rrdp-backoff.json";
+
+/// The delay after the first failure.
+///
+/// The delay doubles with every subsequent failure.
+const BASE_DELAY: Duration = Duration::from_secs(300);
+
+/// The time after which an entry that was not updated is dropped.
+///
+/// Entries are updated whenever a repository is tried, so this only
+/// affects repositories that are not used anymore.
+const PRUNE_AFTER: Duration = Duration::from_secs(86400);
+
+
+//------------ Backoff -------------------------------------------------------
+
+/// The backoff state of all RRDP repositories.
+#[derive(Debug)]
+pub struct Backoff {
+    /// The path of the file to store the state in.
+    path: PathBuf,
+
+    /// The maximum delay.
+    ///
+    /// If this is zero, backoff is disabled.
+    max_delay: Duration,
+
+    /// The state of the backed-off repositories keyed by rpkiNotify URI.
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Backoff {
+    /// Creates the backoff state, loading it from the cache directory.
+    ///
+    /// If the state file cannot be read, starts with an empty state.
+    pub fn load(config: &Config) -> Self {
+        let path = config.cache_dir.join(FILE_NAME);
+        let entries = if config.fresh || config.rrdp_max_backoff.is_zero() {
+            Self::remove_file(&path);
+            HashMap::new()
+        }
+        else {
+            Self::read_file(&path)
+        };
+        Backoff {
+            path,
+            max_delay: config.rrdp_max_backoff,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Reads the state file.
+    fn read_file(path: &Path) -> HashMap<String, Entry> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Failed to read RRDP backoff state {}: {}",
+                        path.display(), err
+                    );
+                }
+                return HashMap::new()
+            }
+        };
+        match serde_json::from_slice(&data) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!(
+                    "Ignoring invalid RRDP backoff state {}: {}",
+                    path.display(), err
+                );
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Removes the state file if it exists.
+    fn remove_file(path: &Path) {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "Failed to delete RRDP backoff state {}: {}",
+                    path.display(), err
+                );
+            }
+        }
+    }
+
+    /// Returns the time until which the repository is backed off.
+    ///
+    /// Returns `None` if the repository can be contacted.
+    pub fn until(&self, rpki_notify: &uri::Https) -> Option<DateTime<Utc>> {
+        let entries = self.entries.lock();
+        let until = entries.get(rpki_notify.as_str())?.until()?;
+        if until > Utc::now() {
+            Some(until)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Records a successful update of the repository.
+    pub fn success(&self, rpki_notify: &uri::Https) {
+        self.entries.lock().remove(rpki_notify.as_str());
+    }
+
+    /// Records a failed update of the repository.
+    ///
+    /// The `retry_after` argument contains the delay requested by the
+    /// server if any.
+    pub fn failure(
+        &self, rpki_notify: &uri::Https, retry_after: Option<Duration>
+    ) {
+        if self.max_delay.is_zero() {
+            return
+        }
+        let mut entries = self.entries.lock();
+        let entry = entries.entry(rpki_notify.as_str().into()).or_default();
+        entry.failures = entry.failures.saturating_add(1);
+        let delay = Self::delay(entry.failures, retry_after, self.max_delay);
+        let until = Utc::now() + chrono::Duration::from_std(delay).unwrap_or(
+            chrono::Duration::zero()
+        );
+        entry.until = until.timestamp();
+        info!(
+            "RRDP {}: Backing off until {} after {} failed update{}.",
+            rpki_notify, until, entry.failures,
+            if entry.failures == 1 { "" } else { "s" }
+        );
+    }
+
+    /// Returns the delay after the given number of failures.
+    fn delay(
+        failures: u32, retry_after: Option<Duration>, max_delay: Duration
+    ) -> Duration {
+        let shift = failures.saturating_sub(1).min(16);
+        let delay = BASE_DELAY.saturating_mul(1 << shift);
+        delay.max(retry_after.unwrap_or_default()).min(max_delay)
+    }
+
+    /// Writes the state to its file.
+    ///
+    /// Entries that have expired long ago are dropped before.
+    pub fn save(&self) {
+        if self.max_delay.is_zero() {
+            return
+        }
+        let mut entries = self.entries.lock();
+        let cutoff = (
+            Utc::now() - chrono::Duration::from_std(PRUNE_AFTER).unwrap()
+        ).timestamp();
+        entries.retain(|_, entry| entry.until > cutoff);
+        if entries.is_empty() {
+            Self::remove_file(&self.path);
+            return
+        }
+        let data = match serde_json::to_vec(&*entries) {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("Failed to encode RRDP backoff state: {}", err);
+                return
+            }
+        };
+        if let Err(err) = fs::write(&self.path, data) {
+            warn!(
+                "Failed to write RRDP backoff state {}: {}",
+                self.path.display(), err
+            );
+        }
+    }
+
+    /// Returns the metrics for all currently backed-off repositories.
+    pub fn metrics(&self) -> Vec<RrdpBackoffMetrics> {
+        let now = Utc::now();
+        let mut res: Vec<_> = self.entries.lock().iter().filter_map(
+            |(uri, entry)| {
+                let until = entry.until()?;
+                if until <= now {
+                    return None
+                }
+                Some(RrdpBackoffMetrics {
+                    notify_uri: uri.clone(),
+                    failures: entry.failures,
+                    until,
+                })
+            }
+        ).collect();
+        res.sort_by(|left, right| left.notify_uri.cmp(&right.notify_uri));
+        res
+    }
+}
+
+
+//------------ Entry ---------------------------------------------------------
+
+/// The backoff state of a single repository.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+struct Entry {
+    /// The number of consecutive failed updates.
+    failures: u32,
+
+    /// The Unix timestamp until which the repository is not contacted.
+    until: i64,
+}
+
+impl Entry {
+    fn until(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(self.until, 0).single()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delay() {
+        let max = Duration::from_secs(3600);
+        let secs = |failures, retry_after: Option<u64>| {
+            Backoff::delay(
+                failures, retry_after.map(Duration::from_secs), max
+            ).as_secs()
+        };
+        assert_eq!(secs(1, None), 300);
+        assert_eq!(secs(2, None), 600);
+        assert_eq!(secs(3, None), 1200);
+        assert_eq!(secs(4, None), 2400);
+        assert_eq!(secs(5, None), 3600);
+        assert_eq!(secs(u32::MAX, None), 3600);
+        assert_eq!(secs(1, Some(900)), 900);
+        assert_eq!(secs(3, Some(900)), 1200);
+        assert_eq!(secs(1, Some(86400)), 3600);
+    }
+}
+
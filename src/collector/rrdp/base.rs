@@ -1,27 +1,39 @@
 use std::{cmp, fs, io};
 use std::collections::{HashMap, HashSet};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, Weak};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 use bytes::Bytes;
+use chrono::Utc;
 use log::{debug, error, info, warn};
 use rpki::uri;
 use rpki::crypto::DigestAlgorithm;
 use rpki::rrdp::{DeltaInfo, DeltaListError, NotificationFile};
+use serde::Deserialize;
 use tempfile::NamedTempFile;
-use crate::config::Config;
-use crate::error::{Fatal, RunFailed};
-use crate::metrics::{Metrics, RrdpRepositoryMetrics};
+use uuid::Uuid;
+use crate::collector::limits::RepositoryLimits;
+use crate::config::{Config, FilterPolicy};
+use crate::error::{Failed, Fatal, RunFailed};
+use crate::metrics::{
+    CacheUsageMetrics, Metrics, RrdpRepositoryMetrics, RrdpUpdateKind,
+};
 use crate::utils::fatal;
 use crate::utils::archive::{ArchiveError, OpenError};
-use crate::utils::dump::DumpRegistry;
+use crate::utils::dump::{DumpRegistry, DumpTarget};
 use crate::utils::json::JsonBuilder;
-use crate::utils::sync::{Mutex, RwLock};
+use crate::utils::lock::LockFile;
+use crate::utils::sync::{Mutex, RwLock, Semaphore};
 use crate::utils::uri::UriExt;
-use super::archive::{FallbackTime, RrdpArchive, RepositoryState};
+use super::archive::{
+    FallbackTime, RrdpArchive, RepositoryState, UpdateHistory, UpdateRecord,
+};
+use super::backoff::Backoff;
 use super::http::{HttpClient, HttpStatus};
 use super::update::{
-    DeltaUpdate, Notification, SnapshotError, SnapshotReason, SnapshotUpdate
+    DeltaError, DeltaUpdate, Notification, SnapshotError, SnapshotReason,
+    SnapshotUpdate
 };
 
 
@@ -38,6 +50,18 @@ pub struct Collector {
 
     /// Various configuration options.
     config: RrdpConfig,
+
+    /// The backoff state of failing repositories.
+    backoff: Backoff,
+
+    /// Did the cache exceed its maximum size during the last cleanup?
+    cache_exceeded: AtomicBool,
+
+    /// A shared lock on the working directory.
+    ///
+    /// This keeps offline maintenance such as archive compaction from
+    /// messing with the archives while we are using them.
+    _lock: LockFile,
 }
 
 impl Collector {
@@ -82,13 +106,56 @@ impl Collector {
         if config.disable_rrdp {
             return Ok(None)
         }
+        let working_dir = Self::create_working_dir(config)?;
+        let lock = match LockFile::shared(&Self::lock_path(config))? {
+            Some(lock) => lock,
+            None => {
+                error!(
+                    "Fatal: RRDP cache in {} is locked for maintenance.",
+                    working_dir.display()
+                );
+                return Err(Fatal)
+            }
+        };
         Ok(Some(Self {
-            working_dir: Self::create_working_dir(config)?,
+            working_dir,
             http: HttpClient::new(config)?,
             config: config.into(),
+            backoff: Backoff::load(config),
+            cache_exceeded: AtomicBool::new(false),
+            _lock: lock,
         }))
     }
 
+    /// Returns the path of the lock file for the working directory.
+    pub fn lock_path(config: &Config) -> PathBuf {
+        config.cache_dir.join("rrdp.lock")
+    }
+
+    /// Returns the paths of all repository archives.
+    pub fn archive_paths(config: &Config) -> Result<Vec<PathBuf>, Fatal> {
+        let working_dir = config.cache_dir.join("rrdp");
+        let mut res = Vec::new();
+        let dir = match fatal::read_existing_dir(&working_dir)? {
+            Some(dir) => dir,
+            None => return Ok(res)
+        };
+        for entry in dir {
+            let entry = entry?;
+            if !entry.is_dir() || entry.file_name() == "tmp" {
+                continue;
+            }
+            for entry in fatal::read_dir(entry.path())? {
+                let entry = entry?;
+                if entry.is_file() {
+                    res.push(entry.into_path())
+                }
+            }
+        }
+        res.sort();
+        Ok(res)
+    }
+
     pub fn ignite(&mut self) -> Result<(), Fatal> {
         self.http.ignite()
     }
@@ -142,15 +209,42 @@ impl Collector {
         Ok(())
     }
 
-    pub fn start(&self) -> Run {
-        Run::new(self)
+    pub fn start(&self, deadline: Option<Instant>) -> Run<'_> {
+        Run::new(self, deadline)
+    }
+
+    /// Downloads a TAL identified by an HTTPS URI.
+    ///
+    /// The collector needs to have been ignited. Returns `None` and logs
+    /// the reason if downloading fails.
+    pub fn load_tal(&self, uri: &uri::Https) -> Option<Bytes> {
+        let mut response = match self.http.response(uri, None, false) {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Failed to fetch TAL {}: {}", uri, err);
+                return None
+            }
+        };
+        if let (Some(len), Some(limit)) = (
+            response.content_length(), self.config.max_object_size
+        ) {
+            if len > limit {
+                warn!("TAL {} exceeds size limit. Ignoring.", uri);
+                return None
+            }
+        }
+        let mut bytes = Vec::new();
+        if let Err(err) = response.copy_to(&mut bytes) {
+            warn!("Failed to fetch TAL {}: {}", uri, err);
+            return None
+        }
+        Some(Bytes::from(bytes))
     }
 
     #[allow(clippy::mutable_key_type)]
-    pub fn dump(&self, dir: &Path) -> Result<(), Fatal> {
-        let dir = dir.join("rrdp");
-        debug!("Dumping RRDP collector content to {}", dir.display());
-        let mut registry = DumpRegistry::new(dir);
+    pub fn dump(&self, target: &mut DumpTarget) -> Result<(), Fatal> {
+        debug!("Dumping RRDP collector content.");
+        let mut registry = DumpRegistry::new(PathBuf::from("rrdp"));
         let mut states = HashMap::new();
         for entry in fatal::read_dir(&self.working_dir)? {
             let entry = entry?;
@@ -161,16 +255,22 @@ impl Collector {
                 let entry = entry?;
                 if entry.is_file() {
                     if let Err(err) = self.dump_repository(
-                        entry.into_path().into(), &mut registry, &mut states
+                        entry.into_path().into(), &mut registry, &mut states,
+                        target,
                     ) {
                         if err.is_fatal() {
                             return Err(Fatal)
                         }
                     }
                 }
+                else if entry.is_dir() {
+                    self.dump_legacy_tree(
+                        entry.path(), &mut registry, &mut states, target
+                    )?;
+                }
             }
         }
-        self.dump_repository_json(registry, states)?;
+        self.dump_repository_json(registry, states, target)?;
         debug!("RRDP collector dump complete.");
         Ok(())
     }
@@ -182,6 +282,7 @@ impl Collector {
         repo_path: Arc<PathBuf>,
         registry: &mut DumpRegistry,
         state_registry: &mut HashMap<uri::Https, RepositoryState>,
+        target: &mut DumpTarget,
     ) -> Result<(), RunFailed> {
         let archive = RrdpArchive::open(repo_path.clone())?;
         let state = archive.load_state()?;
@@ -193,26 +294,60 @@ impl Collector {
             let path = object_path.join(
                 uri.canonical_module().as_ref()
             ).join(uri.path());
-            fatal::create_parent_all(&path)?;
-            fatal::write_file(&path, &data)?;
+            target.write_file(&path, &data)?;
         }
 
         state_registry.insert(state.rpki_notify.clone(), state);
         Ok(())
     }
 
+    /// Dumps an RRDP repository stored as a directory tree.
+    ///
+    /// Such trees are left behind by old versions until they are converted
+    /// into an archive when the repository is next used. Trees without a
+    /// usable state or with an archive next to them are skipped.
+    #[allow(clippy::mutable_key_type)]
+    fn dump_legacy_tree(
+        &self,
+        path: &Path,
+        registry: &mut DumpRegistry,
+        state_registry: &mut HashMap<uri::Https, RepositoryState>,
+        target: &mut DumpTarget,
+    ) -> Result<(), Fatal> {
+        if path.with_extension("bin").exists() {
+            return Ok(())
+        }
+        let state = match RepositoryState::read_legacy(path) {
+            Ok(Some(state)) => state,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                warn!(
+                    "Skipping RRDP repository directory {}: \
+                     failed to read state: {}",
+                    path.display(), err
+                );
+                return Ok(())
+            }
+        };
+        let target_path = registry.get_repo_path(Some(&state.rpki_notify));
+        target.copy_dir_all(&target_path.join("rsync"), &path.join("rsync"))?;
+        state_registry.insert(state.rpki_notify.clone(), state);
+        Ok(())
+    }
+
     /// Dumps the repositories.json.
     #[allow(clippy::mutable_key_type)]
     fn dump_repository_json(
         &self,
         repos: DumpRegistry,
         states: HashMap<uri::Https, RepositoryState>,
+        target: &mut DumpTarget,
     ) -> Result<(), Fatal> {
-        fatal::create_dir_all(repos.base_dir())?;
         let path = repos.base_dir().join("repositories.json");
-        if let Err(err) = fs::write(
+        target.write_file(
             &path, 
             JsonBuilder::build(|builder| {
+                builder.member_raw("version", DUMP_VERSION);
                 builder.member_array("repositories", |builder| {
                     for (key, value) in repos.rrdp_uris() {
                         builder.array_object(|builder| {
@@ -242,12 +377,294 @@ impl Collector {
                         builder.member_str("type", "rsync");
                     });
                 })
-            })
-        ) {
-            error!( "Failed to write {}: {}", path.display(), err);
-            return Err(Fatal)
+            }).as_bytes()
+        )?;
+        Ok(())
+    }
+
+    /// Restores the collector content from a dump.
+    ///
+    /// The dump needs to have been created by [`dump`][Self::dump] into
+    /// the directory `source`. Each RRDP repository listed in its
+    /// repositories.json is written into a fresh archive together with
+    /// the session and serial, so the next update can use deltas.
+    ///
+    /// The caller needs to make sure that the cache is not in use.
+    pub fn restore(config: &Config, source: &Path) -> Result<(), Fatal> {
+        let source = source.join("rrdp");
+        let path = source.join("repositories.json");
+        let json = match fatal::read_existing_file(&path)? {
+            Some(json) => json,
+            None => {
+                if source.exists() {
+                    error!(
+                        "Fatal: {} is missing. Is this a Routinator dump?",
+                        path.display()
+                    );
+                    return Err(Fatal)
+                }
+                debug!("No RRDP collector content in dump.");
+                return Ok(())
+            }
+        };
+        let repos = RestoreRepository::from_json(&source, &json)?;
+        debug!("Restoring RRDP collector content.");
+        let working_dir = Self::create_working_dir(config)?;
+        let fallback = FallbackTime::from_config(config);
+        for repo in repos {
+            Self::restore_repository(&working_dir, &repo, fallback)?;
         }
+        debug!("RRDP collector restore complete.");
+        Ok(())
+    }
 
+    /// Restores a single RRDP repository.
+    fn restore_repository(
+        working_dir: &Path,
+        repo: &RestoreRepository,
+        fallback: FallbackTime,
+    ) -> Result<(), Fatal> {
+        let (file, tmp_path) = Self::create_temp_file(working_dir)?;
+        let mut archive = RrdpArchive::create_with_file(
+            file, tmp_path.clone()
+        ).map_err(|_| Fatal)?;
+        if let Some(dir) = fatal::read_existing_dir(&repo.objects)? {
+            Self::restore_objects(&mut archive, dir, "rsync://")?;
+        }
+        let mut state = RepositoryState {
+            rpki_notify: repo.rpki_notify.clone(),
+            session: repo.session,
+            serial: repo.serial,
+            updated_ts: 0,
+            best_before_ts: 0,
+            last_modified_ts: None,
+            etag: None,
+            delta_state: HashMap::new(),
+            history: UpdateHistory::default(),
+            retry_after_ts: None,
+        };
+        state.touch(fallback);
+        archive.publish_state(&state).map_err(|_| Fatal)?;
+        drop(archive);
+        let path = Self::archive_path(working_dir, &repo.rpki_notify)?;
+        fatal::rename(&tmp_path, &path)?;
+        debug!("Restored RRDP repository {}.", repo.rpki_notify);
+        Ok(())
+    }
+
+    /// Adds all objects in a dumped directory tree to an archive.
+    ///
+    /// The rsync URI for the directory is given via `base`.
+    fn restore_objects(
+        archive: &mut RrdpArchive,
+        dir: fatal::ReadDir<'_>,
+        base: &str,
+    ) -> Result<(), Fatal> {
+        for entry in dir {
+            let entry = entry?;
+            let name = match entry.file_name().to_str() {
+                Some(name) => name,
+                None => {
+                    error!(
+                        "Fatal: invalid file name {} in RRDP dump.",
+                        entry.path().display()
+                    );
+                    return Err(Fatal)
+                }
+            };
+            if entry.is_dir() {
+                Self::restore_objects(
+                    archive,
+                    fatal::read_dir(entry.path())?,
+                    &format!("{base}{name}/"),
+                )?;
+            }
+            else if entry.is_file() {
+                let uri = match uri::Rsync::from_string(
+                    format!("{base}{name}")
+                ) {
+                    Ok(uri) => uri,
+                    Err(_) => {
+                        error!(
+                            "Fatal: invalid object path {} in RRDP dump.",
+                            entry.path().display()
+                        );
+                        return Err(Fatal)
+                    }
+                };
+                let data = fatal::read_file(entry.path())?;
+                if archive.publish_object(&uri, &data).is_err() {
+                    error!(
+                        "Fatal: failed to add {} to RRDP archive {}.",
+                        uri, archive.path().display()
+                    );
+                    return Err(Fatal)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts a repository stored as a directory tree into an archive.
+    ///
+    /// Old versions stored each RRDP repository as a directory tree of
+    /// individual files in place of the archive at `path`, i.e., in a
+    /// directory named like the archive minus its extension. If there is
+    /// such a tree with a usable state but no archive yet, the tree is
+    /// converted into an archive that keeps session and serial so the next
+    /// update can use deltas. The tree is removed afterwards.
+    ///
+    /// Trees that cannot be converted are left alone. They are deleted
+    /// during the next cleanup and the repository is refetched from its
+    /// snapshot. Since this is only an optimization, failing to convert a
+    /// tree is never fatal.
+    fn convert_legacy_tree(
+        working_dir: &Path, path: &Path, rpki_notify: &uri::Https,
+    ) {
+        let tree = path.with_extension("");
+        if path.exists() || !tree.is_dir() {
+            return
+        }
+        let state = match RepositoryState::read_legacy(&tree) {
+            Ok(Some(state)) if state.rpki_notify == *rpki_notify => state,
+            Ok(_) => {
+                info!(
+                    "RRDP {}: no usable state in old repository directory \
+                     {}. Not converting.",
+                    rpki_notify, tree.display()
+                );
+                return
+            }
+            Err(err) => {
+                warn!(
+                    "RRDP {}: failed to read state of old repository \
+                     directory {}: {}. Not converting.",
+                    rpki_notify, tree.display(), err
+                );
+                return
+            }
+        };
+        let (file, tmp_path) = match Self::create_temp_file(working_dir) {
+            Ok(some) => some,
+            Err(_) => return
+        };
+        if Self::write_legacy_archive(file, &tmp_path, &tree, &state, path)
+            .is_err()
+        {
+            warn!(
+                "RRDP {}: failed to convert old repository directory {}. \
+                 Falling back to snapshot.",
+                rpki_notify, tree.display()
+            );
+            if let Err(err) = fs::remove_file(tmp_path.as_ref()) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Failed to remove temporary RRDP file {}: {}",
+                        tmp_path.display(), err
+                    );
+                }
+            }
+            return
+        }
+        if let Err(err) = fs::remove_dir_all(&tree) {
+            warn!(
+                "RRDP {}: failed to remove old repository directory {}: {}",
+                rpki_notify, tree.display(), err
+            );
+        }
+        info!(
+            "RRDP {}: converted old repository directory {} into archive.",
+            rpki_notify, tree.display()
+        );
+    }
+
+    /// Writes the archive for a legacy directory tree.
+    ///
+    /// The archive is written to the temporary file `file` at `tmp_path`
+    /// and moved to `path` once complete. Problems are logged as warnings.
+    fn write_legacy_archive(
+        file: fs::File, tmp_path: &Arc<PathBuf>,
+        tree: &Path, state: &RepositoryState, path: &Path,
+    ) -> Result<(), Failed> {
+        let mut archive = RrdpArchive::create_with_file(
+            file, tmp_path.clone()
+        ).map_err(|_| Failed)?;
+        Self::convert_legacy_objects(
+            &mut archive, &tree.join("rsync"), "rsync://"
+        )?;
+        archive.publish_state(state).map_err(|_| Failed)?;
+        drop(archive);
+        fs::rename(tmp_path.as_ref(), path).map_err(|err| {
+            warn!(
+                "Failed to move {} to {}: {}",
+                tmp_path.display(), path.display(), err
+            );
+            Failed
+        })
+    }
+
+    /// Adds all objects in a legacy directory tree to an archive.
+    ///
+    /// This is similar to [`restore_objects`][Self::restore_objects] but
+    /// logs problems as warnings only. A missing directory is fine.
+    fn convert_legacy_objects(
+        archive: &mut RrdpArchive,
+        dir: &Path,
+        base: &str,
+    ) -> Result<(), Failed> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to open directory {}: {}", dir.display(), err
+                );
+                return Err(Failed)
+            }
+        };
+        for entry in entries {
+            let entry = entry.map_err(|err| {
+                warn!("Failed to read directory {}: {}", dir.display(), err);
+                Failed
+            })?;
+            let path = entry.path();
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => {
+                    warn!("Invalid file name {}.", path.display());
+                    return Err(Failed)
+                }
+            };
+            if path.is_dir() {
+                Self::convert_legacy_objects(
+                    archive, &path, &format!("{base}{name}/"),
+                )?;
+            }
+            else if path.is_file() {
+                let uri = match uri::Rsync::from_string(
+                    format!("{base}{name}")
+                ) {
+                    Ok(uri) => uri,
+                    Err(_) => {
+                        warn!("Invalid object path {}.", path.display());
+                        return Err(Failed)
+                    }
+                };
+                let data = fs::read(&path).map_err(|err| {
+                    warn!("Failed to read file {}: {}", path.display(), err);
+                    Failed
+                })?;
+                if archive.publish_object(&uri, &data).is_err() {
+                    warn!(
+                        "Failed to add {} to RRDP archive {}.",
+                        uri, archive.path().display()
+                    );
+                    return Err(Failed)
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -257,15 +674,44 @@ impl Collector {
     fn repository_path(
         &self, rpki_notify: &uri::Https
     ) -> Result<PathBuf, Fatal> {
-        let mut path = self.working_dir.clone();
-        path.push(rpki_notify.canonical_authority().as_ref());
-        if let Err(err) = fs::create_dir_all(&path) {
-            error!(
-                "Failed to create RRDP archive directory {}: {}",
-                path.display(), err
-            );
-            return Err(Fatal)
+        Self::archive_path(&self.working_dir, rpki_notify)
+    }
+
+    /// Returns the path of the archive for a repository.
+    ///
+    /// Unlike the paths used during validation, this doesn’t create the
+    /// directory the archive lives in, so the file may not exist.
+    pub fn repository_archive_path(
+        config: &Config, rpki_notify: &uri::Https
+    ) -> PathBuf {
+        Self::archive_file_path(&config.cache_dir.join("rrdp"), rpki_notify)
+    }
+
+    /// Returns the path for a repository in the given working directory.
+    ///
+    /// Creates the directory for the archive if necessary.
+    fn archive_path(
+        working_dir: &Path, rpki_notify: &uri::Https
+    ) -> Result<PathBuf, Fatal> {
+        let path = Self::archive_file_path(working_dir, rpki_notify);
+        if let Some(dir) = path.parent() {
+            if let Err(err) = fs::create_dir_all(dir) {
+                error!(
+                    "Failed to create RRDP archive directory {}: {}",
+                    dir.display(), err
+                );
+                return Err(Fatal)
+            }
         }
+        Ok(path)
+    }
+
+    /// Returns the archive file for a repository in the given working dir.
+    fn archive_file_path(
+        working_dir: &Path, rpki_notify: &uri::Https
+    ) -> PathBuf {
+        let mut path = working_dir.to_path_buf();
+        path.push(rpki_notify.canonical_authority().as_ref());
 
         let alg = DigestAlgorithm::sha256();
         let mut dir = String::with_capacity(
@@ -278,13 +724,20 @@ impl Collector {
         );
         dir.push_str(".bin");
         path.push(&dir);
-        Ok(path)
+        path
     }
 
     fn temp_file(
         &self
     ) -> Result<(fs::File, Arc<PathBuf>), Fatal> {
-        let base = self.working_dir.join("tmp");
+        Self::create_temp_file(&self.working_dir)
+    }
+
+    /// Creates a temporary file in the given working directory.
+    fn create_temp_file(
+        working_dir: &Path
+    ) -> Result<(fs::File, Arc<PathBuf>), Fatal> {
+        let base = working_dir.join("tmp");
         if let Err(err) = fs::create_dir_all(&base) {
             error!(
                 "Failed to create RRDP temporary directory {}: {}",
@@ -312,6 +765,14 @@ impl Collector {
         Ok((file, path.into()))
     }
 
+    /// Sets whether the cache has exceeded its maximum size.
+    ///
+    /// While it has, snapshots may not be downloaded, depending on the
+    /// configuration.
+    pub fn set_cache_exceeded(&self, exceeded: bool) {
+        self.cache_exceeded.store(exceeded, Ordering::Relaxed)
+    }
+
     pub(super) fn http(&self) -> &HttpClient {
         &self.http
     }
@@ -322,6 +783,136 @@ impl Collector {
 }
 
 
+//------------ RestoreRepository ---------------------------------------------
+
+/// The version of the RRDP dump layout.
+///
+/// This is included in the repositories.json of a dump. Restoring refuses
+/// dumps with a newer version. Dumps without a version use the layout of
+/// version 1.
+const DUMP_VERSION: u64 = 1;
+
+/// An RRDP repository to be restored from a dump.
+struct RestoreRepository {
+    /// The directory in the dump with the repository’s objects.
+    objects: PathBuf,
+
+    /// The rpkiNotify URI of the repository.
+    rpki_notify: uri::Https,
+
+    /// The session ID of the repository.
+    session: Uuid,
+
+    /// The serial number of the repository.
+    serial: u64,
+}
+
+impl RestoreRepository {
+    /// Returns the RRDP repositories listed in a repositories.json.
+    ///
+    /// The `base` is the RRDP directory of the dump. Checks that the
+    /// content is as expected and refuses to continue otherwise, so that
+    /// nothing is written from a dump we don’t understand.
+    fn from_json(base: &Path, json: &[u8]) -> Result<Vec<Self>, Fatal> {
+        #[derive(Deserialize)]
+        struct Json {
+            version: Option<u64>,
+            repositories: Vec<JsonRepository>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct JsonRepository {
+            path: String,
+            #[serde(rename = "type")]
+            repo_type: String,
+            rpki_notify: Option<String>,
+            serial: Option<u64>,
+            session: Option<String>,
+        }
+
+        let path = base.join("repositories.json");
+        let json: Json = match serde_json::from_slice(json) {
+            Ok(json) => json,
+            Err(err) => {
+                error!(
+                    "Fatal: failed to parse {}: {}", path.display(), err
+                );
+                return Err(Fatal)
+            }
+        };
+        if let Some(version) = json.version {
+            if version > DUMP_VERSION {
+                error!(
+                    "Fatal: RRDP dump in {} has version {} but only \
+                     version {} or lower is supported.",
+                    base.display(), version, DUMP_VERSION
+                );
+                return Err(Fatal)
+            }
+        }
+
+        let mut res = Vec::new();
+        for repo in json.repositories {
+            match repo.repo_type.as_str() {
+                "rrdp" => { }
+                "rsync" => continue,
+                _ => {
+                    error!(
+                        "Fatal: unknown repository type '{}' in {}.",
+                        repo.repo_type, path.display()
+                    );
+                    return Err(Fatal)
+                }
+            }
+            let rel = Path::new(&repo.path);
+            if !rel.components().all(|item| {
+                matches!(item, Component::Normal(_))
+            }) {
+                error!(
+                    "Fatal: invalid repository path '{}' in {}.",
+                    repo.path, path.display()
+                );
+                return Err(Fatal)
+            }
+            let rpki_notify = repo.rpki_notify.and_then(|uri| {
+                uri::Https::from_string(uri).ok()
+            });
+            let rpki_notify = match rpki_notify {
+                Some(uri) => uri,
+                None => {
+                    error!(
+                        "Fatal: missing or invalid rpkiNotify for \
+                         repository '{}' in {}.",
+                        repo.path, path.display()
+                    );
+                    return Err(Fatal)
+                }
+            };
+            let session = repo.session.and_then(|session| {
+                Uuid::parse_str(&session).ok()
+            });
+            let (session, serial) = match (session, repo.serial) {
+                (Some(session), Some(serial)) => (session, serial),
+                _ => {
+                    warn!(
+                        "Skipping RRDP repository {} without session \
+                         and serial in dump.",
+                        rpki_notify
+                    );
+                    continue;
+                }
+            };
+            res.push(RestoreRepository {
+                objects: base.join(rel).join("rsync"),
+                rpki_notify, session, serial,
+            })
+        }
+        Ok(res)
+    }
+}
+
+
 //------------ Run -----------------------------------------------------------
 
 /// Using the collector for a single validation run.
@@ -340,18 +931,33 @@ pub struct Run<'a> {
     /// allowed to actually update.
     running: RwLock<HashMap<uri::Https, Arc<Mutex<()>>>>,
 
+    /// Limits the number of repositories updated concurrently.
+    ///
+    /// If this is `None`, there is no limit.
+    updates: Option<Semaphore>,
+
     /// The server metrics.
     metrics: Mutex<Vec<RrdpRepositoryMetrics>>,
+
+    /// The time by which all requests must have finished.
+    ///
+    /// Requests still running at this time are aborted.
+    deadline: Option<Instant>,
 }
 
 impl<'a> Run<'a> {
     /// Creates a new runner.
-    fn new(collector: &'a Collector) -> Self {
+    fn new(collector: &'a Collector, deadline: Option<Instant>) -> Self {
         Self {
             collector,
             updated: Default::default(),
             running: Default::default(),
+            updates: match collector.config().max_concurrent_updates {
+                0 => None,
+                limit => Some(Semaphore::new(limit)),
+            },
             metrics: Default::default(),
+            deadline,
         }
     }
 
@@ -360,7 +966,9 @@ impl<'a> Run<'a> {
     /// This just downloads the file. It is not cached since that is done
     /// by the store anyway.
     pub fn load_ta(&self, uri: &uri::Https) -> Option<Bytes> {
-        let mut response = match self.collector.http.response(uri, false) {
+        let mut response = match self.collector.http.response(
+            uri, self.deadline, false
+        ) {
             Ok(response) => response,
             Err(_) => return None,
         };
@@ -434,9 +1042,18 @@ impl<'a> Run<'a> {
             (LoadResult::Unavailable, metrics)
         }
         else {
-            RepositoryUpdate::new(
-                self.collector, rpki_notify
-            )?.try_update()?
+            // Wait for our turn if there is a limit on concurrent updates.
+            let queued = Instant::now();
+            let _permit = self.updates.as_ref().map(|updates| {
+                updates.acquire()
+            });
+            let queue_duration = queued.elapsed();
+
+            let (repo, mut metrics) = RepositoryUpdate::new(
+                self.collector, rpki_notify, self.deadline
+            )?.try_update()?;
+            metrics.queue_duration = queue_duration;
+            (repo, metrics)
         };
 
         // Insert metrics.
@@ -453,10 +1070,14 @@ impl<'a> Run<'a> {
         Ok((res, true))
     }
 
+    /// Cleans the collector only keeping the repositories in `retain`.
+    ///
+    /// The disk usage of the repositories kept is added to `usage`.
     #[allow(clippy::mutable_key_type)]
     pub fn cleanup(
         &self,
-        retain: &mut HashSet<uri::Https>
+        retain: &mut HashSet<uri::Https>,
+        usage: &mut CacheUsageMetrics,
     ) -> Result<(), Fatal> {
         // Add all the RRDP repositories we’ve tried during this run to be
         // kept.
@@ -481,7 +1102,7 @@ impl<'a> Run<'a> {
                     self.cleanup_tmp(entry.path())?
                 }
                 else {
-                    self.cleanup_authority(entry.path(), retain)?;
+                    self.cleanup_authority(entry.path(), retain, usage)?;
                 }
             }
         }
@@ -521,16 +1142,24 @@ impl<'a> Run<'a> {
     pub fn cleanup_authority(
         &self,
         path: &Path,
-        retain: &HashSet<uri::Https>
+        retain: &HashSet<uri::Https>,
+        usage: &mut CacheUsageMetrics,
     ) -> Result<(), Fatal> {
         for entry in fatal::read_dir(path)? {
             let entry = entry?;
             if entry.is_file() {
+                let size = entry.len();
                 let entry_path = Arc::new(entry.into_path());
                 let keep = match self.keep_repository(
                     entry_path.clone(), retain
                 ) {
-                    Ok(some) => some,
+                    Ok(Some(rpki_notify)) => {
+                        usage.repositories.push(
+                            (rpki_notify.to_string(), size)
+                        );
+                        true
+                    }
+                    Ok(None) => false,
                     Err(err) if err.should_retry() => {
                         // The RrdpArchive code has deleted the file already
                         // in this case, so we mustn’t do it again, so we
@@ -550,7 +1179,16 @@ impl<'a> Run<'a> {
                 }
             }
             else {
-                // This isn’t supposed to be here. Make it go away.
+                // This isn’t supposed to be here. Most likely, it is a
+                // repository stored as a tree of individual files by an
+                // old version that hasn’t been converted into an archive
+                // because it wasn’t used or couldn’t be converted. Its
+                // content is refetched via the snapshot if necessary, so
+                // make it go away.
+                info!(
+                    "Deleting stray RRDP repository directory {}.",
+                    entry.path().display()
+                );
                 if let Err(err) = fs::remove_dir_all(entry.path()) {
                     error!(
                         "Fatal: failed to delete stray directory {}: {}",
@@ -564,19 +1202,42 @@ impl<'a> Run<'a> {
     }
 
     /// Returns whether we should keep a repository.
+    ///
+    /// Returns the rpkiNotify URI of the repository if it should be kept.
     #[allow(clippy::mutable_key_type)]
     pub fn keep_repository(
         &self,
         path: Arc<PathBuf>,
         retain: &HashSet<uri::Https>
-    ) -> Result<bool, RunFailed> {
+    ) -> Result<Option<uri::Https>, RunFailed> {
         let archive = RrdpArchive::open(path)?;
         let state = archive.load_state()?;
-        Ok(retain.contains(&state.rpki_notify))
+        if retain.contains(&state.rpki_notify) {
+            Ok(Some(state.rpki_notify))
+        }
+        else {
+            Ok(None)
+        }
     }
 
     pub fn done(self, metrics: &mut Metrics) {
-        metrics.rrdp = self.metrics.into_inner()
+        metrics.rrdp = self.metrics.into_inner();
+        self.collector.backoff.save();
+        metrics.rrdp_backoff = self.collector.backoff.metrics();
+        metrics.rrdp_dns = self.collector.http.take_dns_metrics();
+        self.collector.http.prune_responses();
+        if self.collector.config.clock_skew_policy == FilterPolicy::Accept {
+            return
+        }
+        metrics.detect_clock_skew(self.collector.config.clock_skew_threshold);
+        if let Some(skew) = metrics.clock_skew {
+            error!(
+                "The local clock appears to be {} seconds {} the clocks \
+                 of the RRDP servers. Objects may wrongly be considered \
+                 expired or not yet valid. Please check the system time.",
+                skew.abs(), if skew > 0 { "ahead of" } else { "behind" }
+            );
+        }
     }
 }
 
@@ -603,6 +1264,21 @@ pub struct RrdpConfig {
 
     /// The maximum length of the delta list in a notification file.
     pub max_delta_list_len: usize,
+
+    /// The maximum number of concurrent updates or zero for no limit.
+    pub max_concurrent_updates: usize,
+
+    /// The limits for the content of a repository.
+    pub limits: RepositoryLimits,
+
+    /// Refuse snapshot downloads while the cache is too large?
+    pub refuse_snapshots: bool,
+
+    /// The offset of the local clock considered a clock skew.
+    pub clock_skew_threshold: Duration,
+
+    /// How to deal with a skewed local clock.
+    pub clock_skew_policy: FilterPolicy,
 }
 
 impl<'a> From<&'a Config> for RrdpConfig {
@@ -613,6 +1289,11 @@ impl<'a> From<&'a Config> for RrdpConfig {
             max_object_size: config.max_object_size,
             max_delta_count: config.rrdp_max_delta_count,
             max_delta_list_len: config.rrdp_max_delta_list_len,
+            max_concurrent_updates: config.rrdp_max_concurrent_updates,
+            limits: RepositoryLimits::from_config(config),
+            refuse_snapshots: config.cache_max_size_refuse_snapshots,
+            clock_skew_threshold: config.clock_skew_threshold,
+            clock_skew_policy: config.clock_skew_policy,
         }
     }
 }
@@ -713,23 +1394,44 @@ impl Repository {
 
 //------------ RepositoryUpdate ----------------------------------------------
 
+/// The maximum delay requested by a rate-limiting server we honour.
+///
+/// This keeps a misbehaving server from locking us out for good.
+const MAX_RATE_LIMIT: Duration = Duration::from_secs(86400);
+
 /// All the state necessary to update a repository.
 struct RepositoryUpdate<'a> {
     collector: &'a Collector,
     path: Arc<PathBuf>,
     rpki_notify: &'a uri::Https,
     metrics: RrdpRepositoryMetrics,
+
+    /// The delay requested by the server when the update failed.
+    retry_after: Option<Duration>,
+
+    /// The delay requested by the server when it rate-limited us.
+    ///
+    /// This is set if any request of the update was answered with a
+    /// status code of 429 or 503 and a Retry-After header.
+    rate_limit: Option<Duration>,
+
+    /// The time by which the update must have finished.
+    deadline: Option<Instant>,
 }
 
 impl<'a> RepositoryUpdate<'a> {
     fn new(
         collector: &'a Collector, rpki_notify: &'a uri::Https,
+        deadline: Option<Instant>,
     ) -> Result<Self, RunFailed> {
         Ok(Self {
             collector,
             path: Arc::new(collector.repository_path(rpki_notify)?),
             rpki_notify,
             metrics: RrdpRepositoryMetrics::new(rpki_notify.clone()),
+            retry_after: None,
+            rate_limit: None,
+            deadline,
         })
     }
 
@@ -737,6 +1439,9 @@ impl<'a> RepositoryUpdate<'a> {
     fn try_update(
         mut self
     ) -> Result<(LoadResult<Repository>, RrdpRepositoryMetrics), RunFailed> {
+        Collector::convert_legacy_tree(
+            &self.collector.working_dir, &self.path, self.rpki_notify
+        );
         let current = match RrdpArchive::try_open(self.path.clone()) {
             Ok(Some(archive)) => {
                 let state = archive.load_state()?;
@@ -763,8 +1468,42 @@ impl<'a> RepositoryUpdate<'a> {
         let best_before = current.as_ref().and_then(|current|
             current.1.best_before()
         );
-
-        let is_updated = self.update(current)?;
+        let mut history = current.as_ref().map(|current| {
+            current.1.history.clone()
+        }).unwrap_or_default();
+
+        let retry_after = current.as_ref().and_then(|current| {
+            current.1.retry_after()
+        });
+        let backoff = self.collector.backoff.until(self.rpki_notify);
+        let is_updated = if let Some(until) = retry_after {
+            info!(
+                "RRDP {}: Skipping update, server asked to retry after {}.",
+                self.rpki_notify, until
+            );
+            self.metrics.notify_status = HttpStatus::RateLimited;
+            false
+        }
+        else if let Some(until) = backoff {
+            info!(
+                "RRDP {}: Skipping update, backing off until {}.",
+                self.rpki_notify, until
+            );
+            self.metrics.notify_status = HttpStatus::BackedOff;
+            false
+        }
+        else {
+            let is_updated = self.update(current)?;
+            self.update_backoff(is_updated);
+            history.push(UpdateRecord::now(
+                if is_updated { self.metrics.update_kind } else { None },
+                self.rate_limit.is_some(),
+                self.metrics.snapshot_reason,
+            ));
+            self.store_outcome(&history)?;
+            is_updated
+        };
+        self.metrics.history = history;
 
         self.metrics.duration = SystemTime::now().duration_since(start_time);
 
@@ -792,6 +1531,65 @@ impl<'a> RepositoryUpdate<'a> {
         Ok((res, self.metrics))
     }
 
+    /// Stores the outcome of the update with the repository state.
+    ///
+    /// This is the update history and, if the server rate-limited us, the
+    /// time until which it asked not to be contacted again. Both can only
+    /// be kept if there is a local copy of the repository. If there isn’t,
+    /// or if it is broken, nothing happens.
+    fn store_outcome(
+        &self, history: &UpdateHistory
+    ) -> Result<(), RunFailed> {
+        let retry_after_ts = self.rate_limit.map(|delay| {
+            let delay = chrono::Duration::from_std(
+                delay.min(MAX_RATE_LIMIT)
+            ).unwrap_or(chrono::Duration::zero());
+            (Utc::now() + delay).timestamp()
+        });
+        let res = RrdpArchive::try_open(
+            self.path.clone()
+        ).and_then(|archive| {
+            let mut archive = match archive {
+                Some(archive) => archive,
+                None => return Ok(()),
+            };
+            let mut state = archive.load_state()?;
+            state.history = history.clone();
+            state.retry_after_ts = retry_after_ts;
+            archive.update_state(&state)
+        });
+        match res {
+            Err(err) if !err.should_retry() => Err(err),
+            _ => Ok(())
+        }
+    }
+
+    /// Updates the backoff state after an update attempt.
+    ///
+    /// Only failures to fetch the notification file because of a
+    /// connection problem or a server error lead to backing off. Any
+    /// successful fetch resets it. Rate limiting by the server is handled
+    /// via the repository state instead and leaves the backoff alone.
+    fn update_backoff(&self, is_updated: bool) {
+        if self.rate_limit.is_some() {
+            return
+        }
+        if is_updated || self.metrics.notify_status.is_success() {
+            self.collector.backoff.success(self.rpki_notify);
+            return
+        }
+        let backoff = match self.metrics.notify_status {
+            HttpStatus::Error => true,
+            HttpStatus::Response(status) => status.is_server_error(),
+            _ => false,
+        };
+        if backoff {
+            self.collector.backoff.failure(
+                self.rpki_notify, self.retry_after
+            );
+        }
+    }
+
     /// Performs the actual update.
     ///
     /// Returns `Ok(false)` if the update failed.
@@ -799,29 +1597,48 @@ impl<'a> RepositoryUpdate<'a> {
         &mut self,
         current: Option<(RrdpArchive, RepositoryState)>,
     ) -> Result<bool, RunFailed> {
-        let notify = match Notification::get(
+        let (notify, info) = Notification::get(
             &self.collector.http, self.rpki_notify,
-            current.as_ref().map(|x| &x.1),
-            &mut self.metrics.notify_status,
-            self.collector.config.max_delta_list_len,
-        ) {
+            current.as_ref().map(|x| &x.1), &self.collector.config,
+            self.deadline,
+        );
+        self.metrics.notify_status = info.status;
+        self.metrics.remote_addr = info.remote_addr;
+        self.metrics.clock_offset = info.clock_offset;
+        self.retry_after = info.retry_after;
+        let notify = match notify {
             Ok(Some(notify)) => notify,
             Ok(None) => {
                 self.not_modified(current)?;
                 return Ok(true)
             }
-            Err(_) => return Ok(false)
+            Err(_) => {
+                if self.metrics.notify_status.is_rate_limit() {
+                    self.rate_limit = self.retry_after;
+                }
+                return Ok(false)
+            }
         };
 
         self.metrics.serial = Some(notify.content().serial());
         self.metrics.session = Some(notify.content().session_id());
+        self.metrics.notify_last_modified = notify.last_modified();
+        self.metrics.notify_processed = Some(Utc::now());
+        self.metrics.serial_lag = current.as_ref().and_then(|(_, state)| {
+            (state.session == notify.content().session_id()).then(|| {
+                notify.content().serial().saturating_sub(state.serial)
+            })
+        });
 
         if let Some((archive, state)) = current {
             match self.delta_update(&notify, archive, state)? {
-                None => {
+                DeltaResult::Updated => {
                     return Ok(true)
                 }
-                Some(reason) => {
+                DeltaResult::Rejected | DeltaResult::RateLimited => {
+                    return Ok(false)
+                }
+                DeltaResult::Snapshot(reason) => {
                     self.metrics.snapshot_reason = Some(reason)
                 }
             }
@@ -829,6 +1646,16 @@ impl<'a> RepositoryUpdate<'a> {
         else {
             self.metrics.snapshot_reason = Some(SnapshotReason::NewRepository);
         }
+        if self.collector.config.refuse_snapshots
+            && self.collector.cache_exceeded.load(Ordering::Relaxed)
+        {
+            warn!(
+                "RRDP {}: not downloading snapshot since the cache exceeds \
+                 its maximum size.",
+                self.rpki_notify
+            );
+            return Ok(false)
+        }
         self.snapshot_update(&notify)
     }
 
@@ -838,11 +1665,15 @@ impl<'a> RepositoryUpdate<'a> {
         current: Option<(RrdpArchive, RepositoryState)>,
     ) -> Result<(), RunFailed> {
         info!("RRDP {}: Not modified.", self.rpki_notify);
+        self.metrics.update_kind = Some(RrdpUpdateKind::NotModified);
+        self.metrics.notify_processed = Some(Utc::now());
         if let Some((mut archive, mut state)) = current {
             // Copy serial and session to the metrics so they will still be
             // present.
             self.metrics.serial = Some(state.serial);
             self.metrics.session = Some(state.session);
+            self.metrics.notify_last_modified = state.last_modified();
+            self.metrics.serial_lag = Some(0);
             state.touch(self.collector.config().fallback_time);
             archive.update_state(&state)?;
         }
@@ -861,7 +1692,8 @@ impl<'a> RepositoryUpdate<'a> {
         let (file, path) = self.collector.temp_file()?;
         let mut archive = RrdpArchive::create_with_file(file, path.clone())?;
         if let Err(err) = SnapshotUpdate::new(
-            self.collector, &mut archive, notify, &mut self.metrics
+            self.collector, &mut archive, notify, &mut self.metrics,
+            self.deadline,
         ).try_update() {
             if let SnapshotError::RunFailed(err) = err {
                 debug!("RRDP {}: snapshot update failed.", self.rpki_notify);
@@ -869,9 +1701,13 @@ impl<'a> RepositoryUpdate<'a> {
             }
             else {
                 warn!(
+                    repository = self.rpki_notify.as_str();
                     "RRDP {}: failed to process snapshot file {}: {}",
                     self.rpki_notify, notify.content().snapshot().uri(), err
                 );
+                if let SnapshotError::RateLimited(_, delay) = err {
+                    self.rate_limit = Some(delay);
+                }
                 return Ok(false)
             }
         }
@@ -900,6 +1736,7 @@ impl<'a> RepositoryUpdate<'a> {
         }
 
         debug!("RRDP {}: snapshot update completed.", self.rpki_notify);
+        self.metrics.update_kind = Some(RrdpUpdateKind::Snapshot);
         Ok(true)
     }
 
@@ -907,14 +1744,14 @@ impl<'a> RepositoryUpdate<'a> {
     ///
     /// Takes information of the available deltas from `notify`. May not do
     /// anything at all if the repository is up-to-date. Returns whether the
-    /// update succeeded. If `Ok(Some(reason))` is returned, a snapshot update
-    /// should be tried next because of the reason given.
+    /// update succeeded, whether the updated repository was rejected, or
+    /// whether a snapshot update should be tried next.
     fn delta_update(
         &mut self,
         notify: &Notification,
         mut archive: RrdpArchive,
         state: RepositoryState,
-    ) -> Result<Option<SnapshotReason>, RunFailed> {
+    ) -> Result<DeltaResult, RunFailed> {
         if let Err(err) = notify.content().delta_status() {
             match err {
                 DeltaListError::Oversized => {
@@ -922,18 +1759,20 @@ impl<'a> RepositoryUpdate<'a> {
                         "RRDP {}: Overly large delta set in notification file",
                         self.rpki_notify
                     );
-                    return Ok(Some(SnapshotReason::LargeDeltaSet));
+                    return Ok(DeltaResult::Snapshot(
+                        SnapshotReason::LargeDeltaSet
+                    ));
                 }
             }
         }
 
         if let Err(reason) = notify.check_deltas(&state) {
-            return Ok(Some(reason))
+            return Ok(DeltaResult::Snapshot(reason))
         }
 
         let deltas = match self.calc_deltas(notify.content(), &state) {
             Ok(deltas) => deltas,
-            Err(reason) => return Ok(Some(reason)),
+            Err(reason) => return Ok(DeltaResult::Snapshot(reason)),
         };
 
         if !deltas.is_empty() {
@@ -946,15 +1785,44 @@ impl<'a> RepositoryUpdate<'a> {
                 if let Err(err) = DeltaUpdate::new(
                     self.collector, &mut archive,
                     notify.content().session_id(),
-                    info, &mut self.metrics
+                    info, &mut self.metrics, self.deadline,
                 ).try_update() {
                     warn!(
+                        repository = self.rpki_notify.as_str();
                         "RRDP {}: failed to process delta: {}",
                         self.rpki_notify, err,
                     );
-                    return Ok(Some(SnapshotReason::ConflictingDelta))
+                    if let DeltaError::RateLimited(_, delay) = err {
+                        // Falling back to the snapshot would only make
+                        // things worse. Keep the deltas applied so far
+                        // instead.
+                        self.rate_limit = Some(delay);
+                        if i > 0 {
+                            self.store_partial_deltas(
+                                &mut archive, state, &deltas[i - 1]
+                            )?;
+                        }
+                        return Ok(DeltaResult::RateLimited)
+                    }
+                    return Ok(DeltaResult::Snapshot(
+                        SnapshotReason::ConflictingDelta
+                    ))
                 }
             }
+
+            // Only check the limits if something has changed. Since we
+            // don’t update the state if the limits are exceeded, the next
+            // update will try again.
+            match self.check_limits(&archive) {
+                Ok(true) => { }
+                Ok(false) => return Ok(DeltaResult::Rejected),
+                Err(err) if err.should_retry() => {
+                    return Ok(DeltaResult::Snapshot(
+                        SnapshotReason::CorruptArchive
+                    ))
+                }
+                Err(err) => return Err(err),
+            }
         }
 
         // We are up-to-date now, so we can replace the state file with one
@@ -964,7 +1832,9 @@ impl<'a> RepositoryUpdate<'a> {
             &notify.to_repository_state(self.collector.config.fallback_time)
         ) {
             if err.should_retry() {
-                return Ok(Some(SnapshotReason::CorruptArchive))
+                return Ok(DeltaResult::Snapshot(
+                    SnapshotReason::CorruptArchive
+                ))
             }
             else {
                 return Err(err)
@@ -972,7 +1842,67 @@ impl<'a> RepositoryUpdate<'a> {
         }
 
         debug!("RRDP {}: Delta update completed.", self.rpki_notify);
-        Ok(None)
+        self.metrics.update_kind = Some(
+            if deltas.is_empty() { RrdpUpdateKind::NotModified }
+            else { RrdpUpdateKind::Delta }
+        );
+        Ok(DeltaResult::Updated)
+    }
+
+    /// Updates the state after only some of the deltas were applied.
+    ///
+    /// The state’s serial is set to that of `last`, the last delta that
+    /// was applied. Since the notification file needs to be fetched again
+    /// to continue, the ETag and Last-Modified values are dropped. The
+    /// best before time is not changed as the repository isn’t current.
+    fn store_partial_deltas(
+        &self,
+        archive: &mut RrdpArchive,
+        mut state: RepositoryState,
+        last: &DeltaInfo,
+    ) -> Result<(), RunFailed> {
+        state.serial = last.serial();
+        state.etag = None;
+        state.last_modified_ts = None;
+        match archive.update_state(&state) {
+            Err(err) if !err.should_retry() => Err(err),
+            _ => Ok(())
+        }
+    }
+
+    /// Checks the repository limits for an archive.
+    ///
+    /// Returns whether the archive is acceptable.
+    fn check_limits(
+        &mut self, archive: &RrdpArchive
+    ) -> Result<bool, RunFailed> {
+        let limits = self.collector.config().limits;
+        if !limits.is_enabled() {
+            return Ok(true)
+        }
+        let stats = archive.stats()?;
+        let err = match limits.check(stats.object_count, stats.object_size) {
+            Ok(()) => return Ok(true),
+            Err(err) => err,
+        };
+        self.metrics.limit_exceeded = true;
+        match limits.policy() {
+            FilterPolicy::Reject => {
+                warn!(
+                    repository = self.rpki_notify.as_str();
+                    "RRDP {}: {}. Rejecting update.", self.rpki_notify, err
+                );
+                return Ok(false)
+            }
+            FilterPolicy::Warn => {
+                warn!(
+                    repository = self.rpki_notify.as_str();
+                    "RRDP {}: {}.", self.rpki_notify, err
+                );
+            }
+            FilterPolicy::Accept => { }
+        }
+        Ok(true)
     }
 
     /// Calculates the slice of deltas to follow for updating.
@@ -1041,3 +1971,189 @@ impl<'a> RepositoryUpdate<'a> {
     }
 }
 
+
+//------------ DeltaResult ---------------------------------------------------
+
+/// The result of a delta update.
+enum DeltaResult {
+    /// The repository was successfully updated.
+    Updated,
+
+    /// The updated repository exceeds the limits and was rejected.
+    Rejected,
+
+    /// The server rate-limited a delta request.
+    RateLimited,
+
+    /// A snapshot update should be tried for the given reason.
+    Snapshot(SnapshotReason),
+}
+
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use crate::utils::binio::Compose;
+
+    /// Creates a repository tree as stored by an old version.
+    fn legacy_tree(dir: &Path, rpki_notify: &uri::Https) {
+        let mut state = Vec::new();
+        0u8.compose(&mut state).unwrap();
+        rpki_notify.compose(&mut state).unwrap();
+        Uuid::from_u128(7).compose(&mut state).unwrap();
+        12u64.compose(&mut state).unwrap();
+        1_700_000_000i64.compose(&mut state).unwrap();
+        1_700_003_600i64.compose(&mut state).unwrap();
+        None::<i64>.compose(&mut state).unwrap();
+        None::<Bytes>.compose(&mut state).unwrap();
+        let objects = dir.join("rsync/foo.bar/module");
+        fs::create_dir_all(&objects).unwrap();
+        fs::write(dir.join("state.bin"), state).unwrap();
+        fs::write(objects.join("ca.mft"), b"manifest").unwrap();
+    }
+
+    #[test]
+    fn convert_legacy_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let rpki_notify = uri::Https::from_str(
+            "https://foo.bar/notify.xml"
+        ).unwrap();
+        let path = Collector::archive_path(dir.path(), &rpki_notify).unwrap();
+        let tree = path.with_extension("");
+        legacy_tree(&tree, &rpki_notify);
+
+        // A tree for a different repository is left alone.
+        let other = uri::Https::from_str(
+            "https://foo.bar/other.xml"
+        ).unwrap();
+        Collector::convert_legacy_tree(dir.path(), &path, &other);
+        assert!(tree.is_dir());
+        assert!(!path.exists());
+
+        Collector::convert_legacy_tree(dir.path(), &path, &rpki_notify);
+        assert!(!tree.exists());
+        let archive = RrdpArchive::open(Arc::new(path)).unwrap();
+        let state = archive.load_state().unwrap();
+        assert_eq!(state.rpki_notify, rpki_notify);
+        assert_eq!(state.session, Uuid::from_u128(7));
+        assert_eq!(state.serial, 12);
+        let objects = archive.objects().unwrap().map(|item| {
+            item.unwrap()
+        }).collect::<Vec<_>>();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(
+            objects[0].0.as_str(), "rsync://foo.bar/module/ca.mft"
+        );
+        assert_eq!(objects[0].1.as_ref(), b"manifest");
+    }
+
+    #[test]
+    fn convert_legacy_tree_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let rpki_notify = uri::Https::from_str(
+            "https://foo.bar/notify.xml"
+        ).unwrap();
+        let path = Collector::archive_path(dir.path(), &rpki_notify).unwrap();
+        let tree = path.with_extension("");
+        legacy_tree(&tree, &rpki_notify);
+        fs::write(
+            tree.join("rsync/foo.bar/module/b\u{e4}d.roa"), b"roa"
+        ).unwrap();
+
+        // The tree is left for cleanup and the temporary file is removed.
+        Collector::convert_legacy_tree(dir.path(), &path, &rpki_notify);
+        assert!(tree.is_dir());
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(dir.path().join("tmp")).unwrap().count(), 0);
+    }
+
+    /// Creates a dump with a single RRDP repository in `dir`.
+    fn restore_dump(dir: &Path, version: u64) {
+        let objects = dir.join("rrdp/foo.bar/rsync/foo.bar/module");
+        fs::create_dir_all(&objects).unwrap();
+        fs::write(objects.join("ca.mft"), b"manifest").unwrap();
+        fs::write(
+            dir.join("rrdp/repositories.json"),
+            format!(
+                r#"{{
+                    "version": {version},
+                    "repositories": [
+                        {{
+                            "path": "foo.bar",
+                            "type": "rrdp",
+                            "rpkiNotify": "https://foo.bar/notify.xml",
+                            "serial": 12,
+                            "session": "{}"
+                        }},
+                        {{ "path": "rsync", "type": "rsync" }}
+                    ]
+                }}"#,
+                Uuid::from_u128(7)
+            )
+        ).unwrap();
+    }
+
+    #[test]
+    fn restore_repository_state() {
+        let dump = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        restore_dump(dump.path(), DUMP_VERSION);
+        let config = Config {
+            cache_dir: cache.path().into(),
+            .. Default::default()
+        };
+        Collector::restore(&config, dump.path()).unwrap();
+
+        let rpki_notify = uri::Https::from_str(
+            "https://foo.bar/notify.xml"
+        ).unwrap();
+        let archive = RrdpArchive::open(Arc::new(
+            Collector::repository_archive_path(&config, &rpki_notify)
+        )).unwrap();
+        let state = archive.load_state().unwrap();
+        assert_eq!(state.rpki_notify, rpki_notify);
+        assert_eq!(state.session, Uuid::from_u128(7));
+        assert_eq!(state.serial, 12);
+        assert!(state.delta_state.is_empty());
+        let objects = archive.objects().unwrap().map(|item| {
+            item.unwrap()
+        }).collect::<Vec<_>>();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(
+            objects[0].0.as_str(), "rsync://foo.bar/module/ca.mft"
+        );
+        assert_eq!(objects[0].1.as_ref(), b"manifest");
+    }
+
+    #[test]
+    fn restore_refuses_unknown_dump() {
+        let cache = tempfile::tempdir().unwrap();
+        let config = Config {
+            cache_dir: cache.path().into(),
+            .. Default::default()
+        };
+
+        // A newer dump version is refused before anything is written.
+        let dump = tempfile::tempdir().unwrap();
+        restore_dump(dump.path(), DUMP_VERSION + 1);
+        assert!(Collector::restore(&config, dump.path()).is_err());
+        assert!(!cache.path().join("rrdp").exists());
+
+        // So is an RRDP directory without a repositories.json.
+        let dump = tempfile::tempdir().unwrap();
+        restore_dump(dump.path(), DUMP_VERSION);
+        fs::remove_file(dump.path().join("rrdp/repositories.json")).unwrap();
+        assert!(Collector::restore(&config, dump.path()).is_err());
+        assert!(!cache.path().join("rrdp").exists());
+
+        // And unknown repository types.
+        let json = br#"{
+            "repositories": [ { "path": "foo", "type": "ftp" } ]
+        }"#;
+        assert!(RestoreRepository::from_json(dump.path(), json).is_err());
+    }
+}
@@ -0,0 +1,572 @@
+//! Resolving and caching the host names of RRDP servers.
+//!
+//! Each validation run looks up the host names of all RRDP servers, most
+//! of which rarely change their addresses. The [`DnsCache`] keeps the
+//! answers around for their TTL but at least for the time given via the
+//! `rrdp-dns-min-ttl` option. If the resolver fails, a recent expired
+//! answer is used instead so that a resolver outage doesn’t stall a run.
+//!
+//! Names are resolved either through the system resolver or, if servers
+//! are given via the `rrdp-dns-servers` option, by asking these servers
+//! directly via UDP.
+//!
+//! The addresses handed to the HTTP client are restricted or ordered
+//! according to the `rrdp-address-family` option. The client tries the
+//! family of the first address first and falls back to the other family
+//! after a short delay if connecting takes too long.
+
+use std::io;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use log::debug;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use crate::config::{AddressFamily, Config};
+use crate::metrics::RrdpDnsMetrics;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// How long a failed resolution is cached.
+///
+/// This is kept short so that a recovered server is picked up quickly.
+const NEGATIVE_TTL: Duration = Duration::from_secs(10);
+
+/// How long after it was received an expired answer may still be used.
+const STALE_LIMIT: Duration = Duration::from_secs(24 * 3600);
+
+/// The time to wait for an answer from a DNS server.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The port DNS servers listen on.
+const DNS_PORT: u16 = 53;
+
+/// The record type for IPv4 addresses.
+const RTYPE_A: u16 = 1;
+
+/// The record type for IPv6 addresses.
+const RTYPE_AAAA: u16 = 28;
+
+
+//------------ DnsCache ------------------------------------------------------
+
+/// A caching resolver for the RRDP HTTP client.
+#[derive(Debug)]
+pub struct DnsCache {
+    /// The servers to ask or empty for using the system resolver.
+    servers: Vec<SocketAddr>,
+
+    /// The minimum time to keep an answer.
+    min_ttl: Duration,
+
+    /// The address family to use.
+    family: AddressFamily,
+
+    /// The cached entries keyed by host name.
+    entries: Mutex<HashMap<String, Entry>>,
+
+    /// The number of lookups answered from the cache.
+    hits: AtomicU64,
+
+    /// The number of lookups that went to the resolver.
+    misses: AtomicU64,
+
+    /// The number of lookups answered with a stale entry.
+    stale: AtomicU64,
+
+    /// The number of failed resolutions.
+    failures: AtomicU64,
+}
+
+impl DnsCache {
+    /// Creates a new cache from the configuration.
+    pub fn new(config: &Config) -> Self {
+        DnsCache {
+            servers: config.rrdp_dns_servers.iter().map(|addr| {
+                SocketAddr::new(*addr, DNS_PORT)
+            }).collect(),
+            min_ttl: config.rrdp_dns_min_ttl,
+            family: config.rrdp_address_family,
+            entries: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
+            stale: Default::default(),
+            failures: Default::default(),
+        }
+    }
+
+    /// Returns the metrics since the last call and resets them.
+    pub fn take_metrics(&self) -> RrdpDnsMetrics {
+        RrdpDnsMetrics {
+            hits: self.hits.swap(0, Ordering::Relaxed),
+            misses: self.misses.swap(0, Ordering::Relaxed),
+            stale: self.stale.swap(0, Ordering::Relaxed),
+            failures: self.failures.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    /// Looks up a host name.
+    fn lookup(&self, name: &str) -> Result<Vec<IpAddr>, io::Error> {
+        let now = Instant::now();
+        if let Some(entry) = self.entries().get(name) {
+            if entry.expires > now {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return if entry.addrs.is_empty() {
+                    Err(not_found(name))
+                }
+                else {
+                    Ok(entry.addrs.clone())
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        match self.resolve_name(name) {
+            Ok((addrs, ttl)) => {
+                self.entries().insert(
+                    name.into(),
+                    Entry {
+                        addrs: addrs.clone(),
+                        received: now,
+                        expires: now + ttl.max(self.min_ttl),
+                    }
+                );
+                Ok(addrs)
+            }
+            Err(err) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                debug!("RRDP: failed to resolve {}: {}", name, err);
+                self.fail(name, now, err)
+            }
+        }
+    }
+
+    /// Updates the cache after resolving `name` failed.
+    ///
+    /// Returns a recent previous answer if there is one or the error
+    /// otherwise. In both cases, the next attempt to resolve the name
+    /// happens after [`NEGATIVE_TTL`].
+    fn fail(
+        &self, name: &str, now: Instant, err: io::Error,
+    ) -> Result<Vec<IpAddr>, io::Error> {
+        let mut entries = self.entries();
+        let entry = entries.entry(name.into()).or_insert_with(|| {
+            Entry { addrs: Vec::new(), received: now, expires: now }
+        });
+        entry.expires = now + NEGATIVE_TTL;
+        if entry.addrs.is_empty() {
+            return Err(err)
+        }
+        if now.duration_since(entry.received) > STALE_LIMIT {
+            entry.addrs = Vec::new();
+            return Err(err)
+        }
+        self.stale.fetch_add(1, Ordering::Relaxed);
+        Ok(entry.addrs.clone())
+    }
+
+    /// Resolves a name via the configured resolver.
+    ///
+    /// Returns the addresses and the time they may be cached for.
+    fn resolve_name(
+        &self, name: &str
+    ) -> Result<(Vec<IpAddr>, Duration), io::Error> {
+        if self.servers.is_empty() {
+            let addrs: Vec<_> = (name, 0).to_socket_addrs()?.map(|addr| {
+                addr.ip()
+            }).collect();
+            if addrs.is_empty() {
+                return Err(not_found(name))
+            }
+            // The system resolver doesn’t tell us the TTL.
+            return Ok((addrs, Duration::ZERO))
+        }
+
+        let mut last_err = not_found(name);
+        for server in &self.servers {
+            match query_server(*server, name) {
+                Ok(res) => return Ok(res),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Applies the address family to the addresses for `name`.
+    fn select_family(
+        &self, name: &str, mut addrs: Vec<IpAddr>
+    ) -> Result<Vec<IpAddr>, io::Error> {
+        match self.family {
+            AddressFamily::Any => { }
+            AddressFamily::V4 => addrs.retain(IpAddr::is_ipv4),
+            AddressFamily::V6 => addrs.retain(IpAddr::is_ipv6),
+            AddressFamily::PreferV4 => addrs.sort_by_key(IpAddr::is_ipv6),
+            AddressFamily::PreferV6 => addrs.sort_by_key(IpAddr::is_ipv4),
+        }
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no addresses for {} in address family {}",
+                    name, self.family
+                )
+            ))
+        }
+        Ok(addrs)
+    }
+
+    /// Returns the locked entries.
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().expect("poisoned lock")
+    }
+}
+
+
+
+//------------ CacheResolver -------------------------------------------------
+
+/// The resolver handed to reqwest.
+///
+/// This only exists because we need to move a clone of the cache into the
+/// future returned when resolving.
+#[derive(Debug)]
+pub struct CacheResolver(Arc<DnsCache>);
+
+impl CacheResolver {
+    pub fn new(cache: Arc<DnsCache>) -> Self {
+        CacheResolver(cache)
+    }
+}
+
+impl Resolve for CacheResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.0.clone();
+        let name = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::task::spawn_blocking(move || {
+                cache.lookup(&name).and_then(|addrs| {
+                    cache.select_family(&name, addrs)
+                })
+            }).await??;
+            let addrs: Addrs = Box::new(
+                addrs.into_iter().map(|addr| SocketAddr::new(addr, 0))
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+
+//------------ Entry ---------------------------------------------------------
+
+/// A cached answer.
+#[derive(Debug)]
+struct Entry {
+    /// The addresses for the name.
+    ///
+    /// If this is empty, resolving the name failed.
+    addrs: Vec<IpAddr>,
+
+    /// The time the addresses were received.
+    received: Instant,
+
+    /// The time the entry needs to be refreshed.
+    expires: Instant,
+}
+
+
+//------------ Querying a DNS Server -----------------------------------------
+
+/// Resolves a name by asking a DNS server for its A and AAAA records.
+///
+/// Returns the addresses and the smallest TTL of the records.
+fn query_server(
+    server: SocketAddr, name: &str
+) -> Result<(Vec<IpAddr>, Duration), io::Error> {
+    let bind: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let sock = UdpSocket::bind(bind)?;
+    sock.connect(server)?;
+    sock.set_read_timeout(Some(QUERY_TIMEOUT))?;
+
+    let mut addrs = Vec::new();
+    let mut ttl = u32::MAX;
+    for rtype in [RTYPE_A, RTYPE_AAAA] {
+        let id = rand::random();
+        sock.send(&encode_query(id, name, rtype)?)?;
+        let mut buf = [0u8; 512];
+        loop {
+            let len = sock.recv(&mut buf)?;
+            // Ignore late answers to an earlier query.
+            if let Some(answer) = parse_answer(id, &buf[..len])? {
+                for (addr, rr_ttl) in answer {
+                    addrs.push(addr);
+                    ttl = ttl.min(rr_ttl);
+                }
+                break
+            }
+        }
+    }
+    if addrs.is_empty() {
+        return Err(not_found(name))
+    }
+    Ok((addrs, Duration::from_secs(ttl.into())))
+}
+
+/// Encodes a recursive query for `name` and `rtype`.
+fn encode_query(
+    id: u16, name: &str, rtype: u16
+) -> Result<Vec<u8>, io::Error> {
+    let mut res = Vec::with_capacity(name.len() + 18);
+    res.extend_from_slice(&id.to_be_bytes());
+    // Flags with RD set, one question, no other records.
+    res.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "invalid host name"
+            ))
+        }
+        res.push(label.len() as u8);
+        res.extend_from_slice(label.as_bytes());
+    }
+    res.push(0);
+    res.extend_from_slice(&rtype.to_be_bytes());
+    // Class IN.
+    res.extend_from_slice(&[0, 1]);
+    Ok(res)
+}
+
+/// Parses the answer to a query.
+///
+/// Returns `Ok(None)` if the message is not an answer to the query with
+/// the given ID. Otherwise returns the addresses in the answer section
+/// together with their TTLs. CNAME records are skipped since a recursive
+/// resolver also includes the records of the target name.
+#[allow(clippy::type_complexity)]
+fn parse_answer(
+    id: u16, msg: &[u8]
+) -> Result<Option<Vec<(IpAddr, u32)>>, io::Error> {
+    let mut parser = Parser { msg, pos: 0 };
+    if parser.u16()? != id {
+        return Ok(None)
+    }
+    let flags = parser.u16()?;
+    if flags & 0x8000 == 0 {
+        return Ok(None)
+    }
+    match flags & 0x000F {
+        0 => { }
+        3 => return Ok(Some(Vec::new())), // NXDOMAIN
+        rcode => {
+            return Err(io::Error::other(
+                format!("DNS server returned error code {}", rcode)
+            ))
+        }
+    }
+    let qdcount = parser.u16()?;
+    let ancount = parser.u16()?;
+    parser.skip(4)?;
+    for _ in 0..qdcount {
+        parser.skip_name()?;
+        parser.skip(4)?;
+    }
+    let mut res = Vec::new();
+    for _ in 0..ancount {
+        parser.skip_name()?;
+        let rtype = parser.u16()?;
+        let class = parser.u16()?;
+        let ttl = u32::from(parser.u16()?) << 16 | u32::from(parser.u16()?);
+        let rdlen = parser.u16()?;
+        let rdata = parser.take(usize::from(rdlen))?;
+        if class != 1 {
+            continue
+        }
+        if rtype == RTYPE_A {
+            if let Ok(octets) = <[u8; 4]>::try_from(rdata) {
+                res.push((Ipv4Addr::from(octets).into(), ttl))
+            }
+        }
+        else if rtype == RTYPE_AAAA {
+            if let Ok(octets) = <[u8; 16]>::try_from(rdata) {
+                res.push((Ipv6Addr::from(octets).into(), ttl))
+            }
+        }
+    }
+    Ok(Some(res))
+}
+
+
+//------------ Parser --------------------------------------------------------
+
+/// A minimal parser for DNS messages.
+struct Parser<'a> {
+    /// The message.
+    msg: &'a [u8],
+
+    /// The current position in the message.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        let end = self.pos.checked_add(len).ok_or_else(short_message)?;
+        let res = self.msg.get(self.pos..end).ok_or_else(short_message)?;
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), io::Error> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        let data = self.take(2)?;
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Skips over a possibly compressed domain name.
+    fn skip_name(&mut self) -> Result<(), io::Error> {
+        loop {
+            let len = self.take(1)?[0];
+            if len == 0 {
+                return Ok(())
+            }
+            if len & 0xC0 == 0xC0 {
+                // A compression pointer ends the name.
+                return self.skip(1)
+            }
+            self.skip(usize::from(len))?;
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound, format!("no addresses found for {}", name)
+    )
+}
+
+fn short_message() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "short DNS message")
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cache() -> DnsCache {
+        DnsCache {
+            servers: Vec::new(),
+            min_ttl: Duration::from_secs(60),
+            family: AddressFamily::Any,
+            entries: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
+            stale: Default::default(),
+            failures: Default::default(),
+        }
+    }
+
+    #[test]
+    fn query_and_answer() {
+        let query = encode_query(0x1234, "rrdp.example.net.", RTYPE_A)
+            .unwrap();
+        assert_eq!(
+            &query[12..],
+            b"\x04rrdp\x07example\x03net\x00\x00\x01\x00\x01"
+        );
+        assert!(encode_query(1, "rrdp..example.net", RTYPE_A).is_err());
+
+        // Answer with the question, a CNAME and an A record, using
+        // compressed names.
+        let mut answer = query.clone();
+        answer[2] = 0x81;
+        answer[3] = 0x80;
+        answer[7] = 2;
+        answer.extend_from_slice(&[
+            0xc0, 0x0c, 0, 5, 0, 1, 0, 0, 1, 0, 0, 2, 0xc0, 0x11
+        ]);
+        answer.extend_from_slice(&[
+            0xc0, 0x11, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1
+        ]);
+        assert_eq!(
+            parse_answer(0x1234, &answer).unwrap().unwrap(),
+            [(IpAddr::from([192, 0, 2, 1]), 60)]
+        );
+        assert!(parse_answer(0x4321, &answer).unwrap().is_none());
+        assert!(
+            parse_answer(0x1234, &answer[..answer.len() - 1]).is_err()
+        );
+
+        // NXDOMAIN
+        answer[3] = 0x83;
+        assert!(parse_answer(0x1234, &answer).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn select_family() {
+        let v4 = IpAddr::from([192, 0, 2, 1]);
+        let v6 = IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]);
+        let select = |family| {
+            DnsCache { family, .. cache() }.select_family(
+                "rrdp.example.net", vec![v6, v4, v6]
+            ).ok()
+        };
+        assert_eq!(select(AddressFamily::Any), Some(vec![v6, v4, v6]));
+        assert_eq!(select(AddressFamily::V4), Some(vec![v4]));
+        assert_eq!(select(AddressFamily::V6), Some(vec![v6, v6]));
+        assert_eq!(select(AddressFamily::PreferV4), Some(vec![v4, v6, v6]));
+        assert_eq!(select(AddressFamily::PreferV6), Some(vec![v6, v6, v4]));
+        assert!(
+            DnsCache { family: AddressFamily::V6, .. cache() }.select_family(
+                "rrdp.example.net", vec![v4]
+            ).is_err()
+        );
+    }
+
+    #[test]
+    fn stale_and_negative() {
+        let cache = cache();
+        let now = Instant::now();
+        let err = || not_found("rrdp.example.net");
+
+        // Without a previous answer, the failure is cached.
+        assert!(cache.fail("rrdp.example.net", now, err()).is_err());
+        assert!(cache.lookup("rrdp.example.net").is_err());
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 1);
+
+        // A recent previous answer is used instead.
+        let addr = IpAddr::from([192, 0, 2, 1]);
+        cache.entries().insert(
+            "rrdp.example.net".into(),
+            Entry { addrs: vec![addr], received: now, expires: now }
+        );
+        assert_eq!(
+            cache.fail("rrdp.example.net", now, err()).unwrap(), [addr]
+        );
+        assert_eq!(cache.lookup("rrdp.example.net").unwrap(), [addr]);
+
+        // An old one is dropped.
+        let later = now + STALE_LIMIT + Duration::from_secs(1);
+        assert!(cache.fail("rrdp.example.net", later, err()).is_err());
+        assert!(cache.entries()["rrdp.example.net"].addrs.is_empty());
+
+        let metrics = cache.take_metrics();
+        assert_eq!(metrics.hits, 2);
+        assert_eq!(metrics.stale, 1);
+        assert_eq!(cache.take_metrics().hits, 0);
+    }
+}
+
@@ -1,17 +1,24 @@
 use std::{fs, io};
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use log::{error, warn};
 use reqwest::{header, redirect};
-use reqwest::{Certificate, Proxy, StatusCode};
+use reqwest::{Certificate, NoProxy, Proxy, StatusCode};
 use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
 use rpki::uri;
+use tempfile::NamedTempFile;
+use crate::collector::base::limit_timeout;
 use crate::config::Config;
 use crate::error::Fatal;
+use crate::metrics::RrdpDnsMetrics;
 use crate::utils::date::{format_http_date, parse_http_date};
+use super::dns::{CacheResolver, DnsCache};
+use super::pin;
 
 
 //------------ HttpClient ----------------------------------------------------
@@ -28,8 +35,17 @@ pub struct HttpClient {
     /// The base directory for storing copies of responses if that is enabled.
     response_dir: Option<PathBuf>,
 
+    /// Should copies of responses only be kept if processing fails?
+    keep_on_error: bool,
+
+    /// The maximum number of copies of responses to keep.
+    keep_max: Option<usize>,
+
     /// The timeout for requests.
     timeout: Option<Duration>,
+
+    /// The DNS cache used by the client.
+    dns: Arc<DnsCache>,
 }
 
 impl HttpClient {
@@ -62,27 +78,58 @@ impl HttpClient {
         if let Some(addr) = config.rrdp_local_addr {
             builder = builder.local_address(addr)
         }
+        let dns = Arc::new(DnsCache::new(config));
+        builder = builder.dns_resolver(
+            Arc::new(CacheResolver::new(dns.clone()))
+        );
         for path in &config.rrdp_root_certs {
             builder = builder.add_root_certificate(
                 Self::load_cert(path)?
             );
         }
-        for proxy in &config.rrdp_proxies {
-            let proxy = match Proxy::all(proxy) {
+        if let Some(tls) = pin::client_config(config)? {
+            if cfg!(feature = "native-tls") {
+                error!(
+                    "rrdp-pinned-certs is not supported when using \
+                     native TLS."
+                );
+                return Err(Fatal)
+            }
+            builder = builder.use_preconfigured_tls(tls);
+        }
+        let auth = Self::proxy_auth(config)?;
+        let no_proxy = NoProxy::from_string(&config.rrdp_no_proxy.join(","));
+        for uri in &config.rrdp_proxies {
+            let mut proxy = match Proxy::all(uri) {
                 Ok(proxy) => proxy,
                 Err(err) => {
                     error!(
-                        "Invalid rrdp-proxy '{}': {}", proxy, err
+                        "Invalid rrdp-proxy '{}': {}", uri, err
                     );
                     return Err(Fatal)
                 }
             };
-            builder = builder.proxy(proxy);
+            if let Some((username, password)) = auth.as_ref() {
+                // Reqwest panics when setting credentials for SOCKS4.
+                if uri.starts_with("socks4") {
+                    error!(
+                        "rrdp-proxy '{}': SOCKS4 proxies do not support \
+                         authentication.",
+                        uri
+                    );
+                    return Err(Fatal)
+                }
+                proxy = proxy.basic_auth(username, password);
+            }
+            builder = builder.proxy(proxy.no_proxy(no_proxy.clone()));
         }
         Ok(HttpClient {
             client: Err(Some(builder)),
             response_dir: config.rrdp_keep_responses.clone(),
+            keep_on_error: config.rrdp_keep_responses_on_error,
+            keep_max: config.rrdp_keep_responses_max,
             timeout: config.rrdp_timeout,
+            dns,
         })
     }
 
@@ -113,6 +160,128 @@ impl HttpClient {
         Ok(())
     }
 
+    /// Returns the DNS metrics since the last call and resets them.
+    pub fn take_dns_metrics(&self) -> RrdpDnsMetrics {
+        self.dns.take_metrics()
+    }
+
+    /// Deletes the oldest kept responses beyond the configured maximum.
+    ///
+    /// Directories that become empty are deleted, too. Problems are logged
+    /// but otherwise ignored.
+    pub fn prune_responses(&self) {
+        let (base, max) = match (self.response_dir.as_ref(), self.keep_max) {
+            (Some(base), Some(max)) => (base, max),
+            _ => return
+        };
+        let mut files = Vec::new();
+        if let Err(err) = Self::collect_responses(base, &mut files) {
+            warn!(
+                "Cannot prune kept HTTP responses in {}: {}",
+                base.display(), err
+            );
+            return
+        }
+        if files.len() <= max {
+            return
+        }
+        files.sort_unstable();
+        let excess = files.len() - max;
+        for (_, path) in files.into_iter().take(excess) {
+            if let Err(err) = fs::remove_file(&path) {
+                warn!(
+                    "Cannot delete kept HTTP response {}: {}",
+                    path.display(), err
+                );
+                continue
+            }
+            let mut dir = path.parent();
+            while let Some(parent) = dir {
+                if parent == base || fs::remove_dir(parent).is_err() {
+                    break
+                }
+                dir = parent.parent();
+            }
+        }
+    }
+
+    /// Collects all kept responses under `dir`.
+    ///
+    /// Adds the modification time and path of each file to `files`. Skips
+    /// the temporary files of responses still being processed.
+    fn collect_responses(
+        dir: &Path, files: &mut Vec<(SystemTime, PathBuf)>
+    ) -> Result<(), io::Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                Self::collect_responses(&entry.path(), files)?;
+            }
+            else if file_type.is_file()
+                && !entry.file_name().to_string_lossy().starts_with(
+                    TEMP_PREFIX
+                )
+            {
+                files.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Determines the credentials for the RRDP proxies.
+    ///
+    /// Returns the user name and password if authentication is configured
+    /// or `None` otherwise.
+    fn proxy_auth(
+        config: &Config
+    ) -> Result<Option<(String, String)>, Fatal> {
+        let username = match config.rrdp_proxy_username.as_ref() {
+            Some(username) => username.clone(),
+            None => {
+                if config.rrdp_proxy_password.is_some()
+                    || config.rrdp_proxy_password_file.is_some()
+                {
+                    error!(
+                        "A password for the RRDP proxy requires \
+                         rrdp-proxy-username to be set as well."
+                    );
+                    return Err(Fatal)
+                }
+                return Ok(None)
+            }
+        };
+        let password = match (
+            config.rrdp_proxy_password.as_ref(),
+            config.rrdp_proxy_password_file.as_ref()
+        ) {
+            (Some(_), Some(_)) => {
+                error!(
+                    "Only one of rrdp-proxy-password and \
+                     rrdp-proxy-password-file can be given."
+                );
+                return Err(Fatal)
+            }
+            (Some(password), None) => password.clone(),
+            (None, Some(path)) => {
+                match fs::read_to_string(path) {
+                    Ok(password) => {
+                        password.trim_end_matches(['\r', '\n']).into()
+                    }
+                    Err(err) => {
+                        error!(
+                            "Cannot read rrdp-proxy-password-file '{}': {}",
+                            path.display(), err
+                        );
+                        return Err(Fatal)
+                    }
+                }
+            }
+            (None, None) => String::new(),
+        };
+        Ok(Some((username, password)))
+    }
+
     /// Loads a WebPKI trusted certificate.
     fn load_cert(path: &Path) -> Result<Certificate, Fatal> {
         let mut file = match fs::File::open(path) {
@@ -157,19 +326,30 @@ impl HttpClient {
     /// corresponding to the URI. If the resource behind the URI changes over
     /// time and this change should be tracked, set `multi` to `true` to
     /// include the current time in the file name.
+    ///
+    /// If `deadline` is given, the request is aborted if it hasn’t
+    /// completed by then.
     pub fn response(
         &self,
         uri: &uri::Https,
+        deadline: Option<Instant>,
         multi: bool,
     ) -> Result<HttpResponse, reqwest::Error> {
-        self._response(uri, self.client().get(uri.as_str()), multi)
+        self._response(
+            uri, self.client().get(uri.as_str()), deadline, multi
+        )
     }
 
+    /// Performs a conditional HTTP GET request for the given URI.
+    ///
+    /// Unlike [`response`][Self::response], this also returns responses
+    /// with an error status code so that their headers can be inspected.
     pub fn conditional_response(
         &self,
         uri: &uri::Https,
         etag: Option<&Bytes>,
         last_modified: Option<DateTime<Utc>>,
+        deadline: Option<Instant>,
         multi: bool,
     ) -> Result<HttpResponse, reqwest::Error> {
         let mut request = self.client().get(uri.as_str());
@@ -184,23 +364,37 @@ impl HttpClient {
                 format_http_date(last_modified)
             );
         }
-        self._response(uri, request, multi)
+        self._send(uri, request, deadline, multi)
     }
 
-    /// Creates a response from a request builder.
+    /// Creates a successful response from a request builder.
     fn _response(
+        &self,
+        uri: &uri::Https,
+        request: RequestBuilder,
+        deadline: Option<Instant>,
+        multi: bool
+    ) -> Result<HttpResponse, reqwest::Error> {
+        let response = self._send(uri, request, deadline, multi)?;
+        response.response.error_for_status_ref()?;
+        Ok(response)
+    }
+
+    /// Creates a response from a request builder.
+    fn _send(
         &self,
         uri: &uri::Https,
         mut request: RequestBuilder,
+        deadline: Option<Instant>,
         multi: bool
     ) -> Result<HttpResponse, reqwest::Error> {
-        if let Some(timeout) = self.timeout {
+        if let Some(timeout) = limit_timeout(self.timeout, deadline) {
             request = request.timeout(timeout);
         }
-        request.send().and_then(|response| {
-            response.error_for_status()
-        }).map(|response| {
-            HttpResponse::create(response, uri, &self.response_dir, multi)
+        request.send().map(|response| {
+            HttpResponse::create(
+                response, uri, &self.response_dir, self.keep_on_error, multi
+            )
         })
     }
 
@@ -239,7 +433,9 @@ impl HttpClient {
                 response
             }
             Err(err) => {
-                warn!("RRDP {}: {}", uri, err);
+                warn!(
+                    repository = uri.as_str(); "RRDP {}: {}", uri, err
+                );
                 *status = HttpStatus::Error;
                 return Err(Failed)
             }
@@ -295,9 +491,17 @@ pub struct HttpResponse {
 
     /// A file to also store read data into.
     file: Option<fs::File>,
+
+    /// The copy of the response if it is only to be kept on error.
+    copy: Option<ResponseCopy>,
 }
 
 impl HttpResponse {
+    /// Returns the remote address of the connection.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.response.remote_addr()
+    }
+
     /// Creates a new response wrapping a reqwest reponse.
     ///
     /// If `response_dir` is some path, the response will also be written to
@@ -306,34 +510,46 @@ impl HttpResponse {
     /// `false` the last component will be the file name. If `multi` is
     /// `true` the last component will be a directory, too, and the file name
     /// will be the ISO timestamp of the current time.
+    ///
+    /// If `on_error` is `true`, the response is written to a temporary file
+    /// instead. It is only moved to its final place if
+    /// [`take_copy`][Self::take_copy] is used and the copy is explicitly
+    /// kept. Otherwise it is deleted again.
     pub fn create(
         response: Response,
         uri: &uri::Https,
         response_dir: &Option<PathBuf>,
+        on_error: bool,
         multi: bool
     ) -> Self {
-        HttpResponse {
-            response,
-            file: response_dir.as_ref().and_then(|base| {
-                Self::open_file(base, uri, multi)
-            })
-        }
+        let (file, copy) = match response_dir.as_ref() {
+            Some(base) if on_error => {
+                match ResponseCopy::create(base, uri, multi) {
+                    Some((file, copy)) => (Some(file), Some(copy)),
+                    None => (None, None),
+                }
+            }
+            Some(base) => (Self::open_file(base, uri, multi), None),
+            None => (None, None),
+        };
+        HttpResponse { response, file, copy }
     }
 
-    /// Opens the file mirroring file.
+    /// Returns the path of the file mirroring the response.
     ///
     /// See [`create`][Self::create] for the rules.
-    fn open_file(
-        base: &Path, uri: &uri::Https, multi: bool
-    ) -> Option<fs::File> {
+    fn file_path(base: &Path, uri: &uri::Https, multi: bool) -> PathBuf {
         let path = base.join(&uri.as_str()[8..]);
-        let path = if multi {
+        if multi {
             path.join(Utc::now().to_rfc3339())
         }
         else {
             path
-        };
+        }
+    }
 
+    /// Creates the parent directory of the file at `path`.
+    fn create_parent(path: &Path) -> bool {
         let parent = match path.parent() {
             Some(parent) => parent,
             None => {
@@ -342,7 +558,7 @@ impl HttpResponse {
                     URI translated into a bad path '{}'",
                     path.display()
                 );
-                return None
+                return false
             }
         };
         if let Err(err) = fs::create_dir_all(parent) {
@@ -351,6 +567,19 @@ impl HttpResponse {
                 creating directory {} failed: {}",
                 parent.display(), err
             );
+            return false
+        }
+        true
+    }
+
+    /// Opens the file mirroring file.
+    ///
+    /// See [`create`][Self::create] for the rules.
+    fn open_file(
+        base: &Path, uri: &uri::Https, multi: bool
+    ) -> Option<fs::File> {
+        let path = Self::file_path(base, uri, multi);
+        if !Self::create_parent(&path) {
             return None
         }
         match fs::File::create(&path) {
@@ -366,6 +595,15 @@ impl HttpResponse {
         }
     }
 
+    /// Takes the copy of the response that is only kept on error.
+    ///
+    /// The copy needs to be taken before the response is processed. Once
+    /// processing has failed, it can be kept via [`ResponseCopy::keep`].
+    /// Otherwise, the copy is deleted when it is dropped.
+    pub fn take_copy(&mut self) -> Option<ResponseCopy> {
+        self.copy.take()
+    }
+
     /// Returns the value of the content length header if present.
     pub fn content_length(&self) -> Option<u64> {
         self.response.content_length()
@@ -440,6 +678,49 @@ impl HttpResponse {
     ///
     /// The method quietly returns `None` if the content of a header is
     /// malformed or if there is more than one occurence of the header.
+    /// Returns the delay requested via the Retry-After header if present.
+    ///
+    /// The header can either contain a number of seconds or a date. A date
+    /// in the past results in a zero delay.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let value = self.response.headers().get(header::RETRY_AFTER)?;
+        let value = value.to_str().ok()?.trim();
+        if let Ok(secs) = value.parse() {
+            return Some(Duration::from_secs(secs))
+        }
+        let date = parse_http_date(value)?;
+        Some((date - Utc::now()).to_std().unwrap_or_default())
+    }
+
+    /// Returns the delay requested by a rate-limiting server.
+    ///
+    /// A response is considered rate-limiting if it has status 429 (Too
+    /// Many Requests) or 503 (Service Unavailable) and a Retry-After
+    /// header. Returns `None` for all other responses.
+    pub fn rate_limit(&self) -> Option<Duration> {
+        if !HttpStatus::from(self.status()).is_rate_limit() {
+            return None
+        }
+        self.retry_after()
+    }
+
+    /// Returns the offset of the local clock from the server’s clock.
+    ///
+    /// The offset is the current local time minus the time in the Date
+    /// header of the response in seconds. Returns `None` if there isn’t
+    /// exactly one valid Date header.
+    pub fn clock_offset(&self) -> Option<i64> {
+        let mut iter = self.response.headers()
+            .get_all(header::DATE)
+            .into_iter();
+        let value = iter.next()?;
+        if iter.next().is_some() {
+            return None
+        }
+        let date = parse_http_date(value.to_str().ok()?)?;
+        Some(Utc::now().signed_duration_since(date).num_seconds())
+    }
+
     pub fn last_modified(&self) -> Option<DateTime<Utc>> {
         let mut iter = self.response.headers()
             .get_all(header::LAST_MODIFIED)
@@ -466,6 +747,85 @@ impl io::Read for HttpResponse {
 }
 
 
+//------------ ResponseCopy --------------------------------------------------
+
+/// A copy of a response that is only kept if processing fails.
+///
+/// The response is written into a temporary file in the base directory for
+/// kept responses. Unless [`keep`][Self::keep] is called, the temporary
+/// file is deleted when the value is dropped.
+#[derive(Debug)]
+pub struct ResponseCopy {
+    /// The temporary file.
+    temp: NamedTempFile,
+
+    /// The path the file is moved to if it is kept.
+    path: PathBuf,
+}
+
+impl ResponseCopy {
+    /// Creates a new copy.
+    ///
+    /// Returns the file to write the response to and the copy itself.
+    fn create(
+        base: &Path, uri: &uri::Https, multi: bool
+    ) -> Option<(fs::File, Self)> {
+        if let Err(err) = fs::create_dir_all(base) {
+            warn!(
+                "Cannot keep HTTP response; \
+                creating directory {} failed: {}",
+                base.display(), err
+            );
+            return None
+        }
+        let temp = match tempfile::Builder::new().prefix(
+            TEMP_PREFIX
+        ).tempfile_in(base) {
+            Ok(temp) => temp,
+            Err(err) => {
+                warn!(
+                    "Cannot keep HTTP response; \
+                    creating temporary file in {} failed: {}",
+                    base.display(), err
+                );
+                return None
+            }
+        };
+        let file = match temp.as_file().try_clone() {
+            Ok(file) => file,
+            Err(err) => {
+                warn!(
+                    "Cannot keep HTTP response; \
+                    cloning temporary file {} failed: {}",
+                    temp.path().display(), err
+                );
+                return None
+            }
+        };
+        Some((file, ResponseCopy {
+            temp,
+            path: HttpResponse::file_path(base, uri, multi),
+        }))
+    }
+
+    /// Keeps the copy.
+    ///
+    /// Moves the temporary file to its final place.
+    pub fn keep(self) {
+        if !HttpResponse::create_parent(&self.path) {
+            return
+        }
+        if let Err(err) = self.temp.persist(&self.path) {
+            warn!(
+                "Cannot keep HTTP response; \
+                moving temporary file to {} failed: {}",
+                self.path.display(), err.error
+            );
+        }
+    }
+}
+
+
 //------------ HttpStatus ----------------------------------------------------
 
 /// The result of an HTTP request.
@@ -478,18 +838,74 @@ pub enum HttpStatus {
     Rejected,
 
     /// An error happened.
-    Error
+    Error,
+
+    /// The request was skipped because the repository is backed off.
+    BackedOff,
+
+    /// The request was skipped because the server asked us to wait.
+    ///
+    /// This happens if an earlier request was answered with a status
+    /// code of 429 or 503 and a Retry-After header.
+    RateLimited,
+
+    /// The server’s certificate didn’t match the pinned public keys.
+    PinMismatch,
 }
 
 impl HttpStatus {
+    /// Returns the status for a request that failed with the given error.
+    ///
+    /// If the request failed because a proxy demanded authentication, the
+    /// status is a response with status code 407. For HTTPS, this happens
+    /// when establishing the tunnel through the proxy, so there is no
+    /// actual response and we have to dig through the error’s sources to
+    /// find out. If the server certificate didn’t match the pinned public
+    /// keys, the status is [`HttpStatus::PinMismatch`]. Otherwise, the
+    /// status is [`HttpStatus::Error`].
+    pub fn from_error(err: &reqwest::Error) -> Self {
+        if pin::is_pin_mismatch(err) {
+            return HttpStatus::PinMismatch
+        }
+        let proxy_auth = StatusCode::PROXY_AUTHENTICATION_REQUIRED;
+        if err.status() == Some(proxy_auth) {
+            return HttpStatus::Response(proxy_auth)
+        }
+        let mut source = std::error::Error::source(err);
+        while let Some(err) = source {
+            if err.to_string() == "proxy authentication required" {
+                return HttpStatus::Response(proxy_auth)
+            }
+            source = err.source();
+        }
+        HttpStatus::Error
+    }
+
     pub fn into_i16(self) -> i16 {
         match self {
             HttpStatus::Response(code) => code.as_u16() as i16,
             HttpStatus::Rejected => -2,
             HttpStatus::Error => -1,
+            HttpStatus::BackedOff => -3,
+            HttpStatus::PinMismatch => -4,
+            HttpStatus::RateLimited => -5,
         }
     }
 
+    /// Returns whether the status may indicate rate limiting.
+    ///
+    /// This is the case for responses with status codes 429 and 503. The
+    /// response only actually is rate-limiting if it also contained a
+    /// Retry-After header.
+    pub fn is_rate_limit(self) -> bool {
+        matches!(
+            self,
+            HttpStatus::Response(code) if
+                code == StatusCode::TOO_MANY_REQUESTS
+                || code == StatusCode::SERVICE_UNAVAILABLE
+        )
+    }
+
     pub fn is_not_modified(self) -> bool {
         matches!(
             self,
@@ -511,3 +927,68 @@ impl From<StatusCode> for HttpStatus {
     }
 }
 
+
+//------------ Constants -----------------------------------------------------
+
+/// The prefix of the names of temporary files for responses.
+const TEMP_PREFIX: &str = ".tmp";
+
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    #[test]
+    fn response_copy_keep() {
+        let dir = tempfile::tempdir().unwrap();
+        let uri = uri::Https::from_str(
+            "https://rrdp.example.net/rrdp/snapshot.xml"
+        ).unwrap();
+
+        let (mut file, copy) = ResponseCopy::create(
+            dir.path(), &uri, false
+        ).unwrap();
+        file.write_all(b"discarded").unwrap();
+        drop(copy);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let (mut file, copy) = ResponseCopy::create(
+            dir.path(), &uri, false
+        ).unwrap();
+        file.write_all(b"kept").unwrap();
+        copy.keep();
+        assert_eq!(
+            fs::read(dir.path().join("rrdp.example.net/rrdp/snapshot.xml"))
+                .unwrap(),
+            b"kept"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn prune_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["a/1", "a/2", "b/c/3", "d/4"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        fs::write(dir.path().join(".tmp1234"), b"").unwrap();
+
+        let config = Config {
+            rrdp_keep_responses: Some(dir.path().into()),
+            rrdp_keep_responses_max: Some(2),
+            .. Default::default()
+        };
+        HttpClient::new(&config).unwrap().prune_responses();
+        assert!(!dir.path().join("a").exists());
+        assert!(dir.path().join("b/c/3").exists());
+        assert!(dir.path().join("d/4").exists());
+        assert!(dir.path().join(".tmp1234").exists());
+    }
+}
@@ -1,12 +1,16 @@
 #![allow(dead_code)]
 
-pub use self::archive::RrdpArchive;
+pub use self::archive::{RrdpArchive, UpdateHistory, UpdateRecord};
 pub use self::base::{Collector, LoadResult, ReadRepository, Run};
 pub use self::http::HttpStatus;
+pub use self::pin::SpkiPin;
 pub use self::update::SnapshotReason;
 
 mod archive;
+mod backoff;
 mod base;
+mod dns;
 mod http;
+mod pin;
 mod update;
 
@@ -0,0 +1,379 @@
+//! Pinning the public keys of RRDP servers.
+//!
+//! For selected RRDP repositories, the operator can provide the digests of
+//! the public keys the server is expected to use. A connection is only
+//! established if the server’s end-entity certificate has a public key
+//! matching one of the pins for the host. The certificate is still verified
+//! normally, so pinning can only ever reject a server.
+//!
+//! Only the end-entity certificate is considered. Any further certificates
+//! are supplied by the server and aren’t necessarily part of the chain that
+//! was actually verified, so an attacker could just add the genuine, public
+//! certificate.
+//!
+//! Since reqwest doesn’t provide a way to do this, we provide our own
+//! certificate verifier wrapping the default one. This requires using a
+//! rustls config of our own making which we only do if there actually are
+//! pins.
+
+use std::{fmt, fs, io};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use log::error;
+use ring::digest;
+use rpki::uri;
+use rpki::util::base64;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::{
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use crate::config::Config;
+use crate::error::Fatal;
+use crate::utils::tls::cert_spki;
+
+
+//------------ SpkiPin -------------------------------------------------------
+
+/// The SHA-256 digest of the public key a server is expected to use.
+///
+/// The digest is calculated over the DER encoded SubjectPublicKeyInfo of
+/// a certificate. In text form, it is given as `sha256//` followed by the
+/// Base 64 encoded digest. This is the same format curl uses for its
+/// `--pinnedpubkey` option.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// The prefix of the text form.
+    const PREFIX: &'static str = "sha256//";
+
+    /// Creates the pin for a DER encoded SubjectPublicKeyInfo.
+    pub fn from_spki(spki: &[u8]) -> Self {
+        let mut res = [0u8; 32];
+        res.copy_from_slice(
+            digest::digest(&digest::SHA256, spki).as_ref()
+        );
+        SpkiPin(res)
+    }
+
+    /// Returns whether the DER encoded certificate matches the pin.
+    fn matches_cert(self, cert: &[u8]) -> bool {
+        cert_spki(cert).map(|spki| {
+            Self::from_spki(spki) == self
+        }).unwrap_or(false)
+    }
+}
+
+
+//--- FromStr and Display
+
+impl FromStr for SpkiPin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digest = s.strip_prefix(Self::PREFIX).ok_or_else(|| {
+            format!("pin must start with '{}'", Self::PREFIX)
+        })?;
+        let digest = base64::Serde.decode(digest).map_err(|_| {
+            String::from("invalid Base 64 in pin")
+        })?;
+        let digest = <[u8; 32]>::try_from(digest).map_err(|_| {
+            String::from("pin must be a SHA-256 digest")
+        })?;
+        Ok(SpkiPin(digest))
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", Self::PREFIX, base64::Serde.encode(&self.0))
+    }
+}
+
+
+//------------ client_config -------------------------------------------------
+
+/// Creates the TLS client config for pinning if pins are configured.
+///
+/// The config uses the default web PKI trust anchors plus the additional
+/// root certificates from the config.
+pub fn client_config(config: &Config) -> Result<Option<ClientConfig>, Fatal> {
+    if config.rrdp_pinned_certs.is_empty() {
+        return Ok(None)
+    }
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for path in &config.rrdp_root_certs {
+        for cert in load_certs(path)? {
+            if let Err(err) = roots.add(cert) {
+                error!(
+                    "Cannot use rrdp-root-cert file '{}': {}'",
+                    path.display(), err
+                );
+                return Err(Fatal)
+            }
+        }
+    }
+
+    let provider = Arc::new(default_provider());
+    let inner = WebPkiServerVerifier::builder_with_provider(
+        Arc::new(roots), provider.clone()
+    ).build().map_err(|err| {
+        error!("Failed to create TLS certificate verifier: {}", err);
+        Fatal
+    })?;
+    let mut pins = HashMap::<_, Vec<_>>::new();
+    for (uri, pin) in &config.rrdp_pinned_certs {
+        pins.entry(host(uri)).or_default().push(*pin);
+    }
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| {
+            error!("Failed to create TLS client config: {}", err);
+            Fatal
+        })?
+        .dangerous()
+        .with_custom_certificate_verifier(
+            Arc::new(PinningVerifier { inner, pins })
+        )
+        .with_no_client_auth();
+    Ok(Some(config))
+}
+
+/// Returns the lower-case host name of a URI.
+fn host(uri: &uri::Https) -> String {
+    let authority = uri.canonical_authority();
+    match authority.split_once(':') {
+        Some((host, _)) => host.into(),
+        None => authority.into_owned(),
+    }
+}
+
+/// Loads the certificates from a PEM file.
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Fatal> {
+    let file = fs::File::open(path).map_err(|err| {
+        error!(
+            "Cannot open rrdp-root-cert file '{}': {}'",
+            path.display(), err
+        );
+        Fatal
+    })?;
+    rustls_pemfile::certs(
+        &mut io::BufReader::new(file)
+    ).collect::<Result<_, _>>().map_err(|err| {
+        error!(
+            "Cannot decode rrdp-root-cert file '{}': {}'",
+            path.display(), err
+        );
+        Fatal
+    })
+}
+
+
+//------------ PinningVerifier -----------------------------------------------
+
+/// A certificate verifier that additionally checks the pins for a host.
+#[derive(Debug)]
+struct PinningVerifier {
+    /// The verifier doing the actual verification.
+    inner: Arc<WebPkiServerVerifier>,
+
+    /// The pins for each host name.
+    pins: HashMap<String, Vec<SpkiPin>>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let res = self.inner.verify_server_cert(
+            end_entity, intermediates, server_name, ocsp_response, now
+        )?;
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_ascii_lowercase(),
+            ServerName::IpAddress(addr) => {
+                std::net::IpAddr::from(*addr).to_string()
+            }
+            _ => return Ok(res)
+        };
+        let pins = match self.pins.get(&host) {
+            Some(pins) => pins,
+            None => return Ok(res)
+        };
+        if pins.iter().any(|pin| pin.matches_cert(end_entity.as_ref())) {
+            Ok(res)
+        }
+        else {
+            error!(
+                "RRDP server {}: certificate does not match any pinned \
+                 public key.",
+                host
+            );
+            Err(rustls::Error::General(PIN_MISMATCH.into()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+
+//------------ is_pin_mismatch -----------------------------------------------
+
+/// The error message used when a certificate doesn’t match the pins.
+const PIN_MISMATCH: &str = "certificate does not match pinned public keys";
+
+/// Returns whether a request failed because of a pin mismatch.
+///
+/// The rustls error ends up somewhere in the chain of sources, so we have
+/// to dig through them.
+pub fn is_pin_mismatch(err: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.to_string().contains(PIN_MISMATCH) {
+            return true
+        }
+        source = err.source();
+    }
+    false
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spki_pin_from_str() {
+        let pin = SpkiPin::from_spki(b"foo");
+        assert_eq!(SpkiPin::from_str(&pin.to_string()), Ok(pin));
+        assert_eq!(
+            pin.to_string(),
+            "sha256//LCa0a2j/xo/5m0U8HTBBNBNCLXBkg7+g+YpeiGJm564="
+        );
+        assert!(SpkiPin::from_str(
+            "sha1//LCa0a2j/xo/5m0U8HTBBNBNCLXBkg7+g+YpeiGJm564="
+        ).is_err());
+        assert!(SpkiPin::from_str("sha256//Zm9v").is_err());
+        assert!(SpkiPin::from_str("sha256//!!").is_err());
+    }
+
+    #[test]
+    fn uri_host() {
+        fn host_of(uri: &str) -> String {
+            host(&uri::Https::from_str(uri).unwrap())
+        }
+        assert_eq!(
+            host_of("https://RRDP.example.net/notification.xml"),
+            "rrdp.example.net"
+        );
+        assert_eq!(
+            host_of("https://rrdp.example.net:8443/notification.xml"),
+            "rrdp.example.net"
+        );
+    }
+
+    /// Performs a handshake with a server presenting the given certificates.
+    ///
+    /// The first certificate is the end-entity certificate using the
+    /// private key in `key`. Returns whether the client accepted the
+    /// server.
+    fn handshake(client: ClientConfig, certs: &[&[u8]], key: &[u8]) -> bool {
+        let certs = certs.iter().map(|pem| {
+            rustls_pemfile::certs(&mut &**pem).next().unwrap().unwrap()
+        }).collect();
+        let key = rustls_pemfile::private_key(
+            &mut &*key
+        ).unwrap().unwrap();
+        let server = rustls::ServerConfig::builder_with_provider(
+            Arc::new(default_provider())
+        ).with_safe_default_protocol_versions().unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, key).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build().unwrap();
+        runtime.block_on(async {
+            let (client_io, server_io) = tokio::io::duplex(16384);
+            let connector = tokio_rustls::TlsConnector::from(
+                Arc::new(client)
+            );
+            let acceptor = tokio_rustls::TlsAcceptor::from(
+                Arc::new(server)
+            );
+            let (client, _) = tokio::join!(
+                connector.connect(
+                    ServerName::try_from("rrdp.example.net").unwrap(),
+                    client_io
+                ),
+                acceptor.accept(server_io),
+            );
+            client.is_ok()
+        })
+    }
+
+    #[test]
+    fn pin_end_entity_only() {
+        let leaf = include_bytes!("../../../test/pin/leaf.pem").as_ref();
+        let leaf_key = include_bytes!("../../../test/pin/leaf.key");
+        let pinned = include_bytes!("../../../test/pin/pinned.pem").as_ref();
+        let pinned_key = include_bytes!("../../../test/pin/pinned.key");
+        let pinned_der = rustls_pemfile::certs(
+            &mut &*pinned
+        ).next().unwrap().unwrap();
+
+        let config = Config {
+            rrdp_root_certs: vec!["test/pin/ca.pem".into()],
+            rrdp_pinned_certs: vec![(
+                uri::Https::from_str(
+                    "https://rrdp.example.net/notification.xml"
+                ).unwrap(),
+                SpkiPin::from_spki(cert_spki(pinned_der.as_ref()).unwrap()),
+            )],
+            .. Default::default()
+        };
+        let client = || client_config(&config).unwrap().unwrap();
+
+        // The pinned certificate is accepted.
+        assert!(handshake(client(), &[pinned], pinned_key));
+
+        // Another valid certificate is rejected, even if the pinned
+        // certificate is included as an extra certificate.
+        assert!(!handshake(client(), &[leaf], leaf_key));
+        assert!(!handshake(client(), &[leaf, pinned], leaf_key));
+    }
+}
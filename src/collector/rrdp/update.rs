@@ -2,6 +2,8 @@
 use std::{error, fmt, io};
 use std::collections::HashSet;
 use std::io::Read;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use log::{error, warn};
@@ -11,11 +13,13 @@ use ring::constant_time::verify_slices_are_equal;
 use rpki::{rrdp, uri};
 use rpki::rrdp::{DeltaInfo, NotificationFile, ProcessDelta, ProcessSnapshot};
 use uuid::Uuid;
+use crate::collector::limits::LimitExceeded;
+use crate::config::FilterPolicy;
 use crate::error::{Failed, RunFailed};
 use crate::metrics::RrdpRepositoryMetrics;
 use crate::utils::archive::{ArchiveError, PublishError};
 use super::archive::{AccessError, FallbackTime, RepositoryState, RrdpArchive};
-use super::base::Collector;
+use super::base::{Collector, RrdpConfig};
 use super::http::{HttpClient, HttpResponse, HttpStatus};
 
 
@@ -39,58 +43,94 @@ pub struct Notification {
 impl Notification {
     /// Requests, parses, and returns the given RRDP notification file.
     ///
-    /// The value referred to by `status` will be updated to the received
-    /// status code or `HttpStatus::Error` if the request failed.
-    ///
     /// Returns the notification file on success. Returns `Ok(None)` if a
     /// response was received successfully but indicated that the
-    /// notification file was not updated.
+    /// notification file was not updated. In either case, also returns
+    /// information about the response received, if any.
+    ///
+    /// The request is abandoned if it hasn’t completed by `deadline`.
     pub fn get(
         http: &HttpClient,
         uri: &uri::Https,
         state: Option<&RepositoryState>,
-        status: &mut HttpStatus,
-        delta_list_limit: usize,
-    ) -> Result<Option<Self>, Failed> {
+        config: &RrdpConfig,
+        deadline: Option<Instant>,
+    ) -> (Result<Option<Self>, Failed>, ResponseInfo) {
         let response = match http.conditional_response(
             uri,
             state.and_then(|state| state.etag.as_ref()),
             state.and_then(|state| state.last_modified()),
+            deadline,
             true
         ) {
-            Ok(response) => {
-                *status = response.status().into();
-                response
-            }
+            Ok(response) => response,
             Err(err) => {
-                warn!("RRDP {}: {}", uri, err);
-                *status = HttpStatus::Error;
-                return Err(Failed)
+                warn!(
+                    repository = uri.as_str(); "RRDP {}: {}", uri, err
+                );
+                return (
+                    Err(Failed),
+                    ResponseInfo::from_status(HttpStatus::from_error(&err))
+                )
             }
         };
+        let mut info = ResponseInfo {
+            status: response.status().into(),
+            remote_addr: response.remote_addr().map(|addr| addr.ip()),
+            clock_offset: response.clock_offset(),
+            retry_after: None,
+        };
 
-        if response.status() == StatusCode::NOT_MODIFIED {
+        let res = if response.status() == StatusCode::NOT_MODIFIED {
             Ok(None)
         }
         else if response.status() != StatusCode::OK {
-            warn!(
-                "RRDP {}: Getting notification file failed with status {}",
-                uri, response.status()
-            );
+            info.retry_after = response.retry_after();
+            match response.rate_limit() {
+                Some(delay) => {
+                    warn!(
+                        "RRDP {}: Getting notification file was rate-limited \
+                         with status {}, server asks to retry after {} \
+                         seconds",
+                        uri, response.status(), delay.as_secs()
+                    );
+                }
+                None => {
+                    warn!(
+                        "RRDP {}: Getting notification file failed with \
+                         status {}",
+                        uri, response.status()
+                    );
+                }
+            }
             Err(Failed)
         }
         else {
             Notification::from_response(
-                uri.clone(), response, delta_list_limit
+                uri.clone(), response, config.max_delta_list_len
             ).map(Some)
-        }
+        };
+        (res, info)
     }
 
 
     /// Creates a new notification from a successful HTTP response.
     ///
-    /// Assumes that the response status was 200 OK.
+    /// Assumes that the response status was 200 OK. If the response is only
+    /// to be kept on error, it is kept if parsing fails.
     fn from_response(
+        uri: uri::Https, mut response: HttpResponse, delta_list_limit: usize
+    ) -> Result<Self, Failed> {
+        let copy = response.take_copy();
+        let res = Self::parse_response(uri, response, delta_list_limit);
+        if let (Err(_), Some(copy)) = (&res, copy) {
+            copy.keep()
+        }
+        res
+    }
+
+    /// Parses the notification file from a successful HTTP response.
+    fn parse_response(
         uri: uri::Https, response: HttpResponse, delta_list_limit: usize
     ) -> Result<Self, Failed> {
         let etag = response.etag();
@@ -98,7 +138,7 @@ impl Notification {
         let mut content = NotificationFile::parse_limited(
             io::BufReader::new(response), delta_list_limit
         ).map_err(|err| {
-            warn!("RRDP {}: {}", uri, err);
+            warn!(repository = uri.as_str(); "RRDP {}: {}", uri, err);
             Failed
         })?;
         if !content.has_matching_origins(&uri) {
@@ -111,6 +151,11 @@ impl Notification {
         Ok(Notification { uri, content, etag, last_modified })
     }
 
+    /// Returns the Last-Modified time of the notification file if known.
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.last_modified
+    }
+
     /// Returns a reference to the content of the notification file.
     pub fn content(&self) -> &NotificationFile {
         &self.content
@@ -131,6 +176,8 @@ impl Notification {
             delta_state: self.content.deltas().iter().map(|delta| {
                 (delta.serial(), delta.hash())
             }).collect(),
+            history: Default::default(),
+            retry_after_ts: None,
         }
     }
 
@@ -153,6 +200,37 @@ impl Notification {
 }
 
 
+//------------ ResponseInfo --------------------------------------------------
+
+/// Information about the response to a request for a notification file.
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseInfo {
+    /// The status of the request.
+    pub status: HttpStatus,
+
+    /// The address the response was received from if known.
+    pub remote_addr: Option<IpAddr>,
+
+    /// The offset in seconds of the local clock from the server’s clock.
+    pub clock_offset: Option<i64>,
+
+    /// The delay before retrying requested by an error response.
+    pub retry_after: Option<Duration>,
+}
+
+impl ResponseInfo {
+    /// Creates information with only the status.
+    fn from_status(status: HttpStatus) -> Self {
+        ResponseInfo {
+            status,
+            remote_addr: None,
+            clock_offset: None,
+            retry_after: None,
+        }
+    }
+}
+
+
 //------------ SnapshotUpdate ------------------------------------------------
 
 /// An update to a repository performed from a snapshot file.
@@ -172,6 +250,15 @@ pub struct SnapshotUpdate<'a> {
 
     /// The metrics for the update.
     metrics: &'a mut RrdpRepositoryMetrics,
+
+    /// The time by which the update must have finished.
+    deadline: Option<Instant>,
+
+    /// The number of objects published so far.
+    objects: u64,
+
+    /// The overall size of the objects published so far.
+    size: u64,
 }
 
 impl<'a> SnapshotUpdate<'a> {
@@ -180,17 +267,27 @@ impl<'a> SnapshotUpdate<'a> {
         archive: &'a mut RrdpArchive,
         notify: &'a Notification,
         metrics: &'a mut RrdpRepositoryMetrics,
+        deadline: Option<Instant>,
     ) -> Self {
-        SnapshotUpdate { collector, archive, notify, metrics }
+        SnapshotUpdate {
+            collector, archive, notify, metrics, deadline,
+            objects: 0,
+            size: 0,
+        }
     }
 
     pub fn try_update(mut self) -> Result<(), SnapshotError> {
-        let response = match self.collector.http().response(
-            self.notify.content.snapshot().uri(), false
+        let mut response = match self.collector.http().response(
+            self.notify.content.snapshot().uri(), self.deadline, false
         ) {
             Ok(response) => {
                 self.metrics.payload_status = Some(response.status().into());
-                if response.status() != StatusCode::OK {
+                if let Some(delay) = response.rate_limit() {
+                    return Err(SnapshotError::RateLimited(
+                        response.status(), delay
+                    ))
+                }
+                else if response.status() != StatusCode::OK {
                     return Err(response.status().into())
                 }
                 else {
@@ -198,11 +295,25 @@ impl<'a> SnapshotUpdate<'a> {
                 }
             }
             Err(err) => {
-                self.metrics.payload_status = Some(HttpStatus::Error);
+                self.metrics.payload_status = Some(
+                    HttpStatus::from_error(&err)
+                );
                 return Err(err.into())
             }
         };
 
+        let copy = response.take_copy();
+        let res = self.update_from_response(response);
+        if let (Err(_), Some(copy)) = (&res, copy) {
+            copy.keep()
+        }
+        res
+    }
+
+    /// Updates the archive from the snapshot file in the response.
+    fn update_from_response(
+        &mut self, response: HttpResponse
+    ) -> Result<(), SnapshotError> {
         let mut reader = io::BufReader::new(HashRead::new(response));
         self.process(&mut reader)?;
         let hash = reader.into_inner().into_hash();
@@ -219,6 +330,36 @@ impl<'a> SnapshotUpdate<'a> {
         )?;
         Ok(())
     }
+
+    /// Accounts for a published object and checks the repository limits.
+    ///
+    /// Once the limits have been exceeded, no further checks are done.
+    fn check_limits(&mut self, len: usize) -> Result<(), SnapshotError> {
+        let limits = self.collector.config().limits;
+        if !limits.is_enabled() || self.metrics.limit_exceeded {
+            return Ok(())
+        }
+        self.objects += 1;
+        self.size = self.size.saturating_add(
+            u64::try_from(len).unwrap_or(u64::MAX)
+        );
+        if let Err(err) = limits.check(self.objects, self.size) {
+            self.metrics.limit_exceeded = true;
+            match limits.policy() {
+                FilterPolicy::Reject => {
+                    return Err(SnapshotError::LimitExceeded(err))
+                }
+                FilterPolicy::Warn => {
+                    warn!(
+                        repository = self.notify.uri.as_str();
+                        "RRDP {}: {}.", self.notify.uri, err
+                    );
+                }
+                FilterPolicy::Accept => { }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl ProcessSnapshot for SnapshotUpdate<'_> {
@@ -252,6 +393,7 @@ impl ProcessSnapshot for SnapshotUpdate<'_> {
         let content = RrdpDataRead::new(
             data, &uri, self.collector.config().max_object_size,
         ).read_all()?;
+        self.check_limits(content.len())?;
         self.archive.publish_object(&uri, &content).map_err(|err| match err {
             PublishError::AlreadyExists => {
                 SnapshotError::DuplicateObject(uri.clone())
@@ -300,6 +442,9 @@ pub struct DeltaUpdate<'a> {
     /// The metrics for the update.
     metrics: &'a mut RrdpRepositoryMetrics,
 
+    /// The time by which the update must have finished.
+    deadline: Option<Instant>,
+
     /// The URIs we’ve already seen in this delta.
     ///
     /// This is so we can error out if a URI was touched more than once.
@@ -314,20 +459,26 @@ impl<'a> DeltaUpdate<'a> {
         session_id: Uuid,
         info: &'a DeltaInfo,
         metrics: &'a mut RrdpRepositoryMetrics,
+        deadline: Option<Instant>,
     ) -> Self {
         DeltaUpdate {
-            collector, archive, session_id, info, metrics,
+            collector, archive, session_id, info, metrics, deadline,
             seen: Default::default(),
         }
     }
 
     pub fn try_update(mut self) -> Result<(), DeltaError> {
-        let response = match self.collector.http().response(
-            self.info.uri(), false
+        let mut response = match self.collector.http().response(
+            self.info.uri(), self.deadline, false
         ) {
             Ok(response) => {
                 self.metrics.payload_status = Some(response.status().into());
-                if response.status() != StatusCode::OK {
+                if let Some(delay) = response.rate_limit() {
+                    return Err(DeltaError::RateLimited(
+                        response.status(), delay
+                    ))
+                }
+                else if response.status() != StatusCode::OK {
                     return Err(response.status().into())
                 }
                 else {
@@ -335,11 +486,25 @@ impl<'a> DeltaUpdate<'a> {
                 }
             }
             Err(err) => {
-                self.metrics.payload_status = Some(HttpStatus::Error);
+                self.metrics.payload_status = Some(
+                    HttpStatus::from_error(&err)
+                );
                 return Err(err.into())
             }
         };
 
+        let copy = response.take_copy();
+        let res = self.update_from_response(response);
+        if let (Err(_), Some(copy)) = (&res, copy) {
+            copy.keep()
+        }
+        res
+    }
+
+    /// Applies the delta file in the response to the archive.
+    fn update_from_response(
+        &mut self, response: HttpResponse
+    ) -> Result<(), DeltaError> {
         let mut reader = io::BufReader::new(HashRead::new(response));
         self.process(&mut reader)?;
         let hash = reader.into_inner().into_hash();
@@ -589,7 +754,7 @@ impl<R: io::Read> io::Read for RrdpDataRead<'_, R> {
 //------------ SnapshotReason ------------------------------------------------
 
 /// The reason why a snapshot was used.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SnapshotReason {
     /// The respository is new.
     NewRepository,
@@ -670,6 +835,7 @@ enum RrdpDataReadError {
 pub enum SnapshotError {
     Http(reqwest::Error),
     HttpStatus(StatusCode),
+    RateLimited(StatusCode, Duration),
     Rrdp(rrdp::ProcessError),
     SessionMismatch {
         expected: Uuid,
@@ -682,6 +848,7 @@ pub enum SnapshotError {
     DuplicateObject(uri::Rsync),
     HashMismatch,
     LargeObject(uri::Rsync),
+    LimitExceeded(LimitExceeded),
     RunFailed(RunFailed),
 }
 
@@ -735,6 +902,12 @@ impl fmt::Display for SnapshotError {
             SnapshotError::HttpStatus(status) => {
                 write!(f, "HTTP {}", status)
             }
+            SnapshotError::RateLimited(status, delay) => {
+                write!(
+                    f, "HTTP {}, server asks to retry after {} seconds",
+                    status, delay.as_secs()
+                )
+            }
             SnapshotError::Rrdp(ref err) => err.fmt(f),
             SnapshotError::SessionMismatch { ref expected, ref received } => {
                 write!(
@@ -761,6 +934,7 @@ impl fmt::Display for SnapshotError {
             SnapshotError::LargeObject(ref uri) => {
                 write!(f, "object exceeds size limit: {}", uri)
             }
+            SnapshotError::LimitExceeded(ref err) => err.fmt(f),
             SnapshotError::RunFailed(_) => Ok(()),
         }
     }
@@ -780,6 +954,7 @@ impl error::Error for SnapshotError { }
 pub enum DeltaError {
     Http(reqwest::Error),
     HttpStatus(StatusCode),
+    RateLimited(StatusCode, Duration),
     Rrdp(rrdp::ProcessError),
     SessionMismatch {
         expected: Uuid,
@@ -850,6 +1025,12 @@ impl fmt::Display for DeltaError {
             DeltaError::HttpStatus(status) => {
                 write!(f, "HTTP {}", status)
             }
+            DeltaError::RateLimited(status, delay) => {
+                write!(
+                    f, "HTTP {}, server asks to retry after {} seconds",
+                    status, delay.as_secs()
+                )
+            }
             DeltaError::Rrdp(ref err) => err.fmt(f),
             DeltaError::SessionMismatch { ref expected, ref received } => {
                 write!(
@@ -12,27 +12,53 @@
 //! requested, we spawn rsync and block until it returns. If during that time
 //! another thread requests access to the same module, that thread is blocked,
 //! too.
+//!
+//! Distinct modules are updated concurrently by the validation threads
+//! requesting them. The number of rsync processes running at the same time
+//! can be limited. If the limit is reached, threads wait for a free slot
+//! before spawning rsync.
+//!
+//! Unless disabled, the listing of a module is fetched via `--list-only`
+//! before the actual update. If its digest is the same as after the last
+//! update, the update is skipped. The digest is kept in a file next to the
+//! module’s directory. Since the listing may miss some changes, the module
+//! is updated regardless after a number of skipped updates.
+//!
+//! Modules are never updated in place. Instead, rsync syncs into a
+//! temporary directory next to the module’s directory, using the current
+//! content via `--link-dest` so unchanged files are hard-linked rather than
+//! transferred. Only if rsync succeeds is the temporary directory swapped
+//! in. This way, a failed or interrupted update leaves the previous content
+//! of the module intact. The time of the last successful update is kept in
+//! a state file next to the module’s directory. Temporary directories left
+//! behind by an interrupted run are cleaned up when the collector is
+//! ignited.
 
 use std::{fmt, fs, io, ops};
 use std::borrow::{Borrow, Cow};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::{Command as StdCommand, ExitStatus, Stdio};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 use bytes::Bytes;
 use futures::{FutureExt, TryFutureExt};
 use futures::future::Either;
 use log::{debug, error, info, warn};
+use rpki::crypto::digest::DigestAlgorithm;
 use rpki::uri;
 use tokio::io::AsyncBufReadExt;
 use tokio::process::Command as AsyncCommand;
-use crate::config::Config;
+use crate::collector::base::limit_timeout;
+use crate::collector::limits::RepositoryLimits;
+use crate::config::{Config, FilterPolicy};
 use crate::error::{Failed, Fatal};
-use crate::metrics::{Metrics, RsyncModuleMetrics};
+use crate::metrics::{CacheUsageMetrics, Metrics, RsyncModuleMetrics};
+use crate::utils::dump::DumpTarget;
 use crate::utils::fatal;
-use crate::utils::sync::{Mutex, RwLock};
+use crate::utils::sync::{Mutex, RwLock, Semaphore};
 use crate::utils::uri::UriExt;
 
 
@@ -52,6 +78,20 @@ pub struct Collector {
 
     /// Whether to filter dubious authorities in rsync URIs.
     filter_dubious: bool,
+
+    /// The limits for the content of a module.
+    limits: RepositoryLimits,
+
+    /// The maximum number of concurrent rsync processes.
+    ///
+    /// A value of zero means that there is no limit.
+    max_concurrent: usize,
+
+    /// The maximum number of consecutive skipped updates of a module.
+    ///
+    /// If this is `None`, listings aren’t checked and modules are always
+    /// updated.
+    list_max_skips: Option<usize>,
 }
  
 
@@ -64,9 +104,14 @@ impl Collector {
         Ok(())
     }
 
+    /// Returns the path of the working dir.
+    fn working_dir_path(config: &Config) -> PathBuf {
+        config.cache_dir.join("rsync")
+    }
+
     /// Creates the working dir and returns its path.
     fn create_working_dir(config: &Config) -> Result<PathBuf, Failed> {
-        let working_dir = config.cache_dir.join("rsync");
+        let working_dir = Self::working_dir_path(config);
 
         if config.fresh {
             if let Err(err) = fs::remove_dir_all(&working_dir) {
@@ -103,16 +148,43 @@ impl Collector {
                     Self::create_working_dir(config)?
                 ),
                 command: Some(RsyncCommand::new(config)?),
-                filter_dubious: !config.allow_dubious_hosts
+                filter_dubious: !config.allow_dubious_hosts,
+                limits: RepositoryLimits::from_config(config),
+                max_concurrent: config.rsync_max_concurrent,
+                list_max_skips: if config.disable_rsync_list_check {
+                    None
+                }
+                else {
+                    Some(config.rsync_list_max_skips)
+                },
             }))
         }
     }
 
+    /// Returns the paths used for the module of the given rsync URI.
+    ///
+    /// These are the path of the module’s directory, the paths of its
+    /// listing and sync state files, and the paths of the temporary
+    /// directories used during an update. None of them needs to exist.
+    pub fn module_paths(
+        config: &Config, uri: &uri::Rsync
+    ) -> Vec<PathBuf> {
+        let working_dir = WorkingDir::new(Self::working_dir_path(config));
+        let module = Module::from_uri(uri);
+        vec![
+            working_dir.module_path(&module),
+            working_dir.listing_path(&module),
+            working_dir.state_path(&module),
+            working_dir.suffixed_path(&module, TMP_SUFFIX),
+            working_dir.suffixed_path(&module, OLD_SUFFIX),
+        ]
+    }
+
     /// Prepares the collector for use in a validation run.
+    ///
+    /// Recovers from updates interrupted by a previous process.
     pub fn ignite(&mut self) -> Result<(), Failed> {
-        // We don’t need to do anything. But just in case we later will,
-        // let’s keep the method around.
-        Ok(())
+        self.working_dir.recover()
     }
 
     /// Sanitizes the stored data.
@@ -123,84 +195,33 @@ impl Collector {
     }
 
     /// Start a validation run on the collector.
-    pub fn start(&self) -> Run {
-        Run::new(self)
+    ///
+    /// If `deadline` is given, rsync processes still running at that time
+    /// are killed.
+    pub fn start(&self, deadline: Option<Instant>) -> Run<'_> {
+        Run::new(self, deadline)
     }
 
     /// Dumps the content of the rsync collector.
-    pub fn dump(&self, dir: &Path) -> Result<(), Failed> {
-        let target = dir.join("rsync");
-        debug!("Dumping rsync collector content to {}", target.display());
-
-        if let Err(err) = fs::remove_dir_all(&target) {
-            if err.kind() != io::ErrorKind::NotFound {
-                error!(
-                    "Failed to delete directory {}: {}",
-                    dir.display(), err
-                );
-                return Err(Failed)
-            }
-        }
-        Self::dump_dir(&self.working_dir.base, &target)?;
+    pub fn dump(&self, target: &mut DumpTarget) -> Result<(), Failed> {
+        let dir = Path::new("rsync");
+        debug!("Dumping rsync collector content.");
+        target.clear(dir)?;
+        target.copy_dir_all(dir, &self.working_dir.base)?;
         debug!("Rsync collector dump complete.");
         Ok(())
     }
 
-    /// Recursively copies the content of `source` to `target`.
-    fn dump_dir(source: &Path, target: &Path) -> Result<(), Failed> {
-        let read_dir = match fs::read_dir(source) {
-            Ok(read_dir) => read_dir,
-            Err(err) => {
-                error!(
-                    "Failed to open directory {}: {}", source.display(), err
-                );
-                return Err(Failed)
-            }
-        };
-        for item in read_dir {
-            let item = match item {
-                Ok(item) => item,
-                Err(err) => {
-                    error!(
-                        "Failed to read directory {}: {}",
-                        source.display(), err
-                    );
-                    return Err(Failed)
-                }
-            };
-            let file_type = match item.file_type() {
-                Ok(file_type) => file_type,
-                Err(err) => {
-                    error!(
-                        "Failed to file type for {}: {}",
-                        item.path().display(), err
-                    );
-                    return Err(Failed)
-                }
-            };
-
-            if file_type.is_dir() {
-                let target = target.join(item.file_name());
-                if let Err(err) = fs::create_dir_all(&target) {
-                    error!(
-                        "Failed to create directory {}: {}",
-                        target.display(), err
-                    );
-                    return Err(Failed);
-                }
-                Self::dump_dir(&item.path(), &target)?;
-            }
-            else if file_type.is_file() {
-                let target = target.join(item.file_name());
-                if let Err(err) = fs::copy(item.path(), &target) {
-                    error!(
-                        "Failed to copy {} to {}: {}",
-                        item.path().display(), target.display(), err
-                    );
-                    return Err(Failed)
-                }
-            }
-        }
+    /// Restores the collector content from a dump in `source`.
+    ///
+    /// Files present in the dump replace those in the working directory.
+    /// Anything else is left in place and will be dealt with by rsync
+    /// during the next update.
+    pub fn restore(config: &Config, source: &Path) -> Result<(), Failed> {
+        let working_dir = Self::create_working_dir(config)?;
+        debug!("Restoring rsync collector content.");
+        fatal::copy_existing_dir_all(&source.join("rsync"), &working_dir)?;
+        debug!("Rsync collector restore complete.");
         Ok(())
     }
 }
@@ -224,19 +245,48 @@ pub struct Run<'a> {
     /// allowed to actually run rsync.
     running: RwLock<HashMap<OwnedModule, Arc<Mutex<()>>>>,
 
+    /// The modules that were rejected because they exceed the limits.
+    rejected: RwLock<HashSet<OwnedModule>>,
+
+    /// The modules for which running rsync failed.
+    failed: RwLock<HashSet<OwnedModule>>,
+
+    /// Limits the number of rsync processes running concurrently.
+    ///
+    /// If this is `None`, there is no limit.
+    processes: Option<Semaphore>,
+
+    /// The number of rsync processes currently running.
+    active: AtomicUsize,
+
+    /// The largest number of rsync processes running at the same time.
+    peak: AtomicUsize,
+
     /// The metrics for updated rsync modules.
     metrics: Mutex<Vec<RsyncModuleMetrics>>,
+
+    /// The time by which all rsync processes must have finished.
+    deadline: Option<Instant>,
 }
 
 
 impl<'a> Run<'a> {
     /// Creates a new runner from a collector.
-    fn new(collector: &'a Collector) -> Self {
+    fn new(collector: &'a Collector, deadline: Option<Instant>) -> Self {
         Run {
             collector,
             updated: Default::default(),
             running: Default::default(),
+            rejected: Default::default(),
+            failed: Default::default(),
+            processes: match collector.max_concurrent {
+                0 => None,
+                limit => Some(Semaphore::new(limit)),
+            },
+            active: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
             metrics: Default::default(),
+            deadline,
         }
     }
 
@@ -248,21 +298,50 @@ impl<'a> Run<'a> {
         self.updated.read().contains(Module::from_uri(uri).as_ref())
     }
 
+    /// Returns whether updating the module for the given URI failed.
+    ///
+    /// This is only `true` if an update was attempted during this run and
+    /// rsync didn’t succeed.
+    pub fn has_failed(&self, uri: &uri::Rsync) -> bool {
+        self.failed.read().contains(Module::from_uri(uri).as_ref())
+    }
+
+    /// Notes that RRDP was used instead of the module for the given URI.
+    ///
+    /// Returns whether this is the first time for the module.
+    pub fn note_rrdp_fallback(&self, uri: &uri::Rsync) -> bool {
+        let module = Module::from_uri(uri);
+        let mut metrics = self.metrics.lock();
+        let item = metrics.iter_mut().find(|item| {
+            Module::from_uri(&item.module).as_ref() == module.as_ref()
+        });
+        match item {
+            Some(item) if !item.rrdp_fallback => {
+                item.rrdp_fallback = true;
+                true
+            }
+            _ => false
+        }
+    }
+
     /// Tries to update the module for the given URI.
     ///
     /// If the module has not yet been updated, may block until an update
     /// finished. This update may not be successful and files in the module
     /// may be outdated or missing completely.
-    pub fn load_module(&self, uri: &uri::Rsync) {
+    ///
+    /// Returns whether the module can be used. This is not the case if it
+    /// was rejected because it exceeds the repository limits.
+    pub fn load_module(&self, uri: &uri::Rsync) -> bool {
         let command = match self.collector.command.as_ref() {
             Some(command) => command,
-            None => return,
+            None => return true,
         };
         let module = Module::from_uri(uri);
 
         // If it is already up-to-date, return.
         if self.updated.read().contains(module.as_ref()) {
-            return
+            return !self.rejected.read().contains(module.as_ref())
         }
 
         // Get a clone of the (arc-ed) mutex. Make a new one if there isn’t
@@ -277,7 +356,7 @@ impl<'a> Run<'a> {
         // which happens if someone else had it first.
         let _lock = mutex.lock();
         if self.updated.read().contains(module.as_ref()) {
-            return
+            return !self.rejected.read().contains(module.as_ref())
         }
 
         // Check if the module name is dubious. If so, skip updating.
@@ -289,10 +368,38 @@ impl<'a> Run<'a> {
         }
         else {
             // Run the actual update.
-            let metrics = command.update(
-                module.as_ref(),
-                &self.collector.working_dir.module_path(module.as_ref())
+            let path = self.collector.working_dir.module_path(
+                module.as_ref()
             );
+            let mut metrics = self.update(command, module.as_ref(), &path);
+
+            if !matches!(metrics.status, Ok(status) if status.success()) {
+                self.failed.write().insert(module.clone().into_owned());
+                let state = SyncState::read(
+                    &self.collector.working_dir.state_path(module.as_ref())
+                );
+                match state {
+                    Some(state) => {
+                        info!(
+                            "{}: update failed. Using data from last \
+                             successful update {} seconds ago.",
+                            module, state.age().as_secs()
+                        );
+                    }
+                    None => {
+                        info!(
+                            "{}: update failed and no previous data \
+                             available.",
+                            module
+                        );
+                    }
+                }
+            }
+
+            // Check the limits.
+            if !self.check_limits(module.as_ref(), &path, &mut metrics) {
+                self.rejected.write().insert(module.clone().into_owned());
+            }
 
             // Insert into updated map and metrics.
             self.metrics.lock().push(metrics);
@@ -302,7 +409,186 @@ impl<'a> Run<'a> {
         self.running.write().remove(module.as_ref());
 
         // Insert into updated map no matter what.
+        let res = !self.rejected.read().contains(module.as_ref());
         self.updated.write().insert(module.into_owned());
+        res
+    }
+
+    /// Runs rsync for a module once there is a free process slot.
+    fn update(
+        &self,
+        command: &RsyncCommand,
+        module: &Module,
+        path: &Path,
+    ) -> RsyncModuleMetrics {
+        // Wait for our turn if there is a limit on concurrent processes.
+        let queued = Instant::now();
+        let _permit = self.processes.as_ref().map(|processes| {
+            processes.acquire()
+        });
+        let queue_duration = queued.elapsed();
+
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak.fetch_max(active, Ordering::Relaxed);
+        let mut metrics = match self.collector.list_max_skips {
+            Some(max_skips) => {
+                self.checked_update(command, module, path, max_skips)
+            }
+            None => self.sync(command, module, path),
+        };
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        metrics.queue_duration = queue_duration;
+        metrics
+    }
+
+    /// Updates a module via a temporary directory.
+    ///
+    /// Runs rsync into the temporary directory and, if it succeeds, swaps
+    /// it in for the module’s directory and records the time of the
+    /// update in the module’s sync state. Otherwise, the temporary
+    /// directory is removed again and the module is left untouched.
+    fn sync(
+        &self,
+        command: &RsyncCommand,
+        module: &Module,
+        path: &Path,
+    ) -> RsyncModuleMetrics {
+        let working_dir = &self.collector.working_dir;
+        let tmp_path = working_dir.suffixed_path(module, TMP_SUFFIX);
+        if let Err(err) = remove_dir_all(&tmp_path) {
+            warn!(
+                "{}: failed to remove temporary directory {}: {}",
+                module, tmp_path.display(), err
+            );
+        }
+        let mut metrics = command.update(
+            module, &tmp_path, path.is_dir().then_some(path), self.deadline
+        );
+        if matches!(metrics.status, Ok(status) if status.success()) {
+            match working_dir.swap(module, &tmp_path, path) {
+                Ok(()) => {
+                    SyncState::now().write(&working_dir.state_path(module))
+                }
+                Err(err) => {
+                    error!(
+                        "{}: failed to move updated module into place: {}",
+                        module, err
+                    );
+                    metrics.status = Err(err);
+                }
+            }
+        }
+        if let Err(err) = remove_dir_all(&tmp_path) {
+            warn!(
+                "{}: failed to remove temporary directory {}: {}",
+                module, tmp_path.display(), err
+            );
+        }
+        metrics
+    }
+
+    /// Updates a module unless its listing hasn’t changed.
+    ///
+    /// The update is skipped if the digest of the listing is the same as
+    /// the one stored after the last update and the update hasn’t been
+    /// skipped `max_skips` times in a row already. It is never skipped if
+    /// there is no record of a successful update.
+    fn checked_update(
+        &self,
+        command: &RsyncCommand,
+        module: &Module,
+        path: &Path,
+        max_skips: usize,
+    ) -> RsyncModuleMetrics {
+        let state_path = self.collector.working_dir.listing_path(module);
+        let state = ListingState::read(&state_path);
+        let start = SystemTime::now();
+        let (status, output) = command.list(module, self.deadline);
+        let digest = match status {
+            Ok(status) if status.success() => listing_digest(&output.stdout),
+            Ok(_) => None,
+            Err(_) => {
+                // The server didn’t respond properly, so there is no
+                // point in trying the actual update.
+                ListingState::remove(&state_path);
+                return RsyncModuleMetrics::new(
+                    module, status, start, &output
+                )
+            }
+        };
+        if let (Some(state), Some(digest)) = (state, digest) {
+            if
+                state.digest == digest && state.skips < max_skips
+                && path.is_dir()
+                && SyncState::read(
+                    &self.collector.working_dir.state_path(module)
+                ).is_some()
+            {
+                debug!("{}: listing unchanged. Skipping update.", module);
+                ListingState {
+                    digest, skips: state.skips + 1
+                }.write(&state_path);
+                let mut metrics = RsyncModuleMetrics::new(
+                    module, status, start, &output
+                );
+                metrics.unchanged = true;
+                return metrics
+            }
+        }
+        let metrics = self.sync(command, module, path);
+        match digest {
+            Some(digest) if matches!(
+                metrics.status, Ok(status) if status.success()
+            ) => {
+                ListingState { digest, skips: 0 }.write(&state_path)
+            }
+            _ => ListingState::remove(&state_path),
+        }
+        metrics
+    }
+
+    /// Checks the repository limits for a module after an update.
+    ///
+    /// Returns whether the module is acceptable.
+    fn check_limits(
+        &self,
+        module: &Module,
+        path: &Path,
+        metrics: &mut RsyncModuleMetrics,
+    ) -> bool {
+        let limits = self.collector.limits;
+        if !limits.is_enabled() {
+            return true
+        }
+        let (objects, size) = match dir_size(path) {
+            Ok(some) => some,
+            Err(err) => {
+                warn!(
+                    "{}: failed to determine size of module: {}",
+                    module, err
+                );
+                return true
+            }
+        };
+        let err = match limits.check(objects, size) {
+            Ok(()) => return true,
+            Err(err) => err,
+        };
+        metrics.limit_exceeded = true;
+        match limits.policy() {
+            FilterPolicy::Reject => {
+                warn!(
+                    repository = module.as_str();
+                    "{}: {}. Rejecting module.", module, err
+                );
+                false
+            }
+            FilterPolicy::Warn => {
+                warn!(repository = module.as_str(); "{}: {}.", module, err);
+                true
+            }
+            FilterPolicy::Accept => true,
+        }
     }
 
     /// Loads the file for the given URI.
@@ -346,10 +632,14 @@ impl<'a> Run<'a> {
     }
 
     /// Cleans the collector only keeping the modules included in `retain`.
+    ///
+    /// The disk usage of the modules kept is added to `usage`.
     //
     //  This currently is super agressive, deleting everyting that it doesn’t
     //  like.
-    pub fn cleanup(&self, retain: &mut ModuleSet) -> Result<(), Failed> {
+    pub fn cleanup(
+        &self, retain: &mut ModuleSet, usage: &mut CacheUsageMetrics,
+    ) -> Result<(), Failed> {
         if self.collector.command.is_none() {
             return Ok(())
         }
@@ -366,7 +656,9 @@ impl<'a> Run<'a> {
             let keep = match entry.file_name().to_str() {
                 Some(name) => {
                     match retain.authorities.get(name) {
-                        Some(modules) => self.cleanup_host(&entry, modules)?,
+                        Some(modules) => {
+                            self.cleanup_host(&entry, name, modules, usage)?
+                        }
                         None => false,
                     }
                 }
@@ -384,9 +676,13 @@ impl<'a> Run<'a> {
     /// Removes all modules from the directory that are not in `retain`.
     ///
     /// Returns whether the host directory should be kept or can be deleted,
-    /// too.
+    /// too. The disk usage of the modules kept is added to `usage`.
     fn cleanup_host(
-        &self, entry: &fatal::DirEntry, retain: &HashSet<String>
+        &self,
+        entry: &fatal::DirEntry,
+        host: &str,
+        retain: &HashSet<String>,
+        usage: &mut CacheUsageMetrics,
     ) -> Result<bool, Failed> {
         if entry.is_file() {
             fatal::remove_file(entry.path())?;
@@ -397,21 +693,34 @@ impl<'a> Run<'a> {
         }
 
         let mut keep_host = false;
+        let mut modules = BTreeMap::new();
         for entry in fatal::read_dir(entry.path())? {
             let entry = entry?;
-            let keep = match entry.file_name().to_str() {
-                Some(name) => retain.contains(name),
-                None => false
+            let module = match entry.file_name().to_str() {
+                Some(name) => {
+                    let name = name.strip_suffix(
+                        LISTING_SUFFIX
+                    ).or_else(|| {
+                        name.strip_suffix(STATE_SUFFIX)
+                    }).unwrap_or(name);
+                    retain.contains(name).then(|| name.to_string())
+                }
+                None => None
             };
 
-            if !keep {
-                fatal::remove_all(entry.path())?;
-            }
-            else {
-                keep_host = true;
+            match module {
+                Some(module) => {
+                    *modules.entry(module).or_default() +=
+                        fatal::disk_usage(&entry)?;
+                    keep_host = true;
+                }
+                None => fatal::remove_all(entry.path())?,
             }
         }
 
+        usage.repositories.extend(modules.into_iter().map(|(module, size)| {
+            (format!("rsync://{}/{}/", host, module), size)
+        }));
         Ok(keep_host)
     }
 
@@ -423,6 +732,7 @@ impl<'a> Run<'a> {
     /// value, instead.
     pub fn done(self, metrics: &mut Metrics) {
         metrics.rsync = self.metrics.into_inner();
+        metrics.rsync_peak_processes = self.peak.into_inner();
     }
 }
 
@@ -471,6 +781,7 @@ impl RsyncCommand {
                 let mut args = Vec::new();
                 args.push("--no-motd".into());
                 args.push("-z".into());
+                args.push("--stats".into());
                 let has_contimeout =
                    output.stdout.windows(12)
                    .any(|window| window == b"--contimeout");
@@ -491,27 +802,59 @@ impl RsyncCommand {
     }
 
     /// Updates a module by running rsync.
+    ///
+    /// If `link_dest` is given, it is the directory with the current
+    /// content of the module. It must be a sibling of `destination`.
+    ///
+    /// If `deadline` is given, the rsync process is killed if it hasn’t
+    /// finished by then.
     pub fn update(
         &self,
         source: &Module,
-        destination: &Path
+        destination: &Path,
+        link_dest: Option<&Path>,
+        deadline: Option<Instant>,
     ) -> RsyncModuleMetrics {
         let start = SystemTime::now();
+        let mut output = RsyncOutput::default();
         let status = self.command(
-            source, destination
-        ).and_then(|cmd| self.run(source, cmd));
-        RsyncModuleMetrics {
-            module: source.to_uri(),
-            status,
-            duration: SystemTime::now().duration_since(start),
+            source, destination, link_dest
+        ).and_then(|cmd| self.run(source, cmd, deadline, &mut output));
+        RsyncModuleMetrics::new(source, status, start, &output)
+    }
+
+    /// Fetches the listing of a module.
+    ///
+    /// The listing is available in the standard output kept in the
+    /// returned output.
+    pub fn list(
+        &self,
+        source: &Module,
+        deadline: Option<Instant>,
+    ) -> (Result<ExitStatus, io::Error>, RsyncOutput) {
+        let mut output = RsyncOutput::default();
+        let mut cmd = AsyncCommand::new(&self.command);
+        for item in &self.args {
+            cmd.arg(item);
         }
+        cmd.arg("-r")
+           .arg("--list-only")
+           .arg(source.to_string());
+        debug!("{}: Running command {:?}", source, cmd);
+        let status = self.run(source, cmd, deadline, &mut output);
+        (status, output)
     }
 
     /// Actually runs the rsync command.
+    ///
+    /// Information gleaned from the output of the command is added to
+    /// `output`.
     fn run(
         &self,
         source: &Module,
-        mut command: AsyncCommand
+        mut command: AsyncCommand,
+        deadline: Option<Instant>,
+        output: &mut RsyncOutput,
     ) -> Result<ExitStatus, io::Error> {
         // Because we can’t have a timeout on a child process with just std,
         // we resort to Tokio here: We fire up a current-thread runtime and
@@ -533,7 +876,7 @@ impl RsyncCommand {
             );
             let mut stdout = Vec::new();
             let res = tokio::try_join!(
-                match self.timeout {
+                match limit_timeout(self.timeout, deadline) {
                     None => Either::Left(child.wait().map(Ok)),
                     Some(timeout) => {
                         Either::Right(
@@ -558,6 +901,7 @@ impl RsyncCommand {
                     if let Some(mut pipe) = stderr_pipe {
                         let mut line = Vec::new();
                         while pipe.read_until(b'\n', &mut line).await? != 0 {
+                            output.push_stderr(&line);
                             Self::log_err_line(source, &mut line);
                         }
                     }
@@ -584,12 +928,14 @@ impl RsyncCommand {
                 }
             };
             if !stdout.is_empty() {
+                output.bytes_received = parse_bytes_received(&stdout);
                 String::from_utf8_lossy(&stdout).lines().for_each(|l| {
-                    info!("{}: {}", source, l);
+                    debug!("{}: {}", source, l);
                 })
             }
+            output.stdout = stdout;
             if let Err(ref err) = status {
-                warn!("{}: {}", source, err);
+                warn!(repository = source.as_str(); "{}: {}", source, err);
             }
             status
         })
@@ -599,7 +945,8 @@ impl RsyncCommand {
     fn command(
         &self,
         source: &Module,
-        destination: &Path
+        destination: &Path,
+        link_dest: Option<&Path>,
     ) -> Result<AsyncCommand, io::Error> {
         info!("rsyncing from {}.", source);
         fs::create_dir_all(destination)?;
@@ -621,8 +968,15 @@ impl RsyncCommand {
             cmd.arg(item);
         }
         cmd.arg("-rtO")
-           .arg("--delete")
-           .arg(source.to_string())
+           .arg("--delete");
+        // A relative link-dest path is relative to the destination. Since
+        // both are siblings, this avoids having to translate the path.
+        if let Some(name) = link_dest.and_then(Path::file_name) {
+            if let Some(name) = name.to_str() {
+                cmd.arg(format!("--link-dest=../{}", name));
+            }
+        }
+        cmd.arg(source.to_string())
            .arg(destination);
         debug!("{}: Running command {:?}", source, cmd);
         Ok(cmd)
@@ -715,6 +1069,7 @@ impl RsyncCommand {
 
         if len > 0 {
             warn!(
+                repository = source.as_str();
                 "{}: {}",
                 source,
                 String::from_utf8_lossy(&line[..len])
@@ -726,6 +1081,343 @@ impl RsyncCommand {
 }
 
 
+//------------ RsyncOutput ---------------------------------------------------
+
+/// Information collected from the output of an rsync process.
+#[derive(Debug, Default)]
+struct RsyncOutput {
+    /// The number of bytes received as reported via `--stats`.
+    bytes_received: Option<u64>,
+
+    /// The complete standard output of the process.
+    stdout: Vec<u8>,
+
+    /// The beginning of the process’s standard error output.
+    ///
+    /// This is limited to `STDERR_EXCERPT_LEN` bytes.
+    stderr: Vec<u8>,
+}
+
+impl RsyncOutput {
+    /// Adds a line of standard error output to the excerpt.
+    fn push_stderr(&mut self, line: &[u8]) {
+        let space = STDERR_EXCERPT_LEN.saturating_sub(self.stderr.len());
+        self.stderr.extend_from_slice(&line[..line.len().min(space)]);
+    }
+
+    /// Returns the excerpt of the standard error output if there is any.
+    fn stderr(&self) -> Option<String> {
+        let stderr = String::from_utf8_lossy(&self.stderr);
+        let stderr = stderr.trim_end();
+        if stderr.is_empty() {
+            None
+        }
+        else {
+            Some(stderr.into())
+        }
+    }
+}
+
+
+//------------ RsyncModuleMetrics --------------------------------------------
+
+impl RsyncModuleMetrics {
+    /// Creates the metrics for a finished rsync process.
+    fn new(
+        module: &Module,
+        status: Result<ExitStatus, io::Error>,
+        start: SystemTime,
+        output: &RsyncOutput,
+    ) -> Self {
+        RsyncModuleMetrics {
+            module: module.to_uri(),
+            status,
+            duration: SystemTime::now().duration_since(start),
+            limit_exceeded: false,
+            queue_duration: Duration::from_secs(0),
+            bytes_received: output.bytes_received,
+            stderr: output.stderr(),
+            rrdp_fallback: false,
+            unchanged: false,
+        }
+    }
+}
+
+
+//------------ ListingState --------------------------------------------------
+
+/// The state of a module’s listing kept between updates.
+///
+/// The state is stored in a file next to the module’s directory. It
+/// consists of a version byte, the number of skipped updates as a 32 bit
+/// big-endian integer, and the SHA-256 digest of the listing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ListingState {
+    /// The digest of the listing after the last update.
+    digest: ListingDigest,
+
+    /// The number of consecutive updates skipped since.
+    skips: usize,
+}
+
+impl ListingState {
+    /// The version of the file format.
+    const VERSION: u8 = 0;
+
+    /// The length of the file.
+    const LEN: usize = 1 + 4 + 32;
+
+    /// Reads the state from the given path.
+    ///
+    /// Returns `None` if the file is missing or can’t be read.
+    fn read(path: &Path) -> Option<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Failed to read rsync listing state {}: {}",
+                        path.display(), err
+                    );
+                }
+                return None
+            }
+        };
+        Self::decode(&data)
+    }
+
+    /// Decodes the state from the file’s content.
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != Self::LEN || data[0] != Self::VERSION {
+            return None
+        }
+        let skips = u32::from_be_bytes(data[1..5].try_into().ok()?);
+        Some(ListingState {
+            digest: data[5..].try_into().ok()?,
+            skips: usize::try_from(skips).ok()?,
+        })
+    }
+
+    /// Encodes the state into the file’s content.
+    fn encode(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(Self::LEN);
+        res.push(Self::VERSION);
+        res.extend_from_slice(
+            &u32::try_from(self.skips).unwrap_or(u32::MAX).to_be_bytes()
+        );
+        res.extend_from_slice(&self.digest);
+        res
+    }
+
+    /// Writes the state to the given path.
+    ///
+    /// Failures are logged and otherwise ignored. The module will simply
+    /// be updated next time.
+    fn write(&self, path: &Path) {
+        if let Err(err) = fs::write(path, self.encode()) {
+            warn!(
+                "Failed to write rsync listing state {}: {}",
+                path.display(), err
+            );
+        }
+    }
+
+    /// Removes the state file at the given path if it exists.
+    fn remove(path: &Path) {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "Failed to delete rsync listing state {}: {}",
+                    path.display(), err
+                );
+            }
+        }
+    }
+}
+
+
+//------------ SyncState -----------------------------------------------------
+
+/// The state of a module’s last successful update.
+///
+/// The state is stored in a file next to the module’s directory. It
+/// consists of a version byte and the time of the update as seconds since
+/// the Unix epoch as a 64 bit big-endian integer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct SyncState {
+    /// The time of the last successful update.
+    updated: SystemTime,
+}
+
+impl SyncState {
+    /// The version of the file format.
+    const VERSION: u8 = 0;
+
+    /// The length of the file.
+    const LEN: usize = 1 + 8;
+
+    /// Creates the state for an update that happened just now.
+    fn now() -> Self {
+        SyncState { updated: SystemTime::now() }
+    }
+
+    /// Returns the time passed since the update.
+    fn age(&self) -> Duration {
+        SystemTime::now().duration_since(self.updated).unwrap_or_default()
+    }
+
+    /// Reads the state from the given path.
+    ///
+    /// Returns `None` if the file is missing or can’t be read.
+    fn read(path: &Path) -> Option<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Failed to read rsync sync state {}: {}",
+                        path.display(), err
+                    );
+                }
+                return None
+            }
+        };
+        Self::decode(&data)
+    }
+
+    /// Decodes the state from the file’s content.
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != Self::LEN || data[0] != Self::VERSION {
+            return None
+        }
+        let secs = u64::from_be_bytes(data[1..].try_into().ok()?);
+        Some(SyncState {
+            updated: SystemTime::UNIX_EPOCH.checked_add(
+                Duration::from_secs(secs)
+            )?
+        })
+    }
+
+    /// Encodes the state into the file’s content.
+    fn encode(&self) -> Vec<u8> {
+        let secs = self.updated.duration_since(
+            SystemTime::UNIX_EPOCH
+        ).unwrap_or_default().as_secs();
+        let mut res = Vec::with_capacity(Self::LEN);
+        res.push(Self::VERSION);
+        res.extend_from_slice(&secs.to_be_bytes());
+        res
+    }
+
+    /// Writes the state to the given path.
+    ///
+    /// Failures are logged and otherwise ignored. The module then merely
+    /// looks like it has never been updated successfully.
+    fn write(&self, path: &Path) {
+        if let Err(err) = fs::write(path, self.encode()) {
+            warn!(
+                "Failed to write rsync sync state {}: {}",
+                path.display(), err
+            );
+        }
+    }
+}
+
+
+//------------ listing_digest ------------------------------------------------
+
+/// The digest of a module listing.
+type ListingDigest = [u8; 32];
+
+/// Returns the digest of the listing in rsync’s `--list-only` output.
+///
+/// Only lines describing an entry are considered so that other output,
+/// such as statistics, doesn’t affect the digest. These lines start with
+/// a ten character permissions string. Returns `None` if there are no such
+/// lines, since there always is at least the entry for the module itself.
+fn listing_digest(stdout: &[u8]) -> Option<ListingDigest> {
+    let mut digest = DigestAlgorithm::sha256().start();
+    let mut found = false;
+    for line in stdout.split(|&ch| ch == b'\n') {
+        let mode = match line.split(|&ch| ch == b' ').next() {
+            Some(mode) => mode,
+            None => continue,
+        };
+        if
+            mode.len() != 10
+            || !matches!(
+                mode[0], b'-' | b'd' | b'l' | b'p' | b's' | b'c' | b'b'
+            )
+        {
+            continue
+        }
+        digest.update(line);
+        digest.update(b"\n");
+        found = true;
+    }
+    if !found {
+        return None
+    }
+    digest.finish().as_ref().try_into().ok()
+}
+
+
+//------------ parse_bytes_received ------------------------------------------
+
+/// Returns the number of bytes received from rsync’s `--stats` output.
+///
+/// Returns `None` if the output does not contain the statistics or the
+/// number cannot be parsed.
+fn parse_bytes_received(stdout: &[u8]) -> Option<u64> {
+    String::from_utf8_lossy(stdout).lines().find_map(|line| {
+        let value = line.trim().strip_prefix("Total bytes received:")?;
+        value.trim().replace(',', "").parse().ok()
+    })
+}
+
+
+//------------ dir_size ------------------------------------------------------
+
+/// Returns the number of files and their overall size below a directory.
+///
+/// Symbolic links are not followed. A missing directory is empty.
+fn dir_size(path: &Path) -> Result<(u64, u64), io::Error> {
+    let mut objects = 0;
+    let mut size = 0u64;
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let iter = match fs::read_dir(&dir) {
+            Ok(iter) => iter,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        for entry in iter {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path())
+            }
+            else if file_type.is_file() {
+                objects += 1;
+                size = size.saturating_add(entry.metadata()?.len());
+            }
+        }
+    }
+    Ok((objects, size))
+}
+
+
+//------------ remove_dir_all ------------------------------------------------
+
+/// Removes a directory tree if it exists.
+fn remove_dir_all(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(())
+    }
+}
+
+
 //------------ WorkingDir ----------------------------------------------------
 
 /// The working directory of the rsync collector.
@@ -750,6 +1442,104 @@ impl WorkingDir {
         res
     }
 
+    /// Returns the path of the listing state file for the given module.
+    ///
+    /// This is a file next to the module’s directory.
+    fn listing_path(&self, module: &Module) -> PathBuf {
+        self.suffixed_path(module, LISTING_SUFFIX)
+    }
+
+    /// Returns the path of the sync state file for the given module.
+    ///
+    /// This is a file next to the module’s directory.
+    fn state_path(&self, module: &Module) -> PathBuf {
+        self.suffixed_path(module, STATE_SUFFIX)
+    }
+
+    /// Returns the path next to the module’s directory with a suffix.
+    fn suffixed_path(&self, module: &Module, suffix: &str) -> PathBuf {
+        let mut res = self.base.clone();
+        res.push(format!(
+            "{}{}", module.0[8..].trim_end_matches('/'), suffix
+        ));
+        res
+    }
+
+    /// Swaps the updated content of a module into place.
+    ///
+    /// Moves the current directory at `path` aside, moves `tmp_path` into
+    /// its place and then removes the old directory. If the process is
+    /// interrupted between the two moves, [`recover`][Self::recover]
+    /// moves the old directory back.
+    fn swap(
+        &self, module: &Module, tmp_path: &Path, path: &Path
+    ) -> Result<(), io::Error> {
+        let old_path = self.suffixed_path(module, OLD_SUFFIX);
+        remove_dir_all(&old_path)?;
+        if path.is_dir() {
+            fs::rename(path, &old_path)?;
+        }
+        if let Err(err) = fs::rename(tmp_path, path) {
+            if old_path.is_dir() {
+                let _ = fs::rename(&old_path, path);
+            }
+            return Err(err)
+        }
+        if let Err(err) = remove_dir_all(&old_path) {
+            warn!(
+                "{}: failed to remove old content {}: {}",
+                module, old_path.display(), err
+            );
+        }
+        Ok(())
+    }
+
+    /// Cleans up after updates interrupted by a previous process.
+    ///
+    /// Removes all temporary directories. Old module directories are
+    /// moved back into place if the module’s directory is missing and are
+    /// removed otherwise.
+    fn recover(&self) -> Result<(), Failed> {
+        let hosts = match fatal::read_existing_dir(&self.base)? {
+            Some(hosts) => hosts,
+            None => return Ok(())
+        };
+        for host in hosts {
+            let host = host?;
+            if !host.is_dir() {
+                continue
+            }
+            for entry in fatal::read_dir(host.path())? {
+                let entry = entry?;
+                let name = match entry.file_name().to_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if name.ends_with(TMP_SUFFIX) {
+                    info!(
+                        "Removing incomplete rsync update {}.",
+                        entry.path().display()
+                    );
+                    fatal::remove_all(entry.path())?;
+                }
+                else if let Some(module) = name.strip_suffix(OLD_SUFFIX) {
+                    let path = host.path().join(module);
+                    if path.exists() {
+                        fatal::remove_all(entry.path())?;
+                    }
+                    else {
+                        info!(
+                            "Restoring interrupted rsync update {}.",
+                            path.display()
+                        );
+                        fatal::rename(entry.path(), &path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the absolute path for the given URI.
     fn uri_path(&self, uri: &uri::Rsync) -> PathBuf {
         let mut res = self.base.clone();
@@ -790,6 +1580,11 @@ impl Module {
         }
     }
 
+    /// Returns the module as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     /// Converts a module reference into its rsync URI.
     pub fn to_uri(&self) -> uri::Rsync {
         uri::Rsync::from_str(&self.0).unwrap()
@@ -901,3 +1696,152 @@ impl ModuleSet {
     }
 }
 
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum number of bytes of standard error output kept for metrics.
+const STDERR_EXCERPT_LEN: usize = 256;
+
+/// The suffix of the file name of listing state files.
+///
+/// The file name is the module name with this suffix appended.
+const LISTING_SUFFIX: &str = ".rsync-listing";
+
+/// The suffix of the file name of sync state files.
+///
+/// The file name is the module name with this suffix appended.
+const STATE_SUFFIX: &str = ".rsync-state";
+
+/// The suffix of the temporary directory an update is synced into.
+const TMP_SUFFIX: &str = ".rsync-tmp";
+
+/// The suffix of the directory the old content is moved to during a swap.
+const OLD_SUFFIX: &str = ".rsync-old";
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bytes_received() {
+        assert_eq!(
+            parse_bytes_received(
+                b"Number of files: 3 (reg: 2, dir: 1)\n\
+                  Total bytes sent: 43\n\
+                  Total bytes received: 1,234,567\n\
+                  \n\
+                  sent 43 bytes  received 1,234,567 bytes\n"
+            ),
+            Some(1234567)
+        );
+        assert_eq!(
+            parse_bytes_received(b"Total bytes received: 815\n"),
+            Some(815)
+        );
+        assert_eq!(parse_bytes_received(b"receiving file list\n"), None);
+        assert_eq!(parse_bytes_received(b""), None);
+    }
+
+    #[test]
+    fn stderr_excerpt() {
+        let mut output = RsyncOutput::default();
+        assert_eq!(output.stderr(), None);
+        output.push_stderr(b"rsync: connection refused\n");
+        assert_eq!(
+            output.stderr().as_deref(), Some("rsync: connection refused")
+        );
+        for _ in 0..100 {
+            output.push_stderr(b"rsync error: some error\n");
+        }
+        assert_eq!(output.stderr.len(), STDERR_EXCERPT_LEN);
+    }
+
+    #[test]
+    fn listing() {
+        let listing = b"drwxr-xr-x          4,096 2024/05/01 10:00:00 .\n\
+            -rw-r--r--          1,234 2024/05/01 10:00:00 a.mft\n";
+        let stats = b"\nNumber of files: 2\nTotal bytes received: 815\n";
+        let digest = listing_digest(listing).unwrap();
+        assert_eq!(
+            listing_digest(&[listing.as_ref(), stats].concat()),
+            Some(digest)
+        );
+        assert_ne!(
+            listing_digest(
+                b"drwxr-xr-x          4,096 2024/05/01 10:00:00 .\n\
+                -rw-r--r--          1,234 2024/05/01 10:10:00 a.mft\n"
+            ),
+            Some(digest)
+        );
+        assert_eq!(listing_digest(stats), None);
+        assert_eq!(listing_digest(b""), None);
+
+        let state = ListingState { digest, skips: 3 };
+        assert_eq!(ListingState::decode(&state.encode()), Some(state));
+        let mut data = state.encode();
+        data[0] = 1;
+        assert_eq!(ListingState::decode(&data), None);
+        assert_eq!(ListingState::decode(&data[..10]), None);
+    }
+
+    #[test]
+    fn sync_state() {
+        let state = SyncState {
+            updated: SystemTime::UNIX_EPOCH + Duration::from_secs(1234567)
+        };
+        assert_eq!(SyncState::decode(&state.encode()), Some(state));
+        let mut data = state.encode();
+        data[0] = 1;
+        assert_eq!(SyncState::decode(&data), None);
+        assert_eq!(SyncState::decode(&data[..5]), None);
+    }
+
+    #[test]
+    fn swap_and_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let working_dir = WorkingDir::new(dir.path().into());
+        let uri = uri::Rsync::from_str(
+            "rsync://rpki.example.net/repo/"
+        ).unwrap();
+        let module = Module::from_uri(&uri);
+        let path = working_dir.module_path(&module);
+        let tmp_path = working_dir.suffixed_path(&module, TMP_SUFFIX);
+        let old_path = working_dir.suffixed_path(&module, OLD_SUFFIX);
+
+        // Swap in a first version without an existing directory.
+        fs::create_dir_all(&tmp_path).unwrap();
+        fs::write(tmp_path.join("a.cer"), b"1").unwrap();
+        working_dir.swap(&module, &tmp_path, &path).unwrap();
+        assert_eq!(fs::read(path.join("a.cer")).unwrap(), b"1");
+        assert!(!tmp_path.exists());
+
+        // Swap in a second version.
+        fs::create_dir_all(&tmp_path).unwrap();
+        fs::write(tmp_path.join("a.cer"), b"2").unwrap();
+        working_dir.swap(&module, &tmp_path, &path).unwrap();
+        assert_eq!(fs::read(path.join("a.cer")).unwrap(), b"2");
+        assert!(!tmp_path.exists());
+        assert!(!old_path.exists());
+
+        // Interrupted sync: the temporary directory is removed.
+        fs::create_dir_all(&tmp_path).unwrap();
+        working_dir.recover().unwrap();
+        assert!(!tmp_path.exists());
+        assert_eq!(fs::read(path.join("a.cer")).unwrap(), b"2");
+
+        // Interrupted swap: the old directory is moved back.
+        fs::rename(&path, &old_path).unwrap();
+        working_dir.recover().unwrap();
+        assert!(!old_path.exists());
+        assert_eq!(fs::read(path.join("a.cer")).unwrap(), b"2");
+
+        // Interrupted clean up: the old directory is removed.
+        fs::create_dir_all(&old_path).unwrap();
+        working_dir.recover().unwrap();
+        assert!(!old_path.exists());
+        assert_eq!(fs::read(path.join("a.cer")).unwrap(), b"2");
+    }
+}
@@ -18,14 +18,16 @@
 /// the accompanying trait [`ProcessPubPoint`] dealing with individual
 /// publication points.
 
-use std::{cmp, fmt, fs, thread};
+use std::{cmp, fmt, fs, io, thread};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use bytes::Bytes;
+use chrono::Utc;
 use crossbeam_queue::{ArrayQueue, SegQueue};
 use log::{debug, error, info, warn};
 use rpki::crypto::keys::KeyIdentifier;
@@ -37,17 +39,22 @@ use rpki::repository::error::{InspectionError, ValidationError};
 use rpki::repository::manifest::{Manifest, ManifestContent, ManifestHash};
 use rpki::repository::roa::{Roa, RouteOriginAttestation};
 use rpki::repository::sigobj::SignedObject;
-use rpki::repository::tal::{Tal, TalInfo, TalUri};
-use rpki::repository::x509::{Time, Validity};
+use rpki::repository::tal::{Tal, TalUri};
+use rpki::repository::x509::{Serial, Time, Validity};
 use rpki::uri;
 use crate::{collector, store, tals};
 use crate::config::{Config, FilterPolicy};
 use crate::collector::Collector;
 use crate::error::{Failed, Fatal, RunFailed};
 use crate::metrics::{
-    Metrics, PublicationMetrics, RepositoryMetrics, TalMetrics
+    CertExtrasMetrics, ManifestReplayMetrics, Metrics, PublicationMetrics,
+    RepositoryMetrics, StoredPointMetrics, TalMetrics
 };
 use crate::store::{Store, StoredManifest, StoredObject, StoredPoint};
+use crate::utils::certext::CertExtensions;
+use crate::utils::date::format_iso_date;
+use crate::utils::dump::DumpTarget;
+use crate::utils::json::JsonBuilder;
 use crate::utils::str::str_from_ascii;
 
 
@@ -59,6 +66,11 @@ use crate::utils::str::str_from_ascii;
 /// a certain state so may or may not be a good one, really.
 const CRL_CACHE_LIMIT: usize = 50;
 
+/// The maximum number of entries recorded for the rejected report.
+///
+/// Anything rejected beyond this number is only counted.
+const REJECTED_REPORT_LIMIT: usize = 100_000;
+
 
 //------------ Engine --------------------------------------------------------
 
@@ -87,6 +99,9 @@ pub struct Engine {
     /// An optional directory to load TALs from.
     extra_tals_dir: Option<PathBuf>,
 
+    /// The TALs to fetch from HTTPS URIs.
+    remote_tals: Vec<tals::RemoteTal>,
+
     /// A mapping of TAL file names to TAL labels.
     tal_labels: HashMap<String, String>,
 
@@ -107,6 +122,12 @@ pub struct Engine {
     /// How do we deal with stale objects?
     stale: FilterPolicy,
 
+    /// For how long after their next update are stale objects accepted?
+    stale_grace_period: Option<Duration>,
+
+    /// How do we deal with manifests that appear to be replayed?
+    manifest_replay: FilterPolicy,
+
     /// Number of validation threads.
     validation_threads: usize,
 
@@ -115,6 +136,27 @@ pub struct Engine {
 
     /// Maximum depth of the CA chain.
     max_ca_depth: usize,
+
+    /// The path to write the rejected report to, if requested.
+    rejected_report: Option<PathBuf>,
+
+    /// Should we collect informational extensions of CA certificates?
+    collect_cert_extras: bool,
+
+    /// The maximum duration of a validation run.
+    ///
+    /// If this is `None`, runs can take as long as they need.
+    max_run_duration: Option<Duration>,
+
+    /// A mapping of TAL labels to their individual refresh intervals.
+    tal_refresh: HashMap<String, Duration>,
+
+    /// The start time of the last run that updated a TAL with a refresh.
+    ///
+    /// The key is the TAL label. Only TALs mentioned in `tal_refresh`
+    /// are tracked. A TAL is only recorded once its trust anchor
+    /// certificate has successfully been loaded via the collector.
+    tal_last_refresh: Mutex<HashMap<String, Instant>>,
 }
 
 impl Engine {
@@ -151,15 +193,23 @@ impl Engine {
         let mut res = Engine {
             bundled_tals: tals::collect_tals(config)?,
             extra_tals_dir: config.extra_tals_dir.clone(),
+            remote_tals: tals::RemoteTal::from_config(config),
             tal_labels: config.tal_labels.clone(),
             tals: Vec::new(),
             collector,
             store,
             strict: config.strict,
             stale: config.stale,
+            stale_grace_period: config.stale_grace_period,
+            manifest_replay: config.manifest_replay,
             validation_threads: config.validation_threads,
             dirty_repository: config.dirty_repository,
             max_ca_depth: config.max_ca_depth,
+            rejected_report: config.rejected_report.clone(),
+            collect_cert_extras: config.collect_cert_extras,
+            max_run_duration: config.max_run_duration,
+            tal_refresh: config.tal_refresh.clone(),
+            tal_last_refresh: Default::default(),
         };
         res.reload_tals()?;
         Ok(res)
@@ -174,6 +224,9 @@ impl Engine {
     ///
     /// It is not considered an error if there are no TAL files in the TAL
     /// directory. However, a warning will be logged in this case.
+    ///
+    /// TALs fetched from HTTPS URIs are loaded from their cached copies.
+    /// TALs that haven’t been fetched yet are skipped.
     pub fn reload_tals(&mut self) -> Result<(), Failed> {
         let mut res = self.bundled_tals.clone();
         if let Some(extra_tals_dir) = self.extra_tals_dir.as_ref() {
@@ -238,6 +291,14 @@ impl Engine {
                 res.push(tal);
             }
         }
+        for remote in &self.remote_tals {
+            if let Some(mut tal) = remote.read(
+                self.file_name_to_tal_label(remote.file_name())
+            )? {
+                tal.prefer_https();
+                res.push(tal);
+            }
+        }
         if res.is_empty() {
             warn!(
                 "No TALs provided. Starting anyway."
@@ -265,14 +326,35 @@ impl Engine {
         path.file_stem().unwrap().to_string_lossy().into_owned()
     }
 
+    /// Converts the file name of a fetched TAL into a TAL label.
+    ///
+    /// This works just like [`path_to_tal_label`][Self::path_to_tal_label]
+    /// except that there is no path.
+    fn file_name_to_tal_label(&self, name: &str) -> String {
+        if let Some(label) = self.tal_labels.get(name) {
+            return label.clone()
+        }
+        name.strip_suffix(".tal").unwrap_or(name).into()
+    }
+
     /// Ignites validation processing.
     ///
     /// This spawns threads and therefore needs to be done after a
     /// possible fork.
+    ///
+    /// Since it needs the collector’s HTTP client, this is also where the
+    /// TALs from HTTPS URIs are fetched. Fails if any of them can neither
+    /// be fetched nor be found in the cache.
     pub fn ignite(&mut self) -> Result<(), Failed> {
         if let Some(collector) = self.collector.as_mut() {
             collector.ignite()?;
         }
+        if !self.remote_tals.is_empty() {
+            for remote in &self.remote_tals {
+                remote.fetch(self.collector.as_ref())?;
+            }
+            self.reload_tals()?;
+        }
         Ok(())
     }
 
@@ -288,6 +370,16 @@ impl Engine {
         Ok(())
     }
 
+    /// Returns an iterator over the labels of the currently loaded TALs.
+    pub fn tal_labels(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tals.iter().map(|tal| tal.info().name())
+    }
+
+    /// Returns the currently loaded TAL with the given label.
+    pub fn find_tal(&self, label: &str) -> Option<&Tal> {
+        self.tals.iter().find(|tal| tal.info().name() == label)
+    }
+
     /// Starts a validation run.
     ///
     /// During the run, `processor` will be responsible for dealing with
@@ -301,19 +393,123 @@ impl Engine {
         for tal in &self.tals {
             info!("  * {}", tal.info().name());
         }
+        let skip_update = self.skip_update();
         Ok(Run::new(
             self,
-            self.collector.as_ref().map(Collector::start),
             self.store.start(),
-            processor
+            processor,
+            skip_update,
+            None,
         ))
     }
 
+    /// Starts a validation run for a single TAL.
+    ///
+    /// This is like [`start`][Self::start] but only the TAL with the given
+    /// name is processed. Its data is always updated, regardless of the
+    /// `tal_refresh` config option. The metrics of the run still contain
+    /// an element for each TAL, so the TAL indexes are the same as for a
+    /// full run.
+    ///
+    /// Returns the index of the TAL and the run or `None` if there is no
+    /// TAL with the given name.
+    pub fn start_tal<P: ProcessRun>(
+        &self, processor: P, name: &str,
+    ) -> Result<Option<(usize, Run<'_, P>)>, Failed> {
+        let index = match self.tals.iter().position(|tal| {
+            tal.info().name() == name
+        }) {
+            Some(index) => index,
+            None => return Ok(None)
+        };
+        info!("Using only TAL {}.", name);
+        Ok(Some((
+            index,
+            Run::new(
+                self,
+                self.store.start(),
+                processor,
+                vec![false; self.tals.len()],
+                Some(index),
+            )
+        )))
+    }
+
+    /// Determines which TALs should not be updated in the next run.
+    ///
+    /// Returns a vec with an element for each TAL in `self.tals` that is
+    /// `true` if the collector should not be used for the TAL because its
+    /// refresh interval given via the `tal_refresh` config option hasn’t
+    /// expired yet.
+    fn skip_update(&self) -> Vec<bool> {
+        if self.collector.is_none() || self.tal_refresh.is_empty() {
+            return vec![false; self.tals.len()]
+        }
+        let now = Instant::now();
+        let last_refresh = self.tal_last_refresh.lock().unwrap();
+        self.tals.iter().map(|tal| {
+            let name = tal.info().name();
+            let refresh = match self.tal_refresh.get(name) {
+                Some(refresh) => *refresh,
+                None => return false,
+            };
+            if let Some(last) = last_refresh.get(name) {
+                if now.saturating_duration_since(*last) < refresh {
+                    info!(
+                        "TAL {}: skipping update as per tal-refresh.",
+                        name
+                    );
+                    return true
+                }
+            }
+            false
+        }).collect()
+    }
+
+    /// Records that a TAL has been updated by a run started at `started`.
+    ///
+    /// This only has an effect for TALs with an individual refresh
+    /// interval.
+    fn mark_tal_refreshed(&self, name: &str, started: Instant) {
+        if self.tal_refresh.contains_key(name) {
+            self.tal_last_refresh.lock().unwrap().insert(
+                name.into(), started
+            );
+        }
+    }
+
+    /// Returns whether a stale object is within the grace period.
+    ///
+    /// This is the case if a grace period has been configured and the
+    /// object’s `next_update` lies no further than the grace period before
+    /// `now`. In strict mode, there is no grace period.
+    fn in_stale_grace(&self, next_update: Time, now: Time) -> bool {
+        if self.strict {
+            return false
+        }
+        let grace = match self.stale_grace_period {
+            Some(grace) => grace,
+            None => return false,
+        };
+        match chrono::Duration::from_std(grace).ok().and_then(|grace| {
+            next_update.checked_add_signed(grace)
+        }) {
+            Some(end) => *now <= end,
+            None => true,
+        }
+    }
+
     /// Dumps the content of the collector and store owned by the engine.
-    pub fn dump(&self, dir: &Path) -> Result<(), Failed> {
-        self.store.dump(dir)?;
+    ///
+    /// The store is only included if `store` is `true`.
+    pub fn dump(
+        &self, target: &mut DumpTarget, store: bool
+    ) -> Result<(), Failed> {
+        if store {
+            self.store.dump(target)?;
+        }
         if let Some(collector) = self.collector.as_ref() {
-            collector.dump(dir)?;
+            collector.dump(target)?;
         }
         Ok(())
     }
@@ -341,29 +537,80 @@ pub struct Run<'a, P> {
     /// The processor for valid data.
     processor: P,
 
+    /// Should the collector be skipped for a TAL?
+    ///
+    /// This contains one element for each TAL of the engine.
+    skip_update: Vec<bool>,
+
+    /// The index of the only TAL to process if only one is processed.
+    only_tal: Option<usize>,
+
     /// Was an error encountered during the run?
     had_err: AtomicBool,
 
     /// Was a fatal error encountered during the run?
     is_fatal: AtomicBool,
 
+    /// The time when the run was started.
+    started: Instant,
+
+    /// The time when the run has to be aborted, if any.
+    deadline: Option<Instant>,
+
+    /// Has the run exceeded its deadline?
+    timed_out: AtomicBool,
+
     /// The metrics collected during the run.
     metrics: Metrics,
+
+    /// The log of rejected objects if a rejected report was requested.
+    rejected: Option<RejectedLog>,
+
+    /// The informational certificate extensions if collecting is enabled.
+    cert_extras: Option<Mutex<Vec<CertExtrasMetrics>>>,
+
+    /// The manifests that appeared to be replayed.
+    manifest_replays: Mutex<Vec<ManifestReplayMetrics>>,
 }
 
 impl<'a, P> Run<'a, P> {
     /// Creates a new runner from all the parts.
+    ///
+    /// The collector run is started here, too, so that in-flight updates
+    /// are abandoned when the run’s deadline passes.
     fn new(
         validation: &'a Engine,
-        collector: Option<collector::Run<'a>>,
         store: store::Run<'a>,
         processor: P,
+        skip_update: Vec<bool>,
+        only_tal: Option<usize>,
     ) -> Self {
+        let started = Instant::now();
+        let deadline = validation.max_run_duration.map(|duration| {
+            started + duration
+        });
         Run {
-            validation, collector, store, processor,
+            validation,
+            collector: validation.collector.as_ref().map(|collector| {
+                collector.start(deadline)
+            }),
+            store, processor, skip_update, only_tal,
             had_err: AtomicBool::new(false),
             is_fatal: AtomicBool::new(false),
-            metrics: Default::default()
+            started,
+            deadline,
+            timed_out: AtomicBool::new(false),
+            metrics: Default::default(),
+            rejected: validation.rejected_report.as_ref().map(|_| {
+                Default::default()
+            }),
+            cert_extras: if validation.collect_cert_extras {
+                Some(Default::default())
+            }
+            else {
+                None
+            },
+            manifest_replays: Default::default(),
         }
     }
 
@@ -374,20 +621,75 @@ impl<'a, P> Run<'a, P> {
             return Ok(())
         }
 
+        // An aborted run hasn’t seen all publication points, so we can’t
+        // know what to retain.
+        if self.timed_out.load(Ordering::Relaxed) {
+            warn!("Skipping cleanup after aborted validation run.");
+            return Ok(())
+        }
+
         let mut retain = collector::Cleanup::new();
         self.store.cleanup(&mut retain)?;
         if let Some(collector) = self.collector.as_mut() {
-            collector.cleanup(&mut retain)?;
+            self.metrics.cache_usage = Some(collector.cleanup(&mut retain)?);
         }
         Ok(())
     }
 
+    /// Returns whether the run has exceeded its maximum duration.
+    ///
+    /// Logs an error the first time the deadline is found to have passed.
+    fn is_timed_out(&self) -> bool {
+        if self.timed_out.load(Ordering::Relaxed) {
+            return true
+        }
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return false,
+        };
+        if Instant::now() < deadline {
+            return false
+        }
+        if !self.timed_out.swap(true, Ordering::Relaxed) {
+            error!(
+                "Validation run exceeded the maximum run duration of {} \
+                 seconds. Aborting.",
+                self.validation.max_run_duration.unwrap_or_default()
+                    .as_secs()
+            );
+        }
+        true
+    }
+
     /// Finishes the validation run and returns the metrics.
     ///
+    /// If a rejected report was requested, it is written now.
+    ///
     /// If you are not interested in the metrics, you can simple drop the
     /// value, instead.
     pub fn done(self) -> Metrics {
+        if let (Some(log), Some(path)) = (
+            self.rejected.as_ref(), self.validation.rejected_report.as_ref()
+        ) {
+            log.write(path, &self.validation.tals, &self.metrics);
+        }
         let mut metrics = self.metrics;
+        metrics.cert_extras = self.cert_extras.map(|extras| {
+            let mut extras = extras.into_inner().expect("poisoned lock");
+            extras.sort_by(|left, right| {
+                left.uri.as_str().cmp(right.uri.as_str())
+            });
+            extras
+        });
+        metrics.manifest_replays = {
+            let mut replays = self.manifest_replays.into_inner().expect(
+                "poisoned lock"
+            );
+            replays.sort_by(|left, right| {
+                left.manifest_uri.as_str().cmp(right.manifest_uri.as_str())
+            });
+            replays
+        };
         if let Some(collector) = self.collector {
             collector.done(&mut metrics)
         }
@@ -408,8 +710,12 @@ impl<P: ProcessRun> Run<'_, P> {
         let metrics = RunMetrics::default();
         let tasks = SegQueue::new();
         for (index, tal) in self.validation.tals.iter().enumerate() {
-            tasks.push(Task::Tal(TalTask { tal, index }));
-            self.metrics.tals.push(TalMetrics::new(tal.info().clone()));
+            if self.only_tal.map(|only| only == index).unwrap_or(true) {
+                tasks.push(Task::Tal(TalTask { tal, index }));
+            }
+            let mut tal_metrics = TalMetrics::new(tal.info().clone());
+            tal_metrics.update_skipped = self.skip_update[index];
+            self.metrics.tals.push(tal_metrics);
         }
 
         // And off we trot.
@@ -423,6 +729,9 @@ impl<P: ProcessRun> Run<'_, P> {
                 scope.spawn(|| {
                     let mut metrics = metrics.fork();
                     while let Some(task) = tasks.pop() {
+                        if self.is_timed_out() {
+                            break;
+                        }
                         if self.process_task(
                             task, &tasks, &mut metrics,
                         ).is_err() {
@@ -447,6 +756,23 @@ impl<P: ProcessRun> Run<'_, P> {
         while let Some(metrics) = thread_metrics.pop() {
             metrics.collapse(&mut self.metrics);
         }
+        self.metrics.timed_out = self.timed_out.load(Ordering::Relaxed);
+
+        // TALs with a valid trust anchor that were processed and fetched
+        // have been updated successfully -- unless the run was aborted
+        // before their trees were complete. We don’t know which ones were,
+        // so none of them count as updated then.
+        if self.metrics.timed_out {
+            return Ok(())
+        }
+        let now = Utc::now();
+        for (index, tal) in self.metrics.tals.iter_mut().enumerate() {
+            if self.only_tal.map(|only| only == index).unwrap_or(true)
+                && !tal.update_skipped && tal.ta_uri.is_some()
+            {
+                tal.updated = Some(now);
+            }
+        }
 
         Ok(())
     }
@@ -474,79 +800,181 @@ impl<P: ProcessRun> Run<'_, P> {
         tasks: &SegQueue<Task<P::PubPoint>>,
         metrics: &mut RunMetrics,
     ) -> Result<(), Failed> {
-        for uri in task.tal.uris() {
-            let cert = match self.load_ta(uri, task.tal.info())? {
-                Some(cert) => cert,
-                _ => continue,
-            };
-            if cert.subject_public_key_info() != task.tal.key_info() {
+        let (uri, cert, stored) = match self.find_ta(&task, metrics)? {
+            Some(some) => some,
+            None => {
                 warn!(
-                    "Trust anchor {}: key doesn’t match TAL.",
-                    uri
+                    "No valid trust anchor for TAL {}", task.tal.info().name()
                 );
-                continue;
+                return Ok(())
             }
-            let cert = match cert.validate_ta(
-                task.tal.info().clone(), self.validation.strict
-            ) {
-                Ok(cert) => CaCert::root(cert, uri.clone(), task.index),
-                Err(err) => {
-                    warn!("Trust anchor {}: {}.", uri, err);
-                    continue;
-                }
-            };
-            let cert = match cert {
-                Ok(cert) => cert,
-                Err(_) => continue,
-            };
-            debug!("Found valid trust anchor {}. Processing.", uri);
+        };
+        metrics.set_ta_uri(task.index, uri, stored);
+        debug!("Found valid trust anchor {}. Processing.", uri);
 
-            match self.processor.process_ta(
-                task.tal, uri, &cert, cert.tal
-            )? {
-                Some(processor) => {
-                    return self.process_ca_task(
-                        CaTask {
-                            cert, processor,
-                            repository_index: None,
-                            defer: false,
-                        },
-                        tasks, metrics,
-                    )
-                }
-                None => {
-                    debug!("Skipping trust anchor {}.", uri);
-                    return Ok(())
-                }
+        match self.processor.process_ta(
+            task.tal, uri, &cert, cert.tal
+        )? {
+            Some(processor) => {
+                self.process_ca_task(
+                    CaTask {
+                        cert, processor,
+                        repository_index: None,
+                        defer: false,
+                    },
+                    tasks, metrics,
+                )
+            }
+            None => {
+                debug!("Skipping trust anchor {}.", uri);
+                Ok(())
             }
         }
-        warn!("No valid trust anchor for TAL {}", task.tal.info().name());
-        Ok(())
     }
 
-    /// Loads a trust anchor certificate with the given URI.
+    /// Finds a valid trust anchor certificate for a TAL.
+    ///
+    /// Tries to download the certificate from each of the TAL’s URIs in
+    /// turn. Only if none of them provides a valid certificate, falls back
+    /// to the versions in the store.
+    ///
+    /// If updating the TAL is skipped during this run, a stored version is
+    /// used first if it is present and hasn’t expired yet.
     ///
-    /// Attempts to download the certificate from upstream but falls back to
-    /// the version in the store if available.
-    fn load_ta(
+    /// If a downloaded certificate has a different key than the TAL, the
+    /// trust anchor has likely rolled its key and the TAL is outdated. This
+    /// is reported explicitly and the stored certificate continues to be
+    /// used for as long as it is valid.
+    ///
+    /// Returns the URI the certificate was found at, the certificate, and
+    /// whether it was taken from the store.
+    #[allow(clippy::type_complexity)]
+    fn find_ta<'t>(
         &self,
-        uri: &TalUri,
-        _info: &TalInfo,
-    ) -> Result<Option<Cert>, Failed> {
-        // Get the new version, store and return it if it decodes.
+        task: &TalTask<'t>,
+        metrics: &mut RunMetrics,
+    ) -> Result<Option<(&'t TalUri, Arc<CaCert>, bool)>, Failed> {
+        if self.skip_update[task.index] {
+            if let Some((uri, cert)) = self.find_stored_ta(task, true)? {
+                return Ok(Some((uri, cert, true)))
+            }
+        }
+
+        // Get the new version, store and return it if it is valid.
         if let Some(collector) = self.collector.as_ref() {
-            if let Some(bytes) = collector.load_ta(uri) {
-                if let Ok(cert) = Cert::decode(bytes.clone()) {
+            for uri in task.tal.uris() {
+                let bytes = match collector.load_ta(uri) {
+                    Some(bytes) => bytes,
+                    None => continue,
+                };
+                let cert = match Cert::decode(bytes.clone()) {
+                    Ok(cert) => cert,
+                    Err(_) => {
+                        warn!("Trust anchor {}: failed to decode.", uri);
+                        self.reject(
+                            uri, RejectReason::DecodeFailed, None,
+                            task.index, None
+                        );
+                        continue
+                    }
+                };
+                if cert.subject_public_key_info() != task.tal.key_info() {
+                    error!(
+                        "Trust anchor {}: certificate key {} doesn’t match \
+                         key {} of TAL {}. The TAL appears to be outdated.",
+                        uri,
+                        cert.subject_public_key_info().key_identifier(),
+                        task.tal.key_info().key_identifier(),
+                        task.tal.info().name(),
+                    );
+                    self.reject(
+                        uri, RejectReason::InvalidTrustAnchor,
+                        Some(&"key doesn’t match TAL"), task.index, None
+                    );
+                    metrics.set_ta_key_mismatch(task.index);
+                    continue
+                }
+                if let Some(cert) = self.validate_ta(task, uri, cert) {
                     self.store.update_ta(uri, &bytes)?;
-                    return Ok(Some(cert))
+                    self.validation.mark_tal_refreshed(
+                        task.tal.info().name(), self.started
+                    );
+                    return Ok(Some((uri, cert, false)))
                 }
             }
         }
 
         // Get what we have in store.
-        self.store.load_ta(uri).map(|bytes| {
-            bytes.and_then(|bytes| Cert::decode(bytes).ok())
-        })
+        Ok(self.find_stored_ta(task, false)?.map(|(uri, cert)| {
+            (uri, cert, true)
+        }))
+    }
+
+    /// Finds a valid trust anchor certificate for a TAL in the store.
+    ///
+    /// Tries the stored certificates for all URIs of the TAL in turn. If
+    /// `current` is `true`, expired certificates are skipped quietly.
+    fn find_stored_ta<'t>(
+        &self,
+        task: &TalTask<'t>,
+        current: bool,
+    ) -> Result<Option<(&'t TalUri, Arc<CaCert>)>, Failed> {
+        for uri in task.tal.uris() {
+            let cert = match self.store.load_ta(uri)? {
+                Some(bytes) => match Cert::decode(bytes) {
+                    Ok(cert) => cert,
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            if current && cert.validity().not_after() <= Time::now() {
+                continue
+            }
+            if let Some(cert) = self.validate_ta(task, uri, cert) {
+                return Ok(Some((uri, cert)))
+            }
+        }
+        Ok(None)
+    }
+
+    /// Validates a trust anchor certificate found at the given URI.
+    ///
+    /// Checks that the certificate’s key matches the TAL and that it is a
+    /// valid trust anchor certificate. Logs and records the reason if it
+    /// isn’t and returns `None`.
+    fn validate_ta(
+        &self,
+        task: &TalTask,
+        uri: &TalUri,
+        cert: Cert,
+    ) -> Option<Arc<CaCert>> {
+        if cert.subject_public_key_info() != task.tal.key_info() {
+            warn!(
+                "Trust anchor {}: key doesn’t match TAL.",
+                uri
+            );
+            self.reject(
+                uri, RejectReason::InvalidTrustAnchor,
+                Some(&"key doesn’t match TAL"), task.index, None
+            );
+            return None
+        }
+        let expired = cert.validity().not_after() < Time::now();
+        match cert.validate_ta(
+            task.tal.info().clone(), self.validation.strict
+        ) {
+            Ok(cert) => CaCert::root(cert, uri.clone(), task.index).ok(),
+            Err(err) => {
+                warn!("Trust anchor {}: {}.", uri, err);
+                self.reject(
+                    uri,
+                    if expired { RejectReason::ExpiredCertificate }
+                    else { RejectReason::InvalidTrustAnchor },
+                    Some(&err), task.index, None
+                );
+                None
+            }
+        }
     }
 
     /// Processes a CA.
@@ -556,6 +984,9 @@ impl<P: ProcessRun> Run<'_, P> {
         tasks: &SegQueue<Task<P::PubPoint>>,
         metrics: &mut RunMetrics,
     ) -> Result<(), Failed> {
+        if self.is_timed_out() {
+            return Err(Failed)
+        }
         let more_tasks = PubPoint::new(
             self, &task.cert, task.processor, task.repository_index,
         ).and_then(|point| {
@@ -565,7 +996,7 @@ impl<P: ProcessRun> Run<'_, P> {
             Failed
         })?;
         for task in more_tasks {
-            if self.had_err.load(Ordering::Relaxed) {
+            if self.had_err.load(Ordering::Relaxed) || self.is_timed_out() {
                 return Err(Failed)
             }
             if task.defer {
@@ -578,6 +1009,40 @@ impl<P: ProcessRun> Run<'_, P> {
         Ok(())
     }
 
+    /// Records a rejected object or CA for the rejected report.
+    ///
+    /// Does nothing if no report was requested.
+    fn reject(
+        &self,
+        uri: &dyn fmt::Display,
+        reason: RejectReason,
+        detail: Option<&dyn fmt::Display>,
+        tal: usize,
+        parent: Option<&CaCert>,
+    ) {
+        if let Some(log) = self.rejected.as_ref() {
+            log.push(|| RejectedEntry {
+                uri: uri.to_string(),
+                reason,
+                detail: detail.map(ToString::to_string),
+                tal,
+                parent: parent.map(|cert| cert.uri().to_string()),
+            })
+        }
+    }
+
+    /// Records the informational extensions of a CA certificate.
+    fn push_cert_extras(&self, extras: CertExtrasMetrics) {
+        if let Some(list) = self.cert_extras.as_ref() {
+            list.lock().expect("poisoned lock").push(extras)
+        }
+    }
+
+    /// Records a manifest that appeared to be replayed.
+    fn push_manifest_replay(&self, replay: ManifestReplayMetrics) {
+        self.manifest_replays.lock().expect("poisoned lock").push(replay)
+    }
+
     /// Marks the run as failed.
     fn run_failed(&self, err: RunFailed) {
         self.had_err.store(true, Ordering::Relaxed);
@@ -611,6 +1076,11 @@ struct PubPoint<'a, P: ProcessRun> {
     /// collected during object processing via `ValidPointManifest` so we can
     /// drop it if the point gets cancelled.
     metrics: PublicationMetrics,
+
+    /// Did we have to fall back to the stored data?
+    ///
+    /// This is only set if the collector failed to provide the point.
+    fallback: bool,
 }
 
 impl<'a, P: ProcessRun> PubPoint<'a, P> {
@@ -624,6 +1094,7 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         Ok(PubPoint {
             run, cert, processor, repository_index,
             metrics: Default::default(),
+            fallback: false,
         })
     }
 
@@ -631,25 +1102,85 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
     ///
     /// Upon success, returns a list of all the child CAs of this publication
     /// point as CA processing tasks.
+    ///
+    /// The time spent waiting for the collector and the time spent
+    /// validating are added to the metrics of the point’s repository.
     pub fn process(
-        self,
+        mut self,
         metrics: &mut RunMetrics,
+    ) -> Result<Vec<CaTask<P::PubPoint>>, RunFailed> {
+        let start = Instant::now();
+        let repository_index = self.repository_index.unwrap_or_else(|| {
+            metrics.repository_index(self.cert)
+        });
+        self.repository_index = Some(repository_index);
+        let mut fetch = Duration::ZERO;
+        let res = self.process_point(metrics, &mut fetch);
+        metrics.apply_durations(
+            repository_index, fetch, start.elapsed().saturating_sub(fetch)
+        );
+        res
+    }
+
+    /// Performs the actual validation of the publication point.
+    ///
+    /// The time spent waiting for the collector is stored in `fetch`.
+    fn process_point(
+        mut self,
+        metrics: &mut RunMetrics,
+        fetch: &mut Duration,
     ) -> Result<Vec<CaTask<P::PubPoint>>, RunFailed> {
         let mut store = self.run.store.pub_point(self.cert)?;
         if let Some(collector) = self.run.collector.as_ref() {
-            if let Some(collector) = collector.repository(self.cert)? {
+            if self.skip_update(&store) {
+                return Ok(self.process_stored(&mut store, metrics)?)
+            }
+            if self.run.is_timed_out() {
+                self.abandon_point();
+                return Ok(Vec::new())
+            }
+            let fetch_start = Instant::now();
+            let collector = collector.repository(self.cert)?;
+            *fetch = fetch_start.elapsed();
+            if let Some(collector) = collector {
                 match self.process_collected(
                     collector, &mut store, metrics
                 )? {
                     Ok(res) => return Ok(res),
                     Err(mut this) => {
                         this.metrics = Default::default();
-                        return Ok(this.process_stored(store, metrics)?)
+                        this.fallback = true;
+                        return Ok(this.process_stored(&mut store, metrics)?)
                     }
                 }
             }
+            self.fallback = true;
+        }
+        Ok(self.process_stored(&mut store, metrics)?)
+    }
+
+    /// Returns whether the collector should not be used for this point.
+    ///
+    /// This is the case if the point’s TAL is configured to be skipped
+    /// during this run and there is a stored manifest that has neither
+    /// expired nor gone stale.
+    fn skip_update(&self, store: &StoredPoint) -> bool {
+        if !self.run.skip_update[self.cert.tal] {
+            return false
+        }
+        let manifest = match store.manifest() {
+            Some(manifest) => manifest,
+            None => return false,
+        };
+        if manifest.not_after() <= Time::now() {
+            return false
+        }
+        match Manifest::decode(
+            manifest.manifest().clone(), self.run.validation.strict
+        ) {
+            Ok(decoded) => !decoded.content().is_stale(),
+            Err(_) => false,
         }
-        Ok(self.process_stored(store, metrics)?)
     }
 
     /// Tries to update the stored data and validate at the same time.
@@ -660,9 +1191,12 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
     /// hashes. While checking, also processes the data so we only need to do
     /// that once.
     ///
+    /// If the collected manifest is the same as the stored one, the stored
+    /// point is processed right away.
+    ///
     /// Returns a list of the child CAs if it successfully finished or `self`
     /// if there is no update or it is not valid and the store needs to be
-    /// consulted.
+    /// consulted as a fallback.
     #[allow(clippy::type_complexity)] // Yeah, I know ...
     fn process_collected(
         mut self,
@@ -680,9 +1214,9 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         };
 
         // If the stored and collected manifests are the same, nothing has
-        // changed and we can abort the update. However, we need to check that
-        // the stored manifest refers to the same CA repository URI, just to
-        // be sure.
+        // changed and we can process the stored point instead. However, we
+        // need to check that the stored manifest refers to the same CA
+        // repository URI, just to be sure.
         let same = if let Some(mft) = store.manifest() {
             mft.manifest() == &collected
                 && mft.ca_repository() == self.cert.ca_repository()
@@ -691,7 +1225,7 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             false
         };
         if same {
-            return Ok(Err(self))
+            return Ok(Ok(self.process_stored(store, metrics)?))
         }
 
         // Validate the collected manifest. If it is no good, well, you know
@@ -705,24 +1239,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             }
         };
 
-        // Check that the collected manifest’s manifest number and thisUpdate
-        // fields are larger than the stored manifest’s. Otherwise return so
-        // we use the stored manifest.
+        // Check that the collected manifest isn’t older than the stored
+        // manifest. If it is, it may have been replayed and if the policy
+        // says so, we return so we use the stored manifest.
         if let Some(mft) = store.manifest() {
-            if collected.content.manifest_number() <= mft.manifest_number() {
-                warn!(
-                    "{}: manifest number is not greater than in stored \
-                     version. Using stored publication point.",
-                     self.cert.rpki_manifest(),
-                );
-                return Ok(Err(self))
-            }
-            if collected.content.this_update() <= mft.this_update() {
-                warn!(
-                    "{}: manifest thisUpdate is not later than in stored \
-                     version. Using stored publication point.",
-                     self.cert.rpki_manifest(),
-                );
+            if !self.check_manifest_replay(&collected, mft) {
                 return Ok(Err(self))
             }
         }
@@ -758,6 +1279,9 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                 collected.crl_bytes.clone(),
             ),
             || {
+                if self.run.is_timed_out() {
+                    return Err(store::UpdateError::Abort)
+                }
                 let item = match items.next() {
                     Some(item) => item,
                     None => return Ok(None)
@@ -770,6 +1294,14 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                             self.cert.rpki_manifest(),
                             String::from_utf8_lossy(item.file())
                         );
+                        self.reject(
+                            self.cert.rpki_manifest(),
+                            RejectReason::InvalidManifest,
+                            Some(&format_args!(
+                                "illegal file name '{}'",
+                                String::from_utf8_lossy(item.file())
+                            ))
+                        );
                         return Err(store::UpdateError::Abort)
                     }
                 };
@@ -784,13 +1316,21 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                 let content = match collector.load_object(&uri)? {
                     Some(content) => content,
                     None => {
-                        warn!("{}: failed to load.", uri);
+                        warn!(
+                            uri = uri.as_str(), tal = self.tal_name();
+                            "{}: failed to load.", uri
+                        );
+                        self.reject(&uri, RejectReason::MissingObject, None);
                         return Err(store::UpdateError::Abort)
                     }
                 };
 
                 if hash.verify(&content).is_err() {
-                    warn!("{}: file has wrong manifest hash.", uri);
+                    warn!(
+                        uri = uri.as_str(), tal = self.tal_name();
+                        "{}: file has wrong manifest hash.", uri
+                    );
+                    self.reject(&uri, RejectReason::HashMismatch, None);
                     return Err(store::UpdateError::Abort)
                 }
 
@@ -810,7 +1350,7 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                 // Update was successful. We have to accept whatever result
                 // we got.
                 if point_ok {
-                    self.accept_point(collected, metrics);
+                    self.accept_point(collected, store, metrics);
                     Ok(Ok(ca_tasks))
                 }
                 else {
@@ -850,6 +1390,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: failed to decode manifest.",
                     self.cert.rpki_manifest()
                 );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::DecodeFailed,
+                    None
+                );
                 return Ok(None)
             }
         };
@@ -859,22 +1403,55 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(some) => some,
             Err(err) => {
                 self.metrics.invalid_manifests += 1;
-                warn!("{}: {}.", self.cert.rpki_manifest(), err);
+                warn!(
+                    uri = self.cert.rpki_manifest().as_str(),
+                    tal = self.tal_name();
+                    "{}: {}.", self.cert.rpki_manifest(), err
+                );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::InvalidManifest,
+                    Some(&err)
+                );
                 return Ok(None)
             }
         };
 
         if content.this_update() > Time::now() {
             self.metrics.premature_manifests += 1;
-            warn!("{}: premature manifest", self.cert.rpki_manifest());
+            warn!(
+                uri = self.cert.rpki_manifest().as_str(),
+                tal = self.tal_name();
+                "{}: premature manifest", self.cert.rpki_manifest()
+            );
+            self.reject(
+                self.cert.rpki_manifest(), RejectReason::PrematureManifest,
+                None
+            );
             return Ok(None)
         }
 
         if content.is_stale() {
             self.metrics.stale_manifests += 1;
-            match self.run.validation.stale {
+            let policy = if self.run.validation.in_stale_grace(
+                content.next_update(), Time::now()
+            ) {
+                self.metrics.stale_grace_manifests += 1;
+                FilterPolicy::Warn
+            }
+            else {
+                self.run.validation.stale
+            };
+            match policy {
                 FilterPolicy::Reject => {
-                    warn!("{}: stale manifest", self.cert.rpki_manifest());
+                    warn!(
+                        uri = self.cert.rpki_manifest().as_str(),
+                        tal = self.tal_name();
+                        "{}: stale manifest", self.cert.rpki_manifest()
+                    );
+                    self.reject(
+                        self.cert.rpki_manifest(),
+                        RejectReason::StaleManifest, None
+                    );
                     return Ok(None)
                 }
                 FilterPolicy::Warn => {
@@ -920,7 +1497,15 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Some(some) if some.ends_with(".crl") => some.clone(),
             _ => {
                 self.metrics.invalid_manifests += 1;
-                warn!("{}: invalid CRL URI.", self.cert.rpki_manifest());
+                warn!(
+                    uri = self.cert.rpki_manifest().as_str(),
+                    tal = self.tal_name();
+                    "{}: invalid CRL URI.", self.cert.rpki_manifest()
+                );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::InvalidManifest,
+                    Some(&"invalid CRL URI")
+                );
                 return Ok(None)
             }
         };
@@ -932,6 +1517,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: CRL URI outside repository directory.",
                     self.cert.rpki_manifest()
                 );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::InvalidManifest,
+                    Some(&"CRL URI outside repository directory")
+                );
                 return Ok(None)
             }
         };
@@ -946,14 +1535,22 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     Some(bytes) => bytes,
                     None => {
                         self.metrics.invalid_crls += 1;
-                        warn!("{}: failed to load.", crl_uri);
+                        warn!(
+                            uri = crl_uri.as_str(), tal = self.tal_name();
+                            "{}: failed to load.", crl_uri
+                        );
+                        self.reject(&crl_uri, RejectReason::MissingCrl, None);
                         return Ok(None)
                     }
                 };
                 let hash = ManifestHash::new(hash, manifest.file_hash_alg());
                 if hash.verify(&bytes).is_err() {
                     self.metrics.invalid_crls += 1;
-                    warn!("{}: file has wrong hash.", crl_uri);
+                    warn!(
+                        uri = crl_uri.as_str(), tal = self.tal_name();
+                        "{}: file has wrong hash.", crl_uri
+                    );
+                    self.reject(&crl_uri, RejectReason::HashMismatch, None);
                     return Ok(None)
                 }
                 crl_bytes = Some(bytes);
@@ -967,6 +1564,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: CRL not listed on manifest.",
                     self.cert.rpki_manifest()
                 );
+                self.reject(
+                    &crl_uri, RejectReason::MissingCrl,
+                    Some(&"CRL not listed on manifest")
+                );
                 return Ok(None)
             }
         };
@@ -976,7 +1577,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(crl) => crl,
             Err(_) => {
                 self.metrics.invalid_crls += 1;
-                warn!("{}: failed to decode CRL.", crl_uri);
+                warn!(
+                    uri = crl_uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode CRL.", crl_uri
+                );
+                self.reject(&crl_uri, RejectReason::DecodeFailed, None);
                 return Ok(None)
             }
         };
@@ -984,14 +1589,31 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             self.cert.cert().subject_public_key_info()
         ) {
             self.metrics.invalid_crls += 1;
-            warn!("{}: {}.", crl_uri, err);
+            warn!(
+                uri = crl_uri.as_str(), tal = self.tal_name();
+                "{}: {}.", crl_uri, err
+            );
+            self.reject(&crl_uri, RejectReason::InvalidCrl, Some(&err));
             return Ok(None)
         }
         if crl.is_stale() {
             self.metrics.stale_crls += 1;
-            match self.run.validation.stale {
+            let policy = if self.run.validation.in_stale_grace(
+                crl.next_update(), Time::now()
+            ) {
+                self.metrics.stale_grace_crls += 1;
+                FilterPolicy::Warn
+            }
+            else {
+                self.run.validation.stale
+            };
+            match policy {
                 FilterPolicy::Reject => {
-                    warn!("{}: stale CRL.", crl_uri);
+                    warn!(
+                        uri = crl_uri.as_str(), tal = self.tal_name();
+                        "{}: stale CRL.", crl_uri
+                    );
+                    self.reject(&crl_uri, RejectReason::StaleCrl, None);
                     return Ok(None)
                 }
                 FilterPolicy::Warn => {
@@ -1013,6 +1635,9 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                 "{}: certificate has been revoked.",
                 self.cert.rpki_manifest()
             );
+            self.reject(
+                self.cert.rpki_manifest(), RejectReason::Revoked, None
+            );
             return Ok(None)
         }
 
@@ -1021,6 +1646,73 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         Ok(Some((crl_uri, crl, crl_bytes)))
     }
 
+    /// Checks whether a collected manifest appears to be replayed.
+    ///
+    /// See [`ManifestRegression::check`] for when the collected manifest
+    /// is considered a replay. Only the reject policy keeps using the
+    /// stored manifest in this case.
+    ///
+    /// Returns whether the collected manifest should be used.
+    fn check_manifest_replay(
+        &self, collected: &ValidPointManifest, stored: &StoredManifest,
+    ) -> bool {
+        let number = collected.content.manifest_number();
+        let this_update = collected.content.this_update();
+        let regression = match ManifestRegression::check(
+            number, this_update,
+            stored.manifest_number(), stored.this_update(),
+        ) {
+            Some(regression) => regression,
+            None => {
+                if number <= stored.manifest_number() {
+                    info!(
+                        "{}: manifest number {} not greater than stored {} \
+                         but later thisUpdate. Assuming restarted numbering.",
+                        self.cert.rpki_manifest(),
+                        number, stored.manifest_number(),
+                    );
+                }
+                return true
+            }
+        };
+
+        let policy = self.run.validation.manifest_replay;
+        self.run.push_manifest_replay(ManifestReplayMetrics {
+            manifest_uri: self.cert.rpki_manifest().clone(),
+            tal: self.run.validation.tals[self.cert.tal].info().clone(),
+            stored_number: stored.manifest_number(),
+            stored_this_update: stored.this_update(),
+            collected_number: number,
+            collected_this_update: this_update,
+            rejected: matches!(policy, FilterPolicy::Reject),
+        });
+        match policy {
+            FilterPolicy::Reject => {
+                warn!(
+                    "{}: {} (manifest number {}, stored {}). \
+                     Using stored publication point.",
+                    self.cert.rpki_manifest(), regression,
+                    number, stored.manifest_number(),
+                );
+                self.reject(
+                    self.cert.rpki_manifest(),
+                    RejectReason::OutdatedManifest,
+                    Some(&regression)
+                );
+                false
+            }
+            FilterPolicy::Warn => {
+                warn!(
+                    "{}: {} (manifest number {}, stored {}).",
+                    self.cert.rpki_manifest(), regression,
+                    number, stored.manifest_number(),
+                );
+                true
+            }
+            FilterPolicy::Accept => true,
+        }
+    }
+
     /// Process the stored version of the publication point.
     ///
     /// Validates the objects of the currently stored version of the
@@ -1029,7 +1721,7 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
     /// Returns a list of all child CAs of this publication point.
     fn process_stored(
         mut self,
-        mut store: StoredPoint,
+        store: &mut StoredPoint,
         metrics: &mut RunMetrics,
     ) -> Result<Vec<CaTask<P::PubPoint>>, Failed> {
         let manifest = match store.take_manifest() {
@@ -1041,6 +1733,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: no valid manifest {} found.",
                     self.cert.uri(), self.cert.rpki_manifest()
                 );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::MissingManifest,
+                    None
+                );
                 self.metrics.missing_manifests += 1;
                 self.reject_point(metrics);
                 return Ok(Vec::new())
@@ -1058,7 +1754,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         manifest.point_validity(&mut self.processor);
 
         let mut ca_tasks = Vec::new();
-        for object in &mut store {
+        for object in &mut *store {
+            if self.run.is_timed_out() {
+                self.abandon_point();
+                return Ok(Vec::new())
+            }
             let object = match object {
                 Ok(object) => object,
                 Err(err) => {
@@ -1089,7 +1789,7 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             }
         }
 
-        self.accept_point(manifest, metrics);
+        self.accept_point(manifest, store, metrics);
         Ok(ca_tasks)
     }
 
@@ -1114,6 +1814,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: failed to decode manifest.",
                     self.cert.rpki_manifest(),
                 );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::DecodeFailed,
+                    None
+                );
                 return Err(Failed);
             }
         };
@@ -1122,16 +1826,41 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         ) {
             Ok(some) => some,
             Err(err) => {
-                warn!("{}: {}.", self.cert.rpki_manifest(), err);
+                warn!(
+                    uri = self.cert.rpki_manifest().as_str(),
+                    tal = self.tal_name();
+                    "{}: {}.", self.cert.rpki_manifest(), err
+                );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::InvalidManifest,
+                    Some(&err)
+                );
                 self.metrics.invalid_manifests += 1;
                 return Err(Failed);
             }
         };
         if content.is_stale() {
             self.metrics.stale_manifests += 1;
-            match self.run.validation.stale {
+            let policy = if self.run.validation.in_stale_grace(
+                content.next_update(), Time::now()
+            ) {
+                self.metrics.stale_grace_manifests += 1;
+                FilterPolicy::Warn
+            }
+            else {
+                self.run.validation.stale
+            };
+            match policy {
                 FilterPolicy::Reject => {
-                    warn!("{}: stale manifest", self.cert.rpki_manifest());
+                    warn!(
+                        uri = self.cert.rpki_manifest().as_str(),
+                        tal = self.tal_name();
+                        "{}: stale manifest", self.cert.rpki_manifest()
+                    );
+                    self.reject(
+                        self.cert.rpki_manifest(),
+                        RejectReason::StaleManifest, None
+                    );
                     self.metrics.invalid_manifests += 1;
                     return Err(Failed);
                 }
@@ -1151,6 +1880,10 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                     "{}: manifest without CRL URI.",
                     self.cert.rpki_manifest()
                 );
+                self.reject(
+                    self.cert.rpki_manifest(), RejectReason::InvalidManifest,
+                    Some(&"manifest without CRL URI")
+                );
                 self.metrics.invalid_manifests += 1;
                 return Err(Failed)
             }
@@ -1162,23 +1895,44 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Err(_) => {
                 self.metrics.invalid_manifests += 1;
                 self.metrics.invalid_crls += 1;
-                warn!("{}: failed to decode CRL.", crl_uri);
+                warn!(
+                    uri = crl_uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode CRL.", crl_uri
+                );
+                self.reject(&crl_uri, RejectReason::DecodeFailed, None);
                 return Err(Failed)
             }
         };
         if let Err(err) = crl.verify_signature(
             self.cert.cert().subject_public_key_info()
         ) {
-            warn!("{}: {}.", crl_uri, err);
+            warn!(
+                uri = crl_uri.as_str(), tal = self.tal_name();
+                "{}: {}.", crl_uri, err
+            );
+            self.reject(&crl_uri, RejectReason::InvalidCrl, Some(&err));
             self.metrics.invalid_manifests += 1;
             self.metrics.invalid_crls += 1;
             return Err(Failed)
         }
         if crl.is_stale() {
             self.metrics.stale_crls += 1;
-            match self.run.validation.stale {
+            let policy = if self.run.validation.in_stale_grace(
+                crl.next_update(), Time::now()
+            ) {
+                self.metrics.stale_grace_crls += 1;
+                FilterPolicy::Warn
+            }
+            else {
+                self.run.validation.stale
+            };
+            match policy {
                 FilterPolicy::Reject => {
-                    warn!("{}: stale CRL.", crl_uri);
+                    warn!(
+                        uri = crl_uri.as_str(), tal = self.tal_name();
+                        "{}: stale CRL.", crl_uri
+                    );
+                    self.reject(&crl_uri, RejectReason::StaleCrl, None);
                     self.metrics.invalid_manifests += 1;
                     self.metrics.invalid_crls += 1;
                     return Err(Failed)
@@ -1204,6 +1958,9 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
                 "{}: certificate has been revoked.",
                 self.cert.rpki_manifest()
             );
+            self.reject(
+                self.cert.rpki_manifest(), RejectReason::Revoked, None
+            );
             self.metrics.invalid_manifests += 1;
             return Err(Failed)
         }
@@ -1223,11 +1980,29 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
     fn accept_point(
         mut self,
         manifest: ValidPointManifest,
+        store: &StoredPoint,
         metrics: &mut RunMetrics,
     ) {
         self.metrics.valid_points += 1;
         self.metrics += manifest.metrics;
+        self.metrics.note_ca_cert_expiry(
+            self.cert.cert().validity().not_after()
+        );
+        self.metrics.note_next_update(manifest.content.next_update());
+        self.metrics.note_next_update(manifest.crl.next_update());
         self.apply_metrics(metrics);
+        self.run.store.record_point(StoredPointMetrics {
+            manifest_uri: self.cert.rpki_manifest().clone(),
+            rpki_notify: self.cert.rpki_notify().cloned(),
+            ca_repository: self.cert.ca_repository().clone(),
+            tal: self.run.validation.tals[self.cert.tal].info().clone(),
+            refreshed: store.updated(),
+            manifest_number: manifest.content.manifest_number(),
+            this_update: manifest.content.this_update(),
+            next_update: manifest.content.next_update(),
+            file_count: manifest.content.len(),
+            fallback: self.fallback,
+        });
         self.processor.commit();
     }
 
@@ -1235,11 +2010,23 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         mut self,
         metrics: &mut RunMetrics,
     ) {
+        self.run.reject(
+            self.cert.uri(), RejectReason::RejectedCa, None,
+            self.cert.tal, self.cert.parent.as_deref(),
+        );
         self.metrics.rejected_points += 1;
         self.apply_metrics(metrics);
         self.processor.cancel(self.cert);
     }
 
+    /// Abandons processing of the point because the run was aborted.
+    ///
+    /// Unlike [`reject_point`][Self::reject_point], this doesn’t count the
+    /// point as rejected since it may well be fine.
+    fn abandon_point(self) {
+        self.processor.cancel(self.cert);
+    }
+
     fn apply_metrics(
         &mut self,
         metrics: &mut RunMetrics,
@@ -1255,6 +2042,60 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         );
     }
 
+    /// Returns the name of the TAL this publication point belongs to.
+    fn tal_name(&self) -> &str {
+        self.run.validation.tals[self.cert.tal].info().name()
+    }
+
+    /// Returns the informational extensions of a CA certificate.
+    ///
+    /// Returns `None` if collecting them is disabled, the certificate
+    /// doesn’t have any, or they can’t be parsed. Since the extensions are
+    /// purely informational, the latter is only logged at debug level.
+    fn cert_extras(
+        &self, uri: &uri::Rsync, cert: &Cert
+    ) -> Option<CertExtensions> {
+        self.run.cert_extras.as_ref()?;
+        match CertExtensions::parse(cert.to_captured().into_bytes()) {
+            Ok(extras) if !extras.is_empty() => Some(extras),
+            Ok(_) => None,
+            Err(err) => {
+                debug!(
+                    "{}: failed to parse certificate extensions: {}",
+                    uri, err
+                );
+                None
+            }
+        }
+    }
+
+    /// Records a rejected object of this publication point.
+    fn reject(
+        &self,
+        uri: &dyn fmt::Display,
+        reason: RejectReason,
+        detail: Option<&dyn fmt::Display>,
+    ) {
+        self.run.reject(
+            uri, reason, detail, self.cert.tal, Some(self.cert.as_ref())
+        )
+    }
+
+    /// Records a rejected certificate of this publication point.
+    ///
+    /// Since the validation error doesn’t tell us why exactly the
+    /// certificate was rejected, we need to be told whether it had expired.
+    fn reject_cert(
+        &self, uri: &uri::Rsync, expired: bool, err: &dyn fmt::Display,
+    ) {
+        self.reject(
+            uri,
+            if expired { RejectReason::ExpiredCertificate }
+            else { RejectReason::InvalidCertificate },
+            Some(err)
+        )
+    }
+
     /// Processes a single object.
     ///
     /// Returns whether processing should continue or whether the entire (!)
@@ -1307,7 +2148,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(cert) => cert,
             Err(_) => {
                 manifest.metrics.invalid_certs += 1;
-                warn!("{}: failed to decode certificate.", uri);
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode certificate.", uri
+                );
+                self.reject(uri, RejectReason::DecodeFailed, None);
                 return Ok(())
             }
         };
@@ -1328,22 +2173,36 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         ca_task: &mut Vec<CaTask<P::PubPoint>>,
     ) -> Result<(), Failed> {
         if self.cert.check_loop(&cert).is_err() {
-            warn!("{}: certificate loop detected.", uri);
+            warn!(
+                uri = uri.as_str(), tal = self.tal_name();
+                "{}: certificate loop detected.", uri
+            );
+            self.reject(uri, RejectReason::CertificateLoop, None);
             manifest.metrics.invalid_certs += 1;
             return Ok(())
         }
+        let expired = cert.validity().not_after() < Time::now();
+        let extras = self.cert_extras(uri, &cert);
         let cert = match cert.validate_ca(
             self.cert.cert(), self.run.validation.strict
         ) {
             Ok(cert) => cert,
             Err(err) => {
-                warn!("{}: {}.", uri, err);
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: {}.", uri, err
+                );
+                self.reject_cert(uri, expired, &err);
                 manifest.metrics.invalid_certs += 1;
                 return Ok(())
             }
         };
         if let Err(err) = manifest.check_crl(&cert) {
-            warn!("{}: {}.", uri, err);
+            warn!(
+                uri = uri.as_str(), tal = self.tal_name();
+                "{}: {}.", uri, err
+            );
+            self.reject(uri, RejectReason::InvalidCertificate, Some(&err));
             manifest.metrics.invalid_certs += 1;
             return Ok(())
         }
@@ -1353,12 +2212,24 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         ) {
             Ok(cert) => cert,
             Err(_) => {
+                self.reject(
+                    uri, RejectReason::InvalidCertificate,
+                    Some(&"CA depth overrun")
+                );
                 manifest.metrics.invalid_certs += 1;
                 return Ok(())
             }
         };
 
         manifest.metrics.valid_ca_certs += 1;
+        if let Some(extras) = extras {
+            self.run.push_cert_extras(CertExtrasMetrics {
+                uri: uri.clone(),
+                tal: self.run.validation.tals[self.cert.tal].info().clone(),
+                cps: extras.cps,
+                extensions: extras.other,
+            });
+        }
 
         let processor = match self.processor.process_ca(
             uri, &cert
@@ -1396,12 +2267,22 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
         if let Err(err) = cert.validate_router(
             self.cert.cert(), self.run.validation.strict
         ) {
-            warn!("{}: {}.", uri, err);
+            warn!(
+                uri = uri.as_str(), tal = self.tal_name();
+                "{}: {}.", uri, err
+            );
+            self.reject_cert(
+                uri, cert.validity().not_after() < Time::now(), &err
+            );
             manifest.metrics.invalid_certs += 1;
             return Ok(())
         };
         if let Err(err) = manifest.check_crl(&cert) {
-            warn!("{}: {}.", uri, err);
+            warn!(
+                uri = uri.as_str(), tal = self.tal_name();
+                "{}: {}.", uri, err
+            );
+            self.reject(uri, RejectReason::InvalidCertificate, Some(&err));
             manifest.metrics.invalid_certs += 1;
             return Ok(())
         }
@@ -1421,7 +2302,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(roa) => roa,
             Err(_) => {
                 manifest.metrics.invalid_roas += 1;
-                warn!("{}: failed to decode ROA.", uri);
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode ROA.", uri
+                );
+                self.reject(uri, RejectReason::DecodeFailed, None);
                 return Ok(())
             }
         };
@@ -1436,7 +2321,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             }
             Err(err) => {
                 manifest.metrics.invalid_roas += 1;
-                warn!("{}: {}.", uri, err)
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: {}.", uri, err
+                );
+                self.reject(uri, RejectReason::InvalidObject, Some(&err));
             }
         }
         Ok(())
@@ -1454,7 +2343,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(aspa) => aspa,
             Err(err) => {
                 manifest.metrics.invalid_aspas += 1;
-                warn!("{}: failed to decode ASPA.", uri);
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode ASPA.", uri
+                );
+                self.reject(uri, RejectReason::DecodeFailed, None);
                 return Ok(())
             }
         };
@@ -1469,7 +2362,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             }
             Err(err) => {
                 manifest.metrics.invalid_aspas += 1;
-                warn!("{}: {}.", uri, err)
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: {}.", uri, err
+                );
+                self.reject(uri, RejectReason::InvalidObject, Some(&err));
             }
         }
         Ok(())
@@ -1486,7 +2383,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             Ok(obj) => obj,
             Err(_) => {
                 manifest.metrics.invalid_gbrs += 1;
-                warn!("{}: failed to decode GBR.", uri);
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: failed to decode GBR.", uri
+                );
+                self.reject(uri, RejectReason::DecodeFailed, None);
                 return Ok(())
             }
         };
@@ -1501,7 +2402,11 @@ impl<'a, P: ProcessRun> PubPoint<'a, P> {
             }
             Err(err) => {
                 manifest.metrics.invalid_gbrs += 1;
-                warn!("{}: {}.", uri, err)
+                warn!(
+                    uri = uri.as_str(), tal = self.tal_name();
+                    "{}: {}.", uri, err
+                );
+                self.reject(uri, RejectReason::InvalidObject, Some(&err));
             }
         }
         Ok(())
@@ -1582,6 +2487,60 @@ impl ValidPointManifest {
 }
 
 
+//------------ ManifestRegression --------------------------------------------
+
+/// The reason a collected manifest isn’t newer than the stored manifest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ManifestRegression {
+    /// The manifest number is not greater than the stored one.
+    Number,
+
+    /// The thisUpdate time is not later than the stored one.
+    ThisUpdate,
+}
+
+impl ManifestRegression {
+    /// Checks whether a collected manifest is newer than the stored one.
+    ///
+    /// Normally, both the manifest number and the thisUpdate time have to
+    /// have increased. However, a CA may legitimately restart its manifest
+    /// numbering, e.g., after a key roll or when moving to a new
+    /// repository. The thisUpdate time is used as a tiebreaker in this
+    /// case: a manifest number that hasn’t increased is accepted if the
+    /// thisUpdate time is later. A replayed manifest will always have an
+    /// earlier thisUpdate time.
+    ///
+    /// Returns `None` if the collected manifest is newer.
+    fn check(
+        number: Serial, this_update: Time,
+        stored_number: Serial, stored_this_update: Time,
+    ) -> Option<Self> {
+        if this_update > stored_this_update {
+            None
+        }
+        else if number <= stored_number {
+            Some(ManifestRegression::Number)
+        }
+        else {
+            Some(ManifestRegression::ThisUpdate)
+        }
+    }
+}
+
+impl fmt::Display for ManifestRegression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ManifestRegression::Number => {
+                "manifest number is not greater than in stored version"
+            }
+            ManifestRegression::ThisUpdate => {
+                "manifest thisUpdate is not later than in stored version"
+            }
+        })
+    }
+}
+
+
 //------------ Task ----------------------------------------------------------
 
 /// Any task that can be queued for delayed processing.
@@ -1834,6 +2793,213 @@ impl CaCert {
 } 
 
 
+//------------ RejectReason --------------------------------------------------
+
+/// The reason why an object or CA was rejected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RejectReason {
+    /// The trust anchor certificate doesn’t match its TAL or is invalid.
+    InvalidTrustAnchor,
+
+    /// There is no manifest for a CA at all.
+    MissingManifest,
+
+    /// The manifest is invalid.
+    InvalidManifest,
+
+    /// The manifest’s thisUpdate time is in the future.
+    PrematureManifest,
+
+    /// The manifest’s nextUpdate time has passed.
+    StaleManifest,
+
+    /// The manifest is older than the one we already have.
+    OutdatedManifest,
+
+    /// The manifest CRL is missing.
+    MissingCrl,
+
+    /// The manifest CRL is invalid.
+    InvalidCrl,
+
+    /// The manifest CRL’s nextUpdate time has passed.
+    StaleCrl,
+
+    /// The manifest’s EE certificate has been revoked.
+    Revoked,
+
+    /// An object listed on the manifest is missing.
+    MissingObject,
+
+    /// An object doesn’t match the hash given on the manifest.
+    HashMismatch,
+
+    /// An object could not be decoded.
+    DecodeFailed,
+
+    /// A certificate has expired.
+    ExpiredCertificate,
+
+    /// A certificate is invalid.
+    InvalidCertificate,
+
+    /// A CA certificate has a key already used by one of its parents.
+    CertificateLoop,
+
+    /// A signed object is invalid.
+    InvalidObject,
+
+    /// The publication point of a CA has been rejected as a whole.
+    RejectedCa,
+}
+
+impl RejectReason {
+    /// Returns the string value used in the report.
+    pub fn as_str(self) -> &'static str {
+        use self::RejectReason::*;
+
+        match self {
+            InvalidTrustAnchor => "invalid-trust-anchor",
+            MissingManifest => "missing-manifest",
+            InvalidManifest => "invalid-manifest",
+            PrematureManifest => "premature-manifest",
+            StaleManifest => "stale-manifest",
+            OutdatedManifest => "outdated-manifest",
+            MissingCrl => "missing-crl",
+            InvalidCrl => "invalid-crl",
+            StaleCrl => "stale-crl",
+            Revoked => "revoked",
+            MissingObject => "missing-object",
+            HashMismatch => "hash-mismatch",
+            DecodeFailed => "decode-failed",
+            ExpiredCertificate => "expired-certificate",
+            InvalidCertificate => "invalid-certificate",
+            CertificateLoop => "certificate-loop",
+            InvalidObject => "invalid-object",
+            RejectedCa => "rejected-ca",
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ RejectedLog ---------------------------------------------------
+
+/// A record of everything rejected during a validation run.
+///
+/// The number of entries kept is limited to [`REJECTED_REPORT_LIMIT`].
+/// Anything beyond that is only counted so the report can note that it
+/// has been truncated.
+#[derive(Debug, Default)]
+struct RejectedLog {
+    /// The recorded entries.
+    entries: Mutex<Vec<RejectedEntry>>,
+
+    /// The number of entries that were dropped because of the limit.
+    dropped: AtomicUsize,
+}
+
+impl RejectedLog {
+    /// Adds an entry to the log.
+    ///
+    /// The entry is only created if there is still room for it.
+    fn push(&self, entry: impl FnOnce() -> RejectedEntry) {
+        let mut entries = self.entries.lock().expect("poisoned lock");
+        if entries.len() < REJECTED_REPORT_LIMIT {
+            entries.push(entry())
+        }
+        else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Writes the log as a JSON report to the given path.
+    ///
+    /// The TAL names are taken from `tals` which must be the list the
+    /// entries’ TAL indexes refer to. Errors are logged but otherwise
+    /// ignored.
+    fn write(&self, path: &Path, tals: &[Tal], metrics: &Metrics) {
+        let entries = self.entries.lock().expect("poisoned lock");
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        let report = JsonBuilder::build(|json| {
+            json.member_raw("generated", metrics.time.timestamp());
+            json.member_str(
+                "generatedTime", format_iso_date(metrics.time)
+            );
+            json.member_raw("truncated", dropped > 0);
+            json.member_raw("dropped", dropped);
+            json.member_array("rejected", |json| {
+                for entry in entries.iter() {
+                    json.array_object(|json| {
+                        json.member_str("uri", &entry.uri);
+                        json.member_str("reason", entry.reason);
+                        if let Some(detail) = entry.detail.as_ref() {
+                            json.member_str("detail", detail);
+                        }
+                        if let Some(tal) = tals.get(entry.tal) {
+                            json.member_str("tal", tal.info().name());
+                        }
+                        if let Some(parent) = entry.parent.as_ref() {
+                            json.member_str("parentCa", parent);
+                        }
+                    })
+                }
+            })
+        });
+        if let Err(err) = Self::write_file(path, report.as_bytes()) {
+            error!(
+                "Failed to write rejected report {}: {}",
+                path.display(), err
+            );
+        }
+    }
+
+    /// Atomically replaces the file at `path` with `content`.
+    ///
+    /// The content is written to a temporary file in the same directory
+    /// which is then renamed, so readers never see a partial report.
+    fn write_file(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        io::Write::write_all(&mut file, content)?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+
+//------------ RejectedEntry -------------------------------------------------
+
+/// A single rejected object or CA.
+#[derive(Clone, Debug)]
+struct RejectedEntry {
+    /// The URI of the rejected object.
+    uri: String,
+
+    /// Why the object was rejected.
+    reason: RejectReason,
+
+    /// Additional information about the rejection.
+    detail: Option<String>,
+
+    /// The index of the TAL the object was found under.
+    tal: usize,
+
+    /// The URI of the certificate of the CA that issued the object.
+    ///
+    /// This is `None` for trust anchor certificates.
+    parent: Option<String>,
+}
+
+
 //------------ RunMetrics ----------------------------------------------------
 
 /// The metrics collected during a engine run.
@@ -1845,9 +3011,24 @@ struct RunMetrics {
     /// The per-repository metrics.
     repositories: Vec<PublicationMetrics>,
 
+    /// The time spent on each repository.
+    ///
+    /// The first element is the time spent waiting for the collector, the
+    /// second the time spent validating.
+    repository_durations: Vec<(Duration, Duration)>,
+
     /// The overall metrics.
     publication: PublicationMetrics,
 
+    /// The trust anchor certificates used.
+    ///
+    /// Contains the index of the TAL, the URI of the certificate, and
+    /// whether it was taken from the store.
+    ta_uris: Vec<(usize, TalUri, bool)>,
+
+    /// The indexes of TALs whose downloaded certificate had a different key.
+    ta_key_mismatches: Vec<usize>,
+
     /// The indexes of repositories in the repository metrics vec.
     ///
     /// The key is the string representation of the rpkiNotify or rsync
@@ -1861,7 +3042,10 @@ impl RunMetrics {
         RunMetrics {
             tals: Default::default(),
             repositories: Default::default(),
+            repository_durations: Default::default(),
             publication: Default::default(),
+            ta_uris: Default::default(),
+            ta_key_mismatches: Default::default(),
             repository_indexes: self.repository_indexes.clone(),
         }
     }
@@ -1886,6 +3070,18 @@ impl RunMetrics {
         index
     }
 
+    /// Records the trust anchor certificate used for a TAL.
+    pub fn set_ta_uri(
+        &mut self, tal_index: usize, uri: &TalUri, stored: bool
+    ) {
+        self.ta_uris.push((tal_index, uri.clone(), stored))
+    }
+
+    /// Records that a downloaded TA certificate doesn’t match the TAL’s key.
+    pub fn set_ta_key_mismatch(&mut self, tal_index: usize) {
+        self.ta_key_mismatches.push(tal_index)
+    }
+
     /// Apply publication metrics.
     pub fn apply(
         &mut self, metrics: &PublicationMetrics,
@@ -1904,6 +3100,19 @@ impl RunMetrics {
         self.publication += metrics;
     }
 
+    /// Apply the time spent processing a publication point.
+    pub fn apply_durations(
+        &mut self, repository_index: usize,
+        fetch: Duration, validation: Duration,
+    ) {
+        while self.repository_durations.len() <= repository_index {
+            self.repository_durations.push(Default::default())
+        }
+        let target = &mut self.repository_durations[repository_index];
+        target.0 += fetch;
+        target.1 += validation;
+    }
+
     /// Prepares the final metrics.
     pub fn prepare_final(&self, target: &mut Metrics) {
         let mut indexes: Vec<_>
@@ -1932,7 +3141,24 @@ impl RunMetrics {
         ) {
             target.publication += metric
         }
+        for (target, durations) in target.repositories.iter_mut().zip(
+            self.repository_durations
+        ) {
+            target.fetch_duration += durations.0;
+            target.validation_duration += durations.1;
+        }
         target.publication += self.publication;
+        for (index, uri, stored) in self.ta_uris {
+            if let Some(target) = target.tals.get_mut(index) {
+                target.ta_uri = Some(uri);
+                target.ta_stored = stored;
+            }
+        }
+        for index in self.ta_key_mismatches {
+            if let Some(target) = target.tals.get_mut(index) {
+                target.ta_key_mismatch = true;
+            }
+        }
     }
 }
 
@@ -2105,7 +3331,267 @@ mod test {
         config.rsync_command = "echo".into();
         config.rsync_args = Some(vec!["some".into()]);
         let engine = Engine::new(&config, true).unwrap();
-        engine.dump(&target).unwrap();
+        let mut target = DumpTarget::dir(target);
+        engine.dump(&mut target, true).unwrap();
+        target.finish().unwrap();
+    }
+
+    #[test]
+    fn write_rejected_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rejected.json");
+        let log = RejectedLog::default();
+        log.push(|| RejectedEntry {
+            uri: "rsync://example.com/repo/foo.roa".into(),
+            reason: RejectReason::HashMismatch,
+            detail: None,
+            tal: 0,
+            parent: Some("rsync://example.com/ca.cer".into()),
+        });
+        log.write(&path, &[], &Metrics::default());
+        let report = fs::read_to_string(&path).unwrap();
+        assert!(report.contains("\"reason\": \"hash-mismatch\""));
+        assert!(report.contains("\"truncated\": false"));
+        assert!(!report.contains("\"detail\""));
+
+        // The report is replaced and no temporary files are left behind.
+        log.write(&path, &[], &Metrics::default());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stale_grace_period() {
+        let _ = crate::process::Process::init(); // May be inited already.
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_with_paths(
+            Default::default(), dir.path().into()
+        );
+        let now = Time::utc(2024, 6, 1, 12, 0, 0);
+        let boundary = Time::utc(2024, 6, 1, 11, 0, 0);
+        let beyond = Time::utc(2024, 6, 1, 10, 59, 59);
+
+        // No grace period.
+        let engine = Engine::new(&config, false).unwrap();
+        assert!(!engine.in_stale_grace(now, now));
+        assert!(!engine.in_stale_grace(boundary, now));
+
+        // One hour of grace. The boundary itself is still included.
+        config.stale_grace_period = Some(Duration::from_secs(3600));
+        let engine = Engine::new(&config, false).unwrap();
+        assert!(engine.in_stale_grace(now, now));
+        assert!(engine.in_stale_grace(boundary, now));
+        assert!(!engine.in_stale_grace(beyond, now));
+
+        // Strict mode ignores the grace period.
+        config.strict = true;
+        let engine = Engine::new(&config, false).unwrap();
+        assert!(!engine.in_stale_grace(now, now));
+        assert!(!engine.in_stale_grace(boundary, now));
+    }
+
+    #[test]
+    fn manifest_regression() {
+        let earlier = Time::utc(2024, 6, 1, 11, 0, 0);
+        let later = Time::utc(2024, 6, 1, 12, 0, 0);
+        let stored = Serial::from(10u64);
+        let check = |number: u64, this_update| {
+            ManifestRegression::check(
+                Serial::from(number), this_update, stored, earlier
+            )
+        };
+
+        // Both increased.
+        assert_eq!(check(11, later), None);
+
+        // Lower or same number with a later thisUpdate: restarted
+        // numbering.
+        assert_eq!(check(9, later), None);
+        assert_eq!(check(10, later), None);
+        assert_eq!(check(0, later), None);
+
+        // Higher number with a same or earlier thisUpdate.
+        assert_eq!(check(11, earlier), Some(ManifestRegression::ThisUpdate));
+        assert_eq!(
+            check(11, Time::utc(2024, 6, 1, 10, 0, 0)),
+            Some(ManifestRegression::ThisUpdate)
+        );
+
+        // Both regressed.
+        assert_eq!(check(10, earlier), Some(ManifestRegression::Number));
+    }
+
+    /// A processor that skips all trust anchors.
+    #[cfg(unix)]
+    struct SkipTa;
+
+    #[cfg(unix)]
+    impl ProcessRun for SkipTa {
+        type PubPoint = Self;
+
+        fn process_ta(
+            &self, _tal: &Tal, _uri: &TalUri, _cert: &CaCert, _index: usize
+        ) -> Result<Option<Self>, Failed> {
+            Ok(None)
+        }
+    }
+
+    #[cfg(unix)]
+    impl ProcessPubPoint for SkipTa {
+        fn want(&self, _uri: &uri::Rsync) -> Result<bool, Failed> {
+            Ok(false)
+        }
+
+        fn process_ca(
+            &mut self, _uri: &uri::Rsync, _cert: &CaCert,
+        ) -> Result<Option<Self>, Failed> {
+            Ok(None)
+        }
+
+        fn restart(&mut self) -> Result<(), Failed> {
+            Ok(())
+        }
+
+        fn commit(self) { }
+    }
+
+    /// Performs a run for the test TAL and returns its metrics.
+    ///
+    /// See [`ta_config`] for the meaning of the arguments.
+    #[cfg(unix)]
+    fn ta_run(cache: &Path, fetched: Option<&[u8]>) -> TalMetrics {
+        let config = ta_config(cache, fetched);
+        let mut engine = Engine::new(&config, true).unwrap();
+        engine.ignite().unwrap();
+        ta_engine_run(&engine)
+    }
+
+    /// Performs a run with the given engine and returns the TAL metrics.
+    #[cfg(unix)]
+    fn ta_engine_run(engine: &Engine) -> TalMetrics {
+        let mut run = engine.start(SkipTa).unwrap();
+        run.process().unwrap();
+        run.done().tals.remove(0)
+    }
+
+    /// Prepares the cache and returns the config for the test TAL.
+    ///
+    /// The TAL’s only URI is an rsync URI. Since the rsync command used
+    /// always fails, the certificate `fetched` is placed into the rsync
+    /// cache as if it had been downloaded earlier. If it is `None`, there
+    /// is no certificate in the cache.
+    #[cfg(unix)]
+    fn ta_config(cache: &Path, fetched: Option<&[u8]>) -> Config {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _ = crate::process::Process::init(); // May be inited already.
+        let rsync = cache.join("rsync.sh");
+        fs::write(&rsync, "#!/bin/sh\ntest \"$1\" = -h\n").unwrap();
+        fs::set_permissions(
+            &rsync, fs::Permissions::from_mode(0o755)
+        ).unwrap();
+        let tals = cache.join("tals");
+        fs::create_dir_all(&tals).unwrap();
+        fs::write(
+            tals.join("test.tal"), include_bytes!("../test/ta/ta.tal")
+        ).unwrap();
+        let ta = cache.join("rsync/rpki.ripe.net/ta/ripe-ncc-ta.cer");
+        match fetched {
+            Some(data) => {
+                fs::create_dir_all(ta.parent().unwrap()).unwrap();
+                fs::write(&ta, data).unwrap();
+            }
+            None => {
+                let _ = fs::remove_file(&ta);
+            }
+        }
+
+        let mut config = Config::default_with_paths(
+            Default::default(), cache.into()
+        );
+        config.no_rir_tals = true;
+        config.extra_tals_dir = Some(tals);
+        config.disable_rrdp = true;
+        config.rsync_command = rsync.into_os_string().into_string().unwrap();
+        config.rsync_args = Some(Vec::new());
+        config
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn ta_key_mismatch() {
+        let cache = tempfile::tempdir().unwrap();
+
+        // The matching certificate is used and stored.
+        let metrics = ta_run(
+            cache.path(), Some(include_bytes!("../test/ta/ta.cer"))
+        );
+        assert!(metrics.ta_uri.is_some());
+        assert!(!metrics.ta_stored);
+        assert!(!metrics.ta_key_mismatch);
+
+        // A certificate with a different key flags the TAL as outdated
+        // and the stored certificate is used instead.
+        let metrics = ta_run(
+            cache.path(), Some(include_bytes!("../test/ta/ca.cer"))
+        );
+        assert!(metrics.ta_uri.is_some());
+        assert!(metrics.ta_stored);
+        assert!(metrics.ta_key_mismatch);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn ta_store_fallback() {
+        let cache = tempfile::tempdir().unwrap();
+
+        // Without a certificate from any URI or in the store, there is no
+        // trust anchor.
+        let metrics = ta_run(cache.path(), None);
+        assert!(metrics.ta_uri.is_none());
+
+        // Once stored, the certificate is used if all URIs fail.
+        let metrics = ta_run(
+            cache.path(), Some(include_bytes!("../test/ta/ta.cer"))
+        );
+        assert!(!metrics.ta_stored);
+        let metrics = ta_run(cache.path(), None);
+        assert!(metrics.ta_uri.is_some());
+        assert!(metrics.ta_stored);
+        assert!(!metrics.ta_key_mismatch);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn skip_update() {
+        let cache = tempfile::tempdir().unwrap();
+        let mut config = ta_config(cache.path(), None);
+        config.tal_refresh.insert(
+            "test".into(), Duration::from_secs(3600)
+        );
+        let mut engine = Engine::new(&config, true).unwrap();
+        engine.ignite().unwrap();
+        assert_eq!(engine.skip_update(), [false]);
+
+        // A run that fails to load the trust anchor doesn’t count.
+        let metrics = ta_engine_run(&engine);
+        assert!(metrics.ta_uri.is_none());
+        assert!(!metrics.update_skipped);
+        assert_eq!(engine.skip_update(), [false]);
+
+        // Once loaded, the TAL isn’t updated until the refresh expires
+        // and the stored certificate is used.
+        ta_config(cache.path(), Some(include_bytes!("../test/ta/ta.cer")));
+        let metrics = ta_engine_run(&engine);
+        assert!(!metrics.update_skipped);
+        assert!(!metrics.ta_stored);
+        assert_eq!(engine.skip_update(), [true]);
+        let metrics = ta_engine_run(&engine);
+        assert!(metrics.update_skipped);
+        assert!(metrics.ta_stored);
+
+        // Without a refresh interval, TALs are always updated.
+        engine.tal_refresh.clear();
+        assert_eq!(engine.skip_update(), [false]);
     }
 }
 
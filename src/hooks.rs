@@ -0,0 +1,334 @@
+//! Running external commands after validation runs.
+//!
+//! In server mode, Routinator can run a command after each successful and
+//! each failed validation run. The commands are given via the
+//! `on-run-success` and `on-run-failure` options. Information about the
+//! outcome of the run is passed to them via environment variables.
+//!
+//! The [`RunHooks`] type runs the commands on a thread of their own so they
+//! never delay the validation or distribution of data. Only one command is
+//! running at any time. If a run finishes while the command of the
+//! previous run is still running, the new command is skipped.
+
+use std::{cmp, io, thread};
+use std::fmt::Write as _;
+use std::io::BufRead;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use log::{error, info, warn};
+use crate::config::Config;
+use crate::payload::SharedHistory;
+
+
+//------------ RunHooks ------------------------------------------------------
+
+/// Runs the configured commands after validation runs.
+#[derive(Debug)]
+pub struct RunHooks {
+    /// The command to run after a successful run.
+    on_success: Option<Vec<String>>,
+
+    /// The command to run after a failed run.
+    on_failure: Option<Vec<String>>,
+
+    /// The time after which a command is killed.
+    timeout: Duration,
+
+    /// Is a command currently running?
+    running: Arc<AtomicBool>,
+}
+
+impl RunHooks {
+    /// Creates the run hooks if any commands are configured.
+    pub fn new(config: &Config) -> Option<Self> {
+        if config.on_run_success.is_none() && config.on_run_failure.is_none()
+        {
+            return None
+        }
+        Some(RunHooks {
+            on_success: config.on_run_success.clone(),
+            on_failure: config.on_run_failure.clone(),
+            timeout: config.hook_timeout,
+            running: Default::default(),
+        })
+    }
+
+    /// Runs the success command for the current data set.
+    ///
+    /// The command receives the following environment variables:
+    ///
+    /// * `ROUTINATOR_RUN_RESULT`: always `success`,
+    /// * `ROUTINATOR_SERIAL`: the current serial number,
+    /// * `ROUTINATOR_VRPS`: the number of VRPs of the current data set,
+    /// * `ROUTINATOR_TAL_VRPS`: the number of VRPs per TAL as a space
+    ///   separated list of `name=count` pairs,
+    /// * `ROUTINATOR_DURATION`: the duration of the run in seconds.
+    pub fn success(&self, history: &SharedHistory) {
+        let command = match self.on_success.as_ref() {
+            Some(command) => command,
+            None => return,
+        };
+        let (metrics, serial, duration) = {
+            let history = history.read();
+            (
+                history.metrics(),
+                history.serial(),
+                history.last_update_duration(),
+            )
+        };
+        let mut env = vec![
+            ("ROUTINATOR_RUN_RESULT", String::from("success")),
+            ("ROUTINATOR_SERIAL", serial.to_string()),
+        ];
+        if let Some(metrics) = metrics {
+            env.push((
+                "ROUTINATOR_VRPS",
+                metrics.snapshot.payload.vrps().valid.to_string()
+            ));
+            let mut tals = String::new();
+            for tal in &metrics.tals {
+                if !tals.is_empty() {
+                    tals.push(' ');
+                }
+                let _ = write!(
+                    tals, "{}={}", tal.name(), tal.payload.vrps().valid
+                );
+            }
+            env.push(("ROUTINATOR_TAL_VRPS", tals));
+        }
+        if let Some(duration) = duration {
+            env.push((
+                "ROUTINATOR_DURATION",
+                format!("{:.3}", duration.as_secs_f64())
+            ));
+        }
+        self.spawn(command, env);
+    }
+
+    /// Runs the failure command.
+    ///
+    /// The command receives the following environment variables:
+    ///
+    /// * `ROUTINATOR_RUN_RESULT`: always `failure`,
+    /// * `ROUTINATOR_FAILURE_REASON`: a short description of the failure,
+    /// * `ROUTINATOR_SERIAL`: the serial number of the data set still in
+    ///   use,
+    /// * `ROUTINATOR_DURATION`: the time since the start of the run in
+    ///   seconds.
+    pub fn failure(&self, history: &SharedHistory, reason: &str) {
+        let command = match self.on_failure.as_ref() {
+            Some(command) => command,
+            None => return,
+        };
+        let (serial, start) = {
+            let history = history.read();
+            (history.serial(), history.last_update_start())
+        };
+        let duration = Utc::now().signed_duration_since(start);
+        let env = vec![
+            ("ROUTINATOR_RUN_RESULT", String::from("failure")),
+            ("ROUTINATOR_FAILURE_REASON", String::from(reason)),
+            ("ROUTINATOR_SERIAL", serial.to_string()),
+            (
+                "ROUTINATOR_DURATION",
+                format!(
+                    "{:.3}",
+                    duration.num_milliseconds().max(0) as f64 / 1000.
+                )
+            ),
+        ];
+        self.spawn(command, env);
+    }
+
+    /// Starts a command on a thread of its own.
+    ///
+    /// If the previous command is still running, logs a warning and does
+    /// nothing.
+    fn spawn(&self, command: &[String], env: Vec<(&'static str, String)>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            warn!(
+                "Run hook '{}' from the previous run is still running. \
+                 Skipping.",
+                command[0]
+            );
+            return
+        }
+        let command = command.to_vec();
+        let timeout = self.timeout;
+        let running = self.running.clone();
+        let res = thread::Builder::new().name("run-hook".into()).spawn(
+            move || {
+                run_command(&command, env, timeout);
+                running.store(false, Ordering::SeqCst);
+            }
+        );
+        if let Err(err) = res {
+            error!("Failed to start run hook thread: {}", err);
+            self.running.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Runs a command and waits for it to finish.
+///
+/// Its output is logged line by line, standard output at info level and
+/// standard error at warn level. If it doesn’t finish within `timeout`, it
+/// is killed. All problems are logged and otherwise ignored.
+fn run_command(
+    command: &[String],
+    env: Vec<(&'static str, String)>,
+    timeout: Duration,
+) {
+    let name = command[0].as_str();
+    let mut cmd = Command::new(name);
+    cmd.args(&command[1..])
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    close_inherited_fds(&mut cmd);
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            error!("Failed to start run hook '{}': {}", name, err);
+            return
+        }
+    };
+    log_output(&mut child, name);
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if status.success() {
+                    info!("Run hook '{}' finished.", name);
+                }
+                else {
+                    warn!("Run hook '{}' failed: {}", name, status);
+                }
+                return
+            }
+            Ok(None) => {
+                if Instant::now() >= deadline {
+                    warn!(
+                        "Run hook '{}' timed out after {} seconds. \
+                         Killing it.",
+                        name, timeout.as_secs()
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return
+                }
+                thread::sleep(cmp::min(
+                    Duration::from_millis(100),
+                    deadline.saturating_duration_since(Instant::now())
+                ));
+            }
+            Err(err) => {
+                error!("Failed to wait for run hook '{}': {}", name, err);
+                return
+            }
+        }
+    }
+}
+
+/// Starts threads logging the output of the child process.
+///
+/// The threads end when the output is closed. They aren’t waited for as
+/// the command may have started processes of its own that keep the output
+/// open.
+fn log_output(child: &mut Child, name: &str) {
+    fn spawn_logger(
+        output: impl io::Read + Send + 'static,
+        name: String,
+        log: fn(&str, &str),
+    ) {
+        let res = thread::Builder::new().name("run-hook-output".into()).spawn(
+            move || {
+                for line in io::BufReader::new(output).lines() {
+                    match line {
+                        Ok(line) => log(&name, &line),
+                        Err(_) => break,
+                    }
+                }
+            }
+        );
+        if let Err(err) = res {
+            error!("Failed to start run hook output thread: {}", err);
+        }
+    }
+
+    if let Some(stdout) = child.stdout.take() {
+        spawn_logger(stdout, name.into(), |name, line| {
+            info!("{}: {}", name, line)
+        });
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_logger(stderr, name.into(), |name, line| {
+            warn!("{}: {}", name, line)
+        });
+    }
+}
+
+/// Makes sure the command doesn’t inherit any file descriptors.
+///
+/// Sockets created by Routinator itself are opened with the close-on-exec
+/// flag set, but listening sockets passed in by systemd aren’t. We thus
+/// set the flag on all file descriptors other than the standard streams
+/// in the child process before executing the command.
+#[cfg(unix)]
+fn close_inherited_fds(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    use nix::libc;
+
+    // SAFETY: The closure only calls async-signal-safe functions.
+    unsafe {
+        cmd.pre_exec(|| {
+            let max = match libc::sysconf(libc::_SC_OPEN_MAX) {
+                max if max > 0 => cmp::min(max, 65536) as libc::c_int,
+                _ => 1024,
+            };
+            for fd in 3..max {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+            Ok(())
+        });
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skip_while_running() {
+        let hooks = RunHooks::new(&Config {
+            on_run_failure: Some(vec!["true".into()]),
+            .. Default::default()
+        }).unwrap();
+        hooks.running.store(true, Ordering::SeqCst);
+        hooks.spawn(&["true".into()], Vec::new());
+        assert!(hooks.running.load(Ordering::SeqCst));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_command_timeout() {
+        let start = Instant::now();
+        run_command(
+            &["sleep".into(), "10".into()], Vec::new(),
+            Duration::from_millis(200)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,142 @@
+//! Handling of the endpoints providing archived snapshots.
+//!
+//! `/api/v1/archive` lists the archived snapshots while
+//! `/api/v1/archive/<name>.<format>` renders a snapshot in one of the
+//! output formats. The name is a time stamp in the form
+//! `YYYYMMDDTHHMMSSZ`. The snapshot returned is the most recent one that
+//! was created no later than this time.
+
+use std::sync::Arc;
+use futures::stream;
+use log::error;
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::output::{Output, OutputFormat};
+use crate::payload::SnapshotArchive;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ State ---------------------------------------------------------
+
+pub struct State {
+    archive: Option<SnapshotArchive>,
+    output: Output,
+}
+
+impl State {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            archive: SnapshotArchive::new(config),
+            output: Output::from_config(config),
+        }
+    }
+
+    pub async fn handle_get_or_head(
+        &self,
+        req: &Request,
+    ) -> Option<Response> {
+        let path = req.uri().path().strip_prefix("/api/v1/archive")?;
+        if !path.is_empty() && !path.starts_with('/') {
+            return None
+        }
+        let archive = match self.archive.as_ref() {
+            Some(archive) => archive,
+            None => return Some(Response::not_found()),
+        };
+        match path {
+            "" | "/" => Some(Self::list(req, archive)),
+            _ => Some(self.snapshot(req, archive, &path[1..]).await)
+        }
+    }
+
+    /// Produces the list of archived snapshots.
+    fn list(req: &Request, archive: &SnapshotArchive) -> Response {
+        let list = match archive.list() {
+            Ok(list) => list,
+            Err(err) => {
+                error!(
+                    "Failed to read snapshot archive {}: {}",
+                    archive.dir().display(), err
+                );
+                return Response::internal_server_error()
+            }
+        };
+        let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+        if req.is_head() {
+            return res.empty()
+        }
+        res.body(JsonBuilder::build(|json| {
+            json.member_array("snapshots", |json| {
+                for time in list.iter().rev() {
+                    json.array_object(|json| {
+                        json.member_str(
+                            "name", SnapshotArchive::name(*time)
+                        );
+                        json.member_str("created", format_iso_date(*time));
+                    })
+                }
+            })
+        }))
+    }
+
+    /// Produces an archived snapshot in the requested format.
+    async fn snapshot(
+        &self, req: &Request, archive: &SnapshotArchive, name: &str,
+    ) -> Response {
+        let (time, format) = match name.split_once('.') {
+            Some((time, format)) => (time, format),
+            None => return Response::not_found(),
+        };
+        let (time, format) = match (
+            SnapshotArchive::parse_name(time),
+            OutputFormat::try_from_str(format)
+        ) {
+            (Some(time), Some(format)) => (time, format),
+            _ => return Response::not_found(),
+        };
+        let mut output = self.output.clone();
+        if output.update_from_query(req.uri().query()).is_err() {
+            return Response::bad_request()
+        }
+
+        // Reading the snapshot may take a while, so we do it on a thread
+        // where blocking is fine.
+        let archive = archive.clone();
+        let snapshot = tokio::task::spawn_blocking(move || {
+            match archive.find(time)? {
+                Some(time) => archive.load(time).map(Some),
+                None => Ok(None),
+            }
+        }).await;
+        let snapshot = match snapshot {
+            Ok(Ok(Some(snapshot))) => snapshot,
+            Ok(Ok(None)) => return Response::not_found(),
+            Ok(Err(err)) => {
+                error!("Failed to load archived snapshot: {}", err);
+                return Response::internal_server_error()
+            }
+            Err(err) => {
+                error!("Failed to load archived snapshot: {}", err);
+                return Response::internal_server_error()
+            }
+        };
+
+        let res = ResponseBuilder::ok()
+            .content_type(format.content_type())
+            .last_modified(snapshot.created());
+        if req.is_head() {
+            res.vary_encoding().empty()
+        }
+        else {
+            res.stream_for(
+                req,
+                stream::iter(output.stream(
+                    Arc::new(snapshot), Arc::new(Metrics::new()), format
+                ))
+            )
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Authentication of HTTP requests.
+//!
+//! If a token file is configured via the `http-auth-token-file` option,
+//! requests for the protected paths need to carry one of the tokens from
+//! that file as a bearer token in their Authorization header. The paths
+//! are given as a list of prefixes via the `http-auth-paths` option. If
+//! it is empty, all paths are protected.
+//!
+//! The status and monitoring endpoints listed in [`PUBLIC_PATHS`] never
+//! need authentication so that monitoring keeps working.
+
+use std::fs;
+use hyper::header::HeaderMap;
+use log::error;
+use crate::config::Config;
+use crate::error::ExitError;
+use super::response::Response;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The paths that never need authentication.
+const PUBLIC_PATHS: &[&str] = &[
+    "/api/v1/health", "/api/v1/status", "/metrics", "/status", "/version",
+];
+
+
+//------------ State ---------------------------------------------------------
+
+pub struct State {
+    /// The accepted tokens.
+    ///
+    /// If this is empty, authentication is disabled.
+    tokens: Vec<Vec<u8>>,
+
+    /// The path prefixes that need authentication.
+    paths: Vec<String>,
+}
+
+impl State {
+    /// Creates the state, reading the token file if configured.
+    pub fn new(config: &Config) -> Result<Self, ExitError> {
+        let path = match config.http_auth_token_file.as_ref() {
+            Some(path) => path,
+            None => {
+                return Ok(State { tokens: Vec::new(), paths: Vec::new() })
+            }
+        };
+        let content = fs::read_to_string(path).map_err(|err| {
+            error!(
+                "Failed to read HTTP token file {}: {}",
+                path.display(), err
+            );
+            ExitError::Generic
+        })?;
+        let tokens = parse_tokens(&content);
+        if tokens.is_empty() {
+            error!("HTTP token file {} contains no tokens.", path.display());
+            return Err(ExitError::Generic)
+        }
+        Ok(State {
+            tokens,
+            paths: config.http_auth_paths.clone(),
+        })
+    }
+
+    /// Checks whether a request is authorized.
+    ///
+    /// Returns the response to send if it isn’t or `None` if the request
+    /// can be processed.
+    pub fn check(
+        &self, path: &str, headers: &HeaderMap
+    ) -> Option<Response> {
+        if !self.is_protected(path) || self.is_authorized(headers) {
+            None
+        }
+        else {
+            Some(Response::unauthorized())
+        }
+    }
+
+    /// Returns whether requests for `path` need authentication.
+    fn is_protected(&self, path: &str) -> bool {
+        if self.tokens.is_empty() || PUBLIC_PATHS.contains(&path) {
+            return false
+        }
+        self.paths.is_empty() || self.paths.iter().any(|prefix| {
+            path.starts_with(prefix.as_str())
+        })
+    }
+
+    /// Returns whether the headers contain a valid bearer token.
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let token = match headers.get("Authorization").and_then(|value| {
+            bearer_token(value.as_bytes())
+        }) {
+            Some(token) => token,
+            None => return false,
+        };
+
+        // Compare against all tokens so the time taken doesn’t reveal
+        // which one matched.
+        self.tokens.iter().fold(false, |res, item| {
+            constant_time_eq(item, token) | res
+        })
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Parses the content of a token file.
+///
+/// Each non-empty line contains one token. Lines starting with `#` are
+/// comments.
+fn parse_tokens(content: &str) -> Vec<Vec<u8>> {
+    content.lines().map(str::trim).filter(|line| {
+        !line.is_empty() && !line.starts_with('#')
+    }).map(|line| line.as_bytes().to_vec()).collect()
+}
+
+/// Returns the token of an Authorization header value.
+///
+/// Returns `None` if the value does not use the Bearer scheme.
+fn bearer_token(value: &[u8]) -> Option<&[u8]> {
+    let (scheme, token) = value.split_at(value.iter().position(|ch| {
+        *ch == b' '
+    })?);
+    if !scheme.eq_ignore_ascii_case(b"Bearer") {
+        return None
+    }
+    Some(std::str::from_utf8(token).ok()?.trim().as_bytes())
+}
+
+/// Compares two byte strings in time independent of their content.
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    let mut res = left.len() ^ right.len();
+    for (i, ch) in left.iter().enumerate() {
+        res |= usize::from(ch ^ right.get(i).copied().unwrap_or(0));
+    }
+    res == 0
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(paths: &[&str]) -> State {
+        State {
+            tokens: parse_tokens("# current\nsecret1\n\n  secret2  \n"),
+            paths: paths.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut res = HeaderMap::new();
+        res.insert("Authorization", value.parse().unwrap());
+        res
+    }
+
+    #[test]
+    fn token_file() {
+        assert_eq!(
+            parse_tokens("# current\nsecret1\n\n  secret2  \n"),
+            [b"secret1".to_vec(), b"secret2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn is_protected() {
+        let all = state(&[]);
+        assert!(all.is_protected("/jsonext"));
+        assert!(all.is_protected("/api/v1/reload-exceptions"));
+        assert!(all.is_protected("/api/v1/refresh"));
+        assert!(all.is_protected("/api/v1/integrity-check"));
+        assert!(!all.is_protected("/metrics"));
+        assert!(!all.is_protected("/status"));
+        assert!(!all.is_protected("/api/v1/status"));
+
+        let some = state(&["/jsonext", "/api/v1/"]);
+        assert!(some.is_protected("/jsonext"));
+        assert!(some.is_protected("/api/v1/reload-exceptions"));
+        assert!(!some.is_protected("/csv"));
+        assert!(!some.is_protected("/api/v1/health"));
+
+        let disabled = State { tokens: Vec::new(), paths: Vec::new() };
+        assert!(!disabled.is_protected("/jsonext"));
+    }
+
+    #[test]
+    fn check() {
+        // Requests via plain and TLS listeners are covered by the
+        // listener module’s tests.
+        let state = state(&[]);
+        let denied = |value: &str| {
+            state.check("/jsonext", &headers(value)).is_some()
+        };
+        assert!(state.check("/jsonext", &HeaderMap::new()).is_some());
+        assert!(!denied("Bearer secret1"));
+        assert!(!denied("bearer secret2"));
+        assert!(denied("Bearer secret"));
+        assert!(denied("Basic secret1"));
+        assert!(denied("Bearersecret1"));
+        assert!(state.check("/metrics", &HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn compare_tokens() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(!constant_time_eq(b"secret1", b"secret"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
+
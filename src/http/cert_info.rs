@@ -0,0 +1,61 @@
+//! Handling of the endpoint listing informational certificate extensions.
+
+use crate::metrics::Metrics;
+use crate::payload::SharedHistory;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/cert-info" {
+        return None
+    }
+    let metrics = match history.read().metrics() {
+        Some(metrics) => metrics,
+        None => return Some(Response::initial_validation()),
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(cert_info_json(&metrics)))
+    }
+}
+
+
+//------------ cert_info_json ------------------------------------------------
+
+fn cert_info_json(metrics: &Metrics) -> String {
+    JsonBuilder::build(|json| {
+        json.member_str("generatedTime", format_iso_date(metrics.time));
+        json.member_raw("collected", metrics.cert_extras.is_some());
+        json.member_array("certificates", |json| {
+            for item in metrics.cert_extras.iter().flatten() {
+                json.array_object(|json| {
+                    json.member_str("uri", &item.uri);
+                    json.member_str("ta", item.tal.name());
+                    json.member_array("cps", |json| {
+                        for cps in &item.cps {
+                            json.array_str(cps);
+                        }
+                    });
+                    json.member_array("extensions", |json| {
+                        for oid in &item.extensions {
+                            json.array_str(oid);
+                        }
+                    });
+                })
+            }
+        })
+    })
+}
+
@@ -0,0 +1,83 @@
+//! Handling of the endpoint checking individual RPKI objects.
+
+use crate::check::ObjectReport;
+use crate::store::Store;
+use super::request::{BodyError, Request};
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_post ---------------------------------------------------
+
+/// Returns whether the request is an object check request.
+pub fn is_check_object(req: &Request) -> bool {
+    req.uri().path() == "/api/v1/check-object"
+}
+
+/// Processes an object check request.
+///
+/// The request body contains the DER encoded object. The query may contain
+/// a `name` parameter with the object’s file name which is used to
+/// determine the object type if it can’t be derived from the content, and
+/// a `format` parameter which selects between a `json` and a `text`
+/// report, the former being the default.
+///
+/// The body is limited to `max_object_size` if given or a default size
+/// otherwise.
+pub async fn handle_post(
+    req: Request,
+    store: &Store,
+    max_object_size: Option<u64>,
+) -> Response {
+    if !req.is_post() {
+        return Response::method_not_allowed()
+    }
+    let mut name = None;
+    let mut text = false;
+    if let Some(query) = req.uri().query() {
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match (key.as_ref(), value.as_ref()) {
+                ("name", _) => name = Some(value.into_owned()),
+                ("format", "json") => text = false,
+                ("format", "text") => text = true,
+                _ => return Response::bad_request()
+            }
+        }
+    }
+    let limit = max_object_size.and_then(|size| {
+        usize::try_from(size).ok()
+    }).unwrap_or(DEFAULT_MAX_OBJECT_SIZE);
+    let body = match req.read_body(limit).await {
+        Ok(body) => body,
+        Err(BodyError::TooLarge) => return Response::payload_too_large(),
+        Err(BodyError::Failed) => return Response::bad_request(),
+    };
+
+    // Looking for the issuer in the store reads files, so we do this on
+    // a thread where blocking is fine.
+    let store = store.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let mut report = ObjectReport::new(body, name.as_deref());
+        report.check_signature(&store).map(|_| report)
+    }).await;
+    let report = match report {
+        Ok(Ok(report)) => report,
+        _ => return Response::internal_server_error()
+    };
+
+    if text {
+        let mut res = Vec::new();
+        report.write_text(&mut res).expect("writing to vec");
+        ResponseBuilder::ok().content_type(ContentType::TEXT).body(res)
+    }
+    else {
+        ResponseBuilder::ok().content_type(ContentType::JSON).body(
+            report.to_json()
+        )
+    }
+}
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum size of an object if no limit is configured.
+const DEFAULT_MAX_OBJECT_SIZE: usize = 20_000_000;
@@ -0,0 +1,40 @@
+//! Handles the endpoint for the effective configuration.
+
+use bytes::Bytes;
+use crate::config::Config;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+//------------ State ---------------------------------------------------------
+
+pub struct State {
+    /// The JSON representation of the configuration.
+    ///
+    /// The configuration doesn’t change while the server is running, so we
+    /// can create this once.
+    json: Bytes,
+}
+
+impl State {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            json: Bytes::from(config.to_json().to_string()),
+        }
+    }
+
+    pub fn handle_get_or_head(
+        &self,
+        req: &Request,
+    ) -> Option<Response> {
+        if req.uri().path() != "/api/v1/config" {
+            return None
+        }
+        let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+        if req.is_head() {
+            Some(res.empty())
+        }
+        else {
+            Some(res.body(self.json.clone()))
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Cross-origin resource sharing.
+//!
+//! If origins are configured via the `http-cors-origins` option, responses
+//! to requests from these origins get the CORS headers that allow browsers
+//! to use them. Preflight requests, i.e., OPTIONS requests, are answered
+//! directly. Requests from other origins are handled as before without any
+//! CORS headers.
+
+use hyper::header::HeaderValue;
+use crate::config::Config;
+use super::request::Request;
+use super::response::{Response, ResponseBuilder};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The paths of the status and metrics endpoints.
+///
+/// These only get CORS headers if the `http-cors-status` option is set.
+const STATUS_PATHS: &[&str] = &[
+    "/api/v1/health", "/api/v1/status", "/log", "/metrics", "/status",
+    "/version",
+];
+
+/// The methods allowed in preflight responses.
+const ALLOW_METHODS: &str = "GET, HEAD, POST";
+
+/// The request headers allowed in preflight responses.
+const ALLOW_HEADERS: &str =
+    "Authorization, Content-Type, If-Modified-Since, If-None-Match";
+
+/// The number of seconds a browser may cache a preflight response.
+const MAX_AGE: &str = "86400";
+
+
+//------------ State ---------------------------------------------------------
+
+pub struct State {
+    /// The allowed origins.
+    origins: Vec<String>,
+
+    /// Are all origins allowed?
+    any: bool,
+
+    /// Do status and metrics endpoints get CORS headers?
+    status: bool,
+}
+
+impl State {
+    pub fn new(config: &Config) -> Self {
+        State {
+            origins: config.http_cors_origins.clone(),
+            any: config.http_cors_origins.iter().any(|item| item == "*"),
+            status: config.http_cors_status,
+        }
+    }
+
+    /// Answers a preflight request.
+    ///
+    /// Returns `None` if the request isn’t a preflight request for an
+    /// allowed origin, leaving it to the regular dispatch.
+    pub fn handle_preflight(&self, req: &Request) -> Option<Response> {
+        if !req.is_options() {
+            return None
+        }
+        let origin = self.allow_origin(req)?;
+        Some(
+            self.add_headers(
+                Some(origin),
+                ResponseBuilder::no_content().empty()
+            )
+            .with_header("Access-Control-Allow-Methods", ALLOW_METHODS)
+            .with_header("Access-Control-Allow-Headers", ALLOW_HEADERS)
+            .with_header("Access-Control-Max-Age", MAX_AGE)
+        )
+    }
+
+    /// Returns the value of the Access-Control-Allow-Origin header.
+    ///
+    /// Returns `None` if the request should not get CORS headers.
+    pub fn allow_origin(&self, req: &Request) -> Option<HeaderValue> {
+        if !self.applies(req.uri().path()) {
+            return None
+        }
+        let origin = req.headers().get("Origin")?;
+        if self.any {
+            return Some(HeaderValue::from_static("*"))
+        }
+        let value = origin.to_str().ok()?;
+        if self.origins.iter().any(|item| item == value) {
+            Some(origin.clone())
+        }
+        else {
+            None
+        }
+    }
+
+    /// Adds the CORS headers to a response.
+    ///
+    /// The `origin` is the value returned by
+    /// [`allow_origin`][Self::allow_origin] for the request.
+    pub fn add_headers(
+        &self, origin: Option<HeaderValue>, response: Response
+    ) -> Response {
+        if self.origins.is_empty() {
+            return response
+        }
+        // If the header depends on the origin, caches need to know.
+        let response = if self.any {
+            response
+        }
+        else {
+            response.with_header("Vary", "Origin")
+        };
+        match origin {
+            Some(origin) => {
+                response.with_header("Access-Control-Allow-Origin", origin)
+            }
+            None => response
+        }
+    }
+
+    /// Returns whether CORS applies to the given path at all.
+    fn applies(&self, path: &str) -> bool {
+        if self.origins.is_empty()
+            || path == "/" || path == "/ui" || path.starts_with("/ui/")
+        {
+            false
+        }
+        else if STATUS_PATHS.contains(&path) {
+            self.status
+        }
+        else {
+            true
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(origins: &[&str], status: bool) -> State {
+        State {
+            origins: origins.iter().map(ToString::to_string).collect(),
+            any: origins.contains(&"*"),
+            status,
+        }
+    }
+
+    #[test]
+    fn applies() {
+        assert!(!state(&[], true).applies("/json"));
+
+        let cors = state(&["https://tools.example.net"], false);
+        assert!(cors.applies("/json"));
+        assert!(cors.applies("/api/v1/validity/AS65000/192.0.2.0/24"));
+        assert!(!cors.applies("/metrics"));
+        assert!(!cors.applies("/api/v1/status"));
+        assert!(!cors.applies("/"));
+        assert!(!cors.applies("/ui/index.html"));
+
+        let cors = state(&["*"], true);
+        assert!(cors.applies("/metrics"));
+        assert!(cors.applies("/api/v1/status"));
+    }
+}
+
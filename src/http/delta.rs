@@ -47,21 +47,30 @@ pub fn handle_get_or_head(
     // fallback here is fine.
     let created = history.created().unwrap_or(Utc::now());
 
-    if let Some((session, serial)) = version {
-        if session == history.session() {
-            if let Some(delta) = history.delta_since(serial) {
+    let reason = match version {
+        Some((session, serial)) => {
+            if session != history.session() {
+                Some(ResetReason::SessionMismatch)
+            }
+            else if let Some(delta) = history.delta_since(serial) {
                 return Some(handle_delta(
                     session, serial, history.serial(), delta, created
                 ))
             }
+            else {
+                Some(ResetReason::SerialUnavailable)
+            }
         }
-    }
+        None => None,
+    };
 
     let snapshot = match history.current() {
         Some(snapshot) => snapshot,
         None => return Some(Response::initial_validation()),
     };
-    Some(handle_reset(history.session(), history.serial(), snapshot, created))
+    Some(handle_reset(
+        history.session(), history.serial(), snapshot, created, reason
+    ))
 }
 
 fn handle_delta(
@@ -77,16 +86,42 @@ fn handle_delta(
 
 fn handle_reset(
     session: u64, to_serial: Serial, snapshot: Arc<PayloadSnapshot>,
-    created: DateTime<Utc>,
+    created: DateTime<Utc>, reason: Option<ResetReason>,
 ) -> Response {
     ResponseBuilder::ok().content_type(ContentType::JSON).stream(
         stream::iter(
-            SnapshotStream::new(session, to_serial, snapshot, created)
+            SnapshotStream::new(session, to_serial, snapshot, created, reason)
         )
     )
 }
 
 
+//------------ ResetReason ---------------------------------------------------
+
+/// The reason why a full data set is returned instead of a delta.
+#[derive(Clone, Copy, Debug)]
+enum ResetReason {
+    /// The requested session is not the current session.
+    SessionMismatch,
+
+    /// No delta from the requested serial is available anymore.
+    ///
+    /// This happens if the serial is older than the oldest kept delta or
+    /// if it is from the future.
+    SerialUnavailable,
+}
+
+impl ResetReason {
+    /// Returns the value for the `resetReason` member.
+    fn as_str(self) -> &'static str {
+        match self {
+            ResetReason::SessionMismatch => "session-mismatch",
+            ResetReason::SerialUnavailable => "serial-unavailable",
+        }
+    }
+}
+
+
 //------------ handle_notify_get_or_head -------------------------------------
 
 pub async fn handle_notify_get_or_head(
@@ -420,10 +455,10 @@ impl SnapshotStream {
     /// Creates a new snapshot stream.
     fn new(
         session: u64, to_serial: Serial, snapshot: Arc<PayloadSnapshot>,
-        created: DateTime<Utc>,
+        created: DateTime<Utc>, reason: Option<ResetReason>,
     ) -> Self {
         let mut vec = Vec::new();
-        Self::append_header(&mut vec, session, to_serial, created);
+        Self::append_header(&mut vec, session, to_serial, created, reason);
         SnapshotStream {
             header: Some(vec),
             iter: Some(snapshot.arc_iter()),
@@ -434,10 +469,16 @@ impl SnapshotStream {
     fn append_header(
         vec: &mut Vec<u8>,
         session: u64, to_serial: Serial, created: DateTime<Utc>,
+        reason: Option<ResetReason>,
     ) {
         write!(vec, "\
             {{\
-            \n  \"reset\": true,\
+            \n  \"reset\": true,",
+        );
+        if let Some(reason) = reason {
+            write!(vec, "\n  \"resetReason\": \"{}\",", reason.as_str());
+        }
+        write!(vec, "\
             \n  \"session\": \"{}\",\
             \n  \"serial\": {},\
             \n  \"generated\": {},\
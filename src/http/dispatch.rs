@@ -2,11 +2,21 @@
 
 use std::sync::Arc;
 use rpki::rtr::server::NotifySender;
-use crate::config::Config;
+use crate::config::{Config, HttpBasePath, MetricsDetail};
+use crate::error::ExitError;
+use crate::integrity::IntegrityCheckRequest;
 use crate::metrics::{HttpServerMetrics, SharedRtrServerMetrics};
 use crate::payload::SharedHistory;
-use crate::process::LogOutput;
-use super::{delta, log, metrics, payload, status, validity};
+use crate::process::{LogOutput, RunJournal};
+use crate::operation::RefreshRequest;
+use crate::slurm::ReloadRequest;
+use crate::store::Store;
+use crate::utils::tls::TlsConfigs;
+use super::{
+    archive, auth, cert_info, check_object, config, cors, delta, exceptions,
+    explorer, health, integrity, log, manifests, metrics, origins, payload,
+    refresh, rrdp_history, run_log, status, store, ui, unsafe_vrps, validity
+};
 use super::request::Request;
 use super::response::Response;
 
@@ -14,37 +24,120 @@ use super::response::Response;
 
 pub struct State {
     payload: payload::State,
+    archive: archive::State,
+    config: config::State,
     log: log::State,
+    auth: auth::State,
+    cors: cors::State,
+    ui: ui::State,
+    journal: Option<Arc<RunJournal>>,
     history: SharedHistory,
     metrics: Arc<HttpServerMetrics>,
     rtr_metrics: SharedRtrServerMetrics,
     notify: NotifySender,
+    reload: ReloadRequest,
+    refresh: RefreshRequest,
+    integrity: IntegrityCheckRequest,
+    tls_configs: TlsConfigs,
+    validity_batch_limit: usize,
+    store: Store,
+    max_object_size: Option<u64>,
+    metrics_detail: MetricsDetail,
+    base_path: HttpBasePath,
 }
 
 impl State {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: &Config,
         history: SharedHistory,
         rtr_metrics: SharedRtrServerMetrics,
         log: Option<Arc<LogOutput>>,
+        journal: Option<Arc<RunJournal>>,
         notify: NotifySender,
-    ) -> Self {
-        Self {
+        reload: ReloadRequest,
+        refresh: RefreshRequest,
+        integrity: IntegrityCheckRequest,
+        tls_configs: TlsConfigs,
+    ) -> Result<Self, ExitError> {
+        Ok(Self {
             payload: payload::State::new(config),
+            archive: archive::State::new(config),
+            config: config::State::new(config),
             log: log::State::new(log),
+            auth: auth::State::new(config)?,
+            cors: cors::State::new(config),
+            ui: ui::State::new(config),
+            journal,
             history,
             metrics: Arc::new(HttpServerMetrics::default()),
             rtr_metrics,
             notify,
-        }
+            reload,
+            refresh,
+            integrity,
+            tls_configs,
+            validity_batch_limit: config.validity_batch_limit,
+            store: Store::existing(config),
+            max_object_size: config.max_object_size,
+            metrics_detail: config.metrics_detail,
+            base_path: config.http_base_path.clone(),
+        })
     }
     
     pub fn metrics(&self) -> &Arc<HttpServerMetrics> {
         &self.metrics
     }
 
-    pub async fn handle_request(&self, req: Request) -> Response {
+    pub async fn handle_request(
+        &self, mut req: Request, role: Role
+    ) -> Response {
         self.metrics.inc_requests();
+        if !req.strip_base_path(self.base_path.as_str()) {
+            return Response::not_found()
+        }
+        if !role.allows(req.uri().path()) {
+            return Response::not_found()
+        }
+        if let Some(response) = self.cors.handle_preflight(&req) {
+            return response
+        }
+        let origin = self.cors.allow_origin(&req);
+        let response = match self.auth.check(
+            req.uri().path(), req.headers()
+        ) {
+            Some(response) => response,
+            None => self.dispatch(req).await,
+        };
+        self.cors.add_headers(origin, response)
+    }
+
+    async fn dispatch(&self, req: Request) -> Response {
+        if let Some(response) = exceptions::handle_post(
+            &req, &self.reload
+        ) {
+            return response
+        }
+        if let Some(response) = refresh::handle_post(
+            &req, &self.refresh, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = integrity::handle_post(
+            &req, &self.integrity
+        ) {
+            return response
+        }
+        if validity::is_batch(&req) {
+            return validity::handle_post(
+                req, &self.history, self.validity_batch_limit
+            ).await
+        }
+        if check_object::is_check_object(&req) {
+            return check_object::handle_post(
+                req, &self.store, self.max_object_size
+            ).await
+        }
         if !req.is_get_or_head() {
             return Response::method_not_allowed()
         }
@@ -64,16 +157,32 @@ impl State {
         ) {
             return response
         }
+        if let Some(response) = self.archive.handle_get_or_head(
+            &req
+        ).await {
+            return response
+        }
+        if let Some(response) = self.config.handle_get_or_head(&req) {
+            return response
+        }
         if let Some(response) = self.log.handle_get_or_head(&req) {
             return response
         }
+        if let Some(response) = run_log::handle_get_or_head(
+            &req, self.journal.as_deref()
+        ) {
+            return response
+        }
         if let Some(response) = metrics::handle_get_or_head(
-            &req, &self.history, &self.metrics, &self.rtr_metrics
+            &req, &self.history, &self.metrics, &self.rtr_metrics,
+            self.metrics_detail,
         ).await {
             return response
         }
         if let Some(response) = status::handle_get_or_head(
-            &req, &self.history, &self.metrics, &self.rtr_metrics
+            &req, &self.history, &self.metrics, &self.rtr_metrics,
+            &self.tls_configs, self.journal.as_deref(),
+            self.base_path.as_str(),
         ).await {
             return response
         }
@@ -81,9 +190,48 @@ impl State {
             &req, &self.history) {
             return response
         }
+        if let Some(response) = origins::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = explorer::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = rrdp_history::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = store::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = manifests::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = unsafe_vrps::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = cert_info::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
+        if let Some(response) = health::handle_get_or_head(
+            &req, &self.history
+        ) {
+            return response
+        }
 
-        #[cfg(feature = "ui")]
-        if let Some(response) = super::ui::handle_get_or_head(&req) {
+        if let Some(response) = self.ui.handle_get_or_head(&req) {
             return response
         }
         
@@ -91,3 +239,50 @@ impl State {
     }
 }
 
+
+//------------ Role ----------------------------------------------------------
+
+/// The role of the listener a request arrived on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    /// The listener serves all endpoints.
+    Full,
+
+    /// The listener only serves the status and metrics endpoints.
+    Monitoring,
+}
+
+impl Role {
+    /// The paths served by monitoring listeners.
+    const MONITORING_PATHS: &'static [&'static str] = &[
+        "/api/v1/health", "/api/v1/status", "/log", "/metrics", "/status",
+        "/version",
+    ];
+
+    /// Returns whether a listener with this role serves the given path.
+    fn allows(self, path: &str) -> bool {
+        match self {
+            Role::Full => true,
+            Role::Monitoring => Self::MONITORING_PATHS.contains(&path),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn role_allows() {
+        assert!(Role::Full.allows("/json"));
+        assert!(Role::Full.allows("/metrics"));
+        assert!(Role::Monitoring.allows("/metrics"));
+        assert!(Role::Monitoring.allows("/api/v1/status"));
+        assert!(!Role::Monitoring.allows("/json"));
+        assert!(!Role::Monitoring.allows("/api/v1/validity"));
+        assert!(!Role::Monitoring.allows("/ui/"));
+    }
+}
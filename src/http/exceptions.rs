@@ -0,0 +1,27 @@
+//! Handling of endpoints related to local exceptions.
+
+use crate::slurm::ReloadRequest;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_post ---------------------------------------------------
+
+pub fn handle_post(
+    req: &Request,
+    reload: &ReloadRequest,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/reload-exceptions" {
+        return None
+    }
+    if !req.is_post() {
+        return Some(Response::method_not_allowed())
+    }
+    reload.request();
+    Some(
+        ResponseBuilder::accepted().content_type(ContentType::TEXT).body(
+            "Reloading local exceptions.\n"
+        )
+    )
+}
+
@@ -0,0 +1,271 @@
+//! Handling of the endpoints for exploring the current data set.
+//!
+//! `/api/v1/prefixes?prefix=<prefix>` returns all route origins whose
+//! prefix covers or is covered by the given prefix. `/api/v1/asns?asn=<asn>`
+//! returns all route origins, router keys, and ASPAs of the given AS. All
+//! items are returned with their sources.
+//!
+//! Responses contain at most [`MAX_ITEMS`] items. If there are more, the
+//! `truncated` member of the response is `true`.
+
+use std::str::FromStr;
+use rpki::resources::{Asn, Prefix};
+use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
+use crate::payload::{PayloadInfo, PayloadSnapshot, SharedHistory};
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::origins::source_json;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    let query = req.uri().query();
+    let body = match req.uri().path() {
+        "/api/v1/prefixes" => {
+            let prefix = match query_value(query, "prefix").and_then(|v| {
+                Prefix::from_str_relaxed(&v).ok()
+            }) {
+                Some(prefix) => prefix,
+                None => return Some(Response::bad_request()),
+            };
+            let snapshot = match history.read().current() {
+                Some(snapshot) => snapshot,
+                None => return Some(Response::initial_validation()),
+            };
+            prefixes_json(prefix, &snapshot)
+        }
+        "/api/v1/asns" => {
+            let asn = match query_value(query, "asn").and_then(|v| {
+                Asn::from_str(&v).ok()
+            }) {
+                Some(asn) => asn,
+                None => return Some(Response::bad_request()),
+            };
+            let snapshot = match history.read().current() {
+                Some(snapshot) => snapshot,
+                None => return Some(Response::initial_validation()),
+            };
+            asns_json(asn, &snapshot)
+        }
+        _ => return None
+    };
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(body))
+    }
+}
+
+/// Returns the value of the only member of a query.
+///
+/// Returns `None` if the query contains anything other than exactly one
+/// member with the given key.
+pub(super) fn query_value(query: Option<&str>, key: &str) -> Option<String> {
+    let mut res = None;
+    for (name, value) in form_urlencoded::parse(query?.as_bytes()) {
+        if name != key || res.is_some() {
+            return None
+        }
+        res = Some(value.into_owned());
+    }
+    res
+}
+
+
+//------------ prefixes_json -------------------------------------------------
+
+/// Returns the JSON for the route origins related to a prefix.
+///
+/// Route origins with less specific prefixes are listed first, followed by
+/// those for the prefix itself and more specific prefixes.
+fn prefixes_json(prefix: Prefix, snapshot: &PayloadSnapshot) -> String {
+    let mut limit = Limit::default();
+    let mut origins = limit.collect(
+        snapshot.covering_origins(prefix).filter(|(origin, _)| {
+            origin.prefix.prefix() != prefix
+        })
+    );
+    origins.reverse();
+    origins.extend(limit.collect(snapshot.covered_origins(prefix)));
+
+    JsonBuilder::build(|json| {
+        json.member_str(
+            "generatedTime", format_iso_date(snapshot.created())
+        );
+        json.member_str("prefix", prefix);
+        json.member_raw("truncated", limit.truncated);
+        json.member_array("origins", |json| {
+            for (origin, info) in &origins {
+                origin_json(origin, info, json)
+            }
+        });
+    })
+}
+
+
+//------------ asns_json -----------------------------------------------------
+
+/// Returns the JSON for all payload of an AS.
+fn asns_json(asn: Asn, snapshot: &PayloadSnapshot) -> String {
+    let mut limit = Limit::default();
+    let origins = limit.collect(
+        snapshot.origins().filter(|(origin, _)| origin.asn == asn)
+    );
+    let keys = limit.collect(
+        snapshot.router_keys().filter(|(key, _)| key.asn == asn)
+    );
+    let aspas = limit.collect(
+        snapshot.aspas().filter(|(aspa, _)| aspa.customer == asn)
+    );
+
+    JsonBuilder::build(|json| {
+        json.member_str(
+            "generatedTime", format_iso_date(snapshot.created())
+        );
+        json.member_str("asn", asn);
+        json.member_raw("truncated", limit.truncated);
+        json.member_array("origins", |json| {
+            for (origin, info) in &origins {
+                origin_json(origin, info, json)
+            }
+        });
+        json.member_array("routerKeys", |json| {
+            for (key, info) in &keys {
+                router_key_json(key, info, json)
+            }
+        });
+        json.member_array("aspas", |json| {
+            for (aspa, info) in &aspas {
+                aspa_json(aspa, info, json)
+            }
+        });
+    })
+}
+
+
+//------------ Limit ---------------------------------------------------------
+
+/// Keeps track of the number of items included in a response.
+#[derive(Clone, Copy, Debug)]
+struct Limit {
+    /// The number of items that can still be included.
+    remaining: usize,
+
+    /// Were there more items than could be included?
+    truncated: bool,
+}
+
+impl Default for Limit {
+    fn default() -> Self {
+        Limit { remaining: MAX_ITEMS, truncated: false }
+    }
+}
+
+impl Limit {
+    /// Collects as many items from the iterator as can still be included.
+    fn collect<T>(&mut self, iter: impl Iterator<Item = T>) -> Vec<T> {
+        let mut res = Vec::new();
+        for item in iter {
+            if self.remaining == 0 {
+                self.truncated = true;
+                break
+            }
+            self.remaining -= 1;
+            res.push(item);
+        }
+        res
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+fn origin_json(
+    origin: &RouteOrigin, info: &PayloadInfo, json: &mut JsonBuilder
+) {
+    json.array_object(|json| {
+        json.member_str("asn", origin.asn);
+        json.member_str("prefix", origin.prefix.prefix());
+        json.member_raw("maxLength", origin.prefix.resolved_max_len());
+        sources_json(info, json);
+    })
+}
+
+fn router_key_json(
+    key: &RouterKey, info: &PayloadInfo, json: &mut JsonBuilder
+) {
+    json.array_object(|json| {
+        json.member_str("asn", key.asn);
+        json.member_str("SKI", key.key_identifier);
+        json.member_str("routerPublicKey", &key.key_info);
+        sources_json(info, json);
+    })
+}
+
+fn aspa_json(aspa: &Aspa, info: &PayloadInfo, json: &mut JsonBuilder) {
+    json.array_object(|json| {
+        json.member_str("customer", aspa.customer);
+        json.member_array("providers", |json| {
+            for provider in aspa.providers.iter() {
+                json.array_str(provider)
+            }
+        });
+        sources_json(info, json);
+    })
+}
+
+fn sources_json(info: &PayloadInfo, json: &mut JsonBuilder) {
+    json.member_array("sources", |json| {
+        for item in info {
+            source_json(item, json)
+        }
+    });
+}
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum number of items in a response.
+const MAX_ITEMS: usize = 10_000;
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_value_parse() {
+        assert_eq!(
+            query_value(Some("prefix=193.0.0.0%2F16"), "prefix").as_deref(),
+            Some("193.0.0.0/16")
+        );
+        assert_eq!(query_value(None, "asn"), None);
+        assert_eq!(query_value(Some(""), "asn"), None);
+        assert_eq!(query_value(Some("prefix=10.0.0.0/8"), "asn"), None);
+        assert_eq!(query_value(Some("asn=1&asn=2"), "asn"), None);
+        assert_eq!(query_value(Some("asn=1&foo=2"), "asn"), None);
+    }
+
+    #[test]
+    fn limit_collect() {
+        let mut limit = Limit { remaining: 5, truncated: false };
+        assert_eq!(limit.collect(0..3), [0, 1, 2]);
+        assert!(!limit.truncated);
+        assert_eq!(limit.collect(0..2), [0, 1]);
+        assert!(!limit.truncated);
+        assert_eq!(limit.collect(0..0), [0; 0]);
+        assert!(!limit.truncated);
+        assert!(limit.collect(0..1).is_empty());
+        assert!(limit.truncated);
+    }
+}
@@ -0,0 +1,202 @@
+//! Handling of the health endpoint.
+//!
+//! The endpoint is intended for load balancers and similar tools. It only
+//! responds with a status code and a tiny JSON object explaining it.
+
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crate::payload::SharedHistory;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/health" {
+        return None
+    }
+
+    // Only hold the lock for collecting the data.
+    let health = {
+        let history = history.read();
+        Health::evaluate(
+            history.last_update_done(),
+            history.last_update_failed(),
+            history.current().map(|current| {
+                current.origin_refs().next().is_none()
+            }).unwrap_or(true),
+            history.metrics().and_then(|metrics| {
+                metrics.cache_usage.as_ref().map(|usage| usage.is_exceeded())
+            }).unwrap_or(false),
+            history.clock_skew().is_some(),
+            history.health_max_age(),
+            Utc::now(),
+        )
+    };
+
+    let res = match health.reason() {
+        None => ResponseBuilder::ok(),
+        Some(_) => ResponseBuilder::service_unavailable(),
+    }.content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(health.json()))
+    }
+}
+
+
+//------------ Health --------------------------------------------------------
+
+/// The health of the server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Health {
+    /// All is well.
+    Ok,
+
+    /// No validation run has finished yet.
+    NeverRan,
+
+    /// The last successful validation run is too long ago.
+    Stale,
+
+    /// The last validation run has failed.
+    LastRunFailed,
+
+    /// The current data set does not contain any VRPs.
+    EmptyDataset,
+
+    /// The cache exceeds its maximum size.
+    CacheFull,
+
+    /// The local clock appears to be off.
+    ClockSkew,
+}
+
+impl Health {
+    /// Determines the health from the state of the payload history.
+    fn evaluate(
+        done: Option<DateTime<Utc>>,
+        failed: bool,
+        empty: bool,
+        cache_full: bool,
+        clock_skew: bool,
+        max_age: Duration,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let done = match done {
+            Some(done) => done,
+            None => return Health::NeverRan,
+        };
+        if failed {
+            return Health::LastRunFailed
+        }
+        let age = now.signed_duration_since(done).to_std().unwrap_or(
+            Duration::from_secs(0)
+        );
+        if age > max_age {
+            return Health::Stale
+        }
+        if empty {
+            return Health::EmptyDataset
+        }
+        if cache_full {
+            return Health::CacheFull
+        }
+        if clock_skew {
+            return Health::ClockSkew
+        }
+        Health::Ok
+    }
+
+    /// Returns the reason for being unhealthy or `None` if all is well.
+    fn reason(self) -> Option<&'static str> {
+        match self {
+            Health::Ok => None,
+            Health::NeverRan => Some("never-ran"),
+            Health::Stale => Some("stale"),
+            Health::LastRunFailed => Some("last-run-failed"),
+            Health::EmptyDataset => Some("empty-dataset"),
+            Health::CacheFull => Some("cache-full"),
+            Health::ClockSkew => Some("clock-skew"),
+        }
+    }
+
+    /// Returns the JSON body for the health.
+    fn json(self) -> String {
+        JsonBuilder::build(|json| {
+            match self.reason() {
+                Some(reason) => {
+                    json.member_str("status", "unavailable");
+                    json.member_str("reason", reason);
+                }
+                None => json.member_str("status", "ok"),
+            }
+        })
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evaluate() {
+        let now = Utc::now();
+        let max_age = Duration::from_secs(1200);
+        let recent = now - chrono::Duration::seconds(600);
+        let old = now - chrono::Duration::seconds(1800);
+
+        assert_eq!(
+            Health::evaluate(
+                Some(recent), false, false, false, false, max_age, now
+            ),
+            Health::Ok
+        );
+        assert_eq!(
+            Health::evaluate(
+                None, false, true, true, false, max_age, now
+            ),
+            Health::NeverRan
+        );
+        assert_eq!(
+            Health::evaluate(
+                Some(old), false, false, true, false, max_age, now
+            ),
+            Health::Stale
+        );
+        assert_eq!(
+            Health::evaluate(
+                Some(recent), true, false, false, false, max_age, now
+            ),
+            Health::LastRunFailed
+        );
+        assert_eq!(
+            Health::evaluate(
+                Some(recent), false, true, true, false, max_age, now
+            ),
+            Health::EmptyDataset
+        );
+        assert_eq!(
+            Health::evaluate(
+                Some(recent), false, false, true, false, max_age, now
+            ),
+            Health::CacheFull
+        );
+        assert_eq!(
+            Health::evaluate(
+                Some(recent), false, false, false, true, max_age, now
+            ),
+            Health::ClockSkew
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Handling of the endpoint for requesting an archive integrity check.
+//!
+//! A POST to `/api/v1/integrity-check` asks the server to verify all RRDP
+//! archives right away rather than spread out over the configured
+//! interval. The check happens once no validation run is active.
+
+use crate::integrity::IntegrityCheckRequest;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_post ---------------------------------------------------
+
+pub fn handle_post(
+    req: &Request,
+    integrity: &IntegrityCheckRequest,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/integrity-check" {
+        return None
+    }
+    if !req.is_post() {
+        return Some(Response::method_not_allowed())
+    }
+    integrity.request();
+    Some(
+        ResponseBuilder::accepted().content_type(ContentType::TEXT).body(
+            "Starting archive integrity check.\n"
+        )
+    )
+}
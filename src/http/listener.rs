@@ -14,51 +14,85 @@ use log::error;
 use rpki::rtr::server::NotifySender;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpListener;
-use tokio_rustls::TlsAcceptor;
 use crate::config::Config;
 use crate::error::ExitError;
 use crate::metrics::{HttpServerMetrics, SharedRtrServerMetrics};
 use crate::payload::SharedHistory;
-use crate::process::LogOutput;
-use crate::utils::{net, tls};
-use crate::utils::tls::MaybeTlsTcpStream;
-use super::dispatch::State;
+use crate::integrity::IntegrityCheckRequest;
+use crate::process::{LogOutput, RunJournal};
+use crate::operation::RefreshRequest;
+use crate::slurm::ReloadRequest;
+use crate::utils::net;
+use crate::utils::net::StdUnixListener;
+use crate::utils::shutdown::Shutdown;
+use crate::utils::tls::{
+    MaybeTlsTcpStream, ReloadableServerConfig, TlsConfigs
+};
+use super::dispatch::{Role, State};
 
 
 //------------ http_listener -------------------------------------------------
 
 /// Returns a future for all HTTP server listeners.
+#[allow(clippy::too_many_arguments)]
 pub fn http_listener(
     origins: SharedHistory,
     rtr_metrics: SharedRtrServerMetrics,
     log: Option<Arc<LogOutput>>,
+    journal: Option<Arc<RunJournal>>,
     config: &Config,
     notify: NotifySender,
+    reload: ReloadRequest,
+    refresh: RefreshRequest,
+    integrity: IntegrityCheckRequest,
+    shutdown: Shutdown,
+    tls_configs: TlsConfigs,
 ) -> Result<impl Future<Output = ()>, ExitError> {
-    let state = Arc::new(
-        State::new(config, origins, rtr_metrics, log, notify)
-    );
+    let tls_config = if config.http_tls_listen.is_empty() {
+        None
+    }
+    else {
+        Some(create_tls_config(config, &tls_configs)?)
+    };
+    let state = Arc::new(State::new(
+        config, origins, rtr_metrics, log, journal, notify, reload,
+        refresh, integrity, tls_configs
+    )?);
 
     // Binding needs to have happened before dropping privileges
     // during detach. So we do this here synchronously.
     let mut listeners = Vec::new();
     for addr in &config.http_listen {
-        listeners.push((*addr, None, net::bind(addr)?));
+        for (addr, sock) in net::bind_listen_addr(addr, config)? {
+            listeners.push((addr, None, Role::Full, sock));
+        }
     }
-    if !config.http_tls_listen.is_empty() {
-        let tls_config = create_tls_config(config)?;
+    if let Some(tls_config) = tls_config {
         for addr in &config.http_tls_listen {
-            listeners.push(
-                (*addr, Some(tls_config.clone()), net::bind(addr)?)
-            );
+            for (addr, sock) in net::bind_listen_addr(addr, config)? {
+                listeners.push((
+                    addr, Some(tls_config.clone()), Role::Full, sock
+                ));
+            }
+        }
+    }
+    for addr in &config.http_monitoring_listen {
+        for (addr, sock) in net::bind_listen_addr(addr, config)? {
+            listeners.push((addr, None, Role::Monitoring, sock));
         }
     }
-    Ok(_http_listener(state, listeners))
+    let mut unix_listeners = Vec::new();
+    for path in &config.http_listen_unix {
+        unix_listeners.push((
+            format!("{}", path.display()), net::bind_unix(path)?
+        ));
+    }
+    Ok(_http_listener(state, listeners, unix_listeners, shutdown))
 }
 
 fn create_tls_config(
-    config: &Config
-) -> Result<Arc<tls::ServerConfig>, ExitError> {
+    config: &Config, tls_configs: &TlsConfigs,
+) -> Result<Arc<ReloadableServerConfig>, ExitError> {
     let key_path = match config.http_tls_key.as_ref() {
         Some(path) => path.as_ref(),
         None => {
@@ -73,39 +107,58 @@ fn create_tls_config(
             return Err(ExitError::Generic)
         }
     };
-    tls::create_server_config("HTTP", key_path, cert_path).map(Arc::new)
+    tls_configs.create("HTTP", key_path, cert_path)
 }
 
 async fn _http_listener(
     state: Arc<State>,
-    listeners: Vec<(SocketAddr, Option<Arc<tls::ServerConfig>>, StdListener)>,
+    listeners: Vec<(
+        SocketAddr, Option<Arc<ReloadableServerConfig>>, Role, StdListener
+    )>,
+    unix_listeners: Vec<(String, StdUnixListener)>,
+    shutdown: Shutdown,
 ) {
     // If there are no listeners, just never return.
-    if listeners.is_empty() {
+    if listeners.is_empty() && unix_listeners.is_empty() {
         pending::<()>().await;
         return;
     }
 
-    let _ = select_all(
-        listeners.into_iter().map(|(addr, tls_config, listener)| {
+    let tasks = listeners.into_iter().map(
+        |(addr, tls_config, role, listener)| {
             tokio::spawn(single_http_listener(
-                addr, tls_config, listener, state.clone(),
+                addr, tls_config, role, listener, state.clone(),
+                shutdown.clone(),
+            ))
+        }
+    );
+    #[cfg(unix)]
+    let tasks = tasks.chain(
+        unix_listeners.into_iter().map(|(path, listener)| {
+            tokio::spawn(single_http_unix_listener(
+                path, listener, state.clone(), shutdown.clone(),
             ))
         })
-    ).await;
+    );
+    #[cfg(not(unix))]
+    let _ = unix_listeners;
+    let _ = select_all(tasks).await;
 }
 
 /// Returns a future for a single HTTP listener.
 ///
 /// The future will never resolve unless an error happens that breaks the
-/// listener, in which case it will print an error and resolve the error case.
+/// listener, in which case it will print an error and resolve the error case,
+/// or shutdown is triggered, in which case it stops accepting connections.
 /// It will listen bind a Hyper server onto `addr` and produce any data
 /// served from `origins`.
 async fn single_http_listener(
     addr: SocketAddr,
-    tls_config: Option<Arc<tls::ServerConfig>>,
+    tls_config: Option<Arc<ReloadableServerConfig>>,
+    role: Role,
     listener: StdListener,
     state: Arc<State>,
+    mut shutdown: Shutdown,
 ) {
     let listener = HttpAccept {
         sock: match TcpListener::from_std(listener) {
@@ -115,61 +168,134 @@ async fn single_http_listener(
                 return
             }
         },
-        tls: tls_config.map(Into::into),
+        tls: tls_config,
         metrics: state.metrics().clone(),
     };
     loop {
-        let stream = match listener.accept().await {
-            Ok(some) => some,
-            Err(err) => {
-                error!("Fatal error in HTTP server {}: {}", addr, err);
-                break;
-            }
+        let stream = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(some) => some,
+                Err(err) => {
+                    error!("Fatal error in HTTP server {}: {}", addr, err);
+                    break;
+                }
+            },
+            _ = shutdown.triggered() => break,
         };
-        let service_state = state.clone();
-        tokio::task::spawn(async move {
-            let _ = hyper_util::server::conn::auto::Builder::new(
-                TokioExecutor::new()
-            ).serve_connection(
-                TokioIo::new(stream),
-                service_fn(move |req| {
-                    let state = service_state.clone();
-                    async move {
-                        state.handle_request(req.into()).await.into_hyper()
-                    }
-                })
-            ).await;
-        });
+        serve_connection(stream, role, state.clone(), shutdown.clone());
     }
 }
 
+/// Returns a future for a single HTTP listener on a Unix domain socket.
+///
+/// This works just like [`single_http_listener`] except that there is no
+/// TLS.
+#[cfg(unix)]
+async fn single_http_unix_listener(
+    path: String,
+    listener: StdUnixListener,
+    state: Arc<State>,
+    mut shutdown: Shutdown,
+) {
+    let listener = match tokio::net::UnixListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed on listening on {}: {}", path, err);
+            return
+        }
+    };
+    loop {
+        let sock = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((sock, _)) => sock,
+                Err(err) => {
+                    error!("Fatal error in HTTP server {}: {}", path, err);
+                    break;
+                }
+            },
+            _ = shutdown.triggered() => break,
+        };
+        serve_connection(
+            HttpStream::new(sock, state.metrics().clone()), Role::Full,
+            state.clone(), shutdown.clone(),
+        );
+    }
+}
+
+/// Spawns a task serving HTTP requests on a connection.
+///
+/// The `role` of the listener the connection was accepted on determines
+/// which endpoints are available on it.
+///
+/// Once shutdown is triggered, the connection is shut down gracefully,
+/// i.e., a response currently in progress is still completed. Until then
+/// the connection holds a drain guard.
+fn serve_connection<Sock>(
+    stream: HttpStream<Sock>, role: Role, state: Arc<State>,
+    mut shutdown: Shutdown,
+)
+where Sock: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    tokio::task::spawn(async move {
+        let _guard = shutdown.guard();
+        let builder = hyper_util::server::conn::auto::Builder::new(
+            TokioExecutor::new()
+        );
+        let conn = builder.serve_connection(
+            TokioIo::new(stream),
+            service_fn(move |req| {
+                let state = state.clone();
+                async move {
+                    state.handle_request(
+                        req.into(), role
+                    ).await.into_hyper()
+                }
+            })
+        );
+        pin_mut!(conn);
+        tokio::select! {
+            _ = conn.as_mut() => return,
+            _ = shutdown.triggered() => { }
+        }
+        conn.as_mut().graceful_shutdown();
+        let _ = conn.await;
+    });
+}
+
 
 //------------ Wrapped sockets for metrics -----------------------------------
 
 struct HttpAccept {
     sock: TcpListener,
-    tls: Option<TlsAcceptor>,
+    tls: Option<Arc<ReloadableServerConfig>>,
     metrics: Arc<HttpServerMetrics>,
 }
 
 impl HttpAccept {
     async fn accept(&self) -> Result<HttpStream, io::Error> {
         let (sock, _) = self.sock.accept().await?;
-        self.metrics.inc_conn_open();
-        Ok(HttpStream {
-            sock: MaybeTlsTcpStream::new(sock, self.tls.as_ref()),
-            metrics: self.metrics.clone()
-        })
+        // Get a fresh acceptor so we pick up reloaded certificates.
+        let tls = self.tls.as_ref().map(|tls| tls.acceptor());
+        Ok(HttpStream::new(
+            MaybeTlsTcpStream::new(sock, tls.as_ref()),
+            self.metrics.clone()
+        ))
     }
 }
 
 
-struct HttpStream {
-    sock: MaybeTlsTcpStream,
+struct HttpStream<Sock = MaybeTlsTcpStream> {
+    sock: Sock,
     metrics: Arc<HttpServerMetrics>,
 }
 
-impl AsyncRead for HttpStream {
+impl<Sock> HttpStream<Sock> {
+    fn new(sock: Sock, metrics: Arc<HttpServerMetrics>) -> Self {
+        metrics.inc_conn_open();
+        HttpStream { sock, metrics }
+    }
+}
+
+impl<Sock: AsyncRead + Unpin> AsyncRead for HttpStream<Sock> {
     fn poll_read(
         mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf
     ) -> Poll<Result<(), io::Error>> {
@@ -186,7 +312,7 @@ impl AsyncRead for HttpStream {
     }
 }
 
-impl AsyncWrite for HttpStream {
+impl<Sock: AsyncWrite + Unpin> AsyncWrite for HttpStream<Sock> {
     fn poll_write(
         mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]
     ) -> Poll<Result<usize, io::Error>> {
@@ -216,9 +342,125 @@ impl AsyncWrite for HttpStream {
     }
 }
 
-impl Drop for HttpStream {
+impl<Sock> Drop for HttpStream<Sock> {
     fn drop(&mut self) {
         self.metrics.inc_conn_close()
     }
 }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::rustls::crypto::ring::default_provider;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use crate::utils::shutdown::ShutdownTrigger;
+
+    /// Requests `path` over `sock` and returns the response status code.
+    async fn get_status(
+        mut sock: impl AsyncRead + AsyncWrite + Unpin,
+        path: &str, token: Option<&str>,
+    ) -> u16 {
+        let mut req = format!(
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n"
+        );
+        if let Some(token) = token {
+            req.push_str(&format!("Authorization: Bearer {token}\r\n"));
+        }
+        req.push_str("\r\n");
+        sock.write_all(req.as_bytes()).await.unwrap();
+        let mut res = Vec::new();
+        while res.len() < 12 {
+            let mut buf = [0u8; 64];
+            let len = sock.read(&mut buf).await.unwrap();
+            assert!(len > 0);
+            res.extend_from_slice(&buf[..len]);
+        }
+        std::str::from_utf8(&res[9..12]).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn auth_plain_and_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_file = dir.path().join("tokens");
+        std::fs::write(&token_file, "secret\n").unwrap();
+        let mut config = Config::default_with_paths(
+            Default::default(), dir.path().into()
+        );
+        config.http_auth_token_file = Some(token_file);
+
+        let tls_configs = TlsConfigs::default();
+        let tls = tls_configs.create(
+            "HTTP", "test/pin/leaf.key".as_ref(),
+            "test/pin/leaf.pem".as_ref(),
+        ).unwrap();
+        let state = Arc::new(State::new(
+            &config, SharedHistory::from_config(&config),
+            SharedRtrServerMetrics::new(false, 0), None, None,
+            NotifySender::new(), Default::default(), Default::default(),
+            Default::default(), tls_configs,
+        ).unwrap());
+
+        let plain = StdListener::bind("127.0.0.1:0").unwrap();
+        plain.set_nonblocking(true).unwrap();
+        let plain_addr = plain.local_addr().unwrap();
+        let secure = StdListener::bind("127.0.0.1:0").unwrap();
+        secure.set_nonblocking(true).unwrap();
+        let secure_addr = secure.local_addr().unwrap();
+
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(
+            &mut include_bytes!("../../test/pin/ca.pem").as_ref()
+        ) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(
+            ClientConfig::builder_with_provider(Arc::new(default_provider()))
+                .with_safe_default_protocol_versions().unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth()
+        ));
+
+        let (trigger, shutdown) = ShutdownTrigger::new();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all().build().unwrap();
+        runtime.block_on(async {
+            tokio::spawn(_http_listener(
+                state,
+                vec![
+                    (plain_addr, None, Role::Full, plain),
+                    (secure_addr, Some(tls), Role::Full, secure),
+                ],
+                Vec::new(), shutdown,
+            ));
+
+            for token in [None, Some("wrong"), Some("secret")] {
+                let sock = tokio::net::TcpStream::connect(
+                    plain_addr
+                ).await.unwrap();
+                let plain_status = get_status(sock, "/json", token).await;
+                let sock = tokio::net::TcpStream::connect(
+                    secure_addr
+                ).await.unwrap();
+                let sock = connector.connect(
+                    ServerName::try_from("rrdp.example.net").unwrap(), sock
+                ).await.unwrap();
+                let tls_status = get_status(sock, "/json", token).await;
+                assert_eq!(plain_status, tls_status);
+                assert_eq!(plain_status == 401, token != Some("secret"));
+            }
+
+            // Public paths never need a token.
+            let sock = tokio::net::TcpStream::connect(
+                plain_addr
+            ).await.unwrap();
+            assert_ne!(get_status(sock, "/version", None).await, 401);
+        });
+        drop(trigger);
+    }
+}
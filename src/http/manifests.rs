@@ -0,0 +1,76 @@
+//! Handling of the endpoint listing the manifests of publication points.
+
+use crate::metrics::Metrics;
+use crate::payload::SharedHistory;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+use super::store::StoreQuery;
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/manifests" {
+        return None
+    }
+    let query = match StoreQuery::from_query(req.uri().query()) {
+        Some(query) => query,
+        None => return Some(Response::bad_request()),
+    };
+    let metrics = match history.read().metrics() {
+        Some(metrics) => metrics,
+        None => return Some(Response::initial_validation()),
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(manifests_json(&query, &metrics)))
+    }
+}
+
+
+//------------ manifests_json ------------------------------------------------
+
+fn manifests_json(query: &StoreQuery, metrics: &Metrics) -> String {
+    let total = metrics.stored_points.iter().filter(|point| {
+        query.matches(point)
+    }).count();
+    let points = metrics.stored_points.iter().filter(|point| {
+        query.matches(point)
+    }).skip(query.offset).take(query.limit.unwrap_or(usize::MAX));
+
+    JsonBuilder::build(|json| {
+        json.member_str("generatedTime", format_iso_date(metrics.time));
+        json.member_raw("total", total);
+        json.member_raw("offset", query.offset);
+        json.member_array("manifests", |json| {
+            for point in points {
+                json.array_object(|json| {
+                    json.member_str("uri", &point.manifest_uri);
+                    json.member_str("ta", point.tal.name());
+                    json.member_str(
+                        "manifestNumber", point.manifest_number
+                    );
+                    json.member_str(
+                        "thisUpdate",
+                        format_iso_date(point.this_update.into())
+                    );
+                    json.member_str(
+                        "nextUpdate",
+                        format_iso_date(point.next_update.into())
+                    );
+                    json.member_raw("fileCount", point.file_count);
+                })
+            }
+        });
+    })
+}
+
@@ -2,14 +2,21 @@
 
 use std::{cmp, fmt};
 use std::fmt::Write;
-use chrono::Utc;
-use crate::config::FilterPolicy;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rpki::rtr::Serial;
+use crate::config::{FilterPolicy, MetricsDetail};
 use crate::metrics::{
-    HttpServerMetrics, Metrics, PayloadMetrics, PublicationMetrics,
-    RrdpRepositoryMetrics, RsyncModuleMetrics, SharedRtrServerMetrics,
-    VrpMetrics
+    CacheUsageMetrics, ChurnTotals, HttpServerMetrics, Metrics,
+    PayloadMetrics,
+    IntegrityMetrics, PublicationMetrics, PublishMetrics, RrdpDnsMetrics,
+    RrdpDurationHistograms, RrdpRepositoryMetrics, RrdpUpdateTotals,
+    RsyncModuleMetrics,
+    SharedRtrServerMetrics, VrpMetrics
 };
-use crate::payload::SharedHistory;
+use crate::payload::{PayloadHistory, SharedHistory};
 use super::request::Request;
 use super::response::{ContentType, Response, ResponseBuilder};
 
@@ -21,10 +28,11 @@ pub async fn handle_get_or_head(
     history: &SharedHistory,
     http: &HttpServerMetrics,
     rtr: &SharedRtrServerMetrics,
+    detail: MetricsDetail,
 ) -> Option<Response> {
     match req.uri().path() {
         "/metrics" => {
-            Some(handle_metrics(req.is_head(), history, http, rtr).await)
+            Some(handle_metrics(req, history, http, rtr, detail).await)
         }
         _ => None
     }
@@ -34,146 +42,428 @@ pub async fn handle_get_or_head(
 //------------ handle_metrics ------------------------------------------------
 
 async fn handle_metrics(
-    head: bool,
+    req: &Request,
     history: &SharedHistory,
     http: &HttpServerMetrics,
     rtr: &SharedRtrServerMetrics,
+    detail: MetricsDetail,
 ) -> Response {
-    let (metrics, serial, start, done, duration, unsafe_vrps) = {
-        let history = history.read();
-        (
-            match history.metrics() {
-                Some(metrics) => metrics,
-                None => return Response::initial_validation(),
-            },
-            history.serial(),
-            history.last_update_start(),
-            history.last_update_done(),
-            history.last_update_duration(),
-            history.unsafe_vrps(),
-        )
+    let detail = match detail_from_query(req.uri().query(), detail) {
+        Some(detail) => detail,
+        None => return Response::bad_request(),
+    };
+    let data = match MetricsData::from_history(&history.read()) {
+        Some(data) => data,
+        None => return Response::initial_validation(),
     };
 
-    if head {
+    if req.is_head() {
         return ResponseBuilder::ok()
             .content_type(ContentType::PROMETHEUS)
             .empty()
     }
 
     let mut target = Target::default();
+    data.write(&mut target, detail, http, rtr).await;
+    target.into_response(req)
+}
 
-    // Update times.
-    let now = Utc::now();
-    target.single(
-        Metric::new(
-            "last_update_start",
-            "seconds since the start of the last update",
-            MetricType::Gauge,
-        ),
-        now.signed_duration_since(start).num_seconds()   
-    );
+/// Returns the level of detail requested via the query.
+///
+/// The `detail` query parameter overrides the configured level `default`
+/// for ad-hoc debugging. Returns `None` if the query is invalid.
+fn detail_from_query(
+    query: Option<&str>, default: MetricsDetail,
+) -> Option<MetricsDetail> {
+    let query = match query {
+        Some(query) => query,
+        None => return Some(default)
+    };
+    let mut detail = None;
+    for (key, value) in form_urlencoded::parse(query.as_ref()) {
+        if key == "detail" && detail.is_none() {
+            detail = Some(MetricsDetail::from_str(&value).ok()?);
+        }
+        else {
+            return None
+        }
+    }
+    Some(detail.unwrap_or(default))
+}
 
-    let metric = Metric::new(
-        "last_update_duration",
-        "duration of the last update in seconds",
-        MetricType::Gauge,
-    );
-    match duration {
-        Some(duration) => target.single(metric, duration.as_secs()),
-        None => target.single(metric, "NaN")
+
+//------------ MetricsData ---------------------------------------------------
+
+/// The data exposed via the metrics endpoint.
+///
+/// This is taken from the payload history in one go so the lock doesn’t
+/// need to be held while creating the output.
+struct MetricsData {
+    metrics: Arc<Metrics>,
+    rrdp_durations: Arc<RrdpDurationHistograms>,
+    rrdp_updates: Arc<RrdpUpdateTotals>,
+    churn: Arc<ChurnTotals>,
+    serial: Serial,
+    start: DateTime<Utc>,
+    done: Option<DateTime<Utc>>,
+    duration: Option<Duration>,
+    timed_out: bool,
+    clock_skew: Option<i64>,
+    unsafe_vrps: FilterPolicy,
+    vrp_guard_active: bool,
+    vrp_guard_held_runs: usize,
+    publish: Vec<PublishMetrics>,
+    integrity: IntegrityMetrics,
+}
+
+impl MetricsData {
+    /// Collects the data from the history.
+    ///
+    /// Returns `None` if there hasn’t been a validation run yet.
+    fn from_history(history: &PayloadHistory) -> Option<Self> {
+        Some(MetricsData {
+            metrics: history.metrics()?,
+            rrdp_durations: history.rrdp_durations(),
+            rrdp_updates: history.rrdp_updates(),
+            churn: history.churn_totals(),
+            serial: history.serial(),
+            start: history.last_update_start(),
+            done: history.last_update_done(),
+            duration: history.last_update_duration(),
+            timed_out: history.last_update_timed_out(),
+            clock_skew: history.clock_skew(),
+            unsafe_vrps: history.unsafe_vrps(),
+            vrp_guard_active: history.vrp_guard().is_active(),
+            vrp_guard_held_runs: history.vrp_guard().held_runs(),
+            publish: history.publish_metrics().to_vec(),
+            integrity: history.integrity_metrics().clone(),
+        })
     }
 
-    let metric = Metric::new(
-        "last_update_done",
-        "seconds since the end of the last update",
-        MetricType::Gauge,
-    );
-    match done {
-        Some(instant) => {
-            target.single(
-                metric, 
-                now.signed_duration_since(instant).num_seconds()
-            )
+    /// Writes the metrics for the given level of detail.
+    async fn write(
+        &self,
+        target: &mut Target,
+        detail: MetricsDetail,
+        http: &HttpServerMetrics,
+        rtr: &SharedRtrServerMetrics,
+    ) {
+        let MetricsData {
+            metrics, rrdp_durations, rrdp_updates, churn, serial, start,
+            done, duration, timed_out, clock_skew, unsafe_vrps,
+            vrp_guard_active, vrp_guard_held_runs, publish, integrity,
+        } = self;
+        let unsafe_vrps = *unsafe_vrps;
+
+        // Update times.
+        let now = Utc::now();
+        target.single(
+            Metric::new(
+                "last_update_start",
+                "seconds since the start of the last update",
+                MetricType::Gauge,
+            ),
+            now.signed_duration_since(*start).num_seconds()
+        );
+
+        let metric = Metric::new(
+            "last_update_duration",
+            "duration of the last update in seconds",
+            MetricType::Gauge,
+        );
+        match duration {
+            Some(duration) => target.single(metric, duration.as_secs()),
+            None => target.single(metric, "NaN")
         }
-        None => target.single(metric, "NaN")
-    }
 
-    // Serial number.
-    target.single(
-        Metric::new(
-            "serial", "current RTR serial number", MetricType::Gauge
-        ),
-        serial
-    );
+        let metric = Metric::new(
+            "last_update_done",
+            "seconds since the end of the last update",
+            MetricType::Gauge,
+        );
+        match done {
+            Some(instant) => {
+                target.single(
+                    metric, 
+                    now.signed_duration_since(*instant).num_seconds()
+                )
+            }
+            None => target.single(metric, "NaN")
+        }
 
-    // Per-TA metrics.
-    pub_point_metrics(
-        &mut target, Group::Ta,
-        metrics.tals.iter().map(|m| (m.tal.name(), &m.publication))
-    );
-    object_metrics(
-        &mut target, Group::Ta,
-        metrics.tals.iter().map(|m| (m.tal.name(), &m.publication))
-    );
-    vrp_metrics(
-        &mut target, Group::Ta, unsafe_vrps,
-        metrics.tals.iter().map(|m| (m.tal.name(), m.payload.vrps()))
-    );
-    payload_metrics(
-        &mut target, Group::Ta, unsafe_vrps,
-        metrics.tals.iter().map(|m| (m.tal.name(), &m.payload))
-    );
+        target.single(
+            Metric::new(
+                "last_update_timed_out",
+                "whether the last update exceeded the maximum run duration",
+                MetricType::Gauge,
+            ),
+            u8::from(*timed_out)
+        );
 
-    // Per-repository metrics.
-    pub_point_metrics(
-        &mut target, Group::Repository,
-        metrics.repositories.iter().map(|m| (m.uri.as_ref(), &m.publication))
-    );
-    object_metrics(
-        &mut target, Group::Repository,
-        metrics.repositories.iter().map(|m| (m.uri.as_ref(), &m.publication))
-    );
-    vrp_metrics(
-        &mut target, Group::Repository, unsafe_vrps,
-        metrics.repositories.iter().map(|m| (m.uri.as_ref(), m.payload.vrps()))
-    );
-    payload_metrics(
-        &mut target, Group::Repository, unsafe_vrps,
-        metrics.repositories.iter().map(|m| (m.uri.as_ref(), &m.payload))
-    );
+        // Clock skew.
+        target.single(
+            Metric::new(
+                "clock_skew_detected",
+                "whether the local clock appears to be off",
+                MetricType::Gauge,
+            ),
+            u8::from(clock_skew.is_some())
+        );
+        let metric = Metric::new(
+            "clock_skew_seconds",
+            "local time minus the time of the RRDP servers",
+            MetricType::Gauge,
+        );
+        match clock_skew {
+            Some(skew) => target.single(metric, skew),
+            None => target.single(metric, "NaN")
+        }
 
-    // Locally added VRPs
-    target.single(
-        Metric::new(
-            "vrps_added_locally",
-            "VRPs added from local exceptions",
-            MetricType::Gauge
-        ),
-        metrics.local.vrps().contributed
-    );
+        // Serial number.
+        target.single(
+            Metric::new(
+                "serial", "current RTR serial number", MetricType::Gauge
+            ),
+            serial
+        );
 
-    // Large ASPAs
-    target.single(
-        Metric::new(
-            "aspa_large_provider_set",
-            "ASPAs customer ASNs rejected due to large provider set",
-            MetricType::Gauge
-        ),
-        metrics.snapshot.large_aspas
-    );
+        // VRP guard.
+        target.single(
+            Metric::new(
+                "vrp_guard_active",
+                "whether a suspiciously small data set is being held back",
+                MetricType::Gauge,
+            ),
+            u8::from(*vrp_guard_active)
+        );
+        target.single(
+            Metric::new(
+                "vrp_guard_held_runs",
+                "number of consecutive runs whose data set was held back",
+                MetricType::Gauge,
+            ),
+            vrp_guard_held_runs
+        );
 
-    // Collector metrics.
-    rrdp_metrics(&mut target, &metrics.rrdp);
-    rsync_metrics(&mut target, &metrics.rsync);
+        // Per-TA metrics.
+        pub_point_metrics(
+            target, Group::Ta,
+            metrics.tals.iter().map(|m| (m.tal.name(), &m.publication))
+        );
+        object_metrics(
+            target, Group::Ta,
+            metrics.tals.iter().map(|m| (m.tal.name(), &m.publication))
+        );
+        object_type_metrics(
+            target, Group::Ta,
+            metrics.tals.iter().map(|m| (m.tal.name(), &m.publication))
+        );
+        vrp_metrics(
+            target, Group::Ta, unsafe_vrps,
+            metrics.tals.iter().map(|m| (m.tal.name(), m.payload.vrps()))
+        );
+        payload_metrics(
+            target, Group::Ta, unsafe_vrps,
+            metrics.tals.iter().map(|m| (m.tal.name(), &m.payload))
+        );
+        let metric = Metric::new(
+            "ta_update_skipped",
+            "whether updating the trust anchor was skipped in the last run",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            target.multi(metric).label("name", tal.tal.name())
+                .value(u8::from(tal.update_skipped));
+        }
+        let metric = Metric::new(
+            "ta_uri",
+            "the URI of the trust anchor certificate used in the last run",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            if let Some(uri) = tal.ta_uri.as_ref() {
+                target.multi(metric).label("name", tal.tal.name())
+                    .label("uri", uri)
+                    .value(1);
+            }
+        }
+        let metric = Metric::new(
+            "ta_stored",
+            "whether a stored trust anchor certificate was used",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            target.multi(metric).label("name", tal.tal.name())
+                .value(u8::from(tal.ta_stored));
+        }
+        let metric = Metric::new(
+            "ta_key_mismatch",
+            "whether a downloaded trust anchor certificate had a key not \
+             matching the TAL",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            target.multi(metric).label("name", tal.tal.name())
+                .value(u8::from(tal.ta_key_mismatch));
+        }
+        let metric = Metric::new(
+            "ta_last_update",
+            "time of the last successful update of the trust anchor as a \
+             Unix timestamp",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            if let Some(time) = tal.updated {
+                target.multi(metric).label("name", tal.tal.name())
+                    .value(time.timestamp());
+            }
+        }
+        let metric = Metric::new(
+            "ta_min_cert_expiry",
+            "earliest expiry of valid CA certificates as a Unix timestamp",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            if let Some(time) = tal.publication.min_ca_cert_expiry {
+                target.multi(metric).label("name", tal.tal.name())
+                    .value(time.timestamp());
+            }
+        }
+        let metric = Metric::new(
+            "ta_min_next_update",
+            "earliest next update of valid manifests and CRLs as a Unix \
+             timestamp",
+            MetricType::Gauge,
+        );
+        target.header(metric);
+        for tal in &metrics.tals {
+            if let Some(time) = tal.publication.min_next_update {
+                target.multi(metric).label("name", tal.tal.name())
+                    .value(time.timestamp());
+            }
+        }
 
-    // Server metrics.
-    rtr_metrics(&mut target, rtr).await;
-    http_metrics(&mut target, http);
+        // Per-repository metrics.
+        if detail >= MetricsDetail::Standard {
+            pub_point_metrics(
+                target, Group::Repository,
+                metrics.repositories.iter().map(|m| {
+                    (m.uri.as_ref(), &m.publication)
+                })
+            );
+            object_metrics(
+                target, Group::Repository,
+                metrics.repositories.iter().map(|m| {
+                    (m.uri.as_ref(), &m.publication)
+                })
+            );
+            object_type_metrics(
+                target, Group::Repository,
+                metrics.repositories.iter().map(|m| {
+                    (m.uri.as_ref(), &m.publication)
+                })
+            );
+            vrp_metrics(
+                target, Group::Repository, unsafe_vrps,
+                metrics.repositories.iter().map(|m| {
+                    (m.uri.as_ref(), m.payload.vrps())
+                })
+            );
+            payload_metrics(
+                target, Group::Repository, unsafe_vrps,
+                metrics.repositories.iter().map(|m| {
+                    (m.uri.as_ref(), &m.payload)
+                })
+            );
+        }
 
-    //  Deprecated metrics.
-    deprecated_metrics(&mut target, &metrics, unsafe_vrps);
+        // Locally added VRPs
+        target.single(
+            Metric::new(
+                "vrps_added_locally",
+                "VRPs added from local exceptions",
+                MetricType::Gauge
+            ),
+            metrics.local.vrps().contributed
+        );
+
+        // Large ASPAs
+        target.single(
+            Metric::new(
+                "aspa_large_provider_set",
+                "ASPAs customer ASNs rejected due to large provider set",
+                MetricType::Gauge
+            ),
+            metrics.snapshot.large_aspas
+        );
+
+        // Repositories exceeding limits.
+        target.single(
+            Metric::new(
+                "repositories_limit_exceeded",
+                "repositories exceeding the object count or size limit",
+                MetricType::Gauge
+            ),
+            metrics.limit_exceeded_repositories().count()
+        );
+        target.single(
+            Metric::new(
+                "manifest_replays",
+                "manifests with a thisUpdate not later than the stored one",
+                MetricType::Gauge
+            ),
+            metrics.manifest_replays.len()
+        );
+
+        // Slowest repository hosts.
+        if detail >= MetricsDetail::Standard {
+            repository_host_metrics(target, metrics);
+        }
 
-    target.into_response()
+        // Route origin churn.
+        churn_metrics(target, metrics, churn);
+
+        // Collector metrics.
+        if detail >= MetricsDetail::Standard {
+            rrdp_metrics(target, &metrics.rrdp);
+            rrdp_duration_histograms(target, rrdp_durations);
+        }
+        rrdp_update_totals(target, rrdp_updates);
+        rrdp_dns_metrics(target, &metrics.rrdp_dns);
+        if detail >= MetricsDetail::Full {
+            rsync_metrics(target, &metrics.rsync);
+        }
+        target.single(
+            Metric::new(
+                "rsync_peak_processes",
+                "largest number of concurrently running rsync processes",
+                MetricType::Gauge
+            ),
+            metrics.rsync_peak_processes
+        );
+        if let Some(usage) = metrics.cache_usage.as_ref() {
+            cache_usage_metrics(target, usage, detail);
+        }
+
+        // Publish metrics.
+        publish_metrics(target, publish);
+
+        // Archive integrity metrics.
+        integrity_metrics(target, integrity);
+
+        // Server metrics.
+        rtr_metrics(target, rtr, detail).await;
+        http_metrics(target, http);
+
+        //  Deprecated metrics.
+        deprecated_metrics(target, metrics, unsafe_vrps);
+    }
 }
 
 fn pub_point_metrics<'a>(
@@ -223,6 +513,10 @@ fn object_metrics<'a>(
             .label("type", "manifest")
             .label("state", "stale")
             .value(metrics.stale_manifests);
+        target.multi(metric).label(group.label(), name)
+            .label("type", "manifest")
+            .label("state", "stale_grace")
+            .value(metrics.stale_grace_manifests);
         target.multi(metric).label(group.label(), name)
             .label("type", "manifest")
             .label("state", "missing")
@@ -240,6 +534,10 @@ fn object_metrics<'a>(
             .label("type", "crl")
             .label("state", "stale")
             .value(metrics.stale_crls);
+        target.multi(metric).label(group.label(), name)
+            .label("type", "crl")
+            .label("state", "stale_grace")
+            .value(metrics.stale_grace_crls);
         target.multi(metric).label(group.label(), name)
             .label("type", "crl")
             .label("state", "stray")
@@ -292,6 +590,30 @@ fn object_metrics<'a>(
     }
 }
 
+fn object_type_metrics<'a>(
+    target: &mut Target, group: Group,
+    metrics: impl Iterator<Item = (&'a str, &'a PublicationMetrics)>
+) {
+    let metric = Metric::with_prefix(
+        group.prefix(), "object_types_total",
+        ("accepted and rejected objects by type per ", group.help()),
+        MetricType::Gauge
+    );
+    target.header(metric);
+    for (name, metrics) in metrics {
+        for (object_type, counts) in metrics.object_type_counts() {
+            target.multi(metric).label(group.label(), name)
+                .label("type", object_type)
+                .label("state", "accepted")
+                .value(counts.accepted);
+            target.multi(metric).label(group.label(), name)
+                .label("type", object_type)
+                .label("state", "rejected")
+                .value(counts.rejected);
+        }
+    }
+}
+
 fn vrp_metrics<'a>(
     target: &mut Target, group: Group, unsafe_vrps: FilterPolicy,
     metrics: impl Iterator<Item = (&'a str, &'a VrpMetrics)>
@@ -309,6 +631,11 @@ fn vrp_metrics<'a>(
         ),
         MetricType::Gauge
     );
+    let outside_metric = Metric::with_prefix(
+        group.prefix(), "outside_resources_vrps_total",
+        ("number of VRPs outside the accepted resources per ", group.help()),
+        MetricType::Gauge
+    );
     let filtered_metric = Metric::with_prefix(
         group.prefix(), "locally_filtered_vrps_total",
         ("number of VRPs filtered out by local exceptions per ", group.help()),
@@ -329,6 +656,7 @@ fn vrp_metrics<'a>(
     if unsafe_vrps.log() {
         target.header(unsafe_metric);
     }
+    target.header(outside_metric);
     target.header(filtered_metric);
     target.header(duplicate_metric);
     target.header(contributed_metric);
@@ -339,6 +667,8 @@ fn vrp_metrics<'a>(
             target.multi(unsafe_metric).label(group.label(), name)
                 .value(metrics.marked_unsafe);
         }
+        target.multi(outside_metric).label(group.label(), name)
+            .value(metrics.outside_resources);
         target.multi(filtered_metric).label(group.label(), name)
             .value(metrics.locally_filtered);
         target.multi(duplicate_metric).label(group.label(), name)
@@ -365,6 +695,14 @@ fn payload_metrics<'a>(
         ),
         MetricType::Gauge
     );
+    let outside_metric = Metric::with_prefix(
+        group.prefix(), "outside_resources_payload_total",
+        (
+            "number of payload items outside the accepted resources per ",
+            group.help()
+        ),
+        MetricType::Gauge
+    );
     let filtered_metric = Metric::with_prefix(
         group.prefix(), "locally_filtered_payload_total",
         (
@@ -391,6 +729,7 @@ fn payload_metrics<'a>(
     if unsafe_vrps.log() {
         target.header(unsafe_metric);
     }
+    target.header(outside_metric);
     target.header(filtered_metric);
     target.header(duplicate_metric);
     target.header(contributed_metric);
@@ -413,6 +752,10 @@ fn payload_metrics<'a>(
                     .label("type", type_name)
                     .value(metrics.marked_unsafe);
             }
+            target.multi(outside_metric)
+                .label(group.label(), name)
+                .label("type", type_name)
+                .value(metrics.outside_resources);
             target.multi(filtered_metric)
                 .label(group.label(), name)
                 .label("type", type_name)
@@ -431,6 +774,10 @@ fn payload_metrics<'a>(
             .label(group.label(), name)
             .label("type", "aspas")
             .value(metrics.aspas.valid);
+        target.multi(outside_metric)
+            .label(group.label(), name)
+            .label("type", "aspas")
+            .value(metrics.aspas.outside_resources);
         target.multi(duplicate_metric)
             .label(group.label(), name)
             .label("type", "aspas")
@@ -442,6 +789,72 @@ fn payload_metrics<'a>(
     }
 }
 
+fn repository_host_metrics(target: &mut Target, metrics: &Metrics) {
+    let fetch = Metric::new(
+        "repository_host_fetch_duration",
+        "time spent waiting for updates of the slowest repository hosts \
+         in seconds",
+        MetricType::Gauge
+    );
+    target.header(fetch);
+    let validation = Metric::new(
+        "repository_host_validation_duration",
+        "time spent validating data of the slowest repository hosts \
+         in seconds",
+        MetricType::Gauge
+    );
+    target.header(validation);
+
+    for (host, fetch_duration, validation_duration) in
+        metrics.slowest_repository_hosts()
+    {
+        target.multi(fetch).label("host", host).value(
+            format_args!("{:.3}", fetch_duration.as_secs_f64())
+        );
+        target.multi(validation).label("host", host).value(
+            format_args!("{:.3}", validation_duration.as_secs_f64())
+        );
+    }
+}
+
+fn churn_metrics(
+    target: &mut Target, metrics: &Metrics, churn: &ChurnTotals
+) {
+    let added = Metric::new(
+        "origins_added",
+        "total number of route origins added by data set updates",
+        MetricType::Counter
+    );
+    let removed = Metric::new(
+        "origins_removed",
+        "total number of route origins removed by data set updates",
+        MetricType::Counter
+    );
+    target.single(added, churn.all().added);
+    target.single(removed, churn.all().removed);
+
+    let added = Metric::new(
+        "ta_origins_added",
+        "total number of route origins added per trust anchor",
+        MetricType::Counter
+    );
+    let removed = Metric::new(
+        "ta_origins_removed",
+        "total number of route origins removed per trust anchor",
+        MetricType::Counter
+    );
+    target.header(added);
+    for tal in &metrics.tals {
+        target.multi(added).label("name", tal.name())
+            .value(churn.tal(tal.name()).added);
+    }
+    target.header(removed);
+    for tal in &metrics.tals {
+        target.multi(removed).label("name", tal.name())
+            .value(churn.tal(tal.name()).removed);
+    }
+}
+
 fn rrdp_metrics(target: &mut Target, metrics: &[RrdpRepositoryMetrics]) {
     let status = Metric::new(
         "rrdp_status",
@@ -467,12 +880,56 @@ fn rrdp_metrics(target: &mut Target, metrics: &[RrdpRepositoryMetrics]) {
         MetricType::Gauge
     );
     target.header(duration);
+    let queue_duration = Metric::new(
+        "rrdp_queue_duration",
+        "time the RRDP update waited for a free update slot in seconds",
+        MetricType::Gauge
+    );
+    target.header(queue_duration);
     let serial = Metric::new(
         "rrdp_serial_info",
         "serial number of the last RRDP update",
         MetricType::Gauge
     );
     target.header(serial);
+    let ip_version = Metric::new(
+        "rrdp_ip_version",
+        "IP version used for getting the RRDP notification file",
+        MetricType::Gauge
+    );
+    target.header(ip_version);
+    let update_type = Metric::new(
+        "rrdp_update_type",
+        "type of the last RRDP update given in the type label",
+        MetricType::Gauge
+    );
+    target.header(update_type);
+    let last_modified = Metric::new(
+        "rrdp_notification_last_modified",
+        "Last-Modified time of the RRDP notification file as a Unix \
+         timestamp",
+        MetricType::Gauge
+    );
+    target.header(last_modified);
+    let processed = Metric::new(
+        "rrdp_notification_processed",
+        "time the RRDP notification file was processed as a Unix timestamp",
+        MetricType::Gauge
+    );
+    target.header(processed);
+    let delay = Metric::new(
+        "rrdp_notification_delay",
+        "time between modification and processing of the RRDP \
+         notification file in seconds",
+        MetricType::Gauge
+    );
+    target.header(delay);
+    let serial_lag = Metric::new(
+        "rrdp_serial_lag",
+        "number of serials the local copy was behind the RRDP server",
+        MetricType::Gauge
+    );
+    target.header(serial_lag);
 
     for rrdp in metrics {
         target.multi(status).label("uri", &rrdp.notify_uri).value(
@@ -493,9 +950,111 @@ fn rrdp_metrics(target: &mut Target, metrics: &[RrdpRepositoryMetrics]) {
                 )
             )
         }
+        target.multi(queue_duration).label("uri", &rrdp.notify_uri).value(
+            format_args!(
+                "{}.{:03}",
+                rrdp.queue_duration.as_secs(),
+                rrdp.queue_duration.subsec_millis(),
+            )
+        );
         if let Some(value) = rrdp.serial {
             target.multi(serial).label("uri", &rrdp.notify_uri).value(value)
         }
+        if let Some(value) = rrdp.ip_version() {
+            target.multi(ip_version).label("uri", &rrdp.notify_uri).value(
+                value
+            )
+        }
+        if let Some(kind) = rrdp.update_kind {
+            target.multi(update_type).label("uri", &rrdp.notify_uri)
+                .label("type", kind.code()).value(1)
+        }
+        if let Some(time) = rrdp.notify_last_modified {
+            target.multi(last_modified).label("uri", &rrdp.notify_uri)
+                .value(time.timestamp())
+        }
+        if let Some(time) = rrdp.notify_processed {
+            target.multi(processed).label("uri", &rrdp.notify_uri)
+                .value(time.timestamp())
+        }
+        if let Some(value) = rrdp.notify_delay() {
+            target.multi(delay).label("uri", &rrdp.notify_uri).value(value)
+        }
+        if let Some(value) = rrdp.serial_lag {
+            target.multi(serial_lag).label("uri", &rrdp.notify_uri)
+                .value(value)
+        }
+    }
+}
+
+fn rrdp_duration_histograms(
+    target: &mut Target, histograms: &RrdpDurationHistograms
+) {
+    target.header(Metric::new(
+        "rrdp_fetch_duration_seconds",
+        "histogram of RRDP update durations per repository host",
+        MetricType::Histogram
+    ));
+    let bucket = Metric::new(
+        "rrdp_fetch_duration_seconds_bucket", "", MetricType::Histogram
+    );
+    let sum = Metric::new(
+        "rrdp_fetch_duration_seconds_sum", "", MetricType::Histogram
+    );
+    let count = Metric::new(
+        "rrdp_fetch_duration_seconds_count", "", MetricType::Histogram
+    );
+    for (host, histogram) in histograms.iter() {
+        for (bound, value) in histograms.buckets().iter().zip(
+            histogram.counts()
+        ) {
+            target.multi(bucket)
+                .label("host", host).label("le", bound)
+                .value(value);
+        }
+        target.multi(bucket)
+            .label("host", host).label("le", "+Inf")
+            .value(histogram.count());
+        target.multi(sum).label("host", host).value(histogram.sum());
+        target.multi(count).label("host", host).value(histogram.count());
+    }
+}
+
+fn rrdp_update_totals(target: &mut Target, totals: &RrdpUpdateTotals) {
+    let outcomes = Metric::new(
+        "rrdp_updates",
+        "total number of RRDP update attempts by outcome",
+        MetricType::Counter
+    );
+    target.header(outcomes);
+    for (outcome, value) in totals.outcomes() {
+        target.multi(outcomes).label("outcome", outcome).value(value);
+    }
+    let reasons = Metric::new(
+        "rrdp_snapshot_reasons",
+        "total number of RRDP snapshot update attempts by reason",
+        MetricType::Counter
+    );
+    target.header(reasons);
+    for (reason, value) in totals.snapshot_reasons() {
+        target.multi(reasons).label("reason", reason).value(value);
+    }
+}
+
+fn rrdp_dns_metrics(target: &mut Target, metrics: &RrdpDnsMetrics) {
+    let lookups = Metric::new(
+        "rrdp_dns_lookups",
+        "DNS lookups for RRDP servers during the last run by result",
+        MetricType::Gauge
+    );
+    target.header(lookups);
+    for (result, value) in [
+        ("hit", metrics.hits),
+        ("miss", metrics.misses),
+        ("stale", metrics.stale),
+        ("failure", metrics.failures),
+    ] {
+        target.multi(lookups).label("result", result).value(value);
     }
 }
 
@@ -510,6 +1069,24 @@ fn rsync_metrics(target: &mut Target, metrics: &[RsyncModuleMetrics]) {
         MetricType::Gauge
     );
     target.header(duration);
+    let queue_duration = Metric::new(
+        "rsync_queue_duration",
+        "time the rsync update waited for a free process slot in seconds",
+        MetricType::Gauge
+    );
+    target.header(queue_duration);
+    let rrdp_fallback = Metric::new(
+        "rsync_rrdp_fallback",
+        "whether RRDP was used because the rsync update failed",
+        MetricType::Gauge
+    );
+    target.header(rrdp_fallback);
+    let unchanged = Metric::new(
+        "rsync_unchanged",
+        "whether the update was skipped because the listing was unchanged",
+        MetricType::Gauge
+    );
+    target.header(unchanged);
 
     for rsync in metrics {
         target.multi(status).label("uri", &rsync.module).value(
@@ -527,11 +1104,121 @@ fn rsync_metrics(target: &mut Target, metrics: &[RsyncModuleMetrics]) {
                 )
             );
         }
+        target.multi(queue_duration).label("uri", &rsync.module).value(
+            format_args!(
+                "{}.{:03}",
+                rsync.queue_duration.as_secs(),
+                rsync.queue_duration.subsec_millis(),
+            )
+        );
+        target.multi(rrdp_fallback).label("uri", &rsync.module).value(
+            u8::from(rsync.rrdp_fallback)
+        );
+        target.multi(unchanged).label("uri", &rsync.module).value(
+            u8::from(rsync.unchanged)
+        );
     }
 }
 
-async fn rtr_metrics(target: &mut Target, metrics: &SharedRtrServerMetrics) {
-    let detailed = metrics.detailed();
+fn cache_usage_metrics(
+    target: &mut Target, metrics: &CacheUsageMetrics, detail: MetricsDetail,
+) {
+    target.single(
+        Metric::new(
+            "cache_size", "overall size of the cache in bytes",
+            MetricType::Gauge
+        ),
+        metrics.total()
+    );
+    if let Some(max_size) = metrics.max_size {
+        target.single(
+            Metric::new(
+                "cache_max_size", "configured maximum size of the cache \
+                in bytes",
+                MetricType::Gauge
+            ),
+            max_size
+        );
+    }
+    target.single(
+        Metric::new(
+            "cache_max_size_exceeded",
+            "whether the cache exceeds its maximum size",
+            MetricType::Gauge
+        ),
+        u8::from(metrics.is_exceeded())
+    );
+    if detail < MetricsDetail::Standard {
+        return
+    }
+    let size = Metric::new(
+        "cache_repository_size",
+        "size of the largest repositories in the cache in bytes",
+        MetricType::Gauge
+    );
+    target.header(size);
+    for (uri, value) in metrics.largest_repositories() {
+        target.multi(size).label("uri", uri).value(value);
+    }
+}
+
+fn publish_metrics(target: &mut Target, metrics: &[PublishMetrics]) {
+    let failures = Metric::new(
+        "publish_failures",
+        "number of failed uploads to a publish target",
+        MetricType::Counter
+    );
+    target.header(failures);
+    let last_success = Metric::new(
+        "publish_last_success",
+        "time of the last successful upload as a Unix timestamp",
+        MetricType::Gauge
+    );
+    target.header(last_success);
+
+    for item in metrics {
+        target.multi(failures).label("url", &item.url).value(item.failures);
+        if let Some(time) = item.last_success {
+            target.multi(last_success).label("url", &item.url).value(
+                time.timestamp()
+            );
+        }
+    }
+}
+
+fn integrity_metrics(target: &mut Target, metrics: &IntegrityMetrics) {
+    target.single(
+        Metric::new(
+            "archive_checks_total",
+            "number of RRDP archives checked for integrity",
+            MetricType::Counter
+        ),
+        metrics.checked
+    );
+    target.single(
+        Metric::new(
+            "archive_corrupt_total",
+            "number of corrupt RRDP archives found by the integrity check",
+            MetricType::Counter
+        ),
+        metrics.corrupt
+    );
+    let last_pass = Metric::new(
+        "archive_check_last_pass",
+        "time the last integrity check pass finished as a Unix timestamp",
+        MetricType::Gauge
+    );
+    match metrics.last_pass {
+        Some(time) => target.single(last_pass, time.timestamp()),
+        None => target.single(last_pass, "NaN"),
+    }
+}
+
+async fn rtr_metrics(
+    target: &mut Target, metrics: &SharedRtrServerMetrics,
+    detail: MetricsDetail,
+) {
+    let detailed = metrics.detailed() && detail >= MetricsDetail::Full;
     let metrics = metrics.read().await;
 
     target.single(
@@ -696,6 +1383,54 @@ async fn rtr_metrics(target: &mut Target, metrics: &SharedRtrServerMetrics) {
             target.multi(item).label("addr", addr).value(count)
         });
 
+        let item = Metric::new(
+            "rtr_client_version",
+            "negotiated RTR protocol version of a client address",
+            MetricType::Gauge
+        );
+        target.header(item);
+        metrics.fold_clients(None, |version, client| {
+            *version = cmp::max(*version, client.version());
+        }).for_each(|(addr, version)| {
+            match version {
+                Some(version) => {
+                    target.multi(item).label("addr", addr).value(version)
+                }
+                None => target.multi(item).label("addr", addr).value(-1)
+            }
+        });
+
+        let item = Metric::new(
+            "rtr_client_requested_serial",
+            "serial last requested via a serial query by a client address",
+            MetricType::Gauge
+        );
+        target.header(item);
+        metrics.fold_clients(None, |serial, client| {
+            *serial = cmp::max(
+                *serial, client.requested_serial().map(u32::from)
+            );
+        }).for_each(|(addr, serial)| {
+            match serial {
+                Some(serial) => {
+                    target.multi(item).label("addr", addr).value(serial)
+                }
+                None => target.multi(item).label("addr", addr).value(-1)
+            }
+        });
+
+        let item = Metric::new(
+            "rtr_client_sent_pdus",
+            "number of PDUs sent to a client address",
+            MetricType::Counter
+        );
+        target.header(item);
+        metrics.fold_clients(0, |count, client| {
+            *count += client.pdus_sent();
+        }).for_each(|(addr, count)| {
+            target.multi(item).label("addr", addr).value(count)
+        });
+
         let item = Metric::new(
             "rtr_client_written_bytes",
             "number of bytes written to a client address",
@@ -832,9 +1567,9 @@ struct Target {
 }
 
 impl Target {
-    pub fn into_response(self) -> Response {
+    pub fn into_response(self, req: &Request) -> Response {
         ResponseBuilder::ok().content_type(ContentType::PROMETHEUS)
-        .body(self.buf)
+        .body_for(req, self.buf)
     }
 
     pub fn single(&mut self, metric: Metric, value: impl fmt::Display) {
@@ -949,8 +1684,8 @@ impl<'a> LabelValue<'a> {
 enum MetricType {
     Counter,
     Gauge,
-    /* Not currently used:
     Histogram,
+    /* Not currently used:
     Summary,
     */
 }
@@ -961,8 +1696,8 @@ impl fmt::Display for MetricType {
             match *self {
                 MetricType::Counter => "counter",
                 MetricType::Gauge => "gauge",
-                /*
                 MetricType::Histogram => "histogram",
+                /*
                 MetricType::Summary => "summary",
                 */
             }
@@ -1002,3 +1737,156 @@ impl Group {
     }
 }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the names of all metrics exposed at the given detail level.
+    fn metric_names(detail: MetricsDetail) -> Vec<String> {
+        let mut metrics = Metrics::new();
+        metrics.cache_usage = Some(CacheUsageMetrics::new(Some(1)));
+        let data = MetricsData {
+            metrics: Arc::new(metrics),
+            rrdp_durations: Default::default(),
+            rrdp_updates: Default::default(),
+            churn: Default::default(),
+            serial: Serial::default(),
+            start: Utc::now(),
+            done: None,
+            duration: None,
+            timed_out: false,
+            clock_skew: None,
+            unsafe_vrps: FilterPolicy::Warn,
+            vrp_guard_active: false,
+            vrp_guard_held_runs: 0,
+            publish: Vec::new(),
+            integrity: Default::default(),
+        };
+        let mut target = Target::default();
+        futures::executor::block_on(data.write(
+            &mut target, detail, &HttpServerMetrics::default(),
+            &SharedRtrServerMetrics::new(true, 0),
+        ));
+        target.buf.lines().filter_map(|line| {
+            line.strip_prefix("# TYPE routinator_").map(|line| {
+                line.split(' ').next().unwrap().into()
+            })
+        }).collect()
+    }
+
+    #[test]
+    fn detail_tiers() {
+        let minimal = metric_names(MetricsDetail::Minimal);
+        let standard = metric_names(MetricsDetail::Standard);
+        let full = metric_names(MetricsDetail::Full);
+
+        // Each level includes all metrics of the lower levels.
+        assert!(minimal.iter().all(|name| standard.contains(name)));
+        assert!(standard.iter().all(|name| full.contains(name)));
+
+        assert_eq!(
+            minimal,
+            [
+            "last_update_start", "last_update_duration", "last_update_done",
+            "last_update_timed_out", "clock_skew_detected",
+            "clock_skew_seconds", "serial", "vrp_guard_active",
+            "vrp_guard_held_runs", "ta_publication_points_total",
+            "ta_objects_total", "ta_object_types_total",
+            "ta_valid_vrps_total", "ta_unsafe_vrps_total",
+            "ta_outside_resources_vrps_total",
+            "ta_locally_filtered_vrps_total", "ta_duplicate_vrps_total",
+            "ta_contributed_vrps_total", "ta_valid_payload_total",
+            "ta_unsafe_payload_total", "ta_outside_resources_payload_total",
+            "ta_locally_filtered_payload_total", "ta_duplicate_payload_total",
+            "ta_contributed_payload_total", "ta_update_skipped", "ta_uri",
+            "ta_stored", "ta_key_mismatch", "ta_last_update",
+            "ta_min_cert_expiry", "ta_min_next_update",
+            "vrps_added_locally", "aspa_large_provider_set",
+            "repositories_limit_exceeded", "manifest_replays",
+            "origins_added", "origins_removed", "ta_origins_added",
+            "ta_origins_removed", "rrdp_updates", "rrdp_snapshot_reasons",
+            "rrdp_dns_lookups", "rsync_peak_processes", "cache_size",
+            "cache_max_size", "cache_max_size_exceeded", "publish_failures",
+            "publish_last_success", "archive_checks_total",
+            "archive_corrupt_total", "archive_check_last_pass",
+            "rtr_current_connections",
+            "rtr_bytes_read", "rtr_bytes_written", "http_connections",
+            "http_current_connections", "http_bytes_read",
+            "http_bytes_written", "http_requests", "valid_roas", "total_vrps",
+            "vrps_unsafe", "vrps_filtered_locally", "vrps_duplicate",
+            "vrps_final", "stale_objects",
+            ]
+        );
+        assert_eq!(
+            standard.iter().filter(|name| {
+                !minimal.contains(name)
+            }).collect::<Vec<_>>(),
+            [
+            "repository_publication_points_total", "repository_objects_total",
+            "repository_object_types_total", "repository_valid_vrps_total",
+            "repository_unsafe_vrps_total",
+            "repository_outside_resources_vrps_total",
+            "repository_locally_filtered_vrps_total",
+            "repository_duplicate_vrps_total",
+            "repository_contributed_vrps_total",
+            "repository_valid_payload_total",
+            "repository_unsafe_payload_total",
+            "repository_outside_resources_payload_total",
+            "repository_locally_filtered_payload_total",
+            "repository_duplicate_payload_total",
+            "repository_contributed_payload_total",
+            "repository_host_fetch_duration",
+            "repository_host_validation_duration", "rrdp_status",
+            "rrdp_notification_status", "rrdp_payload_status",
+            "rrdp_duration", "rrdp_queue_duration", "rrdp_serial_info",
+            "rrdp_ip_version", "rrdp_update_type",
+            "rrdp_notification_last_modified", "rrdp_notification_processed",
+            "rrdp_notification_delay", "rrdp_serial_lag",
+            "rrdp_fetch_duration_seconds", "cache_repository_size",
+            ]
+        );
+        assert_eq!(
+            full.iter().filter(|name| {
+                !standard.contains(name)
+            }).collect::<Vec<_>>(),
+            [
+            "rsync_status", "rsync_duration", "rsync_queue_duration",
+            "rsync_rrdp_fallback", "rsync_unchanged",
+            "rtr_client_connections", "rtr_client_serial",
+            "rtr_client_last_update_seconds", "rtr_client_last_reset_seconds",
+            "rtr_client_reset_queries", "rtr_client_serial_queries",
+            "rtr_client_read_bytes", "rtr_client_version",
+            "rtr_client_requested_serial", "rtr_client_sent_pdus",
+            "rtr_client_written_bytes",
+            ]
+        );
+    }
+
+    #[test]
+    fn detail_query() {
+        assert_eq!(
+            detail_from_query(None, MetricsDetail::Standard),
+            Some(MetricsDetail::Standard)
+        );
+        assert_eq!(
+            detail_from_query(Some("detail=full"), MetricsDetail::Minimal),
+            Some(MetricsDetail::Full)
+        );
+        assert!(
+            detail_from_query(Some("detail=lots"), MetricsDetail::Full)
+                .is_none()
+        );
+        assert!(
+            detail_from_query(Some("foo=bar"), MetricsDetail::Full).is_none()
+        );
+        assert!(
+            detail_from_query(
+                Some("detail=full&detail=minimal"), MetricsDetail::Full
+            ).is_none()
+        );
+    }
+}
@@ -15,15 +15,32 @@ mod dispatch;
 mod listener;
 
 // The following modules helps dealing with requests and responses
+mod auth;
+mod cors;
 mod request;
 mod response;
 
 // Finally, these modules actually handle requests.
+mod archive;
+mod cert_info;
+mod check_object;
+mod config;
 mod delta;
+mod exceptions;
+mod explorer;
+mod health;
+mod integrity;
 mod log;
+mod manifests;
 mod metrics;
+mod origins;
 mod payload;
+mod refresh;
+mod rrdp_history;
+mod run_log;
 mod status;
+mod store;
 mod ui;
+mod unsafe_vrps;
 mod validity;
 
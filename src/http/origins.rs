@@ -0,0 +1,233 @@
+//! Handling of the endpoint for looking up the sources of route origins.
+
+use std::str::FromStr;
+use rpki::resources::{Asn, Prefix};
+use rpki::repository::x509::Validity;
+use rpki::rtr::payload::RouteOrigin;
+use crate::payload::{PayloadInfo, PayloadSnapshot, SharedHistory};
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/vrp-origins" {
+        return None
+    }
+    let query = match OriginsQuery::from_query(req.uri().query()) {
+        Some(query) => query,
+        None => return Some(Response::bad_request()),
+    };
+    let snapshot = match history.read().current() {
+        Some(snapshot) => snapshot,
+        None => return Some(Response::initial_validation()),
+    };
+
+    let origins: Vec<_> = snapshot.origins().filter(|(origin, _)| {
+        query.matches(*origin)
+    }).collect();
+
+    let res = if origins.is_empty() {
+        ResponseBuilder::not_found()
+    }
+    else {
+        ResponseBuilder::ok()
+    };
+    let res = res.content_type(ContentType::JSON);
+
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(origins_json(&origins, &snapshot)))
+    }
+}
+
+
+//------------ OriginsQuery --------------------------------------------------
+
+/// The parsed query of a request.
+struct OriginsQuery {
+    /// The ASN to look for if any.
+    asn: Option<Asn>,
+
+    /// The prefix to look for if any.
+    prefix: Option<Prefix>,
+
+    /// Should route origins with covering prefixes be included?
+    covering: bool,
+}
+
+impl OriginsQuery {
+    /// Parses the query string.
+    ///
+    /// Returns `None` if the query is invalid or neither ASN nor prefix
+    /// are given.
+    fn from_query(query: Option<&str>) -> Option<Self> {
+        let mut res = OriginsQuery {
+            asn: None, prefix: None, covering: false,
+        };
+        for (key, value) in form_urlencoded::parse(query?.as_bytes()) {
+            if key == "asn" {
+                res.asn = Some(Asn::from_str(&value).ok()?);
+            }
+            else if key == "prefix" {
+                res.prefix = Some(Prefix::from_str_relaxed(&value).ok()?);
+            }
+            else if key == "match" {
+                res.covering = match value.as_ref() {
+                    "exact" => false,
+                    "covering" => true,
+                    _ => return None,
+                };
+            }
+            else {
+                return None
+            }
+        }
+        if res.asn.is_none() && res.prefix.is_none() {
+            return None
+        }
+        Some(res)
+    }
+
+    /// Returns whether the route origin matches the query.
+    fn matches(&self, origin: RouteOrigin) -> bool {
+        if let Some(asn) = self.asn {
+            if origin.asn != asn {
+                return false
+            }
+        }
+        if let Some(prefix) = self.prefix {
+            if self.covering {
+                if !origin.prefix.prefix().covers(prefix) {
+                    return false
+                }
+            }
+            else if origin.prefix.prefix() != prefix {
+                return false
+            }
+        }
+        true
+    }
+}
+
+
+//------------ origins_json --------------------------------------------------
+
+fn origins_json(
+    origins: &[(RouteOrigin, &PayloadInfo)],
+    snapshot: &PayloadSnapshot,
+) -> String {
+    JsonBuilder::build(|json| {
+        json.member_str(
+            "generatedTime", format_iso_date(snapshot.created())
+        );
+        json.member_array("origins", |json| {
+            for (origin, info) in origins {
+                json.array_object(|json| {
+                    json.member_str("asn", origin.asn);
+                    json.member_str("prefix", origin.prefix.prefix());
+                    json.member_raw(
+                        "maxLength", origin.prefix.resolved_max_len()
+                    );
+                    json.member_array("sources", |json| {
+                        for item in *info {
+                            source_json(item, json)
+                        }
+                    });
+                })
+            }
+        });
+    })
+}
+
+pub(super) fn source_json(info: &PayloadInfo, json: &mut JsonBuilder) {
+    if let Some(roa) = info.publish_info() {
+        json.array_object(|json| {
+            json.member_str("type", "roa");
+            match roa.uri.as_ref() {
+                Some(uri) => json.member_str("uri", uri),
+                None => json.member_raw("uri", "null"),
+            }
+            json.member_str("tal", roa.tal.name());
+            match roa.ca_uri.as_ref() {
+                Some(uri) => json.member_str("caUri", uri),
+                None => json.member_raw("caUri", "null"),
+            }
+            validity_json("validity", roa.roa_validity, json);
+            validity_json("chainValidity", roa.chain_validity, json);
+            json.member_str(
+                "stale", format_iso_date(roa.point_stale.into())
+            );
+        })
+    }
+    if let Some(exc) = info.exception_info() {
+        json.array_object(|json| {
+            json.member_str("type", "exception");
+            match exc.path.as_ref() {
+                Some(path) => json.member_str("path", path.display()),
+                None => json.member_raw("path", "null"),
+            }
+            if let Some(comment) = exc.comment.as_ref() {
+                json.member_str("comment", comment)
+            }
+        })
+    }
+}
+
+fn validity_json(key: &str, validity: Validity, json: &mut JsonBuilder) {
+    json.member_object(key, |json| {
+        json.member_str(
+            "notBefore", format_iso_date(validity.not_before().into())
+        );
+        json.member_str(
+            "notAfter", format_iso_date(validity.not_after().into())
+        );
+    })
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rpki::resources::addr::MaxLenPrefix;
+
+    fn origin(prefix: &str, asn: u32) -> RouteOrigin {
+        RouteOrigin::new(
+            MaxLenPrefix::new(Prefix::from_str(prefix).unwrap(), None)
+                .unwrap(),
+            Asn::from_u32(asn)
+        )
+    }
+
+    #[test]
+    fn query_matches() {
+        let query = OriginsQuery::from_query(
+            Some("asn=AS64500&prefix=192.0.2.0/24")
+        ).unwrap();
+        assert!(query.matches(origin("192.0.2.0/24", 64500)));
+        assert!(!query.matches(origin("192.0.2.0/24", 64501)));
+        assert!(!query.matches(origin("192.0.0.0/16", 64500)));
+
+        let query = OriginsQuery::from_query(
+            Some("prefix=192.0.2.0/24&match=covering")
+        ).unwrap();
+        assert!(query.matches(origin("192.0.2.0/24", 64500)));
+        assert!(query.matches(origin("192.0.0.0/16", 64501)));
+        assert!(!query.matches(origin("192.0.2.0/25", 64500)));
+
+        assert!(OriginsQuery::from_query(None).is_none());
+        assert!(OriginsQuery::from_query(Some("match=exact")).is_none());
+        assert!(OriginsQuery::from_query(Some("asn=foo")).is_none());
+        assert!(OriginsQuery::from_query(Some("asn=1&foo=bar")).is_none());
+    }
+}
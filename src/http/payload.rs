@@ -1,11 +1,15 @@
 //! Handles endpoints related to output of payload sets.
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use futures::stream;
+use hyper::Uri;
+use rpki::rtr::Serial;
 use crate::config::Config;
 use crate::output::{Output, OutputFormat};
 use crate::payload::SharedHistory;
 use super::request::Request;
-use super::response::{Response, ResponseBuilder};
+use super::response::{ContentType, Response, ResponseBuilder};
 
 
 //------------ State ---------------------------------------------------------
@@ -26,24 +30,36 @@ impl State {
         req: &Request,
         history: &SharedHistory,
     ) -> Option<Response> {
+        let mut output = self.output.clone();
         let path = req.uri().path();
         let format = if path == "/api/v1/origins/" {
             OutputFormat::Json
         }
+        else if path == "/json-aspa" {
+            output.no_route_origins();
+            output.no_router_keys();
+            OutputFormat::Json
+        }
+        else if path == "/router-keys.json" {
+            OutputFormat::RouterKeysJson
+        }
+        else if path == "/api/v1/vrps.json" {
+            OutputFormat::JsonV2
+        }
         else {
             OutputFormat::from_path(req.uri().path())?
         };
 
-        let mut output = self.output.clone();
         if output.update_from_query(req.uri().query()).is_err() {
             return Some(Response::bad_request())
         };
 
-        let (session, serial, created, snapshot, metrics) = {
+        let (session, serial, digest, created, snapshot, metrics) = {
             let history = history.read();
             (
                 history.session(),
                 history.serial(),
+                history.exceptions_digest(),
                 history.created(),
                 history.current(),
                 history.metrics()
@@ -56,7 +72,18 @@ impl State {
             _ => return Some(Response::initial_validation()),
         };
 
-        let etag = format!("\"{:x}-{}\"", session, serial);
+        if let Err(err) = output.check_tals(
+            metrics.tals.iter().map(|tal| tal.tal.name())
+        ) {
+            return Some(
+                ResponseBuilder::bad_request()
+                .content_type(ContentType::TEXT)
+                .body(format!("{}\n", err))
+            )
+        }
+
+        output.set_rtr_state(session, serial);
+        let etag = etag(session, serial, digest, req.uri());
 
         if let Some(response) = Response::maybe_not_modified(
             req, &etag, created
@@ -68,13 +95,88 @@ impl State {
             .content_type(format.content_type())
             .etag(&etag).last_modified(created);
         if req.is_head() {
-            Some(res.empty())
+            Some(res.vary_encoding().empty())
         }
         else {
-            Some(res.stream(
+            Some(res.stream_for(
+                req,
                 stream::iter(output.stream(snapshot, metrics, format))
             ))
         }
     }
 }
 
+
+
+//------------ etag ----------------------------------------------------------
+
+/// Creates the ETag for a response.
+///
+/// The tag is derived from the session and serial of the current data set
+/// as well as the digest of the local exceptions’ information, which may
+/// change the output without changing the serial. The path and query of
+/// the request URI are included, too, since they determine the format and
+/// selection of the output.
+fn etag(session: u64, serial: Serial, digest: u64, uri: &Uri) -> String {
+    let mut hasher = DefaultHasher::new();
+    digest.hash(&mut hasher);
+    uri.path().hash(&mut hasher);
+    uri.query().hash(&mut hasher);
+    format!("\"{:x}-{}-{:x}\"", session, serial, hasher.finish())
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::slurm::LocalExceptions;
+
+    fn slurm(comment: &str) -> LocalExceptions {
+        LocalExceptions::from_json(
+            &format!(
+                r#"{{
+                    "slurmVersion": 1,
+                    "validationOutputFilters": {{
+                        "prefixFilters": [ ],
+                        "bgpsecFilters": [ ]
+                    }},
+                    "locallyAddedAssertions": {{
+                        "prefixAssertions": [ {{
+                            "asn": 64496,
+                            "prefix": "198.51.100.0/24",
+                            "comment": "{}"
+                        }} ],
+                        "bgpsecAssertions": [ ]
+                    }}
+                }}"#,
+                comment
+            ),
+            true
+        ).unwrap()
+    }
+
+    #[test]
+    fn etag_changes() {
+        fn tag(serial: u32, comment: &str, uri: &'static str) -> String {
+            etag(
+                12, serial.into(), slurm(comment).info_digest(),
+                &Uri::from_static(uri)
+            )
+        }
+
+        let base = tag(7, "foo", "/json");
+
+        // Same data produces the same tag.
+        assert_eq!(base, tag(7, "foo", "/json"));
+
+        // A changed comment in the SLURM file busts the cache.
+        assert_ne!(base, tag(7, "bar", "/json"));
+
+        // So do a new serial, format, or query.
+        assert_ne!(base, tag(8, "foo", "/json"));
+        assert_ne!(base, tag(7, "foo", "/csv"));
+        assert_ne!(base, tag(7, "foo", "/json?select-asn=1"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Handling of the endpoint for requesting a validation run.
+//!
+//! A POST to `/api/v1/refresh` asks the server to start a full validation
+//! run right away. With a `tal=<name>` query, only the TAL with the given
+//! name is validated and its payload merged into the current data set.
+
+use crate::operation::RefreshRequest;
+use crate::payload::SharedHistory;
+use super::explorer::query_value;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_post ---------------------------------------------------
+
+pub fn handle_post(
+    req: &Request,
+    refresh: &RefreshRequest,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/refresh" {
+        return None
+    }
+    if !req.is_post() {
+        return Some(Response::method_not_allowed())
+    }
+    let tal = match req.uri().query() {
+        None | Some("") => None,
+        query => match query_value(query, "tal") {
+            Some(tal) => Some(tal),
+            None => return Some(Response::bad_request()),
+        }
+    };
+    let requested = match tal {
+        Some(tal) => {
+            let metrics = match history.read().metrics() {
+                Some(metrics) => metrics,
+                None => return Some(Response::initial_validation()),
+            };
+            if !metrics.tals.iter().any(|item| item.name() == tal) {
+                return Some(
+                    ResponseBuilder::not_found()
+                        .content_type(ContentType::TEXT)
+                        .body(format!("Unknown TAL {}.\n", tal))
+                )
+            }
+            refresh.request_tal(&tal)
+        }
+        None => refresh.request_full()
+    };
+    if requested {
+        Some(
+            ResponseBuilder::accepted().content_type(ContentType::TEXT).body(
+                "Starting validation run.\n"
+            )
+        )
+    }
+    else {
+        Some(
+            ResponseBuilder::conflict().content_type(ContentType::TEXT).body(
+                "A validation run is already in progress.\n"
+            )
+        )
+    }
+}
@@ -1,6 +1,8 @@
 //! Request handling.
 
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use hyper::{Method, Uri};
+use hyper::body::Bytes;
 use hyper::header::HeaderMap;
 
 
@@ -17,6 +19,16 @@ impl Request {
             || self.hyper.method() == Method::HEAD
     }
 
+    /// Returns whether the method is POST.
+    pub fn is_post(&self) -> bool {
+        self.hyper.method() == Method::POST
+    }
+
+    /// Returns whether the method is OPTIONS.
+    pub fn is_options(&self) -> bool {
+        self.hyper.method() == Method::OPTIONS
+    }
+
     /// Returns whether the method is HEAD.
     pub fn is_head(&self) -> bool {
         self.hyper.method() == Method::HEAD
@@ -31,6 +43,133 @@ impl Request {
     pub fn headers(&self) -> &HeaderMap {
         self.hyper.headers()
     }
+
+    /// Removes the base path from the start of the request path.
+    ///
+    /// Returns whether the request path is located under the base path.
+    /// If it isn’t, the request is left unchanged. An empty base path
+    /// leaves every request unchanged.
+    pub fn strip_base_path(&mut self, base: &str) -> bool {
+        if base.is_empty() {
+            return true
+        }
+        let path = match strip_base_path(self.uri().path(), base) {
+            Some(path) => path,
+            None => return false,
+        };
+        let path_and_query = match self.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.into(),
+        };
+        match Uri::builder().path_and_query(path_and_query).build() {
+            Ok(uri) => {
+                *self.hyper.uri_mut() = uri;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns whether the client accepts gzip-encoded responses.
+    ///
+    /// This is the case if any of the Accept-Encoding headers contains
+    /// `gzip` with a non-zero quality value. We don’t consider `*` since
+    /// clients that really want compressed data will ask for it.
+    pub fn accepts_gzip(&self) -> bool {
+        self.headers().get_all("Accept-Encoding").iter().any(|value| {
+            match value.to_str() {
+                Ok(value) => accepts_gzip(value),
+                Err(_) => false,
+            }
+        })
+    }
+
+    /// Returns whether the request claims to contain JSON.
+    pub fn is_json(&self) -> bool {
+        match self.headers().get("Content-Type") {
+            Some(value) => {
+                match value.to_str() {
+                    Ok(value) => {
+                        value.split(';').next().unwrap_or("").trim()
+                            .eq_ignore_ascii_case("application/json")
+                    }
+                    Err(_) => false,
+                }
+            }
+            None => false
+        }
+    }
+
+    /// Reads the complete body of the request.
+    ///
+    /// The body may be at most `limit` bytes long.
+    pub async fn read_body(self, limit: usize) -> Result<Bytes, BodyError> {
+        match Limited::new(self.hyper.into_body(), limit).collect().await {
+            Ok(body) => Ok(body.to_bytes()),
+            Err(err) => {
+                if err.downcast_ref::<LengthLimitError>().is_some() {
+                    Err(BodyError::TooLarge)
+                }
+                else {
+                    Err(BodyError::Failed)
+                }
+            }
+        }
+    }
+}
+
+
+//------------ BodyError -----------------------------------------------------
+
+/// Reading the body of a request has failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BodyError {
+    /// The body was larger than permitted.
+    TooLarge,
+
+    /// Receiving the body has failed.
+    Failed,
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the remainder of `path` below the base path `base`.
+///
+/// Returns `None` if the path isn’t located under the base path. The
+/// remainder of the base path itself is the root path.
+fn strip_base_path<'a>(path: &'a str, base: &str) -> Option<&'a str> {
+    let path = path.strip_prefix(base)?;
+    if path.is_empty() {
+        Some("/")
+    }
+    else if path.starts_with('/') {
+        Some(path)
+    }
+    else {
+        None
+    }
+}
+
+/// Returns whether an Accept-Encoding header value allows gzip.
+fn accepts_gzip(value: &str) -> bool {
+    value.split(',').any(|item| {
+        let mut item = item.split(';');
+        let coding = item.next().unwrap_or("").trim();
+        if !coding.eq_ignore_ascii_case("gzip") {
+            return false
+        }
+        for param in item {
+            let param = param.trim();
+            if let Some(q) = param.strip_prefix("q=") {
+                return match q.trim().parse::<f32>() {
+                    Ok(q) => q > 0.,
+                    Err(_) => false,
+                }
+            }
+        }
+        true
+    })
 }
 
 
@@ -42,3 +181,36 @@ impl From<hyper::Request<hyper::body::Incoming>> for Request {
     }
 }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_encoding_gzip() {
+        assert!(accepts_gzip("gzip"));
+        assert!(accepts_gzip("GZip"));
+        assert!(accepts_gzip("deflate, gzip;q=1.0, *;q=0.5"));
+        assert!(accepts_gzip("br ,gzip ; q=0.3"));
+        assert!(!accepts_gzip("gzip;q=0"));
+        assert!(!accepts_gzip("deflate, br"));
+        assert!(!accepts_gzip("*"));
+        assert!(!accepts_gzip("identity"));
+        assert!(!accepts_gzip(""));
+    }
+
+    #[test]
+    fn base_path() {
+        assert_eq!(strip_base_path("/rpki", "/rpki"), Some("/"));
+        assert_eq!(strip_base_path("/rpki/", "/rpki"), Some("/"));
+        assert_eq!(
+            strip_base_path("/rpki/api/v1/status", "/rpki"),
+            Some("/api/v1/status")
+        );
+        assert_eq!(strip_base_path("/rpkix/status", "/rpki"), None);
+        assert_eq!(strip_base_path("/status", "/rpki"), None);
+    }
+}
@@ -1,13 +1,21 @@
 //! Building responses.
 
+use std::mem;
 use std::convert::Infallible;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
 use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use futures::stream::{Stream, StreamExt};
 use http_body_util::{BodyExt, Empty, Full, StreamBody};
 use http_body_util::combinators::BoxBody;
 use hyper::body::{Body, Bytes, Frame};
 use hyper::StatusCode;
+use hyper::header::HeaderValue;
 use hyper::http::response::Builder;
+use pin_project_lite::pin_project;
 use crate::utils::date::{parse_http_date, format_http_date};
 use super::request::Request;
 
@@ -36,6 +44,14 @@ impl Response {
             .body("Bad Request")
     }
 
+    /// Returns an Unauthorized response asking for a bearer token.
+    pub fn unauthorized() -> Self {
+        ResponseBuilder::unauthorized()
+            .content_type(ContentType::TEXT)
+            .body("Unauthorized")
+            .with_header("WWW-Authenticate", "Bearer realm=\"routinator\"")
+    }
+
     /// Returns a Not Modified response.
     pub fn not_found() -> Self {
         ResponseBuilder::not_found()
@@ -48,6 +64,20 @@ impl Response {
         ResponseBuilder::not_modified().etag(etag).last_modified(done).empty()
     }
 
+    /// Returns a Payload Too Large response.
+    pub fn payload_too_large() -> Self {
+        ResponseBuilder::payload_too_large()
+            .content_type(ContentType::TEXT)
+            .body("Payload Too Large")
+    }
+
+    /// Returns an Internal Server Error response.
+    pub fn internal_server_error() -> Self {
+        ResponseBuilder::internal_server_error()
+            .content_type(ContentType::TEXT)
+            .body("Internal Server Error")
+    }
+
     /// Returns a Method Not Allowed response.
     pub fn method_not_allowed() -> Self {
         ResponseBuilder::method_not_allowed()
@@ -56,7 +86,6 @@ impl Response {
     }
 
     /// Returns a Moved Permanently response pointing to the given location.
-    #[allow(dead_code)]
     pub fn moved_permanently(location: &str) -> Self {
         ResponseBuilder::moved_permanently()
             .content_type(ContentType::TEXT)
@@ -74,8 +103,11 @@ impl Response {
         etag: &str,
         done: DateTime<Utc>,
     ) -> Option<Response> {
-        // First, check If-None-Match.
+        // First, check If-None-Match. If the header is present, we must
+        // ignore If-Modified-Since.
+        let mut none_match = false;
         for value in req.headers().get_all("If-None-Match").iter() {
+            none_match = true;
             // Skip ill-formatted values. By being lazy here we may falsely
             // return a full response, so this should be fine.
             let value = match value.to_str() {
@@ -93,6 +125,10 @@ impl Response {
             }
         }
 
+        if none_match {
+            return None
+        }
+
         // Now, the If-Modified-Since header.
         if let Some(value) = req.headers().get("If-Modified-Since") {
             if let Some(date) = parse_http_date(value.to_str().ok()?) {
@@ -105,6 +141,15 @@ impl Response {
         None
     }
 
+    /// Adds a header to the response.
+    pub fn with_header<V>(mut self, name: &'static str, value: V) -> Self
+    where V: TryInto<HeaderValue> {
+        if let Ok(value) = value.try_into() {
+            self.0.headers_mut().append(name, value);
+        }
+        self
+    }
+
     /// Converts the response into a hyper response.
     pub fn into_hyper(
         self
@@ -132,6 +177,16 @@ impl ResponseBuilder {
         Self::new(StatusCode::OK)
     }
 
+    /// Creates a new builder for a 202 Accepted response.
+    pub fn accepted() -> Self {
+        Self::new(StatusCode::ACCEPTED)
+    }
+
+    /// Creates a new builder for a 204 No Content response.
+    pub fn no_content() -> Self {
+        Self::new(StatusCode::NO_CONTENT)
+    }
+
     /// Creates a new builder for a Service Unavailable response.
     pub fn service_unavailable() -> Self {
         Self::new(StatusCode::SERVICE_UNAVAILABLE)
@@ -142,6 +197,11 @@ impl ResponseBuilder {
         Self::new(StatusCode::BAD_REQUEST)
     }
 
+    /// Creates a new builder for an Unauthorized response.
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED)
+    }
+
     /// Creates a new builder for a Not Found response.
     pub fn not_found() -> Self {
         Self::new(StatusCode::NOT_FOUND)
@@ -157,11 +217,26 @@ impl ResponseBuilder {
         Self::new(StatusCode::METHOD_NOT_ALLOWED)
     }
 
+    /// Creates a new builder for a Conflict response.
+    pub fn conflict() -> Self {
+        Self::new(StatusCode::CONFLICT)
+    }
+
+    /// Creates a new builder for a Payload Too Large response.
+    pub fn payload_too_large() -> Self {
+        Self::new(StatusCode::PAYLOAD_TOO_LARGE)
+    }
+
     /// Creates a new builder for a Moved Permanently response.
     pub fn moved_permanently() -> Self {
         Self::new(StatusCode::MOVED_PERMANENTLY)
     }
 
+    /// Creates a new builder for an Internal Server Error response.
+    pub fn internal_server_error() -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
     /// Adds the content type header.
     pub fn content_type(self, content_type: ContentType) -> Self {
         ResponseBuilder {
@@ -186,8 +261,21 @@ impl ResponseBuilder {
         }
     }
 
+    /// Adds the Cache-Control header.
+    pub fn cache_control(self, value: &'static str) -> Self {
+        ResponseBuilder {
+            builder: self.builder.header("Cache-Control", value)
+        }
+    }
+
+    /// Adds the Vary header indicating a dependency on Accept-Encoding.
+    pub fn vary_encoding(self) -> Self {
+        ResponseBuilder {
+            builder: self.builder.header("Vary", "Accept-Encoding")
+        }
+    }
+
     /// Adds the Location header.
-    #[allow(dead_code)]
     pub fn location(self, location: &str) -> Self {
         ResponseBuilder {
             builder: self.builder.header(
@@ -213,6 +301,26 @@ impl ResponseBuilder {
         self.finalize(Full::new(body.into()))
     }
 
+    /// Finalizes the response with a body compressed if possible.
+    ///
+    /// The body will be gzip-compressed if the request indicates that the
+    /// client accepts this.
+    pub fn body_for(self, req: &Request, body: impl Into<Bytes>) -> Response {
+        let res = self.vary_encoding();
+        if req.accepts_gzip() {
+            let mut encoder = GzEncoder::new(
+                Vec::new(), Compression::default()
+            );
+            encoder.write_all(&body.into()).expect("writing to vec");
+            ResponseBuilder {
+                builder: res.builder.header("Content-Encoding", "gzip")
+            }.body(encoder.finish().expect("writing to vec"))
+        }
+        else {
+            res.body(body)
+        }
+    }
+
     /// Finalies the response by adding an empty body.
     pub fn empty(self) -> Response {
         self.finalize(Empty::new())
@@ -228,6 +336,94 @@ impl ResponseBuilder {
             }))
         )
     }
+
+    /// Finalizes the response with a gzip-compressed streaming body.
+    ///
+    /// The body is compressed on the fly as the stream is consumed, so the
+    /// response is sent chunked without ever being held in memory in full.
+    pub fn gzip_stream<S>(self, body: S) -> Response
+    where
+        S: Stream<Item = Bytes> + Send + Sync + 'static
+    {
+        ResponseBuilder {
+            builder: self.builder.header("Content-Encoding", "gzip")
+        }.stream(GzipStream::new(body))
+    }
+
+    /// Finalizes the response with a streaming body compressed if possible.
+    ///
+    /// The body will be gzip-compressed if the request indicates that the
+    /// client accepts this.
+    pub fn stream_for<S>(self, req: &Request, body: S) -> Response
+    where
+        S: Stream<Item = Bytes> + Send + Sync + 'static
+    {
+        let res = self.vary_encoding();
+        if req.accepts_gzip() {
+            res.gzip_stream(body)
+        }
+        else {
+            res.stream(body)
+        }
+    }
+}
+
+
+//------------ GzipStream ----------------------------------------------------
+
+pin_project! {
+    /// A stream of bytes compressed with gzip.
+    struct GzipStream<S> {
+        #[pin]
+        inner: S,
+
+        // The encoder. This is `None` once we are done.
+        encoder: Option<GzEncoder<Vec<u8>>>,
+    }
+}
+
+impl<S> GzipStream<S> {
+    fn new(inner: S) -> Self {
+        GzipStream {
+            inner,
+            encoder: Some(GzEncoder::new(Vec::new(), Compression::default())),
+        }
+    }
+}
+
+impl<S: Stream<Item = Bytes>> Stream for GzipStream<S> {
+    type Item = Bytes;
+
+    fn poll_next(
+        self: Pin<&mut Self>, cx: &mut Context
+    ) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let encoder = match this.encoder.as_mut() {
+                Some(encoder) => encoder,
+                None => return Poll::Ready(None),
+            };
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(chunk) => {
+                    encoder.write_all(&chunk).expect("writing to vec");
+
+                    // The encoder may buffer data internally, so only
+                    // produce a chunk once it has written something.
+                    if !encoder.get_ref().is_empty() {
+                        return Poll::Ready(Some(
+                            mem::take(encoder.get_mut()).into()
+                        ))
+                    }
+                }
+                None => {
+                    let encoder = this.encoder.take().expect("checked above");
+                    return Poll::Ready(Some(
+                        encoder.finish().expect("writing to vec").into()
+                    ))
+                }
+            }
+        }
+    }
 }
 
 
@@ -241,6 +437,9 @@ impl ContentType {
         b"text/csv;charset=utf-8;header=present"
     );
     pub const JSON: ContentType = ContentType(b"application/json");
+    pub const JSON_LINES: ContentType = ContentType(
+        b"application/x-ndjson"
+    );
     pub const TEXT: ContentType = ContentType(b"text/plain;charset=utf-8");
     pub const PROMETHEUS: ContentType = ContentType(
         b"text/plain; version=0.0.4"
@@ -249,6 +448,10 @@ impl ContentType {
     pub fn external(value: &'static [u8]) -> Self {
         ContentType(value)
     }
+
+    pub fn as_slice(&self) -> &'static [u8] {
+        self.0
+    }
 }
 
 
@@ -323,5 +526,27 @@ mod test {
             ["\"foo\"", "W/\"bar\"", "\"ba,zz\""]
         );
     }
+
+    #[test]
+    fn gzip_stream() {
+        use std::io::Read;
+        use flate2::read::GzDecoder;
+
+        let chunks = vec![
+            Bytes::from_static(b"foo,"),
+            Bytes::from_static(b""),
+            Bytes::from(vec![b'x'; 100_000]),
+            Bytes::from_static(b",bar"),
+        ];
+        let compressed: Vec<u8> = futures::executor::block_on(
+            GzipStream::new(futures::stream::iter(chunks.clone()))
+                .collect::<Vec<_>>()
+        ).concat();
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(
+            &mut decompressed
+        ).unwrap();
+        assert_eq!(decompressed, chunks.concat());
+    }
 }
 
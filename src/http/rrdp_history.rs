@@ -0,0 +1,132 @@
+//! Handling of the endpoint for the update history of RRDP repositories.
+//!
+//! `/api/v1/rrdp-history?uri=<uri>` returns the outcomes of the most recent
+//! update attempts of the RRDP repository with the given rpkiNotify URI.
+
+use crate::metrics::{Metrics, RrdpRepositoryMetrics};
+use crate::payload::SharedHistory;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::explorer::query_value;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/rrdp-history" {
+        return None
+    }
+    let uri = match query_value(req.uri().query(), "uri") {
+        Some(uri) => uri,
+        None => return Some(Response::bad_request()),
+    };
+    let metrics = match history.read().metrics() {
+        Some(metrics) => metrics,
+        None => return Some(Response::initial_validation()),
+    };
+    let rrdp = match metrics.rrdp.iter().find(|rrdp| {
+        rrdp.notify_uri.as_str() == uri
+    }) {
+        Some(rrdp) => rrdp,
+        None => return Some(Response::not_found()),
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(history_json(&metrics, rrdp)))
+    }
+}
+
+
+//------------ history_json --------------------------------------------------
+
+/// Returns the JSON for the update history of a repository.
+///
+/// The most recent update attempt is listed first.
+fn history_json(metrics: &Metrics, rrdp: &RrdpRepositoryMetrics) -> String {
+    JsonBuilder::build(|json| {
+        json.member_str("generatedTime", format_iso_date(metrics.time));
+        json.member_str("uri", &rrdp.notify_uri);
+        json.member_array("history", |json| {
+            for record in rrdp.history.iter() {
+                json.array_object(|json| {
+                    match record.time() {
+                        Some(time) => {
+                            json.member_str("time", format_iso_date(time))
+                        }
+                        None => json.member_raw("time", "null"),
+                    }
+                    json.member_str("outcome", record.outcome());
+                    match record.snapshot_reason {
+                        Some(reason) => {
+                            json.member_str("snapshotReason", reason.code())
+                        }
+                        None => json.member_raw("snapshotReason", "null"),
+                    }
+                })
+            }
+        });
+    })
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use rpki::uri;
+    use crate::collector::{SnapshotReason, UpdateRecord};
+    use crate::metrics::RrdpUpdateKind;
+
+    #[test]
+    fn history_json_output() {
+        let mut rrdp = RrdpRepositoryMetrics::new(
+            uri::Https::from_str(
+                "https://rrdp.example.net/notification.xml"
+            ).unwrap()
+        );
+        rrdp.history.push(UpdateRecord {
+            timestamp: 1_700_000_000,
+            kind: Some(RrdpUpdateKind::Snapshot),
+            rate_limited: false,
+            snapshot_reason: Some(SnapshotReason::NewSession),
+        });
+        rrdp.history.push(UpdateRecord {
+            timestamp: 1_700_000_600,
+            kind: None,
+            rate_limited: false,
+            snapshot_reason: None,
+        });
+        let json: serde_json::Value = serde_json::from_str(
+            &history_json(&Metrics::new(), &rrdp)
+        ).unwrap();
+        assert_eq!(
+            json["uri"], "https://rrdp.example.net/notification.xml"
+        );
+        assert_eq!(
+            json["history"],
+            serde_json::json!([
+                {
+                    "time": "2023-11-14T22:23:20Z",
+                    "outcome": "failed",
+                    "snapshotReason": null,
+                },
+                {
+                    "time": "2023-11-14T22:13:20Z",
+                    "outcome": "snapshot",
+                    "snapshotReason": "new-session",
+                },
+            ])
+        );
+    }
+}
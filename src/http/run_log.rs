@@ -0,0 +1,33 @@
+//! Handling of the endpoint providing the journal of the last run.
+
+use crate::process::RunJournal;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    journal: Option<&RunJournal>,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/run-log" {
+        return None
+    }
+    let journal = match journal {
+        Some(journal) => journal,
+        None => return Some(Response::not_found()),
+    };
+    let current = match journal.current() {
+        Some(current) => current,
+        None => return Some(Response::initial_validation()),
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON_LINES);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(current.content.clone()))
+    }
+}
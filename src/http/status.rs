@@ -4,12 +4,15 @@ use std::cmp;
 use chrono::{DateTime, Duration, Utc};
 use clap::{crate_name, crate_version};
 use crate::metrics::{
-    HttpServerMetrics, PayloadMetrics, PublicationMetrics,
+    ChurnMetrics, HttpServerMetrics, PayloadMetrics, PublicationMetrics,
     RtrClientMetrics, SharedRtrServerMetrics, VrpMetrics,
 };
 use crate::payload::SharedHistory;
+use crate::process::RunJournal;
+use crate::utils::date::format_iso_date;
 use crate::utils::fmt::WriteOrPanic;
 use crate::utils::json::JsonBuilder;
+use crate::utils::tls::{CertValidity, TlsConfigs};
 use super::request::Request;
 use super::response::{ContentType, Response, ResponseBuilder};
 
@@ -21,12 +24,21 @@ pub async fn handle_get_or_head(
     history: &SharedHistory,
     http: &HttpServerMetrics,
     rtr: &SharedRtrServerMetrics,
+    tls: &TlsConfigs,
+    journal: Option<&RunJournal>,
+    base_path: &str,
 ) -> Option<Response> {
     let head = req.is_head();
     match req.uri().path() {
-        "/status" => Some(handle_status(head, history, http, rtr).await),
+        "/status" => {
+            Some(handle_status(
+                head, history, http, rtr, tls, journal
+            ).await)
+        }
         "/api/v1/status" => {
-            Some(handle_api_status(head, history, http, rtr).await)
+            Some(handle_api_status(
+                head, history, http, rtr, tls, journal, base_path
+            ).await)
         },
         "/version" => Some(handle_version(head)),
         _ => None
@@ -41,19 +53,35 @@ async fn handle_status(
     history: &SharedHistory,
     server_metrics: &HttpServerMetrics,
     rtr_metrics: &SharedRtrServerMetrics,
+    tls: &TlsConfigs,
+    journal: Option<&RunJournal>,
 ) -> Response {
-    let (metrics, serial, start, done, duration, unsafe_vrps) = {
+    let (
+        metrics, churn, serial, start, done, duration, timed_out,
+        clock_skew, unsafe_vrps, exceptions_stale, vrp_guard, stored_data,
+        data_expired, failed_output_files, publish_metrics, integrity,
+    ) = {
         let history = history.read();
         (
             match history.metrics() {
                 Some(metrics) => metrics,
                 None => return Response::initial_validation(),
             },
+            history.churn_totals(),
             history.serial(),
             history.last_update_start(),
             history.last_update_done(),
             history.last_update_duration(),
+            history.last_update_timed_out(),
+            history.clock_skew(),
             history.unsafe_vrps(),
+            history.exceptions_stale(),
+            history.vrp_guard().clone(),
+            history.stored_data(),
+            history.data_expired(),
+            history.failed_output_files().to_vec(),
+            history.publish_metrics().to_vec(),
+            history.integrity_metrics().clone(),
         )
     };
 
@@ -102,6 +130,77 @@ async fn handle_status(
         writeln!(res, "last-update-duration:  -");
     }
 
+    // last-update-timed-out
+    writeln!(res, "last-update-timed-out: {}", timed_out);
+
+    // clock-skew
+    if let Some(skew) = clock_skew {
+        writeln!(res, "clock-skew: {}", skew);
+    }
+    else {
+        writeln!(res, "clock-skew: -");
+    }
+
+    // stored-data
+    writeln!(res, "stored-data: {}", stored_data);
+
+    // data-expired
+    writeln!(res, "data-expired: {}", data_expired);
+
+    // run-log and run-log-records
+    if let Some(journal) = journal {
+        match journal.current() {
+            Some(current) => {
+                writeln!(res, "run-log: {}", current.path.display());
+                writeln!(res, "run-log-records: {}", current.records);
+            }
+            None => {
+                writeln!(res, "run-log: -");
+                writeln!(res, "run-log-records: -");
+            }
+        }
+    }
+
+    // exceptions-stale
+    writeln!(res, "exceptions-stale: {}", exceptions_stale);
+
+    // failed-output-files
+    writeln!(res, "failed-output-files:");
+    for path in &failed_output_files {
+        writeln!(res, "   {}", path.display());
+    }
+
+    // publish-targets
+    writeln!(res, "publish-targets:");
+    for item in &publish_metrics {
+        write!(
+            res, "   {}: failures={}, last-success=",
+            item.url, item.failures
+        );
+        match item.last_success {
+            Some(time) => writeln!(res, "{}", time.format("%+")),
+            None => writeln!(res, "-"),
+        }
+    }
+
+    // archive-checks, archive-corrupt, and corrupt-archives
+    writeln!(res, "archive-checks: {}", integrity.checked);
+    writeln!(res, "archive-corrupt: {}", integrity.corrupt);
+    writeln!(res, "corrupt-archives:");
+    for (path, time) in &integrity.corrupt_archives {
+        writeln!(res, "   {}: found={}", path.display(), time.format("%+"));
+    }
+
+    // vrp-guard-active, -held-runs, and -held-vrps
+    writeln!(res, "vrp-guard-active: {}", vrp_guard.is_active());
+    writeln!(res, "vrp-guard-held-runs: {}", vrp_guard.held_runs());
+    if let Some(vrps) = vrp_guard.held_vrps() {
+        writeln!(res, "vrp-guard-held-vrps: {}", vrps);
+    }
+    else {
+        writeln!(res, "vrp-guard-held-vrps: -");
+    }
+
     // valid-roas
     writeln!(
         res, "valid-roas: {}", metrics.publication.valid_roas
@@ -124,6 +223,48 @@ async fn handle_status(
     }
     writeln!(res);
 
+    // vrps-ipv4-per-tal, vrps-ipv6-per-tal, router-keys-per-tal,
+    // aspas-per-tal
+    type Select = fn(&PayloadMetrics) -> &VrpMetrics;
+    let types: [(&str, Select); 4] = [
+        ("vrps-ipv4", |p| &p.v4_origins),
+        ("vrps-ipv6", |p| &p.v6_origins),
+        ("router-keys", |p| &p.router_keys),
+        ("aspas", |p| &p.aspas),
+    ];
+    for (type_name, select) in types {
+        write!(res, "{}-per-tal: ", type_name);
+        for tal in &metrics.tals {
+            write!(res, "{}={} ", tal.name(), select(&tal.payload).valid);
+        }
+        writeln!(res);
+    }
+
+    // origins-added and origins-removed
+    writeln!(res, "origins-added: {}", metrics.churn.added);
+    writeln!(res, "origins-removed: {}", metrics.churn.removed);
+
+    // origins-added-per-tal and origins-removed-per-tal
+    write!(res, "origins-added-per-tal: ");
+    for tal in &metrics.tals {
+        write!(res, "{}={} ", tal.name(), tal.churn.added);
+    }
+    writeln!(res);
+    write!(res, "origins-removed-per-tal: ");
+    for tal in &metrics.tals {
+        write!(res, "{}={} ", tal.name(), tal.churn.removed);
+    }
+    writeln!(res);
+
+    // recent-origin-churn
+    writeln!(res, "recent-origin-churn:");
+    for (time, item) in churn.recent() {
+        writeln!(
+            res, "   {}: added={}, removed={}",
+            time.format("%+"), item.added, item.removed
+        );
+    }
+
     if unsafe_vrps.log() {
         // unsafe-filtered-vrps
         writeln!(res,
@@ -131,6 +272,12 @@ async fn handle_status(
             metrics.snapshot.payload.vrps().marked_unsafe
         );
 
+        // unsafe-vrps-listed
+        writeln!(res,
+            "unsafe-vrps-listed: {}",
+            metrics.unsafe_vrps.len()
+        );
+
         // unsafe-vrps-per-tal
         write!(res, "unsafe-filtered-vrps-per-tal: ");
         for tal in &metrics.tals {
@@ -143,6 +290,14 @@ async fn handle_status(
         writeln!(res);
     }
 
+    // outside-resources-payload
+    writeln!(res,
+        "outside-resources-payload: {}",
+        metrics.snapshot.payload.vrps().outside_resources
+        + metrics.snapshot.payload.router_keys.outside_resources
+        + metrics.snapshot.payload.aspas.outside_resources
+    );
+
     // locally-filtered-vrps
     writeln!(res,
         "locally-filtered-vrps: {}",
@@ -192,6 +347,81 @@ async fn handle_status(
         res, "stale-count: {}", metrics.publication.stale_objects()
     );
 
+    // stale-grace-count
+    writeln!(
+        res, "stale-grace-count: {}",
+        metrics.publication.stale_grace_objects()
+    );
+
+    // min-ca-cert-expiry-per-tal
+    write!(res, "min-ca-cert-expiry-per-tal: ");
+    for tal in &metrics.tals {
+        if let Some(time) = tal.publication.min_ca_cert_expiry {
+            write!(res, "{}={} ", tal.name(), format_iso_date(time.into()));
+        }
+    }
+    writeln!(res);
+
+    // min-next-update-per-tal
+    write!(res, "min-next-update-per-tal: ");
+    for tal in &metrics.tals {
+        if let Some(time) = tal.publication.min_next_update {
+            write!(res, "{}={} ", tal.name(), format_iso_date(time.into()));
+        }
+    }
+    writeln!(res);
+
+    // update-skipped-tals
+    write!(res, "update-skipped-tals: ");
+    for tal in &metrics.tals {
+        if tal.update_skipped {
+            write!(res, "{} ", tal.name());
+        }
+    }
+    writeln!(res);
+
+    // ta-key-mismatch-tals
+    write!(res, "ta-key-mismatch-tals: ");
+    for tal in &metrics.tals {
+        if tal.ta_key_mismatch {
+            write!(res, "{} ", tal.name());
+        }
+    }
+    writeln!(res);
+
+    // repositories-limit-exceeded
+    write!(res, "repositories-limit-exceeded: ");
+    for uri in metrics.limit_exceeded_repositories() {
+        write!(res, "{} ", uri);
+    }
+    writeln!(res);
+
+    // payload-per-repository
+    writeln!(res, "payload-per-repository:");
+    for repo in &metrics.repositories {
+        writeln!(
+            res,
+            "   {}: vrps-ipv4={}, vrps-ipv6={}, router-keys={}, aspas={}",
+            repo.uri,
+            repo.payload.v4_origins.valid,
+            repo.payload.v6_origins.valid,
+            repo.payload.router_keys.valid,
+            repo.payload.aspas.valid,
+        );
+    }
+
+    // objects-per-tal and objects-per-repository
+    writeln!(res, "objects-per-tal:");
+    for tal in &metrics.tals {
+        write!(res, "   {}:", tal.name());
+        write_object_counts(&mut res, &tal.publication);
+    }
+    writeln!(res, "objects-per-repository:");
+    for repo in &metrics.repositories {
+        write!(res, "   {}:", repo.uri);
+        write_object_counts(&mut res, &repo.publication);
+    }
+
     // rsync_status
     writeln!(res, "rsync-durations:");
     for metrics in &metrics.rsync {
@@ -205,16 +435,20 @@ async fn handle_status(
             }
         );
         if let Ok(duration) = metrics.duration {
-            writeln!(
+            write!(
                 res,
                 ", duration={:.3}s",
                 duration.as_secs() as f64
                 + f64::from(duration.subsec_millis()) / 1000.
             );
         }
-        else {
-            writeln!(res)
+        if metrics.rrdp_fallback {
+            write!(res, ", rrdp-fallback")
         }
+        if metrics.unchanged {
+            write!(res, ", unchanged")
+        }
+        writeln!(res)
     }
 
     // rrdp_status
@@ -241,9 +475,89 @@ async fn handle_status(
         if let Some(serial) = metrics.serial {
             write!(res, ", serial={}", serial)
         }
+        if let Some(version) = metrics.ip_version() {
+            write!(res, ", ip-version={}", version)
+        }
+        if let Some(kind) = metrics.update_kind {
+            write!(res, ", update={}", kind.code())
+        }
+        if let Some(time) = metrics.notify_last_modified {
+            write!(res, ", last-modified={}", format_iso_date(time))
+        }
+        if let Some(delay) = metrics.notify_delay() {
+            write!(res, ", delay={}s", delay)
+        }
+        if let Some(lag) = metrics.serial_lag {
+            write!(res, ", serial-lag={}", lag)
+        }
         writeln!(res)
     }
 
+    // rrdp-backoff
+    writeln!(res, "rrdp-backoff:");
+    for item in &metrics.rrdp_backoff {
+        writeln!(
+            res,
+            "   {}: failures={}, until={}",
+            item.notify_uri, item.failures, item.until.format("%+")
+        );
+    }
+
+    // manifest-replays
+    writeln!(res, "manifest-replays:");
+    for item in &metrics.manifest_replays {
+        writeln!(
+            res,
+            "   {}: number={}, this-update={}, stored-number={}, \
+             stored-this-update={}{}",
+            item.manifest_uri,
+            item.collected_number,
+            item.collected_this_update.format("%+"),
+            item.stored_number,
+            item.stored_this_update.format("%+"),
+            if item.rejected { ", rejected" } else { "" }
+        );
+    }
+
+    // slowest-repositories
+    writeln!(res, "slowest-repositories:");
+    for repo in metrics.slowest_repositories() {
+        writeln!(
+            res,
+            "   {}: fetch={:.3}s, validation={:.3}s",
+            repo.uri,
+            repo.fetch_duration.as_secs_f64(),
+            repo.validation_duration.as_secs_f64(),
+        );
+    }
+
+    // churning-repositories
+    writeln!(res, "churning-repositories:");
+    for (uri, item) in &metrics.churning_repositories {
+        writeln!(
+            res, "   {}: added={}, removed={}",
+            uri, item.added, item.removed
+        );
+    }
+
+    // cache-size
+    if let Some(usage) = metrics.cache_usage.as_ref() {
+        write!(res, "cache-size: {} bytes", usage.total());
+        if let Some(max_size) = usage.max_size {
+            write!(res, ", maximum {} bytes", max_size);
+            if usage.is_exceeded() {
+                write!(res, ", exceeded");
+            }
+        }
+        writeln!(res);
+
+        // largest-cache-repositories
+        writeln!(res, "largest-cache-repositories:");
+        for (uri, size) in usage.largest_repositories() {
+            writeln!(res, "   {}: {} bytes", uri, size);
+        }
+    }
+
     let detailed_rtr = rtr_metrics.detailed();
     let rtr_metrics = rtr_metrics.read().await;
 
@@ -293,10 +607,23 @@ async fn handle_status(
             else {
                 write!(res, "last-reset=N/A, ");
             }
+            if let Some(version) = data.version {
+                write!(res, "version={}, ", version);
+            }
+            else {
+                write!(res, "version=N/A, ");
+            }
+            if let Some(serial) = data.requested_serial {
+                write!(res, "requested-serial={}, ", serial);
+            }
+            else {
+                write!(res, "requested-serial=N/A, ");
+            }
             writeln!(res,
-                "reset-queries={}, serial-queries={}, read={}, written={}",
+                "reset-queries={}, serial-queries={}, read={}, written={}, \
+                 pdus-sent={}",
                 data.reset_queries, data.serial_queries,
-                data.bytes_read, data.bytes_written,
+                data.bytes_read, data.bytes_written, data.pdus_sent,
             );
         });
     }
@@ -317,6 +644,16 @@ async fn handle_status(
         server_metrics.requests()
     );
 
+    // rtr-tls-certificate and http-tls-certificate
+    for (service, label) in [("RTR", "rtr"), ("HTTP", "http")] {
+        if let Some(validity) = tls.validity(service) {
+            writeln!(res,
+                "{}-tls-certificate: not-before={}, not-after={}",
+                label, validity.not_before, validity.not_after
+            );
+        }
+    }
+
     ResponseBuilder::ok().content_type(ContentType::TEXT).body(res)
 }
 
@@ -328,18 +665,35 @@ async fn handle_api_status(
     history: &SharedHistory,
     server_metrics: &HttpServerMetrics,
     rtr_metrics: &SharedRtrServerMetrics,
+    tls: &TlsConfigs,
+    journal: Option<&RunJournal>,
+    base_path: &str,
 ) -> Response {
-    let (metrics, serial, start, done, duration) = {
+    let (
+        metrics, churn, serial, start, done, duration, timed_out,
+        clock_skew, exceptions_stale, vrp_guard, stored_data, data_expired,
+        failed_output_files, publish_metrics, integrity,
+    ) = {
         let history = history.read();
         (
             match history.metrics() {
                 Some(metrics) => metrics,
                 None => return Response::initial_validation()
             },
+            history.churn_totals(),
             history.serial(),
             history.last_update_start(),
             history.last_update_done(),
             history.last_update_duration(),
+            history.last_update_timed_out(),
+            history.clock_skew(),
+            history.exceptions_stale(),
+            history.vrp_guard().clone(),
+            history.stored_data(),
+            history.data_expired(),
+            history.failed_output_files().to_vec(),
+            history.publish_metrics().to_vec(),
+            history.integrity_metrics().clone(),
         )
     };
 
@@ -372,6 +726,76 @@ async fn handle_api_status(
         else {
             target.member_raw("lastUpdateDuration", "null");
         }
+        target.member_raw("lastUpdateTimedOut", timed_out);
+        match clock_skew {
+            Some(skew) => target.member_raw("clockSkew", skew),
+            None => target.member_raw("clockSkew", "null"),
+        }
+        target.member_raw("storedData", stored_data);
+        target.member_raw("dataExpired", data_expired);
+        match journal.and_then(RunJournal::current) {
+            Some(current) => {
+                target.member_object("runLog", |target| {
+                    target.member_str(
+                        "uri", format_args!("{}/api/v1/run-log", base_path)
+                    );
+                    target.member_str("file", current.path.display());
+                    target.member_raw("records", current.records);
+                });
+            }
+            None => target.member_raw("runLog", "null"),
+        }
+        target.member_raw("exceptionsStale", exceptions_stale);
+        target.member_array("failedOutputFiles", |target| {
+            for path in &failed_output_files {
+                target.array_str(path.display());
+            }
+        });
+        target.member_array("publishTargets", |target| {
+            for item in &publish_metrics {
+                target.array_object(|target| {
+                    target.member_str("url", &item.url);
+                    target.member_raw("failures", item.failures);
+                    for (key, value) in [
+                        ("lastSuccess", item.last_success),
+                        ("lastFailure", item.last_failure),
+                    ] {
+                        match value {
+                            Some(time) => {
+                                target.member_str(key, time.format("%+"))
+                            }
+                            None => target.member_raw(key, "null"),
+                        }
+                    }
+                });
+            }
+        });
+        target.member_object("integrityCheck", |target| {
+            target.member_raw("checked", integrity.checked);
+            target.member_raw("corrupt", integrity.corrupt);
+            match integrity.last_pass {
+                Some(time) => {
+                    target.member_str("lastPass", time.format("%+"))
+                }
+                None => target.member_raw("lastPass", "null"),
+            }
+            target.member_array("corruptArchives", |target| {
+                for (path, time) in &integrity.corrupt_archives {
+                    target.array_object(|target| {
+                        target.member_str("path", path.display());
+                        target.member_str("found", time.format("%+"));
+                    });
+                }
+            });
+        });
+        target.member_object("vrpGuard", |target| {
+            target.member_raw("active", vrp_guard.is_active());
+            target.member_raw("heldRuns", vrp_guard.held_runs());
+            match vrp_guard.held_vrps() {
+                Some(vrps) => target.member_raw("heldVrps", vrps),
+                None => target.member_raw("heldVrps", "null"),
+            }
+        });
 
         json_payload_metrics(target, &metrics.snapshot.payload);
 
@@ -380,6 +804,60 @@ async fn handle_api_status(
             metrics.snapshot.large_aspas
         );
 
+        target.member_array("repositoriesLimitExceeded", |target| {
+            for uri in metrics.limit_exceeded_repositories() {
+                target.array_str(uri);
+            }
+        });
+
+        target.member_array("slowestRepositories", |target| {
+            for repo in metrics.slowest_repositories() {
+                target.array_str(&repo.uri);
+            }
+        });
+
+        target.member_object("originChurn", |target| {
+            json_churn_metrics(target, metrics.churn);
+            target.member_object("tals", |target| {
+                for tal in &metrics.tals {
+                    target.member_object(tal.name(), |target| {
+                        json_churn_metrics(target, tal.churn);
+                    });
+                }
+            });
+            target.member_array("recent", |target| {
+                for (time, item) in churn.recent() {
+                    target.array_object(|target| {
+                        target.member_str("time", time.format("%+"));
+                        json_churn_metrics(target, item);
+                    });
+                }
+            });
+            target.member_object("churningRepositories", |target| {
+                for (uri, item) in &metrics.churning_repositories {
+                    target.member_object(uri, |target| {
+                        json_churn_metrics(target, *item);
+                    });
+                }
+            });
+        });
+
+        if let Some(usage) = metrics.cache_usage.as_ref() {
+            target.member_object("cacheUsage", |target| {
+                target.member_raw("size", usage.total());
+                match usage.max_size {
+                    Some(max_size) => target.member_raw("maxSize", max_size),
+                    None => target.member_raw("maxSize", "null"),
+                }
+                target.member_raw("exceeded", usage.is_exceeded());
+                target.member_object("largestRepositories", |target| {
+                    for (uri, size) in usage.largest_repositories() {
+                        target.member_raw(uri, size);
+                    }
+                });
+            });
+        }
+
         target.member_object("tals", |target| {
             for tal in &metrics.tals {
                 target.member_object(tal.tal.name(), |target| {
@@ -387,6 +865,17 @@ async fn handle_api_status(
                     json_publication_metrics(
                         target, &tal.publication
                     );
+                    target.member_raw(
+                        "updateSkipped", tal.update_skipped
+                    );
+                    match tal.ta_uri.as_ref() {
+                        Some(uri) => target.member_str("taUri", uri),
+                        None => target.member_raw("taUri", "null"),
+                    }
+                    target.member_raw("taStored", tal.ta_stored);
+                    target.member_raw(
+                        "taKeyMismatch", tal.ta_key_mismatch
+                    );
                 });
             }
         });
@@ -403,6 +892,16 @@ async fn handle_api_status(
                     else {
                         target.member_str("type", "other");
                     }
+                    target.member_raw("fetchDuration",
+                        format_args!(
+                            "{:.3}", repo.fetch_duration.as_secs_f32()
+                        )
+                    );
+                    target.member_raw("validationDuration",
+                        format_args!(
+                            "{:.3}", repo.validation_duration.as_secs_f32()
+                        )
+                    );
                     json_compat_payload_metrics(target, &repo.payload);
                     json_publication_metrics(
                         target, &repo.publication
@@ -433,6 +932,25 @@ async fn handle_api_status(
                         }
                         Err(_) => target.member_raw("duration", "null")
                     }
+                    target.member_raw("queueDuration",
+                        format_args!(
+                            "{:.3}", metrics.queue_duration.as_secs_f32()
+                        )
+                    );
+                    target.member_raw("rrdpFallback", metrics.rrdp_fallback);
+                    target.member_raw("unchanged", metrics.unchanged);
+                    match metrics.bytes_received {
+                        Some(bytes) => {
+                            target.member_raw("bytesReceived", bytes)
+                        }
+                        None => target.member_raw("bytesReceived", "null")
+                    }
+                    match metrics.stderr {
+                        Some(ref stderr) => {
+                            target.member_str("stderr", stderr)
+                        }
+                        None => target.member_raw("stderr", "null")
+                    }
                 })
             }
         });
@@ -462,6 +980,51 @@ async fn handle_api_status(
                         }
                         Err(_) => target.member_raw("duration", "null")
                     }
+                    target.member_raw("queueDuration",
+                        format_args!(
+                            "{:.3}", metrics.queue_duration.as_secs_f32()
+                        )
+                    );
+                    match metrics.ip_version() {
+                        Some(version) => {
+                            target.member_raw("ipVersion", version)
+                        }
+                        None => target.member_raw("ipVersion", "null")
+                    }
+                    match metrics.update_kind {
+                        Some(kind) => {
+                            target.member_str("updateType", kind.code())
+                        }
+                        None => target.member_raw("updateType", "null")
+                    }
+                    match metrics.notify_last_modified {
+                        Some(time) => {
+                            target.member_str(
+                                "notifyLastModified", time.format("%+")
+                            )
+                        }
+                        None => {
+                            target.member_raw("notifyLastModified", "null")
+                        }
+                    }
+                    match metrics.notify_processed {
+                        Some(time) => {
+                            target.member_str(
+                                "notifyProcessed", time.format("%+")
+                            )
+                        }
+                        None => target.member_raw("notifyProcessed", "null")
+                    }
+                    match metrics.notify_delay() {
+                        Some(delay) => {
+                            target.member_raw("notifyDelay", delay)
+                        }
+                        None => target.member_raw("notifyDelay", "null")
+                    }
+                    match metrics.serial_lag {
+                        Some(lag) => target.member_raw("serialLag", lag),
+                        None => target.member_raw("serialLag", "null")
+                    }
                     if !metrics.status().is_not_modified() {
                         match metrics.serial {
                             Some(serial) => {
@@ -490,6 +1053,38 @@ async fn handle_api_status(
             }
         });
 
+        target.member_object("rrdpBackoff", |target| {
+            for item in &metrics.rrdp_backoff {
+                target.member_object(&item.notify_uri, |target| {
+                    target.member_raw("failures", item.failures);
+                    target.member_str("until", item.until.format("%+"));
+                })
+            }
+        });
+
+        target.member_object("manifestReplays", |target| {
+            for item in &metrics.manifest_replays {
+                target.member_object(&item.manifest_uri, |target| {
+                    target.member_str("ta", item.tal.name());
+                    target.member_str(
+                        "manifestNumber", item.collected_number
+                    );
+                    target.member_str(
+                        "thisUpdate",
+                        item.collected_this_update.format("%+")
+                    );
+                    target.member_str(
+                        "storedManifestNumber", item.stored_number
+                    );
+                    target.member_str(
+                        "storedThisUpdate",
+                        item.stored_this_update.format("%+")
+                    );
+                    target.member_raw("rejected", item.rejected);
+                })
+            }
+        });
+
         target.member_object("rtr", |target| {
             target.member_raw(
                 "currentConnections",
@@ -501,6 +1096,7 @@ async fn handle_api_status(
             target.member_raw(
                 "bytesWritten", rtr_metrics.bytes_written()
             );
+            json_tls_validity(target, tls.validity("RTR"));
 
             if detailed_rtr {
                 target.member_object("clients", |target| {
@@ -548,6 +1144,29 @@ async fn handle_api_status(
                                 target.member_raw(
                                     "written", data.bytes_written
                                 );
+                                match data.version {
+                                    Some(version) => {
+                                        target.member_raw("version", version)
+                                    }
+                                    None => {
+                                        target.member_raw("version", "null")
+                                    }
+                                }
+                                match data.requested_serial {
+                                    Some(serial) => {
+                                        target.member_raw(
+                                            "requestedSerial", serial
+                                        )
+                                    }
+                                    None => {
+                                        target.member_raw(
+                                            "requestedSerial", "null"
+                                        )
+                                    }
+                                }
+                                target.member_raw(
+                                    "pdusSent", data.pdus_sent
+                                );
                             })
                         }
                     );
@@ -573,12 +1192,42 @@ async fn handle_api_status(
             target.member_raw(
                 "bytesWritten", server_metrics.bytes_written()
             );
+            json_tls_validity(target, tls.validity("HTTP"));
         });
     });
    
     ResponseBuilder::ok().content_type(ContentType::JSON).body(res)
 }
 
+fn json_tls_validity(
+    target: &mut JsonBuilder, validity: Option<CertValidity>
+) {
+    if let Some(validity) = validity {
+        target.member_object("tlsCertificate", |target| {
+            target.member_str(
+                "notBefore", validity.not_before.format("%+")
+            );
+            target.member_str(
+                "notAfter", validity.not_after.format("%+")
+            );
+        });
+    }
+}
+
+/// Writes the accepted and rejected object counts by type as one line.
+fn write_object_counts(res: &mut String, metrics: &PublicationMetrics) {
+    let mut first = true;
+    for (object_type, counts) in metrics.object_type_counts() {
+        write!(
+            res, "{} {}={}/{}",
+            if first { "" } else { "," },
+            object_type, counts.accepted, counts.rejected
+        );
+        first = false;
+    }
+    writeln!(res);
+}
+
 fn json_publication_metrics(
     target: &mut JsonBuilder, metrics: &PublicationMetrics
 ) {
@@ -588,10 +1237,14 @@ fn json_publication_metrics(
     target.member_raw("invalidManifests", metrics.invalid_manifests);
     target.member_raw("prematureManifests", metrics.premature_manifests);
     target.member_raw("staleManifests", metrics.stale_manifests);
+    target.member_raw(
+        "staleGraceManifests", metrics.stale_grace_manifests
+    );
     target.member_raw("missingManifests", metrics.missing_manifests);
     target.member_raw("validCRLs", metrics.valid_crls);
     target.member_raw("invalidCRLs", metrics.invalid_crls);
     target.member_raw("staleCRLs", metrics.stale_crls);
+    target.member_raw("staleGraceCRLs", metrics.stale_grace_crls);
     target.member_raw("strayCRLs", metrics.stray_crls);
     target.member_raw("validCACerts", metrics.valid_ca_certs);
 
@@ -607,6 +1260,29 @@ fn json_publication_metrics(
     target.member_raw("validASPAs", metrics.valid_aspas);
     target.member_raw("invalidGBRs", metrics.invalid_gbrs);
     target.member_raw("otherObjects", metrics.others);
+    target.member_object("objectTypes", |target| {
+        for (object_type, counts) in metrics.object_type_counts() {
+            target.member_object(object_type, |target| {
+                target.member_raw("seen", counts.seen());
+                target.member_raw("accepted", counts.accepted);
+                target.member_raw("rejected", counts.rejected);
+            });
+        }
+    });
+    match metrics.min_ca_cert_expiry {
+        Some(time) => {
+            target.member_str(
+                "minCaCertExpiry", format_iso_date(time.into())
+            )
+        }
+        None => target.member_raw("minCaCertExpiry", "null"),
+    }
+    match metrics.min_next_update {
+        Some(time) => {
+            target.member_str("minNextUpdate", format_iso_date(time.into()))
+        }
+        None => target.member_raw("minNextUpdate", "null"),
+    }
 }
 
 fn json_compat_payload_metrics(
@@ -614,6 +1290,9 @@ fn json_compat_payload_metrics(
 ) {
     target.member_raw("vrpsTotal", payload.vrps().valid);
     target.member_raw("vrpsUnsafe", payload.vrps().marked_unsafe);
+    target.member_raw(
+        "vrpsOutsideResources", payload.vrps().outside_resources
+    );
     target.member_raw("vrpsLocallyFiltered", payload.vrps().locally_filtered);
     target.member_raw("vrpsDuplicate", payload.vrps().duplicate);
     target.member_raw("vrpsFinal", payload.vrps().contributed);
@@ -640,6 +1319,11 @@ fn json_payload_metrics(
 }
 
 
+fn json_churn_metrics(target: &mut JsonBuilder, metrics: ChurnMetrics) {
+    target.member_raw("added", metrics.added);
+    target.member_raw("removed", metrics.removed);
+}
+
 fn json_vrps_metrics(
     target: &mut JsonBuilder,
     vrps: &VrpMetrics,
@@ -649,6 +1333,7 @@ fn json_vrps_metrics(
     if include_unsafe {
         target.member_raw("unsafe", vrps.marked_unsafe);
     }
+    target.member_raw("outsideResources", vrps.outside_resources);
     target.member_raw("locallyFiltered", vrps.locally_filtered);
     target.member_raw("duplicate", vrps.duplicate);
     target.member_raw("final", vrps.contributed);
@@ -681,6 +1366,9 @@ struct RtrClientStatus {
     serial_queries: u32,
     bytes_read: u64,
     bytes_written: u64,
+    version: Option<u8>,
+    requested_serial: Option<u32>,
+    pdus_sent: u64,
 }
 
 impl RtrClientStatus {
@@ -714,6 +1402,11 @@ impl RtrClientStatus {
         self.serial_queries += client.serial_queries();
         self.bytes_read += client.bytes_read();
         self.bytes_written += client.bytes_written();
+        self.version = cmp::max(self.version, client.version());
+        self.requested_serial = cmp::max(
+            self.requested_serial, client.requested_serial().map(u32::from)
+        );
+        self.pdus_sent += client.pdus_sent();
     }
 }
 
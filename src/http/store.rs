@@ -0,0 +1,161 @@
+//! Handling of the endpoint for the state of the store.
+
+use crate::metrics::{Metrics, StoredPointMetrics};
+use crate::payload::SharedHistory;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/store" {
+        return None
+    }
+    let query = match StoreQuery::from_query(req.uri().query()) {
+        Some(query) => query,
+        None => return Some(Response::bad_request()),
+    };
+    let metrics = match history.read().metrics() {
+        Some(metrics) => metrics,
+        None => return Some(Response::initial_validation()),
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(store_json(&query, &metrics)))
+    }
+}
+
+
+//------------ StoreQuery ----------------------------------------------------
+
+/// The parsed query of a request.
+///
+/// This is also used by the manifests endpoint.
+#[derive(Debug, Default)]
+pub(super) struct StoreQuery {
+    /// The name of the TAL to limit the output to.
+    tal: Option<String>,
+
+    /// The number of matching points to skip.
+    pub(super) offset: usize,
+
+    /// The maximum number of points to include.
+    pub(super) limit: Option<usize>,
+}
+
+impl StoreQuery {
+    /// Parses the query string.
+    ///
+    /// Returns `None` if the query is invalid.
+    pub(super) fn from_query(query: Option<&str>) -> Option<Self> {
+        let mut res = StoreQuery::default();
+        let query = match query {
+            Some(query) => query,
+            None => return Some(res),
+        };
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            if key == "ta" {
+                res.tal = Some(value.into_owned());
+            }
+            else if key == "offset" {
+                res.offset = value.parse().ok()?;
+            }
+            else if key == "limit" {
+                res.limit = Some(value.parse().ok()?);
+            }
+            else {
+                return None
+            }
+        }
+        Some(res)
+    }
+
+    /// Returns whether the point matches the query.
+    pub(super) fn matches(&self, point: &StoredPointMetrics) -> bool {
+        match self.tal.as_ref() {
+            Some(tal) => point.tal.name() == tal,
+            None => true,
+        }
+    }
+}
+
+
+//------------ store_json ----------------------------------------------------
+
+fn store_json(query: &StoreQuery, metrics: &Metrics) -> String {
+    let total = metrics.stored_points.iter().filter(|point| {
+        query.matches(point)
+    }).count();
+    let points = metrics.stored_points.iter().filter(|point| {
+        query.matches(point)
+    }).skip(query.offset).take(query.limit.unwrap_or(usize::MAX));
+
+    JsonBuilder::build(|json| {
+        json.member_str("generatedTime", format_iso_date(metrics.time));
+        json.member_raw("total", total);
+        json.member_raw("offset", query.offset);
+        json.member_array("points", |json| {
+            for point in points {
+                json.array_object(|json| {
+                    json.member_str("manifest", &point.manifest_uri);
+                    json.member_str("repository", point.repository());
+                    json.member_str("tal", point.tal.name());
+                    match point.refreshed {
+                        Some(time) => {
+                            json.member_str(
+                                "refreshed", format_iso_date(time)
+                            )
+                        }
+                        None => json.member_raw("refreshed", "null"),
+                    }
+                    json.member_str(
+                        "thisUpdate",
+                        format_iso_date(point.this_update.into())
+                    );
+                    json.member_str(
+                        "nextUpdate",
+                        format_iso_date(point.next_update.into())
+                    );
+                    json.member_raw("fallback", point.fallback);
+                })
+            }
+        });
+    })
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_query() {
+        let query = StoreQuery::from_query(None).unwrap();
+        assert!(query.tal.is_none());
+        assert_eq!(query.offset, 0);
+        assert!(query.limit.is_none());
+
+        let query = StoreQuery::from_query(
+            Some("ta=ripe&offset=100&limit=50")
+        ).unwrap();
+        assert_eq!(query.tal.as_deref(), Some("ripe"));
+        assert_eq!(query.offset, 100);
+        assert_eq!(query.limit, Some(50));
+
+        assert!(StoreQuery::from_query(Some("offset=foo")).is_none());
+        assert!(StoreQuery::from_query(Some("limit=-1")).is_none());
+        assert!(StoreQuery::from_query(Some("foo=bar")).is_none());
+    }
+}
@@ -1,14 +1,22 @@
 //! Handling of endpoints related to the UI.
 //!
-//! The frontend is served on BASE_DIR by including all web resources (html,
-//! css, js) from a single vec of structs; each struct holds a bytes array
-//! that represents a single file from the web resources.
-#![cfg(feature = "ui")]
+//! The frontend is served on BASE_URL. If the `ui-path` option is given,
+//! its files are taken from that directory. Otherwise, if the `ui` feature
+//! is enabled, all web resources (html, css, js) are included from a single
+//! vec of structs; each struct holds a bytes array that represents a single
+//! file from the web resources.
+//!
+//! If neither is available, the root path returns a plain text index of
+//! the available endpoints instead.
+//!
+//! If the HTTP server runs under a base path, the absolute paths to the
+//! assets in HTML files are prefixed with it.
 
-use std::path::Path;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::config::Config;
 use super::request::Request;
 use super::response::{ContentType, Response, ResponseBuilder};
-use self::assets::{ASSETS, Asset};
 
 /// Sensible settings for BASE_URL are either:
 /// * `"/"`: just route everything from the domain-name without further ado,
@@ -21,62 +29,306 @@ const BASE_URL: &str = "/ui";
 /// BASE_URL will be redirected to. All other URLs will return a 404.
 const CATCH_ALL_URL: &str = "index.html";
 
-pub fn handle_get_or_head(req: &Request) -> Option<Response> {
-    let head = req.is_head();
-    if req.uri().path() == "/" {
-        return Some(Response::moved_permanently("/ui/"))
-    }
-
-    let req_path = Path::new(req.uri().path());
-    if let Ok(p) = req_path.strip_prefix(BASE_URL) {
-        match get_asset(p) {
-            Some(asset) => Some(serve(head, asset)),
-            None => {
-                // In order to have the frontend handle all routing and
-                // queryparams under BASE_URL, all unknown URLs that start
-                // with BASE_URL will route to CATCH_ALL_URL.
-                //
-                // Note that we could be smarter about this and do a
-                // (somewhat convoluted) regex on the requested URL to figure
-                // out if it makes sense as a search prefix url.
-                if let Some(default) = get_asset(Path::new(CATCH_ALL_URL)) {
-                    Some(serve(head, default))
-                }
-                else {
-                    // if CATCH_ALL_URL is not defined in ui_resources
-                    // we'll return a 404
-                    Some(Response::not_found())
-                }
+/// The media types of the files served from the UI directory.
+///
+/// Files with other extensions are served as `application/octet-stream`.
+const MEDIA_TYPES: &[(&str, &[u8])] = &[
+    ("css", b"text/css"),
+    ("html", b"text/html"),
+    ("ico", b"image/x-icon"),
+    ("js", b"text/javascript"),
+    ("json", b"application/json"),
+    ("png", b"image/png"),
+    ("svg", b"image/svg+xml"),
+    ("woff", b"font/woff"),
+    ("woff2", b"font/woff2"),
+];
+
+/// The Cache-Control value for files that may change at any time.
+///
+/// This is used for HTML files and everything served from the UI
+/// directory so that changes to it are picked up right away.
+const CACHE_REVALIDATE: &str = "no-cache";
+
+/// The Cache-Control value for the embedded assets other than HTML.
+#[cfg(feature = "ui")]
+const CACHE_EMBEDDED: &str = "public, max-age=86400";
+
+/// The index returned for the root path if there is no UI.
+const ENDPOINT_INDEX: &str = "\
+Routinator HTTP service
+
+The UI is not available. The following endpoints are provided:
+
+/csv, /csvcompat, /csvext, /json, /jsonext, /json-v2, /slurm, /openbgpd,
+/bird1, /bird2, /rpsl, /summary, /router-keys-csv, /router-keys-json
+    The current data set in the given output format.
+/api/v1/vrps.json
+    The current data set in the versioned json-v2 format.
+/json-delta
+    Changes to the data set in JSON.
+/metrics
+    Metrics in Prometheus format.
+/status, /api/v1/status
+    Status information in plain text or JSON.
+/version
+    The version of Routinator.
+/log
+    The log of the last validation run.
+/validity, /api/v1/validity/
+    Route origin validity checks.
+/api/v1/health
+    A health check.
+/api/v1/prefixes, /api/v1/asns
+    All payload related to a prefix or an AS.
+/api/v1/vrp-origins
+    The objects that matching VRPs were derived from.
+/api/v1/origins/, /api/v1/store, /api/v1/unsafe-vrps,
+/api/v1/cert-info, /api/v1/run-log
+    Information on the data of the last validation run.
+";
+
+
+//------------ State ---------------------------------------------------------
+
+pub struct State {
+    /// The directory to serve the UI from if given.
+    path: Option<PathBuf>,
+
+    /// The base path of the HTTP server.
+    base: String,
+}
+
+impl State {
+    pub fn new(config: &Config) -> Self {
+        State {
+            path: config.ui_path.clone(),
+            base: config.http_base_path.to_string(),
+        }
+    }
+
+    pub fn handle_get_or_head(&self, req: &Request) -> Option<Response> {
+        let head = req.is_head();
+        if req.uri().path() == "/" {
+            if self.path.is_some() || cfg!(feature = "ui") {
+                return Some(Response::moved_permanently(
+                    &format!("{}{}/", self.base, BASE_URL)
+                ))
+            }
+            return Some(serve_index(head))
+        }
+
+        // This is the last handler in the chain, so if the requested URL
+        // does not start with BASE_URL, we're returning 404.
+        let req_path = Path::new(req.uri().path());
+        let path = match req_path.strip_prefix(BASE_URL) {
+            Ok(path) => path,
+            Err(_) => return Some(Response::not_found())
+        };
+
+        if let Some(base) = self.path.as_ref() {
+            return Some(serve_dir(head, base, path, &self.base))
+        }
+
+        #[cfg(feature = "ui")]
+        {
+            Some(serve_embedded(head, path, &self.base))
+        }
+        #[cfg(not(feature = "ui"))]
+        {
+            Some(Response::not_found())
+        }
+    }
+}
+
+
+//------------ Serving from a Directory --------------------------------------
+
+/// Serves a file from the UI directory `base`.
+///
+/// In order to have the frontend handle all routing and queryparams under
+/// BASE_URL, all unknown URLs will route to CATCH_ALL_URL.
+fn serve_dir(
+    head: bool, base: &Path, path: &Path, base_path: &str
+) -> Response {
+    let file = match dir_file_path(base, path) {
+        Some(file) => file,
+        None => return Response::not_found(),
+    };
+    if let Some(res) = serve_file(head, &file, base_path) {
+        return res
+    }
+    match serve_file(head, &base.join(CATCH_ALL_URL), base_path) {
+        Some(res) => res,
+        None => Response::not_found(),
+    }
+}
+
+/// Returns the path of the file for the request path below `base`.
+///
+/// Only plain path components are accepted, so the returned path never
+/// points outside of `base`. Returns `None` for anything else.
+fn dir_file_path(base: &Path, path: &Path) -> Option<PathBuf> {
+    let mut res = base.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => res.push(name),
+            Component::CurDir => { }
+            _ => return None,
+        }
+    }
+    Some(res)
+}
+
+/// Serves a single file if it exists.
+fn serve_file(head: bool, path: &Path, base_path: &str) -> Option<Response> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None
+    }
+    let media_type = media_type(path);
+    let mut res = ResponseBuilder::ok().content_type(
+        ContentType::external(media_type)
+    ).cache_control(CACHE_REVALIDATE);
+    if let Ok(modified) = metadata.modified() {
+        res = res.last_modified(modified.into())
+    }
+    if head {
+        Some(res.empty())
+    }
+    else {
+        let content = fs::read(path).ok()?;
+        if media_type == b"text/html" && !base_path.is_empty() {
+            if let Ok(content) = std::str::from_utf8(&content) {
+                return Some(res.body(rebase_html(content, base_path)))
             }
         }
-    } else {
-        // This is the last handler in the chain, so if the requested URL did
-        // not start with BASE_URL, we're returning 404.
-        Some(Response::not_found())
+        Some(res.body(content))
     }
 }
 
-fn get_asset(path: &Path) -> Option<&Asset> {
+/// Returns the media type for a file based on its extension.
+fn media_type(path: &Path) -> &'static [u8] {
+    let ext = path.extension().and_then(|ext| ext.to_str());
+    MEDIA_TYPES.iter().find(|(type_ext, _)| {
+        Some(*type_ext) == ext
+    }).map(|(_, media_type)| *media_type).unwrap_or(
+        b"application/octet-stream"
+    )
+}
+
+
+/// Prefixes the absolute paths to UI assets in HTML with the base path.
+///
+/// The UI refers to its assets via absolute paths starting with BASE_URL
+/// in attribute values. These are only reachable below the base path.
+fn rebase_html(content: &str, base_path: &str) -> String {
+    content.replace(
+        &format!("=\"{}/", BASE_URL),
+        &format!("=\"{}{}/", base_path, BASE_URL),
+    )
+}
+
+
+//------------ Serving Embedded Assets ---------------------------------------
+
+#[cfg(feature = "ui")]
+fn serve_embedded(head: bool, path: &Path, base_path: &str) -> Response {
+    match get_asset(path).or_else(|| get_asset(Path::new(CATCH_ALL_URL))) {
+        Some(asset) => serve(head, asset, base_path),
+        None => {
+            // if CATCH_ALL_URL is not defined in ui_resources
+            // we'll return a 404
+            Response::not_found()
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+fn get_asset(path: &Path) -> Option<&assets::Asset> {
     let path = path.to_str()?;
-    ASSETS.iter().find(|asset| asset.path == path)
+    assets::ASSETS.iter().find(|asset| asset.path == path)
 }
 
 /// Creates the response from data and the content type.
-fn serve(head: bool, asset: &Asset) -> Response {
+#[cfg(feature = "ui")]
+fn serve(head: bool, asset: &assets::Asset, base_path: &str) -> Response {
     let res = ResponseBuilder::ok().content_type(
         ContentType::external(asset.media_type.as_bytes())
+    ).cache_control(
+        if asset.media_type == "text/html" { CACHE_REVALIDATE }
+        else { CACHE_EMBEDDED }
     );
     if head {
         res.empty()
     }
+    else if asset.media_type == "text/html" && !base_path.is_empty() {
+        match std::str::from_utf8(asset.content) {
+            Ok(content) => res.body(rebase_html(content, base_path)),
+            Err(_) => res.body(asset.content),
+        }
+    }
     else {
         res.body(asset.content)
     }
 }
 
-
-
+#[cfg(feature = "ui")]
 mod assets {
     include!(concat!(env!("OUT_DIR"), "/ui_assets.rs"));
 }
 
+
+//------------ Serving the Index ---------------------------------------------
+
+/// Creates the plain text index of available endpoints.
+fn serve_index(head: bool) -> Response {
+    let res = ResponseBuilder::ok().content_type(ContentType::TEXT);
+    if head {
+        res.empty()
+    }
+    else {
+        res.body(ENDPOINT_INDEX)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dir_file_path_stays_inside() {
+        let base = Path::new("/srv/ui");
+        assert_eq!(
+            dir_file_path(base, Path::new("js/app.js")),
+            Some(PathBuf::from("/srv/ui/js/app.js"))
+        );
+        assert_eq!(
+            dir_file_path(base, Path::new("./index.html")),
+            Some(PathBuf::from("/srv/ui/index.html"))
+        );
+        assert_eq!(dir_file_path(base, Path::new("")), Some(base.into()));
+        assert_eq!(dir_file_path(base, Path::new("../etc/passwd")), None);
+        assert_eq!(dir_file_path(base, Path::new("js/../../x")), None);
+        assert_eq!(dir_file_path(base, Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn rebase_html_assets() {
+        assert_eq!(
+            rebase_html(
+                "<link href=\"/ui/assets/a.css\">\
+                 <script src=\"/ui/assets/a.js\"></script>\
+                 <a href=\"/uix/\">/ui/</a>",
+                "/rpki"
+            ),
+            "<link href=\"/rpki/ui/assets/a.css\">\
+             <script src=\"/rpki/ui/assets/a.js\"></script>\
+             <a href=\"/uix/\">/ui/</a>"
+        );
+    }
+}
+
@@ -0,0 +1,155 @@
+//! Handling of the endpoint listing unsafe VRPs.
+
+use crate::config::FilterPolicy;
+use crate::metrics::Metrics;
+use crate::payload::SharedHistory;
+use crate::utils::date::format_iso_date;
+use crate::utils::json::JsonBuilder;
+use super::request::Request;
+use super::response::{ContentType, Response, ResponseBuilder};
+
+
+//------------ handle_get_or_head --------------------------------------------
+
+pub fn handle_get_or_head(
+    req: &Request,
+    history: &SharedHistory,
+) -> Option<Response> {
+    if req.uri().path() != "/api/v1/unsafe-vrps" {
+        return None
+    }
+    let (metrics, policy) = {
+        let history = history.read();
+        match history.metrics() {
+            Some(metrics) => (metrics, history.unsafe_vrps()),
+            None => return Some(Response::initial_validation()),
+        }
+    };
+
+    let res = ResponseBuilder::ok().content_type(ContentType::JSON);
+    if req.is_head() {
+        Some(res.empty())
+    }
+    else {
+        Some(res.body(unsafe_vrps_json(&metrics, policy)))
+    }
+}
+
+
+//------------ unsafe_vrps_json ----------------------------------------------
+
+fn unsafe_vrps_json(metrics: &Metrics, policy: FilterPolicy) -> String {
+    JsonBuilder::build(|json| {
+        json.member_str("generatedTime", format_iso_date(metrics.time));
+        json.member_str("policy", policy);
+        json.member_raw("tracked", policy.log());
+        if !policy.log() {
+            json.member_str(
+                "note",
+                "Unsafe VRPs are not tracked with the 'accept' policy."
+            );
+        }
+        let total = metrics.snapshot.payload.vrps().marked_unsafe;
+        json.member_raw("total", total);
+        json.member_raw(
+            "truncated", metrics.unsafe_vrps.len() < total as usize
+        );
+        json.member_array("vrps", |json| {
+            for vrp in &metrics.unsafe_vrps {
+                json.array_object(|json| {
+                    json.member_str("asn", vrp.origin.asn);
+                    json.member_str(
+                        "prefix", vrp.origin.prefix.prefix()
+                    );
+                    json.member_raw(
+                        "maxLength", vrp.origin.prefix.resolved_max_len()
+                    );
+                    json.member_str("ta", vrp.tal.name());
+                    match vrp.roa.as_ref() {
+                        Some(uri) => json.member_str("roa", uri),
+                        None => json.member_raw("roa", "null"),
+                    }
+                    json.member_array("rejectedCas", |json| {
+                        for conflict in &vrp.conflicts {
+                            json.array_object(|json| {
+                                json.member_str("uri", &conflict.ca);
+                                json.member_array("resources", |json| {
+                                    for block in &conflict.blocks {
+                                        if vrp.origin.is_v4() {
+                                            json.array_str(
+                                                block.display_v4()
+                                            );
+                                        }
+                                        else {
+                                            json.array_str(
+                                                block.display_v6()
+                                            );
+                                        }
+                                    }
+                                })
+                            })
+                        }
+                    })
+                })
+            }
+        })
+    })
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use rpki::uri;
+    use rpki::repository::resources::IpBlock;
+    use rpki::repository::tal::{TalInfo, TalUri};
+    use rpki::resources::{Asn, MaxLenPrefix, Prefix};
+    use rpki::rtr::payload::RouteOrigin;
+    use crate::metrics::{UnsafeVrpConflict, UnsafeVrpMetrics};
+
+    #[test]
+    fn json() {
+        let prefix = Prefix::from_str("192.0.2.0/24").unwrap();
+        let mut metrics = Metrics::new();
+        metrics.snapshot.payload.origins.marked_unsafe = 2;
+        metrics.unsafe_vrps.push(UnsafeVrpMetrics {
+            origin: RouteOrigin::new(
+                MaxLenPrefix::new(prefix, Some(24)).unwrap(),
+                Asn::from_u32(64496),
+            ),
+            tal: Arc::new(TalInfo::from_name("test".into())),
+            roa: Some(
+                uri::Rsync::from_str("rsync://example.com/a/b.roa").unwrap()
+            ),
+            conflicts: vec![UnsafeVrpConflict {
+                ca: TalUri::Rsync(
+                    uri::Rsync::from_str("rsync://example.com/a/c.cer")
+                        .unwrap()
+                ),
+                blocks: vec![IpBlock::from(
+                    rpki::repository::resources::Prefix::new(
+                        prefix.addr(), 16
+                    )
+                )],
+            }],
+        });
+
+        let json = unsafe_vrps_json(&metrics, FilterPolicy::Warn);
+        assert!(json.contains("\"tracked\": true"));
+        assert!(json.contains("\"total\": 2"));
+        assert!(json.contains("\"truncated\": true"));
+        assert!(json.contains("\"prefix\": \"192.0.2.0/24\""));
+        assert!(json.contains("\"uri\": \"rsync://example.com/a/c.cer\""));
+        assert!(json.contains("\"192.0.0.0/16\""));
+        assert!(!json.contains("\"note\""));
+
+        let json = unsafe_vrps_json(&Metrics::new(), FilterPolicy::Accept);
+        assert!(json.contains("\"tracked\": false"));
+        assert!(json.contains("\"note\""));
+        assert!(!json.contains("\"asn\""));
+    }
+}
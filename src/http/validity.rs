@@ -4,8 +4,8 @@ use std::str::FromStr;
 use std::sync::Arc;
 use rpki::resources::{Asn, Prefix};
 use crate::payload::{PayloadSnapshot, SharedHistory};
-use crate::validity::RouteValidity;
-use super::request::Request;
+use crate::validity::{RequestList, RouteValidity};
+use super::request::{BodyError, Request};
 use super::response::{ContentType, Response, ResponseBuilder};
 
 
@@ -28,6 +28,65 @@ pub fn handle_get_or_head(
 }
 
 
+//------------ handle_post ---------------------------------------------------
+
+/// Returns whether the request is a batch validity request.
+pub fn is_batch(req: &Request) -> bool {
+    req.uri().path() == "/api/v1/validity"
+}
+
+/// Processes a batch validity request.
+///
+/// The request body contains either a JSON array of objects with `asn`
+/// and `prefix` members if the content type is JSON, or lines with an AS
+/// number and a prefix otherwise. All routes are checked against the same
+/// snapshot and the results are returned in request order.
+pub async fn handle_post(
+    req: Request,
+    history: &SharedHistory,
+    limit: usize,
+) -> Response {
+    if !req.is_post() {
+        return Response::method_not_allowed()
+    }
+    let json = req.is_json();
+    let body = match req.read_body(
+        limit.saturating_mul(MAX_BATCH_ENTRY_LEN)
+    ).await {
+        Ok(body) => body,
+        Err(BodyError::TooLarge) => return Response::payload_too_large(),
+        Err(BodyError::Failed) => return Response::bad_request(),
+    };
+    let requests = if json {
+        RequestList::from_batch_json(&body).map_err(|err| err.to_string())
+    }
+    else {
+        RequestList::from_batch_text(&body).map_err(|err| err.to_string())
+    };
+    let requests = match requests {
+        Ok(requests) => requests,
+        Err(err) => {
+            return ResponseBuilder::bad_request()
+                .content_type(ContentType::TEXT)
+                .body(format!("{}\n", err))
+        }
+    };
+    if requests.len() > limit {
+        return Response::payload_too_large()
+    }
+
+    let current = match validity_check(history) {
+        Ok(current) => current,
+        Err(resp) => return resp
+    };
+    let mut res = Vec::new();
+    requests.validity(&current).write_json(&mut res).expect(
+        "writing to vec"
+    );
+    ResponseBuilder::ok().content_type(ContentType::JSON).body(res)
+}
+
+
 //------------ handle_validity_path and handle_validity_query ----------------
 
 fn handle_validity_path(
@@ -119,3 +178,10 @@ fn validity(
     }
 }
 
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum average size of an entry in a batch request in bytes.
+///
+/// This is used to limit the size of the request body before parsing it.
+const MAX_BATCH_ENTRY_LEN: usize = 256;
@@ -0,0 +1,228 @@
+//! Checking the integrity of the RRDP archives.
+//!
+//! The RRDP archives are memory-mapped files that can get corrupted by disk
+//! issues without anyone noticing until an update trips over them. In
+//! server mode, Routinator can therefore verify all archives in the
+//! background. The [`IntegrityCheck`] spreads a pass over all archives
+//! across the configured interval and is driven by the validation thread
+//! while it waits for the next run, so a check never overlaps with a run.
+//!
+//! A corrupt archive is deleted, which makes the next validation run fetch
+//! the repository from scratch via its snapshot. A pass can also be
+//! requested at any time through an [`IntegrityCheckRequest`].
+
+use std::{cmp, fs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use log::{error, info};
+use crate::collector::{RrdpArchive, RrdpCollector};
+use crate::config::Config;
+use crate::metrics::IntegrityMetrics;
+use crate::payload::SharedHistory;
+use crate::utils::archive::{ArchiveError, OpenError};
+
+
+//------------ IntegrityCheck ------------------------------------------------
+
+/// The state of the background integrity check.
+#[derive(Debug)]
+pub struct IntegrityCheck {
+    /// The time to spread a pass over all archives across.
+    ///
+    /// If this is `None`, passes only happen when requested.
+    interval: Option<Duration>,
+
+    /// The request for an immediate pass.
+    request: IntegrityCheckRequest,
+
+    /// The archives still to be checked in the current pass.
+    ///
+    /// The next archive to check is at the end.
+    pending: Vec<PathBuf>,
+
+    /// The time between checking two archives in the current pass.
+    spacing: Duration,
+
+    /// The time the current pass has started.
+    start: Instant,
+
+    /// The time the next archive is due or the next pass starts.
+    next: Instant,
+
+    /// The metrics collected so far.
+    metrics: IntegrityMetrics,
+}
+
+impl IntegrityCheck {
+    /// Creates a new integrity check.
+    ///
+    /// If an interval is configured, the first pass starts right away.
+    pub fn new(config: &Config, request: IntegrityCheckRequest) -> Self {
+        let now = Instant::now();
+        IntegrityCheck {
+            interval: config.integrity_check_interval,
+            request,
+            pending: Vec::new(),
+            spacing: Duration::ZERO,
+            start: now,
+            next: now,
+            metrics: Default::default(),
+        }
+    }
+
+    /// Checks all archives that are currently due.
+    ///
+    /// This should be called regularly while no validation run is active.
+    /// Any changes to the metrics are stored in `history`.
+    pub fn run_due(&mut self, config: &Config, history: &SharedHistory) {
+        if self.request.take() {
+            info!("Archive integrity check requested via HTTP.");
+            self.start_pass(config, Duration::ZERO);
+        }
+        else if self.pending.is_empty() {
+            match self.interval {
+                Some(interval) if self.next <= Instant::now() => {
+                    self.start_pass(config, interval);
+                }
+                _ => return
+            }
+        }
+
+        let mut changed = false;
+        while self.next <= Instant::now() {
+            let path = match self.pending.pop() {
+                Some(path) => path,
+                None => break,
+            };
+            self.check_archive(path);
+            self.next += self.spacing;
+            changed = true;
+            if self.pending.is_empty() {
+                self.finish_pass();
+            }
+        }
+        if changed {
+            history.set_integrity_metrics(self.metrics.clone());
+        }
+    }
+
+    /// Starts a new pass over all archives spread over `interval`.
+    fn start_pass(&mut self, config: &Config, interval: Duration) {
+        // Errors have been logged already. We just try again next time.
+        self.pending = RrdpCollector::archive_paths(
+            config
+        ).unwrap_or_default();
+        self.pending.reverse();
+        self.spacing = interval / cmp::max(
+            u32::try_from(self.pending.len()).unwrap_or(u32::MAX), 1
+        );
+        self.start = Instant::now();
+        self.next = self.start;
+        if self.pending.is_empty() {
+            self.finish_pass();
+        }
+    }
+
+    /// Finishes the current pass.
+    fn finish_pass(&mut self) {
+        self.metrics.last_pass = Some(Utc::now());
+        self.next = cmp::max(
+            self.start + self.interval.unwrap_or_default(),
+            Instant::now()
+        );
+        info!("Archive integrity check finished.");
+    }
+
+    /// Checks a single archive, deleting it if it is corrupt.
+    fn check_archive(&mut self, path: PathBuf) {
+        self.metrics.checked += 1;
+        match RrdpArchive::verify(&path) {
+            Ok(_) | Err(OpenError::NotFound) => { }
+            Err(OpenError::Archive(ArchiveError::Io(err))) => {
+                error!(
+                    "Failed to read RRDP repository archive {}: {}",
+                    path.display(), err
+                );
+            }
+            Err(OpenError::Archive(ArchiveError::Corrupt)) => {
+                error!(
+                    "RRDP repository archive {} is corrupt. Deleting it so \
+                     the repository is fetched anew during the next run.",
+                    path.display()
+                );
+                if let Err(err) = fs::remove_file(&path) {
+                    error!(
+                        "Failed to delete corrupt RRDP repository archive \
+                         {}: {}",
+                        path.display(), err
+                    );
+                }
+                self.metrics.add_corrupt(path);
+            }
+        }
+    }
+}
+
+
+//------------ IntegrityCheckRequest -----------------------------------------
+
+/// A request to check the integrity of all archives right away.
+///
+/// The value is shared between whoever wants to request a check and the
+/// server which picks up requests regularly.
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityCheckRequest(Arc<AtomicBool>);
+
+impl IntegrityCheckRequest {
+    /// Requests a check of all archives.
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// Takes the pending request, returning whether there was one.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requested_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default_with_paths(
+            dir.path().join("routinator.conf"), dir.path().into()
+        );
+        let repo_dir = dir.path().join("rrdp").join("rrdp.example.net");
+        fs::create_dir_all(&repo_dir).unwrap();
+        let broken = repo_dir.join("0123456789abcdef.bin");
+        fs::write(&broken, b"definitely not an archive").unwrap();
+
+        let history = SharedHistory::from_config(&config);
+        let request = IntegrityCheckRequest::default();
+        let mut check = IntegrityCheck::new(&config, request.clone());
+
+        // Without an interval, nothing happens unless requested.
+        check.run_due(&config, &history);
+        assert_eq!(history.read().integrity_metrics().checked, 0);
+        assert!(broken.exists());
+
+        request.request();
+        check.run_due(&config, &history);
+        let history = history.read();
+        let metrics = history.integrity_metrics();
+        assert_eq!(metrics.checked, 1);
+        assert_eq!(metrics.corrupt, 1);
+        assert!(metrics.last_pass.is_some());
+        assert_eq!(metrics.corrupt_archives[0].0, broken);
+        assert!(!broken.exists());
+    }
+}
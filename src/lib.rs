@@ -6,7 +6,9 @@
 //! In addition, this also lets you use Routinator as a library for your own
 //! dedicated RPKI validation needs. The [operation] module should serve as a
 //! good starting point and set of examples since it contains the code for the
-//! various commands Routinator provides and uses all functionality.
+//! various commands Routinator provides and uses all functionality. If all
+//! you need is the validated payload, [`run_validation`] and
+//! [`ValidationRunner`] provide a quick way to get there.
 //!
 //! The library roughly consists of three parts: one part collects and
 //! validates RPKI data, one processes the validated data, and the third
@@ -50,19 +52,24 @@
 pub use self::config::Config;
 pub use self::error::{Failed, ExitError};
 pub use self::operation::Operation;
+pub use self::payload::{ValidationRunner, run_validation};
 pub use rpki;
 pub use reqwest;
 
+pub mod check;
 pub mod collector;
 pub mod config;
 pub mod engine;
 pub mod error;
+pub mod hooks;
 pub mod http;
+pub mod integrity;
 pub mod metrics;
 pub mod operation;
 pub mod output;
 pub mod payload;
 pub mod process;
+pub mod publish;
 pub mod rtr;
 pub mod rta;
 pub mod slurm;
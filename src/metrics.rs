@@ -5,19 +5,49 @@
 //! [`Metrics`] that collects all metrics gathered during the run. Additional
 //! types contain the metrics related to specific processed entities.
 
-use std::{cmp, io, ops, process, slice};
+use std::{cmp, fmt, io, ops, process, slice};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::iter::Peekable;
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::{Arc};
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicI64, AtomicU64, Ordering};
+use std::sync::atomic::{
+    AtomicBool, AtomicU8, AtomicU32, AtomicI64, AtomicU64, Ordering
+};
 use std::time::{Duration, SystemTimeError};
 use chrono::{DateTime, TimeZone, Utc};
 use rpki::uri;
-use rpki::repository::tal::TalInfo;
+use rpki::repository::resources::IpBlock;
+use rpki::repository::tal::{TalInfo, TalUri};
+use rpki::repository::x509::{self, Time};
+use rpki::rtr::Action;
+use rpki::rtr::payload::RouteOrigin;
 use rpki::rtr::state::Serial;
 use tokio::sync::Mutex;
 use uuid::Uuid;
-use crate::collector::{HttpStatus, SnapshotReason};
+use crate::collector::{HttpStatus, SnapshotReason, UpdateHistory};
+use crate::payload::PayloadInfo;
+
+
+//------------ Configuration -------------------------------------------------
+
+/// The maximum number of repositories reported as the slowest.
+pub const SLOWEST_REPOSITORIES: usize = 10;
+
+/// The maximum number of repositories reported as the largest in the cache.
+pub const LARGEST_CACHE_REPOSITORIES: usize = 10;
+
+/// The maximum number of repositories reported as the most churning.
+pub const CHURNING_REPOSITORIES: usize = 10;
+
+/// The number of recent runs for which the churn is kept.
+pub const CHURN_RECENT_RUNS: usize = 5;
+
+/// The minimum number of RRDP hosts that need to agree on a clock skew.
+pub const CLOCK_SKEW_MIN_HOSTS: usize = 3;
+
+/// The maximum number of unsafe VRPs kept for inspection.
+pub const UNSAFE_VRPS_LIMIT: usize = 10_000;
 
 
 //------------ Metrics -------------------------------------------------------
@@ -31,9 +61,18 @@ pub struct Metrics {
     /// Rsync metrics.
     pub rsync: Vec<RsyncModuleMetrics>,
 
+    /// The largest number of rsync processes running at the same time.
+    pub rsync_peak_processes: usize,
+
     /// RRDP metrics.
     pub rrdp: Vec<RrdpRepositoryMetrics>,
 
+    /// Metrics of the DNS cache used for RRDP.
+    pub rrdp_dns: RrdpDnsMetrics,
+
+    /// The RRDP repositories currently backed off after failures.
+    pub rrdp_backoff: Vec<RrdpBackoffMetrics>,
+
     /// Per-TAL metrics.
     pub tals: Vec<TalMetrics>,
 
@@ -48,6 +87,48 @@ pub struct Metrics {
 
     /// Overall payload metrics.
     pub snapshot: SnapshotMetrics,
+
+    /// The route origin churn relative to the previous data set.
+    pub churn: ChurnMetrics,
+
+    /// The repositories with the largest route origin churn.
+    ///
+    /// Repositories are identified by the rsync module of the ROAs. The
+    /// list contains at most [`CHURNING_REPOSITORIES`] items, the most
+    /// churning first.
+    pub churning_repositories: Vec<(String, ChurnMetrics)>,
+
+    /// The state of the stored publication points.
+    pub stored_points: Vec<StoredPointMetrics>,
+
+    /// The manifests that appeared to be replayed during the run.
+    pub manifest_replays: Vec<ManifestReplayMetrics>,
+
+    /// The unsafe VRPs encountered when creating the payload snapshot.
+    ///
+    /// This is only collected if the unsafe VRP policy is not _accept_
+    /// and contains at most [`UNSAFE_VRPS_LIMIT`] items.
+    pub unsafe_vrps: Vec<UnsafeVrpMetrics>,
+
+    /// The informational extensions of valid CA certificates.
+    ///
+    /// This is `None` if collecting them is disabled.
+    pub cert_extras: Option<Vec<CertExtrasMetrics>>,
+
+    /// The disk usage of the collector’s cache.
+    ///
+    /// This is `None` if the cache wasn’t cleaned up during the run.
+    pub cache_usage: Option<CacheUsageMetrics>,
+
+    /// Was the run aborted because it exceeded its maximum duration?
+    pub timed_out: bool,
+
+    /// The offset of the local clock if it appears to be off.
+    ///
+    /// This is the local time minus the time of the RRDP servers in
+    /// seconds. It is only set if a consistent offset beyond the threshold
+    /// was detected via [`detect_clock_skew`][Self::detect_clock_skew].
+    pub clock_skew: Option<i64>,
 }
 
 impl Metrics {
@@ -56,12 +137,24 @@ impl Metrics {
         Metrics {
             time: Utc::now(),
             rsync: Vec::new(),
+            rsync_peak_processes: 0,
             rrdp: Vec::new(),
+            rrdp_dns: Default::default(),
+            rrdp_backoff: Vec::new(),
             tals: Vec::new(),
             repositories: Vec::new(),
             publication: Default::default(),
             local: Default::default(),
             snapshot: Default::default(),
+            churn: Default::default(),
+            churning_repositories: Vec::new(),
+            stored_points: Vec::new(),
+            manifest_replays: Vec::new(),
+            unsafe_vrps: Vec::new(),
+            cert_extras: None,
+            cache_usage: None,
+            timed_out: false,
+            clock_skew: None,
         }
     }
 
@@ -77,6 +170,301 @@ impl Metrics {
         self.snapshot.finalize();
     }
 
+    /// Takes the TAL update times missing from `previous`.
+    ///
+    /// TALs that haven’t been updated in the run these metrics are for
+    /// keep the time of their last update.
+    pub fn keep_tal_updates(&mut self, previous: &Metrics) {
+        for tal in &mut self.tals {
+            if tal.updated.is_none() {
+                tal.updated = previous.tals.iter().find(|item| {
+                    item.name() == tal.name()
+                }).and_then(|item| item.updated);
+            }
+        }
+    }
+
+    /// Returns the URIs of all repositories that exceeded the limits.
+    ///
+    /// RRDP repositories are listed by their rpkiNotify URI and rsync
+    /// modules by their module URI.
+    pub fn limit_exceeded_repositories(
+        &self
+    ) -> impl Iterator<Item = &str> + '_ {
+        self.rrdp.iter().filter(|item| item.limit_exceeded).map(|item| {
+            item.notify_uri.as_str()
+        }).chain(
+            self.rsync.iter().filter(|item| item.limit_exceeded).map(|item| {
+                item.module.as_str()
+            })
+        )
+    }
+
+    /// Returns the repositories that took the longest to process.
+    ///
+    /// Returns at most [`SLOWEST_REPOSITORIES`] repositories, the slowest
+    /// first.
+    pub fn slowest_repositories(&self) -> Vec<&RepositoryMetrics> {
+        let mut res: Vec<_> = self.repositories.iter().collect();
+        res.sort_by_key(|item| cmp::Reverse(item.duration()));
+        res.truncate(SLOWEST_REPOSITORIES);
+        res
+    }
+
+    /// Returns the hosts whose repositories took the longest to process.
+    ///
+    /// The times of all repositories on the same host are added up. Each
+    /// item contains the host name, the time spent waiting for the
+    /// collector, and the time spent validating. Returns at most
+    /// [`SLOWEST_REPOSITORIES`] hosts, the slowest first.
+    pub fn slowest_repository_hosts(
+        &self
+    ) -> Vec<(&str, Duration, Duration)> {
+        let mut hosts = HashMap::<&str, (Duration, Duration)>::new();
+        for repository in &self.repositories {
+            let item = hosts.entry(repository.host()).or_default();
+            item.0 += repository.fetch_duration;
+            item.1 += repository.validation_duration;
+        }
+        let mut res: Vec<_> = hosts.into_iter().map(|(host, item)| {
+            (host, item.0, item.1)
+        }).collect();
+        res.sort_by_key(|item| cmp::Reverse(item.1 + item.2));
+        res.truncate(SLOWEST_REPOSITORIES);
+        res
+    }
+
+    /// Determines whether the local clock appears to be off.
+    ///
+    /// Compares the local clock with the times reported by the RRDP
+    /// servers. Only one offset per host is considered. A clock skew is
+    /// detected if at least [`CLOCK_SKEW_MIN_HOSTS`] hosts – and more than
+    /// half of all hosts with a known offset – report an offset beyond
+    /// `threshold` in the same direction. In this case, the median of
+    /// their offsets is stored in `self.clock_skew`.
+    pub fn detect_clock_skew(&mut self, threshold: Duration) {
+        let threshold = i64::try_from(threshold.as_secs()).unwrap_or(
+            i64::MAX
+        );
+        let mut hosts = HashMap::<&str, i64>::new();
+        for rrdp in &self.rrdp {
+            if let Some(offset) = rrdp.clock_offset {
+                hosts.entry(rrdp.notify_uri.authority()).or_insert(offset);
+            }
+        }
+        let mut ahead: Vec<_> = hosts.values().copied().filter(|offset| {
+            *offset >= threshold
+        }).collect();
+        let mut behind: Vec<_> = hosts.values().copied().filter(|offset| {
+            *offset <= -threshold
+        }).collect();
+        let skewed = if ahead.len() > behind.len() {
+            &mut ahead
+        }
+        else {
+            &mut behind
+        };
+        self.clock_skew = if
+            skewed.len() >= CLOCK_SKEW_MIN_HOSTS
+            && skewed.len() * 2 > hosts.len()
+        {
+            skewed.sort_unstable();
+            Some(skewed[skewed.len() / 2])
+        }
+        else {
+            None
+        };
+    }
+
+    /// Returns the names of all TALs that produced no valid ROAs.
+    pub fn tals_without_roas(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tals.iter().filter(|tal| {
+            tal.publication.valid_roas == 0
+        }).map(|tal| tal.tal.name())
+    }
+
+    /// Returns a copy of the metrics with all payload metrics reset.
+    ///
+    /// This can be used to produce the metrics of a new payload snapshot
+    /// created from the data of the same validation run.
+    pub fn without_payload(&self) -> Self {
+        Metrics {
+            time: self.time,
+            rsync: self.rsync.clone(),
+            rsync_peak_processes: self.rsync_peak_processes,
+            rrdp: self.rrdp.clone(),
+            rrdp_dns: self.rrdp_dns,
+            rrdp_backoff: self.rrdp_backoff.clone(),
+            tals: self.tals.iter().map(|tal| {
+                TalMetrics {
+                    payload: Default::default(),
+                    churn: Default::default(),
+                    .. tal.clone()
+                }
+            }).collect(),
+            repositories: self.repositories.iter().map(|repo| {
+                RepositoryMetrics {
+                    payload: Default::default(),
+                    .. repo.clone()
+                }
+            }).collect(),
+            publication: self.publication.clone(),
+            local: Default::default(),
+            snapshot: Default::default(),
+            churn: Default::default(),
+            churning_repositories: Vec::new(),
+            stored_points: self.stored_points.clone(),
+            manifest_replays: self.manifest_replays.clone(),
+            unsafe_vrps: Vec::new(),
+            cert_extras: self.cert_extras.clone(),
+            cache_usage: self.cache_usage.clone(),
+            timed_out: self.timed_out,
+            clock_skew: self.clock_skew,
+        }
+    }
+
+    /// Merges the metrics of a validation run for a single TAL.
+    ///
+    /// The metrics in `other` need to have been produced by a run that
+    /// only processed the TAL with index `tal_index` using the same list
+    /// of TALs as the run that produced `self`. All metrics specific to the
+    /// TAL are taken from `other`, everything else is kept from `self`.
+    /// Repository, RRDP, and rsync metrics from `other` replace those for
+    /// the same repository in `self` and are added if they are new.
+    /// Repositories only used by the TAL that have disappeared are kept.
+    ///
+    /// As with [`without_payload`][Self::without_payload], the payload
+    /// metrics are reset in the returned value. In addition, the method
+    /// returns a list that provides the new index for each repository index
+    /// in `other`.
+    pub fn merge_tal(
+        &self, other: Metrics, tal_index: usize
+    ) -> (Self, Vec<usize>) {
+        let mut res = self.without_payload();
+        res.time = other.time;
+        res.timed_out = other.timed_out;
+        res.clock_skew = other.clock_skew;
+        res.rrdp_dns = other.rrdp_dns;
+        res.rrdp_backoff = other.rrdp_backoff;
+        res.rsync_peak_processes = cmp::max(
+            res.rsync_peak_processes, other.rsync_peak_processes
+        );
+
+        let tal = match (
+            res.tals.get_mut(tal_index), other.tals.into_iter().nth(tal_index)
+        ) {
+            (Some(target), Some(tal)) => {
+                *target = TalMetrics {
+                    payload: Default::default(),
+                    churn: Default::default(),
+                    updated: tal.updated.or(target.updated),
+                    .. tal
+                };
+                target.tal.clone()
+            }
+            _ => return (res, Vec::new()),
+        };
+        res.publication = Default::default();
+        for tal in &res.tals {
+            res.publication += tal.publication.clone();
+        }
+
+        let repository_map = other.repositories.into_iter().map(|repo| {
+            let repo = RepositoryMetrics {
+                payload: Default::default(),
+                .. repo
+            };
+            match res.repositories.iter().position(|item| {
+                item.uri == repo.uri
+            }) {
+                Some(index) => {
+                    res.repositories[index] = repo;
+                    index
+                }
+                None => {
+                    res.repositories.push(repo);
+                    res.repositories.len() - 1
+                }
+            }
+        }).collect();
+
+        for rrdp in other.rrdp {
+            match res.rrdp.iter_mut().find(|item| {
+                item.notify_uri == rrdp.notify_uri
+            }) {
+                Some(item) => *item = rrdp,
+                None => res.rrdp.push(rrdp),
+            }
+        }
+        for rsync in other.rsync {
+            match res.rsync.iter_mut().find(|item| {
+                item.module == rsync.module
+            }) {
+                Some(item) => *item = rsync,
+                None => res.rsync.push(rsync),
+            }
+        }
+
+        res.stored_points.retain(|point| point.tal.name() != tal.name());
+        res.stored_points.extend(other.stored_points);
+        res.stored_points.sort_by(|left, right| {
+            left.manifest_uri.as_str().cmp(right.manifest_uri.as_str())
+        });
+        res.manifest_replays.retain(|item| item.tal.name() != tal.name());
+        res.manifest_replays.extend(other.manifest_replays);
+        res.manifest_replays.sort_by(|left, right| {
+            left.manifest_uri.as_str().cmp(right.manifest_uri.as_str())
+        });
+        if let (Some(extras), Some(other)) = (
+            res.cert_extras.as_mut(), other.cert_extras
+        ) {
+            extras.retain(|item| item.tal.name() != tal.name());
+            extras.extend(other);
+            extras.sort_by(|left, right| {
+                left.uri.as_str().cmp(right.uri.as_str())
+            });
+        }
+
+        (res, repository_map)
+    }
+
+    /// Collects the route origin churn of a new data set.
+    ///
+    /// The iterator needs to provide the payload information and action
+    /// of all route origins that changed relative to the previous data
+    /// set. Changes are attributed to the TAL and repository of their
+    /// first source. Changes caused by local exceptions only count
+    /// towards the overall churn.
+    pub fn collect_churn<'a>(
+        &mut self,
+        changes: impl Iterator<Item = (&'a PayloadInfo, Action)>,
+    ) {
+        let mut repositories = HashMap::<String, ChurnMetrics>::new();
+        for (info, action) in changes {
+            self.churn.add(action);
+            if let Some(name) = info.tal_name() {
+                if let Some(tal) = self.tals.iter_mut().find(|tal| {
+                    tal.name() == name
+                }) {
+                    tal.churn.add(action);
+                }
+            }
+            if let Some(uri) = info.uri() {
+                repositories.entry(
+                    uri.canonical_module().into_owned()
+                ).or_default().add(action);
+            }
+        }
+        let mut repositories: Vec<_> = repositories.into_iter().collect();
+        repositories.sort_by(|left, right| {
+            right.1.total().cmp(&left.1.total()).then_with(|| {
+                left.0.cmp(&right.0)
+            })
+        });
+        repositories.truncate(CHURNING_REPOSITORIES);
+        self.churning_repositories = repositories;
+    }
+
     /// Returns the time the metrics were created as a Unix timestamp.
     pub fn timestamp(&self) -> i64 {
         self.time.timestamp()
@@ -119,6 +507,18 @@ pub struct RrdpRepositoryMetrics {
     /// The status of requesting the notification file.
     pub notify_status: HttpStatus,
 
+    /// The remote address the notification file was received from.
+    ///
+    /// This is `None` if the request failed or the address is unknown.
+    pub remote_addr: Option<IpAddr>,
+
+    /// The offset of the local clock from the server’s clock in seconds.
+    ///
+    /// This is the local time minus the time given in the Date header of
+    /// the response for the notification file. It is `None` if there was
+    /// no response or it didn’t contain a usable Date header.
+    pub clock_offset: Option<i64>,
+
     /// The session ID of the last update.
     pub session: Option<Uuid>,
 
@@ -128,6 +528,27 @@ pub struct RrdpRepositoryMetrics {
     /// Was there a reason to fall back to using a snapshot?
     pub snapshot_reason: Option<SnapshotReason>,
 
+    /// The kind of update that brought the local copy up-to-date.
+    ///
+    /// This is `None` if the update failed or wasn’t attempted.
+    pub update_kind: Option<RrdpUpdateKind>,
+
+    /// The Last-Modified time of the notification file.
+    ///
+    /// If the notification file was not modified, this is the time
+    /// received with the last modified version.
+    pub notify_last_modified: Option<DateTime<Utc>>,
+
+    /// The time the notification file was processed.
+    pub notify_processed: Option<DateTime<Utc>>,
+
+    /// The number of serials the local copy was behind.
+    ///
+    /// This is the difference between the serial number of the
+    /// notification file and that of the local copy. It is `None` if there
+    /// was no local copy or it had a different session.
+    pub serial_lag: Option<u64>,
+
     /// The status of requesting the last payload file.
     ///
     /// If multiple payload files had to be requested, for instance because
@@ -140,6 +561,21 @@ pub struct RrdpRepositoryMetrics {
 
     /// The duration of the last update.
     pub duration: Result<Duration, SystemTimeError>,
+
+    /// The time the update had to wait before it could start.
+    ///
+    /// This is the time spent waiting for a free slot if the number of
+    /// concurrent updates is limited.
+    pub queue_duration: Duration,
+
+    /// Did the repository exceed the repository limits?
+    pub limit_exceeded: bool,
+
+    /// The outcomes of the most recent update attempts.
+    ///
+    /// If an update was attempted during this run, its outcome is the
+    /// most recent record.
+    pub history: UpdateHistory,
 }
 
 impl RrdpRepositoryMetrics {
@@ -147,14 +583,40 @@ impl RrdpRepositoryMetrics {
         RrdpRepositoryMetrics {
             notify_uri,
             notify_status: HttpStatus::Error,
+            remote_addr: None,
             session: None,
             serial: None,
             snapshot_reason: None,
+            update_kind: None,
+            notify_last_modified: None,
+            notify_processed: None,
+            serial_lag: None,
+            clock_offset: None,
             payload_status: None,
-            duration: Ok(Duration::from_secs(0))
+            duration: Ok(Duration::from_secs(0)),
+            queue_duration: Duration::from_secs(0),
+            limit_exceeded: false,
+            history: UpdateHistory::default(),
         }
     }
 
+    /// Returns the propagation delay of the notification file in seconds.
+    ///
+    /// This is the time between the Last-Modified time of the notification
+    /// file and the time it was processed. The value can be negative if
+    /// the clocks of the server and ours are not in sync.
+    pub fn notify_delay(&self) -> Option<i64> {
+        Some(
+            (self.notify_processed? - self.notify_last_modified?)
+                .num_seconds()
+        )
+    }
+
+    /// Returns the IP version used for the notification file if known.
+    pub fn ip_version(&self) -> Option<u8> {
+        self.remote_addr.map(|addr| if addr.is_ipv4() { 4 } else { 6 })
+    }
+
     pub fn status(&self) -> HttpStatus {
         if self.notify_status.is_success() {
             if let Some(status) = self.payload_status {
@@ -171,6 +633,325 @@ impl RrdpRepositoryMetrics {
 }
 
 
+//------------ RrdpUpdateKind ------------------------------------------------
+
+/// The kind of update performed for an RRDP repository.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RrdpUpdateKind {
+    /// The repository was updated from the snapshot.
+    Snapshot,
+
+    /// The repository was updated by applying deltas.
+    Delta,
+
+    /// The repository had not changed since the last update.
+    NotModified,
+}
+
+impl RrdpUpdateKind {
+    /// Returns a short string describing the kind of update.
+    pub fn code(self) -> &'static str {
+        match self {
+            RrdpUpdateKind::Snapshot => "snapshot",
+            RrdpUpdateKind::Delta => "delta",
+            RrdpUpdateKind::NotModified => "not-modified",
+        }
+    }
+}
+
+
+//------------ RrdpBackoffMetrics --------------------------------------------
+
+/// An RRDP repository that is currently backed off.
+#[derive(Clone, Debug)]
+pub struct RrdpBackoffMetrics {
+    /// The rpkiNotify URI of the repository.
+    pub notify_uri: String,
+
+    /// The number of consecutive failed updates.
+    pub failures: u32,
+
+    /// The time until which the repository will not be contacted.
+    pub until: DateTime<Utc>,
+}
+
+
+//------------ RrdpDnsMetrics ------------------------------------------------
+
+/// Metrics of the DNS cache used for RRDP during a validation run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RrdpDnsMetrics {
+    /// The number of lookups answered from the cache.
+    pub hits: u64,
+
+    /// The number of lookups that needed asking the resolver.
+    pub misses: u64,
+
+    /// The number of lookups answered with an expired cached answer.
+    ///
+    /// This happens if the resolver failed but a recent answer was
+    /// available.
+    pub stale: u64,
+
+    /// The number of failed resolutions.
+    pub failures: u64,
+}
+
+
+//------------ RrdpDurationHistograms ----------------------------------------
+
+/// Histograms of RRDP update durations across validation runs.
+///
+/// Unlike all the other metrics, which are collected for a single run only,
+/// these histograms accumulate the durations of all runs. They are kept per
+/// repository host rather than per repository to keep the number of series
+/// manageable.
+#[derive(Clone, Debug)]
+pub struct RrdpDurationHistograms {
+    /// The upper bounds of the buckets in seconds.
+    buckets: &'static [f64],
+
+    /// The histograms for each host.
+    hosts: BTreeMap<String, DurationHistogram>,
+}
+
+impl RrdpDurationHistograms {
+    /// The upper bounds of the buckets in seconds.
+    const BUCKETS: &'static [f64] = &[
+        0.5, 1., 2.5, 5., 10., 30., 60., 120., 300.
+    ];
+
+    /// Adds the durations of RRDP updates of a validation run.
+    ///
+    /// Updates where the duration could not be determined are ignored.
+    pub fn update(&mut self, metrics: &[RrdpRepositoryMetrics]) {
+        for rrdp in metrics {
+            let duration = match rrdp.duration {
+                Ok(duration) => duration.as_secs_f64(),
+                Err(_) => continue,
+            };
+            let buckets = self.buckets;
+            self.hosts.entry(
+                rrdp.notify_uri.authority().into()
+            ).or_insert_with(|| {
+                DurationHistogram::new(buckets.len())
+            }).add(buckets, duration);
+        }
+    }
+
+    /// Returns the upper bounds of the buckets in seconds.
+    pub fn buckets(&self) -> &[f64] {
+        self.buckets
+    }
+
+    /// Returns an iterator over the hosts and their histograms.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DurationHistogram)> {
+        self.hosts.iter().map(|(host, hist)| (host.as_str(), hist))
+    }
+}
+
+impl Default for RrdpDurationHistograms {
+    fn default() -> Self {
+        RrdpDurationHistograms {
+            buckets: Self::BUCKETS,
+            hosts: BTreeMap::new(),
+        }
+    }
+}
+
+
+//------------ RrdpUpdateTotals ----------------------------------------------
+
+/// The outcomes of RRDP updates accumulated across validation runs.
+///
+/// Like [`RrdpDurationHistograms`], this is kept for the lifetime of the
+/// process rather than for a single run. Only repositories for which an
+/// update was attempted are counted, i.e., those backed off or skipped
+/// because of rate limiting are not.
+#[derive(Clone, Debug, Default)]
+pub struct RrdpUpdateTotals {
+    /// The number of updates per outcome code.
+    outcomes: BTreeMap<&'static str, u64>,
+
+    /// The number of snapshot updates per reason code.
+    snapshot_reasons: BTreeMap<&'static str, u64>,
+}
+
+impl RrdpUpdateTotals {
+    /// Adds the outcomes of the RRDP updates of a validation run.
+    pub fn update(&mut self, metrics: &[RrdpRepositoryMetrics]) {
+        for rrdp in metrics {
+            if matches!(
+                rrdp.notify_status,
+                HttpStatus::BackedOff | HttpStatus::RateLimited
+            ) {
+                continue
+            }
+            let record = match rrdp.history.latest() {
+                Some(record) => record,
+                None => continue,
+            };
+            *self.outcomes.entry(record.outcome()).or_default() += 1;
+            if let Some(reason) = record.snapshot_reason {
+                *self.snapshot_reasons.entry(reason.code()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Returns an iterator over the outcome codes and their counts.
+    pub fn outcomes(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.outcomes.iter().map(|(code, count)| (*code, *count))
+    }
+
+    /// Returns an iterator over the snapshot reason codes and their counts.
+    pub fn snapshot_reasons(
+        &self
+    ) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.snapshot_reasons.iter().map(|(code, count)| (*code, *count))
+    }
+}
+
+
+//------------ ChurnMetrics --------------------------------------------------
+
+/// The changes of route origins relative to the previous data set.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ChurnMetrics {
+    /// The number of route origins added.
+    pub added: u64,
+
+    /// The number of route origins removed.
+    pub removed: u64,
+}
+
+impl ChurnMetrics {
+    /// Adds a change with the given action.
+    pub fn add(&mut self, action: Action) {
+        match action {
+            Action::Announce => self.added += 1,
+            Action::Withdraw => self.removed += 1,
+        }
+    }
+
+    /// Returns the total number of changes.
+    pub fn total(self) -> u64 {
+        self.added + self.removed
+    }
+}
+
+impl ops::AddAssign for ChurnMetrics {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.removed += other.removed;
+    }
+}
+
+
+//------------ ChurnTotals ---------------------------------------------------
+
+/// Route origin churn accumulated across validation runs.
+///
+/// Like [`RrdpDurationHistograms`], this is kept for the lifetime of the
+/// process rather than for a single run. In addition to the totals, the
+/// churn of the most recent [`CHURN_RECENT_RUNS`] runs is kept.
+#[derive(Clone, Debug, Default)]
+pub struct ChurnTotals {
+    /// The total churn over all TALs and local exceptions.
+    all: ChurnMetrics,
+
+    /// The total churn per TAL.
+    tals: HashMap<String, ChurnMetrics>,
+
+    /// The churn of the most recent runs, the most recent first.
+    recent: VecDeque<(DateTime<Utc>, ChurnMetrics)>,
+}
+
+impl ChurnTotals {
+    /// Adds the churn of a new data set.
+    pub fn update(&mut self, metrics: &Metrics) {
+        self.all += metrics.churn;
+        for tal in &metrics.tals {
+            *self.tals.entry(tal.name().into()).or_default() += tal.churn;
+        }
+        self.recent.push_front((Utc::now(), metrics.churn));
+        self.recent.truncate(CHURN_RECENT_RUNS);
+    }
+
+    /// Returns the total churn over all sources.
+    pub fn all(&self) -> ChurnMetrics {
+        self.all
+    }
+
+    /// Returns the total churn of the TAL with the given name.
+    pub fn tal(&self, name: &str) -> ChurnMetrics {
+        self.tals.get(name).copied().unwrap_or_default()
+    }
+
+    /// Returns an iterator over the churn of the most recent runs.
+    ///
+    /// The iterator starts with the most recent run.
+    pub fn recent(
+        &self
+    ) -> impl Iterator<Item = (DateTime<Utc>, ChurnMetrics)> + '_ {
+        self.recent.iter().copied()
+    }
+}
+
+
+//------------ DurationHistogram ---------------------------------------------
+
+/// A histogram of durations.
+#[derive(Clone, Debug)]
+pub struct DurationHistogram {
+    /// The cumulative number of observations for each bucket.
+    ///
+    /// Each element contains the number of observations less than or equal
+    /// to the upper bound of the bucket with the same index.
+    counts: Vec<u64>,
+
+    /// The sum of all observed durations in seconds.
+    sum: f64,
+
+    /// The total number of observations.
+    count: u64,
+}
+
+impl DurationHistogram {
+    fn new(len: usize) -> Self {
+        DurationHistogram {
+            counts: vec![0; len],
+            sum: 0.,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, buckets: &[f64], duration: f64) {
+        for (bound, count) in buckets.iter().zip(self.counts.iter_mut()) {
+            if duration <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += duration;
+        self.count += 1;
+    }
+
+    /// Returns the cumulative counts for each bucket.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the sum of all observed durations in seconds.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the total number of observations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+
 //------------ RsyncModuleMetrics --------------------------------------------
 
 /// Metrics collected while updating an rsync module.
@@ -179,6 +960,102 @@ pub struct RsyncModuleMetrics {
     pub module: uri::Rsync,
     pub status: Result<process::ExitStatus, io::Error>,
     pub duration: Result<Duration, SystemTimeError>,
+    pub limit_exceeded: bool,
+
+    /// The time the update had to wait before rsync could be started.
+    ///
+    /// This is the time spent waiting for a free slot if the number of
+    /// concurrent rsync processes is limited.
+    pub queue_duration: Duration,
+
+    /// The number of bytes received if reported by rsync.
+    pub bytes_received: Option<u64>,
+
+    /// The beginning of the standard error output of rsync, if any.
+    pub stderr: Option<String>,
+
+    /// Was RRDP used instead because the update failed?
+    pub rrdp_fallback: bool,
+
+    /// Was the update skipped because the module’s listing was unchanged?
+    pub unchanged: bool,
+}
+
+impl Clone for RsyncModuleMetrics {
+    fn clone(&self) -> Self {
+        RsyncModuleMetrics {
+            module: self.module.clone(),
+            status: match self.status {
+                Ok(status) => Ok(status),
+                // IO errors can’t be cloned, so we recreate it from its
+                // kind and message.
+                Err(ref err) => {
+                    Err(io::Error::new(err.kind(), err.to_string()))
+                }
+            },
+            duration: self.duration.clone(),
+            limit_exceeded: self.limit_exceeded,
+            queue_duration: self.queue_duration,
+            bytes_received: self.bytes_received,
+            stderr: self.stderr.clone(),
+            rrdp_fallback: self.rrdp_fallback,
+            unchanged: self.unchanged,
+        }
+    }
+}
+
+
+//------------ CacheUsageMetrics ---------------------------------------------
+
+/// The disk usage of the collector’s cache.
+///
+/// This is determined while cleaning up the cache after a validation run.
+#[derive(Clone, Debug, Default)]
+pub struct CacheUsageMetrics {
+    /// The size of each repository in the cache in bytes.
+    ///
+    /// RRDP repositories are listed by their rpkiNotify URI and rsync
+    /// modules by their module URI.
+    pub repositories: Vec<(String, u64)>,
+
+    /// The configured maximum size of the cache in bytes.
+    pub max_size: Option<u64>,
+}
+
+impl CacheUsageMetrics {
+    /// Creates new metrics for the given maximum size.
+    pub fn new(max_size: Option<u64>) -> Self {
+        CacheUsageMetrics {
+            repositories: Vec::new(),
+            max_size,
+        }
+    }
+
+    /// Returns the overall size of the cache in bytes.
+    pub fn total(&self) -> u64 {
+        self.repositories.iter().map(|item| item.1).sum()
+    }
+
+    /// Returns whether the cache is larger than the maximum size.
+    pub fn is_exceeded(&self) -> bool {
+        match self.max_size {
+            Some(max_size) => self.total() > max_size,
+            None => false,
+        }
+    }
+
+    /// Returns the repositories using the most space.
+    ///
+    /// Returns at most [`LARGEST_CACHE_REPOSITORIES`] repositories, the
+    /// largest first.
+    pub fn largest_repositories(&self) -> Vec<(&str, u64)> {
+        let mut res: Vec<_> = self.repositories.iter().map(|item| {
+            (item.0.as_str(), item.1)
+        }).collect();
+        res.sort_by_key(|item| cmp::Reverse(item.1));
+        res.truncate(LARGEST_CACHE_REPOSITORIES);
+        res
+    }
 }
 
 
@@ -195,6 +1072,31 @@ pub struct TalMetrics {
 
     /// The VRP metrics.
     pub payload: PayloadMetrics,
+
+    /// The route origin churn relative to the previous data set.
+    pub churn: ChurnMetrics,
+
+    /// Was updating the TAL skipped due to its refresh interval?
+    pub update_skipped: bool,
+
+    /// The URI of the trust anchor certificate used.
+    ///
+    /// This is `None` if no valid certificate was found.
+    pub ta_uri: Option<TalUri>,
+
+    /// Was the trust anchor certificate taken from the store?
+    pub ta_stored: bool,
+
+    /// Did a downloaded trust anchor certificate have a different key?
+    ///
+    /// This happens when the trust anchor has rolled its key and the TAL
+    /// is outdated.
+    pub ta_key_mismatch: bool,
+
+    /// The time the last successful update of the TAL’s data completed.
+    ///
+    /// This is `None` if there hasn’t been one yet.
+    pub updated: Option<DateTime<Utc>>,
 }
 
 impl TalMetrics {
@@ -203,6 +1105,12 @@ impl TalMetrics {
             tal,
             publication: Default::default(),
             payload: Default::default(),
+            churn: Default::default(),
+            update_skipped: false,
+            ta_uri: None,
+            ta_stored: false,
+            ta_key_mismatch: false,
+            updated: None,
         }
     }
 
@@ -229,6 +1137,18 @@ pub struct RepositoryMetrics {
 
     /// The VRP metrics.
     pub payload: PayloadMetrics,
+
+    /// The time spent waiting for the collector.
+    ///
+    /// This is the sum over all publication points of the repository and
+    /// all validation threads.
+    pub fetch_duration: Duration,
+
+    /// The time spent validating the repository’s publication points.
+    ///
+    /// This is the sum over all publication points of the repository and
+    /// all validation threads.
+    pub validation_duration: Duration,
 }
 
 impl RepositoryMetrics {
@@ -237,12 +1157,30 @@ impl RepositoryMetrics {
             uri,
             publication: Default::default(),
             payload: Default::default(),
+            fetch_duration: Duration::ZERO,
+            validation_duration: Duration::ZERO,
         }
     }
 
     pub fn finalize(&mut self) {
         self.payload.finalize();
     }
+
+    /// Returns the total time spent on the repository.
+    pub fn duration(&self) -> Duration {
+        self.fetch_duration + self.validation_duration
+    }
+
+    /// Returns the host of the repository.
+    ///
+    /// This is the authority part of the repository URI. If the URI can’t
+    /// be parsed, returns the URI as is.
+    pub fn host(&self) -> &str {
+        match self.uri.split_once("://") {
+            Some((_, uri)) => uri.split('/').next().unwrap_or(uri),
+            None => &self.uri,
+        }
+    }
 }
 
 
@@ -269,6 +1207,11 @@ pub struct PublicationMetrics {
     /// The number of stale manifests.
     pub stale_manifests: u32,
 
+    /// The number of stale manifests accepted within the grace period.
+    ///
+    /// These are also included in `stale_manifests`.
+    pub stale_grace_manifests: u32,
+
     /// The number of missing manifests.
     pub missing_manifests: u32,
 
@@ -281,6 +1224,11 @@ pub struct PublicationMetrics {
     /// The number of stale CRLs.
     pub stale_crls: u32,
 
+    /// The number of stale CRLs accepted within the grace period.
+    ///
+    /// These are also included in `stale_crls`.
+    pub stale_grace_crls: u32,
+
     /// The number of stray CRLs.
     ///
     /// Stray CRLs are CRL objects appearing in publication points that are
@@ -315,14 +1263,80 @@ pub struct PublicationMetrics {
     /// The number of invalid ASPA objects.
     pub invalid_aspas: u32,
 
-    /// The number of other objects.
-    pub others: u32,
-}
+    /// The number of other objects.
+    pub others: u32,
+
+    /// The earliest notAfter time of all valid CA certificates.
+    ///
+    /// Only CA certificates of publication points that have been accepted
+    /// are considered.
+    pub min_ca_cert_expiry: Option<Time>,
+
+    /// The earliest nextUpdate time of all valid manifests and CRLs.
+    ///
+    /// Only manifests and CRLs of publication points that have been
+    /// accepted are considered.
+    pub min_next_update: Option<Time>,
+}
+
+impl PublicationMetrics {
+    /// Returns the number of stale objects.
+    pub fn stale_objects(&self) -> u32 {
+        self.stale_manifests + self.stale_crls
+    }
+
+    /// Returns the number of stale objects accepted within the grace period.
+    pub fn stale_grace_objects(&self) -> u32 {
+        self.stale_grace_manifests + self.stale_grace_crls
+    }
+
+    /// Returns the object counts for the given object type.
+    ///
+    /// Stray CRLs are counted as rejected CRLs. Objects of unknown type
+    /// are never used and are thus counted as rejected, too.
+    pub fn object_counts(&self, object_type: ObjectType) -> ObjectCounts {
+        let (accepted, rejected) = match object_type {
+            ObjectType::Manifest => {
+                (self.valid_manifests, self.invalid_manifests)
+            }
+            ObjectType::Crl => {
+                (self.valid_crls, self.invalid_crls + self.stray_crls)
+            }
+            ObjectType::Certificate => {
+                (
+                    self.valid_ca_certs + self.valid_router_certs,
+                    self.invalid_certs
+                )
+            }
+            ObjectType::Roa => (self.valid_roas, self.invalid_roas),
+            ObjectType::Aspa => (self.valid_aspas, self.invalid_aspas),
+            ObjectType::Gbr => (self.valid_gbrs, self.invalid_gbrs),
+            ObjectType::Unknown => (0, self.others),
+        };
+        ObjectCounts { accepted, rejected }
+    }
+
+    /// Returns an iterator over the object counts of all object types.
+    pub fn object_type_counts(
+        &self
+    ) -> impl Iterator<Item = (ObjectType, ObjectCounts)> + '_ {
+        ObjectType::ALL.into_iter().map(|object_type| {
+            (object_type, self.object_counts(object_type))
+        })
+    }
+
+    /// Notes the notAfter time of a valid CA certificate.
+    pub fn note_ca_cert_expiry(&mut self, not_after: Time) {
+        self.min_ca_cert_expiry = min_time(
+            self.min_ca_cert_expiry, Some(not_after)
+        );
+    }
 
-impl PublicationMetrics {
-    /// Returns the number of stale objects.
-    pub fn stale_objects(&self) -> u32 {
-        self.stale_manifests + self.stale_crls
+    /// Notes the nextUpdate time of a valid manifest or CRL.
+    pub fn note_next_update(&mut self, next_update: Time) {
+        self.min_next_update = min_time(
+            self.min_next_update, Some(next_update)
+        );
     }
 }
 
@@ -344,10 +1358,12 @@ impl<'a> ops::AddAssign<&'a Self> for PublicationMetrics {
         self.invalid_manifests += other.invalid_manifests;
         self.premature_manifests += other.premature_manifests;
         self.stale_manifests += other.stale_manifests;
+        self.stale_grace_manifests += other.stale_grace_manifests;
         self.missing_manifests += other.missing_manifests;
         self.valid_crls += other.valid_crls;
         self.invalid_crls += other.invalid_crls;
         self.stale_crls += other.stale_crls;
+        self.stale_grace_crls += other.stale_grace_crls;
         self.stray_crls += other.stray_crls;
 
         self.valid_ca_certs += other.valid_ca_certs;
@@ -360,6 +1376,21 @@ impl<'a> ops::AddAssign<&'a Self> for PublicationMetrics {
         self.valid_aspas += other.valid_aspas;
         self.invalid_aspas += other.invalid_aspas;
         self.others += other.others;
+
+        self.min_ca_cert_expiry = min_time(
+            self.min_ca_cert_expiry, other.min_ca_cert_expiry
+        );
+        self.min_next_update = min_time(
+            self.min_next_update, other.min_next_update
+        );
+    }
+}
+
+/// Returns the earlier of two optional times.
+fn min_time(left: Option<Time>, right: Option<Time>) -> Option<Time> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(cmp::min(left, right)),
+        (left, right) => left.or(right),
     }
 }
 
@@ -370,6 +1401,222 @@ impl ops::AddAssign for PublicationMetrics {
 }
 
 
+//------------ ObjectType ----------------------------------------------------
+
+/// The type of a published object for the purpose of object accounting.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ObjectType {
+    /// A manifest.
+    Manifest,
+
+    /// A certificate revocation list.
+    Crl,
+
+    /// A CA or router certificate.
+    Certificate,
+
+    /// A route origin authorization.
+    Roa,
+
+    /// An autonomous system provider authorization.
+    Aspa,
+
+    /// A Ghostbuster record.
+    Gbr,
+
+    /// An object of unknown type.
+    Unknown,
+}
+
+impl ObjectType {
+    /// All object types in the order they should be reported.
+    pub const ALL: [Self; 7] = [
+        ObjectType::Manifest, ObjectType::Crl, ObjectType::Certificate,
+        ObjectType::Roa, ObjectType::Aspa, ObjectType::Gbr,
+        ObjectType::Unknown,
+    ];
+
+    /// Returns the short name of the object type.
+    ///
+    /// These names are used as labels in metrics and in the status.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectType::Manifest => "mft",
+            ObjectType::Crl => "crl",
+            ObjectType::Certificate => "cer",
+            ObjectType::Roa => "roa",
+            ObjectType::Aspa => "aspa",
+            ObjectType::Gbr => "gbr",
+            ObjectType::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ ObjectCounts --------------------------------------------------
+
+/// The number of accepted and rejected objects of a certain type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ObjectCounts {
+    /// The number of accepted objects.
+    pub accepted: u32,
+
+    /// The number of rejected objects.
+    pub rejected: u32,
+}
+
+impl ObjectCounts {
+    /// Returns the number of objects seen.
+    pub fn seen(self) -> u32 {
+        self.accepted + self.rejected
+    }
+}
+
+
+//------------ CertExtrasMetrics ---------------------------------------------
+
+/// The informational extensions of a CA certificate.
+#[derive(Clone, Debug)]
+pub struct CertExtrasMetrics {
+    /// The rsync URI of the certificate.
+    pub uri: uri::Rsync,
+
+    /// The TAL the certificate was found under.
+    pub tal: Arc<TalInfo>,
+
+    /// The CPS pointers given in the certificate policies.
+    pub cps: Vec<String>,
+
+    /// The object identifiers of extensions outside the RPKI profile.
+    pub extensions: Vec<String>,
+}
+
+
+//------------ StoredPointMetrics --------------------------------------------
+
+/// The state of a publication point in the store after a validation run.
+#[derive(Clone, Debug)]
+pub struct StoredPointMetrics {
+    /// The rsync URI of the publication point’s manifest.
+    pub manifest_uri: uri::Rsync,
+
+    /// The rpkiNotify URI of the publication point’s CA if present.
+    pub rpki_notify: Option<uri::Https>,
+
+    /// The caRepository URI of the publication point’s CA.
+    pub ca_repository: uri::Rsync,
+
+    /// The TAL the publication point was found under.
+    pub tal: Arc<TalInfo>,
+
+    /// The time the stored point was last updated from the collector.
+    pub refreshed: Option<DateTime<Utc>>,
+
+    /// The manifest number of the stored manifest.
+    pub manifest_number: x509::Serial,
+
+    /// The thisUpdate time of the stored manifest.
+    pub this_update: Time,
+
+    /// The nextUpdate time of the stored manifest.
+    pub next_update: Time,
+
+    /// The number of files listed on the stored manifest.
+    pub file_count: usize,
+
+    /// Was the stored data used because the collector failed?
+    pub fallback: bool,
+}
+
+impl StoredPointMetrics {
+    /// Returns the URI of the repository the point was collected from.
+    ///
+    /// This is the rpkiNotify URI for points in RRDP repositories and the
+    /// rsync module URI otherwise.
+    pub fn repository(&self) -> String {
+        match self.rpki_notify.as_ref() {
+            Some(uri) => uri.as_str().into(),
+            None => self.ca_repository.canonical_module().into_owned(),
+        }
+    }
+}
+
+
+//------------ ManifestReplayMetrics -----------------------------------------
+
+/// Information about a manifest that appeared to be replayed.
+///
+/// A collected manifest appears to be replayed if its thisUpdate time is
+/// not later than that of the manifest already stored for the publication
+/// point.
+#[derive(Clone, Debug)]
+pub struct ManifestReplayMetrics {
+    /// The rsync URI of the manifest.
+    pub manifest_uri: uri::Rsync,
+
+    /// The TAL the publication point was found under.
+    pub tal: Arc<TalInfo>,
+
+    /// The manifest number of the stored manifest.
+    pub stored_number: x509::Serial,
+
+    /// The thisUpdate time of the stored manifest.
+    pub stored_this_update: Time,
+
+    /// The manifest number of the collected manifest.
+    pub collected_number: x509::Serial,
+
+    /// The thisUpdate time of the collected manifest.
+    pub collected_this_update: Time,
+
+    /// Was the collected manifest rejected?
+    pub rejected: bool,
+}
+
+
+//------------ UnsafeVrpMetrics ----------------------------------------------
+
+/// Information about an unsafe VRP.
+///
+/// A VRP is unsafe if its prefix overlaps with the resources of a CA that
+/// had to be rejected during validation.
+#[derive(Clone, Debug)]
+pub struct UnsafeVrpMetrics {
+    /// The route origin of the VRP.
+    pub origin: RouteOrigin,
+
+    /// The TAL the VRP was derived from.
+    pub tal: Arc<TalInfo>,
+
+    /// The rsync URI of the ROA the VRP was derived from.
+    pub roa: Option<uri::Rsync>,
+
+    /// The rejected CAs whose resources overlap with the VRP.
+    pub conflicts: Vec<UnsafeVrpConflict>,
+}
+
+
+//------------ UnsafeVrpConflict ---------------------------------------------
+
+/// A rejected CA whose resources overlap with an unsafe VRP.
+#[derive(Clone, Debug)]
+pub struct UnsafeVrpConflict {
+    /// The URI of the rejected CA’s certificate.
+    pub ca: TalUri,
+
+    /// The address blocks of the CA that overlap with the VRP’s prefix.
+    ///
+    /// The blocks are of the same address family as the prefix.
+    pub blocks: Vec<IpBlock>,
+}
+
+
 //------------ SnapshotMetrics -----------------------------------------------
 
 /// Metrics regarding the full payload set.
@@ -484,6 +1731,9 @@ pub struct VrpMetrics {
     /// The number of VRPs overlapping with rejected publication points.
     pub marked_unsafe: u32,
 
+    /// The number of VRPs dropped for being outside accepted resources.
+    pub outside_resources: u32,
+
     /// The number of VRPs filtered due to local exceptions.
     pub locally_filtered: u32,
 
@@ -515,6 +1765,7 @@ impl<'a> ops::AddAssign<&'a Self> for VrpMetrics {
     fn add_assign(&mut self, other: &'a Self) {
         self.valid += other.valid;
         self.marked_unsafe += other.marked_unsafe;
+        self.outside_resources += other.outside_resources;
         self.locally_filtered += other.locally_filtered;
         self.duplicate += other.duplicate;
         self.contributed += other.contributed;
@@ -528,6 +1779,71 @@ impl ops::AddAssign for VrpMetrics {
 }
 
 
+//------------ PublishMetrics ------------------------------------------------
+
+/// Metrics for a publish target.
+#[derive(Clone, Debug)]
+pub struct PublishMetrics {
+    /// The URL of the target.
+    pub url: String,
+
+    /// The number of failed uploads since the start.
+    pub failures: u64,
+
+    /// The time of the last successful upload, if any.
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The time of the last failed upload, if any.
+    pub last_failure: Option<DateTime<Utc>>,
+}
+
+impl PublishMetrics {
+    /// Creates new metrics for the target with the given URL.
+    pub fn new(url: String) -> Self {
+        PublishMetrics {
+            url,
+            failures: 0,
+            last_success: None,
+            last_failure: None,
+        }
+    }
+}
+
+
+//------------ IntegrityMetrics ----------------------------------------------
+
+/// Metrics for the background integrity check of the RRDP archives.
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityMetrics {
+    /// The number of archives checked since the start.
+    pub checked: u64,
+
+    /// The number of corrupt archives found since the start.
+    pub corrupt: u64,
+
+    /// The time the last complete pass over all archives finished, if any.
+    pub last_pass: Option<DateTime<Utc>>,
+
+    /// The corrupt archives found since the start.
+    ///
+    /// Each archive is listed once with the time it was last found
+    /// corrupt.
+    pub corrupt_archives: Vec<(PathBuf, DateTime<Utc>)>,
+}
+
+impl IntegrityMetrics {
+    /// Records that the archive at the given path was found corrupt.
+    pub fn add_corrupt(&mut self, path: PathBuf) {
+        self.corrupt += 1;
+        let now = Utc::now();
+        match self.corrupt_archives.iter_mut().find(|item| item.0 == path) {
+            Some(item) => item.1 = now,
+            None => self.corrupt_archives.push((path, now)),
+        }
+    }
+}
+
+
 //------------ HttpServerMetrics ---------------------------------------------
 
 #[derive(Debug, Default)]
@@ -601,10 +1917,14 @@ impl SharedRtrServerMetrics {
     /// Creates a new shareable value.
     ///
     /// If `detailed` is `true` per-client statistics should be produced when
-    /// presenting the metrics.
-    pub fn new(detailed: bool) -> Self {
+    /// presenting the metrics. At most `max_clients` client addresses are
+    /// kept track of. A value of zero means that there is no limit.
+    pub fn new(detailed: bool, max_clients: usize) -> Self {
         SharedRtrServerMetrics {
-            metrics: Default::default(),
+            metrics: Arc::new(Mutex::new(RtrServerMetrics {
+                max_clients,
+                .. Default::default()
+            })),
             detailed
         }
     }
@@ -647,6 +1967,11 @@ impl SharedRtrServerMetrics {
 /// open elements for an address if there are multiple open connections from
 /// the address.
 ///
+/// If a limit for the number of addresses is given, the addresses without
+/// open connections that have been updated least recently are dropped
+/// whenever the limit is exceeded. Their byte counts are kept so the totals
+/// stay correct.
+///
 /// The list is always ordered by address. Thus, if you iterate over the
 /// list via [`iter_clients`][Self::iter_clients], all elements with the same
 /// address will appear in an uninterrupted sequence. The
@@ -661,6 +1986,17 @@ pub struct RtrServerMetrics {
     /// inserts a new value. Closed connections (the `open` flag is `false`)
     /// will be collapsed into a single value ever so often.
     clients: Vec<Arc<RtrClientMetrics>>,
+
+    /// The maximum number of client addresses to keep.
+    ///
+    /// A value of zero means that there is no limit.
+    max_clients: usize,
+
+    /// The number of bytes read from clients that have been dropped.
+    dropped_bytes_read: u64,
+
+    /// The number of bytes written to clients that have been dropped.
+    dropped_bytes_written: u64,
 }
 
 impl RtrServerMetrics {
@@ -671,12 +2007,18 @@ impl RtrServerMetrics {
 
     /// Returns the total number of bytes read.
     pub fn bytes_read(&self) -> u64 {
-        self.clients.iter().map(|client| client.bytes_read()).sum()
+        self.dropped_bytes_read
+        + self.clients.iter().map(|client| {
+            client.bytes_read()
+        }).sum::<u64>()
     }
 
     /// Returns the total number of bytes written.
     pub fn bytes_written(&self) -> u64 {
-        self.clients.iter().map(|client| client.bytes_written()).sum()
+        self.dropped_bytes_written
+        + self.clients.iter().map(|client| {
+            client.bytes_written()
+        }).sum::<u64>()
     }
 
     /// Returns an iterator over all clients.
@@ -818,6 +2160,58 @@ impl RtrServerMetrics {
             };
             self.clients.insert(index, client);
         }
+
+        self.limit_clients();
+    }
+
+    /// Drops idle client addresses if there are too many.
+    fn limit_clients(&mut self) {
+        if self.max_clients == 0 {
+            return
+        }
+
+        // Collect the addresses without open connections together with
+        // the time of their last update.
+        let mut addr_count = 0;
+        let mut idle = Vec::new();
+        let folded = self.fold_clients(
+            (false, i64::MIN),
+            |(open, updated), client| {
+                *open |= client.is_open();
+                *updated = cmp::max(
+                    *updated, client.updated.load(Ordering::Relaxed)
+                );
+            }
+        );
+        for (addr, (open, updated)) in folded {
+            addr_count += 1;
+            if !open {
+                idle.push((updated, addr))
+            }
+        }
+        if addr_count <= self.max_clients {
+            return
+        }
+
+        // Drop the least recently updated ones.
+        idle.sort_unstable();
+        let drop: HashSet<_> = idle.into_iter().take(
+            addr_count - self.max_clients
+        ).map(|(_, addr)| addr).collect();
+        let mut bytes_read = 0;
+        let mut bytes_written = 0;
+        self.clients.retain(|client| {
+            if drop.contains(&client.addr) {
+                bytes_read += client.bytes_read();
+                bytes_written += client.bytes_written();
+                false
+            }
+            else {
+                true
+            }
+        });
+        self.dropped_bytes_read += bytes_read;
+        self.dropped_bytes_written += bytes_written;
     }
 }
 
@@ -873,6 +2267,21 @@ pub struct RtrClientMetrics {
 
     /// The number of bytes written.
     bytes_written: AtomicU64,
+
+    /// The negotiated RTR protocol version.
+    ///
+    /// This is actually an option with the value of `u8::MAX` serving as
+    /// `None`.
+    version: AtomicU8,
+
+    /// The serial number the client asked for in its last serial query.
+    ///
+    /// This is actually an option with the value of `u32::MAX` serving as
+    /// `None`.
+    requested_serial: AtomicU32,
+
+    /// The number of PDUs sent to the client.
+    pdus_sent: AtomicU64,
 }
 
 impl RtrClientMetrics {
@@ -888,6 +2297,9 @@ impl RtrClientMetrics {
             serial_queries: AtomicU32::new(0),
             bytes_read: AtomicU64::new(0),
             bytes_written: AtomicU64::new(0),
+            version: AtomicU8::new(u8::MAX),
+            requested_serial: AtomicU32::new(u32::MAX),
+            pdus_sent: AtomicU64::new(0),
         }
     }
 
@@ -921,6 +2333,52 @@ impl RtrClientMetrics {
         self.bytes_written.fetch_add(count, Ordering::Relaxed);
     }
 
+    /// Returns the negotiated RTR protocol version.
+    ///
+    /// Returns `None` if no version has been negotiated yet.
+    pub fn version(&self) -> Option<u8> {
+        let version = self.version.load(Ordering::Relaxed);
+        if version == u8::MAX {
+            None
+        }
+        else {
+            Some(version)
+        }
+    }
+
+    /// Sets the negotiated RTR protocol version.
+    pub fn set_version(&self, version: u8) {
+        self.version.store(version, Ordering::Relaxed)
+    }
+
+    /// Returns the serial number requested in the last serial query.
+    ///
+    /// Returns `None` if the client never sent a serial query.
+    pub fn requested_serial(&self) -> Option<Serial> {
+        let serial = self.requested_serial.load(Ordering::Relaxed);
+        if serial == u32::MAX {
+            None
+        }
+        else {
+            Some(serial.into())
+        }
+    }
+
+    /// Sets the serial number requested by a serial query.
+    pub fn set_requested_serial(&self, serial: Serial) {
+        self.requested_serial.store(serial.into(), Ordering::Relaxed)
+    }
+
+    /// Returns the number of PDUs sent to this client.
+    pub fn pdus_sent(&self) -> u64 {
+        self.pdus_sent.load(Ordering::Relaxed)
+    }
+
+    /// Increases the number of PDUs sent to this client by one.
+    pub fn inc_pdus_sent(&self) {
+        self.pdus_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Returns the serial number of the last successful update.
     ///
     /// Returns `None` if there never was a successful update.
@@ -990,22 +2448,26 @@ impl RtrClientMetrics {
     /// The returned value will use the addr of `self` and will always be
     /// closed.
     fn collapse_closed(&self, other: &Self) -> Self {
+        fn max_option(left: u32, right: u32) -> u32 {
+            if left == u32::MAX {
+                right
+            }
+            else if right == u32::MAX {
+                left
+            }
+            else {
+                cmp::max(left, right)
+            }
+        }
+
         let left_serial = self.serial.load(Ordering::Relaxed);
         let right_serial = other.serial.load(Ordering::Relaxed);
+        let left_version = self.version.load(Ordering::Relaxed);
+        let right_version = other.version.load(Ordering::Relaxed);
         RtrClientMetrics {
             addr: self.addr,
             open: AtomicBool::new(false),
-            serial: AtomicU32::new(
-                if left_serial == u32::MAX {
-                    right_serial
-                }
-                else if right_serial == u32::MAX {
-                    left_serial
-                }
-                else {
-                    cmp::max(left_serial, right_serial)
-                }
-            ),
+            serial: AtomicU32::new(max_option(left_serial, right_serial)),
             updated: AtomicI64::new(
                 cmp::max(
                     self.updated.load(Ordering::Relaxed),
@@ -1034,6 +2496,24 @@ impl RtrClientMetrics {
                 self.bytes_written.load(Ordering::Relaxed)
                 + other.bytes_written.load(Ordering::Relaxed)
             ),
+            version: AtomicU8::new(
+                if right_version == u8::MAX {
+                    left_version
+                }
+                else {
+                    right_version
+                }
+            ),
+            requested_serial: AtomicU32::new(
+                max_option(
+                    self.requested_serial.load(Ordering::Relaxed),
+                    other.requested_serial.load(Ordering::Relaxed),
+                )
+            ),
+            pdus_sent: AtomicU64::new(
+                self.pdus_sent.load(Ordering::Relaxed)
+                + other.pdus_sent.load(Ordering::Relaxed)
+            ),
         }
     }
 }
@@ -1103,6 +2583,266 @@ mod test {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn tals_without_roas() {
+        fn tal(name: &str, valid_roas: u32) -> TalMetrics {
+            let mut res = TalMetrics::new(
+                Arc::new(TalInfo::from_name(name.into()))
+            );
+            res.publication.valid_roas = valid_roas;
+            res
+        }
+
+        // All TALs produced ROAs.
+        let mut metrics = Metrics::new();
+        metrics.tals.push(tal("apnic", 10));
+        metrics.tals.push(tal("ripe", 12));
+        assert_eq!(metrics.tals_without_roas().count(), 0);
+
+        // A TAL without any ROAs.
+        metrics.tals.push(tal("arin", 0));
+        assert_eq!(
+            metrics.tals_without_roas().collect::<Vec<_>>(),
+            ["arin"]
+        );
+
+        // All TALs failed.
+        let mut metrics = Metrics::new();
+        metrics.tals.push(tal("apnic", 0));
+        metrics.tals.push(tal("arin", 0));
+        assert_eq!(
+            metrics.tals_without_roas().collect::<Vec<_>>(),
+            ["apnic", "arin"]
+        );
+    }
+
+    #[test]
+    fn keep_tal_updates() {
+        let time = Utc::now();
+        let mut previous = Metrics::new();
+        for name in ["apnic", "arin"] {
+            let mut tal = TalMetrics::new(
+                Arc::new(TalInfo::from_name(name.into()))
+            );
+            tal.updated = Some(time - chrono::Duration::hours(1));
+            previous.tals.push(tal);
+        }
+        let mut metrics = Metrics::new();
+        for name in ["apnic", "arin", "ripe"] {
+            metrics.tals.push(TalMetrics::new(
+                Arc::new(TalInfo::from_name(name.into()))
+            ));
+        }
+        metrics.tals[0].updated = Some(time);
+        metrics.keep_tal_updates(&previous);
+        assert_eq!(metrics.tals[0].updated, Some(time));
+        assert_eq!(metrics.tals[1].updated, previous.tals[1].updated);
+        assert_eq!(metrics.tals[2].updated, None);
+    }
+
+    #[test]
+    fn merge_tal() {
+        fn metrics(
+            roas: [u32; 2], repos: &[&str], rrdp: &[&str]
+        ) -> Metrics {
+            let mut res = Metrics::new();
+            let names = ["apnic", "arin"];
+            for (name, valid_roas) in names.into_iter().zip(roas) {
+                let mut tal = TalMetrics::new(
+                    Arc::new(TalInfo::from_name(name.into()))
+                );
+                tal.publication.valid_roas = valid_roas;
+                res.publication.valid_roas += valid_roas;
+                tal.payload.v4_origins.valid = valid_roas;
+                res.tals.push(tal);
+            }
+            for uri in repos {
+                let mut repo = RepositoryMetrics::new((*uri).into());
+                repo.publication.valid_roas = 1;
+                res.repositories.push(repo);
+            }
+            for uri in rrdp {
+                res.rrdp.push(RrdpRepositoryMetrics::new(
+                    uri::Https::from_str(uri).unwrap()
+                ));
+            }
+            res
+        }
+
+        let old = metrics(
+            [10, 20],
+            &["https://a.example/", "https://b.example/"],
+            &["https://a.example/", "https://b.example/"],
+        );
+        let mut new = metrics(
+            [0, 25],
+            &["https://c.example/", "https://b.example/"],
+            &["https://b.example/"],
+        );
+        new.repositories[1].publication.valid_roas = 5;
+        new.rrdp[0].serial = Some(12);
+        let (merged, map) = old.merge_tal(new, 1);
+
+        assert_eq!(map, [2, 1]);
+        assert_eq!(merged.tals[0].publication.valid_roas, 10);
+        assert_eq!(merged.tals[1].publication.valid_roas, 25);
+        assert_eq!(merged.publication.valid_roas, 35);
+        assert_eq!(merged.tals[0].payload.v4_origins.valid, 0);
+        assert_eq!(merged.tals[1].payload.v4_origins.valid, 0);
+        assert_eq!(
+            merged.repositories.iter().map(|repo| {
+                (repo.uri.as_str(), repo.publication.valid_roas)
+            }).collect::<Vec<_>>(),
+            [
+                ("https://a.example/", 1),
+                ("https://b.example/", 5),
+                ("https://c.example/", 1),
+            ]
+        );
+        assert_eq!(merged.rrdp.len(), 2);
+        assert_eq!(merged.rrdp[0].serial, None);
+        assert_eq!(merged.rrdp[1].serial, Some(12));
+    }
+
+    #[test]
+    fn detect_clock_skew() {
+        fn metrics(offsets: &[(&str, Option<i64>)]) -> Metrics {
+            let mut res = Metrics::new();
+            for (uri, offset) in offsets {
+                let mut rrdp = RrdpRepositoryMetrics::new(
+                    uri::Https::from_str(uri).unwrap()
+                );
+                rrdp.clock_offset = *offset;
+                res.rrdp.push(rrdp);
+            }
+            res.detect_clock_skew(Duration::from_secs(300));
+            res
+        }
+
+        // All clocks agree.
+        assert_eq!(
+            metrics(&[
+                ("https://a.example/n.xml", Some(1)),
+                ("https://b.example/n.xml", Some(-2)),
+                ("https://c.example/n.xml", Some(0)),
+            ]).clock_skew,
+            None
+        );
+
+        // Three hosts agree on a skew.
+        assert_eq!(
+            metrics(&[
+                ("https://a.example/n.xml", Some(-3600)),
+                ("https://b.example/n.xml", Some(-3602)),
+                ("https://c.example/n.xml", Some(-3601)),
+                ("https://d.example/n.xml", None),
+            ]).clock_skew,
+            Some(-3601)
+        );
+
+        // Repositories on the same host only count once.
+        assert_eq!(
+            metrics(&[
+                ("https://a.example/1.xml", Some(3600)),
+                ("https://a.example/2.xml", Some(3600)),
+                ("https://b.example/n.xml", Some(3600)),
+                ("https://c.example/n.xml", Some(0)),
+            ]).clock_skew,
+            None
+        );
+
+        // Only a minority of hosts is skewed.
+        assert_eq!(
+            metrics(&[
+                ("https://a.example/n.xml", Some(3600)),
+                ("https://b.example/n.xml", Some(3600)),
+                ("https://c.example/n.xml", Some(3600)),
+                ("https://d.example/n.xml", Some(0)),
+                ("https://e.example/n.xml", Some(1)),
+                ("https://f.example/n.xml", Some(-1)),
+            ]).clock_skew,
+            None
+        );
+    }
+
+    #[test]
+    fn object_counts() {
+        let metrics = PublicationMetrics {
+            valid_manifests: 10,
+            invalid_manifests: 1,
+            valid_crls: 10,
+            invalid_crls: 2,
+            stray_crls: 1,
+            valid_ca_certs: 8,
+            valid_router_certs: 2,
+            invalid_certs: 3,
+            valid_roas: 20,
+            others: 4,
+            .. Default::default()
+        };
+        assert_eq!(
+            metrics.object_type_counts().map(|(object_type, counts)| {
+                (object_type.as_str(), counts.accepted, counts.rejected)
+            }).collect::<Vec<_>>(),
+            [
+                ("mft", 10, 1), ("crl", 10, 3), ("cer", 10, 3),
+                ("roa", 20, 0), ("aspa", 0, 0), ("gbr", 0, 0),
+                ("unknown", 0, 4),
+            ]
+        );
+        assert_eq!(metrics.object_counts(ObjectType::Crl).seen(), 13);
+    }
+
+    #[test]
+    fn slowest_repository_hosts() {
+        fn repo(uri: &str, fetch: u64, validation: u64) -> RepositoryMetrics {
+            let mut res = RepositoryMetrics::new(uri.into());
+            res.fetch_duration = Duration::from_secs(fetch);
+            res.validation_duration = Duration::from_secs(validation);
+            res
+        }
+
+        let mut metrics = Metrics::new();
+        metrics.repositories.push(
+            repo("https://rrdp.example.net/notification.xml", 1, 2)
+        );
+        metrics.repositories.push(repo("rsync://rsync.example.com/", 4, 1));
+        metrics.repositories.push(
+            repo("rsync://rrdp.example.net/repo/", 2, 1)
+        );
+        assert_eq!(
+            metrics.slowest_repositories().iter().map(|repo| {
+                repo.uri.as_str()
+            }).collect::<Vec<_>>(),
+            [
+                "rsync://rsync.example.com/",
+                "https://rrdp.example.net/notification.xml",
+                "rsync://rrdp.example.net/repo/",
+            ]
+        );
+        assert_eq!(
+            metrics.slowest_repository_hosts(),
+            [
+                ("rrdp.example.net", Duration::from_secs(3),
+                    Duration::from_secs(3)),
+                ("rsync.example.com", Duration::from_secs(4),
+                    Duration::from_secs(1)),
+            ]
+        );
+
+        for i in 0..2 * SLOWEST_REPOSITORIES {
+            metrics.repositories.push(
+                repo(&format!("rsync://host{}.example.com/", i), 0, 0)
+            );
+        }
+        assert_eq!(
+            metrics.slowest_repositories().len(), SLOWEST_REPOSITORIES
+        );
+        assert_eq!(
+            metrics.slowest_repository_hosts().len(), SLOWEST_REPOSITORIES
+        );
+    }
+
     #[test]
     fn insert_rtr_metrics() {
         let addr1 = IpAddr::from_str("10.0.0.1").unwrap();
@@ -1152,5 +2892,164 @@ mod test {
         assert_eq!(open3.bytes_read(), 0);
         assert_eq!(closed3.bytes_read(), 50);
     }
+
+    #[test]
+    fn limit_rtr_clients() {
+        fn client(addr: &str, updated: i64) -> Arc<RtrClientMetrics> {
+            let res = RtrClientMetrics::new(IpAddr::from_str(addr).unwrap());
+            res.updated.store(updated, Ordering::Relaxed);
+            res.inc_bytes_read(10);
+            res.into()
+        }
+
+        let mut metrics = RtrServerMetrics {
+            max_clients: 3, .. Default::default()
+        };
+        metrics.insert_client(client("10.0.0.1", 100));
+        metrics.insert_client(client("10.0.0.2", 50));
+        metrics.insert_client(client("10.0.0.3", 10));
+        metrics.insert_client(client("10.0.0.3", 10));
+        assert_eq!(metrics.clients.len(), 4);
+
+        // Only closed clients can be dropped and the oldest goes first.
+        metrics.clients[0].close();
+        metrics.clients[1].close();
+        metrics.insert_client(client("10.0.0.4", 0));
+        assert_eq!(
+            metrics.clients.iter().map(|client| {
+                client.addr.to_string()
+            }).collect::<Vec<_>>(),
+            ["10.0.0.1", "10.0.0.3", "10.0.0.3", "10.0.0.4"]
+        );
+        assert_eq!(metrics.bytes_read(), 50);
+    }
+
+    #[test]
+    fn rrdp_duration_histograms() {
+        fn rrdp(uri: &str, secs: u64) -> RrdpRepositoryMetrics {
+            let mut res = RrdpRepositoryMetrics::new(
+                uri::Https::from_str(uri).unwrap()
+            );
+            res.duration = Ok(Duration::from_secs(secs));
+            res
+        }
+
+        let mut hist = RrdpDurationHistograms::default();
+        hist.update(&[
+            rrdp("https://rrdp.example.net/notification.xml", 1),
+            rrdp("https://rrdp.example.net/other/notification.xml", 20),
+            rrdp("https://rrdp.example.com/notification.xml", 400),
+        ]);
+        hist.update(&[
+            rrdp("https://rrdp.example.net/notification.xml", 3),
+        ]);
+
+        let mut iter = hist.iter();
+        let (host, com) = iter.next().unwrap();
+        assert_eq!(host, "rrdp.example.com");
+        assert_eq!(com.counts(), &[0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(com.count(), 1);
+        let (host, net) = iter.next().unwrap();
+        assert_eq!(host, "rrdp.example.net");
+        assert_eq!(net.counts(), &[0, 1, 1, 2, 2, 3, 3, 3, 3]);
+        assert_eq!(net.count(), 3);
+        assert_eq!(net.sum(), 24.);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn rrdp_update_totals() {
+        use crate::collector::UpdateRecord;
+
+        fn rrdp(
+            kind: Option<RrdpUpdateKind>,
+            reason: Option<SnapshotReason>,
+            status: HttpStatus,
+        ) -> RrdpRepositoryMetrics {
+            let rate_limited = status.is_rate_limit();
+            let mut res = RrdpRepositoryMetrics::new(
+                uri::Https::from_str(
+                    "https://rrdp.example.net/notification.xml"
+                ).unwrap()
+            );
+            res.notify_status = status;
+            res.history.push(UpdateRecord::now(kind, rate_limited, reason));
+            res
+        }
+
+        let ok = HttpStatus::Response(reqwest::StatusCode::OK);
+        let mut totals = RrdpUpdateTotals::default();
+        totals.update(&[
+            rrdp(Some(RrdpUpdateKind::Delta), None, ok),
+            rrdp(
+                Some(RrdpUpdateKind::Snapshot),
+                Some(SnapshotReason::NewSession), ok
+            ),
+            rrdp(None, Some(SnapshotReason::NewSession), ok),
+            rrdp(None, None, HttpStatus::BackedOff),
+        ]);
+        totals.update(&[
+            rrdp(Some(RrdpUpdateKind::Delta), None, ok),
+            rrdp(
+                None, None,
+                HttpStatus::Response(reqwest::StatusCode::TOO_MANY_REQUESTS)
+            ),
+            rrdp(None, None, HttpStatus::RateLimited),
+        ]);
+        assert_eq!(
+            totals.outcomes().collect::<Vec<_>>(),
+            [
+                ("delta", 2), ("failed", 1), ("rate-limited", 1),
+                ("snapshot", 1)
+            ]
+        );
+        assert_eq!(
+            totals.snapshot_reasons().collect::<Vec<_>>(),
+            [("new-session", 2)]
+        );
+    }
+
+    #[test]
+    fn rrdp_notify_delay() {
+        let mut rrdp = RrdpRepositoryMetrics::new(
+            uri::Https::from_str(
+                "https://rrdp.example.net/notification.xml"
+            ).unwrap()
+        );
+        assert_eq!(rrdp.notify_delay(), None);
+        rrdp.notify_processed = Utc.timestamp_opt(1_700_000_090, 0).single();
+        assert_eq!(rrdp.notify_delay(), None);
+        rrdp.notify_last_modified = Utc.timestamp_opt(
+            1_700_000_000, 0
+        ).single();
+        assert_eq!(rrdp.notify_delay(), Some(90));
+        rrdp.notify_last_modified = Utc.timestamp_opt(
+            1_700_000_100, 0
+        ).single();
+        assert_eq!(rrdp.notify_delay(), Some(-10));
+    }
+
+    #[test]
+    fn cache_usage() {
+        let mut usage = CacheUsageMetrics::new(None);
+        for (index, size) in [
+            300, 100, 200, 0, 50, 60, 70, 80, 90, 110, 120, 130
+        ].into_iter().enumerate() {
+            usage.repositories.push((format!("repo{}", index), size));
+        }
+        assert_eq!(usage.total(), 1310);
+        assert!(!usage.is_exceeded());
+
+        let largest = usage.largest_repositories();
+        assert_eq!(largest.len(), LARGEST_CACHE_REPOSITORIES);
+        assert_eq!(largest[0], ("repo0", 300));
+        assert_eq!(largest[1], ("repo2", 200));
+        assert_eq!(largest[9], ("repo5", 60));
+
+        usage.max_size = Some(1310);
+        assert!(!usage.is_exceeded());
+        usage.max_size = Some(1309);
+        assert!(usage.is_exceeded());
+    }
 }
 
@@ -11,35 +11,50 @@
 // consisitency.
 #![allow(clippy::unnecessary_wraps)]
 
-use std::{fs, io, thread};
+use std::{cmp, fs, io, mem, ops, thread};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
 use std::sync::mpsc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::RecvTimeoutError;
 use std::time::{Duration, Instant};
 #[cfg(feature = "rta")] use bytes::Bytes;
-use clap::{Arg, Args, ArgAction, ArgMatches, FromArgMatches, Parser};
+use clap::{
+    Arg, Args, ArgAction, ArgMatches, FromArgMatches, Parser, Subcommand
+};
 use log::{error, info, warn};
 use rpki::resources::{Asn, Prefix};
 #[cfg(feature = "rta")] use rpki::repository::rta::Rta;
+use rpki::rtr::{Action, Serial};
 use rpki::rtr::server::NotifySender;
 use tempfile::NamedTempFile;
 use tokio::sync::oneshot;
 #[cfg(feature = "rta")] use crate::rta;
 use crate::{output, validity};
-use crate::config::Config;
+use crate::check::ObjectReport;
+use crate::config::{Config, FilterPolicy};
 use crate::error::{ExitError, Failed, RunFailed};
 use crate::http::http_listener;
-use crate::metrics::{SharedRtrServerMetrics};
+use crate::integrity::{IntegrityCheck, IntegrityCheckRequest};
+use crate::metrics::{Metrics, SharedRtrServerMetrics};
 use crate::output::{Output, OutputFormat};
-use crate::payload::{PayloadSnapshot, SharedHistory, ValidationReport};
-use crate::process::Process;
+use crate::payload::{
+    PayloadDelta, PayloadSnapshot, SharedHistory, SnapshotArchiver,
+    ValidationReport, ValidationRunner, import_payload,
+};
+use crate::process::{Process, ServiceNotifier};
+use crate::hooks::RunHooks;
+use crate::publish::Publisher;
 use crate::engine::Engine;
 use crate::rtr::{rtr_listener};
-use crate::slurm::LocalExceptions;
+use crate::slurm::{ExceptionFiles, LocalExceptions, ReloadRequest};
+use crate::utils::dump::DumpTarget;
+use crate::utils::fatal;
+use crate::utils::net::UnixSocketFiles;
+use crate::utils::shutdown::ShutdownTrigger;
+use crate::utils::tls::TlsConfigs;
 
 #[cfg(unix)] use tokio::signal::unix::{Signal, SignalKind, signal};
 #[cfg(not(unix))] use futures::future::pending;
@@ -65,12 +80,19 @@ pub enum Operation {
     Server(Server),
     Vrps(Vrps),
     Validate(Validate),
+    CheckRib(CheckRib),
+    CheckObject(CheckObject),
     #[cfg(feature = "rta")]
     ValidateDocument(ValidateDocument),
     Update(Update),
     PrintConfig(PrintConfig),
     Dump(Dump),
+    Restore(Restore),
     ArchiveStats(ArchiveStats),
+    Archive(Archive),
+    Slurm(Slurm),
+    Compare(Compare),
+    ClearCache(ClearCache),
     Man(Man),
 }
 
@@ -87,6 +109,8 @@ impl Operation {
         let app = Server::config_args(app);
         let app = Vrps::config_args(app);
         let app = Validate::config_args(app);
+        let app = CheckRib::config_args(app);
+        let app = CheckObject::config_args(app);
 
         #[cfg(feature = "rta")]
         let app = ValidateDocument::config_args(app);
@@ -94,7 +118,12 @@ impl Operation {
         let app = Update::config_args(app);
         let app = PrintConfig::config_args(app);
         let app = Dump::config_args(app);
+        let app = Restore::config_args(app);
         let app = ArchiveStats::config_args(app);
+        let app = Archive::config_args(app);
+        let app = Slurm::config_args(app);
+        let app = Compare::config_args(app);
+        let app = ClearCache::config_args(app);
         Man::config_args(app)
     }
 
@@ -116,6 +145,16 @@ impl Operation {
             Some(("validate", matches)) => {
                 Operation::Validate(Validate::from_arg_matches(matches)?)
             },
+            Some(("check-rib", matches)) => {
+                Operation::CheckRib(
+                    CheckRib::from_arg_matches(matches, cur_dir)?
+                )
+            }
+            Some(("check-object", matches)) => {
+                Operation::CheckObject(
+                    CheckObject::from_arg_matches(matches, cur_dir)?
+                )
+            }
             #[cfg(feature = "rta")]
             Some(("rta", matches)) => {
                 Operation::ValidateDocument(
@@ -133,11 +172,30 @@ impl Operation {
             Some(("dump", matches)) => {
                 Operation::Dump( Dump::from_arg_matches(matches, cur_dir)?)
             }
+            Some(("restore", matches)) => {
+                Operation::Restore(
+                    Restore::from_arg_matches(matches, cur_dir)?
+                )
+            }
             Some(("archive-stats", matches)) => {
                 Operation::ArchiveStats(
                     ArchiveStats::from_arg_matches(matches)?
                 )
             }
+            Some(("archive", matches)) => {
+                Operation::Archive(Archive::from_arg_matches(matches)?)
+            }
+            Some(("slurm", matches)) => {
+                Operation::Slurm(Slurm::from_arg_matches(matches, cur_dir)?)
+            }
+            Some(("compare", matches)) => {
+                Operation::Compare(
+                    Compare::from_arg_matches(matches, cur_dir)?
+                )
+            }
+            Some(("clear-cache", matches)) => {
+                Operation::ClearCache(ClearCache::from_arg_matches(matches)?)
+            }
             Some(("man", matches)) => {
                 Operation::Man(Man::from_arg_matches(matches)?)
             }
@@ -169,12 +227,19 @@ impl Operation {
             Operation::Server(cmd) => cmd.run(process),
             Operation::Vrps(cmd) => cmd.run(process),
             Operation::Validate(cmd) => cmd.run(process),
+            Operation::CheckRib(cmd) => cmd.run(process),
+            Operation::CheckObject(cmd) => cmd.run(process),
             #[cfg(feature = "rta")]
             Operation::ValidateDocument(cmd) => cmd.run(process),
             Operation::Update(cmd) => cmd.run(process),
             Operation::PrintConfig(cmd) => cmd.run(process),
             Operation::Dump(cmd) => cmd.run(process),
+            Operation::Restore(cmd) => cmd.run(process),
             Operation::ArchiveStats(cmd) => cmd.run(process),
+            Operation::Archive(cmd) => cmd.run(process),
+            Operation::Slurm(cmd) => cmd.run(process),
+            Operation::Compare(cmd) => cmd.run(process),
+            Operation::ClearCache(cmd) => cmd.run(process),
             Operation::Man(cmd) => cmd.run(process),
         }
     }
@@ -193,6 +258,10 @@ pub struct Server {
     // which has a few extra consequences.
     #[arg(short, long)]
     detach: bool,
+
+    /// Print the effective configuration as JSON and exit
+    #[arg(long)]
+    dump_config: bool,
 }
 
 impl Server {
@@ -224,58 +293,100 @@ impl Server {
     /// If `detach` is `true`, will fork the server and exit. Otherwise
     /// just runs the server forever.
     pub fn run(self, mut process: Process) -> Result<(), ExitError> {
-        let log = process.switch_logging(
+        if self.dump_config {
+            println!("{:#}", process.config().to_json());
+            return Ok(())
+        }
+        let (log, journal) = process.switch_server_logging(
             self.detach,
             !process.config().http_listen.is_empty()
+            || !process.config().http_monitoring_listen.is_empty()
         )?;
         warn!("Using config file {}.", process.config().config_file.display());
         process.setup_service(self.detach)?;
+        let notifier = process.service_notifier();
         let log = log.map(Arc::new);
+        let journal = journal.map(Arc::new);
         let rtr_metrics = SharedRtrServerMetrics::new(
-            process.config().rtr_client_metrics
+            process.config().rtr_client_metrics,
+            process.config().rtr_client_metrics_limit,
         );
 
         let history = SharedHistory::from_config(process.config());
+        history.load();
         let mut notify = NotifySender::new();
+        let reload = ReloadRequest::default();
+        let refresh = RefreshRequest::default();
+        let integrity_request = IntegrityCheckRequest::default();
+        let (shutdown_trigger, shutdown) = ShutdownTrigger::new();
+        let shutdown_grace = process.config().shutdown_grace;
+        let tls_configs = TlsConfigs::default();
         let rtr = rtr_listener(
             history.clone(), rtr_metrics.clone(), process.config(),
-            notify.clone(), process.get_listen_fd()?
+            notify.clone(), process.get_listen_fd()?, shutdown.clone(),
+            &tls_configs,
         )?;
         let http = http_listener(
-            history.clone(), rtr_metrics, log.clone(), process.config(),
-            notify.clone(),
+            history.clone(), rtr_metrics, log.clone(), journal.clone(),
+            process.config(), notify.clone(), reload.clone(),
+            refresh.clone(), integrity_request.clone(), shutdown,
+            tls_configs.clone(),
         )?;
 
+        let socket_files = UnixSocketFiles::from_config(process.config());
         process.drop_privileges()?;
 
         let mut validation = Engine::new(process.config(), true)?;
+        let outputs = RunOutputs::start(process.config(), &history)?;
         let runtime = process.runtime()?;
         let mut rtr = runtime.spawn(rtr);
         let mut http = runtime.spawn(http);
         let (sig_tx, sig_rx) = mpsc::channel();
         let (err_tx, mut err_rx) = oneshot::channel();
 
+        let mut exceptions = ExceptionFiles::new(process.config(), true);
+        let mut integrity = IntegrityCheck::new(
+            process.config(), integrity_request
+        );
+        if !process.config().disable_warm_start {
+            Self::warm_start(
+                process.config(), &history, &mut notify, &mut exceptions
+            );
+        }
+        Self::notify_service(&notifier, &history);
+
         validation.ignite()?;
 
+        let persist_history = history.clone();
+        let service_history = history.clone();
+        let service_notifier = notifier.clone();
         let join = thread::spawn(move || {
             let mut can_retry = true;
             let err = loop {
                 if let Some(log) = log.as_ref() {
                     log.start();
                 }
+                if let Some(journal) = journal.as_ref() {
+                    journal.start();
+                }
 
-                let timeout = match LocalExceptions::load(
-                    process.config(), true
-                ) {
-                    Ok(exceptions) => {
+                refresh.start_full();
+                let _ = exceptions.load();
+                history.set_exceptions_stale(exceptions.is_stale());
+                let timeout = match exceptions.current() {
+                    Some(exceptions) => {
                         match Self::process_once(
                             process.config(), &validation, &history,
-                            &mut notify, exceptions,
+                            &mut notify, &outputs, exceptions,
                         ) {
                             Ok(()) => {
                                 history.read().refresh_wait()
                             }
                             Err(err) => {
+                                history.mark_update_failed();
+                                outputs.failure(
+                                    &history, "validation failed"
+                                );
                                 if err.should_retry() {
                                     if can_retry {
                                         if validation.sanitize().is_err() {
@@ -302,7 +413,7 @@ impl Server {
                         }
 
                     }
-                    Err(_) => {
+                    None => {
                         error!(
                             "Failed to load exceptions. \
                             Trying again in 10 seconds."
@@ -310,9 +421,14 @@ impl Server {
                         Duration::from_secs(10)
                     }
                 };
+                refresh.finish_full();
+                Self::notify_service(&notifier, &history);
                 if let Some(log) = log.as_ref() {
                     log.flush();
                 }
+                if let Some(journal) = journal.as_ref() {
+                    journal.finish();
+                }
 
                 // Because we don’t want to restart validation upon
                 // log rotation, we need to loop here. But then we need
@@ -328,11 +444,15 @@ impl Server {
                     let timeout = deadline.saturating_duration_since(
                         Instant::now()
                     );
-                    match sig_rx.recv_timeout(timeout) {
+                    match sig_rx.recv_timeout(
+                        cmp::min(timeout, EXCEPTIONS_POLL_INTERVAL)
+                    ) {
                         Ok(UserSignal::ReloadTals) => {
+                            notifier.reloading();
                             match validation.reload_tals() {
                                 Ok(_) => {
                                     info!("Reloaded TALs at user request.");
+                                    Self::notify_service(&notifier, &history);
                                     break None;
                                 },
                                 Err(_) => {
@@ -349,8 +469,66 @@ impl Server {
                                 break Some(Err(Failed));
                             }
                         }
+                        Ok(UserSignal::Shutdown)
+                        | Ok(UserSignal::ReloadTls) => {
+                            // These are handled by the runtime and never
+                            // sent here.
+                        }
                         Err(RecvTimeoutError::Timeout) => {
-                            break None;
+                            if Instant::now() >= deadline {
+                                break None;
+                            }
+                            Self::reload_exceptions(
+                                &mut exceptions, &reload, &history,
+                                &mut notify, &notifier,
+                            );
+                            integrity.run_due(process.config(), &history);
+                            let tals = match refresh.take() {
+                                Some(Refresh::Full) => {
+                                    info!(
+                                        "Validation run requested via HTTP."
+                                    );
+                                    break None;
+                                }
+                                Some(Refresh::Tals(tals)) => tals,
+                                None => continue,
+                            };
+                            let exceptions = match exceptions.current() {
+                                Some(exceptions) => exceptions,
+                                None => continue,
+                            };
+                            let mut end = None;
+                            for tal in tals {
+                                match Self::process_tal(
+                                    process.config(), &validation, &history,
+                                    &mut notify, &outputs, exceptions, &tal,
+                                ) {
+                                    Ok(true) => { }
+                                    Ok(false) => {
+                                        end = Some(None);
+                                        break;
+                                    }
+                                    Err(err) if err.should_retry() => {
+                                        error!(
+                                            "Validation run for TAL {} \
+                                             failed.",
+                                            tal
+                                        );
+                                        if validation.sanitize().is_err() {
+                                            end = Some(Some(Err(Failed)));
+                                            break;
+                                        }
+                                    }
+                                    Err(_) => {
+                                        end = Some(Some(Err(Failed)));
+                                        break;
+                                    }
+                                }
+                            }
+                            Self::notify_service(&notifier, &history);
+                            if let Some(end) = end {
+                                break end;
+                            }
                         }
                         Err(RecvTimeoutError::Disconnected) => {
                             break Some(Ok(()));
@@ -365,23 +543,51 @@ impl Server {
             let _ = err_tx.send(err);
         });
 
-        let res: Result<(), Failed> = runtime.block_on(async move {
+        let res: Result<bool, Failed> = runtime.block_on(async move {
             let mut signal = SignalListener::new()?;
+            let mut watchdog = service_notifier.watchdog_interval().map(
+                tokio::time::interval
+            );
             let res = loop {
                 tokio::select! {
                     sig = signal.next() => {
+                        if let UserSignal::Shutdown = sig {
+                            service_notifier.stopping();
+                            Self::drain(
+                                &mut signal, &shutdown_trigger,
+                                shutdown_grace,
+                            ).await;
+                            break Ok(true)
+                        }
+                        if let UserSignal::ReloadTls = sig {
+                            service_notifier.reloading();
+                            tls_configs.reload();
+                            Self::notify_service(
+                                &service_notifier, &service_history
+                            );
+
+                            // HUP also re-opens the log file so that it
+                            // can be rotated by external tools.
+                            if sig_tx.send(UserSignal::RotateLog).is_err() {
+                                break Err(Failed);
+                            }
+                            continue
+                        }
                         if sig_tx.send(sig).is_err() {
                             break Err(Failed);
                         }
                     }
                     res = &mut err_rx => {
                         match res {
-                            Ok(res) => break res,
+                            Ok(res) => break res.map(|_| false),
                             Err(_) => break Err(Failed)
                         }
                     }
                     _ = &mut rtr => break Err(Failed),
                     _ = &mut http => break Err(Failed),
+                    _ = Self::watchdog_tick(&mut watchdog) => {
+                        service_notifier.watchdog();
+                    }
                 }
             };
             // Dropping sig_tx will lead to sig_rx failing and the thread
@@ -392,8 +598,171 @@ impl Server {
             res
         });
 
+        persist_history.persist();
+
+        // If we were asked to shut down, the validation thread may be in
+        // the middle of a run. We don’t want to wait for that.
+        if let Ok(true) = res {
+            runtime.shutdown_background();
+            socket_files.remove();
+            return Ok(())
+        }
         let _ = join.join();
-        res.map_err(Into::into)
+        socket_files.remove();
+        res.map(|_| ()).map_err(Into::into)
+    }
+
+    /// Waits for the next watchdog notification to become due.
+    ///
+    /// If there is no watchdog, waits forever.
+    async fn watchdog_tick(watchdog: &mut Option<tokio::time::Interval>) {
+        match watchdog.as_mut() {
+            Some(watchdog) => {
+                watchdog.tick().await;
+            }
+            None => std::future::pending().await
+        }
+    }
+
+    /// Sends the current state to the service manager.
+    ///
+    /// Once the history has become active, i.e., once we are serving data,
+    /// this will signal readiness.
+    fn notify_service(notifier: &ServiceNotifier, history: &SharedHistory) {
+        let history = history.read();
+        if !history.is_active() {
+            notifier.status("Waiting for the first validation run.");
+            return
+        }
+        notifier.status(&Self::service_status(
+            history.serial(), history.stored_data(),
+            history.last_update_failed(),
+        ));
+        notifier.ready();
+    }
+
+    /// Returns the status message for the service manager.
+    fn service_status(
+        serial: Serial, stored_data: bool, failed: bool
+    ) -> String {
+        if stored_data {
+            format!(
+                "Serving stored data with serial {}. \
+                 First validation run in progress.",
+                serial
+            )
+        }
+        else if failed {
+            format!(
+                "Serving serial {}. Last validation run failed.", serial
+            )
+        }
+        else {
+            format!(
+                "Serving serial {}. Last validation run succeeded.", serial
+            )
+        }
+    }
+
+    /// Drains connections after shutdown has been requested.
+    ///
+    /// Triggers the shutdown which makes the listeners stop accepting new
+    /// connections and then waits until ongoing HTTP requests and RTR
+    /// transfers have finished, `grace` has passed, or another shutdown
+    /// signal has been received, whichever happens first.
+    async fn drain(
+        signal: &mut SignalListener,
+        trigger: &ShutdownTrigger,
+        grace: Duration,
+    ) {
+        info!(
+            "Shutting down. Waiting up to {} seconds for connections \
+             to finish.",
+            grace.as_secs()
+        );
+        trigger.trigger();
+        let timeout = tokio::time::sleep(grace);
+        tokio::pin!(timeout);
+        loop {
+            tokio::select! {
+                _ = trigger.drained() => {
+                    info!("All connections finished.");
+                    return
+                }
+                _ = &mut timeout => {
+                    warn!(
+                        "Shutdown grace period expired with {} connections \
+                         still active.",
+                        trigger.active()
+                    );
+                    return
+                }
+                sig = signal.next() => {
+                    if let UserSignal::Shutdown = sig {
+                        warn!("Shutting down immediately.");
+                        return
+                    }
+                }
+            }
+        }
+    }
+
+    /// Publishes a data set produced from the store only.
+    ///
+    /// This allows serving data while the first validation run, which
+    /// may have to fetch updates from the network, is still in progress.
+    /// If anything goes wrong or the store doesn’t contain any data, the
+    /// history is left untouched and the server waits for the first
+    /// validation run as usual.
+    fn warm_start(
+        config: &Config,
+        history: &SharedHistory,
+        notify: &mut NotifySender,
+        exceptions: &mut ExceptionFiles,
+    ) {
+        let _ = exceptions.load();
+        history.set_exceptions_stale(exceptions.is_stale());
+        let exceptions = match exceptions.current() {
+            Some(exceptions) => exceptions,
+            None => return
+        };
+        info!("Validating stored data.");
+        let engine = match Engine::new(config, false) {
+            Ok(engine) => engine,
+            Err(_) => {
+                warn!("Failed to validate stored data.");
+                return
+            }
+        };
+        let (report, metrics) = match ValidationReport::process_stored(
+            &engine, config
+        ) {
+            Ok(res) => res,
+            Err(_) => {
+                warn!("Failed to validate stored data.");
+                return
+            }
+        };
+        if metrics.publication.valid_roas == 0 {
+            info!("No usable stored data available.");
+            return
+        }
+        if !check_complete(config, &metrics, None) {
+            return
+        }
+        let must_notify = history.update(report, exceptions, metrics);
+        if !history.read().is_active() {
+            return
+        }
+        history.mark_stored_data();
+        info!(
+            "Serving stored data with serial {} until the first \
+             validation run has finished.",
+            history.read().serial()
+        );
+        if must_notify {
+            notify.notify();
+        }
     }
 
     fn process_once(
@@ -401,14 +770,33 @@ impl Server {
         engine: &Engine,
         history: &SharedHistory,
         notify: &mut NotifySender,
-        exceptions: LocalExceptions,
+        outputs: &RunOutputs,
+        exceptions: &LocalExceptions,
     ) -> Result<(), RunFailed> {
         info!("Starting a validation run.");
         history.mark_update_start();
         let (report, metrics) = ValidationReport::process(engine, config)?;
+        history.set_last_update_timed_out(metrics.timed_out);
+        history.set_clock_skew(metrics.clock_skew);
+        if !check_complete(config, &metrics, None) {
+            warn!("Keeping the data set of the previous validation run.");
+            history.mark_update_incomplete();
+            outputs.failure(
+                history,
+                if metrics.timed_out { "run timed out" }
+                else if metrics.clock_skew.is_some() { "clock skew" }
+                else { "incomplete update" }
+            );
+            return Ok(())
+        }
         let must_notify = history.update(
-            report, &exceptions, metrics,
+            report, exceptions, metrics,
         );
+        if history.read().vrp_guard().is_active() {
+            history.mark_update_incomplete();
+            outputs.failure(history, "data set held back by VRP guard");
+            return Ok(())
+        }
         if log::max_level() >= log::Level::Info {
             info!("Validation completed.");
             let (metrics, serial) = {
@@ -427,8 +815,299 @@ impl Server {
             notify.notify();
         }
         history.mark_update_done();
+        outputs.success(config, history);
         Ok(())
     }
+
+    /// Performs a validation run for a single TAL.
+    ///
+    /// The payload of the TAL is merged into the current data set. If that
+    /// isn’t possible because the payload of the last full run hasn’t been
+    /// retained, returns `Ok(false)` to signal that a full run should be
+    /// done instead.
+    fn process_tal(
+        config: &Config,
+        engine: &Engine,
+        history: &SharedHistory,
+        notify: &mut NotifySender,
+        outputs: &RunOutputs,
+        exceptions: &LocalExceptions,
+        tal: &str,
+    ) -> Result<bool, RunFailed> {
+        info!("Starting a validation run for TAL {}.", tal);
+        let (index, report, metrics) = match ValidationReport::process_tal(
+            engine, config, tal
+        )? {
+            Some(some) => some,
+            None => {
+                warn!("Ignoring validation run for unknown TAL {}.", tal);
+                return Ok(true)
+            }
+        };
+        if !check_complete(config, &metrics, Some(index)) {
+            warn!("Keeping the data set of the previous validation run.");
+            return Ok(true)
+        }
+        let must_notify = match history.update_tal(
+            report, index, exceptions, metrics
+        ) {
+            Some(must_notify) => must_notify,
+            None => {
+                info!(
+                    "Payload of the last validation run not available. \
+                     Starting a full validation run instead."
+                );
+                return Ok(false)
+            }
+        };
+        if history.read().vrp_guard().is_active() {
+            return Ok(true)
+        }
+        info!(
+            "Validation of TAL {} completed. New serial is {}.",
+            tal, history.read().serial()
+        );
+        if must_notify {
+            info!("Sending out notifications.");
+            notify.notify();
+        }
+        outputs.success(config, history);
+        Ok(true)
+    }
+
+    /// Writes the current payload to the configured output files.
+    ///
+    /// Failures are logged and recorded in the history but otherwise
+    /// ignored.
+    fn write_output_files(config: &Config, history: &SharedHistory) {
+        if config.output_files.is_empty() {
+            return
+        }
+        let (state, snapshot, metrics) = {
+            let history = history.read();
+            match (history.current(), history.metrics()) {
+                (Some(snapshot), Some(metrics)) => {
+                    (history.session_and_serial(), snapshot, metrics)
+                }
+                _ => return,
+            }
+        };
+        let mut output = Output::from_config(config);
+        output.set_rtr_state(state.0, state.1);
+        let mut failed = Vec::new();
+        for file in &config.output_files {
+            if let Err(err) = file.write(
+                output.clone(), snapshot.clone(), metrics.clone()
+            ) {
+                error!(
+                    "Failed to write output file {}: {}",
+                    file.path.display(), err
+                );
+                failed.push(file.path.clone());
+            }
+        }
+        history.set_failed_output_files(failed);
+    }
+
+    /// Reloads the local exceptions if necessary.
+    ///
+    /// This happens if any of the exceptions files have changed or if a
+    /// reload was explicitly requested. If the exceptions can be loaded,
+    /// they are applied to the payload of the last validation run.
+    fn reload_exceptions(
+        exceptions: &mut ExceptionFiles,
+        reload: &ReloadRequest,
+        history: &SharedHistory,
+        notify: &mut NotifySender,
+        notifier: &ServiceNotifier,
+    ) {
+        if !reload.take() && !exceptions.changed() {
+            return
+        }
+        notifier.reloading();
+        Self::load_exceptions(exceptions, history, notify);
+        Self::notify_service(notifier, history);
+    }
+
+    /// Loads the local exceptions and applies them to the current payload.
+    fn load_exceptions(
+        exceptions: &mut ExceptionFiles,
+        history: &SharedHistory,
+        notify: &mut NotifySender,
+    ) {
+        info!("Reloading local exceptions.");
+        let res = exceptions.load();
+        history.set_exceptions_stale(exceptions.is_stale());
+        if res.is_err() {
+            return
+        }
+        let current = match exceptions.current() {
+            Some(current) => current,
+            None => return
+        };
+        if history.update_exceptions(current) {
+            info!("New serial is {}.", history.read().serial());
+            if history.read().data_expired() {
+                // RTR clients won’t get the data anyway.
+                return
+            }
+            info!("Sending out notifications.");
+            notify.notify();
+        }
+    }
+}
+
+
+//------------ RunOutputs ----------------------------------------------------
+
+/// Everything the server does with the outcome of a validation run.
+struct RunOutputs {
+    /// The archiver for the data sets of successful runs if enabled.
+    archiver: Option<SnapshotArchiver>,
+
+    /// The publisher uploading the payload if configured.
+    publisher: Option<Publisher>,
+
+    /// The commands to run after each run if configured.
+    hooks: Option<RunHooks>,
+}
+
+impl RunOutputs {
+    /// Starts all outputs configured in `config`.
+    fn start(
+        config: &Config, history: &SharedHistory
+    ) -> Result<Self, Failed> {
+        Ok(RunOutputs {
+            archiver: SnapshotArchiver::start(config, history.clone())?,
+            publisher: Publisher::start(config, history.clone())?,
+            hooks: RunHooks::new(config),
+        })
+    }
+
+    /// Processes the data set of a successful run.
+    ///
+    /// The data set has to be in `history` already.
+    fn success(&self, config: &Config, history: &SharedHistory) {
+        if let Some(archiver) = self.archiver.as_ref() {
+            archiver.trigger();
+        }
+        Server::write_output_files(config, history);
+        if let Some(publisher) = self.publisher.as_ref() {
+            publisher.trigger();
+        }
+        if let Some(hooks) = self.hooks.as_ref() {
+            hooks.success(history);
+        }
+    }
+
+    /// Reports a failed run for the given reason.
+    fn failure(&self, history: &SharedHistory, reason: &str) {
+        if let Some(hooks) = self.hooks.as_ref() {
+            hooks.failure(history, reason);
+        }
+    }
+}
+
+
+//------------ RefreshRequest ------------------------------------------------
+
+/// A request to start a validation run ahead of schedule.
+///
+/// The value is shared between whoever wants to request a run and the
+/// server which checks for requests regularly. A run can be requested
+/// either for all TALs or for individual TALs only. A full run always takes
+/// precedence: while one is running, no further runs can be requested and
+/// a pending request for a full run absorbs all requests for individual
+/// TALs.
+#[derive(Clone, Debug, Default)]
+pub struct RefreshRequest(Arc<Mutex<RefreshState>>);
+
+/// The state of a refresh request.
+#[derive(Debug, Default)]
+struct RefreshState {
+    /// Is a full validation run currently in progress?
+    running: bool,
+
+    /// Has a full run been requested?
+    full: bool,
+
+    /// The names of the TALs requested to be refreshed.
+    tals: Vec<String>,
+}
+
+/// A refresh that has been requested.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Refresh {
+    /// A full validation run.
+    Full,
+
+    /// Validation runs for the TALs with the given names.
+    Tals(Vec<String>),
+}
+
+impl RefreshRequest {
+    /// Requests a full validation run.
+    ///
+    /// Returns `false` if a full run is currently in progress and the
+    /// request has been ignored.
+    pub fn request_full(&self) -> bool {
+        let mut state = self.lock();
+        if state.running {
+            return false
+        }
+        state.full = true;
+        state.tals.clear();
+        true
+    }
+
+    /// Requests a validation run for the TAL with the given name.
+    ///
+    /// Returns `false` if a full run is currently in progress and the
+    /// request has been ignored.
+    pub fn request_tal(&self, name: &str) -> bool {
+        let mut state = self.lock();
+        if state.running {
+            return false
+        }
+        if !state.full && !state.tals.iter().any(|tal| tal == name) {
+            state.tals.push(name.into())
+        }
+        true
+    }
+
+    /// Takes the pending request if there is one.
+    pub fn take(&self) -> Option<Refresh> {
+        let mut state = self.lock();
+        if state.full {
+            state.full = false;
+            Some(Refresh::Full)
+        }
+        else if !state.tals.is_empty() {
+            Some(Refresh::Tals(mem::take(&mut state.tals)))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Marks the start of a full validation run.
+    ///
+    /// Since the run covers everything, all pending requests are dropped.
+    fn start_full(&self) {
+        let mut state = self.lock();
+        state.running = true;
+        state.full = false;
+        state.tals.clear();
+    }
+
+    /// Marks the end of a full validation run.
+    fn finish_full(&self) {
+        self.lock().running = false;
+    }
+
+    fn lock(&self) -> impl ops::DerefMut<Target = RefreshState> + '_ {
+        self.0.lock().expect("refresh request lock poisoned")
+    }
 }
 
 
@@ -485,6 +1164,10 @@ struct VrpsArgs {
     )]
     select_asn: Option<Vec<Asn>>,
 
+    /// Only include records derived from the TAL with the given label
+    #[arg(long, value_name = "TAL")]
+    select_ta: Option<Vec<String>>,
+
     /// Include more specific prefixes in selected output
     #[arg(short, long)]
     more_specifics: bool,
@@ -493,6 +1176,14 @@ struct VrpsArgs {
     #[arg(long)]
     no_route_origins: bool,
 
+    /// Don’t include IPv4 route origins in output
+    #[arg(long)]
+    no_ipv4_route_origins: bool,
+
+    /// Don’t include IPv6 route origins in output
+    #[arg(long)]
+    no_ipv6_route_origins: bool,
+
     /// Don’t include router keys in output
     #[arg(long)]
     no_router_keys: bool,
@@ -545,7 +1236,9 @@ impl Vrps {
 
         let mut output = Output::new();
 
-        if args.select_prefix.is_some() || args.select_asn.is_some() {
+        if args.select_prefix.is_some() || args.select_asn.is_some()
+            || args.select_ta.is_some()
+        {
             let mut selection = output::Selection::new();
             if let Some(list) = args.select_prefix {
                 for value in list {
@@ -557,6 +1250,11 @@ impl Vrps {
                     selection.push_asn(value)
                 }
             }
+            if let Some(list) = args.select_ta {
+                for value in list {
+                    selection.push_tal(value)
+                }
+            }
             selection.set_more_specifics(args.more_specifics);
             output.set_selection(selection);
         };
@@ -564,6 +1262,12 @@ impl Vrps {
         if args.no_route_origins {
             output.no_route_origins();
         }
+        if args.no_ipv4_route_origins {
+            output.no_ipv4_route_origins();
+        }
+        if args.no_ipv6_route_origins {
+            output.no_ipv6_route_origins();
+        }
         if args.no_router_keys {
             output.no_router_keys();
         }
@@ -589,38 +1293,18 @@ impl Vrps {
     /// publication points.
     fn run(mut self, process: Process) -> Result<(), ExitError> {
         self.output.update_from_config(process.config());
-        let mut engine = Engine::new(process.config(), !self.noupdate)?;
-        engine.ignite()?;
+        let runner = ValidationRunner::new(process.config(), !self.noupdate)?;
+        if let Err(err) = self.output.check_tals(runner.tal_labels()) {
+            error!("{}", err);
+            return Err(Failed.into())
+        }
         process.switch_logging(false, false)?;
         warn!("Using config file {}.", process.config().config_file.display());
-        let exceptions = LocalExceptions::load(process.config(), true)?;
-        let (report, mut metrics) = {
-            // Retry once if we get a non-fatal error.
-            let mut once = false;
-
-            loop {
-                match ValidationReport::process(&engine, process.config()) {
-                    Ok(res) => break res,
-                    Err(err) => {
-                        if err.should_retry() {
-                            if once {
-                                error!(
-                                    "Restarted run failed again. Aborting."
-                                );
-                            }
-                            if engine.sanitize().is_ok() {
-                                once = true;
-                                continue
-                            }
-                        }
-                        return Err(ExitError::Generic)
-                    }
-                }
-            }
-        };
-        let vrps = Arc::new(report.into_snapshot(&exceptions, &mut metrics));
+        let (vrps, metrics) = runner.process()?;
         let rsync_complete = metrics.rsync_complete();
-        let metrics = Arc::new(metrics);
+        let run_complete = check_complete(
+            process.config(), &metrics, None
+        );
         let res = match self.path {
             Some(ref path) => {
                 let mut file = match fs::File::create(path) {
@@ -654,7 +1338,7 @@ impl Vrps {
             }
             Err(ExitError::Generic)
         }
-        else if self.complete && !rsync_complete {
+        else if (self.complete && !rsync_complete) || !run_complete {
             Err(ExitError::IncompleteUpdate)
         }
         else {
@@ -796,7 +1480,9 @@ impl Validate {
     /// Outputs whether the given route announcement is valid.
     fn run(self, process: Process) -> Result<(), ExitError> {
         let requests = self.read_requests()?;
-        let snapshot = self.get_snapshot(process)?;
+        let snapshot = Self::get_snapshot(
+            process, self.noupdate, self.complete
+        )?;
         self.output_validity(requests, snapshot)
     }
 
@@ -862,20 +1548,23 @@ impl Validate {
         }
     }
 
+    /// Performs a validation run and returns the resulting data set.
     fn get_snapshot(
-        &self, process: Process
+        process: Process, noupdate: bool, complete: bool,
     ) -> Result<PayloadSnapshot, ExitError> {
-        let mut engine = Engine::new(process.config(), !self.noupdate)?;
+        let mut engine = Engine::new(process.config(), !noupdate)?;
         engine.ignite()?;
         process.switch_logging(false, false)?;
+        let exceptions = LocalExceptions::load(process.config(), false)?;
         let (report, mut metrics) = ValidationReport::process(
             &engine, process.config(),
         )?;
-        let snapshot = report.into_snapshot(
-            &LocalExceptions::load(process.config(), false)?,
-            &mut metrics,
-        );
-        if self.complete && !metrics.rsync_complete() {
+        let snapshot = report.into_snapshot(&exceptions, &mut metrics);
+        if metrics.timed_out {
+            error!("Failed: Validation run exceeded its maximum duration.");
+            Err(ExitError::IncompleteUpdate)
+        }
+        else if complete && !metrics.rsync_complete() {
             error!("Failed: Incomplete update.");
             Err(ExitError::IncompleteUpdate)
         }
@@ -935,34 +1624,357 @@ impl Validate {
 }
 
 
-//------------ ValidateDocument ----------------------------------------------
+//------------ CheckRib ------------------------------------------------------
 
-/// Validates an RTA-signed document.
-///
-/// Performs a validation run in order to find the necessary certificates.
-#[cfg(feature = "rta")]
+/// Checks the route announcements of a routing table dump.
 #[derive(Clone, Debug, Parser)]
-pub struct ValidateDocument {
-    /// Path to the signed document.
-    #[arg(long, value_name = "PATH")]
-    document: PathBuf,
+pub struct CheckRib {
+    /// File with the announcements, '-' for stdin
+    #[arg(value_name = "PATH")]
+    input: PathBuf,
 
-    /// Path to the signature file.
-    #[arg(long, value_name = "PATH")]
-    signature: PathBuf,
+    /// Format of the input: 'auto', 'mrt', or 'text'
+    #[arg(long, value_name = "FORMAT", default_value = "auto")]
+    input_format: RibFormat,
 
-    /// Don’t update the repository.
+    /// Output format: 'csv' or 'json'
+    #[arg(short, long, value_name = "FORMAT", default_value = "csv")]
+    format: validity::ValidityFormat,
+
+    /// Only output invalid announcements
+    #[arg(long)]
+    only_invalid: bool,
+
+    /// Write output to a file
+    #[arg(short, long, value_name = "PATH", default_value = "-")]
+    output: PathBuf,
+
+    /// Don't update the local cache
     #[arg(short, long)]
     noupdate: bool,
+
+    /// Return an error status on incomplete update
+    #[arg(long)]
+    complete: bool,
 }
 
-#[cfg(feature = "rta")]
-impl ValidateDocument {
+impl CheckRib {
     /// Adds the command configuration to a clap app.
     pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
         app.subcommand(
-            ValidateDocument::augment_args(
-                clap::Command::new("rta")
+            CheckRib::augment_args(
+                clap::Command::new("check-rib")
+                    .about("Checks the announcements of a routing table")
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+    ) -> Result<Self, Failed> {
+        let mut res =
+            <CheckRib as FromArgMatches>::from_arg_matches(matches).unwrap();
+        if res.input != Path::new("-") {
+            res.input = cur_dir.join(res.input);
+        }
+        if res.output != Path::new("-") {
+            res.output = cur_dir.join(res.output);
+        }
+        Ok(res)
+    }
+
+    /// Checks all announcements and outputs the results.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        // Read the input first so we fail early if it is broken.
+        let mut requests = self.read_requests()?;
+        requests.dedup();
+        let snapshot = Validate::get_snapshot(
+            process, self.noupdate, self.complete
+        )?;
+
+        let counts = if self.output == Path::new("-") {
+            let stdout = io::stdout();
+            let mut stdout = io::BufWriter::new(stdout.lock());
+            requests.write_validity(
+                &snapshot, self.format, self.only_invalid, &mut stdout
+            ).and_then(|counts| stdout.flush().map(|_| counts))
+        }
+        else {
+            let file = match fs::File::create(&self.output) {
+                Ok(file) => file,
+                Err(err) => {
+                    error!(
+                        "Failed to open output file '{}': {}",
+                        self.output.display(), err
+                    );
+                    return Err(ExitError::Generic)
+                }
+            };
+            let mut file = io::BufWriter::new(file);
+            requests.write_validity(
+                &snapshot, self.format, self.only_invalid, &mut file
+            ).and_then(|counts| file.flush().map(|_| counts))
+        };
+        let counts = counts.map_err(|err| {
+            error!("Failed to write output: {}", err);
+            ExitError::Generic
+        })?;
+        info!(
+            "Checked {} announcements: {} valid, {} invalid, {} not found.",
+            requests.len(), counts.valid, counts.invalid, counts.not_found
+        );
+        Ok(())
+    }
+
+    /// Reads the announcements from the input file.
+    fn read_requests(&self) -> Result<validity::RequestList, ExitError> {
+        let file: Box<dyn io::Read> = if self.input == Path::new("-") {
+            Box::new(io::stdin())
+        }
+        else {
+            match fs::File::open(&self.input) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    error!(
+                        "Failed to open input file '{}': {}",
+                        self.input.display(), err
+                    );
+                    return Err(ExitError::Generic)
+                }
+            }
+        };
+
+        // Gzip files start with 0x1f 0x8b.
+        let mut file = io::BufReader::new(file);
+        let gzip = match io::BufRead::fill_buf(&mut file) {
+            Ok(data) => data.starts_with(&[0x1f, 0x8b]),
+            Err(err) => {
+                error!(
+                    "Failed to read input file '{}': {}",
+                    self.input.display(), err
+                );
+                return Err(ExitError::Generic)
+            }
+        };
+        let mut reader: Box<dyn io::BufRead> = if gzip {
+            Box::new(io::BufReader::new(flate2::read::GzDecoder::new(file)))
+        }
+        else {
+            Box::new(file)
+        };
+
+        let format = match self.input_format {
+            RibFormat::Auto => {
+                match reader.fill_buf() {
+                    Ok(data) => RibFormat::detect(data),
+                    Err(err) => {
+                        error!(
+                            "Failed to read input file '{}': {}",
+                            self.input.display(), err
+                        );
+                        return Err(ExitError::Generic)
+                    }
+                }
+            }
+            format => format
+        };
+
+        let res = match format {
+            RibFormat::Mrt => {
+                validity::RequestList::from_mrt_reader(
+                    &mut reader
+                ).map(|(list, skipped)| {
+                    if skipped > 0 {
+                        warn!(
+                            "Skipped {} routes without a definite origin AS.",
+                            skipped
+                        );
+                    }
+                    list
+                })
+            }
+            _ => validity::RequestList::from_announcements_reader(reader)
+        };
+        res.map_err(|err| {
+            error!(
+                "Failed to read input file '{}': {}",
+                self.input.display(), err
+            );
+            ExitError::Generic
+        })
+    }
+}
+
+
+//------------ RibFormat -----------------------------------------------------
+
+/// The format of a routing table given to the check-rib command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RibFormat {
+    /// Determine the format from the content.
+    Auto,
+
+    /// A RIB dump in MRT `TABLE_DUMP_V2` format.
+    Mrt,
+
+    /// A text file with a prefix and an AS number per line.
+    Text,
+}
+
+impl RibFormat {
+    /// Determines the format from the start of the data.
+    ///
+    /// MRT data starts with a four octet time stamp followed by the two
+    /// octet type which is 13 for `TABLE_DUMP_V2`. A text file won’t ever
+    /// have a zero octet in there.
+    fn detect(data: &[u8]) -> Self {
+        if data.len() >= 6 && data[4..6] == [0, 13] {
+            RibFormat::Mrt
+        }
+        else {
+            RibFormat::Text
+        }
+    }
+}
+
+impl FromStr for RibFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(RibFormat::Auto),
+            "mrt" => Ok(RibFormat::Mrt),
+            "text" | "csv" => Ok(RibFormat::Text),
+            _ => Err(format!("unknown input format '{}'", s))
+        }
+    }
+}
+
+
+//------------ CheckObject ---------------------------------------------------
+
+/// Checks a single RPKI object.
+#[derive(Clone, Debug, Parser)]
+pub struct CheckObject {
+    /// File with the DER encoded object, '-' for stdin
+    #[arg(value_name = "PATH")]
+    input: PathBuf,
+
+    /// Produce output in JSON
+    #[arg(short, long)]
+    json: bool,
+}
+
+impl CheckObject {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        app.subcommand(
+            CheckObject::augment_args(
+                clap::Command::new("check-object")
+                    .about("Checks a single RPKI object")
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+    ) -> Result<Self, Failed> {
+        let mut res = <CheckObject as FromArgMatches>::from_arg_matches(
+            matches
+        ).unwrap();
+        if res.input != Path::new("-") {
+            res.input = cur_dir.join(res.input);
+        }
+        Ok(res)
+    }
+
+    /// Checks the object and prints the report.
+    ///
+    /// Fails with an invalid status if the object isn’t acceptable in the
+    /// configured validation mode.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        use crate::store::Store;
+
+        process.switch_logging(false, false)?;
+        let data = if self.input == Path::new("-") {
+            let mut data = Vec::new();
+            io::Read::read_to_end(&mut io::stdin(), &mut data).map(|_| data)
+        }
+        else {
+            fs::read(&self.input)
+        };
+        let data = data.map_err(|err| {
+            error!(
+                "Failed to read input file '{}': {}",
+                self.input.display(), err
+            );
+            ExitError::Generic
+        })?;
+        let name = if self.input == Path::new("-") {
+            None
+        }
+        else {
+            self.input.file_name().and_then(|name| name.to_str())
+        };
+
+        let mut report = ObjectReport::new(data.into(), name);
+        report.check_signature(&Store::existing(process.config()))?;
+
+        let res = if self.json {
+            println!("{}", report.to_json());
+            Ok(())
+        }
+        else {
+            report.write_text(&mut io::stdout().lock())
+        };
+        res.map_err(|err| {
+            error!("Failed to write output: {}", err);
+            ExitError::Generic
+        })?;
+        if report.is_acceptable(process.config().strict) {
+            Ok(())
+        }
+        else {
+            Err(ExitError::Invalid)
+        }
+    }
+}
+
+
+//------------ ValidateDocument ----------------------------------------------
+
+/// Validates an RTA-signed document.
+///
+/// Performs a validation run in order to find the necessary certificates.
+#[cfg(feature = "rta")]
+#[derive(Clone, Debug, Parser)]
+pub struct ValidateDocument {
+    /// Path to the signed document.
+    #[arg(long, value_name = "PATH")]
+    document: PathBuf,
+
+    /// Path to the signature file.
+    #[arg(long, value_name = "PATH")]
+    signature: PathBuf,
+
+    /// Don’t update the repository.
+    #[arg(short, long)]
+    noupdate: bool,
+}
+
+#[cfg(feature = "rta")]
+impl ValidateDocument {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        app.subcommand(
+            ValidateDocument::augment_args(
+                clap::Command::new("rta")
                 .about("Validates an RTA-signed document")
                 .after_help(AFTER_HELP)
             )
@@ -1066,112 +2078,865 @@ impl ValidateDocument {
 }
 
 
-//------------ Update --------------------------------------------------------
+//------------ Update --------------------------------------------------------
+
+
+/// Update the local repository.
+///
+/// This will also do a validation run in order to discover possible new
+/// publication points.
+#[derive(Clone, Debug, Parser)]
+pub struct Update {
+    /// Return an error on incomplete update.
+    #[arg(short, long)]
+    complete: bool,
+}
+
+impl Update {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        app.subcommand(
+            Update::augment_args(
+                clap::Command::new("update")
+                    .about("Updates the local RPKI repository")
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(matches: &ArgMatches) -> Result<Self, Failed> {
+        Ok(<Update as FromArgMatches>::from_arg_matches(matches).unwrap())
+    }
+
+    /// Updates the repository.
+    ///
+    /// This runs both an update of the already known publication points but
+    /// also does validation in order to discover new points.
+    ///
+    /// Which turns out is just a shortcut for `vrps` with no output.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        let mut engine = Engine::new(process.config(), true)?;
+        engine.ignite()?;
+        process.switch_logging(false, false)?;
+        let (_, metrics) = ValidationReport::process(
+            &engine, process.config(),
+        )?;
+        if metrics.timed_out
+            || (self.complete && !metrics.rsync_complete())
+        {
+            Err(ExitError::IncompleteUpdate)
+        }
+        else {
+           Ok(())
+        }
+    }
+}
+
+
+//------------ Config --------------------------------------------------------
+
+
+/// Shows the current configuration.
+pub struct PrintConfig;
+
+impl PrintConfig {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        // config
+        app.subcommand(Config::server_args(clap::Command::new("config")
+            .about("Prints the current config and exits")
+            .after_help(AFTER_HELP)
+        ))
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+        config: &mut Config,
+    ) -> Result<Self, Failed> {
+        config.apply_server_arg_matches(matches, cur_dir)?;
+        Ok(PrintConfig)
+    }
+
+    /// Prints the current configuration to stdout and exits.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        println!("{}", process.config());
+        Ok(())
+    }
+}
+
+
+//------------ Dump ----------------------------------------------------------
+
+/// Dumps the database content.
+#[derive(Clone, Debug, Parser)]
+pub struct Dump {
+    /// Output directory or file, '-' for stdout with tar formats
+    #[arg(short, long, value_name = "PATH")]
+    output: PathBuf,
+
+    /// The format of the dump: 'dir', 'tar', or 'tar.gz'
+    #[arg(long, value_name = "FORMAT", default_value = "dir")]
+    format: DumpFormat,
+
+    /// Include the store in a tar dump
+    #[arg(long)]
+    include_store: bool,
+}
+
+impl Dump {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        // config
+        app.subcommand(
+            Dump::augment_args(
+                clap::Command::new("dump")
+                    .about("Writes the cache content to disk")
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+    ) -> Result<Self, Failed> {
+        let mut res =
+            <Dump as FromArgMatches>::from_arg_matches(matches).unwrap();
+        if res.output == Path::new("-") {
+            if res.format == DumpFormat::Dir {
+                error!("Cannot dump a directory to stdout.");
+                return Err(Failed)
+            }
+        }
+        else {
+            res.output = cur_dir.join(res.output);
+        }
+        Ok(res)
+    }
+
+    /// Writes the dump and exits.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        let engine = Engine::new(process.config(), true)?;
+        process.switch_logging(false, false)?;
+        let (mut target, store) = match self.format {
+            DumpFormat::Dir => (DumpTarget::dir(self.output), true),
+            DumpFormat::Tar | DumpFormat::TarGz => {
+                let stdout = self.output == Path::new("-");
+                let writer: Box<dyn io::Write> = if stdout {
+                    Box::new(io::BufWriter::new(io::stdout()))
+                }
+                else {
+                    match fs::File::create(&self.output) {
+                        Ok(file) => Box::new(io::BufWriter::new(file)),
+                        Err(err) => {
+                            error!(
+                                "Failed to create dump file {}: {}",
+                                self.output.display(), err
+                            );
+                            return Err(ExitError::Generic)
+                        }
+                    }
+                };
+                (
+                    DumpTarget::tar(writer, self.format == DumpFormat::TarGz),
+                    self.include_store
+                )
+            }
+        };
+        engine.dump(&mut target, store)?;
+        target.finish()?;
+        Ok(())
+    }
+}
+
+
+//------------ DumpFormat ----------------------------------------------------
+
+/// The format of a dump.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DumpFormat {
+    /// A directory tree.
+    Dir,
+
+    /// An uncompressed tar archive.
+    Tar,
+
+    /// A gzip-compressed tar archive.
+    TarGz,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dir" => Ok(DumpFormat::Dir),
+            "tar" => Ok(DumpFormat::Tar),
+            "tar.gz" | "tgz" => Ok(DumpFormat::TarGz),
+            _ => Err(format!("unknown dump format '{}'", s))
+        }
+    }
+}
+
+
+//------------ Restore -------------------------------------------------------
+
+/// Restores the cache content from a dump.
+#[derive(Clone, Debug, Parser)]
+pub struct Restore {
+    /// Dump directory or tar archive to restore from
+    #[arg(value_name = "PATH")]
+    source: PathBuf,
+
+    /// Also restore the trust anchor certificates of the store
+    #[arg(long)]
+    include_store: bool,
+}
+
+impl Restore {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        app.subcommand(
+            Restore::augment_args(
+                clap::Command::new("restore")
+                    .about("Seeds the cache from a previously created dump")
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+    ) -> Result<Self, Failed> {
+        let mut res =
+            <Restore as FromArgMatches>::from_arg_matches(matches).unwrap();
+        res.source = cur_dir.join(res.source);
+        Ok(res)
+    }
+
+    /// Restores the dump and exits.
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        use crate::collector::{Collector, RrdpCollector};
+        use crate::store::Store;
+        use crate::utils::lock::LockFile;
+
+        let config = process.config();
+        process.switch_logging(false, false)?;
+
+        // A running Routinator holds a shared lock on the RRDP cache, so
+        // if we can get an exclusive lock, nobody is using the cache.
+        let lock_path = RrdpCollector::lock_path(config);
+        fatal::create_parent_all(&lock_path)?;
+        let _lock = match LockFile::exclusive(&lock_path)? {
+            Some(lock) => lock,
+            None => {
+                error!(
+                    "Fatal: the cache in {} is currently in use. \
+                     Please stop any running Routinator first.",
+                    config.cache_dir.display()
+                );
+                return Err(ExitError::Generic)
+            }
+        };
+
+        let unpacked = if self.source.is_dir() {
+            None
+        }
+        else {
+            Some(Self::unpack(&self.source, &config.cache_dir)?)
+        };
+        let source = match unpacked.as_ref() {
+            Some(dir) => dir.path(),
+            None => self.source.as_path(),
+        };
+        if !source.join("rrdp").is_dir() && !source.join("rsync").is_dir() {
+            error!(
+                "Fatal: no collector content found in {}. \
+                 Is this a Routinator dump?",
+                self.source.display()
+            );
+            return Err(ExitError::Generic)
+        }
+
+        Collector::restore(config, source)?;
+        if self.include_store {
+            Store::restore_ta(config, source)?;
+        }
+        info!("Restored cache from {}.", self.source.display());
+        Ok(())
+    }
+
+    /// Unpacks a tar archive into a temporary directory.
+    ///
+    /// The archive may be compressed with gzip. The temporary directory is
+    /// created in the cache directory, so restored files can be moved
+    /// into place.
+    fn unpack(
+        path: &Path, cache_dir: &Path,
+    ) -> Result<tempfile::TempDir, ExitError> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                error!(
+                    "Failed to open dump archive {}: {}",
+                    path.display(), err
+                );
+                return Err(ExitError::Generic)
+            }
+        };
+
+        // Gzip files start with 0x1f 0x8b.
+        let mut magic = [0u8; 2];
+        let gzip = file.read_exact(&mut magic).is_ok()
+            && magic == [0x1f, 0x8b];
+        if let Err(err) = file.seek(SeekFrom::Start(0)) {
+            error!(
+                "Failed to read dump archive {}: {}",
+                path.display(), err
+            );
+            return Err(ExitError::Generic)
+        }
+        let reader: Box<dyn io::Read> = if gzip {
+            Box::new(flate2::read::GzDecoder::new(io::BufReader::new(file)))
+        }
+        else {
+            Box::new(io::BufReader::new(file))
+        };
+
+        fatal::create_dir_all(cache_dir)?;
+        let dir = match tempfile::TempDir::new_in(cache_dir) {
+            Ok(dir) => dir,
+            Err(err) => {
+                error!(
+                    "Failed to create temporary directory in {}: {}",
+                    cache_dir.display(), err
+                );
+                return Err(ExitError::Generic)
+            }
+        };
+        if let Err(err) = tar::Archive::new(reader).unpack(dir.path()) {
+            error!(
+                "Failed to unpack dump archive {}: {}",
+                path.display(), err
+            );
+            return Err(ExitError::Generic)
+        }
+        Ok(dir)
+    }
+}
+
+
+//------------ ArchiveStats --------------------------------------------------
+
+/// Prints archive statistics.
+///
+/// This command is deprecated in favour of `archive stats` and only kept
+/// as a hidden alias.
+#[derive(Clone, Debug, Parser)]
+pub struct ArchiveStats {
+    /// Archive file.
+    #[arg(value_name = "PATH")]
+    archive: PathBuf,
+}
+
+impl ArchiveStats {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        // config
+        app.subcommand(
+            ArchiveStats::augment_args(
+                clap::Command::new("archive-stats")
+                    .about("Prints statics for an RRDP archive")
+                    .hide(true)
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(matches: &ArgMatches) -> Result<Self, Failed> {
+        Ok(
+            <ArchiveStats as FromArgMatches>::from_arg_matches(
+                matches
+            ).unwrap()
+        )
+    }
+
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        process.switch_logging(false, false)?;
+        warn!(
+            "The archive-stats command is deprecated. \
+             Please use 'archive stats' instead."
+        );
+        Archive::file_stats(&self.archive)
+    }
+}
+
+
+//------------ Archive -------------------------------------------------------
+
+/// Maintains the RRDP archives in the cache.
+#[derive(Clone, Debug, Parser)]
+pub struct Archive {
+    /// The archive operation to perform.
+    #[command(subcommand)]
+    action: ArchiveAction,
+}
+
+/// The operations available for the archive command.
+#[derive(Clone, Debug, Subcommand)]
+enum ArchiveAction {
+    /// Prints statistics for all RRDP archives
+    Stats {
+        /// Only print statistics for this archive file
+        #[arg(value_name = "PATH")]
+        archive: Option<PathBuf>,
+    },
+
+    /// Rewrites fragmented RRDP archives
+    Compact {
+        /// Minimum share of empty space for an archive to be compacted
+        #[arg(
+            long, value_name = "RATIO", default_value = "0.25",
+            value_parser = parse_ratio,
+        )]
+        min_empty_ratio: f64,
+    },
+}
+
+impl Archive {
+    /// Adds the command configuration to a clap app.
+    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
+        app.subcommand(
+            Archive::augment_args(
+                clap::Command::new("archive")
+                    .about("Maintains the RRDP archives in the cache")
+                    .subcommand_required(true)
+                    .after_help(AFTER_HELP)
+            )
+        )
+    }
+
+    /// Creates a command from clap matches.
+    pub fn from_arg_matches(matches: &ArgMatches) -> Result<Self, Failed> {
+        Ok(
+            <Archive as FromArgMatches>::from_arg_matches(
+                matches
+            ).unwrap()
+        )
+    }
+
+    fn run(self, process: Process) -> Result<(), ExitError> {
+        process.switch_logging(false, false)?;
+        match self.action {
+            ArchiveAction::Stats { archive: Some(archive) } => {
+                Self::file_stats(&archive)
+            }
+            ArchiveAction::Stats { archive: None } => {
+                Self::stats(process.config())
+            }
+            ArchiveAction::Compact { min_empty_ratio } => {
+                Self::compact(process.config(), min_empty_ratio)
+            }
+        }
+    }
+
+    /// Prints the statistics of all archives and their sum.
+    fn stats(config: &Config) -> Result<(), ExitError> {
+        use crate::collector::{RrdpArchive, RrdpCollector};
+        use crate::utils::archive::ArchiveStats;
+
+        let mut total = ArchiveStats::default();
+        let mut broken = 0;
+        for path in RrdpCollector::archive_paths(config)? {
+            match RrdpArchive::inspect(&path) {
+                Ok((notify, stats)) => {
+                    match notify {
+                        Some(notify) => {
+                            println!(
+                                "RRDP archive {} for {}:",
+                                path.display(), notify
+                            );
+                        }
+                        None => {
+                            println!("RRDP archive {}:", path.display());
+                        }
+                    }
+                    stats.print();
+                    total += stats;
+                }
+                Err(err) => {
+                    eprintln!(
+                        "RRDP archive {} is corrupt: {}",
+                        path.display(), err
+                    );
+                    broken += 1;
+                }
+            }
+        }
+        println!("All RRDP archives:");
+        total.print();
+        if broken > 0 {
+            println!("  corrupt archives: {broken}");
+        }
+        Ok(())
+    }
+
+    /// Prints the statistics of a single archive file.
+    fn file_stats(path: &Path) -> Result<(), ExitError> {
+        use crate::collector::RrdpArchive;
+
+        match RrdpArchive::verify(path) {
+            Ok(stats) => {
+                println!("RRDP archive {}:", path.display());
+                stats.print();
+                Ok(())
+            }
+            Err(err) => {
+                eprintln!("Archive is corrupt: {err}");
+                Err(ExitError::Generic)
+            }
+        }
+    }
+
+    /// Compacts all archives with too much empty space.
+    fn compact(config: &Config, min_ratio: f64) -> Result<(), ExitError> {
+        use crate::collector::{RrdpArchive, RrdpCollector};
+        use crate::utils::lock::LockFile;
+
+        let lock_path = RrdpCollector::lock_path(config);
+        fatal::create_parent_all(&lock_path)?;
+        let _lock = match LockFile::exclusive(&lock_path)? {
+            Some(lock) => lock,
+            None => {
+                error!(
+                    "Fatal: the RRDP cache in {} is currently in use. \
+                     Please stop any running Routinator first.",
+                    config.cache_dir.display()
+                );
+                return Err(ExitError::Generic)
+            }
+        };
+
+        let tmp_dir = config.cache_dir.join("rrdp").join("tmp");
+        fatal::create_dir_all(&tmp_dir)?;
+        for path in RrdpCollector::archive_paths(config)? {
+            let old = match RrdpArchive::verify(&path) {
+                Ok(stats) => stats,
+                Err(err) => {
+                    warn!(
+                        "Skipping corrupt RRDP archive {}: {}",
+                        path.display(), err
+                    );
+                    continue;
+                }
+            };
+            if old.empty_ratio() < min_ratio {
+                continue;
+            }
+            match RrdpArchive::compact(&path, &tmp_dir) {
+                Ok(new) => {
+                    info!(
+                        "Compacted RRDP archive {}: {} bytes freed.",
+                        path.display(),
+                        old.data_size().saturating_sub(new.data_size())
+                    );
+                }
+                Err(err) if err.should_retry() => {
+                    warn!(
+                        "Skipping RRDP archive {} that couldn’t be \
+                         compacted.",
+                        path.display()
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a ratio between zero and one.
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    match f64::from_str(value) {
+        Ok(ratio) if (0. ..=1.).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("invalid ratio '{value}', expected 0 to 1")),
+    }
+}
+
 
+//------------ Slurm ---------------------------------------------------------
 
-/// Update the local repository.
-///
-/// This will also do a validation run in order to discover possible new
-/// publication points.
+/// Works with local exceptions files.
 #[derive(Clone, Debug, Parser)]
-pub struct Update {
-    /// Return an error on incomplete update.
-    #[arg(short, long)]
-    complete: bool,
+pub struct Slurm {
+    /// The operation to perform.
+    #[command(subcommand)]
+    action: SlurmAction,
 }
 
-impl Update {
+/// The operations available for the slurm command.
+#[derive(Clone, Debug, Subcommand)]
+enum SlurmAction {
+    /// Checks local exceptions files for problems
+    Check {
+        /// Local exceptions files to check
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Shows how candidate local exceptions files change the data set
+    Diff {
+        /// The format of the output lists
+        #[arg(
+            short, long, value_name = "FORMAT",
+            default_value = OutputFormat::DEFAULT_VALUE,
+        )]
+        format: String,
+
+        /// Write the payload that would be added to this file
+        #[arg(long, value_name = "PATH")]
+        added: Option<PathBuf>,
+
+        /// Write the payload that would be removed to this file
+        #[arg(long, value_name = "PATH")]
+        removed: Option<PathBuf>,
+
+        /// Candidate local exceptions files
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+impl Slurm {
     /// Adds the command configuration to a clap app.
     pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
         app.subcommand(
-            Update::augment_args(
-                clap::Command::new("update")
-                    .about("Updates the local RPKI repository")
+            Slurm::augment_args(
+                clap::Command::new("slurm")
+                    .about("Works with local exceptions files")
+                    .subcommand_required(true)
                     .after_help(AFTER_HELP)
             )
         )
     }
 
     /// Creates a command from clap matches.
-    pub fn from_arg_matches(matches: &ArgMatches) -> Result<Self, Failed> {
-        Ok(<Update as FromArgMatches>::from_arg_matches(matches).unwrap())
+    pub fn from_arg_matches(
+        matches: &ArgMatches,
+        cur_dir: &Path,
+    ) -> Result<Self, Failed> {
+        let mut res = <Slurm as FromArgMatches>::from_arg_matches(
+            matches
+        ).unwrap();
+        let files = match res.action {
+            SlurmAction::Check { ref mut files } => files,
+            SlurmAction::Diff {
+                ref format, ref mut added, ref mut removed, ref mut files
+            } => {
+                if OutputFormat::from_str(format).is_err() {
+                    error!("Unknown output format '{}'", format);
+                    return Err(Failed)
+                }
+                for path in added.iter_mut().chain(removed.iter_mut()) {
+                    if path != Path::new("-") {
+                        *path = cur_dir.join(path.as_path())
+                    }
+                }
+                files
+            }
+        };
+        for path in files {
+            *path = cur_dir.join(path.as_path())
+        }
+        Ok(res)
     }
 
-    /// Updates the repository.
-    ///
-    /// This runs both an update of the already known publication points but
-    /// also does validation in order to discover new points.
-    ///
-    /// Which turns out is just a shortcut for `vrps` with no output.
     fn run(self, process: Process) -> Result<(), ExitError> {
-        let mut engine = Engine::new(process.config(), true)?;
-        engine.ignite()?;
-        process.switch_logging(false, false)?;
-        let (_, metrics) = ValidationReport::process(
-            &engine, process.config(),
-        )?;
-        if self.complete && !metrics.rsync_complete() {
-            Err(ExitError::IncompleteUpdate)
-        }
-        else {
-           Ok(())
+        match self.action {
+            SlurmAction::Check { files } => {
+                process.switch_logging(false, false)?;
+                Self::check(&files)
+            }
+            SlurmAction::Diff { format, added, removed, files } => {
+                Self::diff(
+                    process,
+                    // We checked the format in from_arg_matches.
+                    OutputFormat::from_str(&format).map_err(|_| Failed)?,
+                    added.as_deref(), removed.as_deref(), files
+                )
+            }
         }
     }
-}
 
+    /// Checks the given local exceptions files.
+    ///
+    /// Prints all problems to stdout and fails if any of them is an error.
+    fn check(files: &[PathBuf]) -> Result<(), ExitError> {
+        use crate::slurm::Checker;
 
-//------------ Config --------------------------------------------------------
-
+        let mut checker = Checker::default();
+        for path in files {
+            checker.check_file(path);
+        }
+        let problems = checker.finalize();
+        for problem in &problems {
+            println!("{problem}");
+        }
+        let errors = problems.iter().filter(|item| item.is_error()).count();
+        let warnings = problems.len() - errors;
+        println!(
+            "Checked {} file(s): {} error(s), {} warning(s).",
+            files.len(), errors, warnings
+        );
+        if errors > 0 {
+            Err(ExitError::Generic)
+        }
+        else {
+            Ok(())
+        }
+    }
 
-/// Shows the current configuration.
-pub struct PrintConfig;
+    /// Compares the data set with the configured and candidate exceptions.
+    ///
+    /// Uses the data currently in the local cache without updating it.
+    /// The payload added and removed by switching from the configured
+    /// exceptions to those in `files` is written to `added` and `removed`,
+    /// respectively.
+    fn diff(
+        process: Process,
+        format: OutputFormat,
+        added: Option<&Path>,
+        removed: Option<&Path>,
+        files: Vec<PathBuf>,
+    ) -> Result<(), ExitError> {
+        let runner = ValidationRunner::new(process.config(), false)?;
+        process.switch_logging(false, false)?;
+        let current = LocalExceptions::load(process.config(), true)?;
+        let candidate = {
+            let mut config = process.config().clone();
+            config.exceptions = files;
+            LocalExceptions::load(&config, true)?
+        };
 
-impl PrintConfig {
-    /// Adds the command configuration to a clap app.
-    pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
-        // config
-        app.subcommand(Config::server_args(clap::Command::new("config")
-            .about("Prints the current config and exits")
-            .after_help(AFTER_HELP)
-        ))
-    }
+        let (report, metrics) = runner.process_report()?;
+        let payload = report.into_payload();
+        let mut old_metrics = metrics.without_payload();
+        let old = payload.to_snapshot(&current, &mut old_metrics);
+        let mut new_metrics = metrics;
+        let new = payload.to_snapshot(&candidate, &mut new_metrics);
 
-    /// Creates a command from clap matches.
-    pub fn from_arg_matches(
-        matches: &ArgMatches,
-        cur_dir: &Path,
-        config: &mut Config,
-    ) -> Result<Self, Failed> {
-        config.apply_server_arg_matches(matches, cur_dir)?;
-        Ok(PrintConfig)
+        let delta = match PayloadDelta::construct(&old, &new, Serial(0)) {
+            Some(delta) => delta,
+            None => PayloadDelta::empty(Serial(0)),
+        };
+        let to_stdout = [added, removed].into_iter().flatten().any(|path| {
+            path == Path::new("-")
+        });
+        if !to_stdout {
+            println!(
+                "{} item(s) added, {} item(s) removed.",
+                delta.announce_len(), delta.withdraw_len()
+            );
+        }
+        if let Some(path) = added {
+            Self::write_diff(
+                process.config(), format, path,
+                new.delta_items(&delta, Action::Announce), new_metrics
+            )?;
+        }
+        if let Some(path) = removed {
+            Self::write_diff(
+                process.config(), format, path,
+                old.delta_items(&delta, Action::Withdraw), old_metrics
+            )?;
+        }
+        Ok(())
     }
 
-    /// Prints the current configuration to stdout and exits.
-    fn run(self, process: Process) -> Result<(), ExitError> {
-        println!("{}", process.config());
-        Ok(())
+    /// Writes one side of a diff to the given path.
+    fn write_diff(
+        config: &Config,
+        format: OutputFormat,
+        path: &Path,
+        snapshot: PayloadSnapshot,
+        metrics: Metrics,
+    ) -> Result<(), ExitError> {
+        let output = Output::from_config(config);
+        let snapshot = Arc::new(snapshot);
+        let metrics = Arc::new(metrics);
+        let res = if path == Path::new("-") {
+            output.write(snapshot, metrics, format, &mut io::stdout().lock())
+        }
+        else {
+            let mut file = match fs::File::create(path) {
+                Ok(file) => file,
+                Err(err) => {
+                    error!(
+                        "Failed to open output file '{}': {}",
+                        path.display(), err
+                    );
+                    return Err(ExitError::Generic)
+                }
+            };
+            output.write(snapshot, metrics, format, &mut file)
+        };
+        res.map_err(|err| {
+            error!("Failed to output result: {}", err);
+            ExitError::Generic
+        })
     }
 }
 
 
-//------------ Dump ----------------------------------------------------------
+//------------ Compare -------------------------------------------------------
 
-/// Dumps the database content.
+/// Compare the payload of two sources.
 #[derive(Clone, Debug, Parser)]
-pub struct Dump {
-    /// Output directory.
-    #[arg(short, long, value_name = "PATH")]
-    output: PathBuf,
+pub struct Compare {
+    /// The format for listing the differences
+    #[arg(
+        short, long, value_name = "FORMAT",
+        default_value = OutputFormat::DEFAULT_VALUE,
+    )]
+    format: String,
+
+    /// Also compare router keys
+    #[arg(long)]
+    router_keys: bool,
+
+    /// Also compare ASPAs
+    #[arg(long)]
+    aspas: bool,
+
+    /// Fail if there are more than this many differences
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    max_differences: usize,
+
+    /// The first source, a file or HTTP URL
+    #[arg(value_name = "SOURCE")]
+    source_a: String,
+
+    /// The second source, a file or HTTP URL
+    #[arg(value_name = "SOURCE")]
+    source_b: String,
 }
 
-impl Dump {
+impl Compare {
     /// Adds the command configuration to a clap app.
     pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
-        // config
         app.subcommand(
-            Dump::augment_args(
-                clap::Command::new("dump")
-                    .about("Writes the cache content to disk")
+            Compare::augment_args(
+                clap::Command::new("compare")
+                    .about("Compares the payload of two sources")
                     .after_help(AFTER_HELP)
             )
         )
@@ -1182,40 +2947,159 @@ impl Dump {
         matches: &ArgMatches,
         cur_dir: &Path,
     ) -> Result<Self, Failed> {
-        let mut res =
-            <Dump as FromArgMatches>::from_arg_matches(matches).unwrap();
-        res.output = cur_dir.join(res.output);
+        let mut res = <Compare as FromArgMatches>::from_arg_matches(
+            matches
+        ).unwrap();
+        if OutputFormat::from_str(&res.format).is_err() {
+            error!("Unknown output format '{}'", res.format);
+            return Err(Failed)
+        }
+        for source in [&mut res.source_a, &mut res.source_b] {
+            if !Self::is_url(source) {
+                *source = cur_dir.join(source.as_str()).display().to_string();
+            }
+        }
         Ok(res)
     }
 
-    /// Prints the current configuration to stdout and exits.
+    /// Compares the two sources.
+    ///
+    /// Prints the number of items only present in each source followed by
+    /// a list of these items in the requested format.
     fn run(self, process: Process) -> Result<(), ExitError> {
-        let engine = Engine::new(process.config(), true)?;
         process.switch_logging(false, false)?;
-        engine.dump(&self.output)?;
+        // We checked the format in from_arg_matches.
+        let format = OutputFormat::from_str(&self.format).map_err(|_| {
+            Failed
+        })?;
+        let a = self.load(&self.source_a, process.config())?;
+        let b = self.load(&self.source_b, process.config())?;
+
+        let mut output = Output::new();
+        if !self.router_keys {
+            output.no_router_keys();
+        }
+        if !self.aspas {
+            output.no_aspas();
+        }
+        let metrics = Arc::new(Metrics::new());
+        let mut differences = 0;
+        for (source, snapshot) in [
+            (&self.source_a, a.difference(&b)),
+            (&self.source_b, b.difference(&a)),
+        ] {
+            let mut count = snapshot.origin_count();
+            print!("Only in {}: {} origin(s)", source, count);
+            if self.router_keys {
+                let keys = snapshot.router_keys().count();
+                print!(", {} router key(s)", keys);
+                count += keys;
+            }
+            if self.aspas {
+                let aspas = snapshot.aspas().count();
+                print!(", {} ASPA(s)", aspas);
+                count += aspas;
+            }
+            println!(".");
+            if count > 0 {
+                output.clone().write(
+                    Arc::new(snapshot), metrics.clone(), format,
+                    &mut io::stdout().lock()
+                ).map_err(|err| {
+                    error!("Failed to output result: {}", err);
+                    ExitError::Generic
+                })?;
+            }
+            differences += count;
+        }
+        if differences > self.max_differences {
+            error!(
+                "Found {} difference(s), more than the allowed {}.",
+                differences, self.max_differences
+            );
+            return Err(ExitError::Generic)
+        }
         Ok(())
     }
+
+    /// Loads the payload from a source.
+    fn load(
+        &self, source: &str, config: &Config
+    ) -> Result<PayloadSnapshot, Failed> {
+        let data = if Self::is_url(source) {
+            Self::fetch(source, config)?
+        }
+        else {
+            fs::read(source).map_err(|err| {
+                error!("Failed to read {}: {}", source, err);
+                Failed
+            })?
+        };
+        import_payload(&data).map_err(|err| {
+            error!("Failed to parse {}: {}", source, err);
+            Failed
+        })
+    }
+
+    /// Fetches the data of a source from an HTTP server.
+    fn fetch(source: &str, config: &Config) -> Result<Vec<u8>, Failed> {
+        let mut client = reqwest::blocking::Client::builder()
+            .user_agent(&config.rrdp_user_agent);
+        if let Some(timeout) = config.rrdp_timeout {
+            client = client.timeout(timeout);
+        }
+        let response = client.build().and_then(|client| {
+            client.get(source).send()
+        }).and_then(|response| {
+            response.error_for_status()
+        }).and_then(|response| {
+            response.bytes()
+        });
+        match response {
+            Ok(data) => Ok(data.into()),
+            Err(err) => {
+                error!("Failed to fetch {}: {}", source, err);
+                Err(Failed)
+            }
+        }
+    }
+
+    /// Returns whether a source is an HTTP URL.
+    fn is_url(source: &str) -> bool {
+        source.starts_with("http://") || source.starts_with("https://")
+    }
 }
 
 
-//------------ ArchiveStats --------------------------------------------------
+//------------ ClearCache ----------------------------------------------------
 
-/// Prints archive statistics.
+/// Remove selected repositories from the cache.
 #[derive(Clone, Debug, Parser)]
-pub struct ArchiveStats {
-    /// Archive file.
-    #[arg(value_name = "PATH")]
-    archive: PathBuf,
+pub struct ClearCache {
+    /// Remove the RRDP repository with this rpkiNotify URI
+    #[arg(long, value_name = "URI")]
+    rrdp_uri: Vec<rpki::uri::Https>,
+
+    /// Remove the rsync module of this rsync URI
+    #[arg(long, value_name = "URI")]
+    rsync_module: Vec<rpki::uri::Rsync>,
+
+    /// Remove the stored trust anchor certificates of this TAL
+    #[arg(long, value_name = "NAME")]
+    tal: Vec<String>,
+
+    /// Only print what would be removed
+    #[arg(long)]
+    dry_run: bool,
 }
 
-impl ArchiveStats {
+impl ClearCache {
     /// Adds the command configuration to a clap app.
     pub fn config_args<'a: 'b, 'b>(app: clap::Command) -> clap::Command {
-        // config
         app.subcommand(
-            ArchiveStats::augment_args(
-                clap::Command::new("archive-stats")
-                    .about("Prints statics for an RRDP archive")
+            ClearCache::augment_args(
+                clap::Command::new("clear-cache")
+                    .about("Removes selected repositories from the cache")
                     .after_help(AFTER_HELP)
             )
         )
@@ -1223,28 +3107,89 @@ impl ArchiveStats {
 
     /// Creates a command from clap matches.
     pub fn from_arg_matches(matches: &ArgMatches) -> Result<Self, Failed> {
-        Ok(
-            <ArchiveStats as FromArgMatches>::from_arg_matches(
-                matches
-            ).unwrap()
-        )
+        let res = <ClearCache as FromArgMatches>::from_arg_matches(
+            matches
+        ).unwrap();
+        if res.rrdp_uri.is_empty()
+            && res.rsync_module.is_empty()
+            && res.tal.is_empty()
+        {
+            error!(
+                "At least one of --rrdp-uri, --rsync-module, or --tal \
+                 is required."
+            );
+            return Err(Failed)
+        }
+        Ok(res)
     }
 
+    /// Removes the selected parts of the cache.
     fn run(self, process: Process) -> Result<(), ExitError> {
-        use crate::collector::RrdpArchive;
+        use crate::collector::{RrdpCollector, RsyncCollector};
+        use crate::store::Store;
+        use crate::utils::lock::LockFile;
 
+        let config = process.config();
         process.switch_logging(false, false)?;
-        match RrdpArchive::verify(&self.archive) {
-            Ok(stats) => {
-                println!("RRDP archive {}:", self.archive.display());
-                stats.print();
-                Ok(())
+
+        // A running Routinator holds a shared lock on the RRDP cache, so
+        // if we can get an exclusive lock, nobody is using the cache.
+        let lock_path = RrdpCollector::lock_path(config);
+        fatal::create_parent_all(&lock_path)?;
+        let _lock = match LockFile::exclusive(&lock_path)? {
+            Some(lock) => lock,
+            None => {
+                error!(
+                    "Fatal: the cache in {} is currently in use. \
+                     Please stop any running Routinator first.",
+                    config.cache_dir.display()
+                );
+                return Err(ExitError::Generic)
             }
-            Err(err) => {
-                eprintln!("Archive is corrupt: {err}");
-                Err(ExitError::Generic)
+        };
+
+        let store = Store::existing(config);
+        let mut paths = Vec::new();
+        for uri in &self.rrdp_uri {
+            paths.push(RrdpCollector::repository_archive_path(config, uri));
+            paths.push(store.rrdp_repository_path(uri));
+        }
+        for uri in &self.rsync_module {
+            paths.extend(RsyncCollector::module_paths(config, uri));
+            paths.push(store.rsync_module_path(uri));
+        }
+        if !self.tal.is_empty() {
+            let engine = Engine::new(config, false)?;
+            for label in &self.tal {
+                let tal = match engine.find_tal(label) {
+                    Some(tal) => tal,
+                    None => {
+                        error!("Unknown TAL '{}'.", label);
+                        return Err(ExitError::Generic)
+                    }
+                };
+                paths.extend(tal.uris().map(|uri| store.ta_path(uri)));
+            }
+        }
+
+        let mut removed = 0;
+        for path in paths {
+            if !path.exists() {
+                continue
             }
+            if self.dry_run {
+                println!("Would remove {}", path.display());
+            }
+            else {
+                fatal::remove_all(&path)?;
+                println!("Removed {}", path.display());
+            }
+            removed += 1;
+        }
+        if removed == 0 {
+            println!("Nothing to remove.");
         }
+        Ok(())
     }
 }
 
@@ -1371,12 +3316,62 @@ impl Man {
 }
 
 
+//------------ check_complete ------------------------------------------------
+
+/// Checks whether a validation run is complete.
+///
+/// A run is incomplete if it was aborted because it exceeded its maximum
+/// duration or if the configuration requires all TALs to produce valid
+/// ROAs and at least one of them didn’t. In this case, logs an error and
+/// returns `false`.
+///
+/// If the run only processed a single TAL, its index needs to be given in
+/// `only_tal` so that only this TAL is checked for valid ROAs.
+fn check_complete(
+    config: &Config, metrics: &Metrics, only_tal: Option<usize>,
+) -> bool {
+    if metrics.timed_out {
+        error!(
+            "Incomplete validation run: maximum run duration exceeded."
+        );
+        return false
+    }
+    if let Some(skew) = metrics.clock_skew {
+        if config.clock_skew_policy == FilterPolicy::Reject {
+            error!(
+                "Incomplete validation run: local clock is off by {} \
+                 seconds. Keeping previous data set.",
+                skew
+            );
+            return false
+        }
+    }
+    if !config.require_all_tals {
+        return true
+    }
+    let only_tal = only_tal.and_then(|index| metrics.tals.get(index));
+    let mut empty = metrics.tals_without_roas().filter(|name| {
+        only_tal.map(|tal| tal.name() == *name).unwrap_or(true)
+    }).peekable();
+    if empty.peek().is_none() {
+        return true
+    }
+    error!(
+        "Incomplete validation run: no valid ROAs for TAL(s) {}.",
+        empty.collect::<Vec<_>>().join(", ")
+    );
+    false
+}
+
+
 //------------ SignalListener --------------------------------------------------
 
 #[allow(dead_code)]
 enum UserSignal {
     ReloadTals,
     RotateLog,
+    ReloadTls,
+    Shutdown,
 }
 
 /// Wait for the next validation run or a user telling us to quit or reload.
@@ -1386,6 +3381,9 @@ enum UserSignal {
 struct SignalListener {
     usr1: Signal,
     usr2: Signal,
+    hup: Signal,
+    term: Signal,
+    int: Signal,
 }
 
 #[cfg(unix)]
@@ -1406,6 +3404,27 @@ impl SignalListener {
                     return Err(Failed)
                 }
             },
+            hup: match signal(SignalKind::hangup()) {
+                Ok(hup) => hup,
+                Err(err) => {
+                    error!("Attaching to signal HUP failed: {}", err);
+                    return Err(Failed)
+                }
+            },
+            term: match signal(SignalKind::terminate()) {
+                Ok(term) => term,
+                Err(err) => {
+                    error!("Attaching to signal TERM failed: {}", err);
+                    return Err(Failed)
+                }
+            },
+            int: match signal(SignalKind::interrupt()) {
+                Ok(int) => int,
+                Err(err) => {
+                    error!("Attaching to signal INT failed: {}", err);
+                    return Err(Failed)
+                }
+            },
         })
     }
 
@@ -1416,6 +3435,9 @@ impl SignalListener {
         tokio::select! {
             _ = self.usr1.recv() => UserSignal::ReloadTals,
             _ = self.usr2.recv() => UserSignal::RotateLog,
+            _ = self.hup.recv() => UserSignal::ReloadTls,
+            _ = self.term.recv() => UserSignal::Shutdown,
+            _ = self.int.recv() => UserSignal::Shutdown,
         }
     }
 }
@@ -1443,6 +3465,9 @@ impl SignalListener {
 /// The raw bytes of the manual page.
 const MAN_PAGE: &[u8] = include_bytes!("../doc/routinator.1");
 
+/// How often the server checks whether the exceptions files have changed.
+const EXCEPTIONS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 /// The after help message pointing to the main help.
 const AFTER_HELP: &str = 
     "Additional global options are available. \
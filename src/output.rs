@@ -1,14 +1,17 @@
 //! Output of validated RPKI payload.
 
 use std::{error, fmt, io};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use bytes::Bytes;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use chrono::format::{Item, Numeric, Pad};
 use log::{error, info};
 use rpki::resources::{Asn, Prefix};
 use rpki::resources::addr::ParsePrefixError;
+use rpki::rtr::Serial;
 use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
 use rpki::util::base64;
 use crate::config::Config;
@@ -26,7 +29,7 @@ use crate::utils::json::json_str;
 //------------ OutputFormat --------------------------------------------------
 
 /// The output format for VRPs.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OutputFormat {
     /// CSV format.
     ///
@@ -53,6 +56,14 @@ pub enum OutputFormat {
     /// JSON format with extended information.
     ExtendedJson,
 
+    /// Versioned JSON format with a frozen schema.
+    ///
+    /// This is a JSON object with the members `"metadata"`, `"origins"`,
+    /// `"routerKeys"`, `"aspas"`, and `"counts"`. Members of this format
+    /// are never changed or removed. Any such change requires a new
+    /// format version.
+    JsonV2,
+
     /// JSON format using the SLURM scheme.
     Slurm,
 
@@ -81,6 +92,18 @@ pub enum OutputFormat {
     /// Produces a textual summary of the ROAs and VRPS.
     Summary,
 
+    /// CSV format for router keys only.
+    ///
+    /// Each row has the AS number, subject key identifier, and the
+    /// subject public key info.
+    RouterKeysCsv,
+
+    /// JSON format for router keys only.
+    ///
+    /// This is a JSON object with the elements `"metadata"` and
+    /// `"routerKeys"`, the latter being an array of objects.
+    RouterKeysJson,
+
     /// No output.
     ///
     /// Seriously: no output.
@@ -95,12 +118,15 @@ impl OutputFormat {
         ("csvext", OutputFormat::ExtendedCsv),
         ("json", OutputFormat::Json),
         ("jsonext", OutputFormat::ExtendedJson),
+        ("json-v2", OutputFormat::JsonV2),
         ("slurm", OutputFormat::Slurm),
         ("openbgpd", OutputFormat::Openbgpd),
         ("bird1", OutputFormat::Bird1),
         ("bird2", OutputFormat::Bird2),
         ("rpsl", OutputFormat::Rpsl),
         ("summary", OutputFormat::Summary),
+        ("router-keys-csv", OutputFormat::RouterKeysCsv),
+        ("router-keys-json", OutputFormat::RouterKeysJson),
         ("none", OutputFormat::None),
     ];
 
@@ -118,7 +144,7 @@ impl OutputFormat {
     }
 
     /// Returns the output format for the given string if it is valid.
-    fn try_from_str(value: &str) -> Option<Self> {
+    pub fn try_from_str(value: &str) -> Option<Self> {
         for &(name, res) in Self::VALUES {
             if name == value {
                 return Some(res)
@@ -127,35 +153,63 @@ impl OutputFormat {
         None
     }
 
+    /// Returns the name of the output format.
+    pub fn name(self) -> &'static str {
+        for &(name, value) in Self::VALUES {
+            if value == self {
+                return name
+            }
+        }
+        unreachable!()
+    }
+
     /// Returns the media type string for this output format.
     pub fn content_type(self) -> ContentType {
         match self {
             OutputFormat::Csv | OutputFormat::CompatCsv |
-            OutputFormat::ExtendedCsv
+            OutputFormat::ExtendedCsv | OutputFormat::RouterKeysCsv
                 => ContentType::CSV,
             OutputFormat::Json | OutputFormat::ExtendedJson |
-            OutputFormat::Slurm
+            OutputFormat::JsonV2 | OutputFormat::Slurm |
+            OutputFormat::RouterKeysJson
                 => ContentType::JSON,
             _ => ContentType::TEXT,
         }
     }
 
-    fn formatter<W: io::Write>(self) -> Box<dyn Formatter<W> + Send + Sync> {
+    fn formatter<W: io::Write>(
+        self, output: &Output
+    ) -> Box<dyn Formatter<W> + Send + Sync> {
         match self {
-            OutputFormat::Csv => Box::new(Csv),
+            OutputFormat::Csv => {
+                Box::new(Csv { include_meta: output.include_meta })
+            }
             OutputFormat::CompatCsv => Box::new(CompatCsv),
-            OutputFormat::ExtendedCsv => Box::new(ExtendedCsv),
+            OutputFormat::ExtendedCsv => {
+                Box::new(ExtendedCsv { include_meta: output.include_meta })
+            }
             OutputFormat::Json => Box::new(Json),
             OutputFormat::ExtendedJson => Box::new(ExtendedJson),
+            OutputFormat::JsonV2 => Box::new(JsonV2::new(output.clone())),
             OutputFormat::Slurm => Box::new(Slurm),
             OutputFormat::Openbgpd => Box::new(Openbgpd),
             OutputFormat::Bird1 => Box::new(Bird1),
             OutputFormat::Bird2 => Box::new(Bird2),
             OutputFormat::Rpsl => Box::new(Rpsl),
             OutputFormat::Summary => Box::new(Summary),
+            OutputFormat::RouterKeysCsv => Box::new(RouterKeysCsv),
+            OutputFormat::RouterKeysJson => Box::new(RouterKeysJson),
             OutputFormat::None => Box::new(NoOutput),
         }
     }
+
+    /// Returns whether the format only includes router keys.
+    fn router_keys_only(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::RouterKeysCsv | OutputFormat::RouterKeysJson
+        )
+    }
 }
 
 
@@ -183,6 +237,11 @@ pub struct Selection {
 
     /// Should we include more specific prefixes in the output?
     more_specifics: bool,
+
+    /// The labels of the TALs to select.
+    ///
+    /// If this is empty, payload from all TALs is selected.
+    tals: Vec<String>,
 }
 
 impl Selection {
@@ -206,13 +265,60 @@ impl Selection {
         self.resources.push(SelectResource::Prefix(prefix))
     }
 
+    /// Add the label of a TAL to select.
+    pub fn push_tal(&mut self, tal: String) {
+        self.tals.push(tal)
+    }
+
     /// Returns whether there are any resources.
     pub fn has_resources(&self) -> bool {
         !self.resources.is_empty()
     }
 
+    /// Returns whether there are any TALs.
+    pub fn has_tals(&self) -> bool {
+        !self.tals.is_empty()
+    }
+
+    /// Checks that all selected TALs are among the given labels.
+    ///
+    /// Returns an error message listing the valid labels if not.
+    pub fn check_tals<'a>(
+        &self, labels: impl IntoIterator<Item = &'a str>
+    ) -> Result<(), String> {
+        let labels: Vec<_> = labels.into_iter().collect();
+        for tal in &self.tals {
+            if !labels.contains(&tal.as_str()) {
+                return Err(format!(
+                    "Unknown TAL '{}'. Valid labels are: {}",
+                    tal, labels.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether payload with the given info should be included.
+    ///
+    /// This is the case if no TALs are selected or if any of the
+    /// published objects the payload was derived from belongs to one of
+    /// the selected TALs.
+    pub fn include_info(&self, info: &PayloadInfo) -> bool {
+        if self.tals.is_empty() {
+            return true
+        }
+        info.iter().any(|item| {
+            item.tal_name().map(|name| {
+                self.tals.iter().any(|tal| tal == name)
+            }).unwrap_or(false)
+        })
+    }
+
     /// Returns whether an origin should be included in output.
     pub fn include_origin(&self, origin: RouteOrigin) -> bool {
+        if self.resources.is_empty() {
+            return true
+        }
         for select in &self.resources {
             if select.include_origin(origin, self.more_specifics) {
                 return true
@@ -223,6 +329,9 @@ impl Selection {
 
     /// Returns whether a router key should be included in output.
     pub fn include_router_key(&self, key: &RouterKey) -> bool {
+        if self.resources.is_empty() {
+            return true
+        }
         for select in &self.resources {
             if select.include_router_key(key) {
                 return true
@@ -233,6 +342,9 @@ impl Selection {
 
     /// Returns whether an ASPA should be included in output.
     pub fn include_aspa(&self, aspa: &Aspa) -> bool {
+        if self.resources.is_empty() {
+            return true
+        }
         for select in &self.resources {
             if select.include_aspa(aspa) {
                 return true
@@ -303,11 +415,23 @@ pub struct Output {
     /// Should we include route origins?
     route_origins: bool,
 
+    /// Should we include IPv4 route origins?
+    ipv4_route_origins: bool,
+
+    /// Should we include IPv6 route origins?
+    ipv6_route_origins: bool,
+
     /// Should we include router keys?
     router_keys: bool,
 
     /// Should we include ASPA data?
     aspas: bool,
+
+    /// The RTR session and serial of the data set if available.
+    rtr_state: Option<(u64, Serial)>,
+
+    /// Should formats without metadata include it as comments?
+    include_meta: bool,
 }
 
 impl Output {
@@ -316,8 +440,12 @@ impl Output {
         Self {
             selection: None,
             route_origins: true,
+            ipv4_route_origins: true,
+            ipv6_route_origins: true,
             router_keys: true,
-            aspas: true
+            aspas: true,
+            rtr_state: None,
+            include_meta: false,
         }
     }
 
@@ -366,6 +494,9 @@ impl Output {
                     )
                 );
             }
+            else if key == "select-ta" {
+                selection.tals.push(value.into_owned());
+            }
             else if key == "include" {
                 for value in value.split(',') {
                     #[allow(clippy::single_match)]
@@ -375,10 +506,22 @@ impl Output {
                     }
                 }
             }
+            else if key == "include-meta" {
+                if value != "comment" {
+                    return Err(QueryError)
+                }
+                self.include_meta = true;
+            }
             else if key == "exclude" {
                 for value in value.split(',') {
                     match value {
                         "routeOrigins" => self.route_origins = false,
+                        "ipv4RouteOrigins" => {
+                            self.ipv4_route_origins = false
+                        }
+                        "ipv6RouteOrigins" => {
+                            self.ipv6_route_origins = false
+                        }
                         "routerKeys" => self.router_keys = false,
                         "aspas" => self.aspas = false,
                         _ => { }
@@ -390,7 +533,7 @@ impl Output {
             }
         }
 
-        if selection.has_resources() {
+        if selection.has_resources() || selection.has_tals() {
             self.set_selection(selection)
         }
 
@@ -401,10 +544,28 @@ impl Output {
         self.selection = Some(selection)
     }
 
+    /// Checks that all selected TALs are among the given labels.
+    pub fn check_tals<'a>(
+        &self, labels: impl IntoIterator<Item = &'a str>
+    ) -> Result<(), String> {
+        match self.selection.as_ref() {
+            Some(selection) => selection.check_tals(labels),
+            None => Ok(())
+        }
+    }
+
     pub fn no_route_origins(&mut self) {
         self.route_origins = false
     }
 
+    pub fn no_ipv4_route_origins(&mut self) {
+        self.ipv4_route_origins = false
+    }
+
+    pub fn no_ipv6_route_origins(&mut self) {
+        self.ipv6_route_origins = false
+    }
+
     pub fn no_router_keys(&mut self) {
         self.router_keys = false
     }
@@ -413,6 +574,13 @@ impl Output {
         self.aspas = false
     }
 
+    /// Sets the RTR session and serial of the data set.
+    ///
+    /// These are included in the metadata of some formats.
+    pub fn set_rtr_state(&mut self, session: u64, serial: Serial) {
+        self.rtr_state = Some((session, serial))
+    }
+
     /// Outputs the payload snapshot to the target in the given format.
     pub fn write<W: io::Write>(
         self,
@@ -437,12 +605,27 @@ impl Output {
     }
 
     fn include_origin(&self, origin: RouteOrigin) -> bool {
+        if origin.prefix.addr().is_ipv4() {
+            if !self.ipv4_route_origins {
+                return false
+            }
+        }
+        else if !self.ipv6_route_origins {
+            return false
+        }
         match self.selection.as_ref() {
             Some(selection) => selection.include_origin(origin),
             None => true
         }
     }
 
+    fn include_info(&self, info: &PayloadInfo) -> bool {
+        match self.selection.as_ref() {
+            Some(selection) => selection.include_info(info),
+            None => true
+        }
+    }
+
     fn include_router_key(&self, key: &RouterKey) -> bool {
         match self.selection.as_ref() {
             Some(selection) => selection.include_router_key(key),
@@ -465,14 +648,74 @@ impl Default for Output {
 }
 
 
+//------------ OutputFile ----------------------------------------------------
+
+/// A file the payload is written to after each validation run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutputFile {
+    /// The path of the file.
+    pub path: PathBuf,
+
+    /// The format to write the file in.
+    pub format: OutputFormat,
+}
+
+impl OutputFile {
+    /// Writes the payload snapshot to the file.
+    ///
+    /// The data is first written to a temporary file in the same directory
+    /// which is then moved into place, so the file is replaced atomically.
+    pub fn write(
+        &self,
+        output: Output,
+        snapshot: Arc<PayloadSnapshot>,
+        metrics: Arc<Metrics>,
+    ) -> Result<(), io::Error> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        {
+            let mut writer = io::BufWriter::new(file.as_file_mut());
+            output.write(snapshot, metrics, self.format, &mut writer)?;
+            io::Write::flush(&mut writer)?;
+        }
+
+        // Temporary files are only readable by the owner. Since the file
+        // is likely to be picked up by someone else, make it as readable
+        // as a newly created file.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            file.as_file().set_permissions(
+                std::fs::Permissions::from_mode(0o644)
+            )?;
+        }
+
+        file.persist(&self.path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+
 //------------ OutputStream --------------------------------------------------
 
 struct OutputStream<Target> {
     output: Output,
     snapshot: Arc<PayloadSnapshot>,
     metrics: Arc<Metrics>,
+    meta: Metadata,
     state: StreamState,
     formatter: Box<dyn Formatter<Target> + Send + Sync>,
+
+    /// The route origins already written for the current prefix.
+    ///
+    /// This is only used if the formatter wants duplicates removed. Since
+    /// origins are ordered by prefix first, we only need to remember the
+    /// resolved max length and ASN of the origins with the same prefix.
+    written_origins: Option<(Prefix, Vec<(u8, Asn)>)>,
 }
 
 enum StreamState {
@@ -486,16 +729,54 @@ enum StreamState {
 impl<Target: io::Write> OutputStream<Target> {
     /// Creates a new output stream.
     fn new(
-        output: Output,
+        mut output: Output,
         snapshot: Arc<PayloadSnapshot>,
         metrics: Arc<Metrics>,
         format: OutputFormat,
     ) -> Self {
+        if format.router_keys_only() {
+            output.no_route_origins();
+            output.no_aspas();
+        }
         OutputStream {
+            formatter: format.formatter(&output),
+            meta: Metadata::new(&snapshot, &metrics, output.rtr_state),
             output, snapshot, metrics,
             state: StreamState::Header,
-            formatter: format.formatter(),
+            written_origins: None,
+        }
+    }
+
+    /// Returns whether an equivalent origin has already been written.
+    ///
+    /// Two origins are equivalent if they have the same prefix, resolved
+    /// max length, and ASN. This only returns `true` if the formatter has
+    /// asked for duplicates to be removed.
+    ///
+    /// This is an associated function rather than a method so it can be
+    /// used while the stream state is borrowed.
+    fn is_duplicate_origin(
+        written: &mut Option<(Prefix, Vec<(u8, Asn)>)>,
+        formatter: &(dyn Formatter<Target> + Send + Sync),
+        origin: RouteOrigin,
+    ) -> bool {
+        if !formatter.dedup_origins() {
+            return false
         }
+        let prefix = origin.prefix.prefix();
+        let item = (origin.prefix.resolved_max_len(), origin.asn);
+        match written {
+            Some((written_prefix, items)) if *written_prefix == prefix => {
+                if items.contains(&item) {
+                    return true
+                }
+                items.push(item);
+            }
+            _ => {
+                *written = Some((prefix, vec![item]));
+            }
+        }
+        false
     }
 
     /// Writes the next item to the target.
@@ -509,7 +790,7 @@ impl<Target: io::Write> OutputStream<Target> {
         let next = match self.state {
             StreamState::Header => {
                 self.formatter.header(
-                    &self.snapshot, &self.metrics, target
+                    &self.meta, &self.metrics, target
                 )?;
                 self.progress_header(target)?
             }
@@ -522,7 +803,14 @@ impl<Target: io::Write> OutputStream<Target> {
                             break
                         }
                     };
-                    if !self.output.include_origin(origin) {
+                    if !self.output.include_origin(origin)
+                        || !self.output.include_info(info)
+                    {
+                        continue
+                    }
+                    if Self::is_duplicate_origin(
+                        &mut self.written_origins, &*self.formatter, origin
+                    ) {
                         continue
                     }
                     if *first {
@@ -545,7 +833,9 @@ impl<Target: io::Write> OutputStream<Target> {
                             break
                         }
                     };
-                    if !self.output.include_router_key(key) {
+                    if !self.output.include_router_key(key)
+                        || !self.output.include_info(info)
+                    {
                         continue
                     }
                     if *first {
@@ -568,7 +858,9 @@ impl<Target: io::Write> OutputStream<Target> {
                             break
                         }
                     };
-                    if !self.output.include_aspa(aspa) {
+                    if !self.output.include_aspa(aspa)
+                        || !self.output.include_info(info)
+                    {
                         continue
                     }
                     if *first {
@@ -683,13 +975,123 @@ impl fmt::Display for QueryError {
 impl error::Error for QueryError { }
 
 
+//------------ Metadata ------------------------------------------------------
+
+/// Information about the data set that formats can include.
+#[derive(Clone, Debug)]
+struct Metadata {
+    /// The time the payload snapshot was created.
+    created: DateTime<Utc>,
+
+    /// The serial number of the data set if known.
+    serial: Option<Serial>,
+
+    /// The name of each TAL and the time of its last successful update.
+    tals: Vec<(String, Option<DateTime<Utc>>)>,
+}
+
+impl Metadata {
+    /// Collects the metadata for a snapshot and the metrics of its run.
+    fn new(
+        snapshot: &PayloadSnapshot,
+        metrics: &Metrics,
+        rtr_state: Option<(u64, Serial)>,
+    ) -> Self {
+        Metadata {
+            created: snapshot.created(),
+            serial: rtr_state.map(|(_, serial)| serial),
+            tals: metrics.tals.iter().map(|tal| {
+                (tal.name().into(), tal.updated)
+            }).collect(),
+        }
+    }
+
+    /// Writes the metadata as comment lines starting with a hash.
+    fn write_comment(
+        &self, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        writeln!(target, "# created: {}", format_iso_date(self.created))?;
+        match self.serial {
+            Some(serial) => writeln!(target, "# serial: {}", serial)?,
+            None => writeln!(target, "# serial: N/A")?,
+        }
+        for (name, updated) in &self.tals {
+            match updated {
+                Some(updated) => {
+                    writeln!(target,
+                        "# ta-updated: {}={}", name, format_iso_date(*updated)
+                    )?
+                }
+                None => writeln!(target, "# ta-updated: {}=N/A", name)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata as members of a JSON object.
+    ///
+    /// The members are indented for the `"metadata"` object of the JSON
+    /// formats and are preceded by a comma.
+    fn write_json(
+        &self, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        write!(target,
+            ",\n    \"created\": {},\n    \"createdTime\": \"{}\",\
+             \n    \"serial\": ",
+            self.created.timestamp(), format_iso_date(self.created)
+        )?;
+        match self.serial {
+            Some(serial) => write!(target, "{}", serial)?,
+            None => write!(target, "null")?,
+        }
+        write!(target, ",\n    \"tas\": {{")?;
+        let mut first = true;
+        for (name, updated) in &self.tals {
+            if first {
+                first = false;
+            }
+            else {
+                write!(target, ",")?;
+            }
+            write!(target,
+                "\n      \"{}\": {{ \"updated\": ", json_str(name)
+            )?;
+            match updated {
+                Some(updated) => {
+                    write!(target,
+                        "{}, \"updatedTime\": \"{}\" }}",
+                        updated.timestamp(), format_iso_date(*updated)
+                    )?
+                }
+                None => {
+                    write!(target, "null, \"updatedTime\": null }}")?
+                }
+            }
+        }
+        if !first {
+            write!(target, "\n    ")?;
+        }
+        write!(target, "}}")
+    }
+}
+
+
 //------------ Formatter -----------------------------------------------------
 
 trait Formatter<W> {
+    /// Returns whether duplicate route origins should be dropped.
+    ///
+    /// Formats that don’t include the trust anchor or the max length
+    /// exactly as given may end up with identical lines for distinct
+    /// origins. These can return `true` here to have these skipped.
+    fn dedup_origins(&self) -> bool {
+        false
+    }
+
     fn header(
-        &self, snapshot: &PayloadSnapshot, metrics: &Metrics, target: &mut W
+        &self, meta: &Metadata, metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
-        let _ = (snapshot, metrics, target);
+        let _ = (meta, metrics, target);
         Ok(())
     }
 
@@ -771,12 +1173,18 @@ trait Formatter<W> {
 
 //------------ Csv -----------------------------------------------------------
 
-struct Csv;
+struct Csv {
+    /// Should the metadata be included as comments?
+    include_meta: bool,
+}
 
 impl<W: io::Write> Formatter<W> for Csv {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, _metrics: &Metrics, target: &mut W
+        &self, meta: &Metadata, _metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
+        if self.include_meta {
+            meta.write_comment(target)?;
+        }
         writeln!(target, "ASN,IP Prefix,Max Length,Trust Anchor")
     }
 
@@ -799,7 +1207,7 @@ struct CompatCsv;
 
 impl<W: io::Write> Formatter<W> for CompatCsv {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, _metrics: &Metrics, target: &mut W
+        &self, _meta: &Metadata, _metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
         writeln!(
             target, "\"ASN\",\"IP Prefix\",\"Max Length\",\"Trust Anchor\""
@@ -821,7 +1229,10 @@ impl<W: io::Write> Formatter<W> for CompatCsv {
 
 //------------ ExtendedCsv ---------------------------------------------------
 
-struct ExtendedCsv;
+struct ExtendedCsv {
+    /// Should the metadata be included as comments?
+    include_meta: bool,
+}
 
 impl ExtendedCsv {
     // 2017-08-25 13:12:19
@@ -842,8 +1253,11 @@ impl ExtendedCsv {
 
 impl<W: io::Write> Formatter<W> for ExtendedCsv {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, _metrics: &Metrics, target: &mut W
+        &self, meta: &Metadata, _metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
+        if self.include_meta {
+            meta.write_comment(target)?;
+        }
         writeln!(target, "URI,ASN,IP Prefix,Max Length,Not Before,Not After")
     }
 
@@ -879,17 +1293,18 @@ struct Json;
 
 impl<W: io::Write> Formatter<W> for Json {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, metrics: &Metrics, target: &mut W
+        &self, meta: &Metadata, metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
-        writeln!(target,
+        write!(target,
             "{{\
             \n  \"metadata\": {{\
             \n    \"generated\": {},\
-            \n    \"generatedTime\": \"{}\"\
-            \n  }}",
+            \n    \"generatedTime\": \"{}\"",
             metrics.time.timestamp(),
             format_iso_date(metrics.time)
-        )
+        )?;
+        meta.write_json(target)?;
+        writeln!(target, "\n  }}")
     }
 
     fn before_origins(
@@ -1056,21 +1471,38 @@ impl ExtendedJson {
         }
         Ok(())
     }
+
+    fn string_list(
+        list: &[String], target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        let mut first = true;
+        for item in list {
+            if first {
+                write!(target, "\"{}\"", json_str(item))?;
+                first = false;
+            }
+            else {
+                write!(target, ", \"{}\"", json_str(item))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<W: io::Write> Formatter<W> for ExtendedJson {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, metrics: &Metrics, target: &mut W
+        &self, meta: &Metadata, metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
         write!(target,
             "{{\
             \n  \"metadata\": {{\
             \n    \"generated\": {},\
-            \n    \"generatedTime\": \"{}\"\
-            \n  }}",
+            \n    \"generatedTime\": \"{}\"",
             metrics.time.timestamp(),
             format_iso_date(metrics.time)
-        )
+        )?;
+        meta.write_json(target)?;
+        write!(target, "\n  }}")
     }
 
     fn before_origins(
@@ -1166,20 +1598,294 @@ impl<W: io::Write> Formatter<W> for ExtendedJson {
     }
 
     fn footer(
-        &self, _metrics: &Metrics, target: &mut W
+        &self, metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
+        if let Some(extras) = metrics.cert_extras.as_ref() {
+            write!(target, ",\n  \"certInfo\": [")?;
+            let mut first = true;
+            for item in extras {
+                if first {
+                    first = false;
+                }
+                else {
+                    write!(target, ",")?;
+                }
+                write!(target,
+                    "\n    {{ \"uri\": \"{}\", \"tal\": \"{}\", \
+                    \"cps\": [",
+                    item.uri, json_str(item.tal.name()),
+                )?;
+                Self::string_list(&item.cps, target)?;
+                write!(target, "], \"extensions\": [")?;
+                Self::string_list(&item.extensions, target)?;
+                write!(target, "] }}")?;
+            }
+            write!(target, "\n  ]")?;
+        }
         writeln!(target, "\n}}")
     }
 }
 
 
+//------------ JsonV2 --------------------------------------------------------
+
+/// The versioned JSON format.
+///
+/// The schema of this format is frozen. Members must not be renamed,
+/// removed, or changed in type. If that becomes necessary, a new format
+/// with a new format version has to be added instead.
+struct JsonV2 {
+    /// The output settings used for the metadata.
+    output: Output,
+
+    /// The number of route origins written.
+    origins: AtomicUsize,
+
+    /// The number of router keys written.
+    router_keys: AtomicUsize,
+
+    /// The number of ASPAs written.
+    aspas: AtomicUsize,
+}
+
+impl JsonV2 {
+    /// The version of the format.
+    const FORMAT_VERSION: u32 = 2;
+
+    fn new(output: Output) -> Self {
+        JsonV2 {
+            output,
+            origins: AtomicUsize::new(0),
+            router_keys: AtomicUsize::new(0),
+            aspas: AtomicUsize::new(0),
+        }
+    }
+
+    /// Writes the selection parameters.
+    fn selection(
+        &self, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        let selection = self.output.selection.as_ref();
+        let resources = selection.map(|sel| sel.resources.as_slice());
+        let resources = resources.unwrap_or_default();
+
+        write!(target, "\n    \"selection\": {{\n      \"asns\": [")?;
+        let asns = resources.iter().filter_map(|item| match *item {
+            SelectResource::Asn(asn) => Some(asn.into_u32().to_string()),
+            SelectResource::Prefix(_) => None,
+        });
+        Self::list(asns, target)?;
+
+        write!(target, "],\n      \"prefixes\": [")?;
+        let prefixes = resources.iter().filter_map(|item| match *item {
+            SelectResource::Prefix(prefix) => {
+                Some(format!(
+                    "\"{}/{}\"", prefix.addr(), prefix.len()
+                ))
+            }
+            SelectResource::Asn(_) => None,
+        });
+        Self::list(prefixes, target)?;
+
+        write!(target, "],\n      \"tas\": [")?;
+        let tals = selection.map(|sel| sel.tals.as_slice());
+        Self::list(
+            tals.unwrap_or_default().iter().map(|tal| {
+                format!("\"{}\"", json_str(tal))
+            }),
+            target
+        )?;
+
+        write!(
+            target,
+            "],\n      \"moreSpecifics\": {},\n      \"exclude\": [",
+            selection.map(|sel| sel.more_specifics).unwrap_or(false)
+        )?;
+        let exclude = [
+            ("routeOrigins", self.output.route_origins),
+            ("ipv4RouteOrigins", self.output.ipv4_route_origins),
+            ("ipv6RouteOrigins", self.output.ipv6_route_origins),
+            ("routerKeys", self.output.router_keys),
+            ("aspas", self.output.aspas),
+        ];
+        Self::list(
+            exclude.iter().filter(|(_, include)| !include).map(|(name, _)| {
+                format!("\"{}\"", name)
+            }),
+            target
+        )?;
+        write!(target, "]\n    }}")
+    }
+
+    /// Writes a comma separated list of already formatted items.
+    fn list(
+        items: impl Iterator<Item = String>, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        let mut first = true;
+        for item in items {
+            if first {
+                first = false;
+            }
+            else {
+                write!(target, ", ")?;
+            }
+            write!(target, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: io::Write> Formatter<W> for JsonV2 {
+    fn header(
+        &self, _meta: &Metadata, metrics: &Metrics, target: &mut W
+    ) -> Result<(), io::Error> {
+        write!(target,
+            "{{\
+            \n  \"metadata\": {{\
+            \n    \"formatVersion\": {},\
+            \n    \"generated\": {},\
+            \n    \"generatedTime\": \"{}\",",
+            Self::FORMAT_VERSION,
+            metrics.time.timestamp(),
+            format_iso_date(metrics.time)
+        )?;
+        match self.output.rtr_state {
+            Some((session, serial)) => {
+                write!(target,
+                    "\n    \"session\": {},\n    \"serial\": {},",
+                    session, serial
+                )?;
+            }
+            None => {
+                write!(target,
+                    "\n    \"session\": null,\n    \"serial\": null,"
+                )?;
+            }
+        }
+        self.selection(target)?;
+        write!(target, "\n  }}")
+    }
+
+    fn before_origins(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",\n  \"origins\": [")
+    }
+
+    fn origin(
+        &self, origin: RouteOrigin, info: &PayloadInfo, target: &mut W
+    ) -> Result<(), io::Error> {
+        self.origins.fetch_add(1, Ordering::Relaxed);
+        write!(target,
+            "    {{ \"asn\": {}, \"prefix\": \"{}/{}\", \
+            \"maxLength\": {}, \"ta\": \"{}\" }}",
+            origin.asn.into_u32(),
+            origin.prefix.addr(), origin.prefix.prefix_len(),
+            origin.prefix.resolved_max_len(),
+            json_str(info.tal_name().unwrap_or("N/A")),
+        )
+    }
+
+    fn origin_delimiter(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",")
+    }
+
+    fn after_origins(&self, target: &mut W) -> Result<(), io::Error> {
+        write!(target, "\n  ]")
+    }
+
+    fn before_router_keys(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",\n  \"routerKeys\": [")
+    }
+
+    fn router_key(
+        &self, key: &RouterKey, info: &PayloadInfo, target: &mut W
+    ) -> Result<(), io::Error> {
+        self.router_keys.fetch_add(1, Ordering::Relaxed);
+        write!(target,
+            "    {{ \"asn\": {}, \"ski\": \"{}\", \"publicKey\": \"",
+            key.asn.into_u32(),
+            key.key_identifier,
+        )?;
+        base64::Slurm.write_encoded_slice(key.key_info.as_slice(), target)?;
+        write!(target,
+            "\", \"ta\": \"{}\" }}",
+            json_str(info.tal_name().unwrap_or("N/A")),
+        )
+    }
+
+    fn router_key_delimiter(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",")
+    }
+
+    fn after_router_keys(&self, target: &mut W) -> Result<(), io::Error> {
+        write!(target, "\n  ]")
+    }
+
+    fn before_aspas(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",\n  \"aspas\": [")
+    }
+
+    fn aspa(
+        &self, aspa: &Aspa, info: &PayloadInfo, target: &mut W
+    ) -> Result<(), io::Error> {
+        self.aspas.fetch_add(1, Ordering::Relaxed);
+        write!(target,
+            "    {{ \"customer\": {}, \"providers\": [",
+            aspa.customer.into_u32()
+        )?;
+        Self::list(
+            aspa.providers.iter().map(|asn| asn.into_u32().to_string()),
+            target
+        )?;
+        write!(target,
+            "], \"ta\": \"{}\" }}",
+            json_str(info.tal_name().unwrap_or("N/A"))
+        )
+    }
+
+    fn aspa_delimiter(&self, target: &mut W) -> Result<(), io::Error> {
+        writeln!(target, ",")
+    }
+
+    fn after_aspas(&self, target: &mut W) -> Result<(), io::Error> {
+        write!(target, "\n  ]")
+    }
+
+    fn footer(
+        &self, _metrics: &Metrics, target: &mut W
+    ) -> Result<(), io::Error> {
+        // Excluded arrays are still present, just empty.
+        if !self.output.route_origins {
+            write!(target, ",\n  \"origins\": [ ]")?;
+        }
+        if !self.output.router_keys {
+            write!(target, ",\n  \"routerKeys\": [ ]")?;
+        }
+        if !self.output.aspas {
+            write!(target, ",\n  \"aspas\": [ ]")?;
+        }
+        writeln!(target,
+            ",\
+            \n  \"counts\": {{\
+            \n    \"origins\": {},\
+            \n    \"routerKeys\": {},\
+            \n    \"aspas\": {}\
+            \n  }}\
+            \n}}",
+            self.origins.load(Ordering::Relaxed),
+            self.router_keys.load(Ordering::Relaxed),
+            self.aspas.load(Ordering::Relaxed),
+        )
+    }
+}
+
+
 //------------ Slurm ---------------------------------------------------------
 
 struct Slurm;
 
 impl<W: io::Write> Formatter<W> for Slurm {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, _metrics: &Metrics, target: &mut W
+        &self, _meta: &Metadata, _metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
         writeln!(target,
             "{{\
@@ -1268,8 +1974,12 @@ impl<W: io::Write> Formatter<W> for Slurm {
 struct Openbgpd;
 
 impl<W: io::Write> Formatter<W> for Openbgpd {
+    fn dedup_origins(&self) -> bool {
+        true
+    }
+
     fn header(
-        &self, _snapshot: &PayloadSnapshot, _metrics: &Metrics, target: &mut W
+        &self, _meta: &Metadata, _metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
         writeln!(target, "roa-set {{")
     }
@@ -1301,6 +2011,10 @@ impl<W: io::Write> Formatter<W> for Openbgpd {
 struct Bird1;
 
 impl<W: io::Write> Formatter<W> for Bird1 {
+    fn dedup_origins(&self) -> bool {
+        true
+    }
+
     fn origin(
         &self, origin: RouteOrigin, _info: &PayloadInfo, target: &mut W
     ) -> Result<(), io::Error> {
@@ -1318,6 +2032,10 @@ impl<W: io::Write> Formatter<W> for Bird1 {
 struct Bird2;
 
 impl<W: io::Write> Formatter<W> for Bird2 {
+    fn dedup_origins(&self) -> bool {
+        true
+    }
+
     fn origin(
         &self, origin: RouteOrigin, _info: &PayloadInfo, target: &mut W
     ) -> Result<(), io::Error> {
@@ -1449,7 +2167,7 @@ impl Summary {
 
 impl<W: io::Write> Formatter<W> for Summary {
     fn header(
-        &self, _snapshot: &PayloadSnapshot, metrics: &Metrics, target: &mut W
+        &self, _meta: &Metadata, metrics: &Metrics, target: &mut W
     ) -> Result<(), io::Error> {
         Self::produce_header(metrics, |args| {
             writeln!(target, "{}", args)
@@ -1465,6 +2183,89 @@ impl<W: io::Write> Formatter<W> for Summary {
 
 
 
+//------------ RouterKeysCsv -------------------------------------------------
+
+struct RouterKeysCsv;
+
+impl<W: io::Write> Formatter<W> for RouterKeysCsv {
+    fn header(
+        &self, _meta: &Metadata, _metrics: &Metrics, target: &mut W
+    ) -> Result<(), io::Error> {
+        writeln!(target, "ASN,SKI,Router Public Key")
+    }
+
+    fn origin(
+        &self, _origin: RouteOrigin, _info: &PayloadInfo, _target: &mut W
+    ) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn router_key(
+        &self, key: &RouterKey, _info: &PayloadInfo, target: &mut W
+    ) -> Result<(), io::Error> {
+        write!(target, "{},{},", key.asn, key.key_identifier)?;
+        base64::Slurm.write_encoded_slice(key.key_info.as_slice(), target)?;
+        writeln!(target)
+    }
+}
+
+
+//------------ RouterKeysJson ------------------------------------------------
+
+struct RouterKeysJson;
+
+impl<W: io::Write> Formatter<W> for RouterKeysJson {
+    fn header(
+        &self, _meta: &Metadata, metrics: &Metrics, target: &mut W
+    ) -> Result<(), io::Error> {
+        // The array is opened here rather than in before_router_keys so
+        // that it is present even if router keys are disabled.
+        write!(target,
+            "{{\
+            \n  \"metadata\": {{\
+            \n    \"generated\": {},\
+            \n    \"generatedTime\": \"{}\"\
+            \n  }},\
+            \n  \"routerKeys\": [",
+            metrics.time.timestamp(),
+            format_iso_date(metrics.time)
+        )
+    }
+
+    fn origin(
+        &self, _origin: RouteOrigin, _info: &PayloadInfo, _target: &mut W
+    ) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn router_key(
+        &self, key: &RouterKey, info: &PayloadInfo, target: &mut W
+    ) -> Result<(), io::Error> {
+        write!(target,
+            "\n    {{ \"asn\": \"{}\", \"SKI\": \"{}\", \
+            \"routerPublicKey\": \"",
+            key.asn,
+            key.key_identifier,
+        )?;
+        base64::Slurm.write_encoded_slice(key.key_info.as_slice(), target)?;
+        write!(target,
+            "\", \"ta\": \"{}\" }}",
+            info.tal_name().unwrap_or("N/A"),
+        )
+    }
+
+    fn router_key_delimiter(&self, target: &mut W) -> Result<(), io::Error> {
+        write!(target, ",")
+    }
+
+    fn footer(
+        &self, _metrics: &Metrics, target: &mut W
+    ) -> Result<(), io::Error> {
+        writeln!(target, "\n  ]\n}}")
+    }
+}
+
+
 //------------ NoOutput-------------------------------------------------------
 
 struct NoOutput;
@@ -1478,3 +2279,327 @@ impl<W: io::Write> Formatter<W> for NoOutput {
 }
 
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rpki::resources::addr::MaxLenPrefix;
+    use crate::slurm::ExceptionInfo;
+
+    fn origin(prefix: &str, max_len: Option<u8>, asn: u32) -> RouteOrigin {
+        RouteOrigin::new(
+            MaxLenPrefix::new(Prefix::from_str(prefix).unwrap(), max_len)
+                .unwrap(),
+            Asn::from_u32(asn)
+        )
+    }
+
+    fn info() -> PayloadInfo {
+        PayloadInfo::from(Arc::new(ExceptionInfo {
+            path: None, comment: None
+        }))
+    }
+
+    #[test]
+    fn openbgpd_origins() {
+        let mut target = Vec::new();
+        let info = info();
+        for item in [
+            origin("192.0.2.0/24", None, 64496),
+            origin("192.0.2.0/24", Some(24), 64496),
+            origin("198.51.100.0/22", Some(24), 64497),
+            origin("2001:db8::/32", None, 64498),
+            origin("2001:db8:10::/48", Some(64), 4200000000),
+        ] {
+            Openbgpd.origin(item, &info, &mut target).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(target).unwrap(),
+            "    192.0.2.0/24 source-as 64496\n\
+             \x20   192.0.2.0/24 source-as 64496\n\
+             \x20   198.51.100.0/22 maxlen 24 source-as 64497\n\
+             \x20   2001:db8::/32 source-as 64498\n\
+             \x20   2001:db8:10::/48 maxlen 64 source-as 4200000000\n"
+        );
+    }
+
+    #[test]
+    fn exclude_address_family() {
+        let v4 = origin("192.0.2.0/24", None, 64496);
+        let v6 = origin("2001:db8::/32", None, 64496);
+
+        let mut output = Output::new();
+        output.update_from_query(Some("exclude=ipv6RouteOrigins")).unwrap();
+        assert!(output.include_origin(v4));
+        assert!(!output.include_origin(v6));
+
+        let mut output = Output::new();
+        output.update_from_query(Some("exclude=ipv4RouteOrigins")).unwrap();
+        assert!(!output.include_origin(v4));
+        assert!(output.include_origin(v6));
+    }
+
+    #[test]
+    fn select_tal() {
+        use rpki::repository::tal::TalInfo;
+        use rpki::repository::x509::{Time, Validity};
+        use crate::payload::PublishInfo;
+
+        let validity = Validity::new(Time::now(), Time::now());
+        let published = |name: &str| {
+            PayloadInfo::from(Arc::new(PublishInfo {
+                tal: Arc::new(TalInfo::from_name(name.into())),
+                uri: None,
+                ca_uri: None,
+                roa_validity: validity,
+                chain_validity: validity,
+                point_stale: Time::now(),
+            }))
+        };
+        let ripe = published("ripe");
+        let arin = published("arin");
+        let mut both = published("ripe");
+        both.add_published(Arc::new(arin.publish_info().unwrap().clone()));
+        let v4 = origin("192.0.2.0/24", None, 64496);
+
+        let output = Output::from_query(Some("select-ta=arin")).unwrap();
+        assert!(!output.include_info(&ripe));
+        assert!(output.include_info(&arin));
+        assert!(output.include_info(&both));
+        assert!(!output.include_info(&info()));
+        assert!(output.include_origin(v4));
+        assert!(output.check_tals(["arin", "ripe"]).is_ok());
+        assert_eq!(
+            output.check_tals(["ripe", "apnic"]).unwrap_err(),
+            "Unknown TAL 'arin'. Valid labels are: ripe, apnic"
+        );
+
+        let output = Output::from_query(
+            Some("select-ta=ripe&select-asn=AS64497")
+        ).unwrap();
+        assert!(output.include_info(&ripe));
+        assert!(!output.include_origin(v4));
+    }
+
+    #[test]
+    fn dedup_origins() {
+        fn check(
+            written: &mut Option<(Prefix, Vec<(u8, Asn)>)>,
+            formatter: &(dyn Formatter<Vec<u8>> + Send + Sync),
+            prefix: &str, max_len: Option<u8>, asn: u32,
+        ) -> bool {
+            OutputStream::is_duplicate_origin(
+                written, formatter, origin(prefix, max_len, asn)
+            )
+        }
+
+        let mut written = None;
+        let w = &mut written;
+        assert!(!check(w, &Bird2, "192.0.2.0/24", None, 64496));
+        assert!(check(w, &Bird2, "192.0.2.0/24", Some(24), 64496));
+        assert!(!check(w, &Bird2, "192.0.2.0/24", Some(25), 64496));
+        assert!(!check(w, &Bird2, "192.0.2.0/24", None, 64497));
+        assert!(!check(w, &Bird2, "192.0.2.0/25", None, 64496));
+        assert!(check(w, &Bird2, "192.0.2.0/25", Some(25), 64496));
+
+        // Formats that keep the original max length don’t dedup.
+        let mut written = None;
+        let w = &mut written;
+        assert!(!check(w, &Json, "192.0.2.0/24", None, 64496));
+        assert!(!check(w, &Json, "192.0.2.0/24", None, 64496));
+    }
+
+    #[test]
+    fn router_keys_disabled() {
+        fn write(format: OutputFormat) -> String {
+            let mut output = Output::new();
+            output.no_router_keys();
+            let mut target = Vec::new();
+            output.write(
+                Arc::new(PayloadSnapshot::default()),
+                Arc::new(Metrics::new()),
+                format, &mut target
+            ).unwrap();
+            String::from_utf8(target).unwrap()
+        }
+
+        assert_eq!(
+            write(OutputFormat::RouterKeysCsv),
+            "ASN,SKI,Router Public Key\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(
+            &write(OutputFormat::RouterKeysJson)
+        ).unwrap();
+        assert_eq!(json["routerKeys"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn metadata() {
+        use chrono::TimeZone;
+        use rpki::repository::tal::TalInfo;
+        use crate::metrics::TalMetrics;
+
+        fn write(
+            format: OutputFormat, query: Option<&str>, serial: bool
+        ) -> String {
+            let time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+            let mut metrics = Metrics::new();
+            let mut tal = TalMetrics::new(
+                Arc::new(TalInfo::from_name("ripe".into()))
+            );
+            tal.updated = Some(time);
+            metrics.tals.push(tal);
+            metrics.tals.push(TalMetrics::new(
+                Arc::new(TalInfo::from_name("arin".into()))
+            ));
+            let mut output = Output::from_query(query).unwrap();
+            if serial {
+                output.set_rtr_state(12, 7.into());
+            }
+            let mut target = Vec::new();
+            output.write(
+                Arc::new(PayloadSnapshot::default().with_created(time)),
+                Arc::new(metrics), format, &mut target
+            ).unwrap();
+            String::from_utf8(target).unwrap()
+        }
+
+        let comment = "# created: 2024-05-01T12:00:00Z\n\
+                       # serial: 7\n\
+                       # ta-updated: ripe=2024-05-01T12:00:00Z\n\
+                       # ta-updated: arin=N/A\n";
+
+        // Plain CSV only has the metadata upon request.
+        assert_eq!(
+            write(OutputFormat::Csv, None, true),
+            "ASN,IP Prefix,Max Length,Trust Anchor\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Csv, Some("include-meta=comment"), true),
+            format!("{}ASN,IP Prefix,Max Length,Trust Anchor\n", comment)
+        );
+        assert!(Output::from_query(Some("include-meta=json")).is_err());
+
+        // So does extended CSV.
+        assert!(
+            write(OutputFormat::ExtendedCsv, None, true).starts_with(
+                "URI,ASN,"
+            )
+        );
+        assert!(
+            write(
+                OutputFormat::ExtendedCsv, Some("include-meta=comment"), true
+            ).starts_with(comment)
+        );
+        assert!(
+            write(
+                OutputFormat::ExtendedCsv, Some("include-meta=comment"), false
+            ).contains("# serial: N/A\n")
+        );
+
+        for format in [OutputFormat::Json, OutputFormat::ExtendedJson] {
+            let json: serde_json::Value = serde_json::from_str(
+                &write(format, None, true)
+            ).unwrap();
+            assert_eq!(
+                json["metadata"],
+                serde_json::json!({
+                    "generated": json["metadata"]["generated"],
+                    "generatedTime": json["metadata"]["generatedTime"],
+                    "created": 1714564800,
+                    "createdTime": "2024-05-01T12:00:00Z",
+                    "serial": 7,
+                    "tas": {
+                        "ripe": {
+                            "updated": 1714564800,
+                            "updatedTime": "2024-05-01T12:00:00Z"
+                        },
+                        "arin": { "updated": null, "updatedTime": null }
+                    }
+                })
+            );
+            let json: serde_json::Value = serde_json::from_str(
+                &write(format, None, false)
+            ).unwrap();
+            assert_eq!(json["metadata"]["serial"], serde_json::Value::Null);
+        }
+    }
+
+    /// Creates the output of the json-v2 format for a fixed data set.
+    fn json_v2(query: Option<&str>) -> serde_json::Value {
+        use chrono::TimeZone;
+        use rpki::crypto::keys::KeyIdentifier;
+        use rpki::repository::tal::TalInfo;
+        use rpki::repository::x509::{Time, Validity};
+        use rpki::rtr::pdu::{ProviderAsns, RouterKeyInfo};
+        use crate::payload::PublishInfo;
+
+        let info = PayloadInfo::from(Arc::new(PublishInfo {
+            tal: Arc::new(TalInfo::from_name("test".into())),
+            uri: None,
+            ca_uri: None,
+            roa_validity: Validity::new(Time::now(), Time::now()),
+            chain_validity: Validity::new(Time::now(), Time::now()),
+            point_stale: Time::now(),
+        }));
+        let snapshot = PayloadSnapshot::new(
+            [
+                origin("192.0.2.0/24", None, 64496),
+                origin("2001:db8::/32", Some(48), 64497),
+            ].into_iter().map(|item| (item, info.clone())),
+            [
+                RouterKey::new(
+                    KeyIdentifier::from([0x17; 20]), Asn::from_u32(64496),
+                    RouterKeyInfo::new(
+                        Bytes::from_static(b"router key")
+                    ).unwrap()
+                )
+            ].into_iter().map(|item| (item, info.clone())),
+            [
+                Aspa::new(
+                    Asn::from_u32(64496),
+                    ProviderAsns::try_from_iter([
+                        Asn::from_u32(64498), Asn::from_u32(64499)
+                    ]).unwrap()
+                )
+            ].into_iter().map(|item| (item, info.clone())),
+            None
+        );
+        let mut metrics = Metrics::new();
+        metrics.time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+        let mut output = Output::from_query(query).unwrap();
+        output.set_rtr_state(12, 7.into());
+        let mut target = Vec::new();
+        output.write(
+            Arc::new(snapshot), Arc::new(metrics),
+            OutputFormat::JsonV2, &mut target
+        ).unwrap();
+        serde_json::from_slice(&target).unwrap()
+    }
+
+    #[test]
+    fn json_v2_golden() {
+        // The schema of the json-v2 format is frozen. If this test fails,
+        // do not update the golden file but add a new format instead.
+        let golden: serde_json::Value = serde_json::from_str(
+            include_str!("../test/output/json-v2.json")
+        ).unwrap();
+        assert_eq!(json_v2(None), golden);
+
+        let golden: serde_json::Value = serde_json::from_str(
+            include_str!("../test/output/json-v2-selected.json")
+        ).unwrap();
+        assert_eq!(
+            json_v2(Some(
+                "select-asn=AS64496&select-prefix=192.0.2.0/24\
+                 &select-ta=test&include=more-specifics\
+                 &exclude=routerKeys,ipv6RouteOrigins"
+            )),
+            golden
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! Archiving the payload snapshots of past validation runs.
+//!
+//! If configured, the data set of every validation run is written to a
+//! file in the archive directory. The [`SnapshotArchive`] knows how these
+//! files are named and how to read and write them. The
+//! [`SnapshotArchiver`] writes new snapshots on a thread of its own so
+//! that a slow disk never delays the distribution of new data via RTR.
+
+use std::{fs, io, thread};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{error, info, warn};
+use crate::config::Config;
+use crate::error::Failed;
+use super::history::SharedHistory;
+use super::snapshot::PayloadSnapshot;
+
+
+//------------ SnapshotArchive -----------------------------------------------
+
+/// A directory of archived payload snapshots.
+///
+/// Each snapshot is kept in a file of its own. The file contains the
+/// snapshot in its binary encoding compressed with gzip. The file name
+/// contains the creation time of the snapshot in a format that sorts
+/// lexically, so the file names double as the names of the snapshots.
+/// Only the configured number of most recent snapshots is kept.
+#[derive(Clone, Debug)]
+pub struct SnapshotArchive {
+    /// The directory the snapshots are kept in.
+    dir: PathBuf,
+
+    /// The number of snapshots to keep.
+    count: usize,
+}
+
+impl SnapshotArchive {
+    /// The prefix of the snapshot file names.
+    const FILE_PREFIX: &'static str = "snapshot-";
+
+    /// The suffix of the snapshot file names.
+    const FILE_SUFFIX: &'static str = ".bin.gz";
+
+    /// The prefix of the temporary files used while writing.
+    const TEMP_PREFIX: &'static str = ".snapshot-";
+
+    /// The format of the time in the snapshot names.
+    const TIME_FORMAT: &'static str = "%Y%m%dT%H%M%SZ";
+
+    /// Creates a new archive if one is configured.
+    pub fn new(config: &Config) -> Option<Self> {
+        Some(SnapshotArchive {
+            dir: config.snapshot_archive_dir.clone()?,
+            count: config.snapshot_archive_count,
+        })
+    }
+
+    /// Returns the name of the snapshot created at the given time.
+    pub fn name(time: DateTime<Utc>) -> String {
+        time.format(Self::TIME_FORMAT).to_string()
+    }
+
+    /// Returns the creation time for a snapshot name.
+    pub fn parse_name(name: &str) -> Option<DateTime<Utc>> {
+        NaiveDateTime::parse_from_str(
+            name, Self::TIME_FORMAT
+        ).ok().map(|time| Utc.from_utc_datetime(&time))
+    }
+
+    /// Returns the creation times of all archived snapshots.
+    ///
+    /// The times are returned in ascending order. If the archive directory
+    /// doesn’t exist yet, the list is empty.
+    pub fn list(&self) -> Result<Vec<DateTime<Utc>>, io::Error> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(err)
+        };
+        let mut res = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            let time = entry.file_name().to_str().and_then(|name| {
+                Self::parse_name(
+                    name.strip_prefix(Self::FILE_PREFIX)?
+                        .strip_suffix(Self::FILE_SUFFIX)?
+                )
+            });
+            if let Some(time) = time {
+                res.push(time)
+            }
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    /// Returns the most recent snapshot created no later than `time`.
+    pub fn find(
+        &self, time: DateTime<Utc>
+    ) -> Result<Option<DateTime<Utc>>, io::Error> {
+        Ok(self.list()?.into_iter().rev().find(|item| *item <= time))
+    }
+
+    /// Loads the snapshot created at the given time.
+    pub fn load(
+        &self, time: DateTime<Utc>
+    ) -> Result<PayloadSnapshot, io::Error> {
+        let file = fs::File::open(self.path(time))?;
+        PayloadSnapshot::parse(&mut io::BufReader::new(GzDecoder::new(file)))
+    }
+
+    /// Writes a snapshot to the archive.
+    ///
+    /// The snapshot is first written to a temporary file which is moved
+    /// into place once complete. If writing fails, the temporary file is
+    /// removed again.
+    ///
+    /// Returns the path of the new file.
+    pub fn store(
+        &self, snapshot: &PayloadSnapshot
+    ) -> Result<PathBuf, io::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(snapshot.created());
+        let mut file = tempfile::Builder::new()
+            .prefix(Self::TEMP_PREFIX)
+            .tempfile_in(&self.dir)?;
+        {
+            let mut writer = GzEncoder::new(
+                io::BufWriter::new(file.as_file_mut()),
+                Compression::default()
+            );
+            snapshot.compose(&mut writer)?;
+            io::Write::flush(&mut writer.finish()?)?;
+        }
+        file.persist(&path).map_err(|err| err.error)?;
+        Ok(path)
+    }
+
+    /// Removes all but the most recent snapshots.
+    pub fn prune(&self) -> Result<(), io::Error> {
+        let list = self.list()?;
+        let excess = list.len().saturating_sub(self.count);
+        for time in &list[..excess] {
+            fs::remove_file(self.path(*time))?;
+        }
+        Ok(())
+    }
+
+    /// Removes temporary files left behind by an interrupted write.
+    fn remove_temporary(&self) -> Result<(), io::Error> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(err) => return Err(err)
+        };
+        for entry in dir {
+            let entry = entry?;
+            let is_temp = entry.file_name().to_str().map(|name| {
+                name.starts_with(Self::TEMP_PREFIX)
+            }).unwrap_or(false);
+            if is_temp {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the archive directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the file for the snapshot created at `time`.
+    fn path(&self, time: DateTime<Utc>) -> PathBuf {
+        self.dir.join(format!(
+            "{}{}{}", Self::FILE_PREFIX, Self::name(time), Self::FILE_SUFFIX
+        ))
+    }
+}
+
+
+//------------ SnapshotArchiver ----------------------------------------------
+
+/// Writes the current payload snapshot to the archive.
+///
+/// The archiver runs on a thread of its own. Each call to
+/// [`trigger`][Self::trigger] causes it to write the current snapshot of
+/// the history unless it has done so already.
+#[derive(Debug)]
+pub struct SnapshotArchiver {
+    /// The sending end of the trigger channel.
+    tx: mpsc::SyncSender<()>,
+}
+
+impl SnapshotArchiver {
+    /// Starts the archiver if an archive is configured.
+    pub fn start(
+        config: &Config, history: SharedHistory
+    ) -> Result<Option<Self>, Failed> {
+        let archive = match SnapshotArchive::new(config) {
+            Some(archive) => archive,
+            None => return Ok(None)
+        };
+        let mut state = ArchiverState { archive, history, last: None };
+
+        // A pending trigger is enough. So we only need space for one.
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::Builder::new().name("archiver".into()).spawn(move || {
+            state.remove_temporary();
+            while rx.recv().is_ok() {
+                state.store()
+            }
+        }).map_err(|err: io::Error| {
+            error!("Failed to start snapshot archiver thread: {}", err);
+            Failed
+        })?;
+        Ok(Some(SnapshotArchiver { tx }))
+    }
+
+    /// Triggers archiving the current snapshot.
+    pub fn trigger(&self) {
+        // If the channel is full, archiving is pending already. If it is
+        // disconnected, there is nothing we can do.
+        let _ = self.tx.try_send(());
+    }
+}
+
+
+//------------ ArchiverState -------------------------------------------------
+
+/// The state of the archiver thread.
+struct ArchiverState {
+    /// The archive to write to.
+    archive: SnapshotArchive,
+
+    /// The history to take the snapshots from.
+    history: SharedHistory,
+
+    /// The creation time of the last snapshot written.
+    last: Option<DateTime<Utc>>,
+}
+
+impl ArchiverState {
+    /// Removes temporary files left behind by a previous process.
+    fn remove_temporary(&self) {
+        if let Err(err) = self.archive.remove_temporary() {
+            warn!(
+                "Failed to clean up snapshot archive {}: {}",
+                self.archive.dir().display(), err
+            );
+        }
+    }
+
+    /// Writes the current snapshot if it hasn’t been written yet.
+    ///
+    /// Failures are logged but otherwise ignored.
+    fn store(&mut self) {
+        let snapshot = match self.history.read().current() {
+            Some(snapshot) => snapshot,
+            None => return
+        };
+        if self.last == Some(snapshot.created()) {
+            return
+        }
+        match self.archive.store(&snapshot) {
+            Ok(path) => {
+                info!("Archived data set in {}.", path.display());
+                self.last = Some(snapshot.created());
+            }
+            Err(err) => {
+                error!(
+                    "Failed to archive data set in {}: {}",
+                    self.archive.dir().display(), err
+                );
+                return
+            }
+        }
+        if let Err(err) = self.archive.prune() {
+            warn!(
+                "Failed to clean up snapshot archive {}: {}",
+                self.archive.dir().display(), err
+            );
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use rpki::resources::{Asn, Prefix};
+    use rpki::resources::addr::MaxLenPrefix;
+    use rpki::rtr::payload::RouteOrigin;
+    use crate::payload::PayloadInfo;
+    use crate::slurm::ExceptionInfo;
+
+    fn snapshot(secs: i64, asn: u32) -> PayloadSnapshot {
+        let info = PayloadInfo::from(Arc::new(ExceptionInfo {
+            path: None, comment: None
+        }));
+        PayloadSnapshot::new(
+            std::iter::once((
+                RouteOrigin::new(
+                    MaxLenPrefix::new(
+                        Prefix::from_str("192.0.2.0/24").unwrap(), None
+                    ).unwrap(),
+                    Asn::from_u32(asn)
+                ),
+                info
+            )),
+            std::iter::empty(), std::iter::empty(), None
+        ).with_created(Utc.timestamp_opt(secs, 0).unwrap())
+    }
+
+    #[test]
+    fn names() {
+        let time = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        assert_eq!(SnapshotArchive::name(time), "20231114T221320Z");
+        assert_eq!(
+            SnapshotArchive::parse_name("20231114T221320Z"), Some(time)
+        );
+        assert_eq!(SnapshotArchive::parse_name("20231114T221320"), None);
+        assert_eq!(SnapshotArchive::parse_name("latest"), None);
+    }
+
+    #[test]
+    fn store_list_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = SnapshotArchive {
+            dir: dir.path().join("archive"), count: 2
+        };
+        assert!(archive.list().unwrap().is_empty());
+
+        archive.store(&snapshot(1000, 1)).unwrap();
+        archive.store(&snapshot(3000, 3)).unwrap();
+        archive.store(&snapshot(2000, 2)).unwrap();
+        fs::write(dir.path().join("archive/.snapshot-foo"), b"x").unwrap();
+        fs::write(dir.path().join("archive/unrelated"), b"x").unwrap();
+
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        assert_eq!(
+            archive.list().unwrap(), vec![time(1000), time(2000), time(3000)]
+        );
+        assert_eq!(archive.find(time(2500)).unwrap(), Some(time(2000)));
+        assert_eq!(archive.find(time(3000)).unwrap(), Some(time(3000)));
+        assert_eq!(archive.find(time(999)).unwrap(), None);
+
+        let loaded = archive.load(time(2000)).unwrap();
+        assert_eq!(loaded.created(), time(2000));
+        assert_eq!(
+            loaded.origins().map(|(origin, _)| origin).collect::<Vec<_>>(),
+            snapshot(2000, 2).origins().map(|(origin, _)| {
+                origin
+            }).collect::<Vec<_>>(),
+        );
+
+        archive.prune().unwrap();
+        assert_eq!(archive.list().unwrap(), vec![time(2000), time(3000)]);
+
+        archive.remove_temporary().unwrap();
+        assert!(!dir.path().join("archive/.snapshot-foo").exists());
+        assert!(dir.path().join("archive/unrelated").exists());
+    }
+}
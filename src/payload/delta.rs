@@ -3,12 +3,15 @@
 //! This is a private module. Its relevant public types are re-exported by
 //! the parent.
 
+use std::io;
 use std::sync::Arc;
 use std::cmp::Ordering;
+use std::iter::Peekable;
 use rpki::rtr::{Action, PayloadRef, PayloadType, Serial};
 use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
 use rpki::rtr::pdu::ProviderAsns;
 use rpki::rtr::server::PayloadDiff;
+use crate::utils::binio::{Compose, Parse};
 use super::info::PayloadInfo;
 use super::snapshot::PayloadSnapshot;
 
@@ -133,6 +136,27 @@ impl PayloadDelta {
         + self.aspas.withdraw_len
     }
 
+    /// Reads a delta from an IO reader.
+    pub fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
+        Ok(PayloadDelta {
+            serial: Parse::parse(reader)?,
+            origins: StandardDelta::parse(reader)?,
+            router_keys: StandardDelta::parse(reader)?,
+            aspas: AspaDelta::parse(reader)?,
+        })
+    }
+
+    /// Composes the encoded delta.
+    pub fn compose(
+        &self, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        self.serial.compose(writer)?;
+        self.origins.compose(writer)?;
+        self.router_keys.compose(writer)?;
+        self.aspas.compose(writer)?;
+        Ok(())
+    }
+
     /// Returns an iterator over the changes in a shared delta.
     pub fn arc_iter(self: Arc<Self>) -> DeltaArcIter {
         DeltaArcIter::new(self)
@@ -352,6 +376,28 @@ impl<P> StandardDelta<P> {
     fn get(&self, idx: usize) -> Option<(&P, Action)> {
         self.items.get(idx).map(|item| (&item.0, item.1))
     }
+
+    /// Reads a delta from an IO reader.
+    fn parse<R: io::Read>(reader: &mut R) -> Result<Self, io::Error>
+    where P: Parse<R> {
+        let len = u64::parse(reader)?;
+        let mut res = Self::default();
+        for _ in 0..len {
+            res.push((P::parse(reader)?, Action::parse(reader)?));
+        }
+        Ok(res)
+    }
+
+    /// Composes the encoded delta.
+    fn compose<W: io::Write>(&self, writer: &mut W) -> Result<(), io::Error>
+    where P: Compose<W> {
+        (self.items.len() as u64).compose(writer)?;
+        for (payload, action) in &self.items {
+            payload.compose(writer)?;
+            action.compose(writer)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "arbitrary")]
@@ -616,6 +662,45 @@ impl AspaDelta {
     fn get(&self, idx: usize) -> Option<(&Aspa, Action)> {
         self.items.get(idx).map(|item| (&item.0, (&item.1).into()))
     }
+
+    /// Reads a delta from an IO reader.
+    fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
+        let len = u64::parse(reader)?;
+        let mut res = Self::default();
+        for _ in 0..len {
+            let aspa = Aspa::parse(reader)?;
+            let action = match u8::parse(reader)? {
+                0 => AspaAction::Announce,
+                1 => AspaAction::Update(Parse::parse(reader)?),
+                2 => AspaAction::Withdraw(Parse::parse(reader)?),
+                _ => {
+                    return Err(io::Error::other("invalid ASPA action"))
+                }
+            };
+            res.push((aspa, action));
+        }
+        Ok(res)
+    }
+
+    /// Composes the encoded delta.
+    fn compose(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
+        (self.items.len() as u64).compose(writer)?;
+        for (aspa, action) in &self.items {
+            aspa.compose(writer)?;
+            match action {
+                AspaAction::Announce => 0u8.compose(writer)?,
+                AspaAction::Update(providers) => {
+                    1u8.compose(writer)?;
+                    providers.compose(writer)?;
+                }
+                AspaAction::Withdraw(providers) => {
+                    2u8.compose(writer)?;
+                    providers.compose(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "arbitrary")]
@@ -637,6 +722,70 @@ impl<'a> arbitrary::Arbitrary<'a> for AspaDelta {
 }
 
 
+//------------ origin_changes ------------------------------------------------
+
+/// Returns an iterator over the route origins changed between two snapshots.
+///
+/// Unlike a delta, the iterator provides the payload information of each
+/// changed route origin – taken from the new snapshot for announced and
+/// from the old snapshot for withdrawn origins. This can be used to
+/// determine where the changes originate from.
+pub fn origin_changes<'a>(
+    old: &'a PayloadSnapshot, new: &'a PayloadSnapshot,
+) -> impl Iterator<Item = (&'a PayloadInfo, Action)> + 'a {
+    OriginChanges {
+        old: old.origin_refs().peekable(),
+        new: new.origin_refs().peekable(),
+    }
+}
+
+
+//------------ OriginChanges -------------------------------------------------
+
+/// The iterator returned by [`origin_changes`].
+struct OriginChanges<I: Iterator> {
+    /// The route origins of the old snapshot.
+    old: Peekable<I>,
+
+    /// The route origins of the new snapshot.
+    new: Peekable<I>,
+}
+
+impl<'a, I> Iterator for OriginChanges<I>
+where I: Iterator<Item = (&'a RouteOrigin, &'a PayloadInfo)> {
+    type Item = (&'a PayloadInfo, Action);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.old.peek(), self.new.peek()) {
+                (Some(old), Some(new)) => old.0.cmp(new.0),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return None,
+            };
+            match order {
+                Ordering::Less => {
+                    // Excess old item: withdrawn.
+                    return self.old.next().map(|item| {
+                        (item.1, Action::Withdraw)
+                    })
+                }
+                Ordering::Equal => {
+                    // Same. Ignore.
+                    self.old.next();
+                    self.new.next();
+                }
+                Ordering::Greater => {
+                    // Excess new item: announced.
+                    return self.new.next().map(|item| {
+                        (item.1, Action::Announce)
+                    })
+                }
+            }
+        }
+    }
+}
+
 //----------- DeltaArcIter ---------------------------------------------------
 
 /// An iterator over the elements of a shared delta.
@@ -0,0 +1,199 @@
+//! Protection against suspiciously small data sets.
+//!
+//! This is a private module. Its public types are re-exported by the parent
+//! as needed.
+
+use log::{error, warn};
+use crate::config::Config;
+
+
+//------------ VrpGuard ------------------------------------------------------
+
+/// A guard against replacing the data set with a much smaller one.
+///
+/// A transient problem during validation may lead to a data set that is
+/// much smaller than the real one. Publishing such a set would cause
+/// routers to drop most of their filters. The guard checks the number of
+/// VRPs of a new data set against a configured minimum and against the
+/// maximum permitted drop from the current data set. If either is
+/// violated, the new data set is held back until a configured number of
+/// consecutive runs have produced a violating data set, too.
+#[derive(Clone, Debug)]
+pub struct VrpGuard {
+    /// The minimum number of VRPs a data set must contain.
+    min_vrps: Option<usize>,
+
+    /// The maximum drop in the number of VRPs in percent.
+    max_drop: Option<u8>,
+
+    /// The number of consecutive runs needed to accept a violating set.
+    confirm: usize,
+
+    /// Accept the next data set no matter what?
+    force: bool,
+
+    /// The number of consecutive runs with a violating data set.
+    held_runs: usize,
+
+    /// The number of VRPs of the last held back data set.
+    held_vrps: Option<usize>,
+}
+
+impl VrpGuard {
+    /// Creates a new guard from the configuration.
+    pub fn from_config(config: &Config) -> Self {
+        VrpGuard {
+            min_vrps: config.min_vrps,
+            max_drop: config.max_vrp_drop,
+            confirm: config.vrp_drop_confirm,
+            force: config.accept_vrp_drop,
+            held_runs: 0,
+            held_vrps: None,
+        }
+    }
+
+    /// Returns whether a new data set is currently being held back.
+    pub fn is_active(&self) -> bool {
+        self.held_vrps.is_some()
+    }
+
+    /// Returns the number of consecutive runs that were held back.
+    pub fn held_runs(&self) -> usize {
+        self.held_runs
+    }
+
+    /// Returns the number of VRPs of the last data set held back.
+    pub fn held_vrps(&self) -> Option<usize> {
+        self.held_vrps
+    }
+
+    /// Checks whether a new data set may replace the current one.
+    ///
+    /// The `current` argument is the number of VRPs of the current data
+    /// set or `None` if there is none yet. The `new` argument is the number
+    /// of VRPs of the new data set.
+    ///
+    /// Returns whether the new data set should be accepted. If it should
+    /// not, logs an error.
+    pub fn check(&mut self, current: Option<usize>, new: usize) -> bool {
+        if self.force {
+            self.force = false;
+            self.reset();
+            return true
+        }
+
+        let too_few = self.min_vrps.map(|min| new < min).unwrap_or(false);
+        let too_large_drop = match (current, self.max_drop) {
+            (Some(current), Some(max_drop)) => {
+                let allowed = current.saturating_mul(
+                    usize::from(100 - max_drop)
+                ) / 100;
+                new < allowed
+            }
+            _ => false
+        };
+        if !too_few && !too_large_drop {
+            self.reset();
+            return true
+        }
+
+        self.held_runs += 1;
+        if self.held_runs >= self.confirm {
+            warn!(
+                "Accepting new data set with {} VRPs after {} consecutive \
+                 runs.",
+                new, self.held_runs
+            );
+            self.reset();
+            return true
+        }
+        self.held_vrps = Some(new);
+        match current {
+            Some(current) => {
+                error!(
+                    "New data set with {} VRPs is suspiciously small. \
+                     Keeping current data set with {} VRPs ({} of {} runs).",
+                    new, current, self.held_runs, self.confirm
+                );
+            }
+            None => {
+                error!(
+                    "New data set with {} VRPs is suspiciously small. \
+                     Not publishing it ({} of {} runs).",
+                    new, self.held_runs, self.confirm
+                );
+            }
+        }
+        false
+    }
+
+    /// Resets the guard after a data set was accepted.
+    fn reset(&mut self) {
+        self.held_runs = 0;
+        self.held_vrps = None;
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn guard(
+        min_vrps: Option<usize>, max_drop: Option<u8>, confirm: usize
+    ) -> VrpGuard {
+        VrpGuard {
+            min_vrps, max_drop, confirm,
+            force: false,
+            held_runs: 0,
+            held_vrps: None,
+        }
+    }
+
+    #[test]
+    fn min_vrps() {
+        let mut guard = guard(Some(1000), None, 3);
+        assert!(guard.check(None, 1000));
+        assert!(!guard.check(Some(1000), 999));
+        assert!(guard.is_active());
+        assert_eq!(guard.held_vrps(), Some(999));
+        assert!(guard.check(Some(1000), 1200));
+        assert!(!guard.is_active());
+        assert_eq!(guard.held_runs(), 0);
+    }
+
+    #[test]
+    fn max_drop() {
+        let mut guard = guard(None, Some(50), 3);
+        assert!(guard.check(None, 10));
+        assert!(guard.check(Some(1000), 500));
+        assert!(!guard.check(Some(1000), 499));
+        assert!(guard.check(Some(1000), 2000));
+    }
+
+    #[test]
+    fn confirm() {
+        let mut guard = guard(None, Some(50), 3);
+        assert!(!guard.check(Some(1000), 20));
+        assert!(!guard.check(Some(1000), 20));
+        assert_eq!(guard.held_runs(), 2);
+        assert!(guard.check(Some(1000), 20));
+        assert!(!guard.is_active());
+
+        // A good run in between resets the count.
+        assert!(!guard.check(Some(1000), 20));
+        assert!(guard.check(Some(1000), 900));
+        assert!(!guard.check(Some(1000), 20));
+        assert_eq!(guard.held_runs(), 1);
+    }
+
+    #[test]
+    fn force() {
+        let mut guard = guard(Some(1000), None, 3);
+        guard.force = true;
+        assert!(guard.check(Some(1000), 20));
+        assert!(!guard.check(Some(1000), 20));
+    }
+}
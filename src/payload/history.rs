@@ -3,20 +3,26 @@
 //! This is a private module. Its public types are re-exported by the parent
 //! as needed.
 
-use std::{cmp, ops};
+use std::{cmp, fs, io, ops};
 use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime};
 use chrono::{DateTime, Utc};
-use log::info;
+use log::{info, warn};
 use rpki::rtr::{Serial, State, Timing};
 use rpki::rtr::server::PayloadSource;
 use crate::config::{Config, FilterPolicy};
-use crate::metrics::Metrics;
+use crate::metrics::{
+    ChurnTotals, IntegrityMetrics, Metrics, PublishMetrics,
+    RrdpDurationHistograms, RrdpRepositoryMetrics, RrdpUpdateTotals,
+};
 use crate::slurm::LocalExceptions;
-use super::delta::{DeltaArcIter, PayloadDelta};
+use crate::utils::binio::{Compose, Parse};
+use super::delta::{DeltaArcIter, PayloadDelta, origin_changes};
+use super::guard::VrpGuard;
 use super::snapshot::{PayloadSnapshot, SnapshotArcIter};
-use super::validation::ValidationReport;
+use super::validation::{ValidatedPayload, ValidationReport};
 
 
 //------------ SharedHistory -------------------------------------------------
@@ -38,6 +44,43 @@ impl SharedHistory {
         self.0.read().expect("Payload history lock poisoned")
     }
 
+    /// Loads the history from the history file.
+    ///
+    /// If keeping the history in a file is disabled, the file doesn’t
+    /// exist, or it can’t be used for whatever reason, the history is left
+    /// untouched and a fresh session will be started.
+    pub fn load(&self) {
+        self.write().load()
+    }
+
+    /// Writes the history to the history file.
+    ///
+    /// Does nothing if keeping the history in a file is disabled or there
+    /// is no data yet. Failing to write the file is logged but otherwise
+    /// ignored.
+    pub fn persist(&self) {
+        let (path, session, current, deltas) = {
+            let read = self.read();
+            let path = match read.history_file.clone() {
+                Some(path) => path,
+                None => return
+            };
+            let current = match read.current.clone() {
+                Some(current) => current,
+                None => return
+            };
+            (path, read.session, current, read.deltas.clone())
+        };
+        if let Err(err) = PayloadHistory::write_file(
+            &path, session, &current, &deltas
+        ) {
+            warn!(
+                "Failed to write history file {}: {}",
+                path.display(), err
+            );
+        }
+    }
+
     /// Provides write access to the underlying history.
     ///
     /// This is private because access is only through dedicated update
@@ -52,6 +95,9 @@ impl SharedHistory {
     /// exceptions. If this snapshot differs from the current one, adds a
     /// new version to the history.
     ///
+    /// If the VRP guard considers the new snapshot suspiciously small, it
+    /// is dropped and the history stays unchanged.
+    ///
     /// The method returns whether it has indeed added a new version.
     pub fn update(
         &self,
@@ -59,10 +105,158 @@ impl SharedHistory {
         exceptions: &LocalExceptions,
         mut metrics: Metrics
     ) -> bool {
-        let snapshot = report.into_snapshot(
-            exceptions, &mut metrics,
+        let (payload, snapshot) = if self.read().retain_payload {
+            let payload = report.into_payload();
+            let snapshot = payload.to_snapshot(exceptions, &mut metrics);
+            (Some(payload), snapshot)
+        }
+        else {
+            (None, report.into_snapshot(exceptions, &mut metrics))
+        };
+        if !self.check_vrp_guard(&snapshot) {
+            return false
+        }
+        if let Some(payload) = payload {
+            self.write().validated = Some(Arc::new(payload));
+        }
+        if let Some(current) = self.read().metrics.as_ref() {
+            metrics.keep_tal_updates(current);
+        }
+        self.update_rrdp_totals(&metrics.rrdp);
+        self.apply_snapshot(snapshot, exceptions, metrics)
+    }
+
+    /// Updates the history with the result of a run for a single TAL.
+    ///
+    /// The payload of the TAL with index `tal_index` in the payload of the
+    /// last validation run is replaced with that in the report while the
+    /// payload of all other TALs is kept. A new snapshot is then produced
+    /// from this merged payload just like in [`update`][Self::update].
+    ///
+    /// This is only possible if the payload of the last validation run has
+    /// been retained. If it hasn’t, the method arranges for it to be kept
+    /// from the next full run on and returns `None`. Otherwise returns
+    /// whether a new version has been added.
+    pub fn update_tal(
+        &self,
+        report: ValidationReport,
+        tal_index: usize,
+        exceptions: &LocalExceptions,
+        metrics: Metrics,
+    ) -> Option<bool> {
+        let (payload, current_metrics) = {
+            let read = self.read();
+            match (read.validated.clone(), read.metrics.clone()) {
+                (Some(payload), Some(metrics)) => (payload, metrics),
+                _ => {
+                    drop(read);
+                    self.write().retain_payload = true;
+                    return None
+                }
+            }
+        };
+        self.update_rrdp_totals(&metrics.rrdp);
+        let (mut metrics, repository_map) = current_metrics.merge_tal(
+            metrics, tal_index
+        );
+        let payload = payload.merge_tal(
+            report.into_payload(), tal_index, &repository_map
         );
+        let snapshot = payload.to_snapshot(exceptions, &mut metrics);
+        if !self.check_vrp_guard(&snapshot) {
+            return Some(false)
+        }
+        self.write().validated = Some(Arc::new(payload));
+        Some(self.apply_snapshot(snapshot, exceptions, metrics))
+    }
+
+    /// Checks a new snapshot with the VRP guard.
+    ///
+    /// Returns whether the snapshot can be used. If so, marks the data as
+    /// refreshed.
+    fn check_vrp_guard(&self, snapshot: &PayloadSnapshot) -> bool {
+        let mut history = self.write();
+        let current = history.current.as_ref().map(|current| {
+            current.origin_count()
+        });
+        if !history.vrp_guard.check(current, snapshot.origin_count()) {
+            return false
+        }
+        history.data_refreshed = Some(Utc::now());
+        true
+    }
+
+    /// Adds the RRDP metrics of a run to the accumulated RRDP metrics.
+    fn update_rrdp_totals(&self, metrics: &[RrdpRepositoryMetrics]) {
+        let mut history = self.write();
+        Arc::make_mut(&mut history.rrdp_durations).update(metrics);
+        Arc::make_mut(&mut history.rrdp_updates).update(metrics);
+    }
+
+    /// Applies changed local exceptions to the current payload.
+    ///
+    /// Produces a new snapshot from the payload of the last validation run
+    /// and the given exceptions. If this snapshot differs from the current
+    /// one, adds a new version to the history.
+    ///
+    /// The method returns whether it has indeed added a new version. If
+    /// there hasn’t been a validation run yet or the payload wasn’t
+    /// retained because there are no exceptions files configured, nothing
+    /// happens.
+    pub fn update_exceptions(&self, exceptions: &LocalExceptions) -> bool {
+        let (payload, metrics) = {
+            let read = self.read();
+            match (read.validated.clone(), read.metrics.clone()) {
+                (Some(payload), Some(metrics)) => (payload, metrics),
+                _ => return false,
+            }
+        };
+        let mut metrics = metrics.without_payload();
+        let snapshot = payload.to_snapshot(exceptions, &mut metrics);
+        let res = self.apply_snapshot(snapshot, exceptions, metrics);
+        self.write().update_created(Utc::now());
+        res
+    }
+
+    /// Sets whether the local exceptions are stale.
+    pub fn set_exceptions_stale(&self, stale: bool) {
+        self.write().exceptions_stale = stale
+    }
+
+    /// Sets whether the last update run exceeded its maximum duration.
+    pub fn set_last_update_timed_out(&self, timed_out: bool) {
+        self.write().last_update_timed_out = timed_out
+    }
+
+    /// Sets the clock skew detected during the last update run.
+    pub fn set_clock_skew(&self, skew: Option<i64>) {
+        self.write().clock_skew = skew
+    }
+
+    /// Sets the output files that failed to be written.
+    pub fn set_failed_output_files(&self, failed: Vec<PathBuf>) {
+        self.write().failed_output_files = failed
+    }
+
+    /// Sets the metrics of the publish targets.
+    pub fn set_publish_metrics(&self, metrics: Vec<PublishMetrics>) {
+        self.write().publish_metrics = metrics
+    }
+
+    /// Sets the metrics of the archive integrity check.
+    pub fn set_integrity_metrics(&self, metrics: IntegrityMetrics) {
+        self.write().integrity_metrics = metrics
+    }
 
+    /// Updates the history with a new snapshot.
+    ///
+    /// Returns whether a new version has been added.
+    fn apply_snapshot(
+        &self,
+        snapshot: PayloadSnapshot,
+        exceptions: &LocalExceptions,
+        mut metrics: Metrics,
+    ) -> bool {
         let (current, serial) = {
             let read = self.read();
             (read.current(), read.serial())
@@ -71,8 +265,13 @@ impl SharedHistory {
         let delta = current.as_ref().and_then(|current| {
             PayloadDelta::construct(current, &snapshot, serial)
         });
+        if let (Some(current), Some(_)) = (current.as_ref(), delta.as_ref()) {
+            metrics.collect_churn(origin_changes(current, &snapshot));
+        }
 
         let mut history = self.write();
+        history.exceptions_digest = exceptions.info_digest();
+        Arc::make_mut(&mut history.churn_totals).update(&metrics);
         history.metrics = Some(metrics.into());
         let res = if let Some(delta) = delta {
             // Data has changed.
@@ -95,6 +294,10 @@ impl SharedHistory {
         // Update the snapshot. The refresh time and object information may
         // have changed.
         history.current = Some(snapshot.into());
+        drop(history);
+        if res {
+            self.persist();
+        }
         res
     }
 
@@ -103,11 +306,24 @@ impl SharedHistory {
         self.write().last_update_start = Utc::now();
     }
 
+    /// Marks the current data set as produced from stored data only.
+    ///
+    /// This is used for the data set created from the store before the
+    /// first validation run of a server has finished. The mark is cleared
+    /// by the next update cycle that completes.
+    pub fn mark_stored_data(&self) {
+        let mut locked = self.write();
+        locked.stored_data = true;
+        locked.update_created(Utc::now());
+    }
+
     /// Marks the end of an update cycle.
     pub fn mark_update_done(&self) {
         let mut locked = self.write();
         let now = Utc::now();
+        locked.stored_data = false;
         locked.last_update_done = Some(now);
+        locked.last_update_failed = false;
         locked.last_update_duration = Some(
             now.signed_duration_since(locked.last_update_start)
                 .to_std().unwrap_or_else(|_| Duration::from_secs(0))
@@ -121,21 +337,36 @@ impl SharedHistory {
                 locked.next_update_start = refresh;
             }
         }
-        locked.created = {
-            if let Some(created) = locked.created {
-                // Since we increase the time, the created time may
-                // actually have moved into the future.
-                if now.timestamp() <= created.timestamp() {
-                    Some(created + chrono::Duration::try_seconds(1).unwrap())
-                }
-                else {
-                    Some(now)
-                }
-            }
-            else {
-                Some(now)
-            }
-        };
+        locked.update_created(now);
+    }
+
+    /// Marks that an update cycle has failed.
+    pub fn mark_update_failed(&self) {
+        self.write().last_update_failed = true;
+        self.warn_if_expired();
+    }
+
+    /// Marks the end of an update cycle that produced an incomplete result.
+    ///
+    /// The current data set is kept and the next update cycle is scheduled
+    /// after the regular refresh interval.
+    pub fn mark_update_incomplete(&self) {
+        let mut locked = self.write();
+        locked.last_update_failed = true;
+        locked.next_update_start = SystemTime::now() + locked.refresh;
+        drop(locked);
+        self.warn_if_expired();
+    }
+
+    /// Logs a warning if the data set is too old to be served via RTR.
+    fn warn_if_expired(&self) {
+        if self.read().data_expired() {
+            warn!(
+                "The data set is older than rtr-max-data-age. \
+                 RTR clients receive 'No Data Available' until a \
+                 validation run succeeds."
+            );
+        }
     }
 }
 
@@ -147,7 +378,8 @@ impl PayloadSource for SharedHistory {
     type Diff = DeltaArcIter;
 
     fn ready(&self) -> bool {
-        self.read().is_active()
+        let read = self.read();
+        read.is_active() && !read.data_expired()
     }
 
     fn notify(&self) -> State {
@@ -205,6 +437,39 @@ pub struct PayloadHistory {
     /// The current metrics.
     metrics: Option<Arc<Metrics>>,
 
+    /// The RRDP durations accumulated over all runs.
+    rrdp_durations: Arc<RrdpDurationHistograms>,
+
+    /// The outcomes of RRDP updates accumulated over all runs.
+    rrdp_updates: Arc<RrdpUpdateTotals>,
+
+    /// The route origin churn accumulated over all runs.
+    churn_totals: Arc<ChurnTotals>,
+
+    /// The digest of the information of the current local exceptions.
+    ///
+    /// Since this information isn’t part of the payload, changes to it do
+    /// not result in a new serial number.
+    exceptions_digest: u64,
+
+    /// Are the current local exceptions stale?
+    ///
+    /// This is the case if loading changed exceptions files failed and the
+    /// previous version is used instead.
+    exceptions_stale: bool,
+
+    /// Should the payload of the last validation run be kept?
+    ///
+    /// We only need this if there are local exceptions that can change or
+    /// once the payload of a single TAL has been asked to be refreshed.
+    retain_payload: bool,
+
+    /// The payload of the last validation run.
+    ///
+    /// This is used to apply changed local exceptions without having to
+    /// wait for the next validation run.
+    validated: Option<Arc<ValidatedPayload>>,
+
     /// The session ID.
     session: u64,
 
@@ -226,6 +491,15 @@ pub struct PayloadHistory {
     /// The duration of the last update run.
     last_update_duration: Option<Duration>,
 
+    /// Did the last update run fail?
+    last_update_failed: bool,
+
+    /// Was the last update run aborted for exceeding its maximum duration?
+    last_update_timed_out: bool,
+
+    /// The offset of the local clock detected during the last update run.
+    clock_skew: Option<i64>,
+
     /// The instant when we are scheduled to start the next update.
     next_update_start: SystemTime,
 
@@ -239,6 +513,38 @@ pub struct PayloadHistory {
 
     /// Default RTR timing.
     timing: Timing,
+
+    /// The maximum age of the data set to still be considered healthy.
+    health_max_age: Duration,
+
+    /// The maximum age of the data set to still be served via RTR.
+    rtr_max_data_age: Option<Duration>,
+
+    /// The time the current data set was last produced by validation.
+    ///
+    /// Unlike `created`, this is updated as soon as the new data set is
+    /// accepted, i.e., before notifications are sent out.
+    data_refreshed: Option<DateTime<Utc>>,
+
+    /// The guard against suspiciously small data sets.
+    vrp_guard: VrpGuard,
+
+    /// Was the current data set produced from stored data only?
+    stored_data: bool,
+
+    /// The output files that failed to be written after the last run.
+    failed_output_files: Vec<PathBuf>,
+
+    /// The metrics of the publish targets.
+    publish_metrics: Vec<PublishMetrics>,
+
+    /// The metrics of the archive integrity check.
+    integrity_metrics: IntegrityMetrics,
+
+    /// The file to keep the history in across restarts.
+    ///
+    /// If this is `None`, the history is not kept.
+    history_file: Option<PathBuf>,
 }
 
 impl PayloadHistory {
@@ -248,6 +554,16 @@ impl PayloadHistory {
             current: None,
             deltas: VecDeque::with_capacity(config.history_size),
             metrics: None,
+            rrdp_durations: Default::default(),
+            rrdp_updates: Default::default(),
+            churn_totals: Default::default(),
+            exceptions_digest: 0,
+            exceptions_stale: false,
+            retain_payload: {
+                !config.exceptions.is_empty()
+                || !config.exceptions_inline.is_empty()
+            },
+            validated: None,
             session: {
                 SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH).unwrap()
@@ -259,6 +575,9 @@ impl PayloadHistory {
             last_update_start: Utc::now(),
             last_update_done: None,
             last_update_duration: None,
+            last_update_failed: false,
+            last_update_timed_out: false,
+            clock_skew: None,
             next_update_start: SystemTime::now() + config.refresh,
             created: None,
             timing: Timing {
@@ -266,7 +585,164 @@ impl PayloadHistory {
                 retry: config.retry.as_secs() as u32,
                 expire: config.expire.as_secs() as u32,
             },
+            health_max_age: {
+                config.health_max_age.unwrap_or(config.refresh * 2)
+            },
+            rtr_max_data_age: config.rtr_max_data_age,
+            data_refreshed: None,
+            vrp_guard: VrpGuard::from_config(config),
+            stored_data: false,
+            failed_output_files: Vec::new(),
+            publish_metrics: Vec::new(),
+            integrity_metrics: IntegrityMetrics::default(),
+            history_file: if config.disable_history_file {
+                None
+            }
+            else {
+                Some(config.cache_dir.join("history.bin"))
+            },
+        }
+    }
+
+    /// The current version of the history file format.
+    const FILE_VERSION: u8 = 1;
+
+    /// Loads the history from the history file.
+    ///
+    /// The restored data set is marked as stored data until the first
+    /// validation run has finished. If it is older than the expire
+    /// interval, routers would have dropped it by now, so it is ignored.
+    ///
+    /// See [`SharedHistory::load`] for details.
+    fn load(&mut self) {
+        let path = match self.history_file.as_ref() {
+            Some(path) => path,
+            None => return
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                warn!(
+                    "Failed to open history file {}: {}. \
+                     Starting a new session.",
+                    path.display(), err
+                );
+                return
+            }
+        };
+        let (session, current, mut deltas) = match Self::parse_file(
+            &mut io::BufReader::new(file)
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                warn!(
+                    "Ignoring history file {}: {}. Starting a new session.",
+                    path.display(), err
+                );
+                return
+            }
+        };
+        let age = Utc::now().signed_duration_since(current.created());
+        if age.to_std().unwrap_or_default().as_secs()
+            > u64::from(self.timing.expire)
+        {
+            info!(
+                "Ignoring history file {}: data is older than the expire \
+                 interval. Starting a new session.",
+                path.display()
+            );
+            return
+        }
+        deltas.truncate(self.keep);
+        info!(
+            "Restored history with serial {} from {}.",
+            deltas.front().map(|delta| delta.serial()).unwrap_or(0.into()),
+            path.display()
+        );
+        self.session = session;
+        self.created = Some(current.created());
+        self.data_refreshed = Some(current.created());
+        self.stored_data = true;
+        self.current = Some(current.into());
+        self.deltas = deltas;
+    }
+
+    /// Reads the content of a history file.
+    ///
+    /// Returns the session, the current snapshot, and the deltas.
+    #[allow(clippy::type_complexity)]
+    fn parse_file(
+        reader: &mut impl io::Read
+    ) -> Result<
+        (u64, PayloadSnapshot, VecDeque<Arc<PayloadDelta>>), io::Error
+    > {
+        let version = u8::parse(reader)?;
+        if version != Self::FILE_VERSION {
+            return Err(io::Error::other(
+                format!("unexpected version {}", version)
+            ))
+        }
+        let session = u64::parse(reader)?;
+        let current = PayloadSnapshot::parse(reader)?;
+        let len = u64::parse(reader)?;
+        let mut deltas = VecDeque::<Arc<PayloadDelta>>::new();
+        for _ in 0..len {
+            let delta = PayloadDelta::parse(reader)?;
+            if let Some(last) = deltas.back() {
+                if delta.serial().add(1) != last.serial() {
+                    return Err(io::Error::other("non-consecutive deltas"))
+                }
+            }
+            deltas.push_back(Arc::new(delta));
+        }
+        Ok((session, current, deltas))
+    }
+
+    /// Writes a history file.
+    ///
+    /// The file is first written to a temporary file next to `path` which
+    /// is then moved into place, so the file is replaced atomically.
+    fn write_file(
+        path: &Path,
+        session: u64,
+        current: &PayloadSnapshot,
+        deltas: &VecDeque<Arc<PayloadDelta>>,
+    ) -> Result<(), io::Error> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        {
+            let mut writer = io::BufWriter::new(file.as_file_mut());
+            Self::FILE_VERSION.compose(&mut writer)?;
+            session.compose(&mut writer)?;
+            current.compose(&mut writer)?;
+            (deltas.len() as u64).compose(&mut writer)?;
+            for delta in deltas {
+                delta.compose(&mut writer)?;
+            }
+            io::Write::flush(&mut writer)?;
         }
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Updates the creation time of the current data set.
+    fn update_created(&mut self, now: DateTime<Utc>) {
+        self.created = {
+            if let Some(created) = self.created {
+                // Since we increase the time, the created time may
+                // actually have moved into the future.
+                if now.timestamp() <= created.timestamp() {
+                    Some(created + chrono::Duration::try_seconds(1).unwrap())
+                }
+                else {
+                    Some(now)
+                }
+            }
+            else {
+                Some(now)
+            }
+        };
     }
 
     /// Pushes a new delta to the history
@@ -402,6 +878,34 @@ impl PayloadHistory {
         self.metrics.clone()
     }
 
+    /// Returns the digest of the information of the local exceptions.
+    pub fn exceptions_digest(&self) -> u64 {
+        self.exceptions_digest
+    }
+
+    /// Returns whether the local exceptions are stale.
+    ///
+    /// This is the case if loading the changed exceptions files failed and
+    /// the previous version is still used.
+    pub fn exceptions_stale(&self) -> bool {
+        self.exceptions_stale
+    }
+
+    /// Returns the RRDP durations accumulated over all runs.
+    pub fn rrdp_durations(&self) -> Arc<RrdpDurationHistograms> {
+        self.rrdp_durations.clone()
+    }
+
+    /// Returns the outcomes of RRDP updates accumulated over all runs.
+    pub fn rrdp_updates(&self) -> Arc<RrdpUpdateTotals> {
+        self.rrdp_updates.clone()
+    }
+
+    /// Returns the route origin churn accumulated over all runs.
+    pub fn churn_totals(&self) -> Arc<ChurnTotals> {
+        self.churn_totals.clone()
+    }
+
     /// Returns the time the last update was started.
     pub fn last_update_start(&self) -> DateTime<Utc> {
         self.last_update_start
@@ -412,11 +916,78 @@ impl PayloadHistory {
         self.last_update_done
     }
 
-    /// Returns the time the last update has concluded.
+    /// Returns the duration of the last update.
     pub fn last_update_duration(&self) -> Option<Duration> {
         self.last_update_duration
     }
 
+    /// Returns whether the last update has failed.
+    pub fn last_update_failed(&self) -> bool {
+        self.last_update_failed
+    }
+
+    /// Returns whether the last update exceeded its maximum duration.
+    pub fn last_update_timed_out(&self) -> bool {
+        self.last_update_timed_out
+    }
+
+    /// Returns the clock skew detected during the last update run.
+    ///
+    /// The value is the local time minus the time of the RRDP servers in
+    /// seconds or `None` if the local clock seems fine.
+    pub fn clock_skew(&self) -> Option<i64> {
+        self.clock_skew
+    }
+
+    /// Returns the maximum age of the data set to be considered healthy.
+    pub fn health_max_age(&self) -> Duration {
+        self.health_max_age
+    }
+
+    /// Returns whether the data set is too old to be served via RTR.
+    ///
+    /// This is only ever the case if a maximum data age was configured
+    /// and the last data set produced by validation is older than that.
+    pub fn data_expired(&self) -> bool {
+        let (max_age, refreshed) = match (
+            self.rtr_max_data_age, self.data_refreshed
+        ) {
+            (Some(max_age), Some(refreshed)) => (max_age, refreshed),
+            _ => return false
+        };
+        Utc::now().signed_duration_since(refreshed).to_std().map(|age| {
+            age > max_age
+        }).unwrap_or(false)
+    }
+
+    /// Returns the guard against suspiciously small data sets.
+    pub fn vrp_guard(&self) -> &VrpGuard {
+        &self.vrp_guard
+    }
+
+    /// Returns whether the current data set was produced from stored data.
+    ///
+    /// This is the case while the first validation run of a server that
+    /// started with stored data is still in progress.
+    pub fn stored_data(&self) -> bool {
+        self.stored_data
+    }
+
+    /// Returns the output files that failed to be written.
+    pub fn failed_output_files(&self) -> &[PathBuf] {
+        &self.failed_output_files
+    }
+
+    /// Returns the metrics of the publish targets.
+    pub fn publish_metrics(&self) -> &[PublishMetrics] {
+        &self.publish_metrics
+    }
+
+    /// Returns the metrics of the archive integrity check.
+    pub fn integrity_metrics(&self) -> &IntegrityMetrics {
+        &self.integrity_metrics
+    }
+
     /// Returns the time the current payload snapshot was created.
     ///
     /// The value returned guarantees that no two snapshots where created
@@ -432,3 +1003,191 @@ impl PayloadHistory {
     }
 }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use rpki::resources::{Asn, Prefix};
+    use rpki::resources::addr::MaxLenPrefix;
+    use rpki::rtr::payload::RouteOrigin;
+    use crate::payload::PayloadInfo;
+    use crate::slurm::ExceptionInfo;
+
+    fn snapshot(asns: &[u32]) -> PayloadSnapshot {
+        let info = PayloadInfo::from(Arc::new(ExceptionInfo {
+            path: None, comment: None
+        }));
+        let prefix = MaxLenPrefix::new(
+            Prefix::from_str("192.0.2.0/24").unwrap(), None
+        ).unwrap();
+        PayloadSnapshot::new(
+            asns.iter().map(|asn| {
+                (RouteOrigin::new(prefix, Asn::from_u32(*asn)), info.clone())
+            }),
+            std::iter::empty(), std::iter::empty(), None
+        )
+    }
+
+    /// Creates a history keeping three deltas for serials 0 to 4.
+    fn history() -> PayloadHistory {
+        let config = Config {
+            history_size: 3,
+            disable_history_file: true,
+            .. Default::default()
+        };
+        let mut history = PayloadHistory::from_config(&config);
+        let sets: [&[u32]; 5] = [
+            &[1], &[1, 2], &[2, 3], &[3, 4], &[4, 5]
+        ];
+        let mut current = snapshot(sets[0]);
+        for (serial, set) in sets.iter().enumerate().skip(1) {
+            let new = snapshot(set);
+            history.push_delta(
+                PayloadDelta::construct(
+                    &current, &new, Serial::from(serial as u32 - 1)
+                ).unwrap()
+            );
+            current = new;
+        }
+        history.current = Some(current.into());
+        history
+    }
+
+    #[test]
+    fn delta_since() {
+        let history = history();
+        assert_eq!(history.serial(), Serial::from(4));
+
+        // Same serial: empty delta.
+        let delta = history.delta_since(Serial::from(4)).unwrap();
+        assert_eq!(delta.serial(), Serial::from(4));
+        assert_eq!(delta.announce_len(), 0);
+        assert_eq!(delta.withdraw_len(), 0);
+
+        // One behind: the last delta.
+        let delta = history.delta_since(Serial::from(3)).unwrap();
+        assert_eq!(delta.serial(), Serial::from(4));
+        assert_eq!(delta.announce_len(), 1);
+        assert_eq!(delta.withdraw_len(), 1);
+
+        // Two behind: the combined delta.
+        let delta = history.delta_since(Serial::from(2)).unwrap();
+        assert_eq!(delta.serial(), Serial::from(4));
+        assert_eq!(delta.announce_len(), 2);
+        assert_eq!(delta.withdraw_len(), 2);
+
+        // Serial too old.
+        assert!(history.delta_since(Serial::from(1)).is_none());
+        assert!(history.delta_since(Serial::from(0)).is_none());
+
+        // Serial from the future.
+        assert!(history.delta_since(Serial::from(5)).is_none());
+    }
+
+    #[test]
+    fn history_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = history();
+        history.history_file = Some(dir.path().join("history.bin"));
+        let shared = SharedHistory(Arc::new(RwLock::new(history.clone())));
+        shared.persist();
+
+        let mut loaded = PayloadHistory::from_config(&Config::default());
+        loaded.history_file = history.history_file.clone();
+        loaded.load();
+        assert_eq!(loaded.session(), history.session());
+        assert_eq!(loaded.serial(), Serial::from(4));
+        assert!(loaded.stored_data());
+        assert_eq!(
+            loaded.current().unwrap().origin_count(),
+            history.current().unwrap().origin_count()
+        );
+        let delta = loaded.delta_since(Serial::from(2)).unwrap();
+        assert_eq!(delta.serial(), Serial::from(4));
+        assert_eq!(delta.announce_len(), 2);
+        assert_eq!(delta.withdraw_len(), 2);
+
+        // A file with a different version is ignored.
+        fs::write(
+            loaded.history_file.as_ref().unwrap(), b"\xffgarbage"
+        ).unwrap();
+        let session = PayloadHistory::from_config(&Config::default());
+        let mut ignored = session.clone();
+        ignored.history_file = history.history_file.clone();
+        ignored.load();
+        assert_eq!(ignored.session(), session.session());
+        assert!(!ignored.is_active());
+
+        // A file older than the expire interval is ignored.
+        history.current = history.current.take().map(|current| {
+            Arc::new(
+                PayloadSnapshot::clone(&current).with_created(
+                    Utc::now() - chrono::Duration::try_hours(3).unwrap()
+                )
+            )
+        });
+        let shared = SharedHistory(Arc::new(RwLock::new(history.clone())));
+        shared.persist();
+        let mut ignored = session.clone();
+        ignored.history_file = history.history_file.clone();
+        ignored.load();
+        assert_eq!(ignored.session(), session.session());
+        assert!(!ignored.is_active());
+    }
+
+    #[test]
+    fn data_expired() {
+        let mut history = history();
+        let shared = SharedHistory(Arc::new(RwLock::new(history.clone())));
+        assert!(shared.ready());
+
+        history.rtr_max_data_age = Some(Duration::from_secs(3600));
+        history.data_refreshed = Some(
+            Utc::now() - chrono::Duration::try_hours(2).unwrap()
+        );
+        assert!(history.data_expired());
+        let shared = SharedHistory(Arc::new(RwLock::new(history)));
+        assert!(!shared.ready());
+
+        // A new data set makes it ready again.
+        shared.update(
+            ValidationReport::new(&Config::default()),
+            &LocalExceptions::empty(), Metrics::new(),
+        );
+        assert!(!shared.read().data_expired());
+        assert!(shared.ready());
+    }
+
+    #[test]
+    fn origin_churn() {
+        let shared = SharedHistory(Arc::new(RwLock::new(history())));
+        let exceptions = LocalExceptions::empty();
+
+        // Current set is [4, 5].
+        assert!(shared.apply_snapshot(
+            snapshot(&[5, 6, 7]), &exceptions, Metrics::new()
+        ));
+        let churn = shared.read().metrics().unwrap().churn;
+        assert_eq!(churn.added, 2);
+        assert_eq!(churn.removed, 1);
+
+        // No change: churn is zero but present.
+        assert!(!shared.apply_snapshot(
+            snapshot(&[5, 6, 7]), &exceptions, Metrics::new()
+        ));
+        let churn = shared.read().metrics().unwrap().churn;
+        assert_eq!(churn.total(), 0);
+
+        let totals = shared.read().churn_totals();
+        assert_eq!(totals.all().added, 2);
+        assert_eq!(totals.all().removed, 1);
+        let recent: Vec<_> = totals.recent().map(|item| item.1).collect();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].total(), 0);
+        assert_eq!(recent[1].total(), 3);
+    }
+}
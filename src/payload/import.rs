@@ -0,0 +1,302 @@
+//! Importing payload from the output of a relying party.
+//!
+//! This is a private module. Its public items are re-exported by the parent.
+
+use std::{io, str};
+use std::str::FromStr;
+use std::sync::Arc;
+use bytes::Bytes;
+use rpki::resources::{Asn, MaxLenPrefix, Prefix};
+use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
+use rpki::rtr::pdu::{ProviderAsns, RouterKeyInfo};
+use rpki::util::base64;
+use serde::Deserialize;
+use super::info::{InfoTable, PayloadInfo, PublishInfo};
+use super::snapshot::PayloadSnapshot;
+
+
+//------------ import_payload ------------------------------------------------
+
+/// Creates a snapshot from the output of a relying party.
+///
+/// The data can either be in the _csv_ or _compat_ CSV output formats,
+/// which only contain route origins, or in the _json_ output format. The
+/// latter is detected by its leading opening brace.
+///
+/// Duplicate items are only included once, with the information of the
+/// first one encountered.
+pub fn import_payload(data: &[u8]) -> Result<PayloadSnapshot, io::Error> {
+    let mut importer = Importer::default();
+    if data.iter().find(|ch| !ch.is_ascii_whitespace()) == Some(&b'{') {
+        importer.json(data)?;
+    }
+    else {
+        importer.csv(data)?;
+    }
+    Ok(importer.into_snapshot())
+}
+
+
+//------------ Importer ------------------------------------------------------
+
+/// Collects the imported payload.
+#[derive(Default)]
+struct Importer {
+    /// The route origins.
+    origins: Vec<(RouteOrigin, PayloadInfo)>,
+
+    /// The router keys.
+    router_keys: Vec<(RouterKey, PayloadInfo)>,
+
+    /// The ASPAs.
+    aspas: Vec<(Aspa, PayloadInfo)>,
+
+    /// The table for sharing trust anchor information.
+    table: InfoTable,
+}
+
+impl Importer {
+    /// Imports route origins from CSV data.
+    fn csv(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let data = str::from_utf8(data).map_err(|_| {
+            import_error(format_args!("data is not valid UTF-8"))
+        })?;
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue
+            }
+            let fields: Vec<_> = line.split(',').map(|field| {
+                field.trim().trim_matches('"')
+            }).collect();
+            if line_no == 0 && fields.first() == Some(&"ASN") {
+                continue
+            }
+            let [asn, prefix, max_len, ta] = fields[..] else {
+                return Err(import_error(format_args!(
+                    "line {}: expected four fields", line_no + 1
+                )))
+            };
+            let origin = Self::origin(asn, prefix, max_len).map_err(|err| {
+                import_error(format_args!("line {}: {}", line_no + 1, err))
+            })?;
+            let info = self.info(ta);
+            self.origins.push((origin, info));
+        }
+        Ok(())
+    }
+
+    /// Imports payload from JSON data.
+    fn json(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let json: JsonPayload = serde_json::from_slice(data).map_err(|err| {
+            import_error(format_args!("{}", err))
+        })?;
+        for item in json.roas {
+            let origin = Self::origin(
+                &item.asn, &item.prefix, &item.max_length.to_string()
+            ).map_err(|err| import_error(format_args!("{}", err)))?;
+            let info = self.info(&item.ta);
+            self.origins.push((origin, info));
+        }
+        for item in json.router_keys {
+            let key = Self::router_key(&item).map_err(|err| {
+                import_error(format_args!("{}", err))
+            })?;
+            let info = self.info(&item.ta);
+            self.router_keys.push((key, info));
+        }
+        for item in json.aspas {
+            let aspa = Self::aspa(&item).map_err(|err| {
+                import_error(format_args!("{}", err))
+            })?;
+            let info = self.info(&item.ta);
+            self.aspas.push((aspa, info));
+        }
+        Ok(())
+    }
+
+    /// Creates a route origin from its textual components.
+    fn origin(
+        asn: &str, prefix: &str, max_len: &str,
+    ) -> Result<RouteOrigin, String> {
+        let asn = Asn::from_str(asn).map_err(|_| {
+            format!("invalid ASN '{}'", asn)
+        })?;
+        let prefix = Prefix::from_str(prefix).map_err(|_| {
+            format!("invalid prefix '{}'", prefix)
+        })?;
+        let max_len = u8::from_str(max_len).map_err(|_| {
+            format!("invalid max length '{}'", max_len)
+        })?;
+        let prefix = MaxLenPrefix::new(prefix, Some(max_len)).map_err(|_| {
+            format!("invalid max length '{}'", max_len)
+        })?;
+        Ok(RouteOrigin::new(prefix, asn))
+    }
+
+    /// Creates a router key from its JSON representation.
+    fn router_key(item: &JsonRouterKey) -> Result<RouterKey, String> {
+        let asn = Asn::from_str(&item.asn).map_err(|_| {
+            format!("invalid ASN '{}'", item.asn)
+        })?;
+        let key_identifier = item.ski.parse().map_err(|_| {
+            format!("invalid key identifier '{}'", item.ski)
+        })?;
+        let key_info = base64::Slurm.decode(&item.key).ok().and_then(|key| {
+            RouterKeyInfo::new(Bytes::from(key)).ok()
+        }).ok_or_else(|| {
+            format!("invalid router key '{}'", item.key)
+        })?;
+        Ok(RouterKey::new(key_identifier, asn, key_info))
+    }
+
+    /// Creates an ASPA from its JSON representation.
+    fn aspa(item: &JsonAspa) -> Result<Aspa, String> {
+        let customer = Asn::from_str(&item.customer).map_err(|_| {
+            format!("invalid ASN '{}'", item.customer)
+        })?;
+        let mut providers = item.providers.iter().map(|asn| {
+            Asn::from_str(asn).map_err(|_| format!("invalid ASN '{}'", asn))
+        }).collect::<Result<Vec<_>, _>>()?;
+        providers.sort_unstable();
+        providers.dedup();
+        let providers = ProviderAsns::try_from_iter(providers).map_err(|_| {
+            format!("too many providers for customer {}", customer)
+        })?;
+        Ok(Aspa::new(customer, providers))
+    }
+
+    /// Returns the payload info for the given trust anchor name.
+    fn info(&mut self, ta: &str) -> PayloadInfo {
+        PayloadInfo::from(Arc::new(
+            PublishInfo::imported(self.table.tal(ta.into()))
+        ))
+    }
+
+    /// Converts the importer into a snapshot.
+    fn into_snapshot(self) -> PayloadSnapshot {
+        PayloadSnapshot::new(
+            dedup(self.origins).into_iter(),
+            dedup(self.router_keys).into_iter(),
+            dedup(self.aspas).into_iter(),
+            None,
+        )
+    }
+}
+
+
+//------------ JSON Types ----------------------------------------------------
+
+/// The payload in the _json_ output format.
+#[derive(Deserialize)]
+struct JsonPayload {
+    /// The route origins.
+    roas: Vec<JsonRoa>,
+
+    /// The router keys.
+    ///
+    /// These are missing in the output of older versions.
+    #[serde(rename = "routerKeys", default)]
+    router_keys: Vec<JsonRouterKey>,
+
+    /// The ASPAs.
+    ///
+    /// These are missing in the output of older versions.
+    #[serde(default)]
+    aspas: Vec<JsonAspa>,
+}
+
+/// A route origin in the _json_ output format.
+#[derive(Deserialize)]
+struct JsonRoa {
+    asn: String,
+    prefix: String,
+    #[serde(rename = "maxLength")]
+    max_length: u8,
+    ta: String,
+}
+
+/// A router key in the _json_ output format.
+#[derive(Deserialize)]
+struct JsonRouterKey {
+    asn: String,
+    #[serde(rename = "SKI")]
+    ski: String,
+    #[serde(rename = "routerPublicKey")]
+    key: String,
+    ta: String,
+}
+
+/// An ASPA in the _json_ output format.
+#[derive(Deserialize)]
+struct JsonAspa {
+    customer: String,
+    providers: Vec<String>,
+    ta: String,
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Removes duplicate payload, keeping the first item.
+fn dedup<P: Ord>(mut vec: Vec<(P, PayloadInfo)>) -> Vec<(P, PayloadInfo)> {
+    vec.sort_by(|left, right| left.0.cmp(&right.0));
+    vec.dedup_by(|right, left| left.0 == right.0);
+    vec
+}
+
+/// Creates an import error.
+fn import_error(msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn import_ignores_order_and_duplicates() {
+        let csv = import_payload(
+            b"ASN,IP Prefix,Max Length,Trust Anchor\n\
+              AS64496,192.0.2.0/24,24,ripe\n\
+              AS64497,2001:db8::/32,48,apnic\n\
+              AS64496,192.0.2.0/24,24,ripe\n"
+        ).unwrap();
+        let json = import_payload(
+            br#"{
+              "metadata": { "generated": 0 },
+              "roas": [
+                { "asn": "AS64497", "prefix": "2001:db8::/32",
+                  "maxLength": 48, "ta": "apnic" },
+                { "asn": "AS64496", "prefix": "192.0.2.0/24",
+                  "maxLength": 24, "ta": "ripe" }
+              ],
+              "aspas": [
+                { "customer": "AS64496", "providers": ["AS2", "AS1"],
+                  "ta": "ripe" }
+              ]
+            }"#
+        ).unwrap();
+        assert_eq!(csv.origin_count(), 2);
+        assert_eq!(json.origin_count(), 2);
+        assert_eq!(csv.difference(&json).origin_count(), 0);
+        assert_eq!(json.difference(&csv).origin_count(), 0);
+        assert_eq!(json.difference(&csv).aspas().count(), 1);
+        assert_eq!(
+            csv.origins().next().unwrap().1.tal_name(), Some("ripe")
+        );
+
+        let other = import_payload(
+            b"\"ASN\",\"IP Prefix\",\"Max Length\",\"Trust Anchor\"\n\
+              \"AS64496\",\"192.0.2.0/24\",\"25\",\"ripe\"\n"
+        ).unwrap();
+        assert_eq!(csv.difference(&other).origin_count(), 2);
+        assert_eq!(other.difference(&csv).origin_count(), 1);
+
+        assert!(import_payload(b"AS64496,192.0.2.0/24,24\n").is_err());
+        assert!(import_payload(b"AS64496,192.0.2.0/24,16,ripe\n").is_err());
+    }
+}
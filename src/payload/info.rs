@@ -2,12 +2,16 @@
 //!
 //! This is a private module. Its public types are re-exported by the parent.
 
+use std::io;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 use rpki::uri;
 use rpki::repository::cert::{Cert, ResourceCert};
 use rpki::repository::tal::TalInfo;
 use rpki::repository::x509::{Validity, Time};
 use crate::slurm::ExceptionInfo;
+use crate::utils::binio::{Compose, Parse};
 
 //------------ PayloadInfo ---------------------------------------------------
 
@@ -78,6 +82,66 @@ impl PayloadInfo {
             Err(ref info) => Some(info),
         }
     }
+
+    /// Reads the information from an IO reader.
+    ///
+    /// Trust anchor information and URIs are shared between all values
+    /// read with the same `table`.
+    pub fn parse(
+        reader: &mut impl io::Read, table: &mut InfoTable,
+    ) -> Result<Self, io::Error> {
+        let len = u32::parse(reader)?;
+        let mut heads = Vec::new();
+        for _ in 0..len {
+            heads.push(match u8::parse(reader)? {
+                0 => Ok(Arc::new(PublishInfo::parse(reader, table)?)),
+                1 => {
+                    let path = Option::<String>::parse(reader)?;
+                    Err(Arc::new(ExceptionInfo {
+                        path: path.map(|path| {
+                            Arc::from(PathBuf::from(path).as_path())
+                        }),
+                        comment: Parse::parse(reader)?,
+                    }))
+                }
+                _ => {
+                    return Err(io::Error::other("invalid payload info"))
+                }
+            });
+        }
+        let mut res = None;
+        for head in heads.into_iter().rev() {
+            res = Some(PayloadInfo { head, tail: res.map(Box::new) });
+        }
+        res.ok_or_else(|| {
+            io::Error::other("empty payload info")
+        })
+    }
+
+    /// Composes the encoded information.
+    pub fn compose(
+        &self, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        u32::try_from(self.iter().count()).map_err(|_| {
+            io::Error::other("excessive payload info")
+        })?.compose(writer)?;
+        for item in self {
+            match item.head {
+                Ok(ref info) => {
+                    0u8.compose(writer)?;
+                    info.compose(writer)?;
+                }
+                Err(ref info) => {
+                    1u8.compose(writer)?;
+                    info.path.as_ref().map(|path| {
+                        path.to_string_lossy().into_owned()
+                    }).compose(writer)?;
+                    info.comment.compose(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 
@@ -138,6 +202,9 @@ pub struct PublishInfo {
     /// The rsync URI identifying the ROA.
     pub uri: Option<uri::Rsync>,
 
+    /// The rsync URI of the CA certificate that issued the object.
+    pub ca_uri: Option<uri::Rsync>,
+
     /// The validity of the ROA itself.
     pub roa_validity: Validity,
 
@@ -150,37 +217,212 @@ pub struct PublishInfo {
 
 impl PublishInfo {
     /// Creates a new origin info from the EE certificate of a ROA.
+    ///
+    /// The URI of the issuing CA is shared via `table`.
     pub fn signed_object(
         cert: &ResourceCert,
         ca_validity: Validity,
         point_stale: Time,
+        table: &mut InfoTable,
     ) -> Self {
         PublishInfo {
             tal: cert.tal().clone(),
             uri: cert.signed_object().cloned().map(|mut uri| {
                 uri.unshare(); uri
             }),
+            ca_uri: cert.ca_issuer().map(|uri| table.uri(uri)),
             roa_validity: cert.validity(),
             chain_validity: cert.validity().trim(ca_validity),
             point_stale,
         }
     }
 
+    /// Creates a new origin info from a router certificate.
+    ///
+    /// The URI of the issuing CA is shared via `table`.
     pub fn router_cert(
         cert: &Cert,
         uri: &uri::Rsync,
         tal: Arc<TalInfo>,
         ca_validity: Validity,
         point_stale: Time,
+        table: &mut InfoTable,
     ) -> Self {
         PublishInfo {
             tal,
             uri: Some(uri.clone()),
+            ca_uri: cert.ca_issuer().map(|uri| table.uri(uri)),
             roa_validity: cert.validity(),
             chain_validity: cert.validity().trim(ca_validity),
             point_stale,
         }
     }
 
+    /// Creates a new origin info for payload imported from elsewhere.
+    ///
+    /// Only the TAL is known for such payload. Since there are no objects,
+    /// all times are set to the current time.
+    pub fn imported(tal: Arc<TalInfo>) -> Self {
+        let now = Time::now();
+        PublishInfo {
+            tal,
+            uri: None,
+            ca_uri: None,
+            roa_validity: Validity::new(now, now),
+            chain_validity: Validity::new(now, now),
+            point_stale: now,
+        }
+    }
+
+    /// Reads the information from an IO reader.
+    fn parse(
+        reader: &mut impl io::Read, table: &mut InfoTable,
+    ) -> Result<Self, io::Error> {
+        let tal = table.tal(String::parse(reader)?);
+        let uri = Option::<uri::Rsync>::parse(reader)?;
+        let ca_uri = Option::<uri::Rsync>::parse(reader)?;
+        Ok(PublishInfo {
+            tal,
+            uri: uri.map(|uri| table.uri(&uri)),
+            ca_uri: ca_uri.map(|uri| table.uri(&uri)),
+            roa_validity: Parse::parse(reader)?,
+            chain_validity: Parse::parse(reader)?,
+            point_stale: Parse::parse(reader)?,
+        })
+    }
+
+    /// Composes the encoded information.
+    fn compose(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
+        self.tal.name().to_string().compose(writer)?;
+        self.uri.compose(writer)?;
+        self.ca_uri.compose(writer)?;
+        self.roa_validity.compose(writer)?;
+        self.chain_validity.compose(writer)?;
+        self.point_stale.compose(writer)?;
+        Ok(())
+    }
+}
+
+
+
+//------------ InfoTable -----------------------------------------------------
+
+/// A table of provenance data shared between payload items.
+///
+/// Many payload items refer to the same trust anchor and many objects are
+/// issued by the same CA. Rather than keeping a separate copy of the
+/// trust anchor information and URIs for each of them, the table hands out
+/// clones of a single copy which only share the underlying memory.
+///
+/// The table is only needed while creating payload information. Values
+/// handed out by it stay valid after it has been dropped.
+#[derive(Clone, Debug, Default)]
+pub struct InfoTable {
+    /// The trust anchor information seen so far.
+    tals: Vec<Arc<TalInfo>>,
+
+    /// The URIs seen so far.
+    uris: HashSet<uri::Rsync>,
+}
+
+impl InfoTable {
+    /// Creates a new, empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared trust anchor information for the given name.
+    pub fn tal(&mut self, name: String) -> Arc<TalInfo> {
+        match self.tals.iter().find(|tal| tal.name() == name) {
+            Some(tal) => tal.clone(),
+            None => {
+                let tal = TalInfo::from_name(name).into_arc();
+                self.tals.push(tal.clone());
+                tal
+            }
+        }
+    }
+
+    /// Returns a shared copy of the given URI.
+    ///
+    /// If the URI hasn’t been seen before, it is moved into its own
+    /// memory so it doesn’t keep the buffer it was taken from alive.
+    pub fn uri(&mut self, uri: &uri::Rsync) -> uri::Rsync {
+        if let Some(uri) = self.uris.get(uri) {
+            return uri.clone()
+        }
+        let mut uri = uri.clone();
+        uri.unshare();
+        self.uris.insert(uri.clone());
+        uri
+    }
 }
 
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn publish_info(
+        tal: Arc<TalInfo>, uri: &str, ca_uri: &str
+    ) -> PublishInfo {
+        PublishInfo {
+            tal,
+            uri: Some(uri::Rsync::from_str(uri).unwrap()),
+            ca_uri: Some(uri::Rsync::from_str(ca_uri).unwrap()),
+            roa_validity: Validity::new(
+                Time::utc(2024, 1, 1, 0, 0, 0), Time::utc(2025, 1, 1, 0, 0, 0)
+            ),
+            chain_validity: Validity::new(
+                Time::utc(2024, 1, 1, 0, 0, 0), Time::utc(2025, 1, 1, 0, 0, 0)
+            ),
+            point_stale: Time::utc(2024, 6, 1, 0, 0, 0),
+        }
+    }
+
+    #[test]
+    fn parse_shares_provenance() {
+        let tal = TalInfo::from_name("test".into()).into_arc();
+        let ca = "rsync://example.com/repo/ca.cer";
+        let mut data = Vec::new();
+        for i in 0..10 {
+            PayloadInfo::from(Arc::new(publish_info(
+                tal.clone(),
+                &format!("rsync://example.com/repo/{}.roa", i % 2),
+                ca,
+            ))).compose(&mut data).unwrap();
+        }
+
+        let mut table = InfoTable::new();
+        let mut reader = data.as_slice();
+        let infos = (0..10).map(|_| {
+            PayloadInfo::parse(&mut reader, &mut table).unwrap()
+        }).collect::<Vec<_>>();
+        assert!(reader.is_empty());
+
+        // All items share the same copies of the trust anchor and URIs,
+        // so the table only keeps three distinct URIs around.
+        assert_eq!(table.tals.len(), 1);
+        assert_eq!(table.uris.len(), 3);
+        let first = infos[0].publish_info().unwrap();
+        for (i, info) in infos.iter().enumerate() {
+            let info = info.publish_info().unwrap();
+            assert!(Arc::ptr_eq(&info.tal, &first.tal));
+            assert_eq!(
+                info.ca_uri.as_ref().unwrap().as_str().as_ptr(),
+                first.ca_uri.as_ref().unwrap().as_str().as_ptr(),
+            );
+            assert_eq!(
+                info.uri.as_ref().unwrap().as_str().as_ptr(),
+                infos[i % 2].uri().unwrap().as_str().as_ptr(),
+            );
+            assert_eq!(
+                info.uri.as_ref().unwrap().as_str(),
+                format!("rsync://example.com/repo/{}.roa", i % 2)
+            );
+        }
+    }
+}
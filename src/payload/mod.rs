@@ -6,19 +6,33 @@
 //! between consecutive such snapshots available as [`PayloadDelta`]. A
 //! collection of the two plus additional information is the
 //! [`PayloadHistory`] or, wrapped in an arc, [`SharedHistory`].
+//!
+//! If you simply want to perform validation runs and get at the resulting
+//! snapshots, [`ValidationRunner`] or the function [`run_validation`]
+//! provide a shortcut.
 
-pub use self::delta::{DeltaArcIter, PayloadDelta};
+pub use self::archive::{SnapshotArchive, SnapshotArchiver};
+pub use self::delta::{DeltaArcIter, PayloadDelta, origin_changes};
+pub use self::guard::VrpGuard;
 pub use self::history::{PayloadHistory, SharedHistory};
-pub use self::info::PayloadInfo;
+pub use self::import::import_payload;
+pub use self::info::{InfoTable, PayloadInfo, PublishInfo};
+pub use self::runner::{ValidationRunner, run_validation};
 pub use self::snapshot::{
     PayloadSnapshot, SnapshotArcAspaIter, SnapshotArcIter,
     SnapshotArcOriginIter, SnapshotArcRouterKeyIter,
 };
-pub use self::validation::ValidationReport;
+pub use self::validation::{
+    PayloadConsumer, ValidatedPayload, ValidationReport,
+};
 
+mod archive;
 mod delta;
+mod guard;
 mod history;
+mod import;
 mod info;
+mod runner;
 mod validation;
 mod snapshot;
 
@@ -0,0 +1,190 @@
+//! Running validation to produce payload.
+//!
+//! This is a private module. Its public types are re-exported by the parent.
+//!
+//! The module provides [`ValidationRunner`], a convenient way to go from a
+//! [`Config`] to a [`PayloadSnapshot`] without having to assemble the
+//! engine, report, and local exceptions manually. It is intended for using
+//! Routinator as a library.
+
+use std::sync::Arc;
+use log::{error, info};
+use crate::config::Config;
+use crate::engine::Engine;
+use crate::error::Failed;
+use crate::metrics::Metrics;
+use crate::slurm::LocalExceptions;
+use super::snapshot::PayloadSnapshot;
+use super::validation::ValidationReport;
+
+
+//------------ ValidationRunner ----------------------------------------------
+
+/// Performs validation runs and produces payload.
+///
+/// A runner is created from a configuration via [`new`][Self::new] which
+/// sets up the collector and store and loads the TALs. It can then be used
+/// to perform any number of validation runs, reusing the collector’s local
+/// cache between them.
+///
+/// The runner neither daemonizes nor touches logging. It does, however,
+/// spawn the collector’s worker threads when created, so if your
+/// application forks, you need to create the runner after that.
+///
+/// Each validation run is performed by either [`process`][Self::process],
+/// which returns the final payload snapshot with local exceptions applied,
+/// or [`process_report`][Self::process_report], which returns the raw
+/// validation report for further processing.
+#[derive(Debug)]
+pub struct ValidationRunner {
+    /// The engine performing the actual validation.
+    engine: Engine,
+
+    /// The configuration to use.
+    config: Config,
+}
+
+impl ValidationRunner {
+    /// Creates a new runner from the configuration.
+    ///
+    /// If `update` is `false`, the runner will never try to update the
+    /// local cache and only use data that is already present.
+    pub fn new(config: &Config, update: bool) -> Result<Self, Failed> {
+        let mut engine = Engine::new(config, update)?;
+        engine.ignite()?;
+        Ok(ValidationRunner {
+            engine,
+            config: config.clone(),
+        })
+    }
+
+    /// Returns a reference to the configuration used by the runner.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Reloads the set of TALs.
+    ///
+    /// See [`Engine::reload_tals`] for details.
+    pub fn reload_tals(&mut self) -> Result<(), Failed> {
+        self.engine.reload_tals()
+    }
+
+    /// Returns an iterator over the labels of the currently loaded TALs.
+    pub fn tal_labels(&self) -> impl Iterator<Item = &str> + '_ {
+        self.engine.tal_labels()
+    }
+
+    /// Performs a validation run and returns the validation report.
+    ///
+    /// If the run fails in a way that makes it worthwhile to try again,
+    /// the local data is sanitized and the run is restarted once.
+    pub fn process_report(
+        &self
+    ) -> Result<(ValidationReport, Metrics), Failed> {
+        let mut once = false;
+        loop {
+            match ValidationReport::process(&self.engine, &self.config) {
+                Ok(res) => return Ok(res),
+                Err(err) => {
+                    if err.should_retry() && !once {
+                        self.engine.sanitize()?;
+                        info!("Validation failed but can be retried.");
+                        once = true;
+                        continue
+                    }
+                    if once {
+                        error!("Restarted run failed again. Aborting.");
+                    }
+                    return Err(Failed)
+                }
+            }
+        }
+    }
+
+    /// Performs a validation run and returns the payload snapshot.
+    ///
+    /// The local exceptions given in the configuration are loaded anew
+    /// for each run and applied to the snapshot. They are loaded before the
+    /// run is started, so broken exceptions files are reported right away
+    /// rather than after a possibly lengthy run.
+    pub fn process(
+        &self
+    ) -> Result<(Arc<PayloadSnapshot>, Arc<Metrics>), Failed> {
+        let exceptions = LocalExceptions::load(&self.config, true)?;
+        self.process_with_exceptions(&exceptions)
+    }
+
+    /// Performs a validation run with the given local exceptions.
+    pub fn process_with_exceptions(
+        &self, exceptions: &LocalExceptions,
+    ) -> Result<(Arc<PayloadSnapshot>, Arc<Metrics>), Failed> {
+        let (report, mut metrics) = self.process_report()?;
+        let snapshot = report.into_snapshot(exceptions, &mut metrics);
+        Ok((Arc::new(snapshot), Arc::new(metrics)))
+    }
+}
+
+
+//------------ run_validation ------------------------------------------------
+
+/// Performs a single validation run based on the configuration.
+///
+/// This is a shortcut for creating a [`ValidationRunner`] that updates the
+/// local cache and calling its [`process`][ValidationRunner::process]
+/// method. If you need to perform repeated runs, create and keep a runner
+/// instead.
+pub fn run_validation(
+    config: &Config
+) -> Result<(Arc<PayloadSnapshot>, Arc<Metrics>), Failed> {
+    ValidationRunner::new(config, true)?.process()
+}
+
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn process_without_tals() {
+        let _ = crate::process::Process::init(); // May be inited already.
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_with_paths(
+            Default::default(), dir.path().into()
+        );
+        config.no_rir_tals = true;
+        let runner = ValidationRunner::new(&config, false).unwrap();
+        assert_eq!(runner.tal_labels().count(), 0);
+
+        // Without TALs, only the local assertions make it into the
+        // snapshot.
+        let exceptions = LocalExceptions::from_json(
+            r#"{
+                "slurmVersion": 1,
+                "validationOutputFilters": {
+                    "prefixFilters": [], "bgpsecFilters": []
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [
+                        { "asn": 64496, "prefix": "198.51.100.0/24" }
+                    ],
+                    "bgpsecAssertions": []
+                }
+            }"#,
+            false
+        ).unwrap();
+        let (snapshot, metrics) = runner.process_with_exceptions(
+            &exceptions
+        ).unwrap();
+        assert_eq!(snapshot.origin_count(), 1);
+        assert!(metrics.tals.is_empty());
+
+        // The runner can be used again, this time with the exceptions from
+        // the config of which there are none.
+        let (snapshot, _) = runner.process().unwrap();
+        assert_eq!(snapshot.origin_count(), 0);
+    }
+}
@@ -4,14 +4,20 @@
 
 #![allow(dead_code)]
 
-use std::sync::Arc;
-use chrono::{DateTime, Utc};
+use std::io;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use chrono::{DateTime, TimeZone, Utc};
 use rpki::repository::x509::Time;
+use rpki::resources::addr::Prefix;
 use rpki::rtr::payload::{
-    Aspa, PayloadRef, PayloadType, RouteOrigin, RouterKey
+    Action, Aspa, PayloadRef, PayloadType, RouteOrigin, RouterKey
 };
 use rpki::rtr::server::PayloadSet;
-use super::info::PayloadInfo;
+use crate::config::AddressFamilyFilter;
+use crate::utils::binio::{Compose, Parse};
+use super::delta::PayloadDelta;
+use super::info::{InfoTable, PayloadInfo};
 
 
 //------------ PayloadSnapshot -----------------------------------------------
@@ -34,6 +40,19 @@ pub struct PayloadSnapshot {
 
     /// The time when this snapshot needs to be refreshed at the latest.
     refresh: Option<Time>,
+
+    /// The address families of route origins included in the snapshot.
+    ///
+    /// Route origins for other address families have been dropped during
+    /// validation. This is not kept when storing the snapshot.
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    address_family: AddressFamilyFilter,
+
+    /// An index for looking up route origins by prefix.
+    ///
+    /// This is only built when it is first needed.
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    origin_index: OnceLock<OriginIndex>,
 }
 
 
@@ -46,14 +65,16 @@ impl Default for PayloadSnapshot {
             router_keys: Default::default(),
             aspas: Default::default(),
             created: Utc::now(),
-            refresh: None
+            refresh: None,
+            address_family: AddressFamilyFilter::default(),
+            origin_index: OnceLock::new(),
         }
     }
 }
 
 impl PayloadSnapshot {
     /// Creates a new snapshot from its parts.
-    pub(super) fn new(
+    pub(crate) fn new(
         origins: impl Iterator<Item = (RouteOrigin, PayloadInfo)>,
         router_keys: impl Iterator<Item = (RouterKey, PayloadInfo)>,
         aspas: impl Iterator<Item = (Aspa, PayloadInfo)>,
@@ -65,14 +86,34 @@ impl PayloadSnapshot {
             aspas: PayloadCollection::from_iter(aspas),
             created: Utc::now(),
             refresh,
+            address_family: AddressFamilyFilter::default(),
+            origin_index: OnceLock::new(),
         }
     }
 
+    /// Returns the snapshot with the included address families replaced.
+    pub(crate) fn with_address_family(
+        self, address_family: AddressFamilyFilter
+    ) -> Self {
+        Self { address_family, ..self }
+    }
+
+    /// Returns the snapshot with the creation time replaced.
+    #[cfg(test)]
+    pub(crate) fn with_created(self, created: DateTime<Utc>) -> Self {
+        Self { created, ..self }
+    }
+
     /// Returns when this snapshot was created.
     pub fn created(&self) -> DateTime<Utc> {
         self.created
     }
 
+    /// Returns the address families of route origins in the snapshot.
+    pub fn address_family(&self) -> AddressFamilyFilter {
+        self.address_family
+    }
+
     /// Returns when this snapshot should be refreshed at the latest.
     ///
     /// Returns `None` if there is no known refresh time.
@@ -91,6 +132,11 @@ impl PayloadSnapshot {
         )
     }
 
+    /// Returns the number of route origins.
+    pub fn origin_count(&self) -> usize {
+        self.origins.len()
+    }
+
     /// Returns an iterator over references to route origins.
     pub fn origin_refs(
         &self
@@ -105,6 +151,51 @@ impl PayloadSnapshot {
         self.origins.iter().map(|(origin, info)| (*origin, info))
     }
 
+    /// Returns an iterator over the route origins covering a prefix.
+    ///
+    /// The origins are looked up via an index that is built the first time
+    /// this method is called for the snapshot. Building it is linear in
+    /// the number of route origins, while each lookup afterwards only
+    /// depends on the length of `prefix`. Concurrent callers wait for the
+    /// index to be built.
+    ///
+    /// The origins are returned in the same order as through
+    /// [`origins`][Self::origins], i.e., more specific first.
+    pub fn covering_origins(
+        &self, prefix: Prefix
+    ) -> impl Iterator<Item = (RouteOrigin, &PayloadInfo)> + '_ {
+        self.origin_index.get_or_init(|| {
+            OriginIndex::new(&self.origins)
+        }).covering(&self.origins, prefix).map(|(origin, info)| {
+            (*origin, info)
+        })
+    }
+
+    /// Returns an iterator over the route origins covered by a prefix.
+    ///
+    /// This includes the route origins for `prefix` itself.
+    ///
+    /// Route origins are ordered by prefix with more specific prefixes
+    /// ordered right before the less specific prefixes covering them. The
+    /// route origins covered by `prefix` thus form a consecutive range
+    /// that is found through a binary search.
+    pub fn covered_origins(
+        &self, prefix: Prefix
+    ) -> impl Iterator<Item = (RouteOrigin, &PayloadInfo)> + '_ {
+        let start = self.origins.vec.partition_point(|item| {
+            let other = item.0.prefix.prefix();
+            if other.is_v4() != prefix.is_v4() {
+                other.is_v4()
+            }
+            else {
+                !other.covers(prefix) && other.addr() < prefix.addr()
+            }
+        });
+        self.origins.vec[start..].iter().take_while(move |item| {
+            prefix.covers(item.0.prefix.prefix())
+        }).map(|item| (item.0, &item.1))
+    }
+
     /// Returns an iterator over route origins as payload.
     pub fn origin_payload(
         &self
@@ -140,6 +231,92 @@ impl PayloadSnapshot {
         self.aspas.iter_payload()
     }
 
+    /// Returns the route origins and router keys affected by a delta.
+    ///
+    /// The returned snapshot contains those route origins and router keys
+    /// of `self` for which `delta` contains the given action. For instance,
+    /// if `self` is the new snapshot used when constructing the delta,
+    /// [`Action::Announce`] selects everything that was added.
+    ///
+    /// ASPA are not included since their delta is keyed by the customer
+    /// ASN only.
+    pub fn delta_items(&self, delta: &PayloadDelta, action: Action) -> Self {
+        let origins: Vec<_> = delta.origin_actions().filter_map(|item| {
+            (item.1 == action).then_some(item.0)
+        }).collect();
+        let keys: Vec<_> = delta.router_key_actions().filter_map(|item| {
+            (item.1 == action).then_some(item.0)
+        }).collect();
+        Self {
+            origins: self.origins.iter().filter(|item| {
+                origins.binary_search(item.0).is_ok()
+            }).map(|(origin, info)| (*origin, info.clone())).collect(),
+            router_keys: self.router_keys.iter().filter(|item| {
+                keys.binary_search(&item.0).is_ok()
+            }).map(|(key, info)| (key.clone(), info.clone())).collect(),
+            aspas: Default::default(),
+            created: self.created,
+            refresh: self.refresh,
+            address_family: self.address_family,
+            origin_index: OnceLock::new(),
+        }
+    }
+
+    /// Returns the payload of `self` that is not present in `other`.
+    ///
+    /// Only the payload itself is compared, its information is ignored.
+    /// The information of the returned items is taken from `self`.
+    pub fn difference(&self, other: &Self) -> Self {
+        Self {
+            origins: self.origins.difference(&other.origins),
+            router_keys: self.router_keys.difference(&other.router_keys),
+            aspas: self.aspas.difference(&other.aspas),
+            created: self.created,
+            refresh: self.refresh,
+            address_family: self.address_family,
+            origin_index: OnceLock::new(),
+        }
+    }
+
+    /// Reads a snapshot from an IO reader.
+    pub fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
+        let mut table = InfoTable::new();
+        let created = Utc.timestamp_opt(
+            i64::parse(reader)?, 0
+        ).single().ok_or_else(|| {
+            io::Error::other("invalid timestamp")
+        })?;
+        let refresh = match Option::<i64>::parse(reader)? {
+            Some(ts) => {
+                Some(Utc.timestamp_opt(ts, 0).single().ok_or_else(|| {
+                    io::Error::other("invalid timestamp")
+                })?.into())
+            }
+            None => None
+        };
+        Ok(Self {
+            origins: PayloadCollection::parse(reader, &mut table)?,
+            router_keys: PayloadCollection::parse(reader, &mut table)?,
+            aspas: PayloadCollection::parse(reader, &mut table)?,
+            created,
+            refresh,
+            address_family: AddressFamilyFilter::default(),
+            origin_index: OnceLock::new(),
+        })
+    }
+
+    /// Composes the encoded snapshot.
+    pub fn compose(
+        &self, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        self.created.timestamp().compose(writer)?;
+        self.refresh.map(|time| time.timestamp()).compose(writer)?;
+        self.origins.compose(writer)?;
+        self.router_keys.compose(writer)?;
+        self.aspas.compose(writer)?;
+        Ok(())
+    }
+
     /// Returns an iterator over the payload of a shared snapshot.
     pub fn arc_iter(self: Arc<Self>) -> SnapshotArcIter {
         SnapshotArcIter::new(self)
@@ -221,6 +398,46 @@ impl<P> PayloadCollection<P> {
     where for<'a> &'a P: Into<PayloadRef<'a>> {
         self.vec.iter().map(|item| (&item.0).into())
     }
+
+    /// Returns a collection with the items not present in `other`.
+    fn difference(&self, other: &Self) -> Self
+    where P: Ord + Clone {
+        Self {
+            vec: self.vec.iter().filter(|item| {
+                other.vec.binary_search_by(|probe| {
+                    probe.0.cmp(&item.0)
+                }).is_err()
+            }).cloned().collect()
+        }
+    }
+}
+
+impl<P> PayloadCollection<P> {
+    /// Reads a collection from an IO reader.
+    fn parse<R: io::Read>(
+        reader: &mut R, table: &mut InfoTable,
+    ) -> Result<Self, io::Error>
+    where P: Parse<R> + Ord {
+        let len = u64::parse(reader)?;
+        let mut vec = Vec::new();
+        for _ in 0..len {
+            vec.push((P::parse(reader)?, PayloadInfo::parse(reader, table)?));
+        }
+        Ok(Self::from_vec(vec))
+    }
+
+    /// Composes the encoded collection.
+    fn compose<W: io::Write>(
+        &self, writer: &mut W
+    ) -> Result<(), io::Error>
+    where P: Compose<W> {
+        (self.vec.len() as u64).compose(writer)?;
+        for (payload, info) in &self.vec {
+            payload.compose(writer)?;
+            info.compose(writer)?;
+        }
+        Ok(())
+    }
 }
 
 impl<P: Ord> FromIterator<(P, PayloadInfo)> for PayloadCollection<P> {
@@ -268,6 +485,87 @@ impl<'a> arbitrary::Arbitrary<'a> for PayloadCollection<Aspa> {
 }
 
 
+//------------ OriginIndex ---------------------------------------------------
+
+/// An index of route origins by prefix.
+///
+/// Because route origins are ordered by prefix first, all origins for the
+/// same prefix follow each other in the collection. The index keeps the
+/// position of the first of them for each prefix as well as the prefix
+/// lengths in use. Finding the origins covering a prefix thus needs one
+/// hash lookup for each of those lengths that isn’t longer than the prefix.
+#[derive(Clone, Debug, Default)]
+struct OriginIndex {
+    /// The position of the first route origin for each prefix.
+    start: HashMap<Prefix, usize>,
+
+    /// The IPv4 prefix lengths in use in descending order.
+    v4_lens: Vec<u8>,
+
+    /// The IPv6 prefix lengths in use in descending order.
+    v6_lens: Vec<u8>,
+}
+
+impl OriginIndex {
+    /// Creates the index for the given collection.
+    fn new(origins: &PayloadCollection<RouteOrigin>) -> Self {
+        let mut start = HashMap::new();
+        let mut v4_lens = [false; 33];
+        let mut v6_lens = [false; 129];
+        let mut last = None;
+        for (idx, item) in origins.vec.iter().enumerate() {
+            let prefix = item.0.prefix.prefix();
+            if last == Some(prefix) {
+                continue
+            }
+            last = Some(prefix);
+            start.insert(prefix, idx);
+            if prefix.is_v4() {
+                v4_lens[usize::from(prefix.len())] = true;
+            }
+            else {
+                v6_lens[usize::from(prefix.len())] = true;
+            }
+        }
+        OriginIndex {
+            start,
+            v4_lens: Self::lens_in_use(&v4_lens),
+            v6_lens: Self::lens_in_use(&v6_lens),
+        }
+    }
+
+    /// Converts flags for each length into a list of lengths in use.
+    fn lens_in_use(flags: &[bool]) -> Vec<u8> {
+        flags.iter().enumerate().rev().filter_map(|(len, used)| {
+            // There are at most 129 flags, so this always fits.
+            used.then_some(len as u8)
+        }).collect()
+    }
+
+    /// Returns the route origins covering `prefix`.
+    ///
+    /// More specific prefixes are returned first.
+    fn covering<'a>(
+        &'a self,
+        origins: &'a PayloadCollection<RouteOrigin>,
+        prefix: Prefix,
+    ) -> impl Iterator<Item = &'a (RouteOrigin, PayloadInfo)> + 'a {
+        let lens = if prefix.is_v4() { &self.v4_lens } else { &self.v6_lens };
+        lens.iter().skip_while(move |&&len| {
+            len > prefix.len()
+        }).filter_map(move |&len| {
+            // A shorter length is always valid for the address family.
+            let covering = Prefix::new_relaxed(prefix.addr(), len).unwrap();
+            self.start.get(&covering).map(|&start| {
+                origins.vec[start..].iter().take_while(move |item| {
+                    item.0.prefix.prefix() == covering
+                })
+            })
+        }).flatten()
+    }
+}
+
+
 //----------- SnapshotArcIter ------------------------------------------------
 
 /// An iterator over the VRPs of a shared snapshot.
@@ -12,13 +12,16 @@
 //! [`RejectedResources`] and its [`RejectedResourcesBuilder`] collect
 //! prefixes and AS numbers from certificates for which publication points
 //! had to be rejected so we can avoid partial VRP coverage.
+//!
+//! Instead of building a payload snapshot, the final payload can also be
+//! handed item by item to a [`PayloadConsumer`].
 
-use std::cmp;
+use std::{cmp, fmt, mem};
 use std::collections::hash_map;
 use std::collections::HashMap;
 use std::sync::Arc;
 use crossbeam_queue::SegQueue;
-use log::{info, warn};
+use log::{error, info, warn};
 use rpki::uri;
 use rpki::crypto::keys::KeyIdentifier;
 use rpki::repository::aspa::AsProviderAttestation;
@@ -27,17 +30,22 @@ use rpki::repository::resources::{
     AsBlock, AsBlocks, IpBlock, IpBlocks, IpBlocksBuilder,
 };
 use rpki::repository::roa::RouteOriginAttestation;
-use rpki::repository::tal::{Tal, TalUri};
+use rpki::repository::tal::{Tal, TalInfo, TalUri};
 use rpki::repository::x509::{Time, Validity};
 use rpki::resources::{Asn, Prefix, SmallAsnSet};
 use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
 use rpki::rtr::pdu::{ProviderAsns, RouterKeyInfo};
-use crate::config::{Config, FilterPolicy};
+use crate::config::{
+    AcceptedResources, AddressFamilyFilter, Config, FilterPolicy
+};
 use crate::engine::{CaCert, Engine, ProcessPubPoint, ProcessRun};
 use crate::error::{Failed, RunFailed};
-use crate::metrics::{Metrics, PayloadMetrics, VrpMetrics};
+use crate::metrics::{
+    Metrics, PayloadMetrics, UnsafeVrpConflict, UnsafeVrpMetrics,
+    UNSAFE_VRPS_LIMIT, VrpMetrics,
+};
 use crate::slurm::LocalExceptions;
-use super::info::{PayloadInfo, PublishInfo};
+use super::info::{InfoTable, PayloadInfo, PublishInfo};
 use super::snapshot::PayloadSnapshot;
 
 
@@ -73,8 +81,17 @@ pub struct ValidationReport {
     /// Should we filter IPv6 prefixes longer than a certain length?
     limit_v6_len: Option<u8>,
 
+    /// The address families of route origins we include.
+    address_family: AddressFamilyFilter,
+
     /// How are we dealing with unsafe VRPs?
     unsafe_vrps: FilterPolicy,
+
+    /// The resources payload is accepted for.
+    accept_resources: AcceptedResources,
+
+    /// How are we logging payload outside the accepted resources?
+    accept_resources_policy: FilterPolicy,
 }
 
 impl ValidationReport {
@@ -88,7 +105,10 @@ impl ValidationReport {
             enable_aspa: config.enable_aspa,
             limit_v4_len: config.limit_v4_len,
             limit_v6_len: config.limit_v6_len,
+            address_family: config.filter_address_family,
             unsafe_vrps: config.unsafe_vrps,
+            accept_resources: config.accept_resources.clone(),
+            accept_resources_policy: config.accept_resources_policy,
         }
     }
 
@@ -104,20 +124,77 @@ impl ValidationReport {
         Ok((report, metrics))
     }
 
+    /// Creates a new validation report from stored data only.
+    ///
+    /// The engine should have been created without updates. Unlike
+    /// [`process`][Self::process], the store is not cleaned up afterwards
+    /// since the run may not have seen all data that is still needed.
+    pub fn process_stored(
+        engine: &Engine, config: &Config,
+    ) -> Result<(Self, Metrics), RunFailed> {
+        let report = Self::new(config);
+        let mut run = engine.start(&report)?;
+        run.process()?;
+        let metrics = run.done();
+        Ok((report, metrics))
+    }
+
+    /// Creates a new validation report for a single TAL.
+    ///
+    /// Only the TAL with the given name is processed. As with
+    /// [`process_stored`][Self::process_stored], the store is not cleaned
+    /// up afterwards. Returns the index of the TAL together with the report
+    /// and metrics or `None` if there is no such TAL.
+    pub fn process_tal(
+        engine: &Engine, config: &Config, name: &str,
+    ) -> Result<Option<(usize, Self, Metrics)>, RunFailed> {
+        let report = Self::new(config);
+        let (index, mut run) = match engine.start_tal(&report, name)? {
+            Some(some) => some,
+            None => return Ok(None)
+        };
+        run.process()?;
+        let metrics = run.done();
+        Ok(Some((index, report, metrics)))
+    }
+
     /// Converts the report into a payload snapshot.
     pub fn into_snapshot(
         self,
         exceptions: &LocalExceptions,
         metrics: &mut Metrics,
     ) -> PayloadSnapshot {
-        let mut builder = SnapshotBuilder::new(
-            self.rejected.finalize(), self.unsafe_vrps,
-            exceptions,
-        );
+        self.into_payload().to_snapshot(exceptions, metrics)
+    }
+
+    /// Hands the payload of the report to a consumer.
+    ///
+    /// This is a cheaper alternative to creating a snapshot via
+    /// [`into_snapshot`][Self::into_snapshot] if only the payload items
+    /// themselves are needed. See [`PayloadConsumer`] for details.
+    pub fn consume(
+        self,
+        exceptions: &LocalExceptions,
+        metrics: &mut Metrics,
+        consumer: &mut impl PayloadConsumer,
+    ) {
+        self.into_payload().consume(exceptions, metrics, consumer)
+    }
+
+    /// Converts the report into the validated payload.
+    pub fn into_payload(self) -> ValidatedPayload {
+        let mut pub_points = Vec::with_capacity(self.pub_points.len());
         while let Some(point) = self.pub_points.pop() {
-            builder.process_pub_point(point, metrics)
+            pub_points.push(point)
+        }
+        ValidatedPayload {
+            pub_points,
+            rejected: self.rejected.finalize(),
+            address_family: self.address_family,
+            unsafe_vrps: self.unsafe_vrps,
+            accept_resources: self.accept_resources,
+            accept_resources_policy: self.accept_resources_policy,
         }
-        builder.finalize(metrics)
     }
 }
 
@@ -135,6 +212,7 @@ impl<'a> ProcessRun for &'a ValidationReport {
                 pub_point: PubPoint::new_ta(cert, tal_index),
                 validity: cert.cert().validity(),
                 point_stale: cert.cert().validity().not_after(),
+                info_table: InfoTable::new(),
             }
         ))
     }
@@ -161,6 +239,12 @@ pub struct PubPointProcessor<'a> {
 
     /// When will this publication point become stale.
     point_stale: Time,
+
+    /// The table for sharing provenance information.
+    ///
+    /// Since all objects of a publication point are issued by the same
+    /// CA, this allows them to share the CA’s URI.
+    info_table: InfoTable,
 }
 
 impl ProcessPubPoint for PubPointProcessor<'_> {
@@ -194,6 +278,7 @@ impl ProcessPubPoint for PubPointProcessor<'_> {
                 point_stale: cmp::min(
                     self.point_stale, cert.cert().validity().not_after()
                 ),
+                info_table: InfoTable::new(),
             }
         ))
     }
@@ -245,7 +330,7 @@ impl ProcessPubPoint for PubPointProcessor<'_> {
             asns, id, key,
             Arc::new(PublishInfo::router_cert(
                 &cert, uri, ca_cert.cert().tal().clone(),
-                self.validity, self.point_stale,
+                self.validity, self.point_stale, &mut self.info_table,
             )),
         );
         Ok(())
@@ -260,9 +345,11 @@ impl ProcessPubPoint for PubPointProcessor<'_> {
         if self.pub_point.add_roa(
             route,
             Arc::new(PublishInfo::signed_object(
-                &cert, self.validity, self.point_stale
+                &cert, self.validity, self.point_stale,
+                &mut self.info_table,
             )),
             self.report.limit_v4_len, self.report.limit_v6_len,
+            self.report.address_family,
         ) {
             self.pub_point.update_refresh(cert.validity().not_after());
         }
@@ -282,7 +369,8 @@ impl ProcessPubPoint for PubPointProcessor<'_> {
         self.pub_point.add_aspa(
             aspa,
             Arc::new(PublishInfo::signed_object(
-                &cert, self.validity, self.point_stale
+                &cert, self.validity, self.point_stale,
+                &mut self.info_table,
             ))
         );
         Ok(())
@@ -315,7 +403,252 @@ impl ProcessPubPoint for PubPointProcessor<'_> {
                 warn!("   {}", block);
             }
         }
-        self.report.rejected.extend_from_cert(cert);
+        self.report.rejected.extend_from_cert(
+            cert, self.pub_point.tal_index, self.report.log_rejected
+        );
+    }
+}
+
+
+//------------ ValidatedPayload ----------------------------------------------
+
+/// The payload of a finished validation run.
+///
+/// This is the content of a [`ValidationReport`] before local exceptions
+/// have been applied. It can be turned into a payload snapshot as often as
+/// necessary, for instance if the local exceptions change.
+#[derive(Clone, Debug)]
+pub struct ValidatedPayload {
+    /// The data from all the valid publication points.
+    pub_points: Vec<PubPoint>,
+
+    /// Filter for invalid resources.
+    rejected: RejectedResources,
+
+    /// The address families of route origins included.
+    address_family: AddressFamilyFilter,
+
+    /// How are we dealing with unsafe VRPs?
+    unsafe_vrps: FilterPolicy,
+
+    /// The resources payload is accepted for.
+    accept_resources: AcceptedResources,
+
+    /// How are we logging payload outside the accepted resources?
+    accept_resources_policy: FilterPolicy,
+}
+
+impl ValidatedPayload {
+    /// Replaces the payload of a TAL with that from a partial run.
+    ///
+    /// The payload in `other` needs to have been produced by a validation
+    /// run for only the TAL with index `tal_index`. It replaces all payload
+    /// of that TAL in `self` while the payload of all other TALs is kept.
+    /// Since the repository indexes of `other` refer to the metrics of the
+    /// partial run, `repository_map` needs to provide the new index for
+    /// each of them as returned by [`Metrics::merge_tal`].
+    pub fn merge_tal(
+        &self, other: ValidatedPayload,
+        tal_index: usize, repository_map: &[usize],
+    ) -> Self {
+        let mut pub_points: Vec<_> = self.pub_points.iter().filter(|point| {
+            point.tal_index != tal_index
+        }).cloned().collect();
+        pub_points.extend(other.pub_points.into_iter().filter(|point| {
+            point.tal_index == tal_index
+        }).map(|mut point| {
+            point.repository_index = point.repository_index.and_then(
+                |index| repository_map.get(index).copied()
+            );
+            point
+        }));
+        ValidatedPayload {
+            pub_points,
+            rejected: self.rejected.merge_tal(&other.rejected, tal_index),
+            address_family: other.address_family,
+            unsafe_vrps: other.unsafe_vrps,
+            accept_resources: other.accept_resources,
+            accept_resources_policy: other.accept_resources_policy,
+        }
+    }
+
+    /// Creates a payload snapshot by applying the local exceptions.
+    pub fn to_snapshot(
+        &self,
+        exceptions: &LocalExceptions,
+        metrics: &mut Metrics,
+    ) -> PayloadSnapshot {
+        let mut builder = SnapshotBuilder::new(
+            PayloadFilter::new(self, exceptions)
+        );
+        for point in &self.pub_points {
+            builder.process_pub_point(point, metrics)
+        }
+        builder.finalize(metrics).with_address_family(self.address_family)
+    }
+
+    /// Hands the payload to a consumer after applying local exceptions.
+    ///
+    /// See [`PayloadConsumer`] for details on what the consumer will
+    /// receive and in which order.
+    pub fn consume(
+        &self,
+        exceptions: &LocalExceptions,
+        metrics: &mut Metrics,
+        consumer: &mut impl PayloadConsumer,
+    ) {
+        let mut filter = PayloadFilter::new(self, exceptions);
+        let mut points: Vec<_> = self.pub_points.iter().collect();
+        points.sort_by_key(|point| point.tal_index);
+
+        let mut current = None;
+        let mut large_aspas = 0;
+        for point in points {
+            if current != Some(point.tal_index) {
+                if let Some(index) = current {
+                    consumer.finish_tal(&metrics.tals[index].tal);
+                }
+                consumer.start_tal(&metrics.tals[point.tal_index].tal);
+                current = Some(point.tal_index);
+            }
+
+            let mut point_metrics = AllVrpMetrics::new(
+                metrics, point.tal_index, point.repository_index,
+            );
+            for origin in &point.origins {
+                if filter.keep_origin(origin, &mut point_metrics) {
+                    point_metrics.update_origin(
+                        origin.origin.is_v4(), |m| m.contributed += 1
+                    );
+                    consumer.route_origin(
+                        origin.origin, origin.info.clone().into()
+                    );
+                }
+            }
+            for key in &point.router_keys {
+                point_metrics.update(|m| {
+                    m.router_keys.valid += key.asns.asn_count()
+                });
+                for asn in key.asns.iter_asns() {
+                    let router_key = RouterKey::new(
+                        key.key_id, asn, key.key_info.clone()
+                    );
+                    if !filter.keep_router_key(
+                        &router_key, &mut point_metrics
+                    ) {
+                        continue
+                    }
+                    point_metrics.update(|m| {
+                        m.router_keys.contributed += 1
+                    });
+                    consumer.router_key(router_key, key.info.clone().into());
+                }
+            }
+            for aspa in &point.aspas {
+                point_metrics.update(|m| m.aspas.valid += 1);
+                if !filter.keep_aspa(aspa, &mut point_metrics) {
+                    continue
+                }
+                match ProviderAsns::try_from_iter(aspa.providers.iter()) {
+                    Ok(providers) => {
+                        point_metrics.update(|m| m.aspas.contributed += 1);
+                        consumer.aspa(
+                            Aspa::new(aspa.customer, providers),
+                            aspa.info.clone().into()
+                        );
+                    }
+                    Err(_) => {
+                        warn!(
+                            "Ignoring excessively large ASPA for {} \
+                             with {} provider ASNs.",
+                            aspa.customer, aspa.providers.len()
+                        );
+                        large_aspas += 1;
+                    }
+                }
+            }
+        }
+        if let Some(index) = current {
+            consumer.finish_tal(&metrics.tals[index].tal);
+        }
+        metrics.snapshot.large_aspas += large_aspas;
+
+        for (origin, info) in exceptions.origin_assertions() {
+            if !self.address_family.includes(origin.is_v4()) {
+                continue
+            }
+            if origin.is_v4() {
+                metrics.local.v4_origins.contributed += 1;
+                metrics.snapshot.payload.v4_origins.contributed += 1;
+            }
+            else {
+                metrics.local.v6_origins.contributed += 1;
+                metrics.snapshot.payload.v6_origins.contributed += 1;
+            }
+            consumer.route_origin(origin, info.into());
+        }
+        for (key, info) in exceptions.router_key_assertions() {
+            metrics.local.router_keys.contributed += 1;
+            metrics.snapshot.payload.router_keys.contributed += 1;
+            consumer.router_key(key, info.into());
+        }
+        for (aspa, info) in exceptions.aspa_assertions() {
+            metrics.local.aspas.contributed += 1;
+            metrics.snapshot.payload.aspas.contributed += 1;
+            consumer.aspa(aspa, info.into());
+        }
+
+        filter.finalize(metrics);
+        metrics.finalize();
+    }
+}
+
+
+//------------ PayloadConsumer -----------------------------------------------
+
+/// A type that receives the payload of a validation run item by item.
+///
+/// A consumer can be used with [`ValidationReport::consume`] or
+/// [`ValidatedPayload::consume`] in place of creating a
+/// [`PayloadSnapshot`]. This avoids building the indexes necessary for a
+/// snapshot and is useful if the payload is only to be passed on to some
+/// other place.
+///
+/// Unsafe VRPs are dealt with according to the configured policy and
+/// local exceptions are applied before the consumer sees any items.
+/// Prefixes exceeding the configured maximum prefix lengths never make it
+/// into the report in the first place.
+///
+/// The payload of each trust anchor is delivered in one go, preceded by a
+/// call to [`start_tal`][Self::start_tal] and followed by a call to
+/// [`finish_tal`][Self::finish_tal]. Trust anchors are delivered in the
+/// order they appear in the metrics. Within a trust anchor, there is no
+/// guaranteed order of the items. Assertions from local exceptions are
+/// delivered last, outside of any trust anchor.
+///
+/// Unlike a snapshot, the payload is not deduplicated: the same item can
+/// be delivered multiple times if it is published by multiple objects or
+/// also asserted locally. Likewise, ASPA payload for the same customer ASN
+/// is not merged. Consequently, the metrics will not contain any
+/// duplicate counts.
+pub trait PayloadConsumer {
+    /// Starts delivering the payload for the given trust anchor.
+    fn start_tal(&mut self, tal: &TalInfo) {
+        let _ = tal;
+    }
+
+    /// Receives a route origin.
+    fn route_origin(&mut self, origin: RouteOrigin, info: PayloadInfo);
+
+    /// Receives a router key.
+    fn router_key(&mut self, key: RouterKey, info: PayloadInfo);
+
+    /// Receives an ASPA payload item.
+    fn aspa(&mut self, aspa: Aspa, info: PayloadInfo);
+
+    /// Finishes delivering the payload for the given trust anchor.
+    fn finish_tal(&mut self, tal: &TalInfo) {
+        let _ = tal;
     }
 }
 
@@ -402,15 +735,22 @@ impl PubPoint {
     }
 
     /// Adds the content of a ROA to the payload.
+    ///
+    /// Route origins of an address family not included in `family` are
+    /// dropped. The length limit of such a family is never considered.
     fn add_roa(
         &mut self,
         roa: RouteOriginAttestation,
         info: Arc<PublishInfo>,
         limit_v4_len: Option<u8>,
         limit_v6_len: Option<u8>,
+        family: AddressFamilyFilter,
     ) -> bool {
         let mut any = false;
         for origin in roa.iter_origins() {
+            if !family.includes(origin.prefix.prefix().is_v4()) {
+                continue;
+            }
             let limit = if origin.prefix.prefix().is_v4() {
                 limit_v4_len
             }
@@ -513,9 +853,76 @@ pub struct PubAspa {
 pub struct RejectedResources {
     v4: IpBlocks,
     v6: IpBlocks,
+
+    /// The rejected IP blocks with the index of the TAL they came from.
+    ///
+    /// The second element is whether the block is for IPv4. This is kept
+    /// so that the resources of a single TAL can be replaced.
+    blocks: Vec<(usize, bool, IpBlock)>,
+
+    /// The rejected CAs and their resources.
+    ///
+    /// This is only collected if unsafe VRPs are tracked.
+    cas: Vec<RejectedCa>,
 }
 
 impl RejectedResources {
+    /// Creates the rejected resources from a list of blocks and CAs.
+    fn new(
+        blocks: Vec<(usize, bool, IpBlock)>, cas: Vec<RejectedCa>
+    ) -> Self {
+        let mut v4 = IpBlocksBuilder::new();
+        let mut v6 = IpBlocksBuilder::new();
+        for &(_, is_v4, block) in &blocks {
+            if is_v4 {
+                v4.push(block);
+            }
+            else {
+                v6.push(block);
+            }
+        }
+        RejectedResources {
+            v4: v4.finalize(),
+            v6: v6.finalize(),
+            blocks,
+            cas,
+        }
+    }
+
+    /// Replaces the resources of a TAL with those from `other`.
+    ///
+    /// Only the resources of the TAL with index `tal_index` are taken from
+    /// `other`, those of all other TALs from `self`.
+    fn merge_tal(&self, other: &Self, tal_index: usize) -> Self {
+        Self::new(
+            self.blocks.iter().filter(|item| item.0 != tal_index).chain(
+                other.blocks.iter().filter(|item| item.0 == tal_index)
+            ).copied().collect(),
+            self.cas.iter().filter(|ca| ca.tal_index != tal_index).chain(
+                other.cas.iter().filter(|ca| ca.tal_index == tal_index)
+            ).cloned().collect(),
+        )
+    }
+
+    /// Returns the rejected CAs whose resources overlap with a prefix.
+    pub fn conflicts(&self, prefix: Prefix) -> Vec<UnsafeVrpConflict> {
+        let raw = IpBlock::from(rpki::repository::resources::Prefix::new(
+            prefix.addr(), prefix.len()
+        ));
+        self.cas.iter().filter_map(|ca| {
+            let blocks = if prefix.is_v4() { &ca.v4 } else { &ca.v6 };
+            let blocks: Vec<_> = blocks.iter().filter(|block| {
+                block.min() <= raw.max() && block.max() >= raw.min()
+            }).cloned().collect();
+            if blocks.is_empty() {
+                None
+            }
+            else {
+                Some(UnsafeVrpConflict { ca: ca.uri.clone(), blocks })
+            }
+        }).collect()
+    }
+
     /// Checks whether a prefix should be kept.
     pub fn keep_prefix(&self, prefix: Prefix) -> bool {
         let raw = rpki::repository::resources::Prefix::new(
@@ -538,24 +945,44 @@ impl RejectedResources {
 struct RejectedResourcesBuilder {
     /// The queue of rejected IP blocks.
     ///
-    /// The first element is whether the block is for IPv4.
-    addrs: SegQueue<(bool, IpBlock)>,
+    /// The first element is the index of the TAL, the second whether the
+    /// block is for IPv4.
+    addrs: SegQueue<(usize, bool, IpBlock)>,
 
     /// The queue of rejected AS blocks.
     asns: SegQueue<AsBlock>,
+
+    /// The queue of rejected CAs.
+    cas: SegQueue<RejectedCa>,
 }
 
 impl RejectedResourcesBuilder {
-    fn extend_from_cert(&self, cert: &CaCert) {
+    /// Adds the resources of a rejected CA.
+    ///
+    /// If `track` is `true`, the CA itself is remembered, too, so that
+    /// unsafe VRPs can be traced back to it.
+    fn extend_from_cert(&self, cert: &CaCert, tal_index: usize, track: bool) {
+        if track {
+            self.cas.push(RejectedCa {
+                uri: cert.uri().clone(),
+                tal_index,
+                v4: cert.cert().v4_resources().iter().filter(|block| {
+                    !block.is_slash_zero()
+                }).collect(),
+                v6: cert.cert().v6_resources().iter().filter(|block| {
+                    !block.is_slash_zero()
+                }).collect(),
+            });
+        }
         for block in cert.cert().v4_resources().iter().filter(|block|
             !block.is_slash_zero()
         ) {
-            self.addrs.push((true, block));
+            self.addrs.push((tal_index, true, block));
         }
         for block in cert.cert().v6_resources().iter().filter(|block|
             !block.is_slash_zero()
         ) {
-            self.addrs.push((false, block));
+            self.addrs.push((tal_index, false, block));
         }
         for block in cert.cert().as_resources().iter().filter(|block|
             !block.is_whole_range()
@@ -565,41 +992,52 @@ impl RejectedResourcesBuilder {
     }
 
     fn finalize(self) -> RejectedResources {
-        let mut v4 = IpBlocksBuilder::new();
-        let mut v6 = IpBlocksBuilder::new();
-        while let Some((is_v4, block)) = self.addrs.pop() {
-            if is_v4 {
-                v4.push(block);
-            }
-            else {
-                v6.push(block);
-            }
+        let mut blocks = Vec::new();
+        while let Some(item) = self.addrs.pop() {
+            blocks.push(item)
         }
-        RejectedResources {
-            v4: v4.finalize(),
-            v6: v6.finalize(),
+        let mut cas = Vec::new();
+        while let Some(ca) = self.cas.pop() {
+            cas.push(ca)
         }
+        RejectedResources::new(blocks, cas)
     }
 }
 
 
-//------------ SnapshotBuilder -----------------------------------------------
+//------------ RejectedCa ----------------------------------------------------
 
-/// Helps turning the report into a payload snapshot.
-struct SnapshotBuilder<'a> {
-    /// The collected route origins.
-    origins: HashMap<RouteOrigin, PayloadInfo>,
+/// A CA that had to be rejected.
+#[derive(Clone, Debug)]
+struct RejectedCa {
+    /// The URI of the CA certificate.
+    uri: TalUri,
 
-    /// The collected router keys.
-    router_keys: HashMap<RouterKey, PayloadInfo>,
+    /// The index of the TAL the CA was found under.
+    tal_index: usize,
+
+    /// The IPv4 address blocks of the CA.
+    v4: Vec<IpBlock>,
+
+    /// The IPv6 address blocks of the CA.
+    v6: Vec<IpBlock>,
+}
 
-    /// The collected ASPA payload.
-    ///
-    /// The key is the customer ASN.
-    aspas: HashMap<Asn, (SmallAsnSet, PayloadInfo)>,
 
+//------------ PayloadFilter -------------------------------------------------
+
+/// Decides which payload items make it into the final data set.
+///
+/// The filter deals with unsafe VRPs and applies the local exceptions.
+struct PayloadFilter<'a> {
     /// The list of rejected resources.
-    rejected: RejectedResources,
+    rejected: &'a RejectedResources,
+
+    /// The resources payload is accepted for.
+    accepted: &'a AcceptedResources,
+
+    /// How are we logging payload outside the accepted resources?
+    accepted_policy: FilterPolicy,
 
     /// How are we dealing with unsafe VRPs?
     unsafe_vrps: FilterPolicy,
@@ -607,64 +1045,59 @@ struct SnapshotBuilder<'a> {
     /// Are unsafe VRPs present?
     unsafe_vrps_present: bool,
 
-    /// The time when this snapshot needs to be refreshed at the latest.
-    refresh: Option<Time>,
+    /// The unsafe VRPs encountered so far.
+    unsafe_vrps_list: Vec<UnsafeVrpMetrics>,
 
+    /// The local exceptions to apply.
     exceptions: &'a LocalExceptions,
+
+    /// The address families of route origins included.
+    ///
+    /// This is only applied to origin assertions since validated route
+    /// origins of other families have been dropped already.
+    address_family: AddressFamilyFilter,
 }
 
-impl<'a> SnapshotBuilder<'a> {
+impl<'a> PayloadFilter<'a> {
     fn new(
-        rejected: RejectedResources,
-        unsafe_vrps: FilterPolicy,
+        payload: &'a ValidatedPayload,
         exceptions: &'a LocalExceptions,
-        
     ) -> Self {
         Self {
-            origins: Default::default(),
-            router_keys: Default::default(),
-            aspas: Default::default(),
-            rejected,
-            unsafe_vrps,
+            rejected: &payload.rejected,
+            accepted: &payload.accept_resources,
+            accepted_policy: payload.accept_resources_policy,
+            unsafe_vrps: payload.unsafe_vrps,
             unsafe_vrps_present: false,
-            refresh: None,
+            unsafe_vrps_list: Vec::new(),
             exceptions,
+            address_family: payload.address_family,
         }
     }
 
-
-    fn process_pub_point(
-        &mut self, point: PubPoint, metrics: &mut Metrics
-    ) {
-        let mut metrics = AllVrpMetrics::new(
-            metrics, point.tal_index, point.repository_index,
-        );
-        self.update_refresh(point.refresh);
-        point.origins.into_iter().for_each(|item| {
-            self.process_origin(item, &mut metrics)
-        });
-        point.router_keys.into_iter().for_each(|item| {
-            self.process_key(item, &mut metrics)
-        });
-        point.aspas.into_iter().for_each(|item| {
-            self.process_aspa(item, &mut metrics)
-        });
-    }
-
-    /// Updates the refresh time.
-    fn update_refresh(&mut self, refresh: Time) {
-        self.refresh = match self.refresh {
-            Some(old) => Some(cmp::min(old, refresh)),
-            None => Some(refresh)
-        }
-    }
-
-    fn process_origin(
-        &mut self, origin: PubRouteOrigin, metrics: &mut AllVrpMetrics,
-    ) {
+    /// Returns whether a route origin should be kept.
+    ///
+    /// Updates all metrics except for the contributed and duplicate
+    /// counts which are left to the caller.
+    fn keep_origin(
+        &mut self, origin: &PubRouteOrigin, metrics: &mut AllVrpMetrics,
+    ) -> bool {
         let v4 = origin.origin.is_v4();
         metrics.update_origin(v4, |m| m.valid += 1);
 
+        // Is the prefix outside the accepted resources?
+        if !self.accepted.covers_prefix(origin.origin.prefix.prefix()) {
+            metrics.update_origin(v4, |m| m.outside_resources += 1);
+            self.log_outside(format_args!(
+                "VRP ({}/{}-{}, {})",
+                origin.origin.prefix.addr(),
+                origin.origin.prefix.prefix_len(),
+                origin.origin.prefix.resolved_max_len(),
+                origin.origin.asn
+            ));
+            return false
+        }
+
         // Is the prefix in the rejected resources?
         if !self.rejected.keep_prefix(origin.origin.prefix.prefix()) {
             self.unsafe_vrps_present = true;
@@ -674,6 +1107,7 @@ impl<'a> SnapshotBuilder<'a> {
                 }
                 FilterPolicy::Warn => {
                     metrics.update_origin(v4, |m| m.marked_unsafe += 1);
+                    self.note_unsafe(origin);
                     info!(
                         "Encountered potentially unsafe VRP \
                          ({}/{}-{}, {})",
@@ -685,6 +1119,7 @@ impl<'a> SnapshotBuilder<'a> {
                 }
                 FilterPolicy::Reject => {
                     metrics.update_origin(v4, |m| m.marked_unsafe += 1);
+                    self.note_unsafe(origin);
                     warn!(
                         "Filtering potentially unsafe VRP \
                          ({}/{}-{}, {})",
@@ -693,7 +1128,7 @@ impl<'a> SnapshotBuilder<'a> {
                         origin.origin.prefix.resolved_max_len(),
                         origin.origin.asn
                     );
-                    return
+                    return false
                 }
             }
         }
@@ -701,25 +1136,182 @@ impl<'a> SnapshotBuilder<'a> {
         // Is the origin to be filtered locally?
         if self.exceptions.drop_origin(origin.origin) {
             metrics.update_origin(v4, |m| m.locally_filtered += 1);
+            return false
+        }
+
+        true
+    }
+
+    /// Remembers an unsafe VRP for later inspection.
+    fn note_unsafe(&mut self, origin: &PubRouteOrigin) {
+        if self.unsafe_vrps_list.len() >= UNSAFE_VRPS_LIMIT {
             return
         }
+        self.unsafe_vrps_list.push(UnsafeVrpMetrics {
+            origin: origin.origin,
+            tal: origin.info.tal.clone(),
+            roa: origin.info.uri.clone(),
+            conflicts: self.rejected.conflicts(
+                origin.origin.prefix.prefix()
+            ),
+        })
+    }
+
+    /// Returns whether a router key should be kept.
+    ///
+    /// Only updates the outside resources and locally filtered counts of
+    /// the metrics.
+    fn keep_router_key(
+        &self, key: &RouterKey, metrics: &mut AllVrpMetrics,
+    ) -> bool {
+        if !self.accepted.covers_asn(key.asn) {
+            metrics.update(|m| m.router_keys.outside_resources += 1);
+            self.log_outside(format_args!(
+                "router key ({}, {})", key.asn, key.key_identifier
+            ));
+            false
+        }
+        else if self.exceptions.drop_router_key(key) {
+            metrics.update(|m| m.router_keys.locally_filtered += 1);
+            false
+        }
+        else {
+            true
+        }
+    }
+
+    /// Returns whether an ASPA should be kept.
+    ///
+    /// Only updates the outside resources and locally filtered counts of
+    /// the metrics.
+    fn keep_aspa(
+        &self, aspa: &PubAspa, metrics: &mut AllVrpMetrics,
+    ) -> bool {
+        if !self.accepted.covers_asn(aspa.customer) {
+            metrics.update(|m| m.aspas.outside_resources += 1);
+            self.log_outside(format_args!("ASPA for {}", aspa.customer));
+            false
+        }
+        else if self.exceptions.drop_aspa(aspa.customer, &aspa.providers) {
+            metrics.update(|m| m.aspas.locally_filtered += 1);
+            false
+        }
+        else {
+            true
+        }
+    }
+
+    /// Logs dropping payload outside the accepted resources.
+    fn log_outside(&self, payload: fmt::Arguments) {
+        match self.accepted_policy {
+            FilterPolicy::Reject => {
+                error!("Dropping {} outside accepted resources.", payload);
+            }
+            FilterPolicy::Warn => {
+                warn!("Dropping {} outside accepted resources.", payload);
+            }
+            FilterPolicy::Accept => { }
+        }
+    }
+
+    /// Finishes filtering and stores the unsafe VRPs in the metrics.
+    fn finalize(&mut self, metrics: &mut Metrics) {
+        if self.unsafe_vrps_present && self.unsafe_vrps.log()  {
+            warn!(
+                "For more information on unsafe VRPs, see \
+                 https://routinator.docs.nlnetlabs.nl\
+                 /en/stable/unsafe-vrps.html"
+            );
+        }
+        metrics.unsafe_vrps = mem::take(&mut self.unsafe_vrps_list);
+    }
+}
+
+
+//------------ SnapshotBuilder -----------------------------------------------
+
+/// Helps turning the report into a payload snapshot.
+struct SnapshotBuilder<'a> {
+    /// The collected route origins.
+    origins: HashMap<RouteOrigin, PayloadInfo>,
+
+    /// The collected router keys.
+    router_keys: HashMap<RouterKey, PayloadInfo>,
+
+    /// The collected ASPA payload.
+    ///
+    /// The key is the customer ASN.
+    aspas: HashMap<Asn, (SmallAsnSet, PayloadInfo)>,
+
+    /// The filter deciding which payload to keep.
+    filter: PayloadFilter<'a>,
+
+    /// The time when this snapshot needs to be refreshed at the latest.
+    refresh: Option<Time>,
+}
+
+impl<'a> SnapshotBuilder<'a> {
+    fn new(filter: PayloadFilter<'a>) -> Self {
+        Self {
+            origins: Default::default(),
+            router_keys: Default::default(),
+            aspas: Default::default(),
+            filter,
+            refresh: None,
+        }
+    }
+
+
+    fn process_pub_point(
+        &mut self, point: &PubPoint, metrics: &mut Metrics
+    ) {
+        let mut metrics = AllVrpMetrics::new(
+            metrics, point.tal_index, point.repository_index,
+        );
+        self.update_refresh(point.refresh);
+        point.origins.iter().for_each(|item| {
+            self.process_origin(item, &mut metrics)
+        });
+        point.router_keys.iter().for_each(|item| {
+            self.process_key(item, &mut metrics)
+        });
+        point.aspas.iter().for_each(|item| {
+            self.process_aspa(item, &mut metrics)
+        });
+    }
+
+    /// Updates the refresh time.
+    fn update_refresh(&mut self, refresh: Time) {
+        self.refresh = match self.refresh {
+            Some(old) => Some(cmp::min(old, refresh)),
+            None => Some(refresh)
+        }
+    }
+
+    fn process_origin(
+        &mut self, origin: &PubRouteOrigin, metrics: &mut AllVrpMetrics,
+    ) {
+        if !self.filter.keep_origin(origin, metrics) {
+            return
+        }
+        let v4 = origin.origin.is_v4();
 
         // Insert the origin. If we have it already, we need to
         // update its info instead.
         match self.origins.entry(origin.origin) {
             hash_map::Entry::Vacant(entry) => {
-                entry.insert(origin.info.into());
+                entry.insert(origin.info.clone().into());
                 metrics.update_origin(v4, |m| m.contributed += 1);
             }
             hash_map::Entry::Occupied(mut entry) => {
-                entry.get_mut().add_published(origin.info);
+                entry.get_mut().add_published(origin.info.clone());
                 metrics.update_origin(v4, |m| m.duplicate += 1);
             }
         }
     }
 
     fn process_key(
-        &mut self, key: PubRouterKey, metrics: &mut AllVrpMetrics,
+        &mut self, key: &PubRouterKey, metrics: &mut AllVrpMetrics,
     ) {
         metrics.update(|m| m.router_keys.valid += key.asns.asn_count());
 
@@ -730,8 +1322,7 @@ impl<'a> SnapshotBuilder<'a> {
             );
 
             // Is the key to be filtered locally?
-            if self.exceptions.drop_router_key(&router_key) {
-                metrics.update(|m| m.router_keys.locally_filtered += 1);
+            if !self.filter.keep_router_key(&router_key, metrics) {
                 continue
             }
 
@@ -750,41 +1341,43 @@ impl<'a> SnapshotBuilder<'a> {
         }
     }
 
-    fn process_aspa(&mut self, aspa: PubAspa, metrics: &mut AllVrpMetrics) {
+    fn process_aspa(
+        &mut self, aspa: &PubAspa, metrics: &mut AllVrpMetrics
+    ) {
         metrics.update(|m| m.aspas.valid += 1);
 
-        // SLURM filtering goes here ...
+        if !self.filter.keep_aspa(aspa, metrics) {
+            return
+        }
 
         match self.aspas.entry(aspa.customer) {
             hash_map::Entry::Vacant(entry) => {
-                entry.insert((aspa.providers, aspa.info.into()));
+                entry.insert(
+                    (aspa.providers.clone(), aspa.info.clone().into())
+                );
                 metrics.update(|m| m.aspas.contributed += 1);
             }
             hash_map::Entry::Occupied(mut entry) => {
                 let entry = entry.get_mut();
                 entry.0 = entry.0.union(&aspa.providers).collect();
-                entry.1.add_published(aspa.info);
+                entry.1.add_published(aspa.info.clone());
                 metrics.update(|m| m.aspas.duplicate += 1);
             }
         }
     }
 
     fn finalize(mut self, metrics: &mut Metrics) -> PayloadSnapshot {
-        if self.unsafe_vrps_present && self.unsafe_vrps.log()  {
-            warn!(
-                "For more information on unsafe VRPs, see \
-                 https://routinator.docs.nlnetlabs.nl\
-                 /en/stable/unsafe-vrps.html"
-            );
-        }
-
+        self.filter.finalize(metrics);
         self.insert_assertions(metrics);
         metrics.finalize();
         self.into_snapshot(metrics)
     }
 
     fn insert_assertions(&mut self, metrics: &mut Metrics) {
-        for (origin, info) in self.exceptions.origin_assertions() {
+        for (origin, info) in self.filter.exceptions.origin_assertions() {
+            if !self.filter.address_family.includes(origin.is_v4()) {
+                continue
+            }
             match self.origins.entry(origin) {
                 hash_map::Entry::Vacant(entry) => {
                     entry.insert(info.into());
@@ -811,7 +1404,7 @@ impl<'a> SnapshotBuilder<'a> {
             }
         }
 
-        for (key, info) in self.exceptions.router_key_assertions() {
+        for (key, info) in self.filter.exceptions.router_key_assertions() {
             match self.router_keys.entry(key) {
                 hash_map::Entry::Vacant(entry) => {
                     entry.insert(info.into());
@@ -826,7 +1419,23 @@ impl<'a> SnapshotBuilder<'a> {
             }
         }
 
-        // XXX ASPA assertions.
+        for (aspa, info) in self.filter.exceptions.aspa_assertions() {
+            let providers: SmallAsnSet = aspa.providers.iter().collect();
+            match self.aspas.entry(aspa.customer) {
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert((providers, info.into()));
+                    metrics.local.aspas.contributed += 1;
+                    metrics.snapshot.payload.aspas.contributed += 1;
+                }
+                hash_map::Entry::Occupied(mut entry) => {
+                    let entry = entry.get_mut();
+                    entry.0 = entry.0.union(&providers).collect();
+                    entry.1.add_local(info);
+                    metrics.local.aspas.duplicate += 1;
+                    metrics.snapshot.payload.aspas.duplicate += 1;
+                }
+            }
+        }
     }
 
     fn into_snapshot(self, metrics: &mut Metrics) -> PayloadSnapshot {
@@ -900,3 +1509,283 @@ impl<'a> AllVrpMetrics<'a> {
     }
 }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use rpki::resources::MaxLenPrefix;
+    use crate::metrics::TalMetrics;
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect {
+        tals: usize,
+        origins: Vec<RouteOrigin>,
+    }
+
+    impl PayloadConsumer for Collect {
+        fn start_tal(&mut self, _tal: &TalInfo) {
+            self.tals += 1;
+        }
+
+        fn route_origin(&mut self, origin: RouteOrigin, info: PayloadInfo) {
+            assert!(info.exception_info().is_some());
+            self.origins.push(origin)
+        }
+
+        fn router_key(&mut self, _key: RouterKey, _info: PayloadInfo) {
+            panic!("unexpected router key")
+        }
+
+        fn aspa(&mut self, _aspa: Aspa, _info: PayloadInfo) {
+            panic!("unexpected ASPA")
+        }
+    }
+
+    #[test]
+    fn consume_assertions() {
+        let exceptions = LocalExceptions::from_json(
+            r#"{
+                "slurmVersion": 1,
+                "validationOutputFilters": {
+                    "prefixFilters": [],
+                    "bgpsecFilters": []
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [
+                        { "asn": 64496, "prefix": "198.51.100.0/24" },
+                        { "asn": 64496, "prefix": "2001:db8::/32" }
+                    ],
+                    "bgpsecAssertions": []
+                }
+            }"#,
+            false
+        ).unwrap();
+        let mut metrics = Metrics::new();
+        let mut consumer = Collect::default();
+        ValidationReport::new(&Config::default()).consume(
+            &exceptions, &mut metrics, &mut consumer
+        );
+        assert_eq!(consumer.tals, 0);
+        assert_eq!(consumer.origins.len(), 2);
+        assert_eq!(metrics.local.v4_origins.contributed, 1);
+        assert_eq!(metrics.local.v6_origins.contributed, 1);
+        assert_eq!(metrics.snapshot.payload.vrps().contributed, 2);
+    }
+
+    #[test]
+    fn accept_resources() {
+        let mut config = Config::default();
+        for item in ["192.0.2.0/24", "2001:db8::/32", "AS64496-AS64500"] {
+            config.accept_resources.push_str(item).unwrap();
+        }
+        let tal = Arc::new(TalInfo::from_name("test".into()));
+        let info = Arc::new(PublishInfo::imported(tal.clone()));
+        let mut point = PubPoint::new(Time::now(), 0);
+        for (prefix, max_len, asn) in [
+            ("192.0.2.0/25", 25, 64496),   // inside
+            ("192.0.2.0/24", 32, 64496),   // exactly the boundary
+            ("192.0.2.0/23", 24, 64496),   // straddles the boundary
+            ("192.0.0.0/16", 24, 64496),   // covers the accepted prefix
+            ("198.51.100.0/24", 24, 64496), // outside
+            ("2001:db8:1::/48", 48, 64501), // inside despite the ASN
+        ] {
+            point.origins.push(PubRouteOrigin {
+                origin: RouteOrigin::new(
+                    MaxLenPrefix::new(
+                        prefix.parse().unwrap(), Some(max_len)
+                    ).unwrap(),
+                    Asn::from_u32(asn),
+                ),
+                info: info.clone(),
+            });
+        }
+        for customer in [64496, 64500, 64501] {
+            point.aspas.push(PubAspa {
+                customer: Asn::from_u32(customer),
+                providers: SmallAsnSet::default(),
+                info: info.clone(),
+            });
+        }
+        let report = ValidationReport::new(&config);
+        report.pub_points.push(point);
+
+        let mut metrics = Metrics::new();
+        metrics.tals.push(TalMetrics::new(tal));
+        let snapshot = report.into_payload().to_snapshot(
+            &LocalExceptions::empty(), &mut metrics
+        );
+        let mut origins: Vec<_> = snapshot.origins().map(|item| {
+            item.0.prefix.prefix().to_string()
+        }).collect();
+        origins.sort();
+        assert_eq!(
+            origins, ["192.0.2.0/24", "192.0.2.0/25", "2001:db8:1::/48"]
+        );
+        assert_eq!(snapshot.aspas().count(), 2);
+
+        let payload = &metrics.tals[0].payload;
+        assert_eq!(payload.v4_origins.valid, 5);
+        assert_eq!(payload.v4_origins.outside_resources, 3);
+        assert_eq!(payload.v6_origins.outside_resources, 0);
+        assert_eq!(payload.aspas.outside_resources, 1);
+    }
+
+    #[test]
+    fn aspa_exceptions() {
+        let tal = Arc::new(TalInfo::from_name("test".into()));
+        let info = Arc::new(PublishInfo::imported(tal.clone()));
+        let mut point = PubPoint::new(Time::now(), 0);
+        for (customer, providers) in [
+            (64496, [64500, 64501]),  // filtered by customer
+            (64497, [64500, 64502]),  // duplicated by an assertion
+            (64498, [64500, 64503]),  // untouched
+        ] {
+            point.aspas.push(PubAspa {
+                customer: Asn::from_u32(customer),
+                providers: providers.into_iter().map(Asn::from_u32).collect(),
+                info: info.clone(),
+            });
+        }
+        let report = ValidationReport::new(&Config::default());
+        report.pub_points.push(point);
+        let payload = report.into_payload();
+        let exceptions = LocalExceptions::from_json(
+            r#"{
+                "slurmVersion": 2,
+                "validationOutputFilters": {
+                    "prefixFilters": [],
+                    "bgpsecFilters": [],
+                    "aspaFilters": [
+                        { "customerAsid": 64496 },
+                        { "customerAsid": 64498, "providerSet": [ 64500 ] },
+                        { "customerAsid": 64499 }
+                    ]
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [],
+                    "bgpsecAssertions": [],
+                    "aspaAssertions": [
+                        {
+                            "customerAsid": 64497,
+                            "providerSet": [ 64502, 64500 ]
+                        },
+                        { "customerAsid": 64510, "providerSet": [ 64511 ] }
+                    ]
+                }
+            }"#,
+            false
+        ).unwrap();
+
+        let mut metrics = Metrics::new();
+        metrics.tals.push(TalMetrics::new(tal.clone()));
+        let snapshot = payload.to_snapshot(&exceptions, &mut metrics);
+        let aspas: Vec<_> = snapshot.aspas().map(|(aspa, info)| {
+            (
+                aspa.customer.into_u32(),
+                aspa.providers.iter().map(Asn::into_u32).collect::<Vec<_>>(),
+                info.iter().count(),
+            )
+        }).collect();
+        assert_eq!(
+            aspas,
+            [
+                (64497, vec![64500, 64502], 2),
+                (64498, vec![64500, 64503], 1),
+                (64510, vec![64511], 1),
+            ]
+        );
+        assert_eq!(metrics.tals[0].payload.aspas.valid, 3);
+        assert_eq!(metrics.tals[0].payload.aspas.locally_filtered, 1);
+        assert_eq!(metrics.local.aspas.contributed, 1);
+        assert_eq!(metrics.local.aspas.duplicate, 1);
+
+        // Assertions are not deduplicated when consuming.
+        #[derive(Default)]
+        struct CollectAspas(Vec<u32>);
+
+        impl PayloadConsumer for CollectAspas {
+            fn route_origin(&mut self, _: RouteOrigin, _: PayloadInfo) { }
+            fn router_key(&mut self, _: RouterKey, _: PayloadInfo) { }
+            fn aspa(&mut self, aspa: Aspa, _info: PayloadInfo) {
+                self.0.push(aspa.customer.into_u32())
+            }
+        }
+
+        let mut metrics = Metrics::new();
+        metrics.tals.push(TalMetrics::new(tal));
+        let mut consumer = CollectAspas::default();
+        payload.consume(&exceptions, &mut metrics, &mut consumer);
+        assert_eq!(consumer.0, [64497, 64498, 64497, 64510]);
+        assert_eq!(metrics.local.aspas.contributed, 2);
+    }
+
+    #[test]
+    fn merge_tal() {
+        fn payload(origins: &[(usize, &str)]) -> ValidatedPayload {
+            let report = ValidationReport::new(&Config::default());
+            for &(tal_index, prefix) in origins {
+                let tal = Arc::new(TalInfo::from_name("test".into()));
+                let mut point = PubPoint::new(Time::now(), tal_index);
+                point.repository_index = Some(0);
+                point.origins.push(PubRouteOrigin {
+                    origin: RouteOrigin::new(
+                        MaxLenPrefix::new(prefix.parse().unwrap(), None)
+                            .unwrap(),
+                        Asn::from_u32(64496),
+                    ),
+                    info: Arc::new(PublishInfo::imported(tal)),
+                });
+                report.pub_points.push(point);
+                let prefix: Prefix = prefix.parse().unwrap();
+                report.rejected.addrs.push((
+                    tal_index, true,
+                    rpki::repository::resources::Prefix::new(
+                        prefix.addr(), prefix.len()
+                    ).into()
+                ));
+            }
+            report.into_payload()
+        }
+
+        fn prefixes(payload: &ValidatedPayload) -> Vec<(usize, String)> {
+            let mut res: Vec<_> = payload.pub_points.iter().flat_map(|point| {
+                point.origins.iter().map(|origin| {
+                    (point.tal_index, origin.origin.prefix.to_string())
+                })
+            }).collect();
+            res.sort();
+            res
+        }
+
+        let old = payload(&[(0, "192.0.2.0/24"), (1, "198.51.100.0/24")]);
+        let new = payload(&[(0, "10.0.0.0/8"), (1, "203.0.113.0/24")]);
+        let merged = old.merge_tal(new, 1, &[3]);
+
+        assert_eq!(
+            prefixes(&merged),
+            [
+                (0, String::from("192.0.2.0/24")),
+                (1, String::from("203.0.113.0/24")),
+            ]
+        );
+        for point in &merged.pub_points {
+            assert_eq!(
+                point.repository_index,
+                Some(if point.tal_index == 1 { 3 } else { 0 })
+            );
+        }
+        for (prefix, keep) in [
+            ("192.0.2.0/24", false),
+            ("198.51.100.0/24", true),
+            ("203.0.113.0/24", false),
+            ("10.0.0.0/8", true),
+        ] {
+            assert_eq!(
+                merged.rejected.keep_prefix(prefix.parse().unwrap()), keep
+            );
+        }
+    }
+}
@@ -1,17 +1,19 @@
 //! Managing the process Routinator runs in.
 
-use std::{fs, io, mem, process};
+use std::{cmp, env, fmt, fs, io, mem, process};
 use std::future::Future;
 use std::io::Write;
 use std::net::TcpListener;
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use bytes::Bytes;
-use chrono::Utc;
-use log::{error, LevelFilter};
+use chrono::{DateTime, Utc};
+use log::{error, warn, LevelFilter};
 use tokio::runtime::Runtime;
-use crate::config::{Config, LogTarget};
+use crate::config::{Config, LogFormat, LogTarget};
 use crate::error::Failed;
 use crate::utils::date::{format_iso_date, format_local_iso_date};
 use crate::utils::fmt::WriteOrPanic;
@@ -93,12 +95,46 @@ impl Process {
         else {
             (None, None)
         };
-        let logger = Logger::new(&self.config, daemon, output)?;
+        let logger = Logger::new(&self.config, daemon, output, None)?;
         GLOBAL_LOGGER.switch(logger);
         log::set_max_level(self.config.log_level);
         Ok(res)
     }
 
+    /// Switches logging to the configured target in server mode.
+    ///
+    /// This does the same as [`switch_logging`][Self::switch_logging] but
+    /// also sets up the run journal if a journal directory is configured.
+    pub fn switch_server_logging(
+        &self,
+        daemon: bool,
+        with_output: bool
+    ) -> Result<(Option<LogOutput>, Option<RunJournal>), Failed> {
+        let (output, res) = if with_output {
+            let output = LogOutput::new();
+            (Some(output.0), Some(output.1))
+        }
+        else {
+            (None, None)
+        };
+        let (queue, journal) = match RunJournal::new(&self.config) {
+            Some((queue, journal)) => (Some(queue), Some(journal)),
+            None => (None, None)
+        };
+        let logger = Logger::new(&self.config, daemon, output, queue)?;
+        GLOBAL_LOGGER.switch(logger);
+        if journal.is_some() {
+            // The journal needs warnings even if the log doesn’t.
+            log::set_max_level(
+                cmp::max(self.config.log_level, LevelFilter::Warn)
+            );
+        }
+        else {
+            log::set_max_level(self.config.log_level);
+        }
+        Ok((res, journal))
+    }
+
     /// Rotates the log file if necessary.
     pub fn rotate_log(&self) -> Result<(), Failed> {
         GLOBAL_LOGGER.rotate()
@@ -159,6 +195,20 @@ impl Process {
             Ok(None)
         }
     }
+
+    /// Returns a notifier for the service manager.
+    ///
+    /// If we have been started by systemd with a notification socket, the
+    /// notifier will send state changes to it. Otherwise, it does nothing.
+    ///
+    /// This needs to be called after
+    /// [`setup_service`][Self::setup_service] so that the process ID
+    /// checked against the watchdog configuration is the final one, and
+    /// before [`drop_privileges`][Self::drop_privileges] so that the
+    /// socket can still be reached after changing the root directory.
+    pub fn service_notifier(&self) -> ServiceNotifier {
+        ServiceNotifier::from_env()
+    }
 }
 
 
@@ -208,8 +258,14 @@ struct Logger {
     /// An additional target for showing the log in the HTTP server.
     output: Option<Arc<Mutex<String>>>,
 
+    /// An additional target collecting the run journal.
+    journal: Option<JournalQueue>,
+
     /// The maximum log level.
     log_level: log::LevelFilter,
+
+    /// The format of log records.
+    format: LogFormat,
 }
 
 /// The actual target for logging
@@ -219,6 +275,7 @@ enum LogBackend {
     File {
         file: fs::File,
         path: PathBuf,
+        rotation: Option<LogRotation>,
     },
     Stderr {
         stderr: io::Stderr,
@@ -229,7 +286,8 @@ enum LogBackend {
 impl Logger {
     /// Creates a new logger from config and additional information.
     fn new(
-        config: &Config, daemon: bool, output: Option<Arc<Mutex<String>>>
+        config: &Config, daemon: bool, output: Option<Arc<Mutex<String>>>,
+        journal: Option<JournalQueue>,
     ) -> Result<Self, Failed> {
         let target = match config.log_target {
             #[cfg(unix)]
@@ -246,7 +304,9 @@ impl Logger {
                 Self::new_syslog_target(facility)?
             }
             LogTarget::File(ref path) => {
-                Self::new_file_target(path.clone())?
+                Self::new_file_target(
+                    path.clone(), LogRotation::from_config(config)
+                )?
             }
             LogTarget::Stderr => {
                 Self::new_stderr_target(daemon)
@@ -255,7 +315,9 @@ impl Logger {
         Ok(Self {
             target: Mutex::new(target),
             output,
+            journal,
             log_level: config.log_level,
+            format: config.log_format,
         })
     }
 
@@ -267,24 +329,27 @@ impl Logger {
         SyslogLogger::new(facility).map(LogBackend::Syslog)
     }
 
-    fn new_file_target(path: PathBuf) -> Result<LogBackend, Failed> {
-        Ok(LogBackend::File {
-            file: match Self::open_log_file(&path) {
-                Ok(file) => file,
-                Err(err) => {
-                    error!(
-                        "Failed to open log file '{}': {}",
-                        path.display(), err
-                    );
-                    return Err(Failed)
-                }
-            },
-            path
-        })
+    fn new_file_target(
+        path: PathBuf, mut rotation: Option<LogRotation>,
+    ) -> Result<LogBackend, Failed> {
+        let file = match Self::open_log_file(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                error!(
+                    "Failed to open log file '{}': {}",
+                    path.display(), err
+                );
+                return Err(Failed)
+            }
+        };
+        if let Some(rotation) = rotation.as_mut() {
+            rotation.reset(&file);
+        }
+        Ok(LogBackend::File { file, path, rotation })
     }
 
     /// Opens a log file.
-    fn open_log_file(path: &PathBuf) -> Result<fs::File, io::Error> {
+    fn open_log_file(path: &Path) -> Result<fs::File, io::Error> {
         fs::OpenOptions::new().create(true).append(true).open(path)
     }
 
@@ -304,6 +369,20 @@ impl Logger {
             return;
         }
 
+        if let Some(journal) = self.journal.as_ref() {
+            if record.level() <= log::Level::Warn {
+                if let Some((_, records)) = journal.lock().as_mut() {
+                    writeln!(records, "{}", json_record(record));
+                }
+            }
+        }
+
+        // The journal may have raised the maximum level, so we need to
+        // check ourselves.
+        if record.level() > self.log_level {
+            return;
+        }
+
         if let Some(output) = self.output.as_ref() {
             writeln!(output.lock(), "{}", record.args());
         }
@@ -317,17 +396,46 @@ impl Logger {
     fn try_log(&self, record: &log::Record) -> Result<(), io::Error> {
         match self.target.lock().deref_mut() {
             #[cfg(unix)]
-            LogBackend::Syslog(ref mut logger) => logger.log(record),
-            LogBackend::File { ref mut file, .. } => {
-                writeln!(
-                    file, "[{}] [{}] {}",
-                    format_local_iso_date(chrono::Local::now()),
-                    record.level(),
-                    record.args()
-                )
+            LogBackend::Syslog(ref mut logger) => {
+                match self.format {
+                    LogFormat::Text => {
+                        logger.log(record.level(), record.args())
+                    }
+                    LogFormat::Json => {
+                        logger.log(record.level(), json_record(record))
+                    }
+                }
+            }
+            LogBackend::File { ref mut file, ref path, ref mut rotation } => {
+                let line = if self.format == LogFormat::Json {
+                    format!("{}\n", json_record(record))
+                }
+                else {
+                    format!(
+                        "[{}] [{}] {}\n",
+                        format_local_iso_date(chrono::Local::now()),
+                        record.level(),
+                        record.args()
+                    )
+                };
+                file.write_all(line.as_bytes())?;
+
+                // Since we are holding the lock on the target, rotating
+                // here is atomic with respect to other log calls.
+                if let Some(rotation) = rotation.as_mut() {
+                    rotation.written += line.len() as u64;
+                    if rotation.is_due() {
+                        rotation.rotate(file, path)?;
+                    }
+                }
+                Ok(())
             }
             LogBackend::Stderr{ ref mut stderr, timestamp } => {
                 // We never fail when writing to stderr.
+                if self.format == LogFormat::Json {
+                    let _ = writeln!(stderr, "{}", json_record(record));
+                    return Ok(())
+                }
                 if *timestamp {
                     let _ = write!(stderr, "[{}] ",
                         format_local_iso_date(chrono::Local::now()),
@@ -411,10 +519,12 @@ impl Logger {
 
     /// Rotates the log target if necessary.
     ///
-    /// This method exits the whole process when rotating fails.
+    /// For a log file, this re-opens the file so that it can be rotated by
+    /// external tools. This method exits the whole process when rotating
+    /// fails.
     fn rotate(&self) -> Result<(), Failed> {
         if let LogBackend::File {
-            ref mut file, ref path
+            ref mut file, ref path, ref mut rotation
         } = self.target.lock().deref_mut() {
             // This tries to open the file. If this fails, it writes a
             // message to both the old file and stderr and then exits.
@@ -431,7 +541,177 @@ impl Logger {
                     );
                     return Err(Failed)
                 }
+            };
+            if let Some(rotation) = rotation.as_mut() {
+                rotation.reset(file);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ LogRotation ---------------------------------------------------
+
+/// The state of the built-in rotation of a log file.
+///
+/// When rotating, the current log file is renamed by appending `.1` to its
+/// name, with existing rotated files moving up one number, and a new file
+/// is started. Only the configured number of rotated files are kept.
+struct LogRotation {
+    /// The size of the log file after which it is rotated.
+    size: Option<u64>,
+
+    /// The time after which the log file is rotated.
+    interval: Option<Duration>,
+
+    /// The number of rotated files to keep.
+    keep: usize,
+
+    /// The number of bytes in the current log file.
+    written: u64,
+
+    /// The time the current log file was started.
+    started: Instant,
+}
+
+impl LogRotation {
+    /// Creates the rotation state from the config if rotation is enabled.
+    fn from_config(config: &Config) -> Option<Self> {
+        if config.log_rotate_size.is_none()
+            && config.log_rotate_interval.is_none()
+        {
+            return None
+        }
+        Some(LogRotation {
+            size: config.log_rotate_size,
+            interval: config.log_rotate_interval,
+            keep: config.log_rotate_keep,
+            written: 0,
+            started: Instant::now(),
+        })
+    }
+
+    /// Resets the state for a newly opened log file.
+    ///
+    /// Since the file may already exist, its current size is used.
+    fn reset(&mut self, file: &fs::File) {
+        self.written = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        self.started = Instant::now();
+    }
+
+    /// Returns whether the log file should be rotated now.
+    fn is_due(&self) -> bool {
+        if let Some(size) = self.size {
+            if self.written >= size {
+                return true
+            }
+        }
+        if let Some(interval) = self.interval {
+            if self.started.elapsed() >= interval {
+                return true
+            }
+        }
+        false
+    }
+
+    /// Rotates the log file.
+    ///
+    /// If moving the existing files fails, a message is added to the
+    /// current log file which then continues to be used. Only if opening
+    /// a new log file fails, an error is returned.
+    fn rotate(
+        &mut self, file: &mut fs::File, path: &Path
+    ) -> Result<(), io::Error> {
+        if let Err(err) = Self::move_files(path, self.keep) {
+            writeln!(
+                file, "Rotating log file {} failed: {}",
+                path.display(), err
+            )?;
+            // Try again later rather than for every single message.
+            self.reset(file);
+            self.written = 0;
+            return Ok(())
+        }
+        *file = Logger::open_log_file(path)?;
+        self.reset(file);
+        Ok(())
+    }
+
+    /// Moves the current log file out of the way and prunes old files.
+    fn move_files(path: &Path, keep: usize) -> Result<(), io::Error> {
+        if keep == 0 {
+            fs::remove_file(path)?;
+        }
+        else {
+            for idx in (1..keep).rev() {
+                match fs::rename(
+                    Self::rotated_path(path, idx),
+                    Self::rotated_path(path, idx + 1)
+                ) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        return Err(err)
+                    }
+                    _ => { }
+                }
             }
+            fs::rename(path, Self::rotated_path(path, 1))?;
+        }
+
+        // Remove files left over from a larger number of files to keep.
+        let mut idx = keep + 1;
+        while fs::remove_file(Self::rotated_path(path, idx)).is_ok() {
+            idx += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the rotated file with the given number.
+    fn rotated_path(path: &Path, idx: usize) -> PathBuf {
+        let mut res = path.as_os_str().to_owned();
+        res.push(format!(".{}", idx));
+        res.into()
+    }
+}
+
+
+//------------ json_record ---------------------------------------------------
+
+/// Formats a log record as a single-line JSON object.
+///
+/// The object contains the timestamp, level, target, and message of the
+/// record as well as all its key-value pairs. The latter cannot override
+/// the former.
+fn json_record(record: &log::Record) -> String {
+    let mut fields = JsonFields(serde_json::Map::new());
+    fields.insert("timestamp", format_iso_date(Utc::now()).to_string());
+    fields.insert("level", record.level().as_str());
+    fields.insert("target", record.target());
+    fields.insert("message", record.args().to_string());
+    let _ = record.key_values().visit(&mut fields);
+    serde_json::Value::Object(fields.0).to_string()
+}
+
+
+//------------ JsonFields ----------------------------------------------------
+
+/// The members of a log record formatted as JSON.
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl JsonFields {
+    /// Adds a member unless it is already present.
+    fn insert(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        self.0.entry(key).or_insert_with(|| value.into());
+    }
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields {
+    fn visit_pair(
+        &mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>
+    ) -> Result<(), log::kv::Error> {
+        match value.to_borrowed_str() {
+            Some(value) => self.insert(key.as_str(), value),
+            None => self.insert(key.as_str(), value.to_string()),
         }
         Ok(())
     }
@@ -479,16 +759,18 @@ impl SyslogLogger {
         }
     }
 
-    /// Tries logging.
-    fn log(&mut self, record: &log::Record) -> Result<(), io::Error> {
-        match record.level() {
-            log::Level::Error => self.0.err(record.args()),
-            log::Level::Warn => self.0.warning(record.args()),
-            log::Level::Info => self.0.info(record.args()),
-            log::Level::Debug => self.0.debug(record.args()),
+    /// Tries logging a message with the given level.
+    fn log(
+        &mut self, level: log::Level, message: impl fmt::Display
+    ) -> Result<(), io::Error> {
+        match level {
+            log::Level::Error => self.0.err(message),
+            log::Level::Warn => self.0.warning(message),
+            log::Level::Info => self.0.info(message),
+            log::Level::Debug => self.0.debug(message),
             log::Level::Trace => {
                 // Syslog doesn’t have trace, use debug instead.
-                self.0.debug(record.args())
+                self.0.debug(message)
             }
         }.map_err(|err| {
             match err.0 {
@@ -613,13 +895,305 @@ impl LogOutput {
 }
 
 
+//------------ RunJournal ----------------------------------------------------
+
+/// The queue the logger adds journal records to.
+///
+/// If there is no validation run currently ongoing, this is `None`.
+/// Otherwise it contains the start time of the run and the records
+/// collected so far, each a single-line JSON object.
+type JournalQueue = Arc<Mutex<Option<(DateTime<Utc>, String)>>>;
+
+/// The journal of warnings and errors logged during validation runs.
+///
+/// While a run is ongoing, all log records of level warning or error are
+/// collected. When the run has finished, they are written as JSON lines to
+/// a new file in the journal directory. Only the configured number of most
+/// recent files is kept. The journal of the last finished run is also kept
+/// in memory so it can be served by the HTTP server.
+#[derive(Debug)]
+pub struct RunJournal {
+    /// The queue the logger adds records to.
+    queue: JournalQueue,
+
+    /// The directory the journal files are written to.
+    dir: PathBuf,
+
+    /// The number of journal files to keep.
+    keep: usize,
+
+    /// The journal of the last finished run.
+    current: RwLock<Option<Arc<JournalFile>>>,
+}
+
+impl RunJournal {
+    /// The prefix of the journal file names.
+    const FILE_PREFIX: &'static str = "journal-";
+
+    /// The suffix of the journal file names.
+    const FILE_SUFFIX: &'static str = ".jsonl";
+
+    /// Creates a new journal if one is configured.
+    fn new(config: &Config) -> Option<(JournalQueue, Self)> {
+        let dir = config.journal_dir.clone()?;
+        let queue = Arc::new(Mutex::new(None));
+        let res = RunJournal {
+            queue: queue.clone(),
+            dir,
+            keep: config.journal_keep,
+            current: RwLock::new(None),
+        };
+        Some((queue, res))
+    }
+
+    /// Starts collecting records for a new validation run.
+    ///
+    /// Any records collected for a previous run that hasn’t been finished
+    /// are dropped.
+    pub fn start(&self) {
+        *self.queue.lock() = Some((Utc::now(), String::new()));
+    }
+
+    /// Finishes the current validation run.
+    ///
+    /// Writes the collected records to a new journal file and removes
+    /// journal files beyond the configured number. Failing to do so is
+    /// logged but otherwise ignored.
+    pub fn finish(&self) {
+        let (started, records) = match self.queue.lock().take() {
+            Some(item) => item,
+            None => return,
+        };
+        let path = self.dir.join(format!(
+            "{}{}{}",
+            Self::FILE_PREFIX,
+            started.format("%Y%m%dT%H%M%SZ"),
+            Self::FILE_SUFFIX,
+        ));
+        let file = JournalFile {
+            records: records.lines().count(),
+            content: records.into(),
+            path,
+        };
+        if let Err(err) = self.write(&file.path, &file.content) {
+            warn!(
+                "Failed to write run journal {}: {}",
+                file.path.display(), err
+            );
+        }
+        else if let Err(err) = self.prune() {
+            warn!(
+                "Failed to clean up run journals in {}: {}",
+                self.dir.display(), err
+            );
+        }
+        *self.current.write() = Some(Arc::new(file));
+    }
+
+    /// Returns the journal of the last finished run.
+    pub fn current(&self) -> Option<Arc<JournalFile>> {
+        self.current.read().clone()
+    }
+
+    /// Writes a journal file.
+    fn write(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, content)
+    }
+
+    /// Removes all but the most recent journal files.
+    fn prune(&self) -> Result<(), io::Error> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let is_journal = entry.file_name().to_str().map(|name| {
+                name.starts_with(Self::FILE_PREFIX)
+                && name.ends_with(Self::FILE_SUFFIX)
+            }).unwrap_or(false);
+            if is_journal {
+                files.push(entry.path())
+            }
+        }
+
+        // The file names contain the time stamp in a format that sorts
+        // lexically.
+        files.sort();
+        let excess = files.len().saturating_sub(self.keep);
+        for path in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ JournalFile ---------------------------------------------------
+
+/// The journal of a finished validation run.
+#[derive(Debug)]
+pub struct JournalFile {
+    /// The path of the file the journal was written to.
+    pub path: PathBuf,
+
+    /// The number of records in the journal.
+    pub records: usize,
+
+    /// The content of the journal.
+    pub content: Bytes,
+}
+
+
+//------------ ServiceNotifier -----------------------------------------------
+
+/// Sends state notifications to the service manager.
+///
+/// This implements the systemd notification protocol: If the environment
+/// variable `NOTIFY_SOCKET` is set, state changes are sent as datagrams to
+/// the Unix socket named by it. If the variable isn’t present or we aren’t
+/// running on a Unix system, all notifications are silently dropped.
+///
+/// Values of the notifier can be cloned cheaply. All clones refer to the
+/// same socket.
+#[derive(Clone, Debug, Default)]
+pub struct ServiceNotifier {
+    inner: Option<Arc<NotifierInner>>,
+}
+
+#[derive(Debug)]
+struct NotifierInner {
+    /// The socket to send notifications to.
+    socket: NotifySocket,
+
+    /// The interval for sending watchdog notifications, if requested.
+    watchdog: Option<Duration>,
+
+    /// Have we told the service manager that we are ready?
+    ready: AtomicBool,
+}
+
+impl ServiceNotifier {
+    /// Creates a notifier from the environment.
+    ///
+    /// Removes the variables of the notification protocol from the
+    /// environment so that they aren’t passed on to child processes.
+    fn from_env() -> Self {
+        let socket = match env::var_os("NOTIFY_SOCKET") {
+            Some(path) => NotifySocket::connect(&path),
+            None => None,
+        };
+        let watchdog = Self::watchdog_from_env();
+        env::remove_var("NOTIFY_SOCKET");
+        env::remove_var("WATCHDOG_USEC");
+        env::remove_var("WATCHDOG_PID");
+        ServiceNotifier {
+            inner: socket.map(|socket| {
+                Arc::new(NotifierInner {
+                    socket, watchdog,
+                    ready: AtomicBool::new(false),
+                })
+            })
+        }
+    }
+
+    /// Returns the watchdog interval requested via the environment.
+    ///
+    /// The returned interval is half the watchdog timeout as recommended
+    /// by the systemd documentation.
+    fn watchdog_from_env() -> Option<Duration> {
+        if let Some(pid) = env::var_os("WATCHDOG_PID") {
+            if pid.to_str()?.parse::<u32>().ok()? != process::id() {
+                return None
+            }
+        }
+        let usec = env::var_os("WATCHDOG_USEC")?;
+        let usec = usec.to_str()?.parse::<u64>().ok()?;
+        if usec == 0 {
+            return None
+        }
+        Some(Duration::from_micros(usec) / 2)
+    }
+
+    /// Returns the interval at which watchdog notifications should be sent.
+    ///
+    /// Returns `None` if the service manager hasn’t asked for them.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.inner.as_ref().and_then(|inner| inner.watchdog)
+    }
+
+    /// Notifies that the service has finished starting up or reloading.
+    ///
+    /// The notification is only sent once after start or a reload.
+    pub fn ready(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            if !inner.ready.swap(true, Ordering::Relaxed) {
+                inner.socket.send("READY=1")
+            }
+        }
+    }
+
+    /// Notifies that the service is reloading its configuration.
+    ///
+    /// A call to [`ready`][Self::ready] needs to follow once reloading has
+    /// finished. If the service hasn’t been ready before, nothing is sent
+    /// since it is still starting up.
+    pub fn reloading(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            if !inner.ready.swap(false, Ordering::Relaxed) {
+                return
+            }
+            match NotifySocket::monotonic_usec() {
+                Some(usec) => {
+                    inner.socket.send(&format!(
+                        "RELOADING=1\nMONOTONIC_USEC={}", usec
+                    ))
+                }
+                None => inner.socket.send("RELOADING=1")
+            }
+        }
+    }
+
+    /// Notifies that the service is shutting down.
+    pub fn stopping(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            inner.socket.send("STOPPING=1")
+        }
+    }
+
+    /// Sends a free-form status message.
+    pub fn status(&self, status: &str) {
+        if let Some(inner) = self.inner.as_ref() {
+            inner.socket.send(&status_message(status))
+        }
+    }
+
+    /// Sends a watchdog keep-alive notification.
+    pub fn watchdog(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            inner.socket.send("WATCHDOG=1")
+        }
+    }
+}
+
+/// Creates the notification for the status message.
+///
+/// Since notifications are newline separated, any line breaks in the
+/// status are replaced with spaces.
+fn status_message(status: &str) -> String {
+    let mut res = String::from("STATUS=");
+    res.extend(status.chars().map(|ch| {
+        if ch == '\n' || ch == '\r' { ' ' } else { ch }
+    }));
+    res
+}
+
+
 //------------ Platform-dependent Service Implementation ---------------------
 
 #[cfg(unix)]
-use self::unix::ServiceImpl;
+use self::unix::{NotifySocket, ServiceImpl};
 
 #[cfg(not(unix))]
-use self::noop::ServiceImpl;
+use self::noop::{NotifySocket, ServiceImpl};
 
 
 /// Unix “Service.”
@@ -630,12 +1204,15 @@ use self::noop::ServiceImpl;
 #[cfg(unix)]
 mod unix {
     use std::env::set_current_dir;
-    use std::ffi::CString;
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
     use std::os::unix::io::RawFd;
+    use std::os::unix::net::UnixDatagram;
     use std::path::Path;
-    use log::error;
+    use log::{debug, error, warn};
     use nix::libc;
     use nix::fcntl::{flock, open, FlockArg, OFlag};
+    use nix::time::{clock_gettime, ClockId};
     use nix::unistd::{
         chown, chroot, fork, getpid, setgid, setuid, write, Gid, Uid
     };
@@ -861,13 +1438,114 @@ mod unix {
             Ok(())
         }
     }
+
+    /// The socket for sending notifications to systemd.
+    #[derive(Debug)]
+    pub struct NotifySocket {
+        sock: UnixDatagram,
+    }
+
+    impl NotifySocket {
+        /// Connects to the notification socket at the given address.
+        ///
+        /// The socket is connected right away so that it stays reachable
+        /// after changing the root directory or dropping privileges.
+        pub fn connect(addr: &OsStr) -> Option<Self> {
+            let sock = match UnixDatagram::unbound() {
+                Ok(sock) => sock,
+                Err(err) => {
+                    warn!("Failed to create notification socket: {}", err);
+                    return None
+                }
+            };
+            let res = match addr.as_bytes().split_first() {
+                Some((b'@', name)) => Self::connect_abstract(&sock, name),
+                Some((b'/', _)) => sock.connect(addr),
+                _ => {
+                    warn!(
+                        "Ignoring unsupported notification socket '{}'.",
+                        addr.to_string_lossy()
+                    );
+                    return None
+                }
+            };
+            if let Err(err) = res {
+                warn!(
+                    "Failed to connect to notification socket '{}': {}",
+                    addr.to_string_lossy(), err
+                );
+                return None
+            }
+            Some(NotifySocket { sock })
+        }
+
+        /// Connects to a socket in the abstract namespace.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        fn connect_abstract(
+            sock: &UnixDatagram, name: &[u8]
+        ) -> Result<(), std::io::Error> {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            sock.connect_addr(&SocketAddr::from_abstract_name(name)?)
+        }
+
+        /// Connects to a socket in the abstract namespace.
+        ///
+        /// This is only supported on Linux.
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        fn connect_abstract(
+            _sock: &UnixDatagram, _name: &[u8]
+        ) -> Result<(), std::io::Error> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "abstract sockets not supported on this system"
+            ))
+        }
+
+        /// Sends a notification.
+        ///
+        /// Errors are logged and otherwise ignored.
+        pub fn send(&self, state: &str) {
+            if let Err(err) = self.sock.send(state.as_bytes()) {
+                debug!("Failed to send service notification: {}", err);
+            }
+        }
+
+        /// Returns the current value of the monotonic clock.
+        pub fn monotonic_usec() -> Option<u64> {
+            let now = clock_gettime(ClockId::CLOCK_MONOTONIC).ok()?;
+            let secs = u64::try_from(now.tv_sec()).ok()?;
+            let nsecs = u64::try_from(now.tv_nsec()).ok()?;
+            Some(secs * 1_000_000 + nsecs / 1_000)
+        }
+    }
 }
 
 #[cfg(not(unix))]
 mod noop {
+    use std::ffi::OsStr;
     use crate::error::Failed;
     use crate::config::Config;
 
+    #[derive(Debug)]
+    pub struct NotifySocket;
+
+    impl NotifySocket {
+        pub fn connect(_addr: &OsStr) -> Option<Self> {
+            None
+        }
+
+        pub fn send(&self, _state: &str) { }
+
+        pub fn monotonic_usec() -> Option<u64> {
+            None
+        }
+    }
+
     pub struct ServiceImpl;
 
     impl ServiceImpl {
@@ -892,3 +1570,120 @@ mod noop {
         }
     }
 }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routinator.log");
+        let mut rotation = LogRotation {
+            size: Some(10),
+            interval: None,
+            keep: 2,
+            written: 0,
+            started: Instant::now(),
+        };
+        let mut file = Logger::open_log_file(&path).unwrap();
+        for content in ["first", "second", "third"] {
+            writeln!(file, "{}", content).unwrap();
+            rotation.written += 20;
+            assert!(rotation.is_due());
+            rotation.rotate(&mut file, &path).unwrap();
+            assert!(!rotation.is_due());
+        }
+        assert_eq!(fs::read(&path).unwrap(), b"");
+        assert_eq!(
+            fs::read(LogRotation::rotated_path(&path, 1)).unwrap(),
+            b"third\n"
+        );
+        assert_eq!(
+            fs::read(LogRotation::rotated_path(&path, 2)).unwrap(),
+            b"second\n"
+        );
+        assert!(!LogRotation::rotated_path(&path, 3).exists());
+
+        // Reducing the number of files to keep prunes the excess.
+        rotation.keep = 0;
+        rotation.rotate(&mut file, &path).unwrap();
+        assert!(!LogRotation::rotated_path(&path, 1).exists());
+        assert!(!LogRotation::rotated_path(&path, 2).exists());
+    }
+
+    #[test]
+    fn run_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_with_paths(
+            dir.path().into(), dir.path().into()
+        );
+        config.journal_dir = Some(dir.path().join("journal"));
+        config.journal_keep = 2;
+        let (queue, journal) = RunJournal::new(&config).unwrap();
+        assert!(journal.current().is_none());
+
+        for (i, started) in [
+            "2024-01-01T00:00:00Z", "2024-01-01T01:00:00Z",
+            "2024-01-01T02:00:00Z",
+        ].into_iter().enumerate() {
+            journal.start();
+            let mut records = String::new();
+            for _ in 0..i {
+                records.push_str("{}\n");
+            }
+            *queue.lock() = Some((started.parse().unwrap(), records));
+            journal.finish();
+        }
+
+        let current = journal.current().unwrap();
+        assert_eq!(current.records, 2);
+        assert_eq!(
+            current.path,
+            dir.path().join("journal/journal-20240101T020000Z.jsonl")
+        );
+        let mut names: Vec<_> = fs::read_dir(dir.path().join("journal"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "journal-20240101T010000Z.jsonl",
+                "journal-20240101T020000Z.jsonl"
+            ]
+        );
+        assert_eq!(fs::read(&current.path).unwrap(), b"{}\n{}\n");
+
+        // Finishing without starting does nothing.
+        journal.finish();
+        assert_eq!(journal.current().unwrap().path, current.path);
+    }
+
+    #[test]
+    fn service_status_message() {
+        assert_eq!(
+            status_message("Serving serial 12."), "STATUS=Serving serial 12."
+        );
+        assert_eq!(
+            status_message("Line one\nline two\r\n"),
+            "STATUS=Line one line two  "
+        );
+    }
+
+    #[test]
+    fn service_notifier_without_socket() {
+        // A notifier without a socket must silently ignore everything.
+        let notifier = ServiceNotifier::default();
+        assert!(notifier.watchdog_interval().is_none());
+        notifier.ready();
+        notifier.reloading();
+        notifier.status("Testing.");
+        notifier.watchdog();
+        notifier.stopping();
+    }
+}
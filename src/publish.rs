@@ -0,0 +1,285 @@
+//! Publishing the payload to remote targets.
+//!
+//! In server mode, Routinator can upload the payload in a given output
+//! format to HTTP servers – such as object storage – after each validation
+//! run. A [`PublishTarget`] describes one such upload. The [`Publisher`]
+//! performs the uploads on a thread of its own so they never delay the
+//! distribution of new data via RTR.
+
+use std::{fs, io, thread};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use chrono::Utc;
+use log::{error, info};
+use reqwest::{header, Method, Url};
+use reqwest::blocking::Client;
+use rpki::rtr::Serial;
+use crate::config::Config;
+use crate::error::Failed;
+use crate::metrics::{Metrics, PublishMetrics};
+use crate::output::{Output, OutputFormat};
+use crate::payload::{PayloadSnapshot, SharedHistory};
+
+
+//------------ PublishTarget -------------------------------------------------
+
+/// A remote target the payload is uploaded to after each run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishTarget {
+    /// The URL to upload to.
+    pub url: Url,
+
+    /// The output format to upload.
+    pub format: OutputFormat,
+
+    /// The HTTP method to use for the upload.
+    pub method: PublishMethod,
+
+    /// The authentication to use, if any.
+    pub auth: Option<PublishAuth>,
+}
+
+impl PublishTarget {
+    /// Uploads the payload to the target.
+    ///
+    /// Returns an error message if the upload failed.
+    fn publish(
+        &self,
+        client: &Client,
+        output: Output,
+        snapshot: Arc<PayloadSnapshot>,
+        metrics: Arc<Metrics>,
+    ) -> Result<(), String> {
+        let mut body = Vec::new();
+        output.write(snapshot, metrics, self.format, &mut body).map_err(
+            |err| format!("failed to render output: {}", err)
+        )?;
+        let mut request = client.request(
+            self.method.as_method(), self.url.clone()
+        ).header(
+            header::CONTENT_TYPE,
+            self.format.content_type().as_slice()
+        ).body(body);
+        match self.auth.as_ref() {
+            Some(PublishAuth::Bearer(path)) => {
+                request = request.bearer_auth(
+                    Self::read_credentials(path)?
+                );
+            }
+            Some(PublishAuth::Basic(path)) => {
+                let credentials = Self::read_credentials(path)?;
+                let (user, password) = match credentials.split_once(':') {
+                    Some((user, password)) => (user, Some(password)),
+                    None => (credentials.as_str(), None),
+                };
+                request = request.basic_auth(user, password);
+            }
+            None => { }
+        }
+        let response = request.send().map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("server responded with {}", response.status()))
+        }
+        Ok(())
+    }
+
+    /// Reads the first line of a credentials file.
+    fn read_credentials(path: &PathBuf) -> Result<String, String> {
+        let content = fs::read_to_string(path).map_err(|err| {
+            format!(
+                "failed to read credentials file {}: {}",
+                path.display(), err
+            )
+        })?;
+        Ok(content.lines().next().unwrap_or("").trim().into())
+    }
+}
+
+
+//------------ PublishMethod -------------------------------------------------
+
+/// The HTTP method used for uploading.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PublishMethod {
+    /// Use a PUT request.
+    #[default]
+    Put,
+
+    /// Use a POST request.
+    Post,
+}
+
+impl PublishMethod {
+    /// Returns the method for the given string if it is valid.
+    pub fn try_from_str(value: &str) -> Option<Self> {
+        match value {
+            "PUT" | "put" => Some(PublishMethod::Put),
+            "POST" | "post" => Some(PublishMethod::Post),
+            _ => None
+        }
+    }
+
+    /// Returns the name of the method.
+    pub fn name(self) -> &'static str {
+        match self {
+            PublishMethod::Put => "PUT",
+            PublishMethod::Post => "POST",
+        }
+    }
+
+    /// Returns the HTTP method.
+    fn as_method(self) -> Method {
+        match self {
+            PublishMethod::Put => Method::PUT,
+            PublishMethod::Post => Method::POST,
+        }
+    }
+}
+
+
+//------------ PublishAuth ---------------------------------------------------
+
+/// The authentication used for uploading.
+///
+/// Both variants refer to a file containing the credentials on its first
+/// line. The file is read anew for every upload so credentials can be
+/// rotated without a restart.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PublishAuth {
+    /// Use a bearer token.
+    Bearer(PathBuf),
+
+    /// Use basic authentication with a user name and password.
+    ///
+    /// The file contains the user name and password separated by a colon.
+    Basic(PathBuf),
+}
+
+
+//------------ Publisher -----------------------------------------------------
+
+/// Uploads the payload to all publish targets.
+///
+/// The publisher runs on a thread of its own. Each call to
+/// [`trigger`][Self::trigger] causes it to upload the current payload to
+/// all targets that haven’t received the current serial yet. Targets that
+/// failed are thus retried the next time.
+#[derive(Debug)]
+pub struct Publisher {
+    /// The sending end of the trigger channel.
+    tx: mpsc::SyncSender<()>,
+}
+
+impl Publisher {
+    /// Starts the publisher if there are publish targets.
+    pub fn start(
+        config: &Config, history: SharedHistory
+    ) -> Result<Option<Self>, Failed> {
+        if config.publish_targets.is_empty() {
+            return Ok(None)
+        }
+        let client = Client::builder()
+            .user_agent(&config.rrdp_user_agent)
+            .timeout(config.publish_timeout)
+            .build()
+            .map_err(|err| {
+                error!("Failed to create HTTP client for publishing: {}", err);
+                Failed
+            })?;
+        let mut state = PublisherState {
+            client,
+            output: Output::from_config(config),
+            targets: config.publish_targets.iter().map(|target| {
+                (target.clone(), None)
+            }).collect(),
+            metrics: config.publish_targets.iter().map(|target| {
+                PublishMetrics::new(target.url.as_str().into())
+            }).collect(),
+            history,
+        };
+
+        // A pending trigger is enough. So we only need space for one.
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::Builder::new().name("publisher".into()).spawn(move || {
+            while rx.recv().is_ok() {
+                state.publish()
+            }
+        }).map_err(|err: io::Error| {
+            error!("Failed to start publisher thread: {}", err);
+            Failed
+        })?;
+        Ok(Some(Publisher { tx }))
+    }
+
+    /// Triggers uploading the current payload.
+    pub fn trigger(&self) {
+        // If the channel is full, an upload is pending already. If it is
+        // disconnected, there is nothing we can do.
+        let _ = self.tx.try_send(());
+    }
+}
+
+
+//------------ PublisherState ------------------------------------------------
+
+/// The state of the publisher thread.
+struct PublisherState {
+    /// The HTTP client.
+    client: Client,
+
+    /// The output settings.
+    output: Output,
+
+    /// The targets and the session and serial they last received.
+    targets: Vec<(PublishTarget, Option<(u64, Serial)>)>,
+
+    /// The metrics for each target.
+    metrics: Vec<PublishMetrics>,
+
+    /// The history to take the payload from.
+    history: SharedHistory,
+}
+
+impl PublisherState {
+    /// Uploads the current payload to all targets that need it.
+    fn publish(&mut self) {
+        let (state, snapshot, metrics) = {
+            let history = self.history.read();
+            match (history.current(), history.metrics()) {
+                (Some(snapshot), Some(metrics)) => {
+                    (history.session_and_serial(), snapshot, metrics)
+                }
+                _ => return,
+            }
+        };
+        let mut output = self.output.clone();
+        output.set_rtr_state(state.0, state.1);
+        for ((target, published), target_metrics) in
+            self.targets.iter_mut().zip(self.metrics.iter_mut())
+        {
+            if *published == Some(state) {
+                continue
+            }
+            match target.publish(
+                &self.client, output.clone(),
+                snapshot.clone(), metrics.clone()
+            ) {
+                Ok(()) => {
+                    info!("Published serial {} to {}.", state.1, target.url);
+                    *published = Some(state);
+                    target_metrics.last_success = Some(Utc::now());
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to publish to {}: {}. Trying again after \
+                         the next validation run.",
+                        target.url, err
+                    );
+                    target_metrics.failures += 1;
+                    target_metrics.last_failure = Some(Utc::now());
+                }
+            }
+        }
+        self.history.set_publish_metrics(self.metrics.clone());
+    }
+}
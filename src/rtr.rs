@@ -1,8 +1,8 @@
 /// Support for the RPKI-to-Router Protocol.
 
-use std::io;
+use std::{cmp, io};
 use std::future::Future;
-use std::net::{SocketAddr, TcpListener as StdListener};
+use std::net::{IpAddr, SocketAddr, TcpListener as StdListener};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -19,8 +19,12 @@ use crate::config::Config;
 use crate::error::ExitError;
 use crate::metrics::{SharedRtrServerMetrics, RtrClientMetrics};
 use crate::payload::SharedHistory;
-use crate::utils::{net, tls};
-use crate::utils::tls::MaybeTlsTcpStream;
+use crate::utils::net;
+use crate::utils::net::StdUnixListener;
+use crate::utils::shutdown::{DrainGuard, Shutdown};
+use crate::utils::tls::{
+    MaybeTlsTcpStream, ReloadableServerConfig, TlsConfigs
+};
 
 
 //------------ rtr_listener --------------------------------------------------
@@ -32,6 +36,8 @@ pub fn rtr_listener(
     config: &Config,
     sender: NotifySender,
     extra_listener: Option<StdListener>,
+    shutdown: Shutdown,
+    tls_configs: &TlsConfigs,
 ) -> Result<impl Future<Output = ()>, ExitError> {
     // Binding needs to have happened before dropping privileges
     // during detach. So we do this here synchronously.
@@ -40,26 +46,35 @@ pub fn rtr_listener(
         listeners.push((String::from("systemd socket"), None, extra));
     }
     for addr in &config.rtr_listen {
-        listeners.push((format!("{}", addr), None, net::bind(addr)?));
+        for (addr, sock) in net::bind_listen_addr(addr, config)? {
+            listeners.push((format!("{}", addr), None, sock));
+        }
     }
     if !config.rtr_tls_listen.is_empty() {
-        let tls_config = create_tls_config(config)?;
+        let tls_config = create_tls_config(config, tls_configs)?;
         for addr in &config.rtr_tls_listen {
-            listeners.push((
-                format!("{}", addr),
-                Some(tls_config.clone()),
-                net::bind(addr)?
-            ));
+            for (addr, sock) in net::bind_listen_addr(addr, config)? {
+                listeners.push((
+                    format!("{}", addr), Some(tls_config.clone()), sock
+                ));
+            }
         }
     }
+    let mut unix_listeners = Vec::new();
+    for path in &config.rtr_listen_unix {
+        unix_listeners.push((
+            format!("{}", path.display()), net::bind_unix(path)?
+        ));
+    }
     Ok(_rtr_listener(
-        history, metrics, sender, listeners, config.rtr_tcp_keepalive,
+        history, metrics, sender, listeners, unix_listeners,
+        config.rtr_tcp_keepalive, shutdown,
     ))
 }
 
 fn create_tls_config(
-    config: &Config
-) -> Result<Arc<tls::ServerConfig>, ExitError> {
+    config: &Config, tls_configs: &TlsConfigs,
+) -> Result<Arc<ReloadableServerConfig>, ExitError> {
     let key_path = match config.rtr_tls_key.as_ref() {
         Some(path) => path.as_ref(),
         None => {
@@ -74,40 +89,60 @@ fn create_tls_config(
             return Err(ExitError::Generic)
         }
     };
-    tls::create_server_config("RTR", key_path, cert_path).map(Arc::new)
+    tls_configs.create("RTR", key_path, cert_path)
 }
 
 async fn _rtr_listener(
     origins: SharedHistory,
     metrics: SharedRtrServerMetrics,
     sender: NotifySender,
-    listeners: Vec<(String, Option<Arc<tls::ServerConfig>>, StdListener)>,
+    listeners: Vec<(
+        String, Option<Arc<ReloadableServerConfig>>, StdListener
+    )>,
+    unix_listeners: Vec<(String, StdUnixListener)>,
     keepalive: Option<Duration>,
+    shutdown: Shutdown,
 ) {
     // If there are no listeners, just never return.
-    if listeners.is_empty() {
+    if listeners.is_empty() && unix_listeners.is_empty() {
         pending::<()>().await;
         return;
     }
 
-    let _ = select_all(
-        listeners.into_iter().map(|(addr, tls, listener)| {
-            tokio::spawn(single_rtr_listener(
-                addr, tls, listener, origins.clone(), metrics.clone(),
-                sender.clone(), keepalive,
+    let tasks = listeners.into_iter().map(|(addr, tls, listener)| {
+        tokio::spawn(single_rtr_listener(
+            addr, tls, listener, origins.clone(), metrics.clone(),
+            sender.clone(), keepalive, shutdown.clone(),
+        ))
+    });
+    #[cfg(unix)]
+    let tasks = tasks.chain(
+        unix_listeners.into_iter().map(|(path, listener)| {
+            tokio::spawn(single_rtr_unix_listener(
+                path, listener, origins.clone(), metrics.clone(),
+                sender.clone(), shutdown.clone(),
             ))
         })
-    ).await;
+    );
+    #[cfg(not(unix))]
+    let _ = unix_listeners;
+    let _ = select_all(tasks).await;
 }
 
+/// Runs an RTR server on a TCP listener.
+///
+/// The server stops accepting new connections once shutdown is triggered.
+/// Connections already established are kept until the process exits.
+#[allow(clippy::too_many_arguments)]
 async fn single_rtr_listener(
     addr: String,
-    tls: Option<Arc<tls::ServerConfig>>,
+    tls: Option<Arc<ReloadableServerConfig>>,
     listener: StdListener,
     origins: SharedHistory,
     server_metrics: SharedRtrServerMetrics,
     sender: NotifySender,
     keepalive: Option<Duration>,
+    mut shutdown: Shutdown,
 ) {
     let listener = match TcpListener::from_std(listener) {
         Ok(listener) => listener,
@@ -116,14 +151,49 @@ async fn single_rtr_listener(
             return;
         }
     };
-    let tls = tls.map(TlsAcceptor::from);
     let listener = RtrListener {
-        tcp: listener, tls, keepalive, server_metrics
+        tcp: listener, tls, keepalive, server_metrics,
+        shutdown: shutdown.clone(),
+    };
+    tokio::select! {
+        res = Server::new(listener, sender, origins.clone()).run() => {
+            if let Err(err) = res {
+                error!("Fatal error in RTR server {}: {}", addr, err);
+            }
+        }
+        _ = shutdown.triggered() => { }
+    }
+}
+
+/// Runs an RTR server on a Unix domain socket.
+///
+/// Since there is no TCP involved, there is no keepalive, either.
+#[cfg(unix)]
+async fn single_rtr_unix_listener(
+    path: String,
+    listener: StdUnixListener,
+    origins: SharedHistory,
+    server_metrics: SharedRtrServerMetrics,
+    sender: NotifySender,
+    mut shutdown: Shutdown,
+) {
+    let listener = match tokio::net::UnixListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Fatal error listening on {}: {}", path, err);
+            return;
+        }
+    };
+    let listener = RtrUnixListener {
+        unix: listener, server_metrics, shutdown: shutdown.clone(),
     };
-    if let Err(err) = Server::new(
-        listener, sender, origins.clone()
-    ).run().await {
-        error!("Fatal error in RTR server {}: {}", addr, err);
+    tokio::select! {
+        res = Server::new(listener, sender, origins.clone()).run() => {
+            if let Err(err) = res {
+                error!("Fatal error in RTR server {}: {}", path, err);
+            }
+        }
+        _ = shutdown.triggered() => { }
     }
 }
 
@@ -133,9 +203,10 @@ async fn single_rtr_listener(
 /// A wrapper around an TCP listener that produces RTR streams.
 struct RtrListener {
     tcp: TcpListener,
-    tls: Option<TlsAcceptor>,
+    tls: Option<Arc<ReloadableServerConfig>>,
     keepalive: Option<Duration>,
     server_metrics: SharedRtrServerMetrics,
+    shutdown: Shutdown,
 }
 
 impl Stream for RtrListener {
@@ -147,10 +218,12 @@ impl Stream for RtrListener {
     ) -> Poll<Option<Self::Item>> {
         match self.tcp.poll_accept(ctx) {
             Poll::Ready(Ok((sock, addr))) => {
+                // Get a fresh acceptor so we pick up reloaded certificates.
+                let tls = self.tls.as_ref().map(|tls| tls.acceptor());
                 match RtrStream::new(
                     sock, addr,
-                    self.tls.as_ref(), self.keepalive,
-                    self.server_metrics.clone()
+                    tls.as_ref(), self.keepalive,
+                    self.server_metrics.clone(), self.shutdown.clone(),
                 ) {
                     Ok(stream) => Poll::Ready(Some(Ok(stream))),
                     Err(_) => Poll::Pending,
@@ -162,34 +235,120 @@ impl Stream for RtrListener {
     }
 }
 
+
+//------------ RtrUnixListener ----------------------------------------------
+
+/// A wrapper around a Unix domain socket listener producing RTR streams.
+#[cfg(unix)]
+struct RtrUnixListener {
+    unix: tokio::net::UnixListener,
+    server_metrics: SharedRtrServerMetrics,
+    shutdown: Shutdown,
+}
+
+#[cfg(unix)]
+impl Stream for RtrUnixListener {
+    type Item = Result<RtrStream<tokio::net::UnixStream>, io::Error>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        match self.unix.poll_accept(ctx) {
+            Poll::Ready(Ok((sock, _))) => {
+                Poll::Ready(Some(Ok(RtrStream::new_unix(
+                    sock, self.server_metrics.clone(), self.shutdown.clone(),
+                ))))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+
 //------------ RtrStream ----------------------------------------------------
 
 /// A wrapper around a stream socket that takes care of updating metrics.
-struct RtrStream {
-    sock: MaybeTlsTcpStream,
+struct RtrStream<Sock = MaybeTlsTcpStream> {
+    sock: Sock,
     metrics: Arc<RtrClientMetrics>,
+
+    /// Keeps track of the PDUs received from the client.
+    read_pdus: PduScanner,
+
+    /// Keeps track of the PDUs sent to the client.
+    write_pdus: PduScanner,
+
+    /// The shutdown signal for acquiring drain guards.
+    shutdown: Shutdown,
+
+    /// The drain guard held while a transfer is in progress.
+    ///
+    /// A transfer starts with a Cache Response PDU and ends with an End of
+    /// Data, Cache Reset, or Error Report PDU. Holding the guard makes a
+    /// graceful shutdown wait for the transfer to finish.
+    transfer: Option<DrainGuard>,
 }
 
-impl RtrStream {
+impl<Sock> RtrStream<Sock> {
+    /// Registers a new client with the server metrics.
     #[allow(clippy::redundant_async_block)] // False positive
+    fn client_metrics(
+        addr: IpAddr, server_metrics: SharedRtrServerMetrics,
+    ) -> Arc<RtrClientMetrics> {
+        let metrics = Arc::new(RtrClientMetrics::new(addr));
+        let client_metrics = metrics.clone();
+        tokio::spawn(async move {
+            server_metrics.add_client(client_metrics).await
+        });
+        metrics
+    }
+}
+
+#[cfg(unix)]
+impl RtrStream<tokio::net::UnixStream> {
+    /// Creates a new stream for a Unix domain socket.
+    ///
+    /// Clients connected via Unix domain sockets don’t have an address, so
+    /// they are recorded in the metrics with the unspecified IPv6 address.
+    fn new_unix(
+        sock: tokio::net::UnixStream,
+        server_metrics: SharedRtrServerMetrics,
+        shutdown: Shutdown,
+    ) -> Self {
+        RtrStream {
+            sock,
+            metrics: Self::client_metrics(
+                std::net::Ipv6Addr::UNSPECIFIED.into(), server_metrics
+            ),
+            read_pdus: Default::default(),
+            write_pdus: Default::default(),
+            shutdown,
+            transfer: None,
+        }
+    }
+}
+
+impl RtrStream {
     fn new(
         sock: TcpStream,
         addr: SocketAddr,
         tls: Option<&TlsAcceptor>,
         keepalive: Option<Duration>,
         server_metrics: SharedRtrServerMetrics,
+        shutdown: Shutdown,
     ) -> Result<Self, io::Error> {
         if let Some(duration) = keepalive {
             Self::set_keepalive(&sock, duration)?
         }
-        let metrics = Arc::new(RtrClientMetrics::new(addr.ip()));
-        let client_metrics = metrics.clone();
-        tokio::spawn(async move {
-            server_metrics.add_client(client_metrics).await
-        });
         Ok(RtrStream {
             sock: MaybeTlsTcpStream::new(sock, tls),
-            metrics
+            metrics: Self::client_metrics(addr.ip(), server_metrics),
+            read_pdus: Default::default(),
+            write_pdus: Default::default(),
+            shutdown,
+            transfer: None,
         })
     }
 
@@ -242,13 +401,14 @@ impl RtrStream {
     }
 }
 
-impl Socket for RtrStream {
+impl<Sock> Socket for RtrStream<Sock>
+where Sock: AsyncRead + AsyncWrite + Unpin + Sync + Send + 'static {
     fn update(&self, state: State, reset: bool) {
         self.metrics.update_now(state.serial(), reset);
     }
 }
 
-impl AsyncRead for RtrStream {
+impl<Sock: AsyncRead + Unpin> AsyncRead for RtrStream<Sock> {
     fn poll_read(
         mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf
     ) -> Poll<Result<(), io::Error>> {
@@ -257,15 +417,26 @@ impl AsyncRead for RtrStream {
         pin_mut!(sock);
         let res = sock.poll_read(cx, buf);
         if let Poll::Ready(Ok(())) = res {
-            self.metrics.inc_bytes_read(
-                (buf.filled().len().saturating_sub(len)) as u64
-            )    
+            let this = &mut *self;
+            let data = buf.filled().get(len..).unwrap_or_default();
+            this.metrics.inc_bytes_read(data.len() as u64);
+            let metrics = &this.metrics;
+            this.read_pdus.scan(data, |header| {
+                // Serial Query PDUs have the serial right after the header.
+                if header[1] == SERIAL_QUERY_TYPE && header.len() >= 12 {
+                    let mut serial = [0u8; 4];
+                    serial.copy_from_slice(&header[8..12]);
+                    metrics.set_requested_serial(
+                        u32::from_be_bytes(serial).into()
+                    );
+                }
+            });
         }
         res
     }
 }
 
-impl AsyncWrite for RtrStream {
+impl<Sock: AsyncWrite + Unpin> AsyncWrite for RtrStream<Sock> {
     fn poll_write(
         mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]
     ) -> Poll<Result<usize, io::Error>> {
@@ -273,7 +444,29 @@ impl AsyncWrite for RtrStream {
         pin_mut!(sock);
         let res = sock.poll_write(cx, buf);
         if let Poll::Ready(Ok(n)) = res {
-            self.metrics.inc_bytes_written(n as u64)
+            let this = &mut *self;
+            this.metrics.inc_bytes_written(n as u64);
+            let metrics = &this.metrics;
+            let shutdown = &this.shutdown;
+            let transfer = &mut this.transfer;
+            this.write_pdus.scan(&buf[..n], |header| {
+                metrics.inc_pdus_sent();
+                // Error reports may be sent with a version other than the
+                // negotiated one, so don’t trust those.
+                if header[1] != ERROR_REPORT_TYPE {
+                    metrics.set_version(header[0]);
+                }
+                match header[1] {
+                    CACHE_RESPONSE_TYPE => {
+                        *transfer = Some(shutdown.guard())
+                    }
+                    END_OF_DATA_TYPE | CACHE_RESET_TYPE
+                    | ERROR_REPORT_TYPE => {
+                        *transfer = None
+                    }
+                    _ => { }
+                }
+            });
         }
         res
     }
@@ -295,9 +488,143 @@ impl AsyncWrite for RtrStream {
     }
 }
 
-impl Drop for RtrStream {
+impl<Sock> Drop for RtrStream<Sock> {
     fn drop(&mut self) {
         self.metrics.close()
     }
 }
 
+
+//------------ PduScanner ---------------------------------------------------
+
+/// The PDU type of a Serial Query PDU.
+const SERIAL_QUERY_TYPE: u8 = 1;
+
+/// The PDU type of a Cache Response PDU.
+const CACHE_RESPONSE_TYPE: u8 = 3;
+
+/// The PDU type of an End of Data PDU.
+const END_OF_DATA_TYPE: u8 = 7;
+
+/// The PDU type of a Cache Reset PDU.
+const CACHE_RESET_TYPE: u8 = 8;
+
+/// The PDU type of an Error Report PDU.
+const ERROR_REPORT_TYPE: u8 = 10;
+
+/// The size of the common header of all PDUs.
+const HEADER_SIZE: usize = 8;
+
+/// The number of bytes at the start of a PDU we are interested in.
+const HEAD_SIZE: usize = 12;
+
+/// Finds the PDU boundaries in a stream of RTR data.
+///
+/// The scanner is fed the data as it passes through the socket and keeps
+/// track of where PDUs start. It relies on the length field of the PDU
+/// header only and doesn’t attempt to verify the PDUs any further.
+#[derive(Debug, Default)]
+struct PduScanner {
+    /// The start of the current PDU.
+    head: [u8; HEAD_SIZE],
+
+    /// The number of bytes in `head` collected so far.
+    head_len: usize,
+
+    /// The number of bytes of the current PDU still to be skipped.
+    skip: usize,
+}
+
+impl PduScanner {
+    /// Scans the next chunk of data.
+    ///
+    /// Calls `op` with the first up to twelve bytes of each PDU found in
+    /// the data. These always contain at least the complete header.
+    fn scan(&mut self, mut data: &[u8], mut op: impl FnMut(&[u8])) {
+        while !data.is_empty() {
+            if self.skip > 0 {
+                let len = cmp::min(self.skip, data.len());
+                self.skip -= len;
+                data = &data[len..];
+                continue;
+            }
+            let want = if self.head_len < HEADER_SIZE {
+                HEADER_SIZE
+            }
+            else {
+                cmp::min(self.pdu_len(), HEAD_SIZE)
+            };
+            let len = cmp::min(want - self.head_len, data.len());
+            self.head[self.head_len..self.head_len + len].copy_from_slice(
+                &data[..len]
+            );
+            self.head_len += len;
+            data = &data[len..];
+            if self.head_len >= HEADER_SIZE {
+                let head_size = cmp::min(self.pdu_len(), HEAD_SIZE);
+                if self.head_len == head_size {
+                    op(&self.head[..head_size]);
+                    self.skip = self.pdu_len() - head_size;
+                    self.head_len = 0;
+                }
+            }
+        }
+    }
+
+    /// Returns the length of the current PDU.
+    ///
+    /// The header must be complete. Lengths shorter than the header are
+    /// treated as a header-only PDU so that we always make progress.
+    fn pdu_len(&self) -> usize {
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&self.head[4..8]);
+        cmp::max(
+            usize::try_from(u32::from_be_bytes(len)).unwrap_or(usize::MAX),
+            HEADER_SIZE
+        )
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pdu(version: u8, pdu_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut res = vec![version, pdu_type, 0, 0];
+        res.extend_from_slice(
+            &u32::try_from(body.len() + 8).unwrap().to_be_bytes()
+        );
+        res.extend_from_slice(body);
+        res
+    }
+
+    fn scan_chunks(data: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+        let mut scanner = PduScanner::default();
+        let mut res = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            scanner.scan(chunk, |head| res.push(head.to_vec()));
+        }
+        res
+    }
+
+    #[test]
+    fn scan_pdus() {
+        let mut data = pdu(1, 2, b"");
+        data.extend(pdu(1, 1, &[0, 0, 0, 17]));
+        data.extend(pdu(2, 4, &[0; 24]));
+        let expected = vec![
+            pdu(1, 2, b""),
+            pdu(1, 1, &[0, 0, 0, 17]),
+            pdu(2, 4, &[0; 24])[..HEAD_SIZE].to_vec(),
+        ];
+        for chunk_size in 1..data.len() + 1 {
+            assert_eq!(
+                scan_chunks(&data, chunk_size), expected,
+                "chunk size {}", chunk_size
+            );
+        }
+    }
+}
@@ -1,12 +1,28 @@
 //! Local exceptions per RFC 8416 aka SLURM.
+//!
+//! In addition to RFC 8416 documents, SLURM version 2 documents as
+//! proposed by draft-ietf-sidrops-aspa-slurm are supported. These add
+//! filters and assertions for ASPA payload and are only accepted if ASPA
+//! processing is enabled.
 
 use std::{error, fmt, fs, io};
-use std::path::Path;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
 use log::error;
-use rpki::rtr::payload::{RouteOrigin, RouterKey};
-use rpki::slurm::{BgpsecFilter, PrefixFilter, SlurmFile};
+use rpki::resources::{Asn, SmallAsnSet};
+use rpki::rtr::payload::{Aspa, RouteOrigin, RouterKey};
+use rpki::rtr::pdu::ProviderAsns;
+use rpki::slurm::{
+    BgpsecAssertion, BgpsecFilter, PrefixAssertion, PrefixFilter, SlurmFile
+};
+use serde::{Deserialize, Deserializer};
+use serde::de::{DeserializeOwned, Error as _};
+use serde_json::{Map, Value};
 use crate::config::Config;
 use crate::error::Failed;
 
@@ -18,8 +34,16 @@ pub struct LocalExceptions {
     origin_filters: Vec<PrefixFilter>,
     router_key_filters: Vec<BgpsecFilter>,
 
+    aspa_filters: Vec<AspaFilter>,
+
     origin_assertions: Vec<(RouteOrigin, Arc<ExceptionInfo>)>,
     router_key_assertions: Vec<(RouterKey, Arc<ExceptionInfo>)>,
+    aspa_assertions: Vec<(Aspa, Arc<ExceptionInfo>)>,
+
+    /// Are SLURM version 2 documents to be rejected?
+    ///
+    /// This is the case if ASPA processing is disabled.
+    reject_v2: bool,
 }
 
 impl LocalExceptions {
@@ -27,8 +51,16 @@ impl LocalExceptions {
         Self::default()
     }
 
+    /// Creates empty exceptions to be extended according to the config.
+    fn empty_for_config(config: &Config) -> Self {
+        LocalExceptions {
+            reject_v2: !config.enable_aspa,
+            .. Self::default()
+        }
+    }
+
     pub fn load(config: &Config, keep_comments: bool) -> Result<Self, Failed> {
-        let mut res = LocalExceptions::empty();
+        let mut res = LocalExceptions::empty_for_config(config);
         let mut ok = true;
         for path in &config.exceptions {
             if let Err(err) = res.extend_from_file(path, keep_comments) {
@@ -39,6 +71,11 @@ impl LocalExceptions {
                 ok = false;
             }
         }
+        if res.extend_from_inline(
+            &config.exceptions_inline, keep_comments
+        ).is_err() {
+            ok = false;
+        }
         if ok {
             Ok(res)
         }
@@ -50,7 +87,7 @@ impl LocalExceptions {
     pub fn from_json(
         json: &str,
         keep_comments: bool
-    ) -> Result<Self, serde_json::Error> {
+    ) -> Result<Self, LoadError> {
         let mut res = LocalExceptions::empty();
         res.extend_from_json(json, keep_comments)?;
         Ok(res)
@@ -69,10 +106,9 @@ impl LocalExceptions {
         &mut self,
         json: &str,
         keep_comments: bool
-    ) -> Result<(), serde_json::Error> {
-        self.extend_from_parsed(
-            SlurmFile::from_str(json)?, None, keep_comments
-        );
+    ) -> Result<(), LoadError> {
+        let (file, aspa) = self.parse(json)?;
+        self.extend_from_parsed(file, aspa, None, keep_comments);
         Ok(())
     }
 
@@ -82,16 +118,59 @@ impl LocalExceptions {
         keep_comments: bool
     ) -> Result<(), LoadError> {
         let buf = fs::read_to_string(&path)?;
+        let (file, aspa) = self.parse(&buf)?;
         self.extend_from_parsed(
-            SlurmFile::from_str(&buf)?,
-            Some(path.as_ref().into()), keep_comments
+            file, aspa, Some(path.as_ref().into()), keep_comments
         );
         Ok(())
     }
 
+    /// Parses a local exceptions document.
+    ///
+    /// Returns an error for SLURM version 2 documents if these are to be
+    /// rejected.
+    fn parse(
+        &self, json: &str
+    ) -> Result<(SlurmFile, Option<AspaExceptions>), LoadError> {
+        let (file, aspa) = parse_document(json)?;
+        if aspa.is_some() && self.reject_v2 {
+            return Err(LoadError::AspaDisabled)
+        }
+        Ok((file, aspa))
+    }
+
+    /// Adds the exceptions given directly in the config file.
+    ///
+    /// Each item of `inline` is a complete local exceptions document.
+    /// Logs an error for each item that cannot be parsed.
+    fn extend_from_inline(
+        &mut self,
+        inline: &[String],
+        keep_comments: bool
+    ) -> Result<(), Failed> {
+        let mut ok = true;
+        for (idx, json) in inline.iter().enumerate() {
+            if let Err(err) = self.extend_from_json(json, keep_comments) {
+                error!(
+                    "Failed to load exceptions from \
+                     'exceptions-inline[{}]' in the config file: {}",
+                    idx, err
+                );
+                ok = false;
+            }
+        }
+        if ok {
+            Ok(())
+        }
+        else {
+            Err(Failed)
+        }
+    }
+
     fn extend_from_parsed(
         &mut self,
         json: SlurmFile,
+        aspa: Option<AspaExceptions>,
         path: Option<Arc<Path>>,
         keep_comments: bool,
     ) {
@@ -149,6 +228,32 @@ impl LocalExceptions {
                 )
             })
         );
+
+        let aspa = match aspa {
+            Some(aspa) => aspa,
+            None => return
+        };
+        self.aspa_filters.extend(
+            aspa.filters.into_iter().map(|mut item| {
+                if !keep_comments {
+                    item.comment = None
+                }
+                item
+            })
+        );
+        self.aspa_assertions.extend(
+            aspa.assertions.into_iter().map(|item| {
+                (
+                    item.to_aspa(),
+                    info.cloned().unwrap_or_else(|| {
+                        Arc::new(ExceptionInfo {
+                            path: path.clone(),
+                            comment: item.comment,
+                        })
+                    })
+                )
+            })
+        );
     }
 
     pub fn drop_origin(&self, origin: RouteOrigin) -> bool {
@@ -161,6 +266,13 @@ impl LocalExceptions {
         })
     }
 
+    /// Returns whether ASPA payload should be dropped.
+    pub fn drop_aspa(&self, customer: Asn, providers: &SmallAsnSet) -> bool {
+        self.aspa_filters.iter().any(|filter| {
+            filter.drop_aspa(customer, providers)
+        })
+    }
+
     pub fn origin_assertions(
         &self
     ) -> impl Iterator<Item = (RouteOrigin, Arc<ExceptionInfo>)> + '_ {
@@ -176,12 +288,181 @@ impl LocalExceptions {
             (key.clone(), info.clone())
         })
     }
+
+    pub fn aspa_assertions(
+        &self
+    ) -> impl Iterator<Item = (Aspa, Arc<ExceptionInfo>)> + '_ {
+        self.aspa_assertions.iter().map(|(aspa, info)| {
+            (aspa.clone(), info.clone())
+        })
+    }
+
+    /// Returns a digest of the information attached to assertions.
+    ///
+    /// Changes to this information, such as edited comments, don’t change
+    /// the payload but do change the output of some formats.
+    pub fn info_digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (_, info) in &self.origin_assertions {
+            info.hash(&mut hasher);
+        }
+        for (_, info) in &self.router_key_assertions {
+            info.hash(&mut hasher);
+        }
+        for (_, info) in &self.aspa_assertions {
+            info.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
 }
 
 
-//------------ ExceptionInfo -------------------------------------------------
+//------------ ExceptionFiles ------------------------------------------------
+
+/// The local exceptions loaded from the configured files.
+///
+/// In addition to the exceptions themselves, the type keeps track of the
+/// modification times of the files so it can tell whether they have
+/// changed since they were last loaded. Exceptions given directly in the
+/// config file are added to those from the files.
+///
+/// If loading fails, the last good set of exceptions is kept.
+#[derive(Clone, Debug)]
+pub struct ExceptionFiles {
+    /// The paths of the exceptions files.
+    paths: Vec<PathBuf>,
+
+    /// The exceptions given in the config file.
+    inline: Vec<String>,
+
+    /// Should comments be kept?
+    keep_comments: bool,
+
+    /// Is ASPA processing enabled?
+    ///
+    /// If not, SLURM version 2 documents are rejected.
+    enable_aspa: bool,
+
+    /// The modification times of the files when they were last loaded.
+    modified: Vec<Option<SystemTime>>,
+
+    /// The last set of exceptions that was successfully loaded.
+    current: Option<LocalExceptions>,
+
+    /// Did the last attempt to load the exceptions fail?
+    stale: bool,
+}
+
+impl ExceptionFiles {
+    /// Creates a new value for the exceptions files of the config.
+    ///
+    /// This doesn’t load the files yet.
+    pub fn new(config: &Config, keep_comments: bool) -> Self {
+        ExceptionFiles {
+            paths: config.exceptions.clone(),
+            inline: config.exceptions_inline.clone(),
+            keep_comments,
+            enable_aspa: config.enable_aspa,
+            modified: vec![None; config.exceptions.len()],
+            current: None,
+            stale: false,
+        }
+    }
+
+    /// Returns the last good set of exceptions if there is one.
+    pub fn current(&self) -> Option<&LocalExceptions> {
+        self.current.as_ref()
+    }
+
+    /// Returns whether the last attempt to load the exceptions failed.
+    ///
+    /// If so, the current exceptions are from an earlier version of the
+    /// files.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// Returns whether any of the files has changed since the last load.
+    pub fn changed(&self) -> bool {
+        self.paths.iter().zip(self.modified.iter()).any(|(path, modified)| {
+            Self::modified(path) != *modified
+        })
+    }
+
+    /// Loads the exceptions from the files.
+    ///
+    /// If loading any of the files fails, logs the error and keeps the
+    /// previous exceptions.
+    pub fn load(&mut self) -> Result<(), Failed> {
+        // We note the modification times before loading so we rather load
+        // once too often than miss an update.
+        self.modified = self.paths.iter().map(|path| {
+            Self::modified(path)
+        }).collect();
+        let mut res = LocalExceptions {
+            reject_v2: !self.enable_aspa,
+            .. LocalExceptions::empty()
+        };
+        let mut ok = true;
+        for path in &self.paths {
+            if let Err(err) = res.extend_from_file(path, self.keep_comments) {
+                error!(
+                    "Failed to load exceptions file {}: {}",
+                    path.display(), err
+                );
+                ok = false;
+            }
+        }
+        if res.extend_from_inline(
+            &self.inline, self.keep_comments
+        ).is_err() {
+            ok = false;
+        }
+        self.stale = !ok;
+        if ok {
+            self.current = Some(res);
+            Ok(())
+        }
+        else {
+            if self.current.is_some() {
+                error!("Keeping previously loaded exceptions.");
+            }
+            Err(Failed)
+        }
+    }
 
+    /// Returns the modification time of a file if it can be determined.
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+
+//------------ ReloadRequest -------------------------------------------------
+
+/// A request to reload the local exceptions.
+///
+/// The value is shared between whoever wants to request a reload and the
+/// server which checks for the request regularly.
 #[derive(Clone, Debug, Default)]
+pub struct ReloadRequest(Arc<AtomicBool>);
+
+impl ReloadRequest {
+    /// Requests a reload of the local exceptions.
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// Returns whether a reload was requested and resets the request.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+
+//------------ ExceptionInfo -------------------------------------------------
+
+#[derive(Clone, Debug, Default, Hash)]
 pub struct ExceptionInfo {
     pub path: Option<Arc<Path>>,
     pub comment: Option<String>,
@@ -207,12 +488,182 @@ impl<'a> arbitrary::Arbitrary<'a> for ExceptionInfo {
 }
 
 
+//------------ parse_document ------------------------------------------------
+
+/// Parses a local exceptions document of either SLURM version.
+///
+/// Version 1 documents are handed to [`SlurmFile`] unchanged. For version 2
+/// documents, the ASPA filters and assertions are removed and returned
+/// separately and the remainder is parsed as a version 1 document.
+fn parse_document(
+    json: &str
+) -> Result<(SlurmFile, Option<AspaExceptions>), serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Version {
+        #[serde(rename = "slurmVersion")]
+        version: Option<Value>,
+    }
+
+    match serde_json::from_str::<Version>(json) {
+        Ok(Version { version: Some(version) })
+            if version.as_u64() == Some(2) => { }
+        _ => return Ok((SlurmFile::from_str(json)?, None))
+    }
+
+    let mut json = match serde_json::from_str::<Value>(json)? {
+        Value::Object(json) => json,
+        _ => return Err(serde_json::Error::custom("expected an object"))
+    };
+    let aspa = AspaExceptions {
+        filters: take_aspa_items(
+            &mut json, "validationOutputFilters", "aspaFilters"
+        )?,
+        assertions: take_aspa_items(
+            &mut json, "locallyAddedAssertions", "aspaAssertions"
+        )?,
+    };
+    json.insert("slurmVersion".into(), 1.into());
+    Ok((SlurmFile::deserialize(Value::Object(json))?, Some(aspa)))
+}
+
+/// Removes and decodes the ASPA member `key` of the object `section`.
+///
+/// If `section` is missing or isn’t an object, returns an empty list and
+/// leaves reporting the error to the parser for the rest of the document.
+fn take_aspa_items<T: DeserializeOwned>(
+    json: &mut Map<String, Value>,
+    section: &str,
+    key: &'static str,
+) -> Result<Vec<T>, serde_json::Error> {
+    let section = match json.get_mut(section) {
+        Some(Value::Object(section)) => section,
+        _ => return Ok(Vec::new())
+    };
+    match section.remove(key) {
+        Some(items) => Vec::deserialize(items),
+        None => Err(serde_json::Error::missing_field(key))
+    }
+}
+
+
+//------------ AspaExceptions ------------------------------------------------
+
+/// The ASPA filters and assertions of a SLURM version 2 document.
+#[derive(Clone, Debug, Default)]
+struct AspaExceptions {
+    filters: Vec<AspaFilter>,
+    assertions: Vec<AspaAssertion>,
+}
+
+
+//------------ AspaFilter ----------------------------------------------------
+
+/// A filter for ASPA payload.
+///
+/// If the filter contains a provider set, it only drops ASPA payload with
+/// exactly this set of providers. Otherwise it drops all ASPA payload for
+/// the customer ASN.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AspaFilter {
+    /// The customer ASN of the payload to drop.
+    #[serde(rename = "customerAsid", deserialize_with = "deserialize_asn")]
+    pub customer: Asn,
+
+    /// The provider ASNs of the payload to drop.
+    #[serde(
+        rename = "providerSet", default,
+        deserialize_with = "deserialize_opt_providers"
+    )]
+    pub providers: Option<SmallAsnSet>,
+
+    /// An optional comment.
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl AspaFilter {
+    /// Returns whether ASPA payload should be dropped.
+    pub fn drop_aspa(&self, customer: Asn, providers: &SmallAsnSet) -> bool {
+        customer == self.customer
+            && self.providers.as_ref().map_or(true, |own| own == providers)
+    }
+}
+
+
+//------------ AspaAssertion -------------------------------------------------
+
+/// An assertion of ASPA payload.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AspaAssertion {
+    /// The customer ASN of the payload.
+    #[serde(rename = "customerAsid", deserialize_with = "deserialize_asn")]
+    pub customer: Asn,
+
+    /// The provider ASNs of the payload.
+    #[serde(
+        rename = "providerSet", deserialize_with = "deserialize_providers"
+    )]
+    pub providers: SmallAsnSet,
+
+    /// An optional comment.
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl AspaAssertion {
+    /// Returns the ASPA payload of the assertion.
+    pub fn to_aspa(&self) -> Aspa {
+        Aspa::new(
+            self.customer,
+            ProviderAsns::try_from_iter(self.providers.iter()).expect(
+                "provider set size checked when parsing"
+            )
+        )
+    }
+}
+
+/// Deserializes an ASN given as an integer.
+fn deserialize_asn<'de, D: Deserializer<'de>>(
+    deserializer: D
+) -> Result<Asn, D::Error> {
+    u32::deserialize(deserializer).map(Asn::from_u32)
+}
+
+/// Deserializes a provider set given as an array of integers.
+///
+/// Duplicate ASNs are removed. The set must not contain more ASNs than fit
+/// into ASPA payload.
+fn deserialize_providers<'de, D: Deserializer<'de>>(
+    deserializer: D
+) -> Result<SmallAsnSet, D::Error> {
+    let mut asns = Vec::<u32>::deserialize(deserializer)?;
+    asns.sort_unstable();
+    asns.dedup();
+    if asns.len() > ProviderAsns::MAX_COUNT {
+        return Err(D::Error::custom("too many provider ASNs"))
+    }
+    Ok(asns.into_iter().map(Asn::from_u32).collect())
+}
+
+/// Deserializes an optional provider set.
+fn deserialize_opt_providers<'de, D: Deserializer<'de>>(
+    deserializer: D
+) -> Result<Option<SmallAsnSet>, D::Error> {
+    deserialize_providers(deserializer).map(Some)
+}
+
+
 //------------ LoadError ----------------------------------------------------
 
 #[derive(Debug)]
 pub enum LoadError {
     Io(io::Error),
     Json(serde_json::Error),
+
+    /// A SLURM version 2 document was given but ASPA is disabled.
+    AspaDisabled,
 }
 
 impl From<io::Error> for LoadError {
@@ -232,9 +683,945 @@ impl fmt::Display for LoadError {
         match *self {
             LoadError::Io(ref err) => err.fmt(f),
             LoadError::Json(ref err) => err.fmt(f),
+            LoadError::AspaDisabled => {
+                f.write_str(
+                    "SLURM version 2 documents require ASPA processing \
+                     to be enabled via 'enable-aspa'"
+                )
+            }
         }
     }
 }
 
 impl error::Error for LoadError { }
 
+
+//------------ Checker -------------------------------------------------------
+
+/// Checks a set of local exceptions files for problems.
+///
+/// Files are added one by one via [`check_file`][Self::check_file]. Each
+/// entry of each file is checked individually so that all problems can be
+/// reported at once. Once all files have been added,
+/// [`finalize`][Self::finalize] checks for duplicate and overlapping
+/// entries across all files and returns the list of problems found.
+#[derive(Debug, Default)]
+pub struct Checker {
+    /// The valid prefix filters found so far.
+    prefix_filters: Vec<(Location, PrefixFilter)>,
+
+    /// The valid BGPsec filters found so far.
+    bgpsec_filters: Vec<(Location, BgpsecFilter)>,
+
+    /// The valid ASPA filters found so far.
+    aspa_filters: Vec<(Location, AspaFilter)>,
+
+    /// The valid prefix assertions found so far.
+    prefix_assertions: Vec<(Location, RouteOrigin)>,
+
+    /// The valid BGPsec assertions found so far.
+    bgpsec_assertions: Vec<(Location, RouterKey)>,
+
+    /// The valid ASPA assertions found so far.
+    aspa_assertions: Vec<(Location, AspaAssertion)>,
+
+    /// The problems found so far.
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    /// Checks the local exceptions file at the given path.
+    pub fn check_file(&mut self, path: &Path) {
+        let root = Location::root(path.into());
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.error(root, err, None);
+                return
+            }
+        };
+        let json = match serde_json::from_str::<Value>(&data) {
+            Ok(json) => json,
+            Err(err) => {
+                self.error(root, err, None);
+                return
+            }
+        };
+        let count = self.problems.len();
+        self.check_json(&root, &json);
+
+        // If we haven’t found anything wrong, the whole file must parse,
+        // too. Otherwise we have missed something.
+        if self.problems.len() == count {
+            if let Err(err) = parse_document(&data) {
+                self.error(root, err, None);
+            }
+        }
+    }
+
+    /// Checks the structure and entries of a parsed file.
+    fn check_json(&mut self, root: &Location, json: &Value) {
+        let json = match json.as_object() {
+            Some(json) => json,
+            None => {
+                self.error(root.clone(), "expected an object", Some(json));
+                return
+            }
+        };
+        let v2 = match json.get("slurmVersion") {
+            Some(version) if version.as_u64() == Some(1) => false,
+            Some(version) if version.as_u64() == Some(2) => true,
+            Some(version) => {
+                self.error(
+                    root.member("slurmVersion"),
+                    "unsupported SLURM version", Some(version)
+                );
+                false
+            }
+            None => {
+                self.error(root.clone(), "missing 'slurmVersion'", None);
+                false
+            }
+        };
+
+        let location = root.member("validationOutputFilters");
+        if let Some(section) = self.object(&location, json) {
+            for (location, item) in self.array(
+                &location, section, "prefixFilters"
+            ) {
+                let filter = match self.entry::<PrefixFilter>(
+                    &location, item
+                ) {
+                    Some(filter) => filter,
+                    None => continue,
+                };
+                if filter.prefix.is_none() && filter.asn.is_none() {
+                    self.error(
+                        location, "filter without prefix or ASN", Some(item)
+                    )
+                }
+                else {
+                    self.prefix_filters.push((location, filter))
+                }
+            }
+            for (location, item) in self.array(
+                &location, section, "bgpsecFilters"
+            ) {
+                let filter = match self.entry::<BgpsecFilter>(
+                    &location, item
+                ) {
+                    Some(filter) => filter,
+                    None => continue,
+                };
+                if filter.ski.is_none() && filter.asn.is_none() {
+                    self.error(
+                        location, "filter without SKI or ASN", Some(item)
+                    )
+                }
+                else {
+                    self.bgpsec_filters.push((location, filter))
+                }
+            }
+            if v2 {
+                for (location, item) in self.array(
+                    &location, section, "aspaFilters"
+                ) {
+                    if let Some(filter) = self.entry::<AspaFilter>(
+                        &location, item
+                    ) {
+                        self.aspa_filters.push((location, filter))
+                    }
+                }
+            }
+        }
+
+        let location = root.member("locallyAddedAssertions");
+        if let Some(section) = self.object(&location, json) {
+            for (location, item) in self.array(
+                &location, section, "prefixAssertions"
+            ) {
+                if let Some(item) = self.entry::<PrefixAssertion>(
+                    &location, item
+                ) {
+                    self.prefix_assertions.push((
+                        location, RouteOrigin::new(item.prefix, item.asn)
+                    ))
+                }
+            }
+            for (location, item) in self.array(
+                &location, section, "bgpsecAssertions"
+            ) {
+                if let Some(item) = self.entry::<BgpsecAssertion>(
+                    &location, item
+                ) {
+                    self.bgpsec_assertions.push((
+                        location,
+                        RouterKey::new(
+                            item.ski, item.asn, item.router_public_key.into()
+                        )
+                    ))
+                }
+            }
+            if v2 {
+                for (location, item) in self.array(
+                    &location, section, "aspaAssertions"
+                ) {
+                    if let Some(item) = self.entry::<AspaAssertion>(
+                        &location, item
+                    ) {
+                        self.aspa_assertions.push((location, item))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the object member `location` of `json`.
+    ///
+    /// Adds a problem if the member is missing or not an object.
+    fn object<'a>(
+        &mut self, location: &Location, json: &'a Map<String, Value>
+    ) -> Option<&'a Map<String, Value>> {
+        match json.get(location.last_member()) {
+            Some(Value::Object(value)) => Some(value),
+            Some(value) => {
+                self.error(
+                    location.clone(), "expected an object", Some(value)
+                );
+                None
+            }
+            None => {
+                self.error(location.clone(), "missing member", None);
+                None
+            }
+        }
+    }
+
+    /// Returns the elements of the array member `key` of `json`.
+    ///
+    /// Adds a problem and returns an empty list if the member is missing or
+    /// not an array.
+    fn array<'a>(
+        &mut self,
+        location: &Location,
+        json: &'a Map<String, Value>,
+        key: &str,
+    ) -> Vec<(Location, &'a Value)> {
+        let location = location.member(key);
+        match json.get(key) {
+            Some(Value::Array(items)) => {
+                items.iter().enumerate().map(|(index, item)| {
+                    (location.index(index), item)
+                }).collect()
+            }
+            Some(value) => {
+                self.error(location, "expected an array", Some(value));
+                Vec::new()
+            }
+            None => {
+                self.error(location, "missing member", None);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Decodes a single entry.
+    ///
+    /// Adds a problem and returns `None` if that fails.
+    fn entry<T: DeserializeOwned>(
+        &mut self, location: &Location, value: &Value
+    ) -> Option<T> {
+        match T::deserialize(value) {
+            Ok(res) => Some(res),
+            Err(err) => {
+                self.error(location.clone(), err, Some(value));
+                None
+            }
+        }
+    }
+
+    /// Finishes checking and returns all problems found.
+    ///
+    /// This checks for duplicate and overlapping entries as well as for
+    /// assertions that are also matched by filters.
+    pub fn finalize(mut self) -> Vec<Problem> {
+        self.check_prefix_filters();
+        self.check_bgpsec_filters();
+        self.check_aspa_filters();
+        self.check_prefix_assertions();
+        self.check_bgpsec_assertions();
+        self.check_aspa_assertions();
+        self.problems
+    }
+
+    /// Checks for duplicate and overlapping prefix filters.
+    fn check_prefix_filters(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.prefix_filters;
+        for (i, (location, filter)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                let covered = prefix_filter_covers(other, filter);
+                let covers = prefix_filter_covers(filter, other);
+                if covered && covers {
+                    problems.push(Problem::duplicate(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covered {
+                    problems.push(Problem::covered(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covers {
+                    problems.push(Problem::covered(
+                        other_location, "filter", location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Checks for duplicate and overlapping BGPsec filters.
+    fn check_bgpsec_filters(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.bgpsec_filters;
+        for (i, (location, filter)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                let covered = bgpsec_filter_covers(other, filter);
+                let covers = bgpsec_filter_covers(filter, other);
+                if covered && covers {
+                    problems.push(Problem::duplicate(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covered {
+                    problems.push(Problem::covered(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covers {
+                    problems.push(Problem::covered(
+                        other_location, "filter", location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Checks for duplicate and overlapping ASPA filters.
+    fn check_aspa_filters(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.aspa_filters;
+        for (i, (location, filter)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                let covered = aspa_filter_covers(other, filter);
+                let covers = aspa_filter_covers(filter, other);
+                if covered && covers {
+                    problems.push(Problem::duplicate(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covered {
+                    problems.push(Problem::covered(
+                        location, "filter", other_location
+                    ));
+                }
+                else if covers {
+                    problems.push(Problem::covered(
+                        other_location, "filter", location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Checks prefix assertions for duplicates, overlaps, and filters.
+    fn check_prefix_assertions(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.prefix_assertions;
+        for (i, (location, origin)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                if origin == other {
+                    problems.push(Problem::duplicate(
+                        location, "assertion", other_location
+                    ));
+                }
+                else if origin_covers(other, origin) {
+                    problems.push(Problem::covered(
+                        location, "assertion", other_location
+                    ));
+                }
+                else if origin_covers(origin, other) {
+                    problems.push(Problem::covered(
+                        other_location, "assertion", location
+                    ));
+                }
+            }
+            for (filter_location, filter) in &self.prefix_filters {
+                if filter.drop_origin(*origin) {
+                    problems.push(Problem::shadowed(
+                        location, filter_location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Checks BGPsec assertions for duplicates and filters.
+    fn check_bgpsec_assertions(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.bgpsec_assertions;
+        for (i, (location, key)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                if key == other {
+                    problems.push(Problem::duplicate(
+                        location, "assertion", other_location
+                    ));
+                }
+            }
+            for (filter_location, filter) in &self.bgpsec_filters {
+                if filter.drop_router_key(key) {
+                    problems.push(Problem::shadowed(
+                        location, filter_location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Checks ASPA assertions for duplicates and filters.
+    fn check_aspa_assertions(&mut self) {
+        let mut problems = Vec::new();
+        let items = &self.aspa_assertions;
+        for (i, (location, item)) in items.iter().enumerate() {
+            for (other_location, other) in &items[..i] {
+                if item.customer == other.customer
+                    && item.providers == other.providers
+                {
+                    problems.push(Problem::duplicate(
+                        location, "assertion", other_location
+                    ));
+                }
+            }
+            for (filter_location, filter) in &self.aspa_filters {
+                if filter.drop_aspa(item.customer, &item.providers) {
+                    problems.push(Problem::shadowed(
+                        location, filter_location
+                    ));
+                }
+            }
+        }
+        self.problems.append(&mut problems);
+    }
+
+    /// Adds an error.
+    fn error(
+        &mut self,
+        location: Location,
+        message: impl fmt::Display,
+        value: Option<&Value>,
+    ) {
+        self.problems.push(Problem {
+            severity: Severity::Error,
+            location,
+            message: message.to_string(),
+            value: value.map(Value::to_string),
+        })
+    }
+}
+
+/// Returns whether prefix filter `filter` drops everything `other` drops.
+fn prefix_filter_covers(filter: &PrefixFilter, other: &PrefixFilter) -> bool {
+    let prefix = match (filter.prefix, other.prefix) {
+        (None, _) => true,
+        (Some(prefix), Some(other)) => prefix.covers(other),
+        (Some(_), None) => false,
+    };
+    let asn = match (filter.asn, other.asn) {
+        (None, _) => true,
+        (Some(asn), other) => Some(asn) == other,
+    };
+    prefix && asn
+}
+
+/// Returns whether BGPsec filter `filter` drops everything `other` drops.
+fn bgpsec_filter_covers(filter: &BgpsecFilter, other: &BgpsecFilter) -> bool {
+    let ski = match (filter.ski, other.ski) {
+        (None, _) => true,
+        (Some(ski), other) => Some(ski) == other,
+    };
+    let asn = match (filter.asn, other.asn) {
+        (None, _) => true,
+        (Some(asn), other) => Some(asn) == other,
+    };
+    ski && asn
+}
+
+/// Returns whether ASPA filter `filter` drops everything `other` drops.
+fn aspa_filter_covers(filter: &AspaFilter, other: &AspaFilter) -> bool {
+    filter.customer == other.customer
+        && match (filter.providers.as_ref(), other.providers.as_ref()) {
+            (None, _) => true,
+            (Some(providers), other) => Some(providers) == other,
+        }
+}
+
+/// Returns whether the route origin `origin` covers all of `other`.
+fn origin_covers(origin: &RouteOrigin, other: &RouteOrigin) -> bool {
+    origin.asn == other.asn
+        && origin.prefix.prefix().covers(other.prefix.prefix())
+        && origin.prefix.resolved_max_len()
+            >= other.prefix.resolved_max_len()
+}
+
+
+//------------ Location ------------------------------------------------------
+
+/// The location of a value in a local exceptions file.
+///
+/// This consists of the path of the file and a JSON path expression
+/// pointing to the value within the file.
+#[derive(Clone, Debug)]
+pub struct Location {
+    /// The path of the file.
+    file: Arc<Path>,
+
+    /// The JSON path of the value.
+    path: String,
+
+    /// The length of the path up to the last member.
+    ///
+    /// We need this to get the key of the last member.
+    member: usize,
+}
+
+impl Location {
+    /// Creates the location of the root value of a file.
+    fn root(file: Arc<Path>) -> Self {
+        Location { file, path: "$".into(), member: 1 }
+    }
+
+    /// Returns the location of an object member.
+    fn member(&self, key: &str) -> Self {
+        Location {
+            file: self.file.clone(),
+            path: format!("{}.{}", self.path, key),
+            member: self.path.len() + 1,
+        }
+    }
+
+    /// Returns the location of an array element.
+    fn index(&self, index: usize) -> Self {
+        Location {
+            file: self.file.clone(),
+            path: format!("{}[{}]", self.path, index),
+            member: self.member,
+        }
+    }
+
+    /// Returns the key of the last object member.
+    fn last_member(&self) -> &str {
+        &self.path[self.member..]
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.path)
+    }
+}
+
+
+//------------ Problem -------------------------------------------------------
+
+/// A problem found while checking local exceptions files.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// How bad is the problem?
+    severity: Severity,
+
+    /// Where was the problem found?
+    location: Location,
+
+    /// A description of the problem.
+    message: String,
+
+    /// The offending value, if any, encoded as JSON.
+    value: Option<String>,
+}
+
+impl Problem {
+    /// Creates a problem for a duplicate entry.
+    fn duplicate(location: &Location, what: &str, other: &Location) -> Self {
+        Problem {
+            severity: Severity::Error,
+            location: location.clone(),
+            message: format!("duplicate of {} at {}", what, other),
+            value: None,
+        }
+    }
+
+    /// Creates a problem for an entry covered by another entry.
+    fn covered(location: &Location, what: &str, other: &Location) -> Self {
+        Problem {
+            severity: Severity::Error,
+            location: location.clone(),
+            message: format!("overlaps with {} at {}", what, other),
+            value: None,
+        }
+    }
+
+    /// Creates a problem for an assertion matched by a filter.
+    fn shadowed(location: &Location, filter: &Location) -> Self {
+        Problem {
+            severity: Severity::Warning,
+            location: location.clone(),
+            message: format!("assertion is matched by filter at {}", filter),
+            value: None,
+        }
+    }
+
+    /// Returns whether the problem is an error rather than a warning.
+    pub fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Error)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.location, self.severity, self.message)?;
+        if let Some(value) = self.value.as_ref() {
+            write!(f, " (value: {})", value)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Severity ------------------------------------------------------
+
+/// The severity of a problem.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// The files are not usable as they are.
+    Error,
+
+    /// The files are usable but likely don’t do what was intended.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exception_files_keep_last_good() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exceptions.slurm");
+        fs::write(&path,
+            r#"{
+                "slurmVersion": 1,
+                "validationOutputFilters": {
+                    "prefixFilters": [ ],
+                    "bgpsecFilters": [ ]
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [
+                        { "asn": 64496, "prefix": "198.51.100.0/24" }
+                    ],
+                    "bgpsecAssertions": [ ]
+                }
+            }"#
+        ).unwrap();
+        let config = Config {
+            exceptions: vec![path.clone()],
+            .. Default::default()
+        };
+        let mut files = ExceptionFiles::new(&config, false);
+        assert!(files.current().is_none());
+        assert!(files.changed());
+
+        files.load().unwrap();
+        assert!(!files.changed());
+        assert!(!files.is_stale());
+        assert_eq!(files.current().unwrap().origin_assertions().count(), 1);
+
+        fs::write(&path, "{ broken").unwrap();
+        assert!(files.load().is_err());
+        assert!(files.is_stale());
+        assert_eq!(files.current().unwrap().origin_assertions().count(), 1);
+    }
+
+    #[test]
+    fn checker_finds_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.slurm");
+        let second = dir.path().join("second.slurm");
+        fs::write(&first,
+            r#"{
+                "slurmVersion": 1,
+                "validationOutputFilters": {
+                    "prefixFilters": [
+                        { "prefix": "192.0.2.0/24" },
+                        { "comment": "nothing to filter" }
+                    ],
+                    "bgpsecFilters": [ ]
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [
+                        { "asn": 64496, "prefix": "192.0.2.0/25" },
+                        { "asn": 64496, "prefix": "no prefix" }
+                    ],
+                    "bgpsecAssertions": [ ]
+                }
+            }"#
+        ).unwrap();
+        fs::write(&second,
+            r#"{
+                "slurmVersion": 1,
+                "validationOutputFilters": {
+                    "prefixFilters": [
+                        { "prefix": "192.0.2.0/26", "asn": 64497 }
+                    ],
+                    "bgpsecFilters": [ ]
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [ ]
+                }
+            }"#
+        ).unwrap();
+
+        let mut checker = Checker::default();
+        checker.check_file(&first);
+        checker.check_file(&second);
+        let problems = checker.finalize();
+        let expected = [
+            (Severity::Error, "prefixFilters[1]"),
+            (Severity::Error, "prefixAssertions[1]"),
+            (Severity::Error, "locallyAddedAssertions.bgpsecAssertions"),
+            (Severity::Error, "prefixFilters[0]"),
+            (Severity::Warning, "prefixAssertions[0]"),
+        ];
+        assert_eq!(problems.len(), expected.len());
+        for (problem, (severity, path)) in problems.iter().zip(expected) {
+            assert_eq!(problem.severity, severity);
+            assert!(problem.location.path.ends_with(path));
+        }
+    }
+
+    const V1: &str = r#"{
+        "slurmVersion": 1,
+        "validationOutputFilters": {
+            "prefixFilters": [ { "asn": 64496, "comment": "v1" } ],
+            "bgpsecFilters": [ ]
+        },
+        "locallyAddedAssertions": {
+            "prefixAssertions": [
+                { "asn": 64496, "prefix": "198.51.100.0/24" }
+            ],
+            "bgpsecAssertions": [ ]
+        }
+    }"#;
+
+    const V2: &str = r#"{
+        "slurmVersion": 2,
+        "validationOutputFilters": {
+            "prefixFilters": [ ],
+            "bgpsecFilters": [ ],
+            "aspaFilters": [
+                { "customerAsid": 64496, "comment": "drop all" },
+                { "customerAsid": 64497, "providerSet": [ 64501, 64500 ] }
+            ]
+        },
+        "locallyAddedAssertions": {
+            "prefixAssertions": [
+                { "asn": 64497, "prefix": "192.0.2.0/24" }
+            ],
+            "bgpsecAssertions": [ ],
+            "aspaAssertions": [
+                {
+                    "customerAsid": 64498,
+                    "providerSet": [ 64502, 64500, 64502 ],
+                    "comment": "replacement"
+                }
+            ]
+        }
+    }"#;
+
+    fn asns(asns: &[u32]) -> SmallAsnSet {
+        asns.iter().copied().map(Asn::from_u32).collect()
+    }
+
+    #[test]
+    fn parse_v1_unchanged() {
+        let (file, aspa) = parse_document(V1).unwrap();
+        assert_eq!(file, SlurmFile::from_str(V1).unwrap());
+        assert!(aspa.is_none());
+
+        // ASPA members are not allowed in version 1.
+        assert!(parse_document(
+            &V2.replace(r#""slurmVersion": 2"#, r#""slurmVersion": 1"#)
+        ).is_err());
+        assert!(parse_document(
+            &V1.replace(r#""slurmVersion": 1"#, r#""slurmVersion": 3"#)
+        ).is_err());
+    }
+
+    #[test]
+    fn parse_v2() {
+        let (file, aspa) = parse_document(V2).unwrap();
+        let aspa = aspa.unwrap();
+        assert_eq!(file.assertions.prefix.len(), 1);
+        assert_eq!(
+            aspa.filters,
+            [
+                AspaFilter {
+                    customer: Asn::from_u32(64496),
+                    providers: None,
+                    comment: Some("drop all".into()),
+                },
+                AspaFilter {
+                    customer: Asn::from_u32(64497),
+                    providers: Some(asns(&[64500, 64501])),
+                    comment: None,
+                },
+            ]
+        );
+        assert_eq!(aspa.assertions.len(), 1);
+        assert_eq!(aspa.assertions[0].providers, asns(&[64500, 64502]));
+
+        // The ASPA members are required.
+        assert!(parse_document(
+            &V2.replace(r#""aspaFilters""#, r#""otherFilters""#)
+        ).is_err());
+        assert!(parse_document(
+            &V2.replace(r#""aspaAssertions""#, r#""otherAssertions""#)
+        ).is_err());
+
+        // Entries are checked.
+        assert!(parse_document(
+            &V2.replace(r#""customerAsid": 64496"#, r#""customerAsid": "x""#)
+        ).is_err());
+        assert!(parse_document(
+            &V2.replace(r#""comment": "replacement""#, r#""foo": "bar""#)
+        ).is_err());
+    }
+
+    #[test]
+    fn aspa_filter_drop() {
+        let (_, aspa) = parse_document(V2).unwrap();
+        let filters = aspa.unwrap().filters;
+        assert!(filters[0].drop_aspa(Asn::from_u32(64496), &asns(&[1])));
+        assert!(!filters[0].drop_aspa(Asn::from_u32(64497), &asns(&[1])));
+        assert!(
+            filters[1].drop_aspa(Asn::from_u32(64497), &asns(&[64500, 64501]))
+        );
+        assert!(!filters[1].drop_aspa(Asn::from_u32(64497), &asns(&[64500])));
+    }
+
+    #[test]
+    fn load_mixed_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let v1 = dir.path().join("v1.slurm");
+        let v2 = dir.path().join("v2.slurm");
+        fs::write(&v1, V1).unwrap();
+        fs::write(&v2, V2).unwrap();
+        let mut config = Config {
+            exceptions: vec![v1, v2],
+            enable_aspa: true,
+            .. Default::default()
+        };
+
+        let exceptions = LocalExceptions::load(&config, true).unwrap();
+        assert_eq!(exceptions.origin_assertions().count(), 2);
+        assert!(exceptions.drop_origin(RouteOrigin::new(
+            "192.0.2.0/24".parse().unwrap(), Asn::from_u32(64496)
+        )));
+        assert!(exceptions.drop_aspa(Asn::from_u32(64496), &asns(&[1])));
+        let aspas: Vec<_> = exceptions.aspa_assertions().collect();
+        assert_eq!(aspas.len(), 1);
+        assert_eq!(aspas[0].0.customer, Asn::from_u32(64498));
+        assert_eq!(
+            aspas[0].0.providers.iter().collect::<SmallAsnSet>(),
+            asns(&[64500, 64502])
+        );
+        assert_eq!(aspas[0].1.comment.as_deref(), Some("replacement"));
+
+        let mut files = ExceptionFiles::new(&config, false);
+        files.load().unwrap();
+        assert_eq!(files.current().unwrap().aspa_assertions().count(), 1);
+
+        // Without ASPA, version 2 documents are rejected.
+        config.enable_aspa = false;
+        assert!(LocalExceptions::load(&config, true).is_err());
+        let mut files = ExceptionFiles::new(&config, false);
+        assert!(files.load().is_err());
+        config.exceptions.pop();
+        assert!(LocalExceptions::load(&config, true).is_ok());
+        assert!(matches!(
+            LocalExceptions::empty_for_config(&config).parse(V2),
+            Err(LoadError::AspaDisabled)
+        ));
+    }
+
+    #[test]
+    fn checker_aspa() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("v2.slurm");
+        fs::write(&path,
+            r#"{
+                "slurmVersion": 2,
+                "validationOutputFilters": {
+                    "prefixFilters": [ ],
+                    "bgpsecFilters": [ ],
+                    "aspaFilters": [
+                        { "customerAsid": 64496 },
+                        { "customerAsid": 64496, "providerSet": [ 64500 ] },
+                        { "customerAsid": "64497" }
+                    ]
+                },
+                "locallyAddedAssertions": {
+                    "prefixAssertions": [ ],
+                    "bgpsecAssertions": [ ],
+                    "aspaAssertions": [
+                        { "customerAsid": 64496, "providerSet": [ 64501 ] },
+                        { "customerAsid": 64498, "providerSet": [ 64501 ] },
+                        { "customerAsid": 64498, "providerSet": [ 64501 ] }
+                    ]
+                }
+            }"#
+        ).unwrap();
+
+        let mut checker = Checker::default();
+        checker.check_file(&path);
+        let problems = checker.finalize();
+        let expected = [
+            (Severity::Error, "aspaFilters[2]"),
+            (Severity::Error, "aspaFilters[1]"),
+            (Severity::Warning, "aspaAssertions[0]"),
+            (Severity::Error, "aspaAssertions[2]"),
+        ];
+        assert_eq!(problems.len(), expected.len());
+        for (problem, (severity, path)) in problems.iter().zip(expected) {
+            assert_eq!(problem.severity, severity);
+            assert!(problem.location.path.ends_with(path));
+        }
+    }
+}
@@ -64,14 +64,16 @@
 
 use std::{fs, io};
 use std::fs::File;
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, warn};
 use rand::random;
 use rpki::crypto::digest::DigestAlgorithm;
 use rpki::repository::cert::{Cert, ResourceCert};
-use rpki::repository::manifest::{ManifestContent, ManifestHash};
+use rpki::repository::manifest::{Manifest, ManifestContent, ManifestHash};
 use rpki::repository::tal::TalUri;
 use rpki::repository::x509::{Serial, Time};
 use rpki::uri;
@@ -79,10 +81,11 @@ use crate::collector;
 use crate::config::Config;
 use crate::engine::CaCert;
 use crate::error::{Failed, Fatal, RunFailed};
-use crate::metrics::Metrics;
+use crate::metrics::{Metrics, StoredPointMetrics};
+use crate::utils::date::format_iso_date;
 use crate::utils::fatal;
 use crate::utils::binio::{Compose, Parse, ParseError};
-use crate::utils::dump::DumpRegistry;
+use crate::utils::dump::{DumpRegistry, DumpTarget};
 use crate::utils::json::JsonBuilder;
 use crate::utils::uri::UriExt;
 
@@ -112,10 +115,15 @@ pub struct Store {
 
 impl Store {
     /// Returns the base path for the given config.
-    fn create_base_dir(config: &Config) -> Result<PathBuf, Failed> {
+    fn base_dir(config: &Config) -> PathBuf {
         // We are using "stored" since store was foolishly used in 0.9.0 for
         // the database.
-        let path = config.cache_dir.join("stored");
+        config.cache_dir.join("stored")
+    }
+
+    /// Creates the base path for the given config and returns it.
+    fn create_base_dir(config: &Config) -> Result<PathBuf, Failed> {
+        let path = Self::base_dir(config);
         if let Err(err) = fs::create_dir_all(&path) {
             error!(
                 "Failed to create store directory {}: {}",
@@ -145,6 +153,16 @@ impl Store {
         })
     }
 
+    /// Creates a store value without touching the file system.
+    ///
+    /// The value can only be used for determining the paths of the stored
+    /// data, e.g., for removing them.
+    pub fn existing(config: &Config) -> Self {
+        Store {
+            path: Self::base_dir(config),
+        }
+    }
+
     /// Sanitizes the stored data.
     ///
     /// Currently doesn’t do anything.
@@ -158,15 +176,23 @@ impl Store {
     }
 
     /// Dumps the content of the store.
-    pub fn dump(&self, dir: &Path) -> Result<(), Failed> {
-        self.dump_ta_certs(dir)?;
-        let dir = dir.join("store");
-        debug!("Dumping store content to {}", dir.display());
-        fatal::remove_dir_all(&dir)?;
+    pub fn dump(&self, target: &mut DumpTarget) -> Result<(), Failed> {
+        self.dump_ta_certs(target)?;
+        let dir = PathBuf::from("store");
+        debug!("Dumping store content.");
+        target.clear(&dir)?;
         let mut repos = DumpRegistry::new(dir);
-        self.dump_tree(&self.rsync_repository_path(), &mut repos)?;
-        self.dump_tree(&self.rrdp_repository_base(), &mut repos)?;
-        self.dump_repository_json(repos)?;
+        let mut manifests = Vec::new();
+        self.dump_tree(
+            &self.rsync_repository_path(), &mut repos, &mut manifests, target
+        )?;
+        self.dump_tree(
+            &self.rrdp_repository_base(), &mut repos, &mut manifests, target
+        )?;
+        manifests.sort_by(|left, right| {
+            left.manifest_uri.as_str().cmp(right.manifest_uri.as_str())
+        });
+        self.dump_repository_json(repos, &manifests, target)?;
         debug!("Store dump complete.");
         Ok(())
     }
@@ -174,17 +200,29 @@ impl Store {
     /// Dumps all the stored trust anchor certificates.
     fn dump_ta_certs(
         &self,
-        target_base: &Path
+        target: &mut DumpTarget,
     ) -> Result<(), Failed> {
-        let source = self.path.join("ta");
-        let target = target_base.join("ta");
-        debug!("Dumping trust anchor certificates to {}", target.display());
-        fatal::remove_dir_all(&target)?;
-        fatal::copy_existing_dir_all(&source, &target)?;
+        let dir = Path::new("ta");
+        debug!("Dumping trust anchor certificates.");
+        target.clear(dir)?;
+        target.copy_dir_all(dir, &self.path.join("ta"))?;
         debug!("Trust anchor certificate dump complete.");
         Ok(())
     }
 
+    /// Restores the trust anchor certificates from a dump in `source`.
+    ///
+    /// The stored publication points can’t be restored since the dump
+    /// doesn’t retain their structure. They are rebuilt from the collector
+    /// during the next validation run.
+    pub fn restore_ta(config: &Config, source: &Path) -> Result<(), Failed> {
+        let path = Self::create_base_dir(config)?;
+        debug!("Restoring trust anchor certificates.");
+        fatal::copy_existing_dir_all(&source.join("ta"), &path.join("ta"))?;
+        debug!("Trust anchor certificate restore complete.");
+        Ok(())
+    }
+
     /// Dumps all the stored points found in the tree under `path`.
     ///
     /// The point’s repository and rsync URI is determined from the stored
@@ -193,6 +231,8 @@ impl Store {
         &self,
         path: &Path,
         repos: &mut DumpRegistry,
+        manifests: &mut Vec<DumpedManifest>,
+        target: &mut DumpTarget,
     ) -> Result<(), Failed> {
         let dir = match fatal::read_existing_dir(path)? {
             Some(dir) => dir,
@@ -201,20 +241,24 @@ impl Store {
         for entry in dir {
             let entry = entry?;
             if entry.is_dir() {
-                self.dump_tree(entry.path(), repos)?;
+                self.dump_tree(entry.path(), repos, manifests, target)?;
             }
             else if entry.is_file() {
-                self.dump_point(entry.path(), repos)?;
+                self.dump_point(entry.path(), repos, manifests, target)?;
             }
         }
         Ok(())
     }
 
     /// Dumps all data for a single stored publication point.
+    ///
+    /// Information about the point’s manifest is added to `manifests`.
     fn dump_point(
         &self,
         path: &Path,
         repos: &mut DumpRegistry,
+        manifests: &mut Vec<DumpedManifest>,
+        target: &mut DumpTarget,
     ) -> Result<(), Failed> {
         let mut file = match File::open(path) {
             Ok(file) => file,
@@ -238,11 +282,14 @@ impl Store {
         };
 
         let repo_dir = repos.get_repo_path(manifest.rpki_notify.as_ref());
+        manifests.push(DumpedManifest::new(&manifest));
 
         self.dump_object(
-            &repo_dir, &manifest.manifest_uri, &manifest.manifest
+            &repo_dir, &manifest.manifest_uri, &manifest.manifest, target
+        )?;
+        self.dump_object(
+            &repo_dir, &manifest.crl_uri, &manifest.crl, target
         )?;
-        self.dump_object(&repo_dir, &manifest.crl_uri, &manifest.crl)?;
 
         loop {
             let object = match StoredObject::read(&mut file) {
@@ -256,7 +303,9 @@ impl Store {
                     return Ok(())
                 }
             };
-            self.dump_object(&repo_dir, &object.uri, &object.content)?;
+            self.dump_object(
+                &repo_dir, &object.uri, &object.content, target
+            )?;
         }
 
         Ok(())
@@ -267,47 +316,30 @@ impl Store {
         &self,
         dir: &Path,
         uri: &uri::Rsync,
-        content: &[u8]
+        content: &[u8],
+        target: &mut DumpTarget,
     ) -> Result<(), Failed> {
-        let path = dir.join(
-            format!("{}/{}/{}",
-                uri.canonical_authority(),
-                uri.module_name(),
-                uri.path()
-            )
-        );
-        if let Some(dir) = path.parent() {
-            fatal::create_dir_all(dir)?;
-        }
-        let mut target = match File::create(&path) {
-            Ok(some) => some,
-            Err(err) => {
-                error!(
-                    "Fatal: cannot create target file {}: {}",
-                    path.display(), err
-                );
-                return Err(Failed)
-            }
-        };
-        if let Err(err) = target.write_all(content) {
-            error!(
-                "Fatal: failed to write to target file {}: {}",
-                path.display(), err
-            );
-            return Err(Failed)
-        }
-
-        Ok(())
+        target.write_file(
+            &dir.join(
+                format!("{}/{}/{}",
+                    uri.canonical_authority(),
+                    uri.module_name(),
+                    uri.path()
+                )
+            ),
+            content
+        )
     }
 
     /// Writes the repositories.json file.
     fn dump_repository_json(
         &self,
         repos: DumpRegistry,
+        manifests: &[DumpedManifest],
+        target: &mut DumpTarget,
     ) -> Result<(), Failed> {
-        fatal::create_dir_all(repos.base_dir())?;
         let path = repos.base_dir().join("repositories.json");
-        fatal::write_file(
+        target.write_file(
             &path, 
             JsonBuilder::build(|builder| {
                 builder.member_array("repositories", |builder| {
@@ -327,13 +359,20 @@ impl Store {
                         builder.member_str("path", "rsync");
                         builder.member_str("type", "rsync");
                     });
-                })
+                });
+                builder.member_array("manifests", |builder| {
+                    for item in manifests {
+                        builder.array_object(|builder| {
+                            item.json(builder)
+                        })
+                    }
+                });
             }).as_bytes()
         )
     }
 
     /// Returns the path to use for the trust anchor at the given URI.
-    fn ta_path(&self, uri: &TalUri) -> PathBuf {
+    pub fn ta_path(&self, uri: &TalUri) -> PathBuf {
         match *uri {
             TalUri::Rsync(ref uri) => {
                 self.path.join(
@@ -357,7 +396,7 @@ impl Store {
     }
 
     /// Returns the path for the RRDP repository with the given rpkiNotify URI.
-    fn rrdp_repository_path(&self, uri: &uri::Https) -> PathBuf {
+    pub fn rrdp_repository_path(&self, uri: &uri::Https) -> PathBuf {
         self.path.join(uri.unique_path(Self::RRDP_BASE, ""))
     }
 
@@ -366,6 +405,91 @@ impl Store {
         self.path.join("rsync")
     }
 
+    /// Returns the path for the points of the given rsync module.
+    ///
+    /// Only publication points not hosted in an RRDP repository are
+    /// stored there.
+    pub fn rsync_module_path(&self, uri: &uri::Rsync) -> PathBuf {
+        self.rsync_repository_path().join(
+            format!(
+                "rsync/{}/{}",
+                uri.canonical_authority(),
+                uri.module_name(),
+            )
+        )
+    }
+
+    /// Looks up a stored object via its rsync URI.
+    ///
+    /// Checks the trust anchor certificates and then all stored
+    /// publication points in the directory of the object’s URI in both
+    /// the rsync and all RRDP repositories. Since this involves reading
+    /// quite a few files, it is only intended for diagnostics.
+    ///
+    /// Returns `Ok(None)` if the object can’t be found.
+    pub fn find_object(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<Bytes>, Failed> {
+        let ta_path = self.ta_path(&TalUri::Rsync(uri.clone()));
+        if ta_path.is_file() {
+            return fatal::read_file(&ta_path).map(|data| Some(data.into()))
+        }
+
+        let path = uri.path();
+        let dir = format!(
+            "rsync/{}/{}/{}",
+            uri.canonical_authority(),
+            uri.module_name(),
+            path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or(""),
+        );
+        let mut repositories = vec![(self.rsync_repository_path(), false)];
+        if let Some(hosts) = fatal::read_existing_dir(
+            &self.rrdp_repository_base()
+        )? {
+            for host in hosts {
+                let host = host?;
+                if !host.is_dir() {
+                    continue
+                }
+                for repository in fatal::read_dir(host.path())? {
+                    let repository = repository?;
+                    if repository.is_dir() {
+                        repositories.push(
+                            (repository.path().to_path_buf(), true)
+                        );
+                    }
+                }
+            }
+        }
+
+        for (repository, is_rrdp) in repositories {
+            let dir = repository.join(&dir);
+            let points = match fatal::read_existing_dir(&dir)? {
+                Some(points) => points,
+                None => continue,
+            };
+            for point in points {
+                let point = point?;
+                if !point.is_file() {
+                    continue
+                }
+                let point = StoredPoint::open(
+                    self, point.path().to_path_buf(), is_rrdp
+                )?;
+                for object in point {
+                    match object {
+                        Ok(object) if object.uri() == uri => {
+                            return Ok(Some(object.into_content()))
+                        }
+                        Ok(_) => { }
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
     /// The name of the directory where the temporary files go.
     const TMP_BASE: &'static str = "tmp";
 
@@ -420,6 +544,10 @@ impl Store {
 /// [`update_ta`][Self::update_ta] on [`Run`] directly, while the
 /// [`Repository`] provides means to that for all other data.
 ///
+/// The state of the publication points processed during the run can be
+/// recorded via [`record_point`][Self::record_point]. It ends up in the
+/// metrics when the run is finished via [`done`][Self::done].
+///
 /// This type references the underlying [`Store`]. It can be used with
 /// multiple threads using
 /// [crossbeam’s](https://github.com/crossbeam-rs/crossbeam) scoped threads.
@@ -427,6 +555,9 @@ impl Store {
 pub struct Run<'a> {
     /// A reference to the underlying store.
     store: &'a Store,
+
+    /// The state of the publication points processed so far.
+    points: Mutex<Vec<StoredPointMetrics>>,
 }
 
 impl<'a> Run<'a> {
@@ -434,7 +565,7 @@ impl<'a> Run<'a> {
     fn new(
         store: &'a Store,
     ) -> Self {
-        Run { store }
+        Run { store, points: Default::default() }
     }
 
     /// Finishes the validation run.
@@ -443,7 +574,17 @@ impl<'a> Run<'a> {
     ///
     /// If you are not interested in the metrics, you can simple drop the
     /// value, instead.
-    pub fn done(self, _metrics: &mut Metrics) {
+    pub fn done(self, metrics: &mut Metrics) {
+        let mut points = self.points.into_inner().unwrap();
+        points.sort_by(|left, right| {
+            left.manifest_uri.as_str().cmp(right.manifest_uri.as_str())
+        });
+        metrics.stored_points = points;
+    }
+
+    /// Records the state of a publication point after processing it.
+    pub fn record_point(&self, point: StoredPointMetrics) {
+        self.points.lock().unwrap().push(point)
     }
 
     /// Loads a stored trust anchor certificate.
@@ -734,6 +875,17 @@ impl<'a> StoredPoint<'a> {
         self.is_rrdp
     }
 
+    /// Returns the time the stored point was last updated.
+    ///
+    /// This is the modification time of the underlying file. Returns `None`
+    /// if there is no stored data for the point or the time is not
+    /// available.
+    pub fn updated(&self) -> Option<DateTime<Utc>> {
+        self.file.as_ref()?.metadata().and_then(|meta| {
+            meta.modified()
+        }).ok().map(Into::into)
+    }
+
     /// Returns a reference to the stored manifest if available.
     ///
     /// The manifest will not be available if there is no previously stored
@@ -841,6 +993,79 @@ impl Iterator for StoredPoint<'_> {
 }
 
 
+//------------ DumpedManifest ------------------------------------------------
+
+/// Information about the manifest of a point included in a dump.
+#[derive(Clone, Debug)]
+struct DumpedManifest {
+    /// The manifest’s rsync URI.
+    manifest_uri: uri::Rsync,
+
+    /// The rpkiNotify URI of the issuing CA certificate.
+    rpki_notify: Option<uri::Https>,
+
+    /// The manifest number of the manifest.
+    manifest_number: Serial,
+
+    /// The thisUpdate time of the manifest.
+    this_update: Time,
+
+    /// The nextUpdate time of the manifest.
+    ///
+    /// This is `None` if the stored manifest can’t be decoded.
+    next_update: Option<Time>,
+
+    /// The number of files listed on the manifest.
+    ///
+    /// This is `None` if the stored manifest can’t be decoded.
+    file_count: Option<usize>,
+}
+
+impl DumpedManifest {
+    /// Creates the information from a stored manifest.
+    fn new(stored: &StoredManifest) -> Self {
+        let content = Manifest::decode(
+            stored.manifest.clone(), false
+        ).ok().map(|manifest| manifest.content().clone());
+        DumpedManifest {
+            manifest_uri: stored.manifest_uri.clone(),
+            rpki_notify: stored.rpki_notify.clone(),
+            manifest_number: stored.manifest_number,
+            this_update: stored.this_update,
+            next_update: content.as_ref().map(|content| {
+                content.next_update()
+            }),
+            file_count: content.as_ref().map(|content| content.len()),
+        }
+    }
+
+    /// Adds the information to a JSON object.
+    fn json(&self, builder: &mut JsonBuilder) {
+        builder.member_str("uri", &self.manifest_uri);
+        match self.rpki_notify.as_ref() {
+            Some(uri) => builder.member_str("rpkiNotify", uri),
+            None => builder.member_raw("rpkiNotify", "null"),
+        }
+        builder.member_str("manifestNumber", self.manifest_number);
+        builder.member_str(
+            "thisUpdate", format_iso_date(self.this_update.into())
+        );
+        match self.next_update {
+            Some(time) => {
+                builder.member_str(
+                    "nextUpdate", format_iso_date(time.into())
+                )
+            }
+            None => builder.member_raw("nextUpdate", "null"),
+        }
+        match self.file_count {
+            Some(count) => builder.member_raw("fileCount", count),
+            None => builder.member_raw("fileCount", "null"),
+        }
+    }
+}
+
+
 //------------ StoredManifest ------------------------------------------------
 
 /// The content of a manifest placed in the store.
@@ -1,15 +1,23 @@
-//! The TALs bundled with Routinator.
+//! The TALs bundled with Routinator, given in the config, or fetched via
+//! HTTPS.
 
 use std::collections::HashMap;
-use log::error;
-use rpki::repository::tal::Tal;
+use std::path::PathBuf;
+use log::{error, warn};
+use rpki::uri;
+use rpki::crypto::DigestAlgorithm;
+use rpki::repository::tal::{ReadError, Tal};
+use crate::collector::Collector;
 use crate::config::Config;
 use crate::error::Failed;
+use crate::utils::fatal;
+use crate::utils::str::append_hex;
+use crate::utils::uri::UriExt;
 
 
 //------------ collect_tals --------------------------------------------------
 
-/// Produces the set of bundled TALs to use from config.
+/// Produces the set of bundled and inline TALs to use from config.
 pub fn collect_tals(config: &Config) -> Result<Vec<Tal>, Failed> {
     let mut res = HashMap::new();
 
@@ -46,6 +54,30 @@ pub fn collect_tals(config: &Config) -> Result<Vec<Tal>, Failed> {
         }
     }
 
+    // Add the TALs given in the config file.
+    for tal in &config.inline_tals {
+        if res.contains_key(&tal.label) {
+            error!(
+                "TAL label '{}' in the 'tal' config option is already \
+                 used by a bundled TAL.",
+                tal.label
+            );
+            return Err(Failed)
+        }
+        match tal.to_tal() {
+            Ok(parsed) => {
+                res.insert(tal.label.clone(), parsed);
+            }
+            Err(err) => {
+                error!(
+                    "Invalid TAL '{}' in the 'tal' config option: {}",
+                    tal.label, err
+                );
+                return Err(Failed)
+            }
+        }
+    }
+
     for tal in res.values_mut() {
         tal.prefer_https()
     }
@@ -105,6 +137,26 @@ impl BundledTal {
 }
 
 
+//------------ InlineTal -----------------------------------------------------
+
+/// A TAL given directly in the config file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InlineTal {
+    /// The label of the TAL.
+    pub label: String,
+
+    /// The content of the TAL.
+    pub content: String,
+}
+
+impl InlineTal {
+    /// Parses the content into a TAL.
+    pub fn to_tal(&self) -> Result<Tal, ReadError> {
+        Tal::read_named(self.label.clone(), &mut self.content.as_bytes())
+    }
+}
+
+
 //------------ Category ------------------------------------------------------
 
 /// The category of a TAL.
@@ -124,6 +176,143 @@ pub enum Category {
 }
 
 
+//------------ RemoteTal -----------------------------------------------------
+
+/// A TAL fetched from an HTTPS URI.
+///
+/// The last successfully fetched copy of the TAL is kept in the `tals`
+/// directory under the repository directory. It is used whenever fetching
+/// the TAL fails.
+#[derive(Clone, Debug)]
+pub struct RemoteTal {
+    /// The URI to fetch the TAL from.
+    uri: uri::Https,
+
+    /// The expected SHA-256 digest of the TAL as lower-case hex digits.
+    sha256: Option<String>,
+
+    /// The path of the cached copy of the TAL.
+    path: PathBuf,
+}
+
+impl RemoteTal {
+    /// Creates the list of remote TALs from the config.
+    pub fn from_config(config: &Config) -> Vec<Self> {
+        let base = config.cache_dir.join("tals");
+        config.tal_urls.iter().map(|uri| {
+            RemoteTal {
+                uri: uri.clone(),
+                sha256: config.tal_url_sha256.get(uri.as_str()).cloned(),
+                path: base.join(uri.unique_path("", ".tal")),
+            }
+        }).collect()
+    }
+
+    /// Returns the file name of the TAL.
+    ///
+    /// This is the last component of the URI’s path. It is used to
+    /// determine the TAL’s label just like for TALs loaded from files.
+    pub fn file_name(&self) -> &str {
+        self.uri.as_str().trim_end_matches('/').rsplit('/').next()
+            .unwrap_or_default()
+    }
+
+    /// Fetches the TAL and updates the cached copy.
+    ///
+    /// If `collector` is `None` or fetching fails, the cached copy will be
+    /// used instead. Only if there is no cached copy either, an error is
+    /// returned.
+    pub fn fetch(&self, collector: Option<&Collector>) -> Result<(), Failed> {
+        if let Some(collector) = collector {
+            if let Some(data) = collector.load_tal(&self.uri) {
+                if self.check(&data) {
+                    if let Some(dir) = self.path.parent() {
+                        fatal::create_dir_all(dir)?;
+                    }
+                    return fatal::write_file(&self.path, &data)
+                }
+            }
+            if self.path.is_file() {
+                warn!("Using cached copy of TAL {}.", self.uri);
+            }
+        }
+        if !self.path.is_file() {
+            error!(
+                "TAL {} could not be fetched and there is no cached copy. \
+                 Aborting.",
+                self.uri
+            );
+            return Err(Failed)
+        }
+        Ok(())
+    }
+
+    /// Reads the cached copy of the TAL.
+    ///
+    /// The TAL will be named `label`. Returns `Ok(None)` if there is no
+    /// cached copy. Returns an error if the cached copy cannot be read or
+    /// doesn’t match the configured digest.
+    pub fn read(&self, label: String) -> Result<Option<Tal>, Failed> {
+        let data = match fatal::read_existing_file(&self.path)? {
+            Some(data) => data,
+            None => return Ok(None)
+        };
+        if !self.check_digest(&data) {
+            error!(
+                "Cached copy {} of TAL {} doesn’t match the configured \
+                 digest. Aborting.",
+                self.path.display(), self.uri
+            );
+            return Err(Failed)
+        }
+        match Tal::read_named(label, &mut data.as_slice()) {
+            Ok(tal) => Ok(Some(tal)),
+            Err(err) => {
+                error!(
+                    "Failed to read cached copy {} of TAL {}: {}. \
+                     Aborting.",
+                    self.path.display(), self.uri, err
+                );
+                Err(Failed)
+            }
+        }
+    }
+
+    /// Checks that freshly fetched data is acceptable.
+    ///
+    /// The data must match the configured digest and be a valid TAL.
+    fn check(&self, data: &[u8]) -> bool {
+        if !self.check_digest(data) {
+            warn!(
+                "TAL {} doesn’t match the configured digest. Ignoring.",
+                self.uri
+            );
+            return false
+        }
+        if let Err(err) = Tal::read_named(String::new(), &mut &*data) {
+            warn!("Failed to parse TAL {}: {}. Ignoring.", self.uri, err);
+            return false
+        }
+        true
+    }
+
+    /// Returns whether the data matches the configured digest.
+    ///
+    /// If there is no configured digest, all data matches.
+    fn check_digest(&self, data: &[u8]) -> bool {
+        let expected = match self.sha256.as_ref() {
+            Some(expected) => expected,
+            None => return true,
+        };
+        let mut digest = String::new();
+        append_hex(
+            DigestAlgorithm::sha256().digest(data).as_ref(), &mut digest
+        );
+        digest == *expected
+    }
+}
+
+
 //------------ All Bundled TALs ----------------------------------------------
 
 /// All TALs bundled with Routinators.
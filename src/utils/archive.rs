@@ -29,7 +29,7 @@
 //! If possible (currently on Unix systems only), the file is memory mapped
 //! for faster access.
 
-use std::{cmp, fmt, fs, io, mem};
+use std::{cmp, fmt, fs, io, mem, ops};
 use std::borrow::Cow;
 use std::hash::Hasher;
 use std::marker::PhantomData;
@@ -1008,6 +1008,27 @@ pub struct ArchiveStats {
 }
 
 impl ArchiveStats {
+    /// Returns the share of empty space in the archive’s data area.
+    ///
+    /// Returns zero if the data area is empty.
+    pub fn empty_ratio(self) -> f64 {
+        let total = self.data_size();
+        if total == 0 {
+            0.
+        }
+        else {
+            self.empty_size as f64 / total as f64
+        }
+    }
+
+    /// Returns the size of the data area.
+    ///
+    /// The data area is all the space used by objects, including their
+    /// padding, and empty blocks.
+    pub fn data_size(self) -> u64 {
+        self.object_size + self.empty_size
+    }
+
     /// Prints the stats to stdout.
     ///
     /// Uses a two space indent.
@@ -1036,6 +1057,27 @@ impl ArchiveStats {
 }
 
 
+impl ops::AddAssign for ArchiveStats {
+    fn add_assign(&mut self, other: Self) {
+        if other.empty_count > 0 {
+            if self.empty_count == 0 {
+                self.empty_min = other.empty_min;
+                self.empty_max = other.empty_max;
+            }
+            else {
+                self.empty_min = cmp::min(self.empty_min, other.empty_min);
+                self.empty_max = cmp::max(self.empty_max, other.empty_max);
+            }
+        }
+        self.object_count += other.object_count;
+        self.object_size += other.object_size;
+        self.padding_size += other.padding_size;
+        self.empty_count += other.empty_count;
+        self.empty_size += other.empty_size;
+    }
+}
+
+
 //------------ Magic Cookie --------------------------------------------------
 //
 // The marker we use for a quick file type check.
@@ -1898,5 +1940,30 @@ mod test {
             Update  { name: b"4", data: b"bar" },
         ])
     }
+
+    #[test]
+    fn stats_add_assign() {
+        let mut total = ArchiveStats::default();
+        total += ArchiveStats {
+            object_count: 2, object_size: 300, padding_size: 20,
+            empty_count: 0, empty_size: 0, empty_min: 0, empty_max: 0,
+        };
+        assert_eq!(total.empty_ratio(), 0.);
+        total += ArchiveStats {
+            object_count: 1, object_size: 100, padding_size: 10,
+            empty_count: 2, empty_size: 400, empty_min: 100, empty_max: 300,
+        };
+        total += ArchiveStats {
+            object_count: 1, object_size: 200, padding_size: 0,
+            empty_count: 1, empty_size: 50, empty_min: 50, empty_max: 50,
+        };
+        assert_eq!(total.object_count, 4);
+        assert_eq!(total.padding_size, 30);
+        assert_eq!(total.empty_count, 3);
+        assert_eq!(total.empty_min, 50);
+        assert_eq!(total.empty_max, 300);
+        assert_eq!(total.data_size(), 1050);
+        assert_eq!(total.empty_ratio(), 450. / 1050.);
+    }
 }
 
@@ -6,10 +6,15 @@
 
 use std::{error, fmt, hash, io, slice};
 use std::collections::HashMap;
+use std::net::IpAddr;
 use bytes::Bytes;
 use chrono::{TimeZone, Utc};
-use rpki::{rrdp, uri};
-use rpki::repository::x509::{Serial, Time};
+use rpki::{rrdp, rtr, uri};
+use rpki::repository::x509::{Serial, Time, Validity};
+use rpki::resources::{Asn, Prefix};
+use rpki::resources::addr::MaxLenPrefix;
+use rpki::rtr::payload::{Action, Aspa, RouteOrigin, RouterKey};
+use rpki::rtr::pdu::{ProviderAsns, RouterKeyInfo};
 use uuid::Uuid;
 
 
@@ -350,6 +355,313 @@ impl<R: io::Read> Parse<R> for Time {
 }
 
 
+//------------ String --------------------------------------------------------
+//
+// Encoded as a u32 for the length and then that many bytes of UTF-8. If the
+// length doesn’t fit in a u32, the encoder produces an error.
+
+impl<W: io::Write> Compose<W> for String {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        u32::try_from(self.len())
+        .map_err(|_| ParseError::format("excessively large string"))?
+        .compose(target)?;
+        target.write_all(self.as_bytes())
+    }
+}
+
+impl<R: io::Read> Parse<R> for String {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        let len = usize::try_from(u32::parse(source)?).map_err(|_| {
+            ParseError::format("string too large for this system")
+        })?;
+        let mut bits = vec![0u8; len];
+        source.read_exact(&mut bits)?;
+        String::from_utf8(bits).map_err(|_| {
+            ParseError::format("invalid string")
+        })
+    }
+}
+
+
+//------------ Option<u8> ----------------------------------------------------
+//
+// Encoding starts with a single octet marking the option as for
+// `Option<i64>`.
+
+impl<W: io::Write> Compose<W> for Option<u8> {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        match self.as_ref() {
+            Some(value) => {
+                1u8.compose(target)?;
+                value.compose(target)
+            }
+            None => {
+                0u8.compose(target)
+            }
+        }
+    }
+}
+
+impl<R: io::Read> Parse<R> for Option<u8> {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        match u8::parse(source)? {
+            0 => Ok(None),
+            1 => u8::parse(source).map(Some),
+            _ => Err(ParseError::format("invalid option")),
+        }
+    }
+}
+
+
+//------------ Option<String> ------------------------------------------------
+//
+// Encoding starts with a single octet marking the option as for
+// `Option<i64>`.
+
+impl<W: io::Write> Compose<W> for Option<String> {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        match self.as_ref() {
+            Some(value) => {
+                1u8.compose(target)?;
+                value.compose(target)
+            }
+            None => {
+                0u8.compose(target)
+            }
+        }
+    }
+}
+
+impl<R: io::Read> Parse<R> for Option<String> {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        match u8::parse(source)? {
+            0 => Ok(None),
+            1 => String::parse(source).map(Some),
+            _ => Err(ParseError::format("invalid option")),
+        }
+    }
+}
+
+
+//------------ Option<uri::Rsync> --------------------------------------------
+//
+// Encoding starts with a single octet marking the option as for
+// `Option<i64>`.
+
+impl<W: io::Write> Compose<W> for Option<uri::Rsync> {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        match self.as_ref() {
+            Some(value) => {
+                1u8.compose(target)?;
+                value.compose(target)
+            }
+            None => {
+                0u8.compose(target)
+            }
+        }
+    }
+}
+
+impl<R: io::Read> Parse<R> for Option<uri::Rsync> {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        match u8::parse(source)? {
+            0 => Ok(None),
+            1 => uri::Rsync::parse(source).map(Some),
+            _ => Err(ParseError::format("invalid option")),
+        }
+    }
+}
+
+//------------ Validity ------------------------------------------------------
+//
+// Encoded as the not-before time followed by the not-after time.
+
+impl<W: io::Write> Compose<W> for Validity {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        self.not_before().compose(target)?;
+        self.not_after().compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for Validity {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        Ok(Validity::new(Time::parse(source)?, Time::parse(source)?))
+    }
+}
+
+
+//------------ Asn -----------------------------------------------------------
+
+impl<W: io::Write> Compose<W> for Asn {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        self.into_u32().compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for Asn {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        u32::parse(source).map(Asn::from_u32)
+    }
+}
+
+
+//------------ rtr::Serial ---------------------------------------------------
+
+impl<W: io::Write> Compose<W> for rtr::Serial {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        u32::from(*self).compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for rtr::Serial {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        u32::parse(source).map(Into::into)
+    }
+}
+
+
+//------------ Action --------------------------------------------------------
+//
+// Encoded as the flags octet used by RTR.
+
+impl<W: io::Write> Compose<W> for Action {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        self.into_flags().compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for Action {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        u8::parse(source).map(Action::from_flags)
+    }
+}
+
+
+//------------ RouteOrigin ---------------------------------------------------
+//
+// Encoded as the address family (4 or 6) followed by the address octets,
+// the prefix length, the optional max-length, and the ASN.
+
+impl<W: io::Write> Compose<W> for RouteOrigin {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        match self.prefix.addr() {
+            IpAddr::V4(addr) => {
+                4u8.compose(target)?;
+                target.write_all(&addr.octets())?;
+            }
+            IpAddr::V6(addr) => {
+                6u8.compose(target)?;
+                target.write_all(&addr.octets())?;
+            }
+        }
+        self.prefix.prefix_len().compose(target)?;
+        self.prefix.max_len().compose(target)?;
+        self.asn.compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for RouteOrigin {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        let addr = match u8::parse(source)? {
+            4 => {
+                let mut octets = [0u8; 4];
+                source.read_exact(&mut octets)?;
+                IpAddr::from(octets)
+            }
+            6 => {
+                let mut octets = [0u8; 16];
+                source.read_exact(&mut octets)?;
+                IpAddr::from(octets)
+            }
+            _ => return Err(ParseError::format("invalid address family"))
+        };
+        let prefix = Prefix::new(addr, u8::parse(source)?).map_err(|err| {
+            ParseError::format(format!("invalid prefix: {}", err))
+        })?;
+        let prefix = MaxLenPrefix::new(
+            prefix, Option::<u8>::parse(source)?
+        ).map_err(|err| {
+            ParseError::format(format!("invalid max-length: {}", err))
+        })?;
+        Ok(RouteOrigin::new(prefix, Asn::parse(source)?))
+    }
+}
+
+
+//------------ RouterKey -----------------------------------------------------
+//
+// Encoded as the 20 octets of the key identifier, the ASN, and the key
+// info as `Bytes`.
+
+impl<W: io::Write> Compose<W> for RouterKey {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        target.write_all(self.key_identifier.as_slice())?;
+        self.asn.compose(target)?;
+        Bytes::copy_from_slice(self.key_info.as_slice()).compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for RouterKey {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        let mut key_id = [0u8; 20];
+        source.read_exact(&mut key_id)?;
+        let asn = Asn::parse(source)?;
+        let key_info = RouterKeyInfo::new(
+            Bytes::parse(source)?
+        ).map_err(|_| ParseError::format("invalid router key info"))?;
+        Ok(RouterKey::new(key_id.into(), asn, key_info))
+    }
+}
+
+
+//------------ ProviderAsns --------------------------------------------------
+//
+// Encoded as the number of ASNs as a u16 followed by the ASNs.
+
+impl<W: io::Write> Compose<W> for ProviderAsns {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        target.write_all(&self.asn_count().to_be_bytes())?;
+        for asn in self.iter() {
+            asn.compose(target)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> Parse<R> for ProviderAsns {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        let mut len = [0u8; 2];
+        source.read_exact(&mut len)?;
+        let len = u16::from_be_bytes(len);
+        let mut asns = Vec::with_capacity(len.into());
+        for _ in 0..len {
+            asns.push(Asn::parse(source)?);
+        }
+        ProviderAsns::try_from_iter(asns).map_err(|_| {
+            ParseError::format("too many provider ASNs")
+        })
+    }
+}
+
+
+//------------ Aspa ----------------------------------------------------------
+//
+// Encoded as the customer ASN followed by the provider ASNs.
+
+impl<W: io::Write> Compose<W> for Aspa {
+    fn compose(&self, target: &mut W) -> Result<(), io::Error> {
+        self.customer.compose(target)?;
+        self.providers.compose(target)
+    }
+}
+
+impl<R: io::Read> Parse<R> for Aspa {
+    fn parse(source: &mut R) -> Result<Self, ParseError> {
+        Ok(Aspa::new(Asn::parse(source)?, ProviderAsns::parse(source)?))
+    }
+}
+
+
 //------------ HashMap<K, V> -------------------------------------------------
 //
 // Encoded as the number of items as a u64 followed by pairs of key and value.
@@ -544,5 +856,38 @@ mod test {
     fn write_read_hash() {
         test_write_read(rrdp::Hash::from([7u8; 32]));
     }
+
+    #[test]
+    fn write_read_string() {
+        test_write_read(String::from("ripe"));
+        test_write_read(Some(String::from("ripe")));
+        test_write_read(Option::<String>::None);
+    }
+
+    #[test]
+    fn write_read_route_origin() {
+        test_write_read(RouteOrigin::new(
+            MaxLenPrefix::new(
+                Prefix::from_str("192.0.2.0/24").unwrap(), Some(26)
+            ).unwrap(),
+            Asn::from_u32(64496)
+        ));
+        test_write_read(RouteOrigin::new(
+            MaxLenPrefix::new(
+                Prefix::from_str("2001:db8::/32").unwrap(), None
+            ).unwrap(),
+            Asn::from_u32(64497)
+        ));
+    }
+
+    #[test]
+    fn write_read_aspa() {
+        test_write_read(Aspa::new(
+            Asn::from_u32(64496),
+            ProviderAsns::try_from_iter([
+                Asn::from_u32(64497), Asn::from_u32(64498)
+            ]).unwrap()
+        ));
+    }
 }
 
@@ -0,0 +1,240 @@
+//! Extracting informational extensions from resource certificates.
+//!
+//! The RPKI library only keeps the parts of a certificate that are needed
+//! for validation. This module walks the raw certificate again to pick up
+//! the informational bits it drops. Since these bits have no influence on
+//! validation, the parser here is lenient: it only looks at the parts it
+//! needs and doesn’t check anything else.
+
+use std::fmt;
+use std::convert::Infallible;
+use bcder::{Ia5String, Mode, OctetString, Oid, Tag};
+use bcder::decode::{self, DecodeError};
+use bytes::Bytes;
+use rpki::oid;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The object identifier of the CPS pointer policy qualifier.
+///
+/// This is id-qt-cps from RFC 5280.
+const QT_CPS: Oid<&[u8]> = Oid(&[43, 6, 1, 5, 5, 7, 2, 1]);
+
+/// The extensions defined by the RPKI certificate profile.
+///
+/// These are handled by the RPKI library and thus not reported as other
+/// extensions.
+const PROFILE_EXTENSIONS: &[Oid<&[u8]>] = &[
+    oid::CE_BASIC_CONSTRAINTS,
+    oid::CE_SUBJECT_KEY_IDENTIFIER,
+    oid::CE_AUTHORITY_KEY_IDENTIFIER,
+    oid::CE_KEY_USAGE,
+    oid::CE_EXTENDED_KEY_USAGE,
+    oid::CE_CRL_DISTRIBUTION_POINTS,
+    oid::PE_AUTHORITY_INFO_ACCESS,
+    oid::PE_SUBJECT_INFO_ACCESS,
+    oid::CE_CERTIFICATE_POLICIES,
+    oid::PE_IP_ADDR_BLOCK,
+    oid::PE_IP_ADDR_BLOCK_V2,
+    oid::PE_AUTONOMOUS_SYS_IDS,
+    oid::PE_AUTONOMOUS_SYS_IDS_V2,
+];
+
+
+//------------ CertExtensions ------------------------------------------------
+
+/// The informational extensions of a certificate.
+#[derive(Clone, Debug, Default)]
+pub struct CertExtensions {
+    /// The CPS pointers given in the certificate policies.
+    pub cps: Vec<String>,
+
+    /// The object identifiers of extensions outside the RPKI profile.
+    ///
+    /// The identifiers are given in dotted notation.
+    pub other: Vec<String>,
+}
+
+impl CertExtensions {
+    /// Parses the extensions from the DER encoded certificate.
+    pub fn parse(cert: Bytes) -> Result<Self, ParseError> {
+        Mode::Der.decode(cert, |cons| {
+            cons.take_sequence(|cons| {
+                let res = cons.take_sequence(Self::take_tbs)?;
+                // signatureAlgorithm and signatureValue.
+                cons.skip_all()?;
+                Ok(res)
+            })
+        }).map_err(|err| ParseError(err.to_string()))
+    }
+
+    /// Returns whether there are no extensions.
+    pub fn is_empty(&self) -> bool {
+        self.cps.is_empty() && self.other.is_empty()
+    }
+
+    /// Takes the content of the TBSCertificate sequence.
+    fn take_tbs<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        let mut res = CertExtensions::default();
+
+        // version
+        cons.take_opt_constructed_if(Tag::CTX_0, |cons| cons.skip_all())?;
+        // serialNumber, signature, issuer, validity, subject,
+        // subjectPublicKeyInfo
+        for _ in 0..6 {
+            if cons.skip_one()?.is_none() {
+                return Err(cons.content_err("short TBSCertificate"))
+            }
+        }
+        // issuerUniqueID, subjectUniqueID
+        cons.take_opt_value_if(Tag::CTX_1, |content| {
+            content.as_primitive()?.skip_all()
+        })?;
+        cons.take_opt_value_if(Tag::CTX_2, |content| {
+            content.as_primitive()?.skip_all()
+        })?;
+        cons.take_opt_constructed_if(Tag::CTX_3, |cons| {
+            cons.take_sequence(|cons| {
+                while let Some(()) = cons.take_opt_sequence(|cons| {
+                    let id = Oid::take_from(cons)?;
+                    cons.take_opt_bool()?;
+                    let value = OctetString::take_from(cons)?;
+                    if id == oid::CE_CERTIFICATE_POLICIES {
+                        res.cps.extend(
+                            Self::decode_policies(value)
+                                .map_err(DecodeError::convert)?
+                        );
+                    }
+                    else if !PROFILE_EXTENSIONS.iter().any(|known| {
+                        id == *known
+                    }) {
+                        res.other.push(id.to_string())
+                    }
+                    Ok(())
+                })? { }
+                Ok(())
+            })
+        })?;
+        cons.skip_all()?;
+        Ok(res)
+    }
+
+    /// Decodes the CPS pointers from the certificate policies extension.
+    fn decode_policies(
+        value: OctetString
+    ) -> Result<Vec<String>, DecodeError<Infallible>> {
+        Mode::Der.decode(value, |cons| {
+            let mut res = Vec::new();
+            cons.take_sequence(|cons| {
+                while let Some(()) = cons.take_opt_sequence(|cons| {
+                    // policyIdentifier
+                    Oid::skip_in(cons)?;
+                    // policyQualifiers
+                    cons.take_opt_sequence(|cons| {
+                        while let Some(()) = cons.take_opt_sequence(|cons| {
+                            let id = Oid::take_from(cons)?;
+                            if id == QT_CPS {
+                                res.push(
+                                    Ia5String::take_from(cons)?.to_string()
+                                );
+                            }
+                            else {
+                                cons.skip_all()?;
+                            }
+                            Ok(())
+                        })? { }
+                        Ok(())
+                    })?;
+                    Ok(())
+                })? { }
+                Ok(())
+            })?;
+            Ok(res)
+        })
+    }
+}
+
+
+//------------ ParseError ----------------------------------------------------
+
+/// Parsing the certificate failed.
+#[derive(Clone, Debug)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Encodes a DER value with the given tag and content.
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let len = u8::try_from(content.len()).unwrap();
+        let mut res = if len < 128 { vec![tag, len] }
+                      else { vec![tag, 0x81, len] };
+        res.extend_from_slice(content);
+        res
+    }
+
+    fn seq(items: &[Vec<u8>]) -> Vec<u8> {
+        tlv(0x30, &items.concat())
+    }
+
+    fn oid(value: &Oid<&[u8]>) -> Vec<u8> {
+        tlv(0x06, value.as_ref())
+    }
+
+    fn extension(id: &Oid<&[u8]>, value: &[u8]) -> Vec<u8> {
+        seq(&[oid(id), tlv(0x04, value)])
+    }
+
+    #[test]
+    fn parse() {
+        let policies = seq(&[
+            seq(&[
+                oid(&oid::CP_IPADDR_ASNUMBER),
+                seq(&[
+                    seq(&[
+                        oid(&QT_CPS),
+                        tlv(0x16, b"https://example.com/cps"),
+                    ]),
+                    seq(&[
+                        oid(&Oid(&[43, 6, 1, 5, 5, 7, 2, 2])),
+                        seq(&[]),
+                    ]),
+                ]),
+            ]),
+        ]);
+        let tbs = seq(&[
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            seq(&[]),
+            seq(&[]),
+            seq(&[]),
+            seq(&[]),
+            seq(&[]),
+            tlv(0xa3, &seq(&[
+                extension(&oid::CE_BASIC_CONSTRAINTS, &seq(&[])),
+                extension(&oid::CE_CERTIFICATE_POLICIES, &policies),
+                extension(&Oid(&[43, 6, 1, 4, 1, 1]), &[0x05, 0x00]),
+            ])),
+        ]);
+        let cert = seq(&[tbs, seq(&[]), tlv(0x03, &[0])]);
+
+        let ext = CertExtensions::parse(cert.into()).unwrap();
+        assert_eq!(ext.cps, ["https://example.com/cps"]);
+        assert_eq!(ext.other, ["1.3.6.1.4.1.1"]);
+
+        assert!(CertExtensions::parse(seq(&[seq(&[])]).into()).is_err());
+    }
+}
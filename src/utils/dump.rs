@@ -1,8 +1,16 @@
 /// Utilities for creating data dumps.
 
+use std::{fs, io};
 use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::error;
 use rpki::uri;
+use crate::error::Failed;
+use super::fatal;
 
 
 //------------ DumpRegistry --------------------------------------------------
@@ -83,3 +91,265 @@ impl DumpRegistry {
     }
 }
 
+
+//------------ DumpTarget ----------------------------------------------------
+
+/// The place a dump is written to.
+///
+/// A dump can either be written into a directory tree or as a tar archive
+/// into some writer. All paths handed to the methods of this type are
+/// relative to the root of the dump.
+pub struct DumpTarget {
+    /// The actual target.
+    kind: TargetKind,
+}
+
+/// The kind of a dump target.
+enum TargetKind {
+    /// A directory with the given path.
+    Dir(PathBuf),
+
+    /// A tar archive.
+    Tar(tar::Builder<TarWriter>),
+}
+
+impl DumpTarget {
+    /// Creates a target writing into the given directory.
+    pub fn dir(path: PathBuf) -> Self {
+        DumpTarget { kind: TargetKind::Dir(path) }
+    }
+
+    /// Creates a target writing a tar archive into a writer.
+    ///
+    /// If `gzip` is `true`, the archive will be gzip-compressed.
+    pub fn tar(writer: Box<dyn io::Write>, gzip: bool) -> Self {
+        let writer = if gzip {
+            TarWriter::Gzip(GzEncoder::new(writer, Compression::default()))
+        }
+        else {
+            TarWriter::Plain(writer)
+        };
+        DumpTarget { kind: TargetKind::Tar(tar::Builder::new(writer)) }
+    }
+
+    /// Removes any previous content under the given path.
+    ///
+    /// This only does something for directory targets.
+    pub fn clear(&mut self, path: &Path) -> Result<(), Failed> {
+        match self.kind {
+            TargetKind::Dir(ref base) => {
+                fatal::remove_dir_all(&base.join(path))
+            }
+            TargetKind::Tar(_) => Ok(())
+        }
+    }
+
+    /// Writes a file with the given content.
+    pub fn write_file(
+        &mut self, path: &Path, content: &[u8]
+    ) -> Result<(), Failed> {
+        match self.kind {
+            TargetKind::Dir(ref base) => {
+                let path = base.join(path);
+                fatal::create_parent_all(&path)?;
+                fatal::write_file(&path, content)
+            }
+            TargetKind::Tar(ref mut builder) => {
+                Self::append_tar(builder, path, content.len() as u64, content)
+            }
+        }
+    }
+
+    /// Copies the file at `source` into the dump at `path`.
+    ///
+    /// The file is copied piece by piece rather than loaded into memory.
+    pub fn copy_file(
+        &mut self, path: &Path, source: &Path
+    ) -> Result<(), Failed> {
+        match self.kind {
+            TargetKind::Dir(ref base) => {
+                let path = base.join(path);
+                fatal::create_parent_all(&path)?;
+                if let Err(err) = fs::copy(source, &path) {
+                    error!(
+                        "Fatal: failed to copy {} to {}: {}",
+                        source.display(), path.display(), err
+                    );
+                    return Err(Failed)
+                }
+                Ok(())
+            }
+            TargetKind::Tar(ref mut builder) => {
+                let file = fatal::open_file(source)?;
+                let len = match file.metadata() {
+                    Ok(metadata) => metadata.len(),
+                    Err(err) => {
+                        error!(
+                            "Fatal: failed to read metadata of {}: {}",
+                            source.display(), err
+                        );
+                        return Err(Failed)
+                    }
+                };
+                Self::append_tar(builder, path, len, file)
+            }
+        }
+    }
+
+    /// Copies the content of the directory `source` into the dump.
+    ///
+    /// If the source directory does not exist, does nothing.
+    pub fn copy_dir_all(
+        &mut self, path: &Path, source: &Path
+    ) -> Result<(), Failed> {
+        let dir = match fatal::read_existing_dir(source)? {
+            Some(dir) => dir,
+            None => return Ok(())
+        };
+        for entry in dir {
+            let entry = entry?;
+            if entry.is_file() {
+                self.copy_file(&path.join(entry.file_name()), entry.path())?;
+            }
+            else if entry.is_dir() {
+                self.copy_dir_all(
+                    &path.join(entry.file_name()), entry.path()
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the dump.
+    ///
+    /// For tar archives, this writes the end of the archive and flushes
+    /// the underlying writer. The target must not be dropped without
+    /// calling this method or the archive may be incomplete.
+    pub fn finish(self) -> Result<(), Failed> {
+        let builder = match self.kind {
+            TargetKind::Dir(_) => return Ok(()),
+            TargetKind::Tar(builder) => builder,
+        };
+        let res = builder.into_inner().and_then(|writer| {
+            match writer {
+                TarWriter::Plain(mut writer) => writer.flush(),
+                TarWriter::Gzip(writer) => writer.finish()?.flush(),
+            }
+        });
+        res.map_err(|err| {
+            error!("Fatal: failed to write dump archive: {}", err);
+            Failed
+        })
+    }
+
+    /// Appends a file to a tar archive.
+    fn append_tar(
+        builder: &mut tar::Builder<TarWriter>,
+        path: &Path,
+        len: u64,
+        data: impl io::Read,
+    ) -> Result<(), Failed> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(len);
+        header.set_mode(0o644);
+        header.set_mtime(
+            SystemTime::now().duration_since(
+                SystemTime::UNIX_EPOCH
+            ).map(|duration| duration.as_secs()).unwrap_or(0)
+        );
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, path, data).map_err(|err| {
+            error!(
+                "Fatal: failed to write {} to dump archive: {}",
+                path.display(), err
+            );
+            Failed
+        })
+    }
+}
+
+
+//------------ TarWriter -----------------------------------------------------
+
+/// The writer underneath a tar dump target.
+enum TarWriter {
+    /// The archive is written as is.
+    Plain(Box<dyn io::Write>),
+
+    /// The archive is gzip-compressed.
+    Gzip(GzEncoder<Box<dyn io::Write>>),
+}
+
+impl io::Write for TarWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        match *self {
+            TarWriter::Plain(ref mut writer) => writer.write(buf),
+            TarWriter::Gzip(ref mut writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        match *self {
+            TarWriter::Plain(ref mut writer) => writer.flush(),
+            TarWriter::Gzip(ref mut writer) => writer.flush(),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer appending to a shared vec.
+    #[derive(Clone, Default)]
+    struct SharedVec(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedVec {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), io::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tar_target() {
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("sub")).unwrap();
+        fs::write(
+            source.path().join("sub/file.cer"), b"certificate"
+        ).unwrap();
+
+        let out = SharedVec::default();
+        let mut target = DumpTarget::tar(Box::new(out.clone()), false);
+        target.write_file(
+            Path::new("store/repositories.json"), b"{}"
+        ).unwrap();
+        target.copy_dir_all(Path::new("ta"), source.path()).unwrap();
+        target.finish().unwrap();
+
+        let data = out.0.lock().unwrap().clone();
+        let mut archive = tar::Archive::new(data.as_slice());
+        let mut entries: Vec<_> = archive.entries().unwrap().map(|entry| {
+            let mut entry = entry.unwrap();
+            let mut content = Vec::new();
+            io::Read::read_to_end(&mut entry, &mut content).unwrap();
+            (entry.path().unwrap().into_owned(), content)
+        }).collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("store/repositories.json"), b"{}".to_vec()),
+                (PathBuf::from("ta/sub/file.cer"), b"certificate".to_vec()),
+            ]
+        );
+    }
+}
@@ -230,6 +230,24 @@ pub fn remove_all(path: &Path) -> Result<(), Failed> {
 }
 
 
+//------------ disk_usage ----------------------------------------------------
+
+/// Returns the number of bytes used by the files of a directory entry.
+///
+/// If the entry is a directory, the sizes of all files in the tree below
+/// it are added up.
+pub fn disk_usage(entry: &DirEntry) -> Result<u64, Failed> {
+    if !entry.is_dir() {
+        return Ok(entry.len())
+    }
+    let mut res = 0;
+    for entry in read_dir(entry.path())? {
+        res += disk_usage(&entry?)?;
+    }
+    Ok(res)
+}
+
+
 //------------ rename --------------------------------------------------------
 
 /// Renames a file or directory.
@@ -0,0 +1,128 @@
+//! Advisory locking via lock files.
+//!
+//! This module provides a [`LockFile`] that can be used to make sure that
+//! processes don’t step on each other’s toes when accessing the cache. Any
+//! number of processes can hold a shared lock at the same time, while only
+//! one process can hold an exclusive lock and only if there are no shared
+//! locks held.
+//!
+//! Locking is only implemented on Unix systems. On other systems, acquiring
+//! a lock always succeeds.
+
+use std::fs;
+use std::path::Path;
+use log::error;
+use crate::error::Failed;
+
+
+//------------ LockFile ------------------------------------------------------
+
+/// A lock held via a lock file.
+///
+/// The lock is released when the value is dropped.
+#[derive(Debug)]
+pub struct LockFile {
+    /// The open lock file.
+    ///
+    /// The lock is tied to the open file, so we need to keep it around.
+    _file: fs::File,
+}
+
+impl LockFile {
+    /// Tries to acquire a shared lock on the given file.
+    ///
+    /// Creates the file if necessary. Returns `Ok(None)` if someone else
+    /// is holding an exclusive lock.
+    pub fn shared(path: &Path) -> Result<Option<Self>, Failed> {
+        Self::acquire(path, false)
+    }
+
+    /// Tries to acquire an exclusive lock on the given file.
+    ///
+    /// Creates the file if necessary. Returns `Ok(None)` if someone else
+    /// is holding any lock.
+    pub fn exclusive(path: &Path) -> Result<Option<Self>, Failed> {
+        Self::acquire(path, true)
+    }
+
+    fn acquire(path: &Path, exclusive: bool) -> Result<Option<Self>, Failed> {
+        let file = match fs::OpenOptions::new()
+            .read(true).write(true).create(true).truncate(false)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(err) => {
+                error!(
+                    "Fatal: failed to open lock file {}: {}",
+                    path.display(), err
+                );
+                return Err(Failed)
+            }
+        };
+        if !Self::lock(&file, path, exclusive)? {
+            return Ok(None)
+        }
+        Ok(Some(LockFile { _file: file }))
+    }
+
+    #[cfg(unix)]
+    fn lock(
+        file: &fs::File, path: &Path, exclusive: bool
+    ) -> Result<bool, Failed> {
+        use std::os::unix::io::AsRawFd;
+        use nix::errno::Errno;
+        use nix::fcntl::{flock, FlockArg};
+
+        let arg = if exclusive {
+            FlockArg::LockExclusiveNonblock
+        }
+        else {
+            FlockArg::LockSharedNonblock
+        };
+        match flock(file.as_raw_fd(), arg) {
+            Ok(()) => Ok(true),
+            Err(Errno::EWOULDBLOCK) => Ok(false),
+            Err(err) => {
+                error!(
+                    "Fatal: failed to lock file {}: {}",
+                    path.display(), err
+                );
+                Err(Failed)
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn lock(
+        _file: &fs::File, _path: &Path, _exclusive: bool
+    ) -> Result<bool, Failed> {
+        Ok(true)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_and_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+
+        let shared1 = LockFile::shared(&path).unwrap().unwrap();
+        let shared2 = LockFile::shared(&path).unwrap().unwrap();
+        assert!(LockFile::exclusive(&path).unwrap().is_none());
+        drop(shared1);
+        assert!(LockFile::exclusive(&path).unwrap().is_none());
+        drop(shared2);
+
+        let exclusive = LockFile::exclusive(&path).unwrap().unwrap();
+        assert!(LockFile::shared(&path).unwrap().is_none());
+        assert!(LockFile::exclusive(&path).unwrap().is_none());
+        drop(exclusive);
+        assert!(LockFile::shared(&path).unwrap().is_some());
+    }
+}
@@ -2,12 +2,16 @@
 
 pub mod archive;
 pub mod binio;
+pub mod certext;
 pub mod date;
 pub mod dump;
 pub mod fatal;
 pub mod fmt;
 pub mod json;
+pub mod lock;
+pub mod mrt;
 pub mod net;
+pub mod shutdown;
 pub mod str;
 pub mod sync;
 pub mod tls;
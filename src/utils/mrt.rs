@@ -0,0 +1,353 @@
+//! Reading routing table dumps in MRT format.
+//!
+//! This module implements just enough of [RFC 6396] to extract the prefix
+//! and origin AS number of all unicast routes contained in a
+//! `TABLE_DUMP_V2` RIB dump, including the ADD-PATH variants defined in
+//! [RFC 8050]. All other records are skipped.
+//!
+//! [RFC 6396]: https://tools.ietf.org/html/rfc6396
+//! [RFC 8050]: https://tools.ietf.org/html/rfc8050
+
+use std::io;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use rpki::resources::{Asn, Prefix};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The MRT type for `TABLE_DUMP_V2` records.
+const TABLE_DUMP_V2: u16 = 13;
+
+/// The subtype for IPv4 unicast RIB entries.
+const RIB_IPV4_UNICAST: u16 = 2;
+
+/// The subtype for IPv6 unicast RIB entries.
+const RIB_IPV6_UNICAST: u16 = 4;
+
+/// The subtype for IPv4 unicast RIB entries with path identifiers.
+const RIB_IPV4_UNICAST_ADDPATH: u16 = 8;
+
+/// The subtype for IPv6 unicast RIB entries with path identifiers.
+const RIB_IPV6_UNICAST_ADDPATH: u16 = 10;
+
+/// The BGP path attribute type of the AS_PATH attribute.
+const ATTR_AS_PATH: u8 = 2;
+
+/// The flag marking a BGP path attribute with a two octet length.
+const ATTR_FLAG_EXTENDED_LEN: u8 = 0x10;
+
+/// The AS_PATH segment type for an AS_SET.
+const AS_SET: u8 = 1;
+
+/// The AS_PATH segment type for an AS_SEQUENCE.
+const AS_SEQUENCE: u8 = 2;
+
+
+//------------ read_rib ------------------------------------------------------
+
+/// Reads all unicast routes from an MRT RIB dump.
+///
+/// Calls `op` with the prefix and origin AS number of every RIB entry in
+/// the dump. Since a dump contains the routes learned from all peers, the
+/// same route will typically be reported multiple times.
+///
+/// Following [RFC 6811], the origin AS is the last AS number of the
+/// AS_PATH if the final segment is an AS_SEQUENCE. Otherwise the origin
+/// can’t be determined and `None` is given instead. Confederation segments
+/// are ignored.
+///
+/// [RFC 6811]: https://tools.ietf.org/html/rfc6811
+pub fn read_rib<R: io::Read>(
+    reader: &mut R,
+    mut op: impl FnMut(Prefix, Option<Asn>),
+) -> Result<(), io::Error> {
+    let mut body = Vec::new();
+    while let Some((mrt_type, subtype, len)) = read_header(reader)? {
+        body.clear();
+        let read = reader.by_ref().take(
+            len.into()
+        ).read_to_end(&mut body)?;
+        if read != len as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof, "truncated MRT record"
+            ))
+        }
+        if mrt_type != TABLE_DUMP_V2 {
+            continue
+        }
+        let (v4, addpath) = match subtype {
+            RIB_IPV4_UNICAST => (true, false),
+            RIB_IPV6_UNICAST => (false, false),
+            RIB_IPV4_UNICAST_ADDPATH => (true, true),
+            RIB_IPV6_UNICAST_ADDPATH => (false, true),
+            _ => continue
+        };
+        parse_rib_record(&body, v4, addpath, &mut op)?;
+    }
+    Ok(())
+}
+
+/// Reads the common header of an MRT record.
+///
+/// Returns the type, subtype, and length of the record or `None` if the
+/// reader is at its end.
+fn read_header<R: io::Read>(
+    reader: &mut R
+) -> Result<Option<(u16, u16, u32)>, io::Error> {
+    let mut header = [0u8; 12];
+    let mut read = 0;
+    while read < header.len() {
+        match reader.read(&mut header[read..]) {
+            Ok(0) => {
+                if read == 0 {
+                    return Ok(None)
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof, "truncated MRT header"
+                ))
+            }
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => { }
+            Err(err) => return Err(err)
+        }
+    }
+    // The first four octets are the timestamp which we don’t need.
+    Ok(Some((
+        u16::from_be_bytes([header[4], header[5]]),
+        u16::from_be_bytes([header[6], header[7]]),
+        u32::from_be_bytes([header[8], header[9], header[10], header[11]]),
+    )))
+}
+
+/// Parses the body of an AFI/SAFI-specific RIB record.
+fn parse_rib_record(
+    data: &[u8], v4: bool, addpath: bool,
+    op: &mut impl FnMut(Prefix, Option<Asn>),
+) -> Result<(), io::Error> {
+    let mut data = Data(data);
+
+    // Sequence number.
+    data.skip(4)?;
+
+    let prefix_len = data.u8()?;
+    let prefix = if v4 {
+        if prefix_len > 32 {
+            return Err(Data::error("invalid IPv4 prefix length"))
+        }
+        let mut addr = [0u8; 4];
+        data.prefix_bits(prefix_len, &mut addr)?;
+        Prefix::new_relaxed(IpAddr::V4(Ipv4Addr::from(addr)), prefix_len)
+    }
+    else {
+        if prefix_len > 128 {
+            return Err(Data::error("invalid IPv6 prefix length"))
+        }
+        let mut addr = [0u8; 16];
+        data.prefix_bits(prefix_len, &mut addr)?;
+        Prefix::new_relaxed(IpAddr::V6(Ipv6Addr::from(addr)), prefix_len)
+    }.map_err(|_| Data::error("invalid prefix"))?;
+
+    let count = data.u16()?;
+    for _ in 0..count {
+        // Peer index and originated time.
+        data.skip(6)?;
+        if addpath {
+            // Path identifier.
+            data.skip(4)?;
+        }
+        let attr_len = data.u16()?;
+        let attrs = data.take(attr_len.into())?;
+        op(prefix, origin_as(attrs)?);
+    }
+    Ok(())
+}
+
+/// Determines the origin AS from a sequence of BGP path attributes.
+fn origin_as(attrs: &[u8]) -> Result<Option<Asn>, io::Error> {
+    let mut attrs = Data(attrs);
+    while !attrs.is_empty() {
+        let flags = attrs.u8()?;
+        let attr_type = attrs.u8()?;
+        let len = if flags & ATTR_FLAG_EXTENDED_LEN != 0 {
+            attrs.u16()?
+        }
+        else {
+            attrs.u8()?.into()
+        };
+        let value = attrs.take(len.into())?;
+        if attr_type == ATTR_AS_PATH {
+            return origin_from_path(value)
+        }
+    }
+    Ok(None)
+}
+
+/// Determines the origin AS from the value of an AS_PATH attribute.
+///
+/// In MRT dumps, AS_PATH always uses four octet AS numbers.
+fn origin_from_path(path: &[u8]) -> Result<Option<Asn>, io::Error> {
+    let mut path = Data(path);
+    let mut res = None;
+    while !path.is_empty() {
+        let segment_type = path.u8()?;
+        let count = path.u8()?;
+        let asns = path.take(usize::from(count) * 4)?;
+        match segment_type {
+            AS_SEQUENCE => {
+                res = asns.chunks_exact(4).last().map(|asn| {
+                    Asn::from_u32(u32::from_be_bytes(
+                        [asn[0], asn[1], asn[2], asn[3]]
+                    ))
+                });
+            }
+            AS_SET => res = None,
+            _ => { }
+        }
+    }
+    Ok(res)
+}
+
+
+//------------ Data ----------------------------------------------------------
+
+/// A helper for taking apart a record body.
+struct Data<'a>(&'a [u8]);
+
+impl<'a> Data<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.0.len() < len {
+            return Err(Self::error("truncated RIB record"))
+        }
+        let (res, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(res)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), io::Error> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        self.take(1).map(|data| data[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        self.take(2).map(|data| u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Reads the significant octets of a prefix into `addr`.
+    fn prefix_bits(
+        &mut self, len: u8, addr: &mut [u8]
+    ) -> Result<(), io::Error> {
+        let octets = self.take(usize::from(len).div_ceil(8))?;
+        addr[..octets.len()].copy_from_slice(octets);
+        Ok(())
+    }
+
+    fn error(msg: &'static str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Creates an MRT record with the given type, subtype, and body.
+    fn record(mrt_type: u16, subtype: u16, body: &[u8]) -> Vec<u8> {
+        let mut res = vec![0, 0, 0, 0];
+        res.extend_from_slice(&mrt_type.to_be_bytes());
+        res.extend_from_slice(&subtype.to_be_bytes());
+        res.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        res.extend_from_slice(body);
+        res
+    }
+
+    /// Creates a RIB entry with an AS_PATH made of the given segments.
+    fn rib_entry(segments: &[(u8, &[u32])]) -> Vec<u8> {
+        let mut path = Vec::new();
+        for (segment_type, asns) in segments {
+            path.push(*segment_type);
+            path.push(asns.len() as u8);
+            for asn in asns.iter() {
+                path.extend_from_slice(&asn.to_be_bytes());
+            }
+        }
+        // ORIGIN attribute first, then AS_PATH with extended length.
+        let mut attrs = vec![0x40, 1, 1, 0];
+        attrs.extend_from_slice(&[0x50, ATTR_AS_PATH]);
+        attrs.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        attrs.extend_from_slice(&path);
+
+        let mut res = vec![0, 0, 0, 0, 0, 0];
+        res.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        res.extend_from_slice(&attrs);
+        res
+    }
+
+    fn rib_record(prefix: &[u8], entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut res = vec![0, 0, 0, 1];
+        res.extend_from_slice(prefix);
+        res.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+        for entry in entries {
+            res.extend_from_slice(entry);
+        }
+        res
+    }
+
+    fn read(data: &[u8]) -> Result<Vec<(Prefix, Option<Asn>)>, io::Error> {
+        let mut res = Vec::new();
+        read_rib(&mut &*data, |prefix, asn| res.push((prefix, asn)))?;
+        Ok(res)
+    }
+
+    #[test]
+    fn read_rib_routes() {
+        let mut data = record(TABLE_DUMP_V2, 1, b"peer index table");
+        data.extend(record(RIB_IPV4_UNICAST, 2, b"not a table dump"));
+        data.extend(record(TABLE_DUMP_V2, RIB_IPV4_UNICAST, &rib_record(
+            &[24, 192, 0, 2],
+            &[
+                rib_entry(&[(AS_SEQUENCE, &[64496, 64497])]),
+                rib_entry(&[
+                    (AS_SEQUENCE, &[64498]), (AS_SET, &[64499, 64500])
+                ]),
+                rib_entry(&[]),
+            ]
+        )));
+        data.extend(record(TABLE_DUMP_V2, RIB_IPV6_UNICAST, &rib_record(
+            &[32, 0x20, 0x01, 0x0d, 0xb8],
+            &[rib_entry(&[(AS_SEQUENCE, &[64501]), (3, &[65000])])]
+        )));
+
+        let routes = read(&data).unwrap();
+        let v4 = Prefix::from_str("192.0.2.0/24").unwrap();
+        let v6 = Prefix::from_str("2001:db8::/32").unwrap();
+        assert_eq!(routes, [
+            (v4, Some(Asn::from_u32(64497))),
+            (v4, None),
+            (v4, None),
+            (v6, Some(Asn::from_u32(64501))),
+        ]);
+    }
+
+    #[test]
+    fn read_rib_errors() {
+        assert!(read(&[0, 0, 0, 0, 0, 13]).is_err());
+        assert!(read(&record(TABLE_DUMP_V2, 2, &[0, 0, 0, 1, 33])).is_err());
+        let mut data = record(TABLE_DUMP_V2, 2, &rib_record(
+            &[24, 192, 0, 2], &[rib_entry(&[(AS_SEQUENCE, &[64496])])]
+        ));
+        data.pop();
+        assert!(read(&data).is_err());
+    }
+}
@@ -1,22 +1,310 @@
 //! Utility functions related to networking.
 
+use std::{fs, io};
 use std::net::{SocketAddr, TcpListener as StdListener};
-use log::error;
+use std::path::{Path, PathBuf};
+use log::{error, warn};
+use socket2::{Domain, Socket, Type};
+use crate::config::{Config, ListenAddr};
 use crate::error::ExitError;
 
+#[cfg(unix)]
+pub use std::os::unix::net::UnixListener as StdUnixListener;
 
-pub fn bind(addr: &SocketAddr) -> Result<StdListener, ExitError> {
-    let listener = match StdListener::bind(addr) {
+/// A placeholder for Unix domain socket listeners on other systems.
+///
+/// Since the type is uninhabited, there can never be such a listener.
+#[cfg(not(unix))]
+pub type StdUnixListener = std::convert::Infallible;
+
+
+/// Binds listeners to all the addresses of a listen address.
+///
+/// Host names are resolved first. Returns the socket addresses together
+/// with their listeners. The sockets are configured according to the
+/// `listen-reuseport` and `listen-backlog` options.
+///
+/// If only a port is given, the listener for the IPv6 unspecified address
+/// is restricted to IPv6 so it doesn’t clash with the one for the IPv4
+/// unspecified address.
+pub fn bind_listen_addr(
+    addr: &ListenAddr,
+    config: &Config,
+) -> Result<Vec<(SocketAddr, StdListener)>, ExitError> {
+    let addrs = match addr.resolve() {
+        Ok(addrs) => addrs,
+        Err(err) => {
+            error!(
+                "Fatal: failed to resolve listen address {}: {}", addr, err
+            );
+            return Err(ExitError::Generic);
+        }
+    };
+    let v6only = matches!(addr, ListenAddr::Port(_));
+    addrs.into_iter().map(|sock| {
+        match bind(&sock, v6only && sock.is_ipv6(), config) {
+            Ok(listener) => Ok((sock, listener)),
+            Err(err) => {
+                error!("Fatal error listening on {}: {}", sock, err);
+                Err(ExitError::Generic)
+            }
+        }
+    }).collect()
+}
+
+/// Creates a nonblocking listener bound to the given address.
+fn bind(
+    addr: &SocketAddr,
+    v6only: bool,
+    config: &Config,
+) -> Result<StdListener, io::Error> {
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, None)?;
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    if v6only {
+        socket.set_only_v6(true)?;
+    }
+    if config.listen_reuseport {
+        set_reuse_port(&socket, addr)?;
+    }
+    socket.bind(&(*addr).into())?;
+    socket.listen(
+        i32::try_from(config.listen_backlog).unwrap_or(i32::MAX)
+    )?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Sets the `SO_REUSEPORT` option on a socket.
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &Socket, _addr: &SocketAddr) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+/// Sets the `SO_REUSEPORT` option on a socket.
+///
+/// This system doesn’t support the option, so we only log a warning.
+#[cfg(not(all(
+    unix, not(any(target_os = "solaris", target_os = "illumos"))
+)))]
+fn set_reuse_port(_socket: &Socket, addr: &SocketAddr) -> io::Result<()> {
+    log::warn!(
+        "Ignoring listen-reuseport for {}: not supported on this system.",
+        addr
+    );
+    Ok(())
+}
+
+/// Binds a listener to a Unix domain socket at the given path.
+///
+/// If there already is a socket at the path that nobody is listening on
+/// anymore, for instance because it was left behind by a crashed process,
+/// it is removed first.
+#[cfg(unix)]
+pub fn bind_unix(path: &Path) -> Result<StdUnixListener, ExitError> {
+    remove_stale_socket(path)?;
+    let listener = match StdUnixListener::bind(path) {
         Ok(listener) => listener,
         Err(err) => {
-            error!("Fatal error listening on {}: {}", addr, err);
+            error!("Fatal error listening on {}: {}", path.display(), err);
             return Err(ExitError::Generic);
         }
     };
     if let Err(err) = listener.set_nonblocking(true) {
-        error!("Fatal: error switching {} to nonblocking: {}", addr, err);
+        error!(
+            "Fatal: error switching {} to nonblocking: {}",
+            path.display(), err
+        );
         return Err(ExitError::Generic);
     }
     Ok(listener)
 }
 
+#[cfg(not(unix))]
+pub fn bind_unix(path: &Path) -> Result<StdUnixListener, ExitError> {
+    error!(
+        "Fatal: cannot listen on {}: Unix domain sockets are not \
+         supported on this system.",
+        path.display()
+    );
+    Err(ExitError::Generic)
+}
+
+/// Removes a Unix domain socket at `path` if nobody is listening on it.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<(), ExitError> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    match fs::symlink_metadata(path) {
+        Ok(meta) => {
+            if !meta.file_type().is_socket() {
+                error!(
+                    "Fatal: cannot listen on {}: file exists and is not \
+                     a socket.",
+                    path.display()
+                );
+                return Err(ExitError::Generic)
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            error!("Fatal: cannot access {}: {}", path.display(), err);
+            return Err(ExitError::Generic)
+        }
+    }
+    if UnixStream::connect(path).is_ok() {
+        error!(
+            "Fatal: cannot listen on {}: socket is in use by another \
+             process.",
+            path.display()
+        );
+        return Err(ExitError::Generic)
+    }
+    if let Err(err) = fs::remove_file(path) {
+        error!(
+            "Fatal: failed to remove stale socket {}: {}",
+            path.display(), err
+        );
+        return Err(ExitError::Generic)
+    }
+    Ok(())
+}
+
+
+//------------ UnixSocketFiles -----------------------------------------------
+
+/// The files of the Unix domain sockets the server is listening on.
+///
+/// The files are removed when the server shuts down.
+#[derive(Clone, Debug, Default)]
+pub struct UnixSocketFiles {
+    /// The paths of the socket files as seen after a possible chroot.
+    paths: Vec<PathBuf>,
+}
+
+impl UnixSocketFiles {
+    /// Collects the socket files of the listeners from the config.
+    ///
+    /// This needs to be called with the paths as given in the config. If
+    /// the process will be chrooted, sockets outside the chroot directory
+    /// can’t be reached later and are skipped.
+    pub fn from_config(config: &Config) -> Self {
+        let paths = config.rtr_listen_unix.iter().chain(
+            config.http_listen_unix.iter()
+        );
+        let paths = match config.chroot.as_ref() {
+            Some(chroot) => {
+                paths.filter_map(|path| {
+                    path.strip_prefix(chroot).ok().map(|path| {
+                        Path::new("/").join(path)
+                    })
+                }).collect()
+            }
+            None => paths.cloned().collect(),
+        };
+        UnixSocketFiles { paths }
+    }
+
+    /// Removes the socket files.
+    ///
+    /// Since the files will be removed as stale upon the next start
+    /// anyway, failing to remove them is only logged.
+    pub fn remove(&self) {
+        for path in &self.paths {
+            if let Err(err) = fs::remove_file(path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Failed to remove socket {}: {}",
+                        path.display(), err
+                    );
+                }
+            }
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn bind_reuseport() {
+        let addr = ListenAddr::from_str("127.0.0.1:0").unwrap();
+        let mut config = Config::default();
+        let (_, first) = bind_listen_addr(
+            &addr, &config
+        ).unwrap().pop().unwrap();
+        let addr = ListenAddr::Addr(first.local_addr().unwrap());
+
+        // Without SO_REUSEPORT, the second bind must fail.
+        assert!(bind_listen_addr(&addr, &config).is_err());
+
+        // With it set on both sockets, it succeeds.
+        config.listen_reuseport = true;
+        let (_, first) = bind_listen_addr(
+            &ListenAddr::from_str("127.0.0.1:0").unwrap(), &config
+        ).unwrap().pop().unwrap();
+        let addr = ListenAddr::Addr(first.local_addr().unwrap());
+        assert!(bind_listen_addr(&addr, &config).is_ok());
+    }
+
+    #[test]
+    fn bind_unix_removes_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sock");
+
+        // A live socket must not be replaced.
+        let listener = bind_unix(&path).unwrap();
+        assert!(bind_unix(&path).is_err());
+
+        // Once the listener is gone, the socket file is stale.
+        drop(listener);
+        assert!(path.exists());
+        let _listener = bind_unix(&path).unwrap();
+
+        // Other files are left alone.
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"foo").unwrap();
+        assert!(bind_unix(&file).is_err());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn unix_socket_files() {
+        let mut config = Config {
+            rtr_listen_unix: vec!["/var/run/rtr.sock".into()],
+            http_listen_unix: vec!["/srv/routinator/http.sock".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            UnixSocketFiles::from_config(&config).paths,
+            [
+                PathBuf::from("/var/run/rtr.sock"),
+                PathBuf::from("/srv/routinator/http.sock"),
+            ]
+        );
+
+        // With a chroot, only sockets under it can be removed.
+        config.chroot = Some("/srv/routinator".into());
+        assert_eq!(
+            UnixSocketFiles::from_config(&config).paths,
+            [PathBuf::from("/http.sock")]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sock");
+        let config = Config {
+            rtr_listen_unix: vec![path.clone(), dir.path().join("gone")],
+            ..Default::default()
+        };
+        let listener = bind_unix(&path).unwrap();
+        UnixSocketFiles::from_config(&config).remove();
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
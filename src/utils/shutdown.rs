@@ -0,0 +1,143 @@
+//! Coordinating a graceful shutdown.
+//!
+//! When the server is asked to shut down, it first stops accepting new
+//! connections and then waits for a while for ongoing work to finish. The
+//! types in this module help with that.
+//!
+//! A [`ShutdownTrigger`] is kept by whoever decides when to shut down. It
+//! hands out [`Shutdown`] values to the listeners which can wait for the
+//! shutdown to be triggered. Work that should be finished before the
+//! process exits holds a [`DrainGuard`] for as long as it is in progress.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::future::pending;
+use tokio::sync::watch;
+
+
+//------------ ShutdownTrigger -----------------------------------------------
+
+/// The sending side of a shutdown signal.
+#[derive(Debug)]
+pub struct ShutdownTrigger {
+    /// The sender of the watch channel signalling shutdown.
+    tx: watch::Sender<bool>,
+
+    /// The number of pieces of work still in progress.
+    active: Arc<AtomicUsize>,
+}
+
+impl ShutdownTrigger {
+    /// Creates a new trigger and the receiving side for it.
+    pub fn new() -> (Self, Shutdown) {
+        let (tx, rx) = watch::channel(false);
+        let active = Arc::new(AtomicUsize::new(0));
+        (
+            ShutdownTrigger { tx, active: active.clone() },
+            Shutdown { rx, active },
+        )
+    }
+
+    /// Triggers the shutdown.
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Returns the number of pieces of work still in progress.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Waits until all work in progress has finished.
+    pub async fn drained(&self) {
+        while self.active() > 0 {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await
+        }
+    }
+}
+
+
+//------------ Shutdown ------------------------------------------------------
+
+/// The receiving side of a shutdown signal.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    /// The receiver of the watch channel signalling shutdown.
+    rx: watch::Receiver<bool>,
+
+    /// The number of pieces of work still in progress.
+    active: Arc<AtomicUsize>,
+}
+
+impl Shutdown {
+    /// Returns whether shutdown has been triggered.
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Waits until shutdown has been triggered.
+    ///
+    /// If the trigger is dropped without ever being triggered, waits
+    /// forever.
+    pub async fn triggered(&mut self) {
+        while !*self.rx.borrow_and_update() {
+            if self.rx.changed().await.is_err() {
+                pending::<()>().await
+            }
+        }
+    }
+
+    /// Registers a piece of work that should finish before shutting down.
+    ///
+    /// The work is considered finished when the returned guard is dropped.
+    pub fn guard(&self) -> DrainGuard {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        DrainGuard { active: self.active.clone() }
+    }
+}
+
+
+//------------ DrainGuard ----------------------------------------------------
+
+/// Marks a piece of work in progress.
+#[derive(Debug)]
+pub struct DrainGuard {
+    /// The number of pieces of work in progress.
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for DrainGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+
+//------------ Constants -----------------------------------------------------
+
+/// How often to check whether all work has finished.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guards() {
+        let (trigger, shutdown) = ShutdownTrigger::new();
+        assert!(!shutdown.is_triggered());
+        let guard = shutdown.guard();
+        let other = shutdown.clone().guard();
+        assert_eq!(trigger.active(), 2);
+        drop(guard);
+        assert_eq!(trigger.active(), 1);
+        trigger.trigger();
+        assert!(shutdown.is_triggered());
+        drop(other);
+        assert_eq!(trigger.active(), 0);
+    }
+}
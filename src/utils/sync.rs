@@ -1,6 +1,6 @@
 //! Utilities for concurrency.
 
-use std::sync::{Mutex as StdMutex, RwLock as StdRwLock};
+use std::sync::{Condvar, Mutex as StdMutex, RwLock as StdRwLock};
 
 pub use std::sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
 
@@ -87,3 +87,98 @@ impl<T: ?Sized> Mutex<T> {
     }
 }
 
+
+//------------ Semaphore -----------------------------------------------------
+
+/// A simple counting semaphore for blocking threads.
+#[derive(Debug)]
+pub struct Semaphore {
+    /// The number of currently available permits.
+    permits: Mutex<usize>,
+
+    /// The condition variable to wait on for a permit to become available.
+    available: Condvar,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: Mutex::new(permits),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Acquires a permit.
+    ///
+    /// The current thread will be blocked until a permit becomes available.
+    /// The permit is returned when the returned value is dropped.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the internal mutex is poisoned.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock();
+        while *permits == 0 {
+            permits = self.available.wait(permits).expect(
+                "acquiring a poisoned mutex"
+            );
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+}
+
+
+//------------ SemaphorePermit -----------------------------------------------
+
+/// A permit acquired from a semaphore.
+///
+/// The permit is returned to the semaphore when the value is dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock() += 1;
+        self.semaphore.available.notify_one();
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn semaphore_limits_concurrency() {
+        let semaphore = Arc::new(Semaphore::new(2));
+        let current = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..8).map(|_| {
+            let semaphore = semaphore.clone();
+            let current = current.clone();
+            let max = max.clone();
+            thread::spawn(move || {
+                let _permit = semaphore.acquire();
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                current.fetch_sub(1, Ordering::SeqCst);
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(max.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*semaphore.permits.lock(), 2);
+    }
+}
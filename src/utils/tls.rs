@@ -1,11 +1,13 @@
 //! Utility functions for dealing with TLS.
 
-use std::io;
+use std::{io, str};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use log::error;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{error, info};
 use futures::{pin_mut, ready, TryFuture};
 use futures::future::Either;
 use pin_project_lite::pin_project;
@@ -15,6 +17,7 @@ use tokio_rustls::{Accept, TlsAcceptor};
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::server::TlsStream;
 use crate::error::ExitError;
+use crate::utils::sync::RwLock;
 
 pub use tokio_rustls::rustls::ServerConfig;
 
@@ -25,13 +28,20 @@ pub use tokio_rustls::rustls::ServerConfig;
 ///
 /// The service this config is for should be given through `service`. This is
 /// used for logging.
-pub fn create_server_config(
+///
+/// Also returns the validity of the server certificate, i.e., the first
+/// certificate in the file, or `None` if it can’t be parsed.
+fn create_server_config(
     service: &str, key_path: &Path, cert_path: &Path
-) -> Result<ServerConfig, ExitError> {
-
+) -> Result<(ServerConfig, Option<CertValidity>), ExitError> {
+    let certs = read_certs(cert_path)?;
+    let validity = certs.first().and_then(|cert| {
+        CertValidity::from_der(cert.as_ref())
+    });
     ServerConfig::builder()
         .with_no_client_auth()
-        .with_single_cert(read_certs(cert_path)?, read_key(key_path)?)
+        .with_single_cert(certs, read_key(key_path)?)
+        .map(|config| (config, validity))
         .map_err(|err| {
             error!("Failed to create {} TLS server config: {}", service, err);
             ExitError::Generic
@@ -123,6 +133,234 @@ fn read_key(key_path: &Path) -> Result<PrivateKeyDer<'static>, ExitError> {
 }
 
 
+//------------ ReloadableServerConfig ----------------------------------------
+
+/// A TLS server config that can be reloaded from its files.
+///
+/// New connections should acquire a new acceptor via
+/// [`acceptor`][Self::acceptor] each time so they pick up a reloaded config.
+/// Existing connections keep using the config they were started with.
+#[derive(Debug)]
+pub struct ReloadableServerConfig {
+    /// The name of the service for logging.
+    service: &'static str,
+
+    /// The path to the private key file.
+    key_path: PathBuf,
+
+    /// The path to the certificate file.
+    cert_path: PathBuf,
+
+    /// The current config and the validity of its certificate.
+    current: RwLock<(Arc<ServerConfig>, Option<CertValidity>)>,
+}
+
+impl ReloadableServerConfig {
+    /// Creates a new config by loading the key and certificate files.
+    pub fn new(
+        service: &'static str, key_path: PathBuf, cert_path: PathBuf,
+    ) -> Result<Self, ExitError> {
+        let (config, validity) = create_server_config(
+            service, &key_path, &cert_path
+        )?;
+        Ok(ReloadableServerConfig {
+            service, key_path, cert_path,
+            current: RwLock::new((Arc::new(config), validity)),
+        })
+    }
+
+    /// Returns the name of the service the config is for.
+    pub fn service(&self) -> &'static str {
+        self.service
+    }
+
+    /// Returns an acceptor using the current config.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().0.clone())
+    }
+
+    /// Returns the validity of the current certificate if it is known.
+    pub fn validity(&self) -> Option<CertValidity> {
+        self.current.read().1
+    }
+
+    /// Reloads the config from the key and certificate files.
+    ///
+    /// If loading fails, logs an error and keeps the current config.
+    pub fn reload(&self) {
+        match create_server_config(
+            self.service, &self.key_path, &self.cert_path
+        ) {
+            Ok((config, validity)) => {
+                *self.current.write() = (Arc::new(config), validity);
+                info!("Reloaded {} TLS certificate.", self.service);
+            }
+            Err(_) => {
+                error!(
+                    "Reloading {} TLS certificate failed. \
+                     Keeping the current certificate.",
+                    self.service
+                );
+            }
+        }
+    }
+}
+
+
+//------------ TlsConfigs ----------------------------------------------------
+
+/// The collection of all reloadable TLS server configs.
+///
+/// Values of this type can be cloned cheaply and all clones refer to the
+/// same collection.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfigs(Arc<Mutex<Vec<Arc<ReloadableServerConfig>>>>);
+
+impl TlsConfigs {
+    /// Creates a new reloadable config and adds it to the collection.
+    pub fn create(
+        &self, service: &'static str, key_path: &Path, cert_path: &Path,
+    ) -> Result<Arc<ReloadableServerConfig>, ExitError> {
+        let res = Arc::new(ReloadableServerConfig::new(
+            service, key_path.into(), cert_path.into()
+        )?);
+        self.lock().push(res.clone());
+        Ok(res)
+    }
+
+    /// Reloads all configs.
+    pub fn reload(&self) {
+        for config in self.lock().iter() {
+            config.reload()
+        }
+    }
+
+    /// Returns the validity of the current certificate for a service.
+    ///
+    /// Returns `None` if there is no config for the service or the
+    /// validity is unknown.
+    pub fn validity(&self, service: &str) -> Option<CertValidity> {
+        self.lock().iter().find(|config| {
+            config.service() == service
+        }).and_then(|config| config.validity())
+    }
+
+    fn lock(
+        &self
+    ) -> std::sync::MutexGuard<'_, Vec<Arc<ReloadableServerConfig>>> {
+        self.0.lock().expect("poisoned TLS configs lock")
+    }
+}
+
+
+//------------ CertValidity --------------------------------------------------
+
+/// The validity period of a certificate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CertValidity {
+    /// The time before which the certificate is not valid.
+    pub not_before: DateTime<Utc>,
+
+    /// The time after which the certificate is not valid.
+    pub not_after: DateTime<Utc>,
+}
+
+impl CertValidity {
+    /// Extracts the validity from a DER encoded X.509 certificate.
+    ///
+    /// This only walks the structure as far as necessary and doesn’t check
+    /// the certificate in any way. Returns `None` if the data can’t be
+    /// parsed.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (cert, _) = der_value(der, 0x30)?;
+        let (mut tbs, _) = der_value(cert, 0x30)?;
+        // The version is optional and explicitly tagged.
+        if tbs.first() == Some(&0xa0) {
+            tbs = der_value(tbs, 0xa0)?.1;
+        }
+        let tbs = der_value(tbs, 0x02)?.1; // serialNumber
+        let tbs = der_value(tbs, 0x30)?.1; // signature
+        let tbs = der_value(tbs, 0x30)?.1; // issuer
+        let (validity, _) = der_value(tbs, 0x30)?;
+        let (not_before, validity) = der_time(validity)?;
+        let (not_after, _) = der_time(validity)?;
+        Some(CertValidity { not_before, not_after })
+    }
+}
+
+
+//------------ cert_spki -----------------------------------------------------
+
+/// Returns the SubjectPublicKeyInfo of a DER encoded X.509 certificate.
+///
+/// The returned data is the complete DER encoded value including tag and
+/// length. Like [`CertValidity::from_der`], this doesn’t check the
+/// certificate at all and returns `None` if the data can’t be parsed.
+pub fn cert_spki(der: &[u8]) -> Option<&[u8]> {
+    let (cert, _) = der_value(der, 0x30)?;
+    let (mut tbs, _) = der_value(cert, 0x30)?;
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_value(tbs, 0xa0)?.1;
+    }
+    let tbs = der_value(tbs, 0x02)?.1; // serialNumber
+    let tbs = der_value(tbs, 0x30)?.1; // signature
+    let tbs = der_value(tbs, 0x30)?.1; // issuer
+    let tbs = der_value(tbs, 0x30)?.1; // validity
+    let tbs = der_value(tbs, 0x30)?.1; // subject
+    let (_, rest) = der_value(tbs, 0x30)?;
+    Some(&tbs[..tbs.len() - rest.len()])
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Splits off a DER encoded value with the given single-octet tag.
+///
+/// Returns the content of the value and the remaining data.
+fn der_value(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *data.first()? != tag {
+        return None
+    }
+    let first = *data.get(1)?;
+    let (len, start) = if first & 0x80 == 0 {
+        (usize::from(first), 2)
+    }
+    else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 {
+            return None
+        }
+        let mut len = 0usize;
+        for &octet in data.get(2..2 + octets)? {
+            len = (len << 8) | usize::from(octet);
+        }
+        (len, 2 + octets)
+    };
+    let end = start.checked_add(len)?;
+    Some((data.get(start..end)?, data.get(end..)?))
+}
+
+/// Splits off a DER encoded UTCTime or GeneralizedTime.
+fn der_time(data: &[u8]) -> Option<(DateTime<Utc>, &[u8])> {
+    let (time, rest) = match *data.first()? {
+        0x17 => {
+            let (value, rest) = der_value(data, 0x17)?;
+            let value = str::from_utf8(value).ok()?;
+            // RFC 5280 says two-digit years of 50 and up are in the 1900s.
+            let century = if value.get(..2)? >= "50" { "19" } else { "20" };
+            (format!("{}{}", century, value), rest)
+        }
+        0x18 => {
+            let (value, rest) = der_value(data, 0x18)?;
+            (str::from_utf8(value).ok()?.into(), rest)
+        }
+        _ => return None
+    };
+    let time = NaiveDateTime::parse_from_str(&time, "%Y%m%d%H%M%SZ").ok()?;
+    Some((time.and_utc(), rest))
+}
+
+
 //------------ TlsTcpStream --------------------------------------------------
 
 pin_project! {
@@ -337,3 +575,78 @@ impl AsyncWrite for MaybeTlsTcpStream {
     }
 }
 
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut res = vec![tag];
+        if content.len() < 0x80 {
+            res.push(content.len() as u8);
+        }
+        else {
+            res.push(0x82);
+            res.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        }
+        res.extend_from_slice(content);
+        res
+    }
+
+    #[test]
+    fn cert_validity() {
+        let validity = [
+            tlv(0x17, b"991231235959Z"), tlv(0x18, b"20510101000000Z")
+        ].concat();
+        let tbs = [
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            tlv(0x30, b""),
+            tlv(0x30, &[0u8; 200]),
+            tlv(0x30, &validity),
+            tlv(0x30, b""),
+        ].concat();
+        let cert = tlv(0x30, &[
+            tlv(0x30, &tbs), tlv(0x30, b""), tlv(0x03, &[0])
+        ].concat());
+        assert_eq!(
+            CertValidity::from_der(&cert),
+            Some(CertValidity {
+                not_before: Utc.with_ymd_and_hms(
+                    1999, 12, 31, 23, 59, 59
+                ).unwrap(),
+                not_after: Utc.with_ymd_and_hms(
+                    2051, 1, 1, 0, 0, 0
+                ).unwrap(),
+            })
+        );
+        assert!(CertValidity::from_der(&cert[..cert.len() - 1]).is_none());
+        assert!(CertValidity::from_der(b"").is_none());
+    }
+
+    #[test]
+    fn spki() {
+        let spki = tlv(0x30, &[
+            tlv(0x30, &tlv(0x06, &[0x2b, 0x65, 0x70])),
+            tlv(0x03, &[0u8; 33]),
+        ].concat());
+        let tbs = [
+            tlv(0x02, &[1]),
+            tlv(0x30, b""),
+            tlv(0x30, &[0u8; 200]),
+            tlv(0x30, b""),
+            tlv(0x30, b""),
+            spki.clone(),
+            tlv(0xa3, b""),
+        ].concat();
+        let cert = tlv(0x30, &[
+            tlv(0x30, &tbs), tlv(0x30, b""), tlv(0x03, &[0])
+        ].concat());
+        assert_eq!(cert_spki(&cert), Some(spki.as_slice()));
+        assert!(cert_spki(&cert[..40]).is_none());
+        assert!(cert_spki(b"").is_none());
+    }
+}
@@ -1,12 +1,14 @@
 //! Checking for validity of route announcements.
 
 use std::{fmt, io};
+use std::io::BufRead;
 use std::str::FromStr;
 use chrono::{DateTime, Utc};
 use rpki::resources::{Asn, Prefix};
 use rpki::rtr::payload::RouteOrigin;
 use serde::Deserialize;
 use crate::payload::{PayloadInfo, PayloadSnapshot};
+use crate::utils::mrt;
 use crate::utils::date::format_iso_date;
 
 
@@ -93,11 +95,29 @@ pub struct RouteValidity<'a> {
     /// Indexes of covering VRPs that don’t match because of the ´asn`.
     bad_asn: Vec<(RouteOrigin, &'a PayloadInfo)>,
 
-    /// Indexes of covering VRPs that don’t match because of the prefix length.
+    /// Indexes of covering VRPs with the right AS number that don’t match
+    /// because of the prefix length.
     bad_len: Vec<(RouteOrigin, &'a PayloadInfo)>,
+
+    /// Was the route’s address family excluded from validation?
+    excluded: bool,
 }
 
 impl<'a> RouteValidity<'a> {
+    /// Determines the validity of a route announcement.
+    ///
+    /// Each VRP covering the announcement is sorted into one of three
+    /// categories: if its AS number differs from the announcement’s, it
+    /// is unmatched because of the AS number, no matter its maximum length.
+    /// Otherwise, if the announcement’s prefix is longer than the maximum
+    /// length, it is unmatched because of its length. Otherwise it matches.
+    ///
+    /// The covering VRPs are looked up via
+    /// [`PayloadSnapshot::covering_origins`], so the cost of a check does
+    /// not grow with the total number of VRPs.
+    ///
+    /// If the address family of the prefix has been excluded from
+    /// validation, the announcement is always not found.
     pub fn new(
         prefix: Prefix,
         asn: Asn,
@@ -106,20 +126,25 @@ impl<'a> RouteValidity<'a> {
         let mut matched = Vec::new();
         let mut bad_asn = Vec::new();
         let mut bad_len = Vec::new();
-        for item in snapshot.origins() {
-            if item.0.prefix.prefix().covers(prefix) {
-                if prefix.len() > item.0.prefix.resolved_max_len() {
-                    bad_len.push(item);
-                }
-                else if item.0.asn != asn {
-                    bad_asn.push(item);
-                }
-                else {
-                    matched.push(item)
-                }
+        if !snapshot.address_family().includes(prefix.is_v4()) {
+            return RouteValidity {
+                prefix, asn, matched, bad_asn, bad_len, excluded: true
+            }
+        }
+        for item in snapshot.covering_origins(prefix) {
+            if item.0.asn != asn {
+                bad_asn.push(item);
+            }
+            else if prefix.len() > item.0.prefix.resolved_max_len() {
+                bad_len.push(item);
+            }
+            else {
+                matched.push(item)
             }
         }
-        RouteValidity { prefix, asn, matched, bad_asn, bad_len }
+        RouteValidity {
+            prefix, asn, matched, bad_asn, bad_len, excluded: false
+        }
     }
 
     pub fn prefix(&self) -> Prefix {
@@ -144,14 +169,25 @@ impl<'a> RouteValidity<'a> {
         }
     }
 
+    /// Returns the reason for an invalid announcement.
+    ///
+    /// If there is a VRP for the announcement’s AS number that only fails
+    /// because of the length, the reason is `"length"` since the origin
+    /// is authorized but the prefix is too specific. Otherwise, the reason
+    /// is `"as"`. Returns `None` if the announcement isn’t invalid unless
+    /// its address family was excluded from validation in which case the
+    /// reason is `"address-family"`.
     pub fn reason(&self) -> Option<&'static str> {
-        if self.matched.is_empty() {
-            if !self.bad_asn.is_empty() {
-                Some("as")
-            }
-            else if !self.bad_len.is_empty() {
+        if self.excluded {
+            Some("address-family")
+        }
+        else if self.matched.is_empty() {
+            if !self.bad_len.is_empty() {
                 Some("length")
             }
+            else if !self.bad_asn.is_empty() {
+                Some("as")
+            }
             else {
                 None
             }
@@ -162,13 +198,16 @@ impl<'a> RouteValidity<'a> {
     }
 
     pub fn description(&self) -> &'static str {
-        if self.matched.is_empty() {
-            if !self.bad_asn.is_empty() {
-                DESCRIPTION_BAD_ASN
-            }
-            else if !self.bad_len.is_empty() {
+        if self.excluded {
+            DESCRIPTION_EXCLUDED
+        }
+        else if self.matched.is_empty() {
+            if !self.bad_len.is_empty() {
                 DESCRIPTION_BAD_LEN
             }
+            else if !self.bad_asn.is_empty() {
+                DESCRIPTION_BAD_ASN
+            }
             else {
                 DESCRIPTION_NOT_FOUND
             }
@@ -197,6 +236,56 @@ impl<'a> RouteValidity<'a> {
         writeln!(target, "{} => {}: {}", self.prefix, self.asn, self.state())
     }
 
+    /// Writes the header line for [`write_csv`][Self::write_csv].
+    pub fn write_csv_header<W: io::Write>(
+        target: &mut W
+    ) -> Result<(), io::Error> {
+        writeln!(target,
+            "Prefix,ASN,State,Reason,Matched VRPs,Unmatched AS VRPs,\
+            Unmatched Length VRPs"
+        )
+    }
+
+    /// Writes the validity as a line of comma-separated values.
+    ///
+    /// The VRPs of each category are given as a single field with the
+    /// VRPs separated by semicolons.
+    pub fn write_csv<W: io::Write>(
+        &self,
+        target: &mut W
+    ) -> Result<(), io::Error> {
+        write!(target, "{},{},{},{},",
+            self.prefix, self.asn, self.state(), self.reason().unwrap_or("")
+        )?;
+        Self::write_vrps_csv(&self.matched, target)?;
+        write!(target, ",")?;
+        Self::write_vrps_csv(&self.bad_asn, target)?;
+        write!(target, ",")?;
+        Self::write_vrps_csv(&self.bad_len, target)?;
+        writeln!(target)
+    }
+
+    fn write_vrps_csv<W: io::Write>(
+        vrps: &[(RouteOrigin, &'a PayloadInfo)],
+        target: &mut W
+    ) -> Result<(), io::Error> {
+        let mut first = true;
+        for item in vrps {
+            if first {
+                first = false;
+            }
+            else {
+                write!(target, ";")?;
+            }
+            write!(target, "{}-{} => {}",
+                item.0.prefix.prefix(),
+                item.0.prefix.resolved_max_len(),
+                item.0.asn,
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn into_json(self, current: &PayloadSnapshot) -> Vec<u8> {
         let mut res = Vec::new();
         self.write_json(current, &mut res).unwrap();
@@ -324,7 +413,7 @@ impl<'a> RouteValidity<'a> {
 /// three variants of this enum.
 ///
 /// [RFC 6811]: https://tools.ietf.org/html/rfc6811
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RouteState {
     /// RPKI Valid.
     ///
@@ -464,6 +553,74 @@ impl RequestList {
         Ok(res)
     }
 
+    /// Loads the request list from a text file with route announcements.
+    ///
+    /// Each non-empty line contains a prefix and an AS number separated by
+    /// white space or a comma. Any further fields are ignored, as are lines
+    /// starting with `#`. If the first line doesn’t start with a prefix, it
+    /// is considered a header line and skipped, too.
+    pub fn from_announcements_reader<R: io::BufRead>(
+        reader: R
+    ) -> Result<Self, io::Error> {
+        let mut res = Self::default();
+
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let mut tokens = line.split(|ch: char| {
+                ch == ',' || ch.is_whitespace()
+            }).filter(|token| !token.is_empty());
+
+            // PREFIX[,] ASN [anything]
+
+            let prefix = match tokens.next().map(Prefix::from_str) {
+                Some(Ok(prefix)) => prefix,
+                _ if line_no == 0 => continue,
+                _ => {
+                    return Err(io::Error::other(
+                        format!("line {}: expecting prefix", line_no + 1)
+                    ))
+                }
+            };
+            let asn = match tokens.next().map(Asn::from_str) {
+                Some(Ok(asn)) => asn,
+                _ => {
+                    return Err(io::Error::other(
+                        format!(
+                            "line {}: expecting AS number", line_no + 1
+                        )
+                    ))
+                }
+            };
+            res.routes.push(Request { prefix, asn });
+        }
+
+        Ok(res)
+    }
+
+    /// Loads the request list from an MRT RIB dump.
+    ///
+    /// Returns the list and the number of routes skipped because their
+    /// origin AS number cannot be determined. The list will contain a
+    /// route once for every peer it was learned from, so you probably want
+    /// to call [`dedup`][Self::dedup] afterwards.
+    pub fn from_mrt_reader<R: io::Read>(
+        reader: &mut R
+    ) -> Result<(Self, usize), io::Error> {
+        let mut res = Self::default();
+        let mut skipped = 0;
+        mrt::read_rib(reader, |prefix, asn| {
+            match asn {
+                Some(asn) => res.routes.push(Request { prefix, asn }),
+                None => skipped += 1,
+            }
+        })?;
+        Ok((res, skipped))
+    }
+
     /// Loads the request list from a json-formatted reader.
     pub fn from_json_reader<R: io::Read>(
         reader: &mut R
@@ -471,6 +628,83 @@ impl RequestList {
         serde_json::from_reader(reader)
     }
 
+    /// Loads the request list from a batch request in plain text.
+    ///
+    /// Each non-empty line contains an AS number and a prefix separated by
+    /// white space.
+    pub fn from_batch_text(data: &[u8]) -> Result<Self, io::Error> {
+        let mut res = Self::default();
+
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+
+            // ASN PREFIX
+
+            let asn = match tokens.next() {
+                Some(asn) => {
+                    match Asn::from_str(asn) {
+                        Ok(asn) => asn,
+                        Err(_) => {
+                            return Err(io::Error::other(
+                                format!(
+                                    "line {}: expecting AS number, got '{}'",
+                                    line_no + 1, asn
+                                )
+                            ))
+                        }
+                    }
+                }
+                None => continue
+            };
+
+            let prefix = match tokens.next() {
+                Some(prefix) => {
+                    match Prefix::from_str(prefix) {
+                        Ok(prefix) => prefix,
+                        Err(_) => {
+                            return Err(io::Error::other(
+                                format!(
+                                    "line {}: expecting prefix, got '{}'",
+                                    line_no + 1, prefix
+                                )
+                            ))
+                        }
+                    }
+                }
+                None => {
+                    return Err(io::Error::other(
+                        format!(
+                            "line {}: expecting prefix, got end of line",
+                            line_no + 1
+                        )
+                    ))
+                }
+            };
+
+            if let Some(token) = tokens.next() {
+                return Err(io::Error::other(
+                    format!(
+                        "line {}: expecting end of line, got '{}'",
+                        line_no + 1, token
+                    )
+                ))
+            }
+
+            res.routes.push(Request { prefix, asn });
+        }
+
+        Ok(res)
+    }
+
+    /// Loads the request list from a batch request in JSON.
+    ///
+    /// The request is a JSON array of objects with an `asn` and a `prefix`
+    /// member each.
+    pub fn from_batch_json(data: &[u8]) -> Result<Self, serde_json::Error> {
+        Ok(RequestList { routes: serde_json::from_slice(data)? })
+    }
+
     /// Creates a request list with a single entry.
     pub fn single(prefix: Prefix, asn: Asn) -> Self {
         RequestList {
@@ -478,6 +712,77 @@ impl RequestList {
         }
     }
 
+    /// Returns the number of requests in the list.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Sorts the list and removes duplicate requests.
+    pub fn dedup(&mut self) {
+        self.routes.sort_unstable();
+        self.routes.dedup();
+    }
+
+    /// Checks the validity of all routes and writes the results.
+    ///
+    /// Unlike [`validity`][Self::validity], this doesn’t keep the results
+    /// around and is thus suitable for large lists. If `only_invalid` is
+    /// `true`, only invalid routes are written. Returns the number of
+    /// routes in each state.
+    pub fn write_validity<W: io::Write>(
+        &self,
+        snapshot: &PayloadSnapshot,
+        format: ValidityFormat,
+        only_invalid: bool,
+        target: &mut W
+    ) -> Result<ValidityCounts, io::Error> {
+        let mut counts = ValidityCounts::default();
+        match format {
+            ValidityFormat::Csv => {
+                RouteValidity::write_csv_header(target)?
+            }
+            ValidityFormat::Json => {
+                writeln!(target, "{{\n  \"validated_routes\": [")?
+            }
+        }
+        let mut first = true;
+        for route in &self.routes {
+            let route = RouteValidity::new(route.prefix, route.asn, snapshot);
+            let state = route.state();
+            counts.add(state);
+            if only_invalid && state != RouteState::Invalid {
+                continue
+            }
+            match format {
+                ValidityFormat::Csv => route.write_csv(target)?,
+                ValidityFormat::Json => {
+                    if first {
+                        first = false;
+                    }
+                    else {
+                        writeln!(target, ",")?;
+                    }
+                    write!(target, "    ")?;
+                    route.write_single_json("    ", target)?;
+                }
+            }
+        }
+        if let ValidityFormat::Json = format {
+            writeln!(target,
+                "\n  ],\
+                \n  \"generatedTime\": \"{}\"\
+                \n}}",
+                format_iso_date(snapshot.created()),
+            )?;
+        }
+        Ok(counts)
+    }
+
     /// Checks the validity of all routes and returns a vec with results.
     pub fn validity<'a>(
         &self,
@@ -491,7 +796,7 @@ impl RequestList {
 //------------ Request -------------------------------------------------------
 
 /// A request for a route validity check.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
 struct Request {
     /// The address prefix of the route announcement.
     prefix: Prefix,
@@ -502,6 +807,57 @@ struct Request {
 }
 
 
+//------------ ValidityFormat ------------------------------------------------
+
+/// The format for writing the results of route validity checks.
+#[derive(Clone, Copy, Debug)]
+pub enum ValidityFormat {
+    /// One line of comma-separated values per route.
+    Csv,
+
+    /// The JSON format also used by the validity HTTP endpoint.
+    Json,
+}
+
+impl FromStr for ValidityFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ValidityFormat::Csv),
+            "json" => Ok(ValidityFormat::Json),
+            _ => Err(format!("unknown output format '{}'", s))
+        }
+    }
+}
+
+
+//------------ ValidityCounts ------------------------------------------------
+
+/// The number of routes found in each RPKI state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidityCounts {
+    /// The number of RPKI valid routes.
+    pub valid: usize,
+
+    /// The number of RPKI invalid routes.
+    pub invalid: usize,
+
+    /// The number of RPKI not found routes.
+    pub not_found: usize,
+}
+
+impl ValidityCounts {
+    fn add(&mut self, state: RouteState) {
+        match state {
+            RouteState::Valid => self.valid += 1,
+            RouteState::Invalid => self.invalid += 1,
+            RouteState::NotFound => self.not_found += 1,
+        }
+    }
+}
+
+
 //------------ Constants -----------------------------------------------------
 
 // Description texts as provided by the RIPE NCC Validator.
@@ -516,12 +872,385 @@ const DESCRIPTION_BAD_LEN: &str = "At least one VRP Covers the Route Prefix, \
                                    matching this route origin ASN";
 const DESCRIPTION_NOT_FOUND: &str = "No VRP Covers the Route Prefix";
 
+// Not from the RIPE NCC Validator.
+const DESCRIPTION_EXCLUDED: &str = "The address family of the Route Prefix \
+                                    is excluded from validation";
+
 
 //============ Tests =========================================================
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::sync::Arc;
+    use rpki::resources::addr::MaxLenPrefix;
+    use crate::config::AddressFamilyFilter;
+    use crate::slurm::ExceptionInfo;
+
+    fn snapshot(vrps: &[(&str, Option<u8>, u32)]) -> PayloadSnapshot {
+        let info = PayloadInfo::from(Arc::new(ExceptionInfo {
+            path: None, comment: None
+        }));
+        PayloadSnapshot::new(
+            vrps.iter().map(|&(prefix, max_len, asn)| {
+                (
+                    RouteOrigin::new(
+                        MaxLenPrefix::new(
+                            Prefix::from_str(prefix).unwrap(), max_len
+                        ).unwrap(),
+                        Asn::from_u32(asn)
+                    ),
+                    info.clone()
+                )
+            }),
+            std::iter::empty(), std::iter::empty(), None
+        )
+    }
+
+    fn validity<'a>(
+        prefix: &str, asn: u32, snapshot: &'a PayloadSnapshot
+    ) -> RouteValidity<'a> {
+        RouteValidity::new(
+            Prefix::from_str(prefix).unwrap(), Asn::from_u32(asn), snapshot
+        )
+    }
+
+    #[test]
+    fn route_validity() {
+        let snapshot = snapshot(&[
+            ("192.0.2.0/24", None, 64496),
+            ("192.0.2.0/23", Some(24), 64497),
+            ("198.51.100.0/24", None, 64496),
+            ("198.51.100.0/22", Some(24), 64497),
+            ("203.0.113.0/24", None, 64496),
+        ]);
+
+        // Matched.
+        let res = validity("192.0.2.0/24", 64496, &snapshot);
+        assert!(matches!(res.state(), RouteState::Valid));
+        assert_eq!(res.reason(), None);
+        assert_eq!(res.matched().len(), 1);
+        assert_eq!(res.bad_asn().len(), 1);
+        assert_eq!(res.bad_len().len(), 0);
+
+        // Not found.
+        let res = validity("10.0.0.0/8", 64496, &snapshot);
+        assert!(matches!(res.state(), RouteState::NotFound));
+        assert_eq!(res.reason(), None);
+        assert_eq!(res.description(), DESCRIPTION_NOT_FOUND);
+
+        // Only a VRP with the wrong AS number covers it.
+        let res = validity("203.0.113.0/24", 64497, &snapshot);
+        assert!(matches!(res.state(), RouteState::Invalid));
+        assert_eq!(res.reason(), Some("as"));
+        assert_eq!(res.description(), DESCRIPTION_BAD_ASN);
+
+        // Wrong AS number and too long: classified as bad AS number.
+        let res = validity("203.0.113.0/25", 64497, &snapshot);
+        assert_eq!(res.reason(), Some("as"));
+        assert_eq!(res.bad_asn().len(), 1);
+        assert_eq!(res.bad_len().len(), 0);
+
+        // One VRP matches the AS number but not the length, another
+        // one matches the length but not the AS number.
+        let res = validity("198.51.100.0/24", 64497, &snapshot);
+        assert!(matches!(res.state(), RouteState::Valid));
+        let res = validity("198.51.100.0/25", 64496, &snapshot);
+        assert!(matches!(res.state(), RouteState::Invalid));
+        assert_eq!(res.reason(), Some("length"));
+        assert_eq!(res.description(), DESCRIPTION_BAD_LEN);
+        assert_eq!(res.matched().len(), 0);
+        assert_eq!(res.bad_asn().len(), 1);
+        assert_eq!(res.bad_asn()[0].0.asn, Asn::from_u32(64497));
+        assert_eq!(res.bad_len().len(), 1);
+        assert_eq!(res.bad_len()[0].0.asn, Asn::from_u32(64496));
+
+        // Same, but the route length is fine for the other AS number.
+        let res = validity("198.51.100.0/24", 64498, &snapshot);
+        assert_eq!(res.reason(), Some("as"));
+        assert_eq!(res.bad_asn().len(), 2);
+        assert_eq!(res.bad_len().len(), 0);
+    }
+
+    #[test]
+    fn route_validity_json() {
+        let snapshot = snapshot(&[
+            ("192.0.2.0/24", None, 64496),
+            ("192.0.2.0/23", Some(24), 64497),
+        ]);
+        let json = validity("192.0.2.0/25", 64496, &snapshot)
+            .into_json(&snapshot);
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let validity = &json["validated_route"]["validity"];
+        assert_eq!(validity["state"], "invalid");
+        assert_eq!(validity["reason"], "length");
+        let vrps = &validity["VRPs"];
+        assert_eq!(vrps["matched"].as_array().unwrap().len(), 0);
+        assert_eq!(vrps["unmatched_as"][0]["asn"], "AS64497");
+        assert_eq!(vrps["unmatched_as"][0]["prefix"], "192.0.2.0/23");
+        assert_eq!(vrps["unmatched_as"][0]["max_length"], "24");
+        assert_eq!(vrps["unmatched_length"][0]["asn"], "AS64496");
+        assert_eq!(vrps["unmatched_length"][0]["max_length"], "24");
+    }
+
+    #[test]
+    fn route_validity_excluded_family() {
+        let snapshot = snapshot(&[
+            ("192.0.2.0/24", None, 64496),
+        ]).with_address_family(AddressFamilyFilter::V6);
+        let res = validity("192.0.2.0/24", 64496, &snapshot);
+        assert!(matches!(res.state(), RouteState::NotFound));
+        assert_eq!(res.reason(), Some("address-family"));
+        assert_eq!(res.description(), DESCRIPTION_EXCLUDED);
+        assert_eq!(res.matched().len(), 0);
+
+        let res = validity("2001:db8::/32", 64496, &snapshot);
+        assert!(matches!(res.state(), RouteState::NotFound));
+        assert_eq!(res.reason(), None);
+    }
+
+    #[test]
+    fn covering_origins() {
+        let snapshot = snapshot(&[
+            ("0.0.0.0/0", None, 64496),
+            ("10.0.0.0/8", Some(24), 64496),
+            ("10.0.0.0/16", None, 64497),
+            ("10.1.0.0/16", None, 64498),
+            ("10.0.0.0/24", None, 64499),
+            ("10.0.0.0/24", Some(32), 64500),
+            ("11.0.0.0/8", None, 64501),
+            ("2001:db8::/32", None, 64502),
+            ("2001:db8::/48", None, 64503),
+        ]);
+        for prefix in [
+            "10.0.0.0/24", "10.0.0.0/25", "10.1.2.0/24", "10.0.0.0/8",
+            "9.0.0.0/8", "0.0.0.0/0", "10.0.0.1/32", "2001:db8::/64",
+            "2001:db8:1::/48", "::/0",
+        ] {
+            let prefix = Prefix::from_str(prefix).unwrap();
+            let expected = snapshot.origins().filter(|item| {
+                item.0.prefix.prefix().covers(prefix)
+            }).map(|item| item.0).collect::<Vec<_>>();
+            let found = snapshot.covering_origins(prefix).map(|item| {
+                item.0
+            }).collect::<Vec<_>>();
+            assert_eq!(expected, found, "{}", prefix);
+        }
+    }
+
+    #[test]
+    fn covered_origins() {
+        let snapshot = snapshot(&[
+            ("0.0.0.0/0", None, 64496),
+            ("10.0.0.0/8", Some(24), 64496),
+            ("10.0.0.0/16", None, 64497),
+            ("10.1.0.0/16", None, 64498),
+            ("10.0.0.0/24", None, 64499),
+            ("10.0.0.0/24", Some(32), 64500),
+            ("10.0.255.0/24", None, 64500),
+            ("11.0.0.0/8", None, 64501),
+            ("2001:db8::/32", None, 64502),
+            ("2001:db8::/48", None, 64503),
+        ]);
+        for prefix in [
+            "10.0.0.0/24", "10.0.0.0/16", "10.0.0.0/8", "10.0.0.0/7",
+            "9.0.0.0/8", "0.0.0.0/0", "10.0.0.1/32", "2001:db8::/32",
+            "2001:db8:1::/48", "::/0",
+        ] {
+            let prefix = Prefix::from_str(prefix).unwrap();
+            let expected = snapshot.origins().filter(|item| {
+                prefix.covers(item.0.prefix.prefix())
+            }).map(|item| item.0).collect::<Vec<_>>();
+            let found = snapshot.covered_origins(prefix).map(|item| {
+                item.0
+            }).collect::<Vec<_>>();
+            assert_eq!(expected, found, "{}", prefix);
+        }
+    }
+
+    /// Determines validity by looking at all VRPs in the snapshot.
+    fn scan_validity<'a>(
+        prefix: Prefix, asn: Asn, snapshot: &'a PayloadSnapshot
+    ) -> RouteValidity<'a> {
+        let mut matched = Vec::new();
+        let mut bad_asn = Vec::new();
+        let mut bad_len = Vec::new();
+        for item in snapshot.origins() {
+            if item.0.prefix.prefix().covers(prefix) {
+                if item.0.asn != asn {
+                    bad_asn.push(item);
+                }
+                else if prefix.len() > item.0.prefix.resolved_max_len() {
+                    bad_len.push(item);
+                }
+                else {
+                    matched.push(item)
+                }
+            }
+        }
+        RouteValidity {
+            prefix, asn, matched, bad_asn, bad_len, excluded: false
+        }
+    }
+
+    /// A simple xorshift pseudo random number generator.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, low: u8, high: u8) -> u8 {
+            low + (self.next() % u64::from(high - low + 1)) as u8
+        }
+
+        /// Returns a prefix from a small part of the address space.
+        ///
+        /// Only a few bits are random so that prefixes overlap often.
+        fn prefix(&mut self) -> Prefix {
+            use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+            let bits = self.next();
+            if bits & 1 == 0 {
+                let addr = 0x0a00_0000 | ((bits as u32 >> 1) & 0x00e0_f000);
+                Prefix::new_relaxed(
+                    IpAddr::V4(Ipv4Addr::from(addr)), self.range(8, 32)
+                ).unwrap()
+            }
+            else {
+                let addr = 0x2001_0db8_u128 << 96
+                    | (u128::from(bits >> 1) & 0xe0f0) << 64;
+                Prefix::new_relaxed(
+                    IpAddr::V6(Ipv6Addr::from(addr)), self.range(32, 64)
+                ).unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn route_validity_random() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut counts = ValidityCounts::default();
+        for _ in 0..20 {
+            let info = PayloadInfo::from(Arc::new(ExceptionInfo {
+                path: None, comment: None
+            }));
+            let count = rng.range(0, 200);
+            let snapshot = PayloadSnapshot::new(
+                (0..count).map(|_| {
+                    let prefix = rng.prefix();
+                    let max_len = if prefix.is_v4() { 32 } else { 128 };
+                    let max_len = match rng.next() % 3 {
+                        0 => None,
+                        _ => Some(rng.range(prefix.len(), max_len))
+                    };
+                    (
+                        RouteOrigin::new(
+                            MaxLenPrefix::new(prefix, max_len).unwrap(),
+                            Asn::from_u32(64496 + rng.range(0, 3) as u32)
+                        ),
+                        info.clone()
+                    )
+                }).collect::<Vec<_>>().into_iter(),
+                std::iter::empty(), std::iter::empty(), None
+            );
+            for _ in 0..500 {
+                let prefix = rng.prefix();
+                let asn = Asn::from_u32(64496 + rng.range(0, 3) as u32);
+                let validity = RouteValidity::new(prefix, asn, &snapshot);
+                counts.add(validity.state());
+                assert_eq!(
+                    scan_validity(prefix, asn, &snapshot)
+                        .into_json(&snapshot),
+                    validity.into_json(&snapshot),
+                    "{} => {}", prefix, asn
+                );
+            }
+        }
+
+        // Make sure the data actually exercises all states.
+        assert!(counts.valid > 100, "{:?}", counts);
+        assert!(counts.invalid > 100, "{:?}", counts);
+        assert!(counts.not_found > 100, "{:?}", counts);
+    }
+
+    #[test]
+    fn request_list_from_announcements_reader() {
+        let mut list = RequestList::from_announcements_reader(
+            b"prefix,asn\n\
+            192.0.2.0/24,AS64496\n\
+            # comment\n\
+            \n\
+            2001:db8::/32 64497 extra fields\n\
+            192.0.2.0/24, 64496\n".as_ref()
+        ).unwrap();
+        assert_eq!(list.len(), 3);
+        list.dedup();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.routes[0].asn, Asn::from_u32(64496));
+        assert_eq!(list.routes[1].asn, Asn::from_u32(64497));
+
+        assert!(
+            RequestList::from_announcements_reader(
+                b"192.0.2.0/24 AS64496\nfoo 64497\n".as_ref()
+            ).is_err()
+        );
+        assert!(
+            RequestList::from_announcements_reader(
+                b"192.0.2.0/24\n".as_ref()
+            ).is_err()
+        );
+    }
+
+    #[test]
+    fn request_list_write_validity() {
+        let snapshot = snapshot(&[
+            ("192.0.2.0/24", None, 64496),
+            ("192.0.2.0/23", Some(24), 64497),
+        ]);
+        let list = RequestList::from_announcements_reader(
+            b"192.0.2.0/24 64496\n192.0.2.0/25 64496\n10.0.0.0/8 64496\n"
+            .as_ref()
+        ).unwrap();
+
+        let mut csv = Vec::new();
+        let counts = list.write_validity(
+            &snapshot, ValidityFormat::Csv, false, &mut csv
+        ).unwrap();
+        assert_eq!(
+            (counts.valid, counts.invalid, counts.not_found), (1, 1, 1)
+        );
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines().skip(1);
+        assert_eq!(
+            lines.next().unwrap(),
+            "192.0.2.0/24,AS64496,valid,,192.0.2.0/24-24 => AS64496,\
+             192.0.2.0/23-24 => AS64497,"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "192.0.2.0/25,AS64496,invalid,length,,\
+             192.0.2.0/23-24 => AS64497,192.0.2.0/24-24 => AS64496"
+        );
+        assert_eq!(
+            lines.next().unwrap(), "10.0.0.0/8,AS64496,not-found,,,,"
+        );
+        assert!(lines.next().is_none());
+
+        let mut json = Vec::new();
+        list.write_validity(
+            &snapshot, ValidityFormat::Json, true, &mut json
+        ).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let routes = json["validated_routes"].as_array().unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0]["route"]["prefix"], "192.0.2.0/25");
+        assert_eq!(routes[0]["validity"]["state"], "invalid");
+    }
 
     #[test]
     fn request_list_from_json_reader() {
@@ -529,5 +1258,48 @@ mod test {
             &mut include_bytes!("../test/validate/beacons.json").as_ref()
         );
     }
+
+    #[test]
+    fn request_list_from_batch_text() {
+        let list = RequestList::from_batch_text(
+            b"AS64496 192.0.2.0/24\n\n  64497\t2001:db8::/32  \n"
+        ).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.routes[0].asn, Asn::from_u32(64496));
+        assert_eq!(
+            list.routes[0].prefix, Prefix::from_str("192.0.2.0/24").unwrap()
+        );
+        assert_eq!(list.routes[1].asn, Asn::from_u32(64497));
+        assert_eq!(
+            list.routes[1].prefix, Prefix::from_str("2001:db8::/32").unwrap()
+        );
+
+        assert!(
+            RequestList::from_batch_text(b"192.0.2.0/24 AS64496").is_err()
+        );
+        assert!(RequestList::from_batch_text(b"AS64496").is_err());
+        assert!(
+            RequestList::from_batch_text(b"AS64496 192.0.2.0/24 x").is_err()
+        );
+    }
+
+    #[test]
+    fn request_list_from_batch_json() {
+        let list = RequestList::from_batch_json(
+            br#"[
+                { "asn": "AS64496", "prefix": "192.0.2.0/24" },
+                { "asn": 64497, "prefix": "2001:db8::/32" }
+            ]"#
+        ).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.routes[0].asn, Asn::from_u32(64496));
+        assert_eq!(list.routes[1].asn, Asn::from_u32(64497));
+        assert_eq!(
+            list.routes[1].prefix, Prefix::from_str("2001:db8::/32").unwrap()
+        );
+
+        assert!(RequestList::from_batch_json(b"{}").is_err());
+        assert!(RequestList::from_batch_json(b"[{\"asn\": 1}]").is_err());
+    }
 }
 